@@ -0,0 +1,110 @@
+use crate::recording::FieldCodec;
+use crate::{clamp_finite, Sim};
+use wasm_bindgen::prelude::*;
+
+const MAX_ACCEL_RAMP: f32 = 100.0;
+
+// Slew-rate limit on the classic models' applied force. Each boid's force may
+// change by at most `rate` times max force per second, so a config change or
+// an attractor toggling on eases the flock into its new steering over a few
+// frames instead of snapping it in one. The limit applies after fish and
+// burst-and-coast forces are added. Flock2's buffers hold headings, which its
+// own turn limits already smooth. Off while the rate is 0.
+#[derive(Default)]
+pub struct AccelRamp {
+    rate: f32,
+    // Force applied last step, as xyz triples per slot.
+    applied: Vec<f32>,
+}
+
+impl AccelRamp {
+    pub fn enabled(&self) -> bool {
+        self.rate > 0.0
+    }
+
+    pub fn forget(&mut self, i: usize) {
+        if let Some(row) = self.applied.get_mut(3 * i..3 * i + 3) {
+            row.fill(0.0);
+        }
+    }
+
+    pub fn swap(&mut self, a: usize, b: usize) {
+        if self.applied.len() < 3 * (a.max(b) + 1) {
+            return;
+        }
+        for axis in 0..3 {
+            self.applied.swap(3 * a + axis, 3 * b + axis);
+        }
+    }
+
+    pub fn visit_settings(&mut self, codec: &mut dyn FieldCodec) {
+        codec.f32(&mut self.rate);
+    }
+
+    // The history holds at most one triple per slot of a `count`-boid sim.
+    pub fn visit_state(&mut self, codec: &mut dyn FieldCodec, count: usize) {
+        let mut len = self.applied.len();
+        codec.usize(&mut len);
+        if len > 3 * count || !len.is_multiple_of(3) {
+            codec.malformed();
+            len = (len / 3).min(count) * 3;
+        }
+        self.applied.resize(len, 0.0);
+        codec.f32_slice(&mut self.applied);
+    }
+}
+
+impl Sim {
+    // Moves each boid's force in the accel arrays at most `rate * max_force *
+    // dt` from the one it applied last step. Slots without history take their
+    // force as is.
+    pub(super) fn apply_accel_ramp(&mut self, dt: f32) {
+        let ramp = &mut self.accel_ramp;
+        if !ramp.enabled() {
+            return;
+        }
+        let known = ramp.applied.len() / 3;
+        let count = self.boid_ids.len();
+        if known != count {
+            ramp.applied.resize(3 * count, 0.0);
+            for i in known..count {
+                ramp.applied[3 * i..3 * i + 3].copy_from_slice(&[
+                    self.accel_x[i],
+                    self.accel_y[i],
+                    self.accel_z[i],
+                ]);
+            }
+        }
+        let max_step = ramp.rate * self.config.max_force * dt;
+        for i in 0..self.active_count {
+            let previous = &mut ramp.applied[3 * i..3 * i + 3];
+            let dx = self.accel_x[i] - previous[0];
+            let dy = self.accel_y[i] - previous[1];
+            let dz = self.accel_z[i] - previous[2];
+            let change = (dx * dx + dy * dy + dz * dz).sqrt();
+            if change > max_step {
+                let keep = max_step / change;
+                self.accel_x[i] = previous[0] + dx * keep;
+                self.accel_y[i] = previous[1] + dy * keep;
+                self.accel_z[i] = previous[2] + dz * keep;
+            }
+            previous.copy_from_slice(&[self.accel_x[i], self.accel_y[i], self.accel_z[i]]);
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl Sim {
+    // Largest change in applied force per second, in multiples of max force,
+    // clamped to [0, 100]; 0 turns the ramp off and forgets its history.
+    pub fn set_accel_ramp(&mut self, rate: f32) {
+        self.accel_ramp.rate = clamp_finite(rate, 0.0, MAX_ACCEL_RAMP, 0.0);
+        if !self.accel_ramp.enabled() {
+            self.accel_ramp.applied = Vec::new();
+        }
+    }
+
+    pub fn accel_ramp(&self) -> f32 {
+        self.accel_ramp.rate
+    }
+}
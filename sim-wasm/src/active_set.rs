@@ -0,0 +1,176 @@
+use crate::Sim;
+use wasm_bindgen::prelude::*;
+
+// Handles pack a generation above the boid id so a reference to a boid that
+// was despawned or respawned stops resolving instead of silently pointing at
+// whatever fills its storage next. Generations wrap after 1024 reuses.
+const HANDLE_ID_BITS: u32 = 22;
+const HANDLE_ID_MASK: u32 = (1 << HANDLE_ID_BITS) - 1;
+const HANDLE_GENERATION_MASK: u32 = u32::MAX >> HANDLE_ID_BITS;
+
+impl Sim {
+    // Exchanges every piece of per-boid state between two storage slots,
+    // including the render buffers so frozen boids keep drawing in place.
+    fn swap_boids(&mut self, a: usize, b: usize) {
+        if a == b {
+            return;
+        }
+        for values in [
+            &mut self.pos_x,
+            &mut self.pos_y,
+            &mut self.pos_z,
+            &mut self.vel_x,
+            &mut self.vel_y,
+            &mut self.vel_z,
+            &mut self.heading_x,
+            &mut self.heading_y,
+            &mut self.heading_z,
+            &mut self.accel_x,
+            &mut self.accel_y,
+            &mut self.accel_z,
+            &mut self.render_z,
+            &mut self.render_crowding,
+            &mut self.render_scale,
+            &mut self.boid_size,
+            &mut self.variation,
+            &mut self.external_scalar,
+            &mut self.altitude_integral,
+            &mut self.evasion_timer,
+            &mut self.reaction_scale,
+        ] {
+            values.swap(a, b);
+        }
+        for axis in 0..3 {
+            self.evasion_xyz.swap(3 * a + axis, 3 * b + axis);
+        }
+        for values in [&mut self.render_xy, &mut self.render_heading_xy] {
+            values.swap(2 * a, 2 * b);
+            values.swap(2 * a + 1, 2 * b + 1);
+        }
+        for channel in 0..4 {
+            self.color_map.rgba.swap(4 * a + channel, 4 * b + channel);
+        }
+        self.heading_smoothing.swap(a, b);
+        self.neighbor_memory.swap(a, b);
+        self.familiarity.swap(a, b);
+        self.accel_ramp.swap(a, b);
+        self.boundary_hit_flags.swap(a, b);
+        self.lod_tiers.swap(a, b);
+        self.species.swap(a, b);
+        self.behavior.states.swap(a, b);
+        self.role.swap(a, b);
+        self.boid_ids.swap(a, b);
+        self.boid_slots[self.boid_ids[a] as usize] = a as u32;
+        self.boid_slots[self.boid_ids[b] as usize] = b as u32;
+    }
+
+    fn handle_of_id(&self, id: u32) -> Option<u32> {
+        let generation = *self.boid_generations.get(id as usize)?;
+        (id <= HANDLE_ID_MASK).then_some(generation << HANDLE_ID_BITS | id)
+    }
+
+    fn id_of_handle(&self, handle: u32) -> Option<u32> {
+        let id = handle & HANDLE_ID_MASK;
+        let generation = *self.boid_generations.get(id as usize)?;
+        (generation == handle >> HANDLE_ID_BITS).then_some(id)
+    }
+
+    fn bump_generation(&mut self, id: u32) {
+        let generation = &mut self.boid_generations[id as usize];
+        *generation = (*generation + 1) & HANDLE_GENERATION_MASK;
+    }
+
+    pub(super) fn rebuild_boid_slots(&mut self) {
+        for (slot, &id) in self.boid_ids.iter().enumerate() {
+            if let Some(entry) = self.boid_slots.get_mut(id as usize) {
+                *entry = slot as u32;
+            }
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl Sim {
+    // Simulates exactly the boids with these ids and freezes the rest. Storage
+    // is reordered so the chosen boids fill the active prefix in the given
+    // order; `boid_ids` maps each slot back to its id. Unknown and repeated ids
+    // are ignored. Snapshots are cleared because they assume the old order.
+    pub fn set_active_indices(&mut self, ids: &[u32]) {
+        let mut active = 0;
+        for &id in ids {
+            let Some(&slot) = self.boid_slots.get(id as usize) else {
+                continue;
+            };
+            let slot = slot as usize;
+            if slot < active {
+                continue;
+            }
+            self.swap_boids(active, slot);
+            active += 1;
+        }
+        self.active_count = active;
+        self.clear_snapshots();
+    }
+
+    pub fn boid_ids_ptr(&self) -> *const u32 {
+        self.boid_ids.as_ptr()
+    }
+
+    pub fn boid_ids_len(&self) -> usize {
+        self.boid_ids.len()
+    }
+
+    pub fn boid_slot(&self, id: u32) -> Option<u32> {
+        self.boid_slots.get(id as usize).copied()
+    }
+
+    // Persistent reference to the boid in `slot`, valid until that boid is
+    // despawned or respawned, however storage gets reordered meanwhile.
+    pub fn boid_handle(&self, slot: usize) -> Option<u32> {
+        self.handle_of_id(*self.boid_ids.get(slot)?)
+    }
+
+    // Current slot of a handle's boid, or None once the handle went stale.
+    pub fn handle_slot(&self, handle: u32) -> Option<u32> {
+        self.boid_slot(self.id_of_handle(handle)?)
+    }
+
+    pub fn handle_is_live(&self, handle: u32) -> bool {
+        self.id_of_handle(handle).is_some()
+    }
+
+    // Activates the first inactive slot as a freshly spawned boid and returns
+    // its handle, or None at capacity. Handles to the boid that was frozen
+    // there go stale.
+    pub fn spawn(&mut self) -> Option<u32> {
+        if self.active_count >= self.count {
+            return None;
+        }
+        let slot = self.active_count;
+        self.respawn_boid(slot);
+        let id = self.boid_ids[slot];
+        self.bump_generation(id);
+        self.active_count += 1;
+        self.spawned_since_step += 1;
+        self.handle_of_id(id)
+    }
+
+    // Moves the handle's boid to the end of the active prefix and shrinks the
+    // prefix past it, so the active boids stay packed; the boid that was last
+    // takes its slot. Returns false for a stale or inactive handle.
+    pub fn despawn(&mut self, handle: u32) -> bool {
+        let Some(id) = self.id_of_handle(handle) else {
+            return false;
+        };
+        let slot = self.boid_slots[id as usize] as usize;
+        if slot >= self.active_count {
+            return false;
+        }
+        self.active_count -= 1;
+        self.swap_boids(slot, self.active_count);
+        self.bump_generation(id);
+        self.despawned_since_step += 1;
+        self.clear_snapshots();
+        true
+    }
+}
@@ -0,0 +1,80 @@
+use crate::flock2::FLOCK2_WORLD_SCALE;
+use crate::{axis_delta, clamp_finite, Sim};
+use wasm_bindgen::prelude::*;
+
+const MIN_ALTITUDE_GAIN: f32 = 0.0;
+const MAX_ALTITUDE_GAIN: f32 = 50.0;
+const DEFAULT_ALTITUDE_TARGET: f32 = 0.5;
+const DEFAULT_ALTITUDE_KP: f32 = 4.0;
+const DEFAULT_ALTITUDE_KI: f32 = 0.5;
+const DEFAULT_ALTITUDE_KD: f32 = 2.5;
+// Bounds on the commanded vertical acceleration and on the integral term, in
+// flight-model units, so a far-off target cannot wind the controller up.
+const MAX_ALTITUDE_ACCEL: f32 = 20.0;
+const MAX_ALTITUDE_INTEGRAL: f32 = 10.0;
+
+// Altitude is world y, the axis gravity acts along in the flight models.
+#[derive(Clone, Copy)]
+pub struct AltitudeHold {
+    pub enabled: bool,
+    pub target: f32,
+    pub kp: f32,
+    pub ki: f32,
+    pub kd: f32,
+}
+
+impl Default for AltitudeHold {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            target: DEFAULT_ALTITUDE_TARGET,
+            kp: DEFAULT_ALTITUDE_KP,
+            ki: DEFAULT_ALTITUDE_KI,
+            kd: DEFAULT_ALTITUDE_KD,
+        }
+    }
+}
+
+impl Sim {
+    // Vertical acceleration from the PID controller for boid `i`. The error is
+    // converted to flight-model units so the gains act on metres and m/s.
+    pub(super) fn altitude_hold_accel(&mut self, i: usize, dt: f32) -> f32 {
+        let hold = self.altitude_hold;
+        if !hold.enabled {
+            return 0.0;
+        }
+        let error = axis_delta(hold.target - self.pos_y[i], !self.bounce_y) / FLOCK2_WORLD_SCALE;
+        let integral = &mut self.altitude_integral[i];
+        *integral = (*integral + error * dt).clamp(-MAX_ALTITUDE_INTEGRAL, MAX_ALTITUDE_INTEGRAL);
+        let accel = hold.kp * error + hold.ki * *integral - hold.kd * self.vel_y[i];
+        accel.clamp(-MAX_ALTITUDE_ACCEL, MAX_ALTITUDE_ACCEL)
+    }
+}
+
+#[wasm_bindgen]
+impl Sim {
+    // Holds the flight models at `target` world height. Gains act on the
+    // height error, its integral and the vertical speed; any change resets
+    // the accumulated integral.
+    pub fn set_altitude_hold(&mut self, enabled: bool, target: f32, kp: f32, ki: f32, kd: f32) {
+        let gain = |value: f32, fallback: f32| {
+            clamp_finite(value, MIN_ALTITUDE_GAIN, MAX_ALTITUDE_GAIN, fallback)
+        };
+        self.altitude_hold = AltitudeHold {
+            enabled,
+            target: clamp_finite(target, 0.0, 1.0, DEFAULT_ALTITUDE_TARGET),
+            kp: gain(kp, DEFAULT_ALTITUDE_KP),
+            ki: gain(ki, DEFAULT_ALTITUDE_KI),
+            kd: gain(kd, DEFAULT_ALTITUDE_KD),
+        };
+        self.altitude_integral.fill(0.0);
+    }
+
+    pub fn altitude_hold_enabled(&self) -> bool {
+        self.altitude_hold.enabled
+    }
+
+    pub fn altitude_hold_target(&self) -> f32 {
+        self.altitude_hold.target
+    }
+}
@@ -0,0 +1,372 @@
+use crate::math::MathPath;
+use crate::recording::FieldCodec;
+use crate::{axis_delta, clamp_finite, steer_towards_3d, Sim, DEFAULT_Z_LAYER};
+use wasm_bindgen::prelude::*;
+
+const BEHAVIOR_STATE_COUNT: usize = 5;
+const MAX_BEHAVIOR_WEIGHT: f32 = 8.0;
+const MIN_LANDING_RADIUS: f32 = 0.001;
+const MAX_LANDING_RADIUS: f32 = 0.25;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BehaviorState {
+    Wander,
+    Flock,
+    Flee,
+    Seek,
+    Perch,
+}
+
+impl BehaviorState {
+    pub fn from_u32(value: u32) -> Self {
+        match value {
+            0 => Self::Wander,
+            2 => Self::Flee,
+            3 => Self::Seek,
+            4 => Self::Perch,
+            _ => Self::Flock,
+        }
+    }
+
+    pub fn as_u32(self) -> u32 {
+        match self {
+            Self::Wander => 0,
+            Self::Flock => 1,
+            Self::Flee => 2,
+            Self::Seek => 3,
+            Self::Perch => 4,
+        }
+    }
+}
+
+// Multipliers on the classic weights while in a state, plus the absolute
+// weight of the pull toward the goal or landing point.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StateWeights {
+    pub separation: f32,
+    pub alignment: f32,
+    pub cohesion: f32,
+    pub jitter: f32,
+    pub seek: f32,
+}
+
+impl StateWeights {
+    const NEUTRAL: Self = Self {
+        separation: 1.0,
+        alignment: 1.0,
+        cohesion: 1.0,
+        jitter: 1.0,
+        seek: 0.0,
+    };
+
+    fn defaults(state: BehaviorState) -> Self {
+        match state {
+            BehaviorState::Wander => Self {
+                alignment: 0.25,
+                cohesion: 0.25,
+                jitter: 2.0,
+                ..Self::NEUTRAL
+            },
+            BehaviorState::Flock => Self::NEUTRAL,
+            BehaviorState::Flee => Self {
+                cohesion: 0.25,
+                ..Self::NEUTRAL
+            },
+            BehaviorState::Seek => Self {
+                alignment: 0.5,
+                cohesion: 0.5,
+                seek: 1.5,
+                ..Self::NEUTRAL
+            },
+            BehaviorState::Perch => Self {
+                separation: 0.0,
+                alignment: 0.0,
+                cohesion: 0.0,
+                jitter: 0.0,
+                seek: 0.0,
+            },
+        }
+    }
+}
+
+// Per-boid states for the classic model. Each refreshed boid re-evaluates its
+// state before steering: a predator in range means FLEE; an active landing
+// command means SEEK toward it until within the landing radius, then PERCH
+// (perched boids hold still); a goal means SEEK; otherwise boids FLOCK, or
+// WANDER if they had no neighbors on their previous refresh.
+pub struct Behavior {
+    pub enabled: bool,
+    pub states: Vec<u8>,
+    weights: [StateWeights; BEHAVIOR_STATE_COUNT],
+    goal: Option<[f32; 3]>,
+    // x, y, z and the landing radius.
+    landing: Option<[f32; 4]>,
+}
+
+impl Default for Behavior {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            states: Vec::new(),
+            weights: std::array::from_fn(|state| {
+                StateWeights::defaults(BehaviorState::from_u32(state as u32))
+            }),
+            goal: None,
+            landing: None,
+        }
+    }
+}
+
+impl Behavior {
+    pub fn resize(&mut self, count: usize) {
+        self.states
+            .resize(count, BehaviorState::Flock.as_u32() as u8);
+    }
+
+    pub fn state(&self, i: usize) -> BehaviorState {
+        BehaviorState::from_u32(u32::from(self.states[i]))
+    }
+
+    pub fn is_perched(&self, i: usize) -> bool {
+        self.enabled && self.state(i) == BehaviorState::Perch
+    }
+
+    pub fn weights(&self, i: usize) -> StateWeights {
+        if self.enabled {
+            self.weights[self.state(i).as_u32() as usize]
+        } else {
+            StateWeights::NEUTRAL
+        }
+    }
+
+    // Lone boids drift apart into WANDER and rejoin FLOCK once they see
+    // anyone; other states are left to the event rules.
+    pub fn settle_roaming(&mut self, i: usize, has_neighbors: bool) {
+        if !self.enabled {
+            return;
+        }
+        let next = match self.state(i) {
+            BehaviorState::Flock | BehaviorState::Wander if has_neighbors => BehaviorState::Flock,
+            BehaviorState::Flock | BehaviorState::Wander => BehaviorState::Wander,
+            state => state,
+        };
+        self.states[i] = next.as_u32() as u8;
+    }
+
+    pub fn visit_settings(&mut self, codec: &mut dyn FieldCodec) {
+        codec.bool(&mut self.enabled);
+        for weights in &mut self.weights {
+            codec.f32(&mut weights.separation);
+            codec.f32(&mut weights.alignment);
+            codec.f32(&mut weights.cohesion);
+            codec.f32(&mut weights.jitter);
+            codec.f32(&mut weights.seek);
+        }
+        let mut has_goal = self.goal.is_some();
+        codec.bool(&mut has_goal);
+        let mut goal = self.goal.unwrap_or_default();
+        codec.f32_slice(&mut goal);
+        self.goal = has_goal.then_some(goal);
+        let mut has_landing = self.landing.is_some();
+        codec.bool(&mut has_landing);
+        let mut landing = self.landing.unwrap_or_default();
+        codec.f32_slice(&mut landing);
+        self.landing = has_landing.then_some(landing);
+    }
+
+    pub fn visit_state(&mut self, codec: &mut dyn FieldCodec) {
+        for state in &mut self.states {
+            let mut raw = u32::from(*state);
+            codec.u32(&mut raw);
+            *state = BehaviorState::from_u32(raw).as_u32() as u8;
+        }
+    }
+}
+
+impl Sim {
+    fn offset_to(&self, i: usize, target: [f32; 3]) -> [f32; 3] {
+        [
+            axis_delta(target[0] - self.pos_x[i], !self.bounce_x),
+            axis_delta(target[1] - self.pos_y[i], !self.bounce_y),
+            if self.z_mode_enabled {
+                self.z_extent
+                    .delta(target[2] - self.pos_z[i], !self.bounce_z)
+            } else {
+                0.0
+            },
+        ]
+    }
+
+    pub(super) fn update_behavior_state(&mut self, i: usize) {
+        if !self.behavior.enabled {
+            return;
+        }
+        let current = self.behavior.state(i);
+        let next = if self.predator_threat(i).is_some() {
+            BehaviorState::Flee
+        } else if let Some(landing) = self.behavior.landing {
+            let [dx, dy, dz] = self.offset_to(i, [landing[0], landing[1], landing[2]]);
+            if current == BehaviorState::Perch
+                || dx * dx + dy * dy + dz * dz <= landing[3] * landing[3]
+            {
+                BehaviorState::Perch
+            } else {
+                BehaviorState::Seek
+            }
+        } else if self.behavior.goal.is_some() {
+            BehaviorState::Seek
+        } else {
+            match current {
+                BehaviorState::Wander => BehaviorState::Wander,
+                _ => BehaviorState::Flock,
+            }
+        };
+        self.behavior.states[i] = next.as_u32() as u8;
+    }
+
+    // Perched boids skip integration and sit at rest.
+    pub(super) fn hold_perched(&mut self, i: usize) -> bool {
+        if !self.behavior.is_perched(i) {
+            return false;
+        }
+        self.vel_x[i] = 0.0;
+        self.vel_y[i] = 0.0;
+        self.vel_z[i] = 0.0;
+        true
+    }
+
+    // Steering toward the landing point, or the goal without one, for boids
+    // whose state has a seek weight. Only the goal pull follows the attractor
+    // falloff; landings always pull until the boid perches.
+    pub(super) fn behavior_seek_force(&self, i: usize, vx: f32, vy: f32, vz: f32) -> [f32; 3] {
+        let (target, is_goal) = match (self.behavior.landing, self.behavior.goal) {
+            (Some(landing), _) => ([landing[0], landing[1], landing[2]], false),
+            (None, Some(goal)) => (goal, true),
+            (None, None) => return [0.0; 3],
+        };
+        let [dx, dy, dz] = self.offset_to(i, target);
+        let mut weight = self.behavior.weights(i).seek;
+        if is_goal {
+            weight *= self
+                .attractor_falloff
+                .scale((dx * dx + dy * dy + dz * dz).sqrt());
+        }
+        if weight <= 0.0 {
+            return [0.0; 3];
+        }
+        let (sx, sy, sz) = steer_towards_3d(
+            self.math(MathPath::Steering),
+            dx,
+            dy,
+            dz,
+            vx,
+            vy,
+            vz,
+            self.config.max_speed,
+        );
+        [sx * weight, sy * weight, sz * weight]
+    }
+}
+
+#[wasm_bindgen]
+impl Sim {
+    // Classic model only. Enabling or disabling resets every boid to FLOCK.
+    pub fn set_behavior_enabled(&mut self, enabled: bool) {
+        self.behavior.enabled = enabled;
+        self.behavior
+            .states
+            .fill(BehaviorState::Flock.as_u32() as u8);
+    }
+
+    pub fn behavior_enabled(&self) -> bool {
+        self.behavior.enabled
+    }
+
+    // States are 0 WANDER, 1 FLOCK, 2 FLEE, 3 SEEK, 4 PERCH. Multipliers and
+    // the seek weight are clamped to [0, 8].
+    pub fn set_behavior_weights(
+        &mut self,
+        state: u32,
+        separation: f32,
+        alignment: f32,
+        cohesion: f32,
+        jitter: f32,
+        seek: f32,
+    ) {
+        let Some(entry) = self.behavior.weights.get_mut(state as usize) else {
+            return;
+        };
+        let defaults = StateWeights::defaults(BehaviorState::from_u32(state));
+        let clamp =
+            |value: f32, fallback: f32| clamp_finite(value, 0.0, MAX_BEHAVIOR_WEIGHT, fallback);
+        *entry = StateWeights {
+            separation: clamp(separation, defaults.separation),
+            alignment: clamp(alignment, defaults.alignment),
+            cohesion: clamp(cohesion, defaults.cohesion),
+            jitter: clamp(jitter, defaults.jitter),
+            seek: clamp(seek, defaults.seek),
+        };
+    }
+
+    // Separation, alignment, cohesion, jitter and seek for `state`.
+    pub fn behavior_weights(&self, state: u32) -> Vec<f32> {
+        self.behavior
+            .weights
+            .get(state as usize)
+            .map_or_else(Vec::new, |w| {
+                vec![w.separation, w.alignment, w.cohesion, w.jitter, w.seek]
+            })
+    }
+
+    pub fn set_behavior_goal(&mut self, x: f32, y: f32, z: f32) {
+        self.behavior.goal = Some([
+            clamp_finite(x, 0.0, 1.0, 0.5),
+            clamp_finite(y, 0.0, 1.0, 0.5),
+            clamp_finite(z, 0.0, 1.0, DEFAULT_Z_LAYER),
+        ]);
+    }
+
+    pub fn clear_behavior_goal(&mut self) {
+        self.behavior.goal = None;
+    }
+
+    pub fn has_behavior_goal(&self) -> bool {
+        self.behavior.goal.is_some()
+    }
+
+    // Boids head for the point and perch once within `radius` of it. They stay
+    // perched until `command_takeoff`; a predator flushes them into FLEE and
+    // they come back once it has gone.
+    pub fn command_landing(&mut self, x: f32, y: f32, z: f32, radius: f32) {
+        self.behavior.landing = Some([
+            clamp_finite(x, 0.0, 1.0, 0.5),
+            clamp_finite(y, 0.0, 1.0, 0.5),
+            clamp_finite(z, 0.0, 1.0, DEFAULT_Z_LAYER),
+            clamp_finite(radius, MIN_LANDING_RADIUS, MAX_LANDING_RADIUS, 0.02),
+        ]);
+    }
+
+    pub fn command_takeoff(&mut self) {
+        self.behavior.landing = None;
+    }
+
+    pub fn landing_active(&self) -> bool {
+        self.behavior.landing.is_some()
+    }
+
+    pub fn behavior_state(&self, slot: usize) -> u32 {
+        self.behavior
+            .states
+            .get(slot)
+            .map_or(BehaviorState::Flock.as_u32(), |&state| u32::from(state))
+    }
+
+    // One byte per slot, updated as boids are steered.
+    pub fn behavior_states_ptr(&self) -> *const u8 {
+        self.behavior.states.as_ptr()
+    }
+
+    pub fn behavior_states_len(&self) -> usize {
+        self.behavior.states.len()
+    }
+}
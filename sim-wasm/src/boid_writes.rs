@@ -0,0 +1,97 @@
+use crate::flock2::normalize_or_default;
+use crate::{project_axis_position, Sim};
+use wasm_bindgen::prelude::*;
+
+// Channels `set_boids` can write, combined as bit flags. Each selected channel
+// takes x, y and z per boid, in this order.
+pub const BOID_WRITE_POSITION: u32 = 1;
+pub const BOID_WRITE_VELOCITY: u32 = 2;
+pub const BOID_WRITE_HEADING: u32 = 4;
+const BOID_WRITE_CHANNELS: [u32; 3] =
+    [BOID_WRITE_POSITION, BOID_WRITE_VELOCITY, BOID_WRITE_HEADING];
+
+// Non-finite inputs keep the boid's current value.
+fn finite_or(value: f32, current: f32) -> f32 {
+    if value.is_finite() {
+        value
+    } else {
+        current
+    }
+}
+
+impl Sim {
+    fn write_boid(&mut self, i: usize, layout: u32, values: &[f32]) {
+        let mut channels = values.chunks_exact(3).map(|v| [v[0], v[1], v[2]]);
+        let z_mode = self.z_mode_enabled;
+        if layout & BOID_WRITE_POSITION != 0 {
+            let [x, y, z] = channels.next().unwrap_or_default();
+            self.pos_x[i] = project_axis_position(finite_or(x, self.pos_x[i]), self.bounce_x);
+            self.pos_y[i] = project_axis_position(finite_or(y, self.pos_y[i]), self.bounce_y);
+            if z_mode {
+                self.pos_z[i] = self
+                    .z_extent
+                    .project(finite_or(z, self.pos_z[i]), self.bounce_z);
+            }
+        }
+        if layout & BOID_WRITE_VELOCITY != 0 {
+            let [x, y, z] = channels.next().unwrap_or_default();
+            self.vel_x[i] = finite_or(x, self.vel_x[i]);
+            self.vel_y[i] = finite_or(y, self.vel_y[i]);
+            self.vel_z[i] = if z_mode {
+                finite_or(z, self.vel_z[i])
+            } else {
+                0.0
+            };
+        }
+        let [hx, hy, hz] = if layout & BOID_WRITE_HEADING != 0 {
+            channels.next().unwrap_or_default()
+        } else {
+            [self.vel_x[i], self.vel_y[i], self.vel_z[i]]
+        };
+        if layout & (BOID_WRITE_HEADING | BOID_WRITE_VELOCITY) != 0 {
+            // A zero or broken heading keeps the old one.
+            let (hx, hy, hz) = normalize_or_default(
+                finite_or(hx, 0.0),
+                finite_or(hy, 0.0),
+                if z_mode { finite_or(hz, 0.0) } else { 0.0 },
+                self.heading_x[i],
+                self.heading_y[i],
+                self.heading_z[i],
+            );
+            self.heading_x[i] = hx;
+            self.heading_y[i] = hy;
+            self.heading_z[i] = hz;
+        }
+        self.settle_boid(i);
+    }
+}
+
+#[wasm_bindgen]
+impl Sim {
+    // Writes the `layout` channels for each slot in `indices`, reading
+    // consecutive x, y, z triples from `data` per boid. Positions wrap or
+    // clamp into the world, z is ignored outside z mode, and headings follow
+    // the written velocity unless written themselves. Velocities are in the
+    // current model's units, as `reseed` leaves them. Slots past the capacity
+    // are skipped along with their values; stops early if `data` runs out.
+    // Returns how many boids were written.
+    pub fn set_boids(&mut self, indices: &[u32], data: &[f32], layout: u32) -> usize {
+        let stride = 3 * BOID_WRITE_CHANNELS
+            .iter()
+            .filter(|&&channel| layout & channel != 0)
+            .count();
+        if stride == 0 {
+            return 0;
+        }
+        let mut written = 0;
+        for (&slot, values) in indices.iter().zip(data.chunks_exact(stride)) {
+            let slot = slot as usize;
+            if slot >= self.count {
+                continue;
+            }
+            self.write_boid(slot, layout, values);
+            written += 1;
+        }
+        written
+    }
+}
@@ -0,0 +1,151 @@
+use crate::math::{self, MathPath};
+use crate::recording::FieldCodec;
+use crate::{clamp_finite, Sim};
+use wasm_bindgen::prelude::*;
+
+const MIN_BURST_PERIOD_S: f32 = 0.1;
+const MAX_BURST_PERIOD_S: f32 = 10.0;
+const DEFAULT_BURST_PERIOD_S: f32 = 1.0;
+const MIN_BURST_DUTY: f32 = 0.05;
+const MAX_BURST_DUTY: f32 = 0.95;
+const DEFAULT_BURST_DUTY: f32 = 0.3;
+const MAX_BURST_STRENGTH: f32 = 4.0;
+
+// Burst-and-coast locomotion for the classic and flock2 flight models. Each
+// boid spends `duty` of every period pushing along its heading with
+// `strength` times the model's propulsion (max force in classic, thrust in
+// flock2) and the rest coasting, slowed by an equal and opposite impulse so
+// the mean speed holds. Boids sit at fixed offsets into the shared cycle so
+// the flock does not pulse in unison. Off while the strength is 0; fish keep
+// their own cycle.
+#[derive(Clone, Copy)]
+pub struct BurstCoast {
+    period_s: f32,
+    duty: f32,
+    strength: f32,
+    pub phase: f32,
+}
+
+impl Default for BurstCoast {
+    fn default() -> Self {
+        Self {
+            period_s: DEFAULT_BURST_PERIOD_S,
+            duty: DEFAULT_BURST_DUTY,
+            strength: 0.0,
+            phase: 0.0,
+        }
+    }
+}
+
+impl BurstCoast {
+    pub fn enabled(&self) -> bool {
+        self.strength > 0.0
+    }
+
+    pub fn advance(&mut self, dt: f32) {
+        if self.enabled() {
+            self.phase = (self.phase + dt / self.period_s).fract();
+        }
+    }
+
+    // Multiple of the model's propulsion added while bursting, and removed
+    // while coasting.
+    fn push(&self, bursting: bool) -> f32 {
+        if bursting {
+            self.strength
+        } else {
+            -self.strength * self.duty / (1.0 - self.duty)
+        }
+    }
+
+    pub fn visit_settings(&mut self, codec: &mut dyn FieldCodec) {
+        codec.f32(&mut self.period_s);
+        codec.f32(&mut self.duty);
+        codec.f32(&mut self.strength);
+    }
+}
+
+impl Sim {
+    pub(super) fn burst_coast_active(&self) -> bool {
+        self.burst_coast.enabled() && !self.fish_enabled()
+    }
+
+    fn bursting(&self, i: usize) -> bool {
+        let burst = &self.burst_coast;
+        (burst.phase + self.variation[i]).fract() < burst.duty
+    }
+
+    // Flock2 thrust for boid `i` at this point of its cycle; never negative.
+    pub(super) fn burst_coast_thrust(&self, i: usize, thrust: f32) -> f32 {
+        if !self.burst_coast.enabled() {
+            return thrust;
+        }
+        (thrust * (1.0 + self.burst_coast.push(self.bursting(i)))).max(0.0)
+    }
+
+    // Adds the burst or coast push along each boid's velocity to the classic
+    // steering result held in the accel arrays.
+    pub(super) fn apply_burst_coast(&mut self) {
+        let propulsion = self.config.max_force;
+        for i in 0..self.active_count {
+            // Stale accelerations already carry the push.
+            if !self.lod_refreshes(i) {
+                continue;
+            }
+            let vz = if self.z_mode_enabled {
+                self.vel_z[i]
+            } else {
+                0.0
+            };
+            let push = self.burst_coast.push(self.bursting(i)) * propulsion;
+            let (fx, fy, fz) = math::normalize_to_magnitude(
+                self.math(MathPath::Flight),
+                self.vel_x[i],
+                self.vel_y[i],
+                vz,
+                push.abs(),
+            );
+            let sign = push.signum();
+            self.accel_x[i] += fx * sign;
+            self.accel_y[i] += fy * sign;
+            if self.z_mode_enabled {
+                self.accel_z[i] += fz * sign;
+            }
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl Sim {
+    // Period is clamped to [0.1, 10] seconds, duty to [0.05, 0.95] and
+    // strength to [0, 4]; a strength of 0 turns the mode off.
+    pub fn set_burst_coast(&mut self, period_s: f32, duty: f32, strength: f32) {
+        let burst = &mut self.burst_coast;
+        burst.period_s = clamp_finite(
+            period_s,
+            MIN_BURST_PERIOD_S,
+            MAX_BURST_PERIOD_S,
+            DEFAULT_BURST_PERIOD_S,
+        );
+        burst.duty = clamp_finite(duty, MIN_BURST_DUTY, MAX_BURST_DUTY, DEFAULT_BURST_DUTY);
+        burst.strength = clamp_finite(strength, 0.0, MAX_BURST_STRENGTH, 0.0);
+    }
+
+    pub fn burst_period(&self) -> f32 {
+        self.burst_coast.period_s
+    }
+
+    pub fn burst_duty(&self) -> f32 {
+        self.burst_coast.duty
+    }
+
+    pub fn burst_strength(&self) -> f32 {
+        self.burst_coast.strength
+    }
+
+    // Whether the boid in `slot` is in the pushing part of its cycle; false
+    // while the mode is off or for slots past the active count.
+    pub fn boid_bursting(&self, slot: usize) -> bool {
+        self.burst_coast_active() && slot < self.active_count && self.bursting(slot)
+    }
+}
@@ -0,0 +1,122 @@
+use crate::recording::FieldCodec;
+use crate::{clamp_finite, Sim, EPSILON};
+use wasm_bindgen::prelude::*;
+
+const MAX_CULL_MARGIN: f32 = 1.0;
+
+// Column-major view-projection matrix, as WebGL expects it. While one is set,
+// every render sync writes clip-space x, y, z, w per boid and collects the
+// slots that land inside the frustum.
+pub struct Camera {
+    view_proj: Option<[f32; 16]>,
+    // Extra room past the frustum sides, as a fraction of w, so sprites
+    // straddling the screen edge are not culled.
+    cull_margin: f32,
+    pub clip_xyzw: Vec<f32>,
+    pub visible: Vec<u32>,
+    visible_count: usize,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            view_proj: None,
+            cull_margin: 0.0,
+            clip_xyzw: Vec::new(),
+            visible: Vec::new(),
+            visible_count: 0,
+        }
+    }
+}
+
+impl Camera {
+    pub fn resize(&mut self, count: usize) {
+        self.clip_xyzw.resize(count * 4, 0.0);
+        self.visible.resize(count, 0);
+    }
+
+    pub fn visit_settings(&mut self, codec: &mut dyn FieldCodec) {
+        let mut present = self.view_proj.is_some();
+        codec.bool(&mut present);
+        let mut matrix = self.view_proj.unwrap_or([0.0; 16]);
+        codec.f32_slice(&mut matrix);
+        self.view_proj = present.then_some(matrix);
+        codec.f32(&mut self.cull_margin);
+    }
+}
+
+impl Sim {
+    pub(super) fn sync_camera_outputs(&mut self) {
+        let camera = &mut self.camera;
+        let Some(m) = camera.view_proj else {
+            camera.visible_count = 0;
+            return;
+        };
+        let reach = 1.0 + camera.cull_margin;
+        let mut visible_count = 0;
+        for i in 0..self.active_count {
+            let (x, y, z) = (self.pos_x[i], self.pos_y[i], self.render_z[i]);
+            let clip = [
+                m[0] * x + m[4] * y + m[8] * z + m[12],
+                m[1] * x + m[5] * y + m[9] * z + m[13],
+                m[2] * x + m[6] * y + m[10] * z + m[14],
+                m[3] * x + m[7] * y + m[11] * z + m[15],
+            ];
+            camera.clip_xyzw[i * 4..i * 4 + 4].copy_from_slice(&clip);
+            let w = clip[3];
+            if w > EPSILON
+                && clip[0].abs() <= w * reach
+                && clip[1].abs() <= w * reach
+                && clip[2].abs() <= w
+            {
+                camera.visible[visible_count] = i as u32;
+                visible_count += 1;
+            }
+        }
+        camera.visible_count = visible_count;
+    }
+}
+
+#[wasm_bindgen]
+impl Sim {
+    // Takes 16 floats; anything else clears the camera.
+    pub fn set_camera(&mut self, view_proj: &[f32]) {
+        self.camera.view_proj = view_proj.try_into().ok();
+        self.sync_camera_outputs();
+    }
+
+    pub fn camera_enabled(&self) -> bool {
+        self.camera.view_proj.is_some()
+    }
+
+    pub fn set_camera_cull_margin(&mut self, margin: f32) {
+        self.camera.cull_margin = clamp_finite(margin, 0.0, MAX_CULL_MARGIN, 0.0);
+    }
+
+    pub fn camera_cull_margin(&self) -> f32 {
+        self.camera.cull_margin
+    }
+
+    // Four floats per slot, refreshed with the render buffers.
+    pub fn clip_positions_ptr(&self) -> *const f32 {
+        self.camera.clip_xyzw.as_ptr()
+    }
+
+    pub fn clip_positions_len(&self) -> usize {
+        self.camera.clip_xyzw.len()
+    }
+
+    // Slots inside the frustum in ascending order; only the first
+    // `visible_count` entries are meaningful.
+    pub fn visible_indices_ptr(&self) -> *const u32 {
+        self.camera.visible.as_ptr()
+    }
+
+    pub fn visible_indices_len(&self) -> usize {
+        self.camera.visible.len()
+    }
+
+    pub fn visible_count(&self) -> usize {
+        self.camera.visible_count
+    }
+}
@@ -0,0 +1,114 @@
+use crate::recording::FieldCodec;
+use crate::{clamp_finite, Sim, EPSILON};
+use wasm_bindgen::prelude::*;
+
+const MAX_CLASSIC_HEADING_TIME_CONSTANT_S: f32 = 5.0;
+const DEFAULT_CLASSIC_HEADING_TIME_CONSTANT_S: f32 = 0.1;
+
+// Keeps the heading arrays current under the classic and fish models, which
+// steer by velocity alone. After each integration a boid's heading eases
+// toward its velocity direction over `time_constant_s`, so readers of the
+// heading arrays get unit forward vectors whatever the model. Boids at rest
+// keep their last heading; a time constant of 0 takes the velocity direction
+// every step.
+pub struct ClassicHeadings {
+    time_constant_s: f32,
+}
+
+impl Default for ClassicHeadings {
+    fn default() -> Self {
+        Self {
+            time_constant_s: DEFAULT_CLASSIC_HEADING_TIME_CONSTANT_S,
+        }
+    }
+}
+
+impl ClassicHeadings {
+    fn blend(&self, dt: f32) -> f32 {
+        if self.time_constant_s > 0.0 {
+            1.0 - (-dt / self.time_constant_s).exp()
+        } else {
+            1.0
+        }
+    }
+
+    pub fn visit_settings(&mut self, codec: &mut dyn FieldCodec) {
+        codec.f32(&mut self.time_constant_s);
+    }
+}
+
+impl Sim {
+    pub(super) fn sync_classic_headings(&mut self, dt: f32) {
+        let blend = self.classic_headings.blend(dt);
+        for i in 0..self.active_count {
+            let vz = if self.z_mode_enabled {
+                self.vel_z[i]
+            } else {
+                0.0
+            };
+            let (vx, vy) = (self.vel_x[i], self.vel_y[i]);
+            let speed_sq = vx * vx + vy * vy + vz * vz;
+            if speed_sq <= EPSILON {
+                continue;
+            }
+            let inv_speed = speed_sq.sqrt().recip();
+            let target = (vx * inv_speed, vy * inv_speed, vz * inv_speed);
+            let hz = if self.z_mode_enabled {
+                self.heading_z[i]
+            } else {
+                0.0
+            };
+            let x = self.heading_x[i] + (target.0 - self.heading_x[i]) * blend;
+            let y = self.heading_y[i] + (target.1 - self.heading_y[i]) * blend;
+            let z = hz + (target.2 - hz) * blend;
+            let len_sq = x * x + y * y + z * z;
+            // Reversing passes through zero; snap to the velocity direction.
+            let (hx, hy, hz) = if len_sq > EPSILON {
+                let inv_len = len_sq.sqrt().recip();
+                (x * inv_len, y * inv_len, z * inv_len)
+            } else {
+                target
+            };
+            self.heading_x[i] = hx;
+            self.heading_y[i] = hy;
+            self.heading_z[i] = hz;
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl Sim {
+    // Time constant in seconds of the classic heading average, clamped to
+    // [0, 5]; 0 follows velocity exactly. Flock2 integrates its headings
+    // directly and ignores this.
+    pub fn set_classic_heading_smoothing(&mut self, time_constant_s: f32) {
+        self.classic_headings.time_constant_s = clamp_finite(
+            time_constant_s,
+            0.0,
+            MAX_CLASSIC_HEADING_TIME_CONSTANT_S,
+            DEFAULT_CLASSIC_HEADING_TIME_CONSTANT_S,
+        );
+    }
+
+    pub fn classic_heading_smoothing(&self) -> f32 {
+        self.classic_headings.time_constant_s
+    }
+
+    // Per-boid unit forward vectors for every model, one array per axis, each
+    // `heading_len` long. Only the first `active_count` entries are live.
+    pub fn heading_x_ptr(&self) -> *const f32 {
+        self.heading_x.as_ptr()
+    }
+
+    pub fn heading_y_ptr(&self) -> *const f32 {
+        self.heading_y.as_ptr()
+    }
+
+    pub fn heading_z_ptr(&self) -> *const f32 {
+        self.heading_z.as_ptr()
+    }
+
+    pub fn heading_len(&self) -> usize {
+        self.heading_x.len()
+    }
+}
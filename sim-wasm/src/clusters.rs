@@ -0,0 +1,548 @@
+use crate::neighbor_grid::NeighborGrid;
+use crate::{axis_delta, clamp_finite, Sim, DEFAULT_Z_LAYER, EPSILON, WORLD_SIZE};
+use wasm_bindgen::prelude::*;
+
+pub const NO_CLUSTER: u32 = u32::MAX;
+const MIN_CLUSTER_LINK_RADIUS: f32 = 0.005;
+const MAX_CLUSTER_LINK_RADIUS: f32 = 0.25;
+const DEFAULT_CLUSTER_LINK_RADIUS: f32 = 0.05;
+const MAX_CLUSTER_MIN_SIZE: u32 = 1_024;
+const DEFAULT_CLUSTER_MIN_SIZE: u32 = 2;
+pub const CLUSTER_SPLIT: u32 = 0;
+pub const CLUSTER_MERGE: u32 = 1;
+// Kind, cluster id, size, first part and part count.
+const CLUSTER_EVENT_STRIDE: usize = 5;
+
+// Connected components of the "within link radius" graph. Components smaller
+// than `min_size` are not clusters; their boids are labelled NO_CLUSTER.
+pub struct Clusters {
+    link_radius: f32,
+    min_size: u32,
+    grid: NeighborGrid,
+    parent: Vec<u32>,
+    pub labels: Vec<u32>,
+    pub sizes: Vec<u32>,
+    // Lowest boid index per cluster; centroids are measured relative to it so
+    // clusters straddling a wrapped edge stay contiguous.
+    anchors: Vec<u32>,
+    pub centroids: Vec<f32>,
+    pub mean_velocities: Vec<f32>,
+    pub bounding_radii: Vec<f32>,
+    pub milling: Vec<f32>,
+    // Labels by boid id and sizes from the previous detection, for events.
+    previous_labels: Vec<u32>,
+    previous_sizes: Vec<u32>,
+    has_previous: bool,
+    // Structure changes since the previous detection. A split names the old
+    // cluster and the new clusters it became; a merge names the new cluster
+    // and the old ones it absorbed. Parts are (cluster id, size) pairs.
+    pub events: Vec<u32>,
+    pub event_parts: Vec<u32>,
+}
+
+impl Clusters {
+    pub fn new(count: usize) -> Self {
+        Self {
+            link_radius: DEFAULT_CLUSTER_LINK_RADIUS,
+            min_size: DEFAULT_CLUSTER_MIN_SIZE,
+            grid: NeighborGrid::new(count, WORLD_SIZE, WORLD_SIZE, DEFAULT_CLUSTER_LINK_RADIUS),
+            parent: vec![0; count],
+            labels: vec![NO_CLUSTER; count],
+            sizes: Vec::new(),
+            anchors: Vec::new(),
+            centroids: Vec::new(),
+            mean_velocities: Vec::new(),
+            bounding_radii: Vec::new(),
+            milling: Vec::new(),
+            previous_labels: vec![NO_CLUSTER; count],
+            previous_sizes: Vec::new(),
+            has_previous: false,
+            events: Vec::new(),
+            event_parts: Vec::new(),
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.sizes.len()
+    }
+
+    pub fn resize(&mut self, count: usize) {
+        self.parent.resize(count, 0);
+        self.labels.resize(count, NO_CLUSTER);
+        self.previous_labels.resize(count, NO_CLUSTER);
+    }
+
+    pub fn event_count(&self) -> usize {
+        self.events.len() / CLUSTER_EVENT_STRIDE
+    }
+
+    fn count_events(&self, kind: u32) -> u32 {
+        self.events
+            .chunks_exact(CLUSTER_EVENT_STRIDE)
+            .filter(|event| event[0] == kind)
+            .count() as u32
+    }
+
+    fn push_event(&mut self, kind: u32, cluster: u32, size: u32, parts: &[(u32, u32)]) {
+        self.events.extend([
+            kind,
+            cluster,
+            size,
+            (self.event_parts.len() / 2) as u32,
+            parts.len() as u32,
+        ]);
+        for &(part, part_size) in parts {
+            self.event_parts.extend([part, part_size]);
+        }
+    }
+}
+
+fn find_root(parent: &mut [u32], mut i: u32) -> u32 {
+    while parent[i as usize] != i {
+        let grandparent = parent[parent[i as usize] as usize];
+        parent[i as usize] = grandparent;
+        i = grandparent;
+    }
+    i
+}
+
+impl Sim {
+    // Labels every active boid. Cluster ids follow the lowest boid index in
+    // each cluster, so they are stable while membership does not change.
+    pub(super) fn detect_clusters(&mut self) {
+        let n = self.active_count;
+        let wrap = (!self.bounce_x, !self.bounce_y, !self.bounce_z);
+        let z_mode = self.z_mode_enabled;
+        let z_extent = self.z_extent;
+        let pos_z = &self.pos_z;
+        let clusters = &mut self.clusters;
+        let radius = clusters.link_radius;
+
+        clusters.grid.set_cell_size(radius);
+        clusters
+            .grid
+            .rebuild(&self.pos_x[..n], &self.pos_y[..n], WORLD_SIZE, WORLD_SIZE);
+        for (i, parent) in clusters.parent[..n].iter_mut().enumerate() {
+            *parent = i as u32;
+        }
+
+        let parent = &mut clusters.parent;
+        for i in 0..n {
+            clusters
+                .grid
+                .for_each_neighbor_with_wrap(i, radius, wrap.0, wrap.1, |j| {
+                    if j > i {
+                        let dz = if z_mode {
+                            z_extent.delta(pos_z[j] - pos_z[i], wrap.2)
+                        } else {
+                            0.0
+                        };
+                        // The grid already checked the planar distance.
+                        if dz.abs() <= radius {
+                            let root_i = find_root(parent, i as u32);
+                            let root_j = find_root(parent, j as u32);
+                            parent[root_i.max(root_j) as usize] = root_i.min(root_j);
+                        }
+                    }
+                    true
+                });
+        }
+
+        // Roots are the lowest index of their component, so a single forward
+        // pass sees every root before its members.
+        let mut component_size = vec![0u32; n];
+        for i in 0..n {
+            let root = find_root(parent, i as u32);
+            component_size[root as usize] += 1;
+        }
+        clusters.sizes.clear();
+        clusters.anchors.clear();
+        for i in 0..n {
+            let root = find_root(parent, i as u32) as usize;
+            if component_size[root] < clusters.min_size {
+                clusters.labels[i] = NO_CLUSTER;
+            } else if root == i {
+                clusters.labels[i] = clusters.sizes.len() as u32;
+                clusters.sizes.push(component_size[root]);
+                clusters.anchors.push(i as u32);
+            } else {
+                clusters.labels[i] = clusters.labels[root];
+            }
+        }
+        clusters.labels[n..].fill(NO_CLUSTER);
+
+        self.record_cluster_events();
+        self.measure_clusters();
+    }
+
+    // Old and new clusters are linked when they share at least `min_size`
+    // boids, so a few boids changing groups is not a split or merge.
+    fn record_cluster_events(&mut self) {
+        let n = self.active_count;
+        let boid_ids = &self.boid_ids;
+        let clusters = &mut self.clusters;
+        clusters.events.clear();
+        clusters.event_parts.clear();
+        if clusters.has_previous {
+            let mut shared: Vec<(u32, u32)> = (0..n)
+                .filter_map(|i| {
+                    let old = clusters.previous_labels[boid_ids[i] as usize];
+                    let new = clusters.labels[i];
+                    (old != NO_CLUSTER && new != NO_CLUSTER).then_some((old, new))
+                })
+                .collect();
+            shared.sort_unstable();
+            let mut links: Vec<(u32, u32)> = shared
+                .chunk_by(|a, b| a == b)
+                .filter(|run| run.len() as u32 >= clusters.min_size)
+                .map(|run| run[0])
+                .collect();
+
+            for group in links.chunk_by(|a, b| a.0 == b.0) {
+                if group.len() > 1 {
+                    let old = group[0].0;
+                    let parts: Vec<(u32, u32)> = group
+                        .iter()
+                        .map(|&(_, new)| (new, clusters.sizes[new as usize]))
+                        .collect();
+                    let size = clusters.previous_sizes[old as usize];
+                    clusters.push_event(CLUSTER_SPLIT, old, size, &parts);
+                }
+            }
+            links.sort_unstable_by_key(|&(old, new)| (new, old));
+            for group in links.chunk_by(|a, b| a.1 == b.1) {
+                if group.len() > 1 {
+                    let new = group[0].1;
+                    let parts: Vec<(u32, u32)> = group
+                        .iter()
+                        .map(|&(old, _)| (old, clusters.previous_sizes[old as usize]))
+                        .collect();
+                    let size = clusters.sizes[new as usize];
+                    clusters.push_event(CLUSTER_MERGE, new, size, &parts);
+                }
+            }
+        }
+
+        clusters.previous_labels.fill(NO_CLUSTER);
+        for (&id, &label) in boid_ids[..n].iter().zip(&clusters.labels) {
+            clusters.previous_labels[id as usize] = label;
+        }
+        clusters.previous_sizes.clone_from(&clusters.sizes);
+        clusters.has_previous = true;
+    }
+
+    fn wrapped_offset(&self, from: usize, to: usize) -> [f32; 3] {
+        let dz = if self.z_mode_enabled {
+            self.z_extent
+                .delta(self.pos_z[to] - self.pos_z[from], !self.bounce_z)
+        } else {
+            0.0
+        };
+        [
+            axis_delta(self.pos_x[to] - self.pos_x[from], !self.bounce_x),
+            axis_delta(self.pos_y[to] - self.pos_y[from], !self.bounce_y),
+            dz,
+        ]
+    }
+
+    fn measure_clusters(&mut self) {
+        let cluster_count = self.clusters.count();
+        let mut offset_sums = vec![[0.0_f32; 3]; cluster_count];
+        for i in 0..self.active_count {
+            let label = self.clusters.labels[i];
+            if label == NO_CLUSTER {
+                continue;
+            }
+            let anchor = self.clusters.anchors[label as usize] as usize;
+            let offset = self.wrapped_offset(anchor, i);
+            for axis in 0..3 {
+                offset_sums[label as usize][axis] += offset[axis];
+            }
+        }
+
+        // Subtracting the mean anchor offset gives each boid's lever arm about
+        // its cluster centroid.
+        let mut momentum = vec![[0.0_f32; 3]; cluster_count];
+        let mut lever_speed = vec![0.0_f32; cluster_count];
+        let mut velocity_sums = vec![[0.0_f32; 3]; cluster_count];
+        let mut max_lever_sq = vec![0.0_f32; cluster_count];
+        for i in 0..self.active_count {
+            let label = self.clusters.labels[i];
+            if label == NO_CLUSTER {
+                continue;
+            }
+            let c = label as usize;
+            let inv_size = 1.0 / self.clusters.sizes[c] as f32;
+            let offset = self.wrapped_offset(self.clusters.anchors[c] as usize, i);
+            let rx = offset[0] - offset_sums[c][0] * inv_size;
+            let ry = offset[1] - offset_sums[c][1] * inv_size;
+            let rz = offset[2] - offset_sums[c][2] * inv_size;
+            let vx = self.vel_x[i];
+            let vy = self.vel_y[i];
+            let vz = if self.z_mode_enabled {
+                self.vel_z[i]
+            } else {
+                0.0
+            };
+            momentum[c][0] += ry * vz - rz * vy;
+            momentum[c][1] += rz * vx - rx * vz;
+            momentum[c][2] += rx * vy - ry * vx;
+            let lever_sq = rx * rx + ry * ry + rz * rz;
+            lever_speed[c] += lever_sq.sqrt() * (vx * vx + vy * vy + vz * vz).sqrt();
+            max_lever_sq[c] = max_lever_sq[c].max(lever_sq);
+            velocity_sums[c][0] += vx;
+            velocity_sums[c][1] += vy;
+            velocity_sums[c][2] += vz;
+        }
+
+        let clusters = &mut self.clusters;
+        clusters.centroids.clear();
+        clusters.mean_velocities.clear();
+        clusters.bounding_radii.clear();
+        clusters.milling.clear();
+        for c in 0..cluster_count {
+            let anchor = clusters.anchors[c] as usize;
+            let inv_size = 1.0 / clusters.sizes[c] as f32;
+            clusters.centroids.extend([
+                (self.pos_x[anchor] + offset_sums[c][0] * inv_size).rem_euclid(WORLD_SIZE),
+                (self.pos_y[anchor] + offset_sums[c][1] * inv_size).rem_euclid(WORLD_SIZE),
+                if self.z_mode_enabled {
+                    self.z_extent
+                        .project(self.pos_z[anchor] + offset_sums[c][2] * inv_size, false)
+                } else {
+                    DEFAULT_Z_LAYER
+                },
+            ]);
+            clusters
+                .mean_velocities
+                .extend(velocity_sums[c].map(|sum| sum * inv_size));
+            clusters.bounding_radii.push(max_lever_sq[c].sqrt());
+
+            let [lx, ly, lz] = momentum[c];
+            let magnitude = (lx * lx + ly * ly + lz * lz).sqrt();
+            clusters.milling.push(if lever_speed[c] > EPSILON {
+                magnitude / lever_speed[c]
+            } else {
+                0.0
+            });
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl Sim {
+    pub fn set_cluster_link_radius(&mut self, radius: f32) {
+        self.clusters.link_radius = clamp_finite(
+            radius,
+            MIN_CLUSTER_LINK_RADIUS,
+            MAX_CLUSTER_LINK_RADIUS,
+            DEFAULT_CLUSTER_LINK_RADIUS,
+        );
+    }
+
+    pub fn cluster_link_radius(&self) -> f32 {
+        self.clusters.link_radius
+    }
+
+    pub fn set_cluster_min_size(&mut self, min_size: u32) {
+        self.clusters.min_size = min_size.clamp(1, MAX_CLUSTER_MIN_SIZE);
+    }
+
+    pub fn cluster_min_size(&self) -> u32 {
+        self.clusters.min_size
+    }
+
+    // Runs detection on the current positions and returns the cluster count.
+    // Labels stay readable through `cluster_labels_ptr` until the next call.
+    pub fn detect_cluster_count(&mut self) -> usize {
+        self.detect_clusters();
+        self.clusters.count()
+    }
+
+    // Per-cluster buffers below all come from the latest detection and are
+    // indexed by cluster id; vector buffers hold xyz triples.
+    pub fn cluster_count(&self) -> usize {
+        self.clusters.count()
+    }
+
+    pub fn cluster_sizes_ptr(&self) -> *const u32 {
+        self.clusters.sizes.as_ptr()
+    }
+
+    pub fn cluster_centroids_ptr(&self) -> *const f32 {
+        self.clusters.centroids.as_ptr()
+    }
+
+    pub fn cluster_mean_velocities_ptr(&self) -> *const f32 {
+        self.clusters.mean_velocities.as_ptr()
+    }
+
+    // Distance from the centroid to the farthest member.
+    pub fn cluster_bounding_radii_ptr(&self) -> *const f32 {
+        self.clusters.bounding_radii.as_ptr()
+    }
+
+    // Id of the cluster with the most members, or NO_CLUSTER when none exist.
+    pub fn largest_cluster(&self) -> u32 {
+        let sizes = &self.clusters.sizes;
+        (0..sizes.len())
+            .max_by_key(|&c| (sizes[c], std::cmp::Reverse(c)))
+            .map_or(NO_CLUSTER, |c| c as u32)
+    }
+
+    // Normalized angular momentum about each cluster's centroid, from the
+    // latest detection: near 1 for a rotating mill or torus, near 0 for a
+    // polarized or disordered group.
+    pub fn cluster_milling_ptr(&self) -> *const f32 {
+        self.clusters.milling.as_ptr()
+    }
+
+    pub fn cluster_milling_len(&self) -> usize {
+        self.clusters.milling.len()
+    }
+
+    pub fn cluster_labels_ptr(&self) -> *const u32 {
+        self.clusters.labels.as_ptr()
+    }
+
+    pub fn cluster_labels_len(&self) -> usize {
+        self.clusters.labels.len()
+    }
+
+    // Splits and merges found by the latest detection, compared with the one
+    // before it. Events are five u32s each: kind (0 split, 1 merge), cluster
+    // id, its size, the index of its first part and the part count. A split
+    // names the old cluster and its parts are new clusters; a merge names the
+    // new cluster and its parts are old ones. Parts are (cluster id, size)
+    // pairs in `cluster_event_parts_ptr`.
+    pub fn cluster_event_count(&self) -> usize {
+        self.clusters.event_count()
+    }
+
+    pub fn cluster_events_ptr(&self) -> *const u32 {
+        self.clusters.events.as_ptr()
+    }
+
+    pub fn cluster_event_parts_ptr(&self) -> *const u32 {
+        self.clusters.event_parts.as_ptr()
+    }
+
+    pub fn cluster_event_parts_len(&self) -> usize {
+        self.clusters.event_parts.len()
+    }
+
+    pub fn cluster_splits(&self) -> u32 {
+        self.clusters.count_events(CLUSTER_SPLIT)
+    }
+
+    pub fn cluster_merges(&self) -> u32 {
+        self.clusters.count_events(CLUSTER_MERGE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CLUSTER_MERGE, CLUSTER_SPLIT, NO_CLUSTER};
+    use crate::Sim;
+
+    #[test]
+    fn link_radius_joins_chains_and_drops_singletons() {
+        let mut sim = Sim::new(6, 1, 1.0, 1.0);
+        let xs = [0.98, 0.02, 0.06, 0.5, 0.53, 0.8];
+        for (i, x) in xs.iter().enumerate() {
+            sim.pos_x[i] = *x;
+            sim.pos_y[i] = 0.5;
+        }
+        sim.set_cluster_link_radius(0.05);
+
+        // Boids 0-2 chain across the wrapped x edge.
+        assert_eq!(sim.detect_cluster_count(), 2);
+        assert_eq!(sim.clusters.labels[..5], [0, 0, 0, 1, 1]);
+        assert_eq!(sim.clusters.labels[5], NO_CLUSTER);
+        assert_eq!(sim.clusters.sizes, [3, 2]);
+
+        sim.set_cluster_min_size(3);
+        assert_eq!(sim.detect_cluster_count(), 1);
+    }
+
+    #[test]
+    fn cluster_stats_describe_each_group() {
+        let mut sim = Sim::new(5, 1, 1.0, 1.0);
+        sim.set_z_mode(false);
+        let positions = [
+            (0.2, 0.2),
+            (0.24, 0.2),
+            (0.7, 0.7),
+            (0.74, 0.7),
+            (0.72, 0.73),
+        ];
+        for (i, (x, y)) in positions.iter().enumerate() {
+            sim.pos_x[i] = *x;
+            sim.pos_y[i] = *y;
+            sim.vel_x[i] = i as f32;
+            sim.vel_y[i] = 0.0;
+        }
+
+        assert_eq!(sim.detect_cluster_count(), 2);
+        assert_eq!(sim.clusters.sizes, [2, 3]);
+        assert_eq!(sim.largest_cluster(), 1);
+        assert!((sim.clusters.centroids[0] - 0.22).abs() < 1e-5);
+        assert!((sim.clusters.mean_velocities[3] - 3.0).abs() < 1e-5);
+        assert!((sim.clusters.bounding_radii[0] - 0.02).abs() < 1e-5);
+    }
+
+    #[test]
+    fn milling_separates_rotation_from_polarized_motion() {
+        let mut sim = Sim::new(12, 1, 1.0, 1.0);
+        sim.set_z_mode(false);
+        for i in 0..12 {
+            let angle = i as f32 / 12.0 * std::f32::consts::TAU;
+            // The ring straddles the wrapped x edge.
+            sim.pos_x[i] = (0.01 + 0.03 * angle.cos()).rem_euclid(1.0);
+            sim.pos_y[i] = 0.5 + 0.03 * angle.sin();
+            sim.vel_x[i] = -angle.sin();
+            sim.vel_y[i] = angle.cos();
+        }
+        assert_eq!(sim.detect_cluster_count(), 1);
+        assert!(sim.clusters.milling[0] > 0.99);
+        assert!((sim.clusters.centroids[0] - 0.01).abs() < 1e-4);
+
+        for i in 0..12 {
+            sim.vel_x[i] = 1.0;
+            sim.vel_y[i] = 0.2;
+        }
+        sim.detect_cluster_count();
+        assert!(sim.clusters.milling[0] < 0.01);
+    }
+
+    #[test]
+    fn events_report_merges_and_splits() {
+        let mut sim = Sim::new(7, 1, 1.0, 1.0);
+        let place = |sim: &mut Sim, xs: [f32; 7]| {
+            for (i, x) in xs.iter().enumerate() {
+                sim.pos_x[i] = *x;
+                sim.pos_y[i] = 0.5;
+            }
+            sim.detect_cluster_count()
+        };
+        let apart = [0.2, 0.23, 0.26, 0.6, 0.63, 0.66, 0.69];
+        assert_eq!(place(&mut sim, apart), 2);
+        assert_eq!(sim.cluster_event_count(), 0);
+
+        let together = [0.2, 0.23, 0.26, 0.29, 0.32, 0.35, 0.38];
+        assert_eq!(place(&mut sim, together), 1);
+        assert_eq!((sim.cluster_merges(), sim.cluster_splits()), (1, 0));
+        assert_eq!(sim.clusters.events, [CLUSTER_MERGE, 0, 7, 0, 2]);
+        assert_eq!(sim.clusters.event_parts, [0, 3, 1, 4]);
+
+        assert_eq!(place(&mut sim, apart), 2);
+        assert_eq!((sim.cluster_merges(), sim.cluster_splits()), (0, 1));
+        assert_eq!(sim.clusters.events, [CLUSTER_SPLIT, 0, 7, 0, 2]);
+        assert_eq!(sim.clusters.event_parts, [0, 3, 1, 4]);
+
+        // One boid changing groups is neither.
+        let moved = [0.2, 0.23, 0.26, 0.29, 0.63, 0.66, 0.69];
+        assert_eq!(place(&mut sim, moved), 2);
+        assert_eq!(sim.cluster_event_count(), 0);
+    }
+}
@@ -0,0 +1,169 @@
+use crate::clusters::NO_CLUSTER;
+use crate::recording::FieldCodec;
+use crate::{Sim, EPSILON};
+use wasm_bindgen::prelude::*;
+
+const MAX_GRADIENT_STOPS: usize = 256;
+// Spreads consecutive cluster ids far apart along the gradient.
+const CLUSTER_HUE_STEP: f32 = 0.618_034;
+const DEFAULT_GRADIENT: [u32; 2] = [
+    u32::from_le_bytes([40, 90, 200, 255]),
+    u32::from_le_bytes([255, 170, 60, 255]),
+];
+
+// Scalar that picks each boid's position along the gradient.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorSource {
+    Off,
+    Speed,
+    Density,
+    Cluster,
+    Depth,
+}
+
+impl ColorSource {
+    pub fn from_u32(value: u32) -> Self {
+        match value {
+            1 => Self::Speed,
+            2 => Self::Density,
+            3 => Self::Cluster,
+            4 => Self::Depth,
+            _ => Self::Off,
+        }
+    }
+
+    pub fn as_u32(self) -> u32 {
+        match self {
+            Self::Off => 0,
+            Self::Speed => 1,
+            Self::Density => 2,
+            Self::Cluster => 3,
+            Self::Depth => 4,
+        }
+    }
+}
+
+// Gradient stops are packed RGBA, evenly spaced over [0, 1] and blended
+// linearly between neighbours.
+pub struct ColorMap {
+    source: ColorSource,
+    stops: Vec<u32>,
+    pub rgba: Vec<u8>,
+}
+
+impl Default for ColorMap {
+    fn default() -> Self {
+        Self {
+            source: ColorSource::Off,
+            stops: DEFAULT_GRADIENT.to_vec(),
+            rgba: Vec::new(),
+        }
+    }
+}
+
+impl ColorMap {
+    pub fn resize(&mut self, count: usize) {
+        self.rgba.resize(count * 4, 255);
+    }
+
+    fn sample(&self, t: f32) -> [u8; 4] {
+        let last = self.stops.len() - 1;
+        let position = t.clamp(0.0, 1.0) * last as f32;
+        let lower = (position.floor() as usize).min(last);
+        let upper = (lower + 1).min(last);
+        let blend = position - lower as f32;
+        let a = self.stops[lower].to_le_bytes();
+        let b = self.stops[upper].to_le_bytes();
+        std::array::from_fn(|c| {
+            (f32::from(a[c]) + (f32::from(b[c]) - f32::from(a[c])) * blend).round() as u8
+        })
+    }
+
+    pub fn visit_settings(&mut self, codec: &mut dyn FieldCodec) {
+        let mut source = self.source.as_u32();
+        codec.u32(&mut source);
+        self.source = ColorSource::from_u32(source);
+        let mut stops = self.stops.len();
+        codec.usize(&mut stops);
+        self.stops
+            .resize(stops.clamp(1, MAX_GRADIENT_STOPS), DEFAULT_GRADIENT[0]);
+        for stop in &mut self.stops {
+            codec.u32(stop);
+        }
+    }
+}
+
+impl Sim {
+    pub(super) fn sync_colors(&mut self) {
+        let source = self.color_map.source;
+        if source == ColorSource::Off {
+            return;
+        }
+        let max_speed = if self.model_kind.uses_flock2_units() {
+            self.flock2_config.max_speed
+        } else {
+            self.config.max_speed
+        };
+        for i in 0..self.active_count {
+            let t = match source {
+                ColorSource::Off => 0.0,
+                ColorSource::Speed => {
+                    let (vx, vy, vz) = (self.vel_x[i], self.vel_y[i], self.vel_z[i]);
+                    (vx * vx + vy * vy + vz * vz).sqrt() / max_speed.max(EPSILON)
+                }
+                ColorSource::Density => self.render_crowding[i],
+                ColorSource::Cluster => match self.clusters.labels[i] {
+                    NO_CLUSTER => 0.0,
+                    label => ((label + 1) as f32 * CLUSTER_HUE_STEP).fract(),
+                },
+                ColorSource::Depth => self.z_extent.fraction(self.render_z[i]),
+            };
+            let color = self.color_map.sample(t);
+            self.color_map.rgba[i * 4..i * 4 + 4].copy_from_slice(&color);
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl Sim {
+    // 0 off, 1 speed relative to max speed, 2 classic crowding, 3 cluster id
+    // from the latest detection (unclustered boids take the first stop),
+    // 4 depth through the z extent. While off the buffer is left untouched.
+    pub fn set_color_source(&mut self, source: u32) {
+        self.color_map.source = ColorSource::from_u32(source);
+        self.sync_colors();
+    }
+
+    pub fn color_source(&self) -> u32 {
+        self.color_map.source.as_u32()
+    }
+
+    // RGBA bytes, four per stop, at most 256 stops; a trailing partial stop
+    // is ignored and an empty upload restores the default gradient.
+    pub fn set_color_gradient(&mut self, rgba: &[u8]) {
+        let stops: Vec<u32> = rgba
+            .chunks_exact(4)
+            .take(MAX_GRADIENT_STOPS)
+            .map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+            .collect();
+        self.color_map.stops = if stops.is_empty() {
+            DEFAULT_GRADIENT.to_vec()
+        } else {
+            stops
+        };
+        self.sync_colors();
+    }
+
+    pub fn color_gradient_stops(&self) -> usize {
+        self.color_map.stops.len()
+    }
+
+    // Four bytes per slot, refreshed with the render buffers.
+    pub fn colors_ptr(&self) -> *const u8 {
+        self.color_map.rgba.as_ptr()
+    }
+
+    pub fn colors_len(&self) -> usize {
+        self.color_map.rgba.len()
+    }
+}
@@ -0,0 +1,119 @@
+use crate::config_report::ConfigAdjustment;
+use crate::flock2::Flock2Config;
+use crate::math::MathMode;
+use crate::{DragModel, SeparationKernel, Sim, SimConfig};
+use wasm_bindgen::prelude::*;
+
+// Both configs cross the boundary as classes with one property per field, or
+// as plain objects keyed in camelCase with enums by name. Keys missing from a
+// plain object take their defaults. Nothing is clamped until the config is
+// applied, so the applying call can report each adjustment.
+#[wasm_bindgen]
+impl SimConfig {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> SimConfig {
+        SimConfig::default()
+    }
+
+    #[wasm_bindgen(js_name = fromObject)]
+    pub fn from_object(value: JsValue) -> Result<SimConfig, JsValue> {
+        Ok(serde_wasm_bindgen::from_value(value)?)
+    }
+
+    #[wasm_bindgen(js_name = toObject)]
+    pub fn plain_object(&self) -> Result<JsValue, JsValue> {
+        Ok(serde_wasm_bindgen::to_value(self)?)
+    }
+
+    // One mode per math path, in `MathPath` order.
+    #[wasm_bindgen(getter = mathModes)]
+    pub fn math_mode_names(&self) -> Vec<String> {
+        self.math_modes
+            .iter()
+            .map(|mode| mode.name().to_string())
+            .collect()
+    }
+
+    // Unknown names and paths past the end leave their modes unchanged.
+    #[wasm_bindgen(setter = mathModes)]
+    pub fn set_math_mode_names(&mut self, names: Vec<String>) {
+        for (mode, name) in self.math_modes.iter_mut().zip(&names) {
+            if let Some(next) = MathMode::from_name(name) {
+                *mode = next;
+            }
+        }
+    }
+
+    #[wasm_bindgen(getter = dragModel)]
+    pub fn drag_model_name(&self) -> String {
+        self.drag_model.name().to_string()
+    }
+
+    #[wasm_bindgen(setter = dragModel)]
+    pub fn set_drag_model_name(&mut self, name: &str) {
+        if let Some(model) = DragModel::from_name(name) {
+            self.drag_model = model;
+        }
+    }
+
+    #[wasm_bindgen(getter = separationKernel)]
+    pub fn separation_kernel_name(&self) -> String {
+        self.separation_kernel.name().to_string()
+    }
+
+    #[wasm_bindgen(setter = separationKernel)]
+    pub fn set_separation_kernel_name(&mut self, name: &str) {
+        if let Some(kernel) = SeparationKernel::from_name(name) {
+            self.separation_kernel = kernel;
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl Flock2Config {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Flock2Config {
+        Flock2Config::default()
+    }
+
+    #[wasm_bindgen(js_name = fromObject)]
+    pub fn from_object(value: JsValue) -> Result<Flock2Config, JsValue> {
+        Ok(serde_wasm_bindgen::from_value(value)?)
+    }
+
+    #[wasm_bindgen(js_name = toObject)]
+    pub fn plain_object(&self) -> Result<JsValue, JsValue> {
+        Ok(serde_wasm_bindgen::to_value(self)?)
+    }
+}
+
+#[wasm_bindgen]
+impl Sim {
+    pub fn config(&self) -> SimConfig {
+        self.config
+    }
+
+    // Replaces the whole classic config, clamping like the individual setters
+    // and reporting every field it had to change.
+    pub fn apply_config(&mut self, config: &SimConfig) -> Vec<ConfigAdjustment> {
+        self.config = *config;
+        let mut report = Vec::new();
+        self.config.sanitize_reported(&mut report);
+        self.sync_neighbor_cell_size();
+        report
+    }
+
+    pub fn flock2_config(&self) -> Flock2Config {
+        self.flock2_config
+    }
+
+    // Replaces the social and flight configs together. Velocities keep their
+    // units; the flight update brings them inside new speed limits.
+    pub fn apply_flock2_config(&mut self, config: &Flock2Config) -> Vec<ConfigAdjustment> {
+        self.flock2_config = *config;
+        let mut report = Vec::new();
+        self.flock2_config.sanitize_reported(&mut report);
+        self.sync_neighbor_cell_size();
+        report
+    }
+}
@@ -0,0 +1,229 @@
+use crate::recording::FieldCodec;
+use crate::regions::{Region, RegionList};
+use crate::roles::RoleOverrides;
+use crate::{clamp_finite, Sim, WORLD_SIZE};
+use wasm_bindgen::prelude::*;
+
+pub const MAX_CONFIG_REGIONS: usize = 16;
+const MIN_REGION_RADIUS_SCALE: f32 = 0.25;
+const MAX_REGION_RADIUS_SCALE: f32 = 4.0;
+const MAX_REGION_WEIGHT_SCALE: f32 = 8.0;
+const MIN_REGION_SPEED_SCALE: f32 = 0.05;
+const MAX_REGION_SPEED_SCALE: f32 = 4.0;
+const MAX_REGION_FEATHER: f32 = 0.5 * WORLD_SIZE;
+
+// Multipliers a region applies to the classic config: the neighbor radius,
+// the separation, alignment and cohesion weights, and the speed limits.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ConfigScales {
+    pub radius: f32,
+    pub separation: f32,
+    pub alignment: f32,
+    pub cohesion: f32,
+    pub speed: f32,
+}
+
+impl Default for ConfigScales {
+    fn default() -> Self {
+        Self {
+            radius: 1.0,
+            separation: 1.0,
+            alignment: 1.0,
+            cohesion: 1.0,
+            speed: 1.0,
+        }
+    }
+}
+
+impl ConfigScales {
+    fn fields_mut(&mut self) -> [&mut f32; 5] {
+        [
+            &mut self.radius,
+            &mut self.separation,
+            &mut self.alignment,
+            &mut self.cohesion,
+            &mut self.speed,
+        ]
+    }
+
+    // Stacks on top of a role's overrides.
+    pub fn apply_to(self, role: RoleOverrides) -> RoleOverrides {
+        RoleOverrides {
+            radius_scale: role.radius_scale * self.radius,
+            separation: role.separation * self.separation,
+            alignment: role.alignment * self.alignment,
+            cohesion: role.cohesion * self.cohesion,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct ConfigRegion {
+    feather: f32,
+    scales: ConfigScales,
+}
+
+impl ConfigRegion {
+    // Share of the region's scales in effect at `distance` from its edge:
+    // a smoothstep across a band `feather` wide centred on the edge.
+    fn blend(&self, distance: f32) -> f32 {
+        if self.feather <= 0.0 {
+            return if distance < 0.0 { 1.0 } else { 0.0 };
+        }
+        let t = (0.5 - distance / self.feather).clamp(0.0, 1.0);
+        t * t * (3.0 - 2.0 * t)
+    }
+}
+
+// Environments painted onto the world. Where regions overlap their blended
+// scales multiply.
+pub struct ConfigRegions {
+    regions: RegionList<ConfigRegion>,
+}
+
+impl Default for ConfigRegions {
+    fn default() -> Self {
+        Self {
+            regions: RegionList::new(MAX_CONFIG_REGIONS),
+        }
+    }
+}
+
+impl ConfigRegions {
+    pub fn is_empty(&self) -> bool {
+        self.regions.is_empty()
+    }
+
+    pub fn visit_settings(&mut self, codec: &mut dyn FieldCodec) {
+        self.regions.visit_settings(codec, |region, codec| {
+            codec.f32(&mut region.feather);
+            for scale in region.scales.fields_mut() {
+                codec.f32(scale);
+            }
+        });
+    }
+}
+
+impl Sim {
+    // The role's overrides with the regions around boid `i` stacked on.
+    pub(super) fn classic_overrides(&self, i: usize) -> RoleOverrides {
+        let role = self.role_overrides(i);
+        if self.config_regions.is_empty() {
+            role
+        } else {
+            self.config_region_scales(i).apply_to(role)
+        }
+    }
+
+    pub(super) fn config_speed_scale(&self, i: usize) -> f32 {
+        if self.config_regions.is_empty() {
+            1.0
+        } else {
+            self.config_region_scales(i).speed
+        }
+    }
+
+    pub(super) fn config_region_scales(&self, i: usize) -> ConfigScales {
+        let mut scales = ConfigScales::default();
+        for entry in &self.config_regions.regions.entries {
+            let weight = entry.data.blend(self.region_distance(i, &entry.region).0);
+            if weight <= 0.0 {
+                continue;
+            }
+            let mut region = entry.data.scales;
+            for (total, scale) in scales.fields_mut().into_iter().zip(region.fields_mut()) {
+                *total *= 1.0 + (*scale - 1.0) * weight;
+            }
+        }
+        scales
+    }
+
+    fn add_config_region(&mut self, region: Region, feather: f32) -> i32 {
+        let feather = clamp_finite(feather, 0.0, MAX_REGION_FEATHER, 0.0);
+        self.config_regions.regions.add(
+            region,
+            ConfigRegion {
+                feather,
+                scales: ConfigScales::default(),
+            },
+        )
+    }
+}
+
+#[wasm_bindgen]
+impl Sim {
+    // Classic model only. New regions start neutral; give them scales with
+    // `set_config_region_scales`. `feather` is the width of the blend band
+    // across the edge, clamped to [0, 0.5]. Returns the new region's id, or
+    // -1 when the region is empty or `MAX_CONFIG_REGIONS` are in use.
+    pub fn add_config_circle(&mut self, x: f32, y: f32, radius: f32, feather: f32) -> i32 {
+        self.add_config_region(Region::circle(x, y, radius), feather)
+    }
+
+    pub fn add_config_rect(&mut self, x0: f32, y0: f32, x1: f32, y1: f32, feather: f32) -> i32 {
+        self.add_config_region(Region::rect(x0, y0, x1, y1), feather)
+    }
+
+    // The radius scale is clamped to [0.25, 4], the weight scales to [0, 8]
+    // and the speed scale, which covers both speed limits, to [0.05, 4].
+    // Returns false for an unknown id.
+    pub fn set_config_region_scales(
+        &mut self,
+        id: u32,
+        radius: f32,
+        separation: f32,
+        alignment: f32,
+        cohesion: f32,
+        speed: f32,
+    ) -> bool {
+        let Some(entry) = self.config_regions.regions.get_mut(id) else {
+            return false;
+        };
+        let weight = |value: f32| clamp_finite(value, 0.0, MAX_REGION_WEIGHT_SCALE, 1.0);
+        entry.data.scales = ConfigScales {
+            radius: clamp_finite(
+                radius,
+                MIN_REGION_RADIUS_SCALE,
+                MAX_REGION_RADIUS_SCALE,
+                1.0,
+            ),
+            separation: weight(separation),
+            alignment: weight(alignment),
+            cohesion: weight(cohesion),
+            speed: clamp_finite(speed, MIN_REGION_SPEED_SCALE, MAX_REGION_SPEED_SCALE, 1.0),
+        };
+        true
+    }
+
+    // Radius, separation, alignment, cohesion and speed scales for `id`.
+    pub fn config_region_scales_of(&self, id: u32) -> Vec<f32> {
+        self.config_regions
+            .regions
+            .get(id)
+            .map_or_else(Vec::new, |entry| {
+                let s = entry.data.scales;
+                vec![s.radius, s.separation, s.alignment, s.cohesion, s.speed]
+            })
+    }
+
+    pub fn remove_config_region(&mut self, id: u32) -> bool {
+        self.config_regions.regions.remove(id)
+    }
+
+    pub fn clear_config_regions(&mut self) {
+        self.config_regions.regions.clear();
+    }
+
+    pub fn config_region_count(&self) -> usize {
+        self.config_regions.regions.entries.len()
+    }
+
+    // The blended scales acting on `slot`, in the same order.
+    pub fn boid_config_scales(&self, slot: usize) -> Vec<f32> {
+        if slot >= self.active_count {
+            return Vec::new();
+        }
+        let s = self.config_region_scales(slot);
+        vec![s.radius, s.separation, s.alignment, s.cohesion, s.speed]
+    }
+}
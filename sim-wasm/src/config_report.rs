@@ -0,0 +1,145 @@
+use wasm_bindgen::prelude::*;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AdjustmentReason {
+    NonFinite,
+    BelowMin,
+    AboveMax,
+}
+
+impl AdjustmentReason {
+    pub fn as_u32(self) -> u32 {
+        match self {
+            Self::NonFinite => 0,
+            Self::BelowMin => 1,
+            Self::AboveMax => 2,
+        }
+    }
+}
+
+// One entry per field that `sanitize` had to change, so UIs can explain why a
+// supplied value was not applied verbatim.
+#[wasm_bindgen]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConfigAdjustment {
+    field: &'static str,
+    supplied: f32,
+    applied: f32,
+    reason: AdjustmentReason,
+}
+
+impl ConfigAdjustment {
+    pub fn reason_kind(&self) -> AdjustmentReason {
+        self.reason
+    }
+
+    pub fn field_name(&self) -> &'static str {
+        self.field
+    }
+}
+
+#[wasm_bindgen]
+impl ConfigAdjustment {
+    pub fn field(&self) -> String {
+        self.field.to_string()
+    }
+
+    pub fn supplied(&self) -> f32 {
+        self.supplied
+    }
+
+    pub fn applied(&self) -> f32 {
+        self.applied
+    }
+
+    pub fn reason(&self) -> u32 {
+        self.reason.as_u32()
+    }
+}
+
+pub fn clamp_reported(
+    report: &mut Vec<ConfigAdjustment>,
+    field: &'static str,
+    value: f32,
+    min: f32,
+    max: f32,
+    fallback: f32,
+) -> f32 {
+    let (applied, reason) = if !value.is_finite() {
+        (fallback, AdjustmentReason::NonFinite)
+    } else if value < min {
+        (min, AdjustmentReason::BelowMin)
+    } else if value > max {
+        (max, AdjustmentReason::AboveMax)
+    } else {
+        return value;
+    };
+
+    report.push(ConfigAdjustment {
+        field,
+        supplied: value,
+        applied,
+        reason,
+    });
+    applied
+}
+
+pub fn clamp_count_reported(
+    report: &mut Vec<ConfigAdjustment>,
+    field: &'static str,
+    value: usize,
+    min: usize,
+    max: usize,
+) -> usize {
+    let (applied, reason) = if value < min {
+        (min, AdjustmentReason::BelowMin)
+    } else if value > max {
+        (max, AdjustmentReason::AboveMax)
+    } else {
+        return value;
+    };
+
+    report.push(ConfigAdjustment {
+        field,
+        supplied: value as f32,
+        applied: applied as f32,
+        reason,
+    });
+    applied
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{clamp_count_reported, clamp_reported, AdjustmentReason};
+
+    #[test]
+    fn in_range_values_are_not_reported() {
+        let mut report = Vec::new();
+        assert_eq!(clamp_reported(&mut report, "x", 0.5, 0.0, 1.0, 0.2), 0.5);
+        assert_eq!(clamp_count_reported(&mut report, "n", 3, 1, 8), 3);
+        assert!(report.is_empty());
+    }
+
+    #[test]
+    fn out_of_range_values_record_reason() {
+        let mut report = Vec::new();
+        assert_eq!(
+            clamp_reported(&mut report, "a", f32::NAN, 0.0, 1.0, 0.2),
+            0.2
+        );
+        assert_eq!(clamp_reported(&mut report, "b", -1.0, 0.0, 1.0, 0.2), 0.0);
+        assert_eq!(clamp_count_reported(&mut report, "c", 99, 1, 8), 8);
+
+        let reasons: Vec<_> = report.iter().map(|entry| entry.reason_kind()).collect();
+        assert_eq!(
+            reasons,
+            vec![
+                AdjustmentReason::NonFinite,
+                AdjustmentReason::BelowMin,
+                AdjustmentReason::AboveMax
+            ]
+        );
+        assert_eq!(report[2].field_name(), "c");
+        assert_eq!(report[2].supplied(), 99.0);
+    }
+}
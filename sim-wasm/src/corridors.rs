@@ -0,0 +1,208 @@
+use crate::recording::FieldCodec;
+use crate::{axis_delta, clamp_finite, project_axis_position, Sim, EPSILON, WORLD_SIZE};
+use wasm_bindgen::prelude::*;
+
+pub const MAX_CORRIDORS: usize = 16;
+pub const MAX_CORRIDOR_POINTS: usize = 64;
+const MIN_CORRIDOR_WIDTH: f32 = 0.005;
+const MAX_CORRIDOR_WIDTH: f32 = WORLD_SIZE;
+const MAX_CORRIDOR_WEIGHT: f32 = 20.0;
+const DEFAULT_CORRIDOR_WEIGHT: f32 = 4.0;
+const DEFAULT_CORRIDOR_EDGE_FRACTION: f32 = 0.3;
+
+#[derive(Clone, Default)]
+struct Corridor {
+    id: u32,
+    points_xy: Vec<f32>,
+    half_width: f32,
+}
+
+// Thick polylines in the xy plane that boids must stay inside; with several,
+// anywhere inside one of them will do. Classic boids in the outer
+// `edge_fraction` of a corridor's half width steer back toward its
+// centerline, ramping up to `weight` at the border, and every model is
+// clamped onto the border if it still gets out.
+pub struct Corridors {
+    corridors: Vec<Corridor>,
+    next_id: u32,
+    weight: f32,
+    edge_fraction: f32,
+}
+
+impl Default for Corridors {
+    fn default() -> Self {
+        Self {
+            corridors: Vec::new(),
+            next_id: 0,
+            weight: DEFAULT_CORRIDOR_WEIGHT,
+            edge_fraction: DEFAULT_CORRIDOR_EDGE_FRACTION,
+        }
+    }
+}
+
+impl Corridors {
+    pub fn is_empty(&self) -> bool {
+        self.corridors.is_empty()
+    }
+
+    pub fn visit_settings(&mut self, codec: &mut dyn FieldCodec) {
+        codec.f32(&mut self.weight);
+        codec.f32(&mut self.edge_fraction);
+        codec.u32(&mut self.next_id);
+        let mut count = self.corridors.len();
+        codec.usize(&mut count);
+        self.corridors
+            .resize(count.min(MAX_CORRIDORS), Corridor::default());
+        for corridor in &mut self.corridors {
+            codec.u32(&mut corridor.id);
+            codec.f32(&mut corridor.half_width);
+            let mut values = corridor.points_xy.len();
+            codec.usize(&mut values);
+            corridor
+                .points_xy
+                .resize(values.min(MAX_CORRIDOR_POINTS * 2) / 2 * 2, 0.0);
+            codec.f32_slice(&mut corridor.points_xy);
+        }
+    }
+}
+
+impl Sim {
+    // Offset from the nearest centerline point to (x, y), and how far inside
+    // that corridor's border the point is (negative outside), for the
+    // corridor the point is deepest in.
+    pub(super) fn corridor_offset(&self, x: f32, y: f32) -> Option<([f32; 2], f32, f32)> {
+        let mut best: Option<([f32; 2], f32, f32)> = None;
+        for corridor in &self.corridors.corridors {
+            let points = &corridor.points_xy;
+            if points.len() < 2 {
+                continue;
+            }
+            let segments = (points.len() / 2).saturating_sub(1).max(1);
+            for s in 0..segments {
+                let a = [points[s * 2], points[s * 2 + 1]];
+                let b = points.get(s * 2 + 2..s * 2 + 4).map_or(a, |b| [b[0], b[1]]);
+                let to_point = [
+                    axis_delta(x - a[0], !self.bounce_x),
+                    axis_delta(y - a[1], !self.bounce_y),
+                ];
+                let along = [b[0] - a[0], b[1] - a[1]];
+                let length_sq = along[0] * along[0] + along[1] * along[1];
+                let u = if length_sq > EPSILON * EPSILON {
+                    ((to_point[0] * along[0] + to_point[1] * along[1]) / length_sq).clamp(0.0, 1.0)
+                } else {
+                    0.0
+                };
+                let offset = [to_point[0] - along[0] * u, to_point[1] - along[1] * u];
+                let distance = (offset[0] * offset[0] + offset[1] * offset[1]).sqrt();
+                let depth = corridor.half_width - distance;
+                if best.is_none_or(|(_, d, _)| depth > d) {
+                    best = Some((offset, depth, corridor.half_width));
+                }
+            }
+        }
+        best
+    }
+
+    // Classic pull back toward the centerline near a corridor's border.
+    pub(super) fn corridor_force(&self, i: usize) -> (f32, f32) {
+        let corridors = &self.corridors;
+        let Some((offset, depth, half_width)) = self.corridor_offset(self.pos_x[i], self.pos_y[i])
+        else {
+            return (0.0, 0.0);
+        };
+        let edge = half_width * corridors.edge_fraction;
+        if depth >= edge || edge <= EPSILON {
+            return (0.0, 0.0);
+        }
+        let distance = half_width - depth;
+        if distance <= EPSILON {
+            return (0.0, 0.0);
+        }
+        let strength = corridors.weight * (1.0 - depth.max(0.0) / edge) / distance;
+        (-offset[0] * strength, -offset[1] * strength)
+    }
+
+    // Puts boid `i` back on the border of the corridor it is closest to if it
+    // left every corridor, dropping the outward part of `velocity`, which is
+    // returned.
+    pub(super) fn confine_to_corridors(&mut self, i: usize, vx: f32, vy: f32) -> (f32, f32) {
+        let Some((offset, depth, half_width)) = self.corridor_offset(self.pos_x[i], self.pos_y[i])
+        else {
+            return (vx, vy);
+        };
+        if depth >= 0.0 {
+            return (vx, vy);
+        }
+        let distance = half_width - depth;
+        let normal = [offset[0] / distance, offset[1] / distance];
+        self.pos_x[i] = project_axis_position(self.pos_x[i] + normal[0] * depth, self.bounce_x);
+        self.pos_y[i] = project_axis_position(self.pos_y[i] + normal[1] * depth, self.bounce_y);
+        let outward = (vx * normal[0] + vy * normal[1]).max(0.0);
+        (vx - normal[0] * outward, vy - normal[1] * outward)
+    }
+}
+
+#[wasm_bindgen]
+impl Sim {
+    // Flat x, y centerline points in world units, at most
+    // `MAX_CORRIDOR_POINTS`; a single point makes a round pocket. `width` is
+    // the full thickness, clamped to [0.005, 1]. Returns the new corridor's
+    // id, or -1 without points or when `MAX_CORRIDORS` are in use.
+    pub fn add_corridor(&mut self, points_xy: &[f32], width: f32) -> i32 {
+        let corridors = &mut self.corridors;
+        if points_xy.len() < 2 || corridors.corridors.len() >= MAX_CORRIDORS {
+            return -1;
+        }
+        let points_xy = points_xy
+            .iter()
+            .take(MAX_CORRIDOR_POINTS * 2)
+            .map(|&v| clamp_finite(v, 0.0, 1.0, 0.5))
+            .collect::<Vec<_>>();
+        let id = corridors.next_id;
+        corridors.next_id += 1;
+        corridors.corridors.push(Corridor {
+            id,
+            points_xy: points_xy[..points_xy.len() / 2 * 2].to_vec(),
+            half_width: 0.5
+                * clamp_finite(
+                    width,
+                    MIN_CORRIDOR_WIDTH,
+                    MAX_CORRIDOR_WIDTH,
+                    MIN_CORRIDOR_WIDTH,
+                ),
+        });
+        id as i32
+    }
+
+    pub fn remove_corridor(&mut self, id: u32) -> bool {
+        let before = self.corridors.corridors.len();
+        self.corridors
+            .corridors
+            .retain(|corridor| corridor.id != id);
+        self.corridors.corridors.len() != before
+    }
+
+    pub fn clear_corridors(&mut self) {
+        self.corridors.corridors.clear();
+    }
+
+    pub fn corridor_count(&self) -> usize {
+        self.corridors.corridors.len()
+    }
+
+    // Weight is clamped to [0, 20] and the edge fraction to [0, 1].
+    pub fn set_corridor_steering(&mut self, weight: f32, edge_fraction: f32) {
+        self.corridors.weight =
+            clamp_finite(weight, 0.0, MAX_CORRIDOR_WEIGHT, DEFAULT_CORRIDOR_WEIGHT);
+        self.corridors.edge_fraction =
+            clamp_finite(edge_fraction, 0.0, 1.0, DEFAULT_CORRIDOR_EDGE_FRACTION);
+    }
+
+    pub fn corridor_weight(&self) -> f32 {
+        self.corridors.weight
+    }
+
+    pub fn corridor_edge_fraction(&self) -> f32 {
+        self.corridors.edge_fraction
+    }
+}
@@ -0,0 +1,136 @@
+use crate::recording::FieldCodec;
+use crate::regions::{Region, RegionList};
+use crate::{clamp_finite, Sim, WORLD_SIZE};
+use wasm_bindgen::prelude::*;
+
+pub const MAX_DANGER_ZONES: usize = 32;
+const MAX_DREAD_WEIGHT: f32 = 40.0;
+const DEFAULT_DREAD_WEIGHT: f32 = 8.0;
+const MIN_DANGER_MARGIN: f32 = 0.005;
+const MAX_DANGER_MARGIN: f32 = 0.5 * WORLD_SIZE;
+const DEFAULT_DANGER_MARGIN: f32 = 0.06;
+
+// Persistent regions the classic model steers out of and around. The dread
+// push ramps up over `margin` outside the edge like the obstacle push, is
+// at full weight everywhere inside, and defaults to twice the obstacle
+// weight; it still goes through the max force clamp. Each zone carries the
+// number of boids inside it after the latest step.
+pub struct DangerZones {
+    zones: RegionList<u32>,
+    dread_weight: f32,
+    margin: f32,
+}
+
+impl Default for DangerZones {
+    fn default() -> Self {
+        Self {
+            zones: RegionList::new(MAX_DANGER_ZONES),
+            dread_weight: DEFAULT_DREAD_WEIGHT,
+            margin: DEFAULT_DANGER_MARGIN,
+        }
+    }
+}
+
+impl DangerZones {
+    pub fn is_empty(&self) -> bool {
+        self.zones.is_empty()
+    }
+
+    pub fn visit_settings(&mut self, codec: &mut dyn FieldCodec) {
+        codec.f32(&mut self.dread_weight);
+        codec.f32(&mut self.margin);
+        self.zones.visit_settings(codec, |_, _| {});
+    }
+}
+
+impl Sim {
+    // Zones are flat, so the push has no z component.
+    pub(super) fn danger_zone_force(&self, i: usize) -> (f32, f32) {
+        let zones = &self.danger_zones;
+        let mut force = [0.0; 2];
+        for zone in &zones.zones.entries {
+            let (distance, normal) = self.region_distance(i, &zone.region);
+            if distance >= zones.margin {
+                continue;
+            }
+            let strength = zones.dread_weight * (1.0 - distance.max(0.0) / zones.margin);
+            force[0] += normal[0] * strength;
+            force[1] += normal[1] * strength;
+        }
+        (force[0], force[1])
+    }
+
+    pub(super) fn count_danger_zone_occupancy(&mut self) {
+        for z in 0..self.danger_zones.zones.entries.len() {
+            let region = self.danger_zones.zones.entries[z].region;
+            let occupancy = (0..self.active_count)
+                .filter(|&i| self.region_distance(i, &region).0 < 0.0)
+                .count();
+            self.danger_zones.zones.entries[z].data = occupancy as u32;
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl Sim {
+    // Zones live in the xy plane and span every depth. Returns the new zone's
+    // id, or -1 when the zone is empty or `MAX_DANGER_ZONES` are in use.
+    pub fn add_danger_circle(&mut self, x: f32, y: f32, radius: f32) -> i32 {
+        self.danger_zones.zones.add(Region::circle(x, y, radius), 0)
+    }
+
+    pub fn add_danger_rect(&mut self, x0: f32, y0: f32, x1: f32, y1: f32) -> i32 {
+        self.danger_zones.zones.add(Region::rect(x0, y0, x1, y1), 0)
+    }
+
+    pub fn remove_danger_zone(&mut self, id: u32) -> bool {
+        self.danger_zones.zones.remove(id)
+    }
+
+    pub fn clear_danger_zones(&mut self) {
+        self.danger_zones.zones.clear();
+    }
+
+    pub fn danger_zone_count(&self) -> usize {
+        self.danger_zones.zones.entries.len()
+    }
+
+    // Ids in insertion order, matching `danger_zone_occupancies`.
+    pub fn danger_zone_ids(&self) -> Vec<u32> {
+        self.danger_zones.zones.ids()
+    }
+
+    // Boids inside each zone after the latest step.
+    pub fn danger_zone_occupancies(&self) -> Vec<u32> {
+        self.danger_zones
+            .zones
+            .entries
+            .iter()
+            .map(|zone| zone.data)
+            .collect()
+    }
+
+    pub fn danger_zone_occupancy(&self, id: u32) -> u32 {
+        self.danger_zones.zones.get(id).map_or(0, |zone| zone.data)
+    }
+
+    // Dread weight is clamped to [0, 40] and the margin to [0.005, 0.5].
+    pub fn set_danger_avoidance(&mut self, dread_weight: f32, margin: f32) {
+        self.danger_zones.dread_weight =
+            clamp_finite(dread_weight, 0.0, MAX_DREAD_WEIGHT, DEFAULT_DREAD_WEIGHT);
+        self.danger_zones.margin = clamp_finite(
+            margin,
+            MIN_DANGER_MARGIN,
+            MAX_DANGER_MARGIN,
+            DEFAULT_DANGER_MARGIN,
+        );
+    }
+
+    pub fn danger_dread_weight(&self) -> f32 {
+        self.danger_zones.dread_weight
+    }
+
+    pub fn danger_margin(&self) -> f32 {
+        self.danger_zones.margin
+    }
+}
@@ -0,0 +1,62 @@
+use crate::recording::FieldCodec;
+use crate::{clamp_finite, Sim};
+use wasm_bindgen::prelude::*;
+
+const MIN_DEPTH_SPEED_SCALE: f32 = 0.05;
+const MAX_DEPTH_SPEED_SCALE: f32 = 4.0;
+
+// Speed limit multiplier per boid, interpolated over render z from `far` at
+// 0 to `near` at 1, so parallax layers of one flock keep believable screen
+// speeds. Both limits scale in the integrator, for every model. Off while
+// both ends are 1.
+#[derive(Clone, Copy)]
+pub struct DepthSpeed {
+    far: f32,
+    near: f32,
+}
+
+impl Default for DepthSpeed {
+    fn default() -> Self {
+        Self {
+            far: 1.0,
+            near: 1.0,
+        }
+    }
+}
+
+impl DepthSpeed {
+    pub fn visit_settings(&mut self, codec: &mut dyn FieldCodec) {
+        codec.f32(&mut self.far);
+        codec.f32(&mut self.near);
+    }
+}
+
+impl Sim {
+    pub(super) fn depth_speed_scale(&self, i: usize) -> f32 {
+        let depth = self.depth_speed;
+        depth.far + (depth.near - depth.far) * self.render_z[i]
+    }
+}
+
+#[wasm_bindgen]
+impl Sim {
+    // Speed limit scale at render z 0 and at render z 1, each clamped to
+    // [0.05, 4]. Outside z mode every boid sits at the mid layer and gets the
+    // average of the two.
+    pub fn set_depth_speed_scale(&mut self, far: f32, near: f32) {
+        let clamp =
+            |scale: f32| clamp_finite(scale, MIN_DEPTH_SPEED_SCALE, MAX_DEPTH_SPEED_SCALE, 1.0);
+        self.depth_speed = DepthSpeed {
+            far: clamp(far),
+            near: clamp(near),
+        };
+    }
+
+    pub fn depth_speed_scale_far(&self) -> f32 {
+        self.depth_speed.far
+    }
+
+    pub fn depth_speed_scale_near(&self) -> f32 {
+        self.depth_speed.near
+    }
+}
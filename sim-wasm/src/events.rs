@@ -0,0 +1,263 @@
+use crate::Sim;
+use wasm_bindgen::prelude::*;
+
+pub const BOUNDARY_HIT_X: u8 = 1 << 0;
+pub const BOUNDARY_HIT_Y: u8 = 1 << 1;
+pub const BOUNDARY_HIT_Z: u8 = 1 << 2;
+pub const MAX_RECORDED_CONTACTS: usize = 4_096;
+
+// Counters describing what happened during the most recent step. They are
+// reset at the start of every `step` so readers always see one frame's worth.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StepEvents {
+    pub boundary_hits: [u32; 3],
+    pub boundary_correction: f32,
+    pub simulated_dt: f32,
+    pub contact_count: u32,
+    pub contact_penetration_sum: f32,
+    pub recorded_contacts: usize,
+    // Measured after the last substep's constraint pass.
+    pub remaining_overlaps: u32,
+    pub max_remaining_penetration: f32,
+    pub flight: FlightSamples,
+    // Handles spawned and despawned since the previous step.
+    pub spawned: u32,
+    pub despawned: u32,
+}
+
+// Flight-model samples summed over every boid and substep of a step.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FlightSamples {
+    pub samples: u32,
+    pub airspeed_sum: f32,
+    pub climb_rate_sum: f32,
+    pub thrust_work: f32,
+    pub stalled: u32,
+}
+
+// Everything a dashboard reads after a step, gathered in one call.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct StepReport {
+    simulated_dt: f32,
+    neighbors_visited: usize,
+    boundary_hits: [u32; 3],
+    boundary_correction: f32,
+    contact_count: u32,
+    contact_penetration_sum: f32,
+    remaining_overlaps: u32,
+    max_remaining_penetration: f32,
+    spawned: u32,
+    despawned: u32,
+}
+
+#[wasm_bindgen]
+impl StepReport {
+    pub fn simulated_dt(&self) -> f32 {
+        self.simulated_dt
+    }
+
+    pub fn neighbors_visited(&self) -> usize {
+        self.neighbors_visited
+    }
+
+    pub fn boundary_hits_x(&self) -> u32 {
+        self.boundary_hits[0]
+    }
+
+    pub fn boundary_hits_y(&self) -> u32 {
+        self.boundary_hits[1]
+    }
+
+    pub fn boundary_hits_z(&self) -> u32 {
+        self.boundary_hits[2]
+    }
+
+    pub fn boundary_correction(&self) -> f32 {
+        self.boundary_correction
+    }
+
+    pub fn contact_count(&self) -> u32 {
+        self.contact_count
+    }
+
+    pub fn contact_penetration_sum(&self) -> f32 {
+        self.contact_penetration_sum
+    }
+
+    pub fn remaining_overlaps(&self) -> u32 {
+        self.remaining_overlaps
+    }
+
+    pub fn max_remaining_penetration(&self) -> f32 {
+        self.max_remaining_penetration
+    }
+
+    pub fn spawned(&self) -> u32 {
+        self.spawned
+    }
+
+    pub fn despawned(&self) -> u32 {
+        self.despawned
+    }
+}
+
+impl StepEvents {
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+impl Sim {
+    pub(super) fn begin_step_events(&mut self) {
+        self.step_events.reset();
+        self.step_events.spawned = std::mem::take(&mut self.spawned_since_step);
+        self.step_events.despawned = std::mem::take(&mut self.despawned_since_step);
+        if self.boundary_hit_flags_enabled {
+            self.boundary_hit_flags.fill(0);
+        }
+    }
+
+    pub(super) fn record_boundary_hits(&mut self, i: usize, hit_x: bool, hit_y: bool, hit_z: bool) {
+        let mut flags = 0;
+        if hit_x {
+            self.step_events.boundary_hits[0] += 1;
+            flags |= BOUNDARY_HIT_X;
+        }
+        if hit_y {
+            self.step_events.boundary_hits[1] += 1;
+            flags |= BOUNDARY_HIT_Y;
+        }
+        if hit_z {
+            self.step_events.boundary_hits[2] += 1;
+            flags |= BOUNDARY_HIT_Z;
+        }
+
+        if self.boundary_hit_flags_enabled {
+            self.boundary_hit_flags[i] = flags;
+        }
+    }
+
+    // Sum of |velocity change| caused by wall reflections. Wrapping leaves
+    // velocity untouched, so it never adds pressure.
+    pub(super) fn record_boundary_correction(&mut self, dvx: f32, dvy: f32, dvz: f32) {
+        self.step_events.boundary_correction += dvx.abs() + dvy.abs() + dvz.abs();
+    }
+
+    // Every contact feeds the totals; only the first MAX_RECORDED_CONTACTS pairs
+    // are kept so the pair buffer never reallocates under JS views.
+    pub(super) fn record_contact(&mut self, i: usize, j: usize, penetration: f32) {
+        self.step_events.contact_count += 1;
+        self.step_events.contact_penetration_sum += penetration;
+
+        let slot = self.step_events.recorded_contacts;
+        if slot < MAX_RECORDED_CONTACTS {
+            self.contact_pairs[2 * slot] = i as u32;
+            self.contact_pairs[2 * slot + 1] = j as u32;
+            self.step_events.recorded_contacts += 1;
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl Sim {
+    pub fn boundary_hits_x(&self) -> u32 {
+        self.step_events.boundary_hits[0]
+    }
+
+    pub fn boundary_hits_y(&self) -> u32 {
+        self.step_events.boundary_hits[1]
+    }
+
+    pub fn boundary_hits_z(&self) -> u32 {
+        self.step_events.boundary_hits[2]
+    }
+
+    pub fn boundary_correction(&self) -> f32 {
+        self.step_events.boundary_correction
+    }
+
+    // Wall correction per active boid per simulated second. Configs where the
+    // flock keeps fighting the walls score high regardless of count or dt.
+    pub fn boundary_pressure(&self) -> f32 {
+        let boid_seconds = self.active_count as f32 * self.step_events.simulated_dt;
+        if boid_seconds <= 0.0 {
+            return 0.0;
+        }
+        self.step_events.boundary_correction / boid_seconds
+    }
+
+    // The per-boid flag buffer costs an extra write per boid, so it is opt-in.
+    pub fn set_boundary_hit_flags_enabled(&mut self, enabled: bool) {
+        self.boundary_hit_flags_enabled = enabled;
+        self.boundary_hit_flags.fill(0);
+    }
+
+    pub fn boundary_hit_flags_enabled(&self) -> bool {
+        self.boundary_hit_flags_enabled
+    }
+
+    pub fn boundary_hit_flags_ptr(&self) -> *const u8 {
+        self.boundary_hit_flags.as_ptr()
+    }
+
+    pub fn boundary_hit_flags_len(&self) -> usize {
+        self.boundary_hit_flags.len()
+    }
+
+    pub fn contact_count(&self) -> u32 {
+        self.step_events.contact_count
+    }
+
+    pub fn contact_penetration_sum(&self) -> f32 {
+        self.step_events.contact_penetration_sum
+    }
+
+    pub fn recorded_contact_count(&self) -> usize {
+        self.step_events.recorded_contacts
+    }
+
+    pub fn set_overlap_metrics_enabled(&mut self, enabled: bool) {
+        self.overlap_metrics_enabled = enabled;
+    }
+
+    pub fn overlap_metrics_enabled(&self) -> bool {
+        self.overlap_metrics_enabled
+    }
+
+    // Pairs still closer than hard_min_distance after the resolve pass, and
+    // the deepest of them. Zero unless overlap metrics are enabled.
+    pub fn remaining_overlap_count(&self) -> u32 {
+        self.step_events.remaining_overlaps
+    }
+
+    pub fn max_remaining_penetration(&self) -> f32 {
+        self.step_events.max_remaining_penetration
+    }
+
+    pub fn contact_pairs_ptr(&self) -> *const u32 {
+        self.contact_pairs.as_ptr()
+    }
+
+    pub fn contact_pairs_len(&self) -> usize {
+        self.contact_pairs.len()
+    }
+
+    // The counters above for the most recent step, plus the neighbors it
+    // visited and the spawns and despawns that led into it.
+    pub fn step_report(&self) -> StepReport {
+        let events = &self.step_events;
+        StepReport {
+            simulated_dt: events.simulated_dt,
+            neighbors_visited: self.neighbors_visited_last_step,
+            boundary_hits: events.boundary_hits,
+            boundary_correction: events.boundary_correction,
+            contact_count: events.contact_count,
+            contact_penetration_sum: events.contact_penetration_sum,
+            remaining_overlaps: events.remaining_overlaps,
+            max_remaining_penetration: events.max_remaining_penetration,
+            spawned: events.spawned,
+            despawned: events.despawned,
+        }
+    }
+}
@@ -0,0 +1,264 @@
+use crate::neighbor_grid::NeighborGrid;
+use crate::{Lcg32, Sim, SimConfig, WORLD_SIZE};
+use wasm_bindgen::prelude::*;
+
+const EVOLVE_DT: f32 = 1.0 / 60.0;
+const MAX_POPULATION: u32 = 64;
+const MAX_GENERATIONS: u32 = 200;
+const MAX_EVAL_STEPS: u32 = 2_000;
+const MAX_EVAL_BOIDS: u32 = 2_000;
+const DEFAULT_COLLISION_DISTANCE: f32 = 0.01;
+// Fitness is sampled over the second half of each run, every few steps, so
+// the random start does not dominate the score.
+const SAMPLE_INTERVAL: u32 = 10;
+const MUTATION_RATE: f32 = 0.3;
+const MUTATION_SPAN: f32 = 0.15;
+
+// Evolved classic parameters, in `SimConfig` units.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Genome {
+    pub sep_weight: f32,
+    pub align_weight: f32,
+    pub coh_weight: f32,
+    pub neighbor_radius: f32,
+    pub separation_radius: f32,
+}
+
+const GENE_COUNT: usize = 5;
+const GENE_RANGES: [(f32, f32); GENE_COUNT] = [
+    (0.0, 4.0),
+    (0.0, 4.0),
+    (0.0, 4.0),
+    (0.02, 0.2),
+    (0.005, 0.08),
+];
+
+impl Genome {
+    fn from_genes(genes: [f32; GENE_COUNT]) -> Self {
+        Self {
+            sep_weight: genes[0],
+            align_weight: genes[1],
+            coh_weight: genes[2],
+            neighbor_radius: genes[3],
+            separation_radius: genes[4],
+        }
+    }
+
+    fn apply(self, config: &mut SimConfig) {
+        config.sep_weight = self.sep_weight;
+        config.align_weight = self.align_weight;
+        config.coh_weight = self.coh_weight;
+        config.neighbor_radius = self.neighbor_radius;
+        config.separation_radius = self.separation_radius;
+        config.sanitize();
+    }
+
+    // The values a sim would actually run with, e.g. a separation radius no
+    // larger than the neighbor radius.
+    fn sanitized(self) -> Self {
+        let mut config = SimConfig::default();
+        self.apply(&mut config);
+        Self {
+            sep_weight: config.sep_weight,
+            align_weight: config.align_weight,
+            coh_weight: config.coh_weight,
+            neighbor_radius: config.neighbor_radius,
+            separation_radius: config.separation_radius,
+        }
+    }
+
+    fn genes(self) -> [f32; GENE_COUNT] {
+        [
+            self.sep_weight,
+            self.align_weight,
+            self.coh_weight,
+            self.neighbor_radius,
+            self.separation_radius,
+        ]
+    }
+
+    fn random(rng: &mut Lcg32) -> Self {
+        Self::from_genes(GENE_RANGES.map(|(lo, hi)| lo + (hi - lo) * rng.next_f32())).sanitized()
+    }
+
+    fn offspring(a: Self, b: Self, rng: &mut Lcg32) -> Self {
+        let (a, b) = (a.genes(), b.genes());
+        Self::from_genes(std::array::from_fn(|g| {
+            let (lo, hi) = GENE_RANGES[g];
+            let mut gene = if rng.next_f32() < 0.5 { a[g] } else { b[g] };
+            if rng.next_f32() < MUTATION_RATE {
+                gene += (rng.next_f32() * 2.0 - 1.0) * MUTATION_SPAN * (hi - lo);
+            }
+            gene.clamp(lo, hi)
+        }))
+        .sanitized()
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct EvolutionSettings {
+    pub seed: u32,
+    pub population: u32,
+    pub generations: u32,
+    pub steps: u32,
+    pub boid_count: u32,
+    pub polarization_weight: f32,
+    pub collision_weight: f32,
+    pub collision_distance: f32,
+}
+
+impl EvolutionSettings {
+    fn sanitized(self) -> Self {
+        let finite = |value: f32| if value.is_finite() { value } else { 0.0 };
+        Self {
+            population: self.population.clamp(2, MAX_POPULATION),
+            generations: self.generations.clamp(1, MAX_GENERATIONS),
+            steps: self.steps.clamp(1, MAX_EVAL_STEPS),
+            boid_count: self.boid_count.clamp(1, MAX_EVAL_BOIDS),
+            polarization_weight: finite(self.polarization_weight),
+            collision_weight: finite(self.collision_weight),
+            collision_distance: if self.collision_distance > 0.0 {
+                self.collision_distance.min(0.5 * WORLD_SIZE)
+            } else {
+                DEFAULT_COLLISION_DISTANCE
+            },
+            ..self
+        }
+    }
+}
+
+pub struct EvolutionResult {
+    pub best: Genome,
+    pub best_fitness: f32,
+    // Best fitness after each generation; never decreases since the elite
+    // half survives unchanged.
+    pub history: Vec<f32>,
+}
+
+impl EvolutionResult {
+    // Genome keys match the web client's boids config, so the parsed object
+    // can be applied directly.
+    pub fn to_json(&self) -> String {
+        let best = self.best;
+        let history: Vec<String> = self.history.iter().map(f32::to_string).collect();
+        format!(
+            "{{\"sepWeight\":{},\"alignWeight\":{},\"cohWeight\":{},\"neighborRadius\":{},\"separationRadius\":{},\"fitness\":{},\"history\":[{}]}}",
+            best.sep_weight,
+            best.align_weight,
+            best.coh_weight,
+            best.neighbor_radius,
+            best.separation_radius,
+            self.best_fitness,
+            history.join(","),
+        )
+    }
+}
+
+// Pairs closer than `distance`, measured in the plane.
+fn close_pairs(sim: &Sim, grid: &mut NeighborGrid, distance: f32) -> usize {
+    let n = sim.active_count;
+    grid.set_cell_size(distance);
+    grid.rebuild(&sim.pos_x[..n], &sim.pos_y[..n], WORLD_SIZE, WORLD_SIZE);
+    let mut pairs = 0;
+    for i in 0..n {
+        grid.for_each_neighbor_with_wrap(i, distance, !sim.bounce_x, !sim.bounce_y, |j| {
+            if j > i {
+                pairs += 1;
+            }
+            true
+        });
+    }
+    pairs
+}
+
+// Mean polarization minus mean close pairs per boid, each weighted. Every
+// genome runs from the same seed so scores are directly comparable.
+fn evaluate(genome: Genome, settings: &EvolutionSettings) -> f32 {
+    let mut sim = Sim::new(settings.boid_count as usize, settings.seed, 1.0, 1.0);
+    genome.apply(&mut sim.config);
+
+    let mut grid = NeighborGrid::new(0, WORLD_SIZE, WORLD_SIZE, settings.collision_distance);
+    let mut polarization = 0.0;
+    let mut collisions = 0.0;
+    let mut samples = 0;
+    for step in 0..settings.steps {
+        sim.step(EVOLVE_DT);
+        if step >= settings.steps / 2 && (settings.steps - 1 - step).is_multiple_of(SAMPLE_INTERVAL)
+        {
+            polarization += sim.polarization();
+            collisions += close_pairs(&sim, &mut grid, settings.collision_distance) as f32
+                / settings.boid_count as f32;
+            samples += 1;
+        }
+    }
+    let samples = samples.max(1) as f32;
+    settings.polarization_weight * polarization / samples
+        - settings.collision_weight * collisions / samples
+}
+
+// Truncation selection with elitism: each generation keeps the better half
+// and refills the rest with mutated crossovers of random survivors.
+pub fn evolve_classic(settings: EvolutionSettings) -> EvolutionResult {
+    let settings = settings.sanitized();
+    let mut rng = Lcg32::new(settings.seed ^ 0x5EED_E701);
+    let mut scored: Vec<(Genome, f32)> = (0..settings.population)
+        .map(|_| {
+            let genome = Genome::random(&mut rng);
+            (genome, evaluate(genome, &settings))
+        })
+        .collect();
+    let mut history = Vec::with_capacity(settings.generations as usize);
+    let survivors = (settings.population as usize).div_ceil(2);
+
+    for generation in 0..settings.generations {
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+        history.push(scored[0].1);
+        if generation + 1 == settings.generations {
+            break;
+        }
+        scored.truncate(survivors);
+        while scored.len() < settings.population as usize {
+            let a = scored[(rng.next_f32() * survivors as f32) as usize].0;
+            let b = scored[(rng.next_f32() * survivors as f32) as usize].0;
+            let child = Genome::offspring(a, b, &mut rng);
+            scored.push((child, evaluate(child, &settings)));
+        }
+    }
+
+    let (best, best_fitness) = scored[0];
+    EvolutionResult {
+        best,
+        best_fitness,
+        history,
+    }
+}
+
+// Headless search over the classic weights and radii. Fitness rewards mean
+// polarization and penalizes pairs closer than `collision_distance` (0 picks
+// 0.01). Runs synchronously, so keep budgets small or call it from a worker.
+// Returns the best config as JSON with its fitness and the best fitness per
+// generation.
+#[wasm_bindgen]
+#[allow(clippy::too_many_arguments)]
+pub fn evolve_classic_config(
+    seed: u32,
+    population: u32,
+    generations: u32,
+    steps: u32,
+    boid_count: u32,
+    polarization_weight: f32,
+    collision_weight: f32,
+    collision_distance: f32,
+) -> String {
+    evolve_classic(EvolutionSettings {
+        seed,
+        population,
+        generations,
+        steps,
+        boid_count,
+        polarization_weight,
+        collision_weight,
+        collision_distance,
+    })
+    .to_json()
+}
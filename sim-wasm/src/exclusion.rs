@@ -0,0 +1,162 @@
+use crate::flock2::{dot3, normalize_or_default};
+use crate::recording::FieldCodec;
+use crate::{axis_delta, clamp_finite, project_axis_position, Sim, EPSILON};
+use wasm_bindgen::prelude::*;
+
+// The centre may sit up to a world outside the box, where a camera usually is.
+const MIN_EXCLUSION_CENTER: f32 = -1.0;
+const MAX_EXCLUSION_CENTER: f32 = 2.0;
+const MAX_EXCLUSION_RADIUS: f32 = 1.0;
+const MAX_EXCLUSION_WEIGHT: f32 = 20.0;
+const DEFAULT_EXCLUSION_WEIGHT: f32 = 4.0;
+const MIN_EXCLUSION_MARGIN: f32 = 0.005;
+const MAX_EXCLUSION_MARGIN: f32 = 0.5;
+const DEFAULT_EXCLUSION_MARGIN: f32 = 0.08;
+
+// One sphere in world units, usually the camera or a foreground subject.
+// Boids within `margin` of its surface steer straight away from the centre,
+// harder the closer they are, and the integrator never leaves one inside.
+// Without z mode it acts as a circle in the xy plane. Off while the radius
+// is 0.
+#[derive(Clone, Copy)]
+pub struct ExclusionSphere {
+    center: [f32; 3],
+    radius: f32,
+    weight: f32,
+    margin: f32,
+}
+
+impl Default for ExclusionSphere {
+    fn default() -> Self {
+        Self {
+            center: [0.5; 3],
+            radius: 0.0,
+            weight: DEFAULT_EXCLUSION_WEIGHT,
+            margin: DEFAULT_EXCLUSION_MARGIN,
+        }
+    }
+}
+
+impl ExclusionSphere {
+    pub fn enabled(&self) -> bool {
+        self.radius > 0.0
+    }
+
+    pub fn visit_settings(&mut self, codec: &mut dyn FieldCodec) {
+        codec.f32_slice(&mut self.center);
+        codec.f32(&mut self.radius);
+        codec.f32(&mut self.weight);
+        codec.f32(&mut self.margin);
+    }
+}
+
+impl Sim {
+    // Offset from the sphere's centre to boid `i` and its length.
+    fn exclusion_offset(&self, i: usize) -> ([f32; 3], f32) {
+        let center = self.exclusion.center;
+        let dz = if self.z_mode_enabled {
+            self.z_extent
+                .delta(self.pos_z[i] - center[2], !self.bounce_z)
+        } else {
+            0.0
+        };
+        let offset = [
+            axis_delta(self.pos_x[i] - center[0], !self.bounce_x),
+            axis_delta(self.pos_y[i] - center[1], !self.bounce_y),
+            dz,
+        ];
+        let dist = (offset[0] * offset[0] + offset[1] * offset[1] + offset[2] * offset[2]).sqrt();
+        (offset, dist)
+    }
+
+    // Unit direction away from the centre and its weight, growing linearly to
+    // the full weight at the surface; None while boid `i` is clear.
+    pub(super) fn exclusion_steer(&self, i: usize) -> Option<([f32; 3], f32)> {
+        let sphere = &self.exclusion;
+        if !sphere.enabled() || sphere.weight <= 0.0 {
+            return None;
+        }
+        let (offset, dist) = self.exclusion_offset(i);
+        let clearance = dist - sphere.radius;
+        if clearance >= sphere.margin || dist <= EPSILON {
+            return None;
+        }
+        let away = [offset[0] / dist, offset[1] / dist, offset[2] / dist];
+        Some((
+            away,
+            sphere.weight * (1.0 - clearance.max(0.0) / sphere.margin),
+        ))
+    }
+
+    // Moves boid `i` onto the surface if it ended a step inside, dropping the
+    // inward part of the returned velocity.
+    pub(super) fn keep_out_of_exclusion(
+        &mut self,
+        i: usize,
+        vx: f32,
+        vy: f32,
+        vz: f32,
+    ) -> (f32, f32, f32) {
+        if !self.exclusion.enabled() {
+            return (vx, vy, vz);
+        }
+        let (offset, dist) = self.exclusion_offset(i);
+        let push = self.exclusion.radius - dist;
+        if push <= 0.0 {
+            return (vx, vy, vz);
+        }
+        // A boid at the very centre leaves against its own velocity.
+        let (nx, ny, nz) = normalize_or_default(offset[0], offset[1], offset[2], -vx, -vy, -vz);
+        let (nx, ny, nz) = normalize_or_default(nx, ny, nz, 1.0, 0.0, 0.0);
+        let push = push + EPSILON;
+        self.pos_x[i] = project_axis_position(self.pos_x[i] + nx * push, self.bounce_x);
+        self.pos_y[i] = project_axis_position(self.pos_y[i] + ny * push, self.bounce_y);
+        if self.z_mode_enabled {
+            self.pos_z[i] = (self.pos_z[i] + nz * push).clamp(self.z_extent.min, self.z_extent.max);
+        }
+        let inward = dot3(vx, vy, vz, nx, ny, nz).min(0.0);
+        (vx - nx * inward, vy - ny * inward, vz - nz * inward)
+    }
+}
+
+#[wasm_bindgen]
+impl Sim {
+    // Centre coordinates are clamped to [-1, 2] and the radius to [0, 1];
+    // a radius of 0 turns the sphere off.
+    pub fn set_exclusion_sphere(&mut self, x: f32, y: f32, z: f32, radius: f32) {
+        let clamp = |v: f32| clamp_finite(v, MIN_EXCLUSION_CENTER, MAX_EXCLUSION_CENTER, 0.5);
+        self.exclusion.center = [clamp(x), clamp(y), clamp(z)];
+        self.exclusion.radius = clamp_finite(radius, 0.0, MAX_EXCLUSION_RADIUS, 0.0);
+    }
+
+    pub fn clear_exclusion_sphere(&mut self) {
+        self.exclusion.radius = 0.0;
+    }
+
+    // x, y, z, radius.
+    pub fn exclusion_sphere(&self) -> Vec<f32> {
+        let [x, y, z] = self.exclusion.center;
+        vec![x, y, z, self.exclusion.radius]
+    }
+
+    // Weight is clamped to [0, 20] and the margin to [0.005, 0.5]. A weight
+    // of 0 stops the steering but still keeps boids out.
+    pub fn set_exclusion_avoidance(&mut self, weight: f32, margin: f32) {
+        self.exclusion.weight =
+            clamp_finite(weight, 0.0, MAX_EXCLUSION_WEIGHT, DEFAULT_EXCLUSION_WEIGHT);
+        self.exclusion.margin = clamp_finite(
+            margin,
+            MIN_EXCLUSION_MARGIN,
+            MAX_EXCLUSION_MARGIN,
+            DEFAULT_EXCLUSION_MARGIN,
+        );
+    }
+
+    pub fn exclusion_weight(&self) -> f32 {
+        self.exclusion.weight
+    }
+
+    pub fn exclusion_margin(&self) -> f32 {
+        self.exclusion.margin
+    }
+}
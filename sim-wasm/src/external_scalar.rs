@@ -0,0 +1,81 @@
+use crate::recording::FieldCodec;
+use crate::{clamp_finite, Sim};
+use wasm_bindgen::prelude::*;
+
+const MAX_EXTERNAL_SCALAR: f32 = 16.0;
+
+// Which classic terms the uploaded per-boid scalars multiply. Both scale the
+// configured weight, so a zero jitter strength or cohesion weight stays zero.
+#[derive(Clone, Copy)]
+pub struct ExternalScalarTargets {
+    pub jitter: bool,
+    pub cohesion: bool,
+}
+
+impl Default for ExternalScalarTargets {
+    fn default() -> Self {
+        Self {
+            jitter: true,
+            cohesion: false,
+        }
+    }
+}
+
+impl ExternalScalarTargets {
+    pub fn visit_settings(&mut self, codec: &mut dyn FieldCodec) {
+        codec.bool(&mut self.jitter);
+        codec.bool(&mut self.cohesion);
+    }
+}
+
+impl Sim {
+    pub(super) fn jitter_scale(&self, i: usize) -> f32 {
+        if self.external_targets.jitter {
+            self.external_scalar[i]
+        } else {
+            1.0
+        }
+    }
+
+    pub(super) fn cohesion_scale(&self, i: usize) -> f32 {
+        if self.external_targets.cohesion {
+            self.external_scalar[i]
+        } else {
+            1.0
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl Sim {
+    // Multiplier per slot, meant to be refreshed every frame from outside
+    // input. Values are clamped to [0, 16]; slots past the end of `scalars`
+    // are reset to 1.
+    pub fn set_external_scalars(&mut self, scalars: &[f32]) {
+        for (slot, entry) in self.external_scalar.iter_mut().enumerate() {
+            *entry = scalars.get(slot).map_or(1.0, |&scalar| {
+                clamp_finite(scalar, 0.0, MAX_EXTERNAL_SCALAR, 1.0)
+            });
+        }
+    }
+
+    pub fn clear_external_scalars(&mut self) {
+        self.external_scalar.fill(1.0);
+    }
+
+    pub fn external_scalar(&self, slot: usize) -> f32 {
+        self.external_scalar.get(slot).copied().unwrap_or(1.0)
+    }
+
+    pub fn set_external_scalar_targets(&mut self, jitter: bool, cohesion: bool) {
+        self.external_targets = ExternalScalarTargets { jitter, cohesion };
+    }
+
+    pub fn external_scalar_scales_jitter(&self) -> bool {
+        self.external_targets.jitter
+    }
+
+    pub fn external_scalar_scales_cohesion(&self) -> bool {
+        self.external_targets.cohesion
+    }
+}
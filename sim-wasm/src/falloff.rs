@@ -0,0 +1,123 @@
+use crate::recording::FieldCodec;
+use crate::{clamp_finite, Sim, WORLD_SIZE};
+use wasm_bindgen::prelude::*;
+
+const MAX_FALLOFF_RADIUS: f32 = WORLD_SIZE;
+// Distance at which the inverse profiles reach full strength when there is
+// no dead zone to normalize against.
+const MIN_FALLOFF_REFERENCE: f32 = 0.01;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FalloffProfile {
+    Constant,
+    Linear,
+    Inverse,
+    InverseSquare,
+    Smoothstep,
+}
+
+impl FalloffProfile {
+    pub fn from_u32(value: u32) -> Self {
+        match value {
+            1 => Self::Linear,
+            2 => Self::Inverse,
+            3 => Self::InverseSquare,
+            4 => Self::Smoothstep,
+            _ => Self::Constant,
+        }
+    }
+
+    pub fn as_u32(self) -> u32 {
+        match self {
+            Self::Constant => 0,
+            Self::Linear => 1,
+            Self::Inverse => 2,
+            Self::InverseSquare => 3,
+            Self::Smoothstep => 4,
+        }
+    }
+}
+
+// How attractor and goal pulls scale with distance. Nothing pulls inside the
+// dead zone or beyond the max radius (0 means unbounded). Linear and
+// smoothstep fade from full strength at the dead zone edge to zero at the
+// max radius, or over one world width without one; the inverse profiles are
+// 1 at the dead zone edge and decay as 1/d or 1/d^2 beyond it.
+#[derive(Clone, Copy)]
+pub struct AttractorFalloff {
+    profile: FalloffProfile,
+    dead_zone: f32,
+    max_radius: f32,
+}
+
+impl Default for AttractorFalloff {
+    fn default() -> Self {
+        Self {
+            profile: FalloffProfile::Constant,
+            dead_zone: 0.0,
+            max_radius: 0.0,
+        }
+    }
+}
+
+impl AttractorFalloff {
+    pub fn scale(&self, distance: f32) -> f32 {
+        if distance <= self.dead_zone || (self.max_radius > 0.0 && distance > self.max_radius) {
+            return 0.0;
+        }
+        let reach = if self.max_radius > 0.0 {
+            self.max_radius
+        } else {
+            self.dead_zone + WORLD_SIZE
+        };
+        let t = ((distance - self.dead_zone) / (reach - self.dead_zone)).clamp(0.0, 1.0);
+        let reference = self.dead_zone.max(MIN_FALLOFF_REFERENCE);
+        match self.profile {
+            FalloffProfile::Constant => 1.0,
+            FalloffProfile::Linear => 1.0 - t,
+            FalloffProfile::Inverse => (reference / distance).min(1.0),
+            FalloffProfile::InverseSquare => (reference / distance).powi(2).min(1.0),
+            FalloffProfile::Smoothstep => 1.0 - t * t * (3.0 - 2.0 * t),
+        }
+    }
+
+    pub fn visit_settings(&mut self, codec: &mut dyn FieldCodec) {
+        let mut profile = self.profile.as_u32();
+        codec.u32(&mut profile);
+        self.profile = FalloffProfile::from_u32(profile);
+        codec.f32(&mut self.dead_zone);
+        codec.f32(&mut self.max_radius);
+    }
+}
+
+#[wasm_bindgen]
+impl Sim {
+    // Profiles are 0 constant, 1 linear, 2 inverse, 3 inverse-square and
+    // 4 smoothstep. Radii are clamped to [0, 1]; a max radius inside the dead
+    // zone is raised to it.
+    pub fn set_attractor_falloff(&mut self, profile: u32, dead_zone: f32, max_radius: f32) {
+        let dead_zone = clamp_finite(dead_zone, 0.0, MAX_FALLOFF_RADIUS, 0.0);
+        let max_radius = clamp_finite(max_radius, 0.0, MAX_FALLOFF_RADIUS, 0.0);
+        self.attractor_falloff = AttractorFalloff {
+            profile: FalloffProfile::from_u32(profile),
+            dead_zone,
+            max_radius: if max_radius > 0.0 {
+                max_radius.max(dead_zone)
+            } else {
+                0.0
+            },
+        };
+    }
+
+    pub fn attractor_falloff_profile(&self) -> u32 {
+        self.attractor_falloff.profile.as_u32()
+    }
+
+    pub fn attractor_dead_zone(&self) -> f32 {
+        self.attractor_falloff.dead_zone
+    }
+
+    pub fn attractor_max_radius(&self) -> f32 {
+        self.attractor_falloff.max_radius
+    }
+}
@@ -0,0 +1,217 @@
+use crate::recording::FieldCodec;
+use crate::{clamp_finite, Sim};
+use wasm_bindgen::prelude::*;
+
+const MAX_FAMILIARITY_BOOST: f32 = 4.0;
+const MIN_FAMILIARITY_BUILD_S: f32 = 0.1;
+const MAX_FAMILIARITY_BUILD_S: f32 = 60.0;
+const DEFAULT_FAMILIARITY_BUILD_S: f32 = 2.0;
+const MIN_FAMILIARITY_DECAY_S: f32 = 1.0;
+const MAX_FAMILIARITY_DECAY_S: f32 = 600.0;
+const DEFAULT_FAMILIARITY_DECAY_S: f32 = 30.0;
+// Bonds each boid keeps; a new flockmate replaces the weakest one not seen
+// this step.
+const FAMILIARITY_SLOTS: usize = 8;
+// Bonds that decay below this free their slot.
+const FORGET_BOND: f32 = 1.0e-3;
+const EMPTY: u32 = u32::MAX;
+
+// Bonds between flockmates. Every step a boid counts another as a neighbor,
+// its bond toward that boid grows toward 1 over `build_s`; bonds not renewed
+// fade over the much longer `decay_s`. A neighbor's alignment and cohesion
+// pull is scaled by 1 + boost * bond, so boids that have flown together keep
+// to each other and long runs settle into lasting sub-groups. Classic models
+// bond with the boids they visit individually, flock2 with its topological
+// set; far-field cells and Flock2 Lite stay unweighted. Off while the boost
+// is 0.
+pub struct Familiarity {
+    boost: f32,
+    build_s: f32,
+    decay_s: f32,
+    // FAMILIARITY_SLOTS entries per slot: partner boid id, bond strength and
+    // whether the partner was a neighbor this step.
+    partners: Vec<u32>,
+    bonds: Vec<f32>,
+    seen: Vec<bool>,
+}
+
+impl Default for Familiarity {
+    fn default() -> Self {
+        Self {
+            boost: 0.0,
+            build_s: DEFAULT_FAMILIARITY_BUILD_S,
+            decay_s: DEFAULT_FAMILIARITY_DECAY_S,
+            partners: Vec::new(),
+            bonds: Vec::new(),
+            seen: Vec::new(),
+        }
+    }
+}
+
+impl Familiarity {
+    pub fn enabled(&self) -> bool {
+        self.boost > 0.0
+    }
+
+    // Keeps a row of bonds for each of `count` slots.
+    pub fn prepare(&mut self, count: usize) {
+        let len = count * FAMILIARITY_SLOTS;
+        if self.partners.len() != len {
+            self.partners.resize(len, EMPTY);
+            self.bonds.resize(len, 0.0);
+            self.seen.resize(len, false);
+        }
+    }
+
+    fn row(i: usize) -> std::ops::Range<usize> {
+        i * FAMILIARITY_SLOTS..(i + 1) * FAMILIARITY_SLOTS
+    }
+
+    fn bond(&self, i: usize, id: u32) -> f32 {
+        let Some(partners) = self.partners.get(Self::row(i)) else {
+            return 0.0;
+        };
+        partners
+            .iter()
+            .position(|&partner| partner == id)
+            .map_or(0.0, |k| self.bonds[i * FAMILIARITY_SLOTS + k])
+    }
+
+    // Marks boid `id` as a neighbor of slot `i` this step and returns the
+    // weight of its influence.
+    pub fn touch(&mut self, i: usize, id: u32) -> f32 {
+        if !self.enabled() || self.partners.len() < (i + 1) * FAMILIARITY_SLOTS {
+            return 1.0;
+        }
+        let row = Self::row(i);
+        let partners = &self.partners[row.clone()];
+        if let Some(k) = partners.iter().position(|&partner| partner == id) {
+            let entry = row.start + k;
+            self.seen[entry] = true;
+            return 1.0 + self.boost * self.bonds[entry];
+        }
+        let weakest = row
+            .clone()
+            .filter(|&entry| !self.seen[entry])
+            .min_by(|&a, &b| self.bonds[a].total_cmp(&self.bonds[b]));
+        if let Some(entry) = weakest {
+            self.partners[entry] = id;
+            self.bonds[entry] = 0.0;
+            self.seen[entry] = true;
+        }
+        1.0
+    }
+
+    pub fn advance(&mut self, dt: f32) {
+        if !self.enabled() {
+            return;
+        }
+        let grow = 1.0 - (-dt / self.build_s).exp();
+        let keep = (-dt / self.decay_s).exp();
+        for ((partner, bond), seen) in self
+            .partners
+            .iter_mut()
+            .zip(&mut self.bonds)
+            .zip(&mut self.seen)
+        {
+            if std::mem::take(seen) {
+                *bond += (1.0 - *bond) * grow;
+            } else if *partner != EMPTY {
+                *bond *= keep;
+                if *bond < FORGET_BOND {
+                    (*partner, *bond) = (EMPTY, 0.0);
+                }
+            }
+        }
+    }
+
+    pub fn forget(&mut self, i: usize) {
+        if self.partners.len() >= (i + 1) * FAMILIARITY_SLOTS {
+            self.partners[Self::row(i)].fill(EMPTY);
+            self.bonds[Self::row(i)].fill(0.0);
+            self.seen[Self::row(i)].fill(false);
+        }
+    }
+
+    pub fn swap(&mut self, a: usize, b: usize) {
+        if self.partners.len() < (a.max(b) + 1) * FAMILIARITY_SLOTS {
+            return;
+        }
+        for k in 0..FAMILIARITY_SLOTS {
+            let (ea, eb) = (a * FAMILIARITY_SLOTS + k, b * FAMILIARITY_SLOTS + k);
+            self.partners.swap(ea, eb);
+            self.bonds.swap(ea, eb);
+            self.seen.swap(ea, eb);
+        }
+    }
+
+    pub fn visit_settings(&mut self, codec: &mut dyn FieldCodec) {
+        codec.f32(&mut self.boost);
+        codec.f32(&mut self.build_s);
+        codec.f32(&mut self.decay_s);
+    }
+
+    // Bonds fill at most one row per slot of a `count`-boid sim.
+    pub fn visit_state(&mut self, codec: &mut dyn FieldCodec, count: usize) {
+        let mut len = self.partners.len();
+        codec.usize(&mut len);
+        if len > count * FAMILIARITY_SLOTS || !len.is_multiple_of(FAMILIARITY_SLOTS) {
+            codec.malformed();
+            len = len.min(count * FAMILIARITY_SLOTS);
+        }
+        self.prepare(len / FAMILIARITY_SLOTS);
+        for partner in &mut self.partners {
+            codec.u32(partner);
+        }
+        codec.f32_slice(&mut self.bonds);
+    }
+}
+
+#[wasm_bindgen]
+impl Sim {
+    // Boost is clamped to [0, 4], the build time to [0.1, 60] seconds and the
+    // decay time to [1, 600] seconds. A boost of 0 turns familiarity off and
+    // forgets every bond.
+    pub fn set_familiarity(&mut self, boost: f32, build_s: f32, decay_s: f32) {
+        let familiarity = &mut self.familiarity;
+        familiarity.boost = clamp_finite(boost, 0.0, MAX_FAMILIARITY_BOOST, 0.0);
+        familiarity.build_s = clamp_finite(
+            build_s,
+            MIN_FAMILIARITY_BUILD_S,
+            MAX_FAMILIARITY_BUILD_S,
+            DEFAULT_FAMILIARITY_BUILD_S,
+        );
+        familiarity.decay_s = clamp_finite(
+            decay_s,
+            MIN_FAMILIARITY_DECAY_S,
+            MAX_FAMILIARITY_DECAY_S,
+            DEFAULT_FAMILIARITY_DECAY_S,
+        );
+        if !familiarity.enabled() {
+            familiarity.partners = Vec::new();
+            familiarity.bonds = Vec::new();
+            familiarity.seen = Vec::new();
+        }
+    }
+
+    pub fn familiarity_boost(&self) -> f32 {
+        self.familiarity.boost
+    }
+
+    pub fn familiarity_build_time(&self) -> f32 {
+        self.familiarity.build_s
+    }
+
+    pub fn familiarity_decay_time(&self) -> f32 {
+        self.familiarity.decay_s
+    }
+
+    // Bond in [0, 1] of the boid in slot `from` toward the one in slot `to`;
+    // 0 for unknown slots or while familiarity is off.
+    pub fn familiarity_bond(&self, from: usize, to: usize) -> f32 {
+        if from >= self.active_count || to >= self.active_count {
+            return 0.0;
+        }
+        self.familiarity.bond(from, self.boid_ids[to])
+    }
+}
@@ -0,0 +1,102 @@
+use super::config_report::{clamp_reported, ConfigAdjustment};
+
+pub const FISH_MIN_LATERAL_ALIGNMENT: f32 = 0.0;
+pub const FISH_MAX_LATERAL_ALIGNMENT: f32 = 5.0;
+pub const FISH_MIN_DEPTH_WEIGHT: f32 = 0.0;
+pub const FISH_MAX_DEPTH_WEIGHT: f32 = 5.0;
+pub const FISH_MIN_BURST_PERIOD: f32 = 0.1;
+pub const FISH_MAX_BURST_PERIOD: f32 = 10.0;
+pub const FISH_MIN_BURST_FRACTION: f32 = 0.05;
+pub const FISH_MAX_BURST_FRACTION: f32 = 0.95;
+pub const FISH_MIN_BURST_THRUST: f32 = 0.0;
+pub const FISH_MAX_BURST_THRUST: f32 = 5.0;
+pub const FISH_MIN_COAST_DRAG: f32 = 0.0;
+pub const FISH_MAX_COAST_DRAG: f32 = 6.0;
+
+// Schooling runs on the classic steering pipeline; these only describe what
+// the fish variant layers on top of it.
+#[derive(Clone, Copy)]
+pub struct FishConfig {
+    pub lateral_alignment: f32,
+    pub preferred_depth: f32,
+    pub depth_weight: f32,
+    pub burst_period: f32,
+    pub burst_fraction: f32,
+    pub burst_thrust: f32,
+    pub coast_drag: f32,
+}
+
+impl Default for FishConfig {
+    fn default() -> Self {
+        Self {
+            lateral_alignment: 1.8,
+            preferred_depth: 0.5,
+            depth_weight: 0.6,
+            burst_period: 1.2,
+            burst_fraction: 0.3,
+            burst_thrust: 0.6,
+            coast_drag: 1.2,
+        }
+    }
+}
+
+impl FishConfig {
+    pub fn sanitize_reported(&mut self, report: &mut Vec<ConfigAdjustment>) {
+        self.lateral_alignment = clamp_reported(
+            report,
+            "lateral_alignment",
+            self.lateral_alignment,
+            FISH_MIN_LATERAL_ALIGNMENT,
+            FISH_MAX_LATERAL_ALIGNMENT,
+            1.8,
+        );
+        self.preferred_depth = clamp_reported(
+            report,
+            "preferred_depth",
+            self.preferred_depth,
+            0.0,
+            1.0,
+            0.5,
+        );
+        self.depth_weight = clamp_reported(
+            report,
+            "depth_weight",
+            self.depth_weight,
+            FISH_MIN_DEPTH_WEIGHT,
+            FISH_MAX_DEPTH_WEIGHT,
+            0.6,
+        );
+        self.burst_period = clamp_reported(
+            report,
+            "burst_period",
+            self.burst_period,
+            FISH_MIN_BURST_PERIOD,
+            FISH_MAX_BURST_PERIOD,
+            1.2,
+        );
+        self.burst_fraction = clamp_reported(
+            report,
+            "burst_fraction",
+            self.burst_fraction,
+            FISH_MIN_BURST_FRACTION,
+            FISH_MAX_BURST_FRACTION,
+            0.3,
+        );
+        self.burst_thrust = clamp_reported(
+            report,
+            "burst_thrust",
+            self.burst_thrust,
+            FISH_MIN_BURST_THRUST,
+            FISH_MAX_BURST_THRUST,
+            0.6,
+        );
+        self.coast_drag = clamp_reported(
+            report,
+            "coast_drag",
+            self.coast_drag,
+            FISH_MIN_COAST_DRAG,
+            FISH_MAX_COAST_DRAG,
+            1.2,
+        );
+    }
+}
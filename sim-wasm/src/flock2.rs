@@ -1,7 +1,16 @@
+use super::config_report::{clamp_count_reported, clamp_reported, ConfigAdjustment};
+use super::math::{self, Math};
 use super::{MAX_NEIGHBOR_RADIUS, MIN_NEIGHBOR_RADIUS};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
 
-pub const FLOCK2_MAX_TOPOLOGICAL_NEIGHBORS: usize = 64;
+pub const FLOCK2_MAX_TOPOLOGICAL_NEIGHBORS: usize = 1024;
+// Caps up to this size keep their nearest-neighbor list on the stack.
+pub const FLOCK2_INLINE_TOPOLOGICAL_NEIGHBORS: usize = 64;
 pub const FLOCK2_MIN_TOPOLOGICAL_NEIGHBORS: usize = 1;
+// Flock2 Lite stops after this many visible neighbors; 0 removes the extra
+// cap so only `topological_neighbors` applies.
+pub const FLOCK2_DEFAULT_LITE_NEIGHBOR_CAP: usize = 16;
 pub const FLOCK2_MAX_BOUNDARY_COUNT: f32 = 256.0;
 pub const FLOCK2_MIN_FOV_DEG: f32 = 30.0;
 pub const FLOCK2_MAX_FOV_DEG: f32 = 360.0;
@@ -23,30 +32,65 @@ pub const FLOCK2_MIN_GRAVITY: f32 = 0.0;
 pub const FLOCK2_MAX_GRAVITY: f32 = 30.0;
 pub const FLOCK2_MIN_AIR_DENSITY: f32 = 0.1;
 pub const FLOCK2_MAX_AIR_DENSITY: f32 = 3.0;
+pub const FLOCK2_MIN_BANK_DEG: f32 = 5.0;
+// At 90 degrees the bank limit is off.
+pub const FLOCK2_MAX_BANK_DEG: f32 = 90.0;
+pub const FLOCK2_MAX_DECISION_NOISE_DEG: f32 = 45.0;
 pub const FLOCK2_WORLD_SCALE: f32 = 0.02;
 const EPSILON: f32 = 1.0e-6;
 
-#[derive(Clone, Copy)]
+#[wasm_bindgen]
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
 pub struct Flock2Config {
+    #[wasm_bindgen(js_name = avoidWeight)]
     pub avoid_weight: f32,
+    #[wasm_bindgen(js_name = alignWeight)]
     pub align_weight: f32,
+    #[wasm_bindgen(js_name = cohesionWeight)]
     pub cohesion_weight: f32,
+    #[wasm_bindgen(js_name = boundaryWeight)]
     pub boundary_weight: f32,
+    #[wasm_bindgen(js_name = boundaryCount)]
     pub boundary_count: f32,
+    #[wasm_bindgen(js_name = neighborRadius)]
     pub neighbor_radius: f32,
+    #[wasm_bindgen(js_name = topologicalNeighbors)]
     pub topological_neighbors: usize,
+    #[wasm_bindgen(js_name = liteNeighborCap)]
+    pub lite_neighbor_cap: usize,
+    #[wasm_bindgen(js_name = fieldOfViewDeg)]
     pub field_of_view_deg: f32,
+    // Field of view for the nearest-neighbor avoidance term; 0 uses
+    // `field_of_view_deg`.
+    #[wasm_bindgen(js_name = avoidFieldOfViewDeg)]
+    pub avoid_field_of_view_deg: f32,
+    #[wasm_bindgen(js_name = reactionTimeMs)]
     pub reaction_time_ms: f32,
+    #[wasm_bindgen(js_name = dynamicStability)]
     pub dynamic_stability: f32,
     pub mass: f32,
+    #[wasm_bindgen(js_name = wingArea)]
     pub wing_area: f32,
+    #[wasm_bindgen(js_name = liftFactor)]
     pub lift_factor: f32,
+    #[wasm_bindgen(js_name = dragFactor)]
     pub drag_factor: f32,
     pub thrust: f32,
+    #[wasm_bindgen(js_name = minSpeed)]
     pub min_speed: f32,
+    #[wasm_bindgen(js_name = maxSpeed)]
     pub max_speed: f32,
     pub gravity: f32,
+    #[wasm_bindgen(js_name = airDensity)]
     pub air_density: f32,
+    #[wasm_bindgen(js_name = maxBankDeg)]
+    pub max_bank_deg: f32,
+    // Bound on the random yaw and pitch added to each steering decision.
+    #[wasm_bindgen(js_name = decisionNoiseDeg)]
+    pub decision_noise_deg: f32,
+    #[wasm_bindgen(js_name = decisionNoiseSeed)]
+    pub decision_noise_seed: u32,
 }
 
 impl Default for Flock2Config {
@@ -59,7 +103,9 @@ impl Default for Flock2Config {
             boundary_count: 20.0,
             neighbor_radius: 0.10,
             topological_neighbors: 7,
+            lite_neighbor_cap: FLOCK2_DEFAULT_LITE_NEIGHBOR_CAP,
             field_of_view_deg: 290.0,
+            avoid_field_of_view_deg: 0.0,
             reaction_time_ms: 250.0,
             dynamic_stability: 0.70,
             mass: 0.08,
@@ -71,81 +117,215 @@ impl Default for Flock2Config {
             max_speed: 18.0,
             gravity: 9.8,
             air_density: 1.225,
+            max_bank_deg: FLOCK2_MAX_BANK_DEG,
+            decision_noise_deg: 0.0,
+            decision_noise_seed: 0,
         }
     }
 }
 
 impl Flock2Config {
     pub fn sanitize(&mut self) {
-        self.avoid_weight = clamp_finite(self.avoid_weight, 0.0, 2.0, 0.02);
-        self.align_weight = clamp_finite(self.align_weight, 0.0, 2.0, 0.60);
-        self.cohesion_weight = clamp_finite(self.cohesion_weight, 0.0, 2.0, 0.004);
-        self.boundary_weight = clamp_finite(self.boundary_weight, 0.0, 2.0, 0.10);
-        self.boundary_count =
-            clamp_finite(self.boundary_count, 0.0, FLOCK2_MAX_BOUNDARY_COUNT, 20.0);
-        self.neighbor_radius = clamp_finite(
+        self.sanitize_reported(&mut Vec::new());
+    }
+
+    pub fn sanitize_reported(&mut self, report: &mut Vec<ConfigAdjustment>) {
+        self.avoid_weight =
+            clamp_reported(report, "avoid_weight", self.avoid_weight, 0.0, 2.0, 0.02);
+        self.align_weight =
+            clamp_reported(report, "align_weight", self.align_weight, 0.0, 2.0, 0.60);
+        self.cohesion_weight = clamp_reported(
+            report,
+            "cohesion_weight",
+            self.cohesion_weight,
+            0.0,
+            2.0,
+            0.004,
+        );
+        self.boundary_weight = clamp_reported(
+            report,
+            "boundary_weight",
+            self.boundary_weight,
+            0.0,
+            2.0,
+            0.10,
+        );
+        self.boundary_count = clamp_reported(
+            report,
+            "boundary_count",
+            self.boundary_count,
+            0.0,
+            FLOCK2_MAX_BOUNDARY_COUNT,
+            20.0,
+        );
+        self.neighbor_radius = clamp_reported(
+            report,
+            "neighbor_radius",
             self.neighbor_radius,
             MIN_NEIGHBOR_RADIUS,
             MAX_NEIGHBOR_RADIUS,
             0.10,
         );
-        self.topological_neighbors = self.topological_neighbors.clamp(
+        self.topological_neighbors = clamp_count_reported(
+            report,
+            "topological_neighbors",
+            self.topological_neighbors,
             FLOCK2_MIN_TOPOLOGICAL_NEIGHBORS,
             FLOCK2_MAX_TOPOLOGICAL_NEIGHBORS,
         );
-        self.field_of_view_deg = clamp_finite(
+        self.lite_neighbor_cap = clamp_count_reported(
+            report,
+            "lite_neighbor_cap",
+            self.lite_neighbor_cap,
+            0,
+            FLOCK2_MAX_TOPOLOGICAL_NEIGHBORS,
+        );
+        self.field_of_view_deg = clamp_reported(
+            report,
+            "field_of_view_deg",
             self.field_of_view_deg,
             FLOCK2_MIN_FOV_DEG,
             FLOCK2_MAX_FOV_DEG,
             290.0,
         );
-        self.reaction_time_ms = clamp_finite(
+        if self.avoid_field_of_view_deg != 0.0 {
+            self.avoid_field_of_view_deg = clamp_reported(
+                report,
+                "avoid_field_of_view_deg",
+                self.avoid_field_of_view_deg,
+                FLOCK2_MIN_FOV_DEG,
+                FLOCK2_MAX_FOV_DEG,
+                0.0,
+            );
+        }
+        self.reaction_time_ms = clamp_reported(
+            report,
+            "reaction_time_ms",
             self.reaction_time_ms,
             FLOCK2_MIN_REACTION_MS,
             FLOCK2_MAX_REACTION_MS,
             250.0,
         );
-        self.dynamic_stability = clamp_finite(
+        self.dynamic_stability = clamp_reported(
+            report,
+            "dynamic_stability",
             self.dynamic_stability,
             FLOCK2_MIN_DYNAMIC_STABILITY,
             FLOCK2_MAX_DYNAMIC_STABILITY,
             0.70,
         );
-        self.mass = clamp_finite(self.mass, FLOCK2_MIN_MASS, FLOCK2_MAX_MASS, 0.08);
-        self.wing_area = clamp_finite(
+        self.mass = clamp_reported(
+            report,
+            "mass",
+            self.mass,
+            FLOCK2_MIN_MASS,
+            FLOCK2_MAX_MASS,
+            0.08,
+        );
+        self.wing_area = clamp_reported(
+            report,
+            "wing_area",
             self.wing_area,
             FLOCK2_MIN_WING_AREA,
             FLOCK2_MAX_WING_AREA,
             0.0224,
         );
-        self.lift_factor = clamp_finite(
+        self.lift_factor = clamp_reported(
+            report,
+            "lift_factor",
             self.lift_factor,
             FLOCK2_MIN_LIFT_FACTOR,
             FLOCK2_MAX_LIFT_FACTOR,
             0.5714,
         );
-        self.drag_factor = clamp_finite(
+        self.drag_factor = clamp_reported(
+            report,
+            "drag_factor",
             self.drag_factor,
             FLOCK2_MIN_DRAG_FACTOR,
             FLOCK2_MAX_DRAG_FACTOR,
             0.1731,
         );
-        self.thrust = clamp_finite(self.thrust, FLOCK2_MIN_THRUST, FLOCK2_MAX_THRUST, 0.2373);
-        self.min_speed = clamp_finite(self.min_speed, 0.0, 200.0, 5.0);
-        self.max_speed = clamp_finite(self.max_speed, self.min_speed.max(0.1), 250.0, 18.0);
-        self.gravity = clamp_finite(self.gravity, FLOCK2_MIN_GRAVITY, FLOCK2_MAX_GRAVITY, 9.8);
-        self.air_density = clamp_finite(
+        self.thrust = clamp_reported(
+            report,
+            "thrust",
+            self.thrust,
+            FLOCK2_MIN_THRUST,
+            FLOCK2_MAX_THRUST,
+            0.2373,
+        );
+        self.min_speed = clamp_reported(report, "min_speed", self.min_speed, 0.0, 200.0, 5.0);
+        self.max_speed = clamp_reported(
+            report,
+            "max_speed",
+            self.max_speed,
+            self.min_speed.max(0.1),
+            250.0,
+            18.0,
+        );
+        self.gravity = clamp_reported(
+            report,
+            "gravity",
+            self.gravity,
+            FLOCK2_MIN_GRAVITY,
+            FLOCK2_MAX_GRAVITY,
+            9.8,
+        );
+        self.air_density = clamp_reported(
+            report,
+            "air_density",
             self.air_density,
             FLOCK2_MIN_AIR_DENSITY,
             FLOCK2_MAX_AIR_DENSITY,
             1.225,
         );
+        self.max_bank_deg = clamp_reported(
+            report,
+            "max_bank_deg",
+            self.max_bank_deg,
+            FLOCK2_MIN_BANK_DEG,
+            FLOCK2_MAX_BANK_DEG,
+            FLOCK2_MAX_BANK_DEG,
+        );
+        self.decision_noise_deg = clamp_reported(
+            report,
+            "decision_noise_deg",
+            self.decision_noise_deg,
+            0.0,
+            FLOCK2_MAX_DECISION_NOISE_DEG,
+            0.0,
+        );
+    }
+
+    // Largest heading change over `dt` for a coordinated turn at the bank
+    // limit: turn rate is g * tan(bank) / speed. None when unlimited.
+    pub fn max_turn_angle(self, speed: f32, dt: f32) -> Option<f32> {
+        if self.max_bank_deg >= FLOCK2_MAX_BANK_DEG {
+            return None;
+        }
+        let turn_rate = self.gravity * self.max_bank_deg.to_radians().tan() / speed.max(EPSILON);
+        Some(turn_rate * dt)
+    }
+
+    pub fn lite_neighbor_limit(self) -> usize {
+        if self.lite_neighbor_cap == 0 {
+            self.topological_neighbors
+        } else {
+            self.topological_neighbors.min(self.lite_neighbor_cap)
+        }
     }
 
     pub fn fov_cos(self) -> f32 {
         let half_angle = (self.field_of_view_deg * 0.5).to_radians();
         half_angle.cos()
     }
+
+    pub fn avoid_fov_cos(self) -> f32 {
+        if self.avoid_field_of_view_deg == 0.0 {
+            return self.fov_cos();
+        }
+        (self.avoid_field_of_view_deg * 0.5).to_radians().cos()
+    }
 }
 
 pub fn dot3(ax: f32, ay: f32, az: f32, bx: f32, by: f32, bz: f32) -> f32 {
@@ -193,14 +373,14 @@ pub fn heading_basis(
 }
 
 pub fn rotate_vector_around_axis(
+    mode: Math<'_>,
     vector: (f32, f32, f32),
     axis: (f32, f32, f32),
     angle_radians: f32,
 ) -> (f32, f32, f32) {
     let (ux, uy, uz) = normalize_or_default(axis.0, axis.1, axis.2, 0.0, 1.0, 0.0);
     let (vx, vy, vz) = vector;
-    let cos_theta = angle_radians.cos();
-    let sin_theta = angle_radians.sin();
+    let (sin_theta, cos_theta) = math::sin_cos(mode, angle_radians);
     let dot = dot3(ux, uy, uz, vx, vy, vz);
     let (cross_x, cross_y, cross_z) = cross3(ux, uy, uz, vx, vy, vz);
 
@@ -214,10 +394,3 @@ pub fn rotate_vector_around_axis(
 fn cross3(ax: f32, ay: f32, az: f32, bx: f32, by: f32, bz: f32) -> (f32, f32, f32) {
     (ay * bz - az * by, az * bx - ax * bz, ax * by - ay * bx)
 }
-
-fn clamp_finite(value: f32, min: f32, max: f32, fallback: f32) -> f32 {
-    if !value.is_finite() {
-        return fallback;
-    }
-    value.clamp(min, max)
-}
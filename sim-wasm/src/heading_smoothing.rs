@@ -0,0 +1,97 @@
+use crate::recording::FieldCodec;
+use crate::{clamp_finite, Sim, EPSILON};
+use wasm_bindgen::prelude::*;
+
+const MAX_HEADING_TIME_CONSTANT_S: f32 = 5.0;
+
+// An exponential moving average over the exported xy headings, so jitter and
+// perception noise do not make sprites shimmer. It only touches the render
+// buffer: boids still steer by their raw headings. Time is the caller's dt,
+// so fast-forward does not change how smooth the output looks. Off while the
+// time constant is zero.
+#[derive(Default)]
+pub struct HeadingSmoothing {
+    time_constant_s: f32,
+    // Caller time not yet folded into the average.
+    pending_s: f32,
+    headings_xy: Vec<f32>,
+}
+
+impl HeadingSmoothing {
+    pub fn enabled(&self) -> bool {
+        self.time_constant_s > 0.0
+    }
+
+    pub fn resize(&mut self, count: usize) {
+        self.headings_xy.resize(count * 2, 0.0);
+    }
+
+    pub fn swap(&mut self, a: usize, b: usize) {
+        self.headings_xy.swap(2 * a, 2 * b);
+        self.headings_xy.swap(2 * a + 1, 2 * b + 1);
+    }
+
+    pub fn advance(&mut self, dt: f32) {
+        if self.enabled() {
+            self.pending_s += dt;
+        }
+    }
+
+    // Share of the way to move toward the raw headings for the time passed
+    // since the last render sync.
+    pub fn take_blend(&mut self) -> f32 {
+        let elapsed = std::mem::take(&mut self.pending_s);
+        1.0 - (-elapsed / self.time_constant_s).exp()
+    }
+
+    pub fn reset(&mut self, i: usize, heading: (f32, f32)) {
+        if let Some(slot) = self.headings_xy.get_mut(2 * i..2 * i + 2) {
+            slot.copy_from_slice(&[heading.0, heading.1]);
+        }
+    }
+
+    pub fn smooth(&mut self, i: usize, raw: (f32, f32), blend: f32) -> (f32, f32) {
+        let slot = &mut self.headings_xy[2 * i..2 * i + 2];
+        let x = slot[0] + (raw.0 - slot[0]) * blend;
+        let y = slot[1] + (raw.1 - slot[1]) * blend;
+        let len_sq = x * x + y * y;
+        // Turning right around passes through zero; snap to the raw heading.
+        let heading = if len_sq > EPSILON {
+            let inv_len = len_sq.sqrt().recip();
+            (x * inv_len, y * inv_len)
+        } else {
+            raw
+        };
+        slot.copy_from_slice(&[heading.0, heading.1]);
+        heading
+    }
+
+    pub fn visit_settings(&mut self, codec: &mut dyn FieldCodec) {
+        codec.f32(&mut self.time_constant_s);
+    }
+}
+
+#[wasm_bindgen]
+impl Sim {
+    // Time constant of the render heading average in seconds, clamped to
+    // [0, 5]; 0 exports raw headings. Turning it on starts the average from
+    // the current render headings.
+    pub fn set_heading_smoothing(&mut self, time_constant_s: f32) {
+        let was_enabled = self.heading_smoothing.enabled();
+        self.heading_smoothing.time_constant_s =
+            clamp_finite(time_constant_s, 0.0, MAX_HEADING_TIME_CONSTANT_S, 0.0);
+        self.heading_smoothing.pending_s = 0.0;
+        if !was_enabled {
+            let smoothing = &mut self.heading_smoothing;
+            let count = smoothing
+                .headings_xy
+                .len()
+                .min(self.render_heading_xy.len());
+            smoothing.headings_xy[..count].copy_from_slice(&self.render_heading_xy[..count]);
+        }
+    }
+
+    pub fn heading_smoothing(&self) -> f32 {
+        self.heading_smoothing.time_constant_s
+    }
+}
@@ -0,0 +1,103 @@
+use crate::neighbor_grid::NeighborGrid;
+
+pub const MAX_GRID_LEVELS: usize = 4;
+
+// Stack of uniform grids whose cell sizes double per level. Each query uses the
+// finest level whose cells cover its radius, so small and large interaction
+// radii in the same world both scan a 3x3 block instead of degenerating.
+pub struct HierarchicalGrid {
+    levels: Vec<NeighborGrid>,
+    active_levels: usize,
+    base_cell_size: f32,
+}
+
+impl HierarchicalGrid {
+    pub fn new(count: usize, width: f32, height: f32, base_cell_size: f32) -> Self {
+        let levels = (0..MAX_GRID_LEVELS)
+            .map(|level| {
+                NeighborGrid::new(count, width, height, base_cell_size * (1 << level) as f32)
+            })
+            .collect();
+        Self {
+            levels,
+            active_levels: 1,
+            base_cell_size,
+        }
+    }
+
+    // Only the levels needed to span [min_radius, max_radius] are rebuilt; radii
+    // beyond the top level fall back to scanning more of its cells.
+    pub fn rebuild(
+        &mut self,
+        positions_x: &[f32],
+        positions_y: &[f32],
+        width: f32,
+        height: f32,
+        min_radius: f32,
+        max_radius: f32,
+    ) {
+        self.base_cell_size = min_radius;
+        let ratio = (max_radius / min_radius).max(1.0);
+        self.active_levels = (ratio.log2().ceil() as usize + 1).min(MAX_GRID_LEVELS);
+
+        for (level, grid) in self.levels[..self.active_levels].iter_mut().enumerate() {
+            grid.set_cell_size(min_radius * (1 << level) as f32);
+            grid.rebuild(positions_x, positions_y, width, height);
+        }
+    }
+
+    pub fn level_for_radius(&self, radius: f32) -> usize {
+        let mut cell_size = self.base_cell_size;
+        for level in 0..self.active_levels {
+            if radius <= cell_size {
+                return level;
+            }
+            cell_size *= 2.0;
+        }
+        self.active_levels - 1
+    }
+
+    pub fn for_each_neighbor_with_wrap<F>(
+        &self,
+        i: usize,
+        radius: f32,
+        wrap_x: bool,
+        wrap_y: bool,
+        callback: F,
+    ) where
+        F: FnMut(usize) -> bool,
+    {
+        self.levels[self.level_for_radius(radius)]
+            .for_each_neighbor_with_wrap(i, radius, wrap_x, wrap_y, callback);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HierarchicalGrid;
+
+    #[test]
+    fn queries_pick_level_by_radius() {
+        let pos_x = vec![0.5, 0.51, 0.56, 0.9];
+        let pos_y = vec![0.5, 0.5, 0.5, 0.5];
+        let mut grid = HierarchicalGrid::new(pos_x.len(), 1.0, 1.0, 0.02);
+        grid.rebuild(&pos_x, &pos_y, 1.0, 1.0, 0.02, 0.16);
+
+        assert_eq!(grid.level_for_radius(1.0), 3);
+        assert_eq!(grid.level_for_radius(0.015), 0);
+        assert_eq!(grid.level_for_radius(0.07), 2);
+
+        let neighbors = |radius: f32| {
+            let mut found = Vec::new();
+            grid.for_each_neighbor_with_wrap(0, radius, false, false, |j| {
+                found.push(j);
+                true
+            });
+            found.sort_unstable();
+            found
+        };
+        assert_eq!(neighbors(0.02), vec![1]);
+        assert_eq!(neighbors(0.1), vec![1, 2]);
+        assert_eq!(neighbors(0.45), vec![1, 2, 3]);
+    }
+}
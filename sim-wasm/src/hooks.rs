@@ -0,0 +1,356 @@
+use crate::{clamp_finite, Sim, WORLD_SIZE};
+use wasm_bindgen::prelude::*;
+
+pub const STEP_STAGE_COUNT: usize = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StepStage {
+    AfterForces,
+    BeforeIntegration,
+    AfterConstraints,
+}
+
+impl StepStage {
+    pub fn from_u32(value: u32) -> Option<Self> {
+        match value {
+            0 => Some(Self::AfterForces),
+            1 => Some(Self::BeforeIntegration),
+            2 => Some(Self::AfterConstraints),
+            _ => None,
+        }
+    }
+
+    pub fn as_u32(self) -> u32 {
+        match self {
+            Self::AfterForces => 0,
+            Self::BeforeIntegration => 1,
+            Self::AfterConstraints => 2,
+        }
+    }
+
+    fn index(self) -> usize {
+        self.as_u32() as usize
+    }
+}
+
+// Mutable views over the active prefix of the SoA state. Acceleration holds the
+// steering force in the classic model and the next heading in flock2 models.
+pub struct SoaViewMut<'a> {
+    pub pos_x: &'a mut [f32],
+    pub pos_y: &'a mut [f32],
+    pub pos_z: &'a mut [f32],
+    pub vel_x: &'a mut [f32],
+    pub vel_y: &'a mut [f32],
+    pub vel_z: &'a mut [f32],
+    pub heading_x: &'a mut [f32],
+    pub heading_y: &'a mut [f32],
+    pub heading_z: &'a mut [f32],
+    pub accel_x: &'a mut [f32],
+    pub accel_y: &'a mut [f32],
+    pub accel_z: &'a mut [f32],
+}
+
+pub trait StepHook {
+    fn on_stage(&mut self, stage: StepStage, state: SoaViewMut<'_>);
+}
+
+// Inputs cover the active prefix; outputs start zeroed each step and are added
+// to the classic steering sum before the max_force clamp.
+pub struct CustomForceView<'a> {
+    pub pos_x: &'a [f32],
+    pub pos_y: &'a [f32],
+    pub pos_z: &'a [f32],
+    pub vel_x: &'a [f32],
+    pub vel_y: &'a [f32],
+    pub vel_z: &'a [f32],
+    pub out_x: &'a mut [f32],
+    pub out_y: &'a mut [f32],
+    pub out_z: &'a mut [f32],
+}
+
+pub trait CustomForce {
+    fn compute(&mut self, view: CustomForceView<'_>);
+}
+
+#[wasm_bindgen(typescript_custom_section)]
+const STEP_HOOK_CALLBACK_TS: &str = r#"
+export type StepHookCallback = (
+  stage: number,
+  posX: Float32Array,
+  posY: Float32Array,
+  posZ: Float32Array,
+  velX: Float32Array,
+  velY: Float32Array,
+  velZ: Float32Array,
+  headingX: Float32Array,
+  headingY: Float32Array,
+  headingZ: Float32Array,
+  accelX: Float32Array,
+  accelY: Float32Array,
+  accelZ: Float32Array,
+) => void;
+"#;
+
+#[wasm_bindgen(typescript_custom_section)]
+const CUSTOM_FORCE_CALLBACK_TS: &str = r#"
+export type CustomForceCallback = (
+  posX: Float32Array,
+  posY: Float32Array,
+  posZ: Float32Array,
+  velX: Float32Array,
+  velY: Float32Array,
+  velZ: Float32Array,
+  outX: Float32Array,
+  outY: Float32Array,
+  outZ: Float32Array,
+) => void;
+"#;
+
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(typescript_type = "CustomForceCallback")]
+    pub type CustomForceCallback;
+
+    #[allow(clippy::too_many_arguments)]
+    #[wasm_bindgen(method, js_name = call)]
+    fn invoke(
+        this: &CustomForceCallback,
+        this_arg: &JsValue,
+        pos_x: &[f32],
+        pos_y: &[f32],
+        pos_z: &[f32],
+        vel_x: &[f32],
+        vel_y: &[f32],
+        vel_z: &[f32],
+        out_x: &mut [f32],
+        out_y: &mut [f32],
+        out_z: &mut [f32],
+    );
+
+    #[wasm_bindgen(typescript_type = "StepHookCallback")]
+    pub type StepHookCallback;
+
+    // wasm-bindgen copies each `&mut [f32]` into a typed array for the call and
+    // copies it back afterwards, so JS writes land in the SoA buffers.
+    #[allow(clippy::too_many_arguments)]
+    #[wasm_bindgen(method, js_name = call)]
+    fn invoke(
+        this: &StepHookCallback,
+        this_arg: &JsValue,
+        stage: u32,
+        pos_x: &mut [f32],
+        pos_y: &mut [f32],
+        pos_z: &mut [f32],
+        vel_x: &mut [f32],
+        vel_y: &mut [f32],
+        vel_z: &mut [f32],
+        heading_x: &mut [f32],
+        heading_y: &mut [f32],
+        heading_z: &mut [f32],
+        accel_x: &mut [f32],
+        accel_y: &mut [f32],
+        accel_z: &mut [f32],
+    );
+}
+
+struct JsStepHook {
+    callback: StepHookCallback,
+}
+
+impl StepHook for JsStepHook {
+    fn on_stage(&mut self, stage: StepStage, state: SoaViewMut<'_>) {
+        self.callback.invoke(
+            &JsValue::NULL,
+            stage.as_u32(),
+            state.pos_x,
+            state.pos_y,
+            state.pos_z,
+            state.vel_x,
+            state.vel_y,
+            state.vel_z,
+            state.heading_x,
+            state.heading_y,
+            state.heading_z,
+            state.accel_x,
+            state.accel_y,
+            state.accel_z,
+        );
+    }
+}
+
+struct JsCustomForce {
+    callback: CustomForceCallback,
+}
+
+impl CustomForce for JsCustomForce {
+    fn compute(&mut self, view: CustomForceView<'_>) {
+        self.callback.invoke(
+            &JsValue::NULL,
+            view.pos_x,
+            view.pos_y,
+            view.pos_z,
+            view.vel_x,
+            view.vel_y,
+            view.vel_z,
+            view.out_x,
+            view.out_y,
+            view.out_z,
+        );
+    }
+}
+
+impl Sim {
+    pub fn set_custom_force(&mut self, force: Option<Box<dyn CustomForce>>) {
+        self.custom_force = force;
+        self.custom_force_x.fill(0.0);
+        self.custom_force_y.fill(0.0);
+        self.custom_force_z.fill(0.0);
+    }
+
+    // Returns false when no custom force is registered so callers can skip the
+    // per-boid lookups entirely.
+    pub(super) fn compute_custom_forces(&mut self) -> bool {
+        let Some(mut force) = self.custom_force.take() else {
+            return false;
+        };
+
+        let n = self.active_count;
+        self.custom_force_x[..n].fill(0.0);
+        self.custom_force_y[..n].fill(0.0);
+        self.custom_force_z[..n].fill(0.0);
+        force.compute(CustomForceView {
+            pos_x: &self.pos_x[..n],
+            pos_y: &self.pos_y[..n],
+            pos_z: &self.pos_z[..n],
+            vel_x: &self.vel_x[..n],
+            vel_y: &self.vel_y[..n],
+            vel_z: &self.vel_z[..n],
+            out_x: &mut self.custom_force_x[..n],
+            out_y: &mut self.custom_force_y[..n],
+            out_z: &mut self.custom_force_z[..n],
+        });
+        self.custom_force = Some(force);
+        true
+    }
+
+    pub fn set_step_hook(&mut self, stage: StepStage, hook: Box<dyn StepHook>) {
+        self.step_hooks[stage.index()] = Some(hook);
+    }
+
+    pub(super) fn run_step_hook(&mut self, stage: StepStage) {
+        // Taking the hook out lets it borrow the SoA slices mutably.
+        let Some(mut hook) = self.step_hooks[stage.index()].take() else {
+            return;
+        };
+
+        let n = self.active_count;
+        // Hooks may write anything, so keep the prior state to fall back on.
+        let mut before = std::mem::take(&mut self.step_hook_scratch);
+        before.clear();
+        for column in self.hook_columns() {
+            before.extend_from_slice(&column[..n]);
+        }
+        hook.on_stage(
+            stage,
+            SoaViewMut {
+                pos_x: &mut self.pos_x[..n],
+                pos_y: &mut self.pos_y[..n],
+                pos_z: &mut self.pos_z[..n],
+                vel_x: &mut self.vel_x[..n],
+                vel_y: &mut self.vel_y[..n],
+                vel_z: &mut self.vel_z[..n],
+                heading_x: &mut self.heading_x[..n],
+                heading_y: &mut self.heading_y[..n],
+                heading_z: &mut self.heading_z[..n],
+                accel_x: &mut self.accel_x[..n],
+                accel_y: &mut self.accel_y[..n],
+                accel_z: &mut self.accel_z[..n],
+            },
+        );
+        self.step_hooks[stage.index()] = Some(hook);
+        self.sanitize_hook_writes(&before);
+        self.step_hook_scratch = before;
+    }
+
+    fn hook_columns(&self) -> [&Vec<f32>; 12] {
+        [
+            &self.pos_x,
+            &self.pos_y,
+            &self.pos_z,
+            &self.vel_x,
+            &self.vel_y,
+            &self.vel_z,
+            &self.heading_x,
+            &self.heading_y,
+            &self.heading_z,
+            &self.accel_x,
+            &self.accel_y,
+            &self.accel_z,
+        ]
+    }
+
+    // Non-finite writes fall back to the value before the hook, and xy
+    // positions are clamped into the world, as config setters do with input.
+    fn sanitize_hook_writes(&mut self, before: &[f32]) {
+        let n = self.active_count;
+        let columns = [
+            &mut self.pos_x,
+            &mut self.pos_y,
+            &mut self.pos_z,
+            &mut self.vel_x,
+            &mut self.vel_y,
+            &mut self.vel_z,
+            &mut self.heading_x,
+            &mut self.heading_y,
+            &mut self.heading_z,
+            &mut self.accel_x,
+            &mut self.accel_y,
+            &mut self.accel_z,
+        ];
+        for (k, (column, prior)) in columns
+            .into_iter()
+            .zip(before.chunks_exact(n.max(1)))
+            .enumerate()
+        {
+            let (min, max) = if k < 2 {
+                (0.0, WORLD_SIZE)
+            } else {
+                (f32::MIN, f32::MAX)
+            };
+            for (value, &prior) in column[..n].iter_mut().zip(prior) {
+                *value = clamp_finite(*value, min, max, prior);
+            }
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl Sim {
+    pub fn set_step_hook_callback(&mut self, stage: u32, callback: StepHookCallback) {
+        if let Some(stage) = StepStage::from_u32(stage) {
+            self.set_step_hook(stage, Box::new(JsStepHook { callback }));
+        }
+    }
+
+    pub fn clear_step_hook(&mut self, stage: u32) {
+        if let Some(stage) = StepStage::from_u32(stage) {
+            self.step_hooks[stage.index()] = None;
+        }
+    }
+
+    pub fn clear_step_hooks(&mut self) {
+        self.step_hooks = Default::default();
+    }
+
+    pub fn set_custom_force_callback(&mut self, callback: CustomForceCallback) {
+        self.set_custom_force(Some(Box::new(JsCustomForce { callback })));
+    }
+
+    pub fn clear_custom_force_callback(&mut self) {
+        self.set_custom_force(None);
+    }
+
+    pub fn has_custom_force(&self) -> bool {
+        self.custom_force.is_some()
+    }
+}
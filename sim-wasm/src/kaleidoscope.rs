@@ -0,0 +1,132 @@
+use crate::recording::FieldCodec;
+use crate::{Sim, WORLD_SIZE};
+use std::f32::consts::TAU;
+use wasm_bindgen::prelude::*;
+
+const MAX_KALEIDOSCOPE_SEGMENTS: u32 = 64;
+const CENTER: f32 = 0.5 * WORLD_SIZE;
+
+fn rotate(x: f32, y: f32, (sin, cos): (f32, f32)) -> (f32, f32) {
+    (x * cos - y * sin, x * sin + y * cos)
+}
+
+// N-fold radial repetition. Boids live in the wedge between 0 and TAU / N
+// around the world center; one leaving through either edge re-enters
+// through the other, turned by the wedge angle, so the seams line up once
+// the wedge is repeated. The render output holds N rotated copies.
+#[derive(Default)]
+pub struct Kaleidoscope {
+    // 0 is off.
+    pub segments: u32,
+    pub xy: Vec<f32>,
+    pub heading_xy: Vec<f32>,
+}
+
+impl Kaleidoscope {
+    pub fn enabled(&self) -> bool {
+        self.segments > 1
+    }
+
+    fn wedge_angle(&self) -> f32 {
+        TAU / self.segments as f32
+    }
+
+    pub fn visit_settings(&mut self, codec: &mut dyn FieldCodec) {
+        codec.u32(&mut self.segments);
+    }
+}
+
+impl Sim {
+    // Turns boid `i` back into the first wedge, along with `velocity`, which
+    // is returned.
+    pub(super) fn wrap_into_wedge(&mut self, i: usize, vx: f32, vy: f32) -> (f32, f32) {
+        if !self.kaleidoscope.enabled() {
+            return (vx, vy);
+        }
+        let (dx, dy) = (self.pos_x[i] - CENTER, self.pos_y[i] - CENTER);
+        let wedge_angle = self.kaleidoscope.wedge_angle();
+        let wedge = (dy.atan2(dx).rem_euclid(TAU) / wedge_angle).floor();
+        if wedge == 0.0 {
+            return (vx, vy);
+        }
+        let turn = (-wedge * wedge_angle).sin_cos();
+        let (x, y) = rotate(dx, dy, turn);
+        self.pos_x[i] = (CENTER + x).clamp(0.0, WORLD_SIZE);
+        self.pos_y[i] = (CENTER + y).clamp(0.0, WORLD_SIZE);
+        (self.heading_x[i], self.heading_y[i]) = rotate(self.heading_x[i], self.heading_y[i], turn);
+        rotate(vx, vy, turn)
+    }
+
+    // Copy `k` of boid `i` sits at `k * active_count + i`, turned by `k` wedge
+    // angles about the center.
+    pub(super) fn sync_kaleidoscope(&mut self) {
+        let kaleidoscope = &mut self.kaleidoscope;
+        if !kaleidoscope.enabled() {
+            kaleidoscope.xy.clear();
+            kaleidoscope.heading_xy.clear();
+            return;
+        }
+        let n = self.active_count;
+        let copies = kaleidoscope.segments as usize;
+        kaleidoscope.xy.resize(2 * n * copies, 0.0);
+        kaleidoscope.heading_xy.resize(2 * n * copies, 0.0);
+        let wedge_angle = kaleidoscope.wedge_angle();
+        for copy in 0..copies {
+            let turn = (copy as f32 * wedge_angle).sin_cos();
+            let base = 2 * n * copy;
+            for i in 0..n {
+                let (x, y) = rotate(
+                    self.render_xy[2 * i] - CENTER,
+                    self.render_xy[2 * i + 1] - CENTER,
+                    turn,
+                );
+                let (hx, hy) = rotate(
+                    self.render_heading_xy[2 * i],
+                    self.render_heading_xy[2 * i + 1],
+                    turn,
+                );
+                let slot = base + 2 * i;
+                kaleidoscope.xy[slot] = CENTER + x;
+                kaleidoscope.xy[slot + 1] = CENTER + y;
+                kaleidoscope.heading_xy[slot] = hx;
+                kaleidoscope.heading_xy[slot + 1] = hy;
+            }
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl Sim {
+    // 0 or 1 turns the transform off; otherwise clamped to 64. Boids outside
+    // the first wedge are turned into it. Neighbor queries do not see across
+    // the seams, so boids near an edge flock only with their own side.
+    pub fn set_kaleidoscope_segments(&mut self, segments: u32) {
+        self.kaleidoscope.segments = segments.min(MAX_KALEIDOSCOPE_SEGMENTS);
+        for i in 0..self.active_count {
+            (self.vel_x[i], self.vel_y[i]) = self.wrap_into_wedge(i, self.vel_x[i], self.vel_y[i]);
+        }
+        self.sync_render_buffers();
+    }
+
+    pub fn kaleidoscope_segments(&self) -> u32 {
+        self.kaleidoscope.segments
+    }
+
+    // Empty while the transform is off. Other per-boid render buffers are
+    // shared by every copy: index them with `copy_index % active_count`.
+    pub fn kaleidoscope_xy_ptr(&self) -> *const f32 {
+        self.kaleidoscope.xy.as_ptr()
+    }
+
+    pub fn kaleidoscope_xy_len(&self) -> usize {
+        self.kaleidoscope.xy.len()
+    }
+
+    pub fn kaleidoscope_heading_xy_ptr(&self) -> *const f32 {
+        self.kaleidoscope.heading_xy.as_ptr()
+    }
+
+    pub fn kaleidoscope_heading_xy_len(&self) -> usize {
+        self.kaleidoscope.heading_xy.len()
+    }
+}
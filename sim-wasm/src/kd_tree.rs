@@ -0,0 +1,236 @@
+// Implicit 2D k-d tree: `order` is laid out so every subrange [lo, hi) stores
+// its splitting point at the midpoint, alternating x/y by depth.
+pub struct KdTree {
+    order: Vec<u32>,
+    built_x: Vec<f32>,
+    built_y: Vec<f32>,
+    particle_count: usize,
+    steps_since_rebuild: u32,
+    query_slack: f32,
+}
+
+impl KdTree {
+    pub fn new(count: usize) -> Self {
+        Self {
+            order: Vec::with_capacity(count),
+            built_x: vec![0.0; count],
+            built_y: vec![0.0; count],
+            particle_count: 0,
+            steps_since_rebuild: 0,
+            query_slack: 0.0,
+        }
+    }
+
+    pub fn rebuild(&mut self, positions_x: &[f32], positions_y: &[f32]) {
+        assert_eq!(positions_x.len(), positions_y.len());
+
+        let count = positions_x.len();
+        if self.built_x.len() < count {
+            self.built_x.resize(count, 0.0);
+            self.built_y.resize(count, 0.0);
+        }
+        self.built_x[..count].copy_from_slice(positions_x);
+        self.built_y[..count].copy_from_slice(positions_y);
+        self.particle_count = count;
+        self.steps_since_rebuild = 0;
+        self.query_slack = 0.0;
+
+        self.order.clear();
+        self.order.extend(0..count as u32);
+        self.build_range(0, count, 0);
+    }
+
+    pub fn particle_count(&self) -> usize {
+        self.particle_count
+    }
+
+    pub fn steps_since_rebuild(&self) -> u32 {
+        self.steps_since_rebuild
+    }
+
+    pub fn query_slack(&self) -> f32 {
+        self.query_slack
+    }
+
+    // Reuses the current layout for another step. Queries widen by the largest
+    // displacement since the build so no neighbor can be missed; callers must
+    // re-check distances against current positions.
+    pub fn reuse(&mut self, positions_x: &[f32], positions_y: &[f32], width: f32, height: f32) {
+        let mut max_drift_sq = 0.0_f32;
+        for i in 0..self.particle_count {
+            let dx = wrapped_delta(positions_x[i] - self.built_x[i], width);
+            let dy = wrapped_delta(positions_y[i] - self.built_y[i], height);
+            max_drift_sq = max_drift_sq.max(dx * dx + dy * dy);
+        }
+        self.steps_since_rebuild += 1;
+        self.query_slack = max_drift_sq.sqrt();
+    }
+
+    // Mirrors `NeighborGrid::for_each_neighbor_with_wrap`, but the query point is
+    // passed explicitly because the tree may hold positions from an older step.
+    #[allow(clippy::too_many_arguments)]
+    pub fn for_each_neighbor_with_wrap<F>(
+        &self,
+        i: usize,
+        x: f32,
+        y: f32,
+        radius: f32,
+        width: f32,
+        height: f32,
+        wrap_x: bool,
+        wrap_y: bool,
+        mut callback: F,
+    ) where
+        F: FnMut(usize) -> bool,
+    {
+        if self.particle_count == 0 {
+            return;
+        }
+
+        let radius = radius.max(0.0) + self.query_slack;
+        let shifts_x = image_shifts(x, radius, width, wrap_x);
+        let shifts_y = image_shifts(y, radius, height, wrap_y);
+        for shift_x in shifts_x.into_iter().flatten() {
+            for shift_y in shifts_y.into_iter().flatten() {
+                if !self.query_range(
+                    0,
+                    self.particle_count,
+                    0,
+                    x + shift_x,
+                    y + shift_y,
+                    radius,
+                    i,
+                    &mut callback,
+                ) {
+                    return;
+                }
+            }
+        }
+    }
+
+    fn build_range(&mut self, lo: usize, hi: usize, depth: usize) {
+        if hi - lo <= 1 {
+            return;
+        }
+
+        let mid = (lo + hi) / 2;
+        let keys = if depth.is_multiple_of(2) {
+            &self.built_x
+        } else {
+            &self.built_y
+        };
+        self.order[lo..hi].select_nth_unstable_by(mid - lo, |a, b| {
+            keys[*a as usize].total_cmp(&keys[*b as usize])
+        });
+        self.build_range(lo, mid, depth + 1);
+        self.build_range(mid + 1, hi, depth + 1);
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn query_range<F>(
+        &self,
+        lo: usize,
+        hi: usize,
+        depth: usize,
+        x: f32,
+        y: f32,
+        radius: f32,
+        exclude: usize,
+        callback: &mut F,
+    ) -> bool
+    where
+        F: FnMut(usize) -> bool,
+    {
+        if lo >= hi {
+            return true;
+        }
+
+        let mid = (lo + hi) / 2;
+        let j = self.order[mid] as usize;
+        let dx = self.built_x[j] - x;
+        let dy = self.built_y[j] - y;
+        if j != exclude && dx * dx + dy * dy <= radius * radius && !callback(j) {
+            return false;
+        }
+
+        let split_delta = if depth.is_multiple_of(2) { dx } else { dy };
+        let (near, far) = if split_delta > 0.0 {
+            ((lo, mid), (mid + 1, hi))
+        } else {
+            ((mid + 1, hi), (lo, mid))
+        };
+        if !self.query_range(near.0, near.1, depth + 1, x, y, radius, exclude, callback) {
+            return false;
+        }
+        if split_delta.abs() <= radius {
+            return self.query_range(far.0, far.1, depth + 1, x, y, radius, exclude, callback);
+        }
+        true
+    }
+}
+
+// Offsets of the periodic images a wrapped query must visit. The caller keeps
+// radius below half the world so the images never overlap.
+fn image_shifts(value: f32, radius: f32, extent: f32, wrap: bool) -> [Option<f32>; 2] {
+    if !wrap {
+        return [Some(0.0), None];
+    }
+    if value - radius < 0.0 {
+        [Some(0.0), Some(extent)]
+    } else if value + radius > extent {
+        [Some(0.0), Some(-extent)]
+    } else {
+        [Some(0.0), None]
+    }
+}
+
+fn wrapped_delta(delta: f32, world_extent: f32) -> f32 {
+    let half_extent = world_extent * 0.5;
+    if delta > half_extent {
+        delta - world_extent
+    } else if delta < -half_extent {
+        delta + world_extent
+    } else {
+        delta
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::KdTree;
+
+    fn sorted_neighbors(tree: &KdTree, i: usize, x: f32, y: f32, radius: f32) -> Vec<usize> {
+        let mut neighbors = Vec::new();
+        tree.for_each_neighbor_with_wrap(i, x, y, radius, 1.0, 1.0, true, true, |j| {
+            neighbors.push(j);
+            true
+        });
+        neighbors.sort_unstable();
+        neighbors
+    }
+
+    #[test]
+    fn matches_brute_force_with_wrap() {
+        let mut xs = Vec::new();
+        let mut ys = Vec::new();
+        for k in 0..200 {
+            xs.push(((k * 37) % 200) as f32 / 200.0);
+            ys.push(((k * 91) % 200) as f32 / 200.0);
+        }
+        let mut tree = KdTree::new(xs.len());
+        tree.rebuild(&xs, &ys);
+
+        let radius = 0.12;
+        for i in [0, 17, 99, 150] {
+            let mut expected = Vec::new();
+            for j in 0..xs.len() {
+                let dx = super::wrapped_delta(xs[j] - xs[i], 1.0);
+                let dy = super::wrapped_delta(ys[j] - ys[i], 1.0);
+                if j != i && dx * dx + dy * dy <= radius * radius {
+                    expected.push(j);
+                }
+            }
+            assert_eq!(sorted_neighbors(&tree, i, xs[i], ys[i], radius), expected);
+        }
+    }
+}
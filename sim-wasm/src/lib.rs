@@ -1,33 +1,170 @@
+mod accel_ramp;
+mod active_set;
+mod altitude_hold;
+mod behavior;
+mod boid_writes;
+mod burst_coast;
+mod camera;
+mod classic_headings;
+mod clusters;
+mod color_map;
+mod config_objects;
+mod config_regions;
+mod config_report;
+mod corridors;
+mod danger_zones;
+mod depth_speed;
+mod events;
+mod evolve;
+mod exclusion;
+mod external_scalar;
+mod falloff;
+mod familiarity;
+mod fish;
 mod flock2;
+mod heading_smoothing;
+mod hierarchical_grid;
+mod hooks;
+mod kaleidoscope;
+mod kd_tree;
+mod lod;
 mod math;
+mod metrics;
+mod mirror;
 mod model_classic;
+mod model_fish;
 mod model_flock2;
+mod neighbor_backend;
 mod neighbor_grid;
-
-use flock2::{normalize_or_default, Flock2Config};
-use math::MathMode;
+mod neighbor_memory;
+mod objective;
+mod obstacles;
+mod pair_avoidance;
+mod partial_step;
+mod predators;
+mod predictive;
+mod projection;
+mod reaction;
+mod recording;
+mod regions;
+mod render_scale;
+mod roles;
+mod safe_zones;
+mod snapshot;
+mod spawn_layout;
+mod species;
+mod species_partition;
+mod speed_radius;
+mod split_world;
+mod steering_debug;
+mod terrain;
+mod turn_noise;
+mod variation;
+mod view_rect;
+mod wind;
+mod wrap_ghosts;
+mod z_extent;
+
+use accel_ramp::AccelRamp;
+use altitude_hold::AltitudeHold;
+use behavior::{Behavior, BehaviorState};
+use burst_coast::BurstCoast;
+use camera::Camera;
+use classic_headings::ClassicHeadings;
+use clusters::Clusters;
+use color_map::ColorMap;
+use config_regions::ConfigRegions;
+use config_report::{clamp_reported, ConfigAdjustment};
+use corridors::Corridors;
+use danger_zones::DangerZones;
+use depth_speed::DepthSpeed;
+use events::{StepEvents, MAX_RECORDED_CONTACTS};
+use exclusion::ExclusionSphere;
+use external_scalar::ExternalScalarTargets;
+use falloff::AttractorFalloff;
+use familiarity::Familiarity;
+use fish::FishConfig;
+use flock2::{
+    normalize_or_default, Flock2Config, FLOCK2_MAX_BANK_DEG, FLOCK2_MAX_DECISION_NOISE_DEG,
+    FLOCK2_MAX_FOV_DEG, FLOCK2_MAX_TOPOLOGICAL_NEIGHBORS, FLOCK2_MIN_BANK_DEG, FLOCK2_MIN_FOV_DEG,
+};
+use heading_smoothing::HeadingSmoothing;
+use hierarchical_grid::HierarchicalGrid;
+use hooks::STEP_STAGE_COUNT;
+pub use hooks::{CustomForce, CustomForceView, SoaViewMut, StepHook, StepStage};
+use kaleidoscope::Kaleidoscope;
+use kd_tree::KdTree;
+use lod::{FocusRegion, DEFAULT_LOD_INTERVAL, DEFAULT_UPDATE_FRACTION};
+use math::{Math, MathDivergence, MathMode, MathPath, MATH_PATH_COUNT};
+use metrics::MetricHistory;
+use mirror::Mirror;
+use neighbor_backend::NeighborBackend;
 use neighbor_grid::NeighborGrid;
+use neighbor_memory::NeighborMemory;
+use objective::ObjectiveState;
+pub use objective::{Objective, ObjectiveMetrics};
+use obstacles::Obstacles;
+use pair_avoidance::PairAvoidance;
+use partial_step::PartialStep;
+use predators::Predators;
+use predictive::PredictiveAvoidance;
+use projection::Projection;
+use reaction::ReactionSpread;
+pub use recording::run_golden;
+use recording::Recording;
+use render_scale::RenderScale;
+use roles::Roles;
+use safe_zones::SafeZones;
+use serde::{Deserialize, Serialize};
+use snapshot::SnapshotHistory;
+use spawn_layout::SpawnLayout;
+use species::{AeroProfile, SpeciesHardMin, MAX_SPECIES};
+use species_partition::SpeciesPartition;
+use speed_radius::SpeedRadius;
+use split_world::SplitWorld;
 use std::f32::consts::TAU;
+use steering_debug::SteeringDebug;
+use terrain::Terrain;
+use turn_noise::TurnNoise;
+use view_rect::ViewGrid;
 use wasm_bindgen::prelude::*;
+use wind::Wind;
+use wrap_ghosts::WrapGhosts;
+use z_extent::ZExtent;
 
 const MIN_BOUND: f32 = 1.0e-6;
 const EPSILON: f32 = 1.0e-6;
 const DT_MIN: f32 = 0.0;
 const DT_MAX: f32 = 0.1;
+const DEFAULT_DEBUG_DT: f32 = 1.0 / 60.0;
+const MIN_TIME_SCALE: f32 = 0.01;
+const MAX_TIME_SCALE: f32 = 8.0;
 const WORLD_SIZE: f32 = 1.0;
 const DEFAULT_Z_LAYER: f32 = 0.5;
 
+const MIN_STEERING_WEIGHT: f32 = 0.0;
+const MAX_STEERING_WEIGHT: f32 = 10.0;
+const DEFAULT_SEP_WEIGHT: f32 = 1.45;
+const DEFAULT_ALIGN_WEIGHT: f32 = 1.0;
+const DEFAULT_COH_WEIGHT: f32 = 0.85;
+const DEFAULT_SPEED_MATCH_WEIGHT: f32 = 0.0;
 const MIN_NEIGHBOR_RADIUS: f32 = 0.001;
 const MAX_NEIGHBOR_RADIUS: f32 = 0.5;
+const DEFAULT_NEIGHBOR_RADIUS: f32 = 0.08;
 const MIN_SEPARATION_RADIUS: f32 = 0.0005;
+const DEFAULT_SEPARATION_RADIUS: f32 = 0.035;
 const MIN_SPEED: f32 = 0.0;
 const MAX_SPEED: f32 = 3.0;
+const DEFAULT_MIN_SPEED: f32 = 0.045;
+const DEFAULT_MAX_SPEED: f32 = 0.19;
 const MIN_MAX_FORCE: f32 = 0.0;
 const MAX_MAX_FORCE: f32 = 5.0;
 const DEFAULT_MAX_FORCE: f32 = 0.42;
 const MIN_Z_FORCE_SCALE: f32 = 0.0;
 const MAX_Z_FORCE_SCALE: f32 = 2.0;
 const DEFAULT_Z_FORCE_SCALE: f32 = 0.75;
+const DEFAULT_WALL_RESTITUTION: f32 = 1.0;
+const DEFAULT_WALL_FRICTION: f32 = 0.0;
 const MIN_MIN_DISTANCE: f32 = 0.0;
 const MAX_MIN_DISTANCE: f32 = 1.0;
 const DEFAULT_SOFT_MIN_DISTANCE: f32 = 0.008;
@@ -38,10 +175,23 @@ const DEFAULT_JITTER_STRENGTH: f32 = 0.01;
 const MIN_DRAG: f32 = 0.0;
 const MAX_DRAG: f32 = 6.0;
 const DEFAULT_DRAG: f32 = 0.0;
+const MIN_QUADRATIC_DRAG: f32 = 0.0;
+const MAX_QUADRATIC_DRAG: f32 = 40.0;
+const DEFAULT_QUADRATIC_DRAG: f32 = 0.0;
+const MAX_GLOBAL_ACCELERATION: f32 = 5.0;
 const MIN_SHAPE_ATTRACTOR_WEIGHT: f32 = 0.0;
 const MAX_SHAPE_ATTRACTOR_WEIGHT: f32 = 5.0;
 const DEFAULT_SHAPE_ATTRACTOR_WEIGHT: f32 = 0.02;
 const MAX_SHAPE_POINTS: usize = 128;
+const MIN_CROWDING_CAP: f32 = 1.0;
+const MAX_CROWDING_CAP: f32 = 64.0;
+const DEFAULT_CROWDING_CAP: f32 = 6.0;
+const MIN_FAR_FIELD_OPENING: f32 = 0.0;
+const MAX_FAR_FIELD_OPENING: f32 = 1.5;
+const DEFAULT_FAR_FIELD_OPENING: f32 = 0.0;
+// Far-field mode needs several cells per neighbor radius; otherwise every cell
+// in range touches the query boid and nothing can be aggregated.
+const FAR_FIELD_CELL_FRACTION: f32 = 0.25;
 const HARD_CONSTRAINT_RELAXATION: f32 = 0.05;
 const HARD_CONSTRAINT_MAX_PUSH: f32 = 0.0025;
 
@@ -52,6 +202,7 @@ enum ModelKind {
     Flock2SocialFlight,
     Flock2LiteSocial,
     Flock2LiteSocialFlight,
+    FishSchool,
 }
 
 impl ModelKind {
@@ -61,6 +212,7 @@ impl ModelKind {
             2 => Self::Flock2SocialFlight,
             3 => Self::Flock2LiteSocial,
             4 => Self::Flock2LiteSocialFlight,
+            5 => Self::FishSchool,
             _ => Self::Classic,
         }
     }
@@ -72,6 +224,64 @@ impl ModelKind {
             Self::Flock2SocialFlight => 2,
             Self::Flock2LiteSocial => 3,
             Self::Flock2LiteSocialFlight => 4,
+            Self::FishSchool => 5,
+        }
+    }
+
+    // Classic and fish share velocity units; flock2 variants run in
+    // FLOCK2_WORLD_SCALE units and need a conversion when switched to or from.
+    fn uses_flock2_units(self) -> bool {
+        !matches!(self, Self::Classic | Self::FishSchool)
+    }
+}
+
+// Exponential damping is the original behavior. The force-based models treat
+// `drag` as the linear coefficient and `quadratic_drag` as the |v|^2 term.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum DragModel {
+    Exponential,
+    Linear,
+    Quadratic,
+    Combined,
+}
+
+impl DragModel {
+    fn from_u32(value: u32) -> Self {
+        match value {
+            1 => Self::Linear,
+            2 => Self::Quadratic,
+            3 => Self::Combined,
+            _ => Self::Exponential,
+        }
+    }
+
+    fn as_u32(self) -> u32 {
+        match self {
+            Self::Exponential => 0,
+            Self::Linear => 1,
+            Self::Quadratic => 2,
+            Self::Combined => 3,
+        }
+    }
+
+    // Names match the serde encoding and the TypeScript `SimDragModel` union.
+    fn name(self) -> &'static str {
+        match self {
+            Self::Exponential => "exponential",
+            Self::Linear => "linear",
+            Self::Quadratic => "quadratic",
+            Self::Combined => "combined",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "exponential" => Some(Self::Exponential),
+            "linear" => Some(Self::Linear),
+            "quadratic" => Some(Self::Quadratic),
+            "combined" => Some(Self::Combined),
+            _ => None,
         }
     }
 }
@@ -100,106 +310,368 @@ impl Lcg32 {
     }
 }
 
+// Weight on each neighbor's offset in the classic separation sum. The
+// inverse powers give a push of magnitude 1, 1/d and 1/d^2; `Smooth` fades
+// a unit push to zero at the separation radius.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum SeparationKernel {
+    InverseLinear,
+    InverseSquare,
+    InverseCube,
+    Smooth,
+}
+
+impl SeparationKernel {
+    fn from_u32(value: u32) -> Self {
+        match value {
+            0 => Self::InverseLinear,
+            2 => Self::InverseCube,
+            3 => Self::Smooth,
+            _ => Self::InverseSquare,
+        }
+    }
+
+    fn as_u32(self) -> u32 {
+        match self {
+            Self::InverseLinear => 0,
+            Self::InverseSquare => 1,
+            Self::InverseCube => 2,
+            Self::Smooth => 3,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::InverseLinear => "inverse-linear",
+            Self::InverseSquare => "inverse-square",
+            Self::InverseCube => "inverse-cube",
+            Self::Smooth => "smooth",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "inverse-linear" => Some(Self::InverseLinear),
+            "inverse-square" => Some(Self::InverseSquare),
+            "inverse-cube" => Some(Self::InverseCube),
+            "smooth" => Some(Self::Smooth),
+            _ => None,
+        }
+    }
+
+    fn offset_weight(self, dist_sq: f32, radius: f32) -> f32 {
+        let dist_sq = dist_sq.max(EPSILON);
+        match self {
+            Self::InverseLinear => 1.0 / dist_sq.sqrt(),
+            Self::InverseSquare => 1.0 / dist_sq,
+            Self::InverseCube => 1.0 / (dist_sq * dist_sq.sqrt()),
+            Self::Smooth => {
+                let dist = dist_sq.sqrt();
+                let fade = (1.0 - dist / radius.max(EPSILON)).max(0.0);
+                fade * fade / dist
+            }
+        }
+    }
+}
+
+// How strongly each classic behavior acts along z. Attractor also covers the
+// obstacle and predator scene forces.
 #[derive(Clone, Copy)]
-struct SimConfig {
-    sep_weight: f32,
-    align_weight: f32,
-    coh_weight: f32,
-    neighbor_radius: f32,
-    separation_radius: f32,
-    min_speed: f32,
-    max_speed: f32,
-    max_force: f32,
-    math_mode: MathMode,
-    max_neighbors_sampled: usize,
-    soft_min_distance: f32,
-    hard_min_distance: f32,
-    jitter_strength: f32,
-    drag: f32,
-    shape_attractor_weight: f32,
+struct ZForceScales {
+    separation: f32,
+    alignment: f32,
+    cohesion: f32,
+    attractor: f32,
+}
+
+impl ZForceScales {
+    fn uniform(scale: f32) -> Self {
+        Self {
+            separation: scale,
+            alignment: scale,
+            cohesion: scale,
+            attractor: scale,
+        }
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct SimConfig {
+    #[wasm_bindgen(js_name = sepWeight)]
+    pub sep_weight: f32,
+    #[wasm_bindgen(js_name = alignWeight)]
+    pub align_weight: f32,
+    #[wasm_bindgen(js_name = cohWeight)]
+    pub coh_weight: f32,
+    #[wasm_bindgen(js_name = speedMatchWeight)]
+    pub speed_match_weight: f32,
+    #[wasm_bindgen(js_name = neighborRadius)]
+    pub neighbor_radius: f32,
+    #[wasm_bindgen(js_name = separationRadius)]
+    pub separation_radius: f32,
+    #[wasm_bindgen(js_name = minSpeed)]
+    pub min_speed: f32,
+    #[wasm_bindgen(js_name = maxSpeed)]
+    pub max_speed: f32,
+    #[wasm_bindgen(js_name = maxForce)]
+    pub max_force: f32,
+    math_modes: [MathMode; MATH_PATH_COUNT],
+    #[wasm_bindgen(js_name = maxNeighborsSampled)]
+    pub max_neighbors_sampled: usize,
+    #[wasm_bindgen(js_name = softMinDistance)]
+    pub soft_min_distance: f32,
+    #[wasm_bindgen(js_name = hardMinDistance)]
+    pub hard_min_distance: f32,
+    #[wasm_bindgen(js_name = jitterStrength)]
+    pub jitter_strength: f32,
+    pub drag: f32,
+    drag_model: DragModel,
+    #[wasm_bindgen(js_name = quadraticDrag)]
+    pub quadratic_drag: f32,
+    #[wasm_bindgen(js_name = axisDragEnabled)]
+    pub axis_drag_enabled: bool,
+    #[wasm_bindgen(js_name = zDrag)]
+    pub z_drag: f32,
+    #[wasm_bindgen(js_name = zQuadraticDrag)]
+    pub z_quadratic_drag: f32,
+    #[wasm_bindgen(js_name = globalAccelX)]
+    pub global_accel_x: f32,
+    #[wasm_bindgen(js_name = globalAccelY)]
+    pub global_accel_y: f32,
+    #[wasm_bindgen(js_name = globalAccelZ)]
+    pub global_accel_z: f32,
+    #[wasm_bindgen(js_name = shapeAttractorWeight)]
+    pub shape_attractor_weight: f32,
+    #[wasm_bindgen(js_name = farFieldOpening)]
+    pub far_field_opening: f32,
+    // Align to neighbors' directions only, so fast neighbors do not dominate.
+    #[wasm_bindgen(js_name = alignToHeadings)]
+    pub align_to_headings: bool,
+    // Weight cohesion toward closer neighbors so edge boids do not overshoot.
+    #[wasm_bindgen(js_name = distanceWeightedCohesion)]
+    pub distance_weighted_cohesion: bool,
+    separation_kernel: SeparationKernel,
 }
 
 impl Default for SimConfig {
     fn default() -> Self {
         Self {
-            sep_weight: 1.45,
-            align_weight: 1.0,
-            coh_weight: 0.85,
-            neighbor_radius: 0.08,
-            separation_radius: 0.035,
-            min_speed: 0.045,
-            max_speed: 0.19,
+            sep_weight: DEFAULT_SEP_WEIGHT,
+            align_weight: DEFAULT_ALIGN_WEIGHT,
+            coh_weight: DEFAULT_COH_WEIGHT,
+            speed_match_weight: DEFAULT_SPEED_MATCH_WEIGHT,
+            neighbor_radius: DEFAULT_NEIGHBOR_RADIUS,
+            separation_radius: DEFAULT_SEPARATION_RADIUS,
+            min_speed: DEFAULT_MIN_SPEED,
+            max_speed: DEFAULT_MAX_SPEED,
             max_force: DEFAULT_MAX_FORCE,
-            math_mode: MathMode::Accurate,
+            math_modes: [MathMode::Accurate; MATH_PATH_COUNT],
             max_neighbors_sampled: 0,
             soft_min_distance: DEFAULT_SOFT_MIN_DISTANCE,
             hard_min_distance: DEFAULT_HARD_MIN_DISTANCE,
             jitter_strength: DEFAULT_JITTER_STRENGTH,
             drag: DEFAULT_DRAG,
+            drag_model: DragModel::Exponential,
+            quadratic_drag: DEFAULT_QUADRATIC_DRAG,
+            axis_drag_enabled: false,
+            z_drag: DEFAULT_DRAG,
+            z_quadratic_drag: DEFAULT_QUADRATIC_DRAG,
+            global_accel_x: 0.0,
+            global_accel_y: 0.0,
+            global_accel_z: 0.0,
             shape_attractor_weight: DEFAULT_SHAPE_ATTRACTOR_WEIGHT,
+            far_field_opening: DEFAULT_FAR_FIELD_OPENING,
+            align_to_headings: false,
+            distance_weighted_cohesion: false,
+            separation_kernel: SeparationKernel::InverseSquare,
         }
     }
 }
 
 impl SimConfig {
+    // The z coefficients only apply once axis drag is enabled so existing
+    // z_mode setups keep isotropic damping.
+    fn z_drag_coefficients(&self) -> (f32, f32) {
+        if self.axis_drag_enabled {
+            (self.z_drag, self.z_quadratic_drag)
+        } else {
+            (self.drag, self.quadratic_drag)
+        }
+    }
+
     fn sanitize(&mut self) {
-        self.sep_weight = clamp_finite(self.sep_weight, 0.0, 10.0, 1.45);
-        self.align_weight = clamp_finite(self.align_weight, 0.0, 10.0, 1.0);
-        self.coh_weight = clamp_finite(self.coh_weight, 0.0, 10.0, 0.85);
+        self.sanitize_reported(&mut Vec::new());
+    }
 
-        self.neighbor_radius = clamp_finite(
+    fn sanitize_reported(&mut self, report: &mut Vec<ConfigAdjustment>) {
+        self.sep_weight = clamp_reported(
+            report,
+            "sep_weight",
+            self.sep_weight,
+            MIN_STEERING_WEIGHT,
+            MAX_STEERING_WEIGHT,
+            DEFAULT_SEP_WEIGHT,
+        );
+        self.align_weight = clamp_reported(
+            report,
+            "align_weight",
+            self.align_weight,
+            MIN_STEERING_WEIGHT,
+            MAX_STEERING_WEIGHT,
+            DEFAULT_ALIGN_WEIGHT,
+        );
+        self.coh_weight = clamp_reported(
+            report,
+            "coh_weight",
+            self.coh_weight,
+            MIN_STEERING_WEIGHT,
+            MAX_STEERING_WEIGHT,
+            DEFAULT_COH_WEIGHT,
+        );
+        self.speed_match_weight = clamp_reported(
+            report,
+            "speed_match_weight",
+            self.speed_match_weight,
+            MIN_STEERING_WEIGHT,
+            MAX_STEERING_WEIGHT,
+            DEFAULT_SPEED_MATCH_WEIGHT,
+        );
+
+        self.neighbor_radius = clamp_reported(
+            report,
+            "neighbor_radius",
             self.neighbor_radius,
             MIN_NEIGHBOR_RADIUS,
             MAX_NEIGHBOR_RADIUS,
-            0.08,
+            DEFAULT_NEIGHBOR_RADIUS,
         );
 
-        self.separation_radius = clamp_finite(
+        self.separation_radius = clamp_reported(
+            report,
+            "separation_radius",
             self.separation_radius,
             MIN_SEPARATION_RADIUS,
             self.neighbor_radius,
-            0.035,
+            DEFAULT_SEPARATION_RADIUS,
         );
 
-        self.min_speed = clamp_finite(self.min_speed, MIN_SPEED, MAX_SPEED, 0.045);
-        self.max_speed = clamp_finite(
+        self.min_speed = clamp_reported(
+            report,
+            "min_speed",
+            self.min_speed,
+            MIN_SPEED,
+            MAX_SPEED,
+            DEFAULT_MIN_SPEED,
+        );
+        self.max_speed = clamp_reported(
+            report,
+            "max_speed",
             self.max_speed,
             self.min_speed.max(MIN_NEIGHBOR_RADIUS),
             MAX_SPEED,
-            0.19,
+            DEFAULT_MAX_SPEED,
         );
 
-        self.max_force = clamp_finite(
+        self.max_force = clamp_reported(
+            report,
+            "max_force",
             self.max_force,
             MIN_MAX_FORCE,
             MAX_MAX_FORCE,
             DEFAULT_MAX_FORCE,
         );
-        self.soft_min_distance = clamp_finite(
+        self.soft_min_distance = clamp_reported(
+            report,
+            "soft_min_distance",
             self.soft_min_distance,
             MIN_MIN_DISTANCE,
             MAX_MIN_DISTANCE,
             DEFAULT_SOFT_MIN_DISTANCE,
         );
-        self.hard_min_distance = clamp_finite(
+        self.hard_min_distance = clamp_reported(
+            report,
+            "hard_min_distance",
             self.hard_min_distance,
             MIN_MIN_DISTANCE,
             MAX_MIN_DISTANCE,
             DEFAULT_HARD_MIN_DISTANCE,
         );
-        self.jitter_strength = clamp_finite(
+        self.jitter_strength = clamp_reported(
+            report,
+            "jitter_strength",
             self.jitter_strength,
             MIN_JITTER_STRENGTH,
             MAX_JITTER_STRENGTH,
             DEFAULT_JITTER_STRENGTH,
         );
-        self.drag = clamp_finite(self.drag, MIN_DRAG, MAX_DRAG, DEFAULT_DRAG);
-        self.shape_attractor_weight = clamp_finite(
+        self.drag = clamp_reported(report, "drag", self.drag, MIN_DRAG, MAX_DRAG, DEFAULT_DRAG);
+        self.quadratic_drag = clamp_reported(
+            report,
+            "quadratic_drag",
+            self.quadratic_drag,
+            MIN_QUADRATIC_DRAG,
+            MAX_QUADRATIC_DRAG,
+            DEFAULT_QUADRATIC_DRAG,
+        );
+        self.z_drag = clamp_reported(
+            report,
+            "z_drag",
+            self.z_drag,
+            MIN_DRAG,
+            MAX_DRAG,
+            DEFAULT_DRAG,
+        );
+        self.z_quadratic_drag = clamp_reported(
+            report,
+            "z_quadratic_drag",
+            self.z_quadratic_drag,
+            MIN_QUADRATIC_DRAG,
+            MAX_QUADRATIC_DRAG,
+            DEFAULT_QUADRATIC_DRAG,
+        );
+        self.global_accel_x = clamp_reported(
+            report,
+            "global_accel_x",
+            self.global_accel_x,
+            -MAX_GLOBAL_ACCELERATION,
+            MAX_GLOBAL_ACCELERATION,
+            0.0,
+        );
+        self.global_accel_y = clamp_reported(
+            report,
+            "global_accel_y",
+            self.global_accel_y,
+            -MAX_GLOBAL_ACCELERATION,
+            MAX_GLOBAL_ACCELERATION,
+            0.0,
+        );
+        self.global_accel_z = clamp_reported(
+            report,
+            "global_accel_z",
+            self.global_accel_z,
+            -MAX_GLOBAL_ACCELERATION,
+            MAX_GLOBAL_ACCELERATION,
+            0.0,
+        );
+        self.shape_attractor_weight = clamp_reported(
+            report,
+            "shape_attractor_weight",
             self.shape_attractor_weight,
             MIN_SHAPE_ATTRACTOR_WEIGHT,
             MAX_SHAPE_ATTRACTOR_WEIGHT,
             DEFAULT_SHAPE_ATTRACTOR_WEIGHT,
         );
+        self.far_field_opening = clamp_reported(
+            report,
+            "far_field_opening",
+            self.far_field_opening,
+            MIN_FAR_FIELD_OPENING,
+            MAX_FAR_FIELD_OPENING,
+            DEFAULT_FAR_FIELD_OPENING,
+        );
     }
 }
 
@@ -211,12 +683,27 @@ pub struct Sim {
     height: f32,
     model_kind: ModelKind,
     config: SimConfig,
+    rng: Lcg32,
     flock2_config: Flock2Config,
+    fish_config: FishConfig,
+    fish_phase: f32,
+    burst_coast: BurstCoast,
+    accel_ramp: AccelRamp,
+    spawn_layout: SpawnLayout,
+    classic_headings: ClassicHeadings,
+    speed_radius: SpeedRadius,
+    species_partition: SpeciesPartition,
+    turn_noise: TurnNoise,
     bounce_x: bool,
     bounce_y: bool,
     bounce_z: bool,
+    // Fraction of the normal speed kept on a bounce, and of the tangential
+    // speed lost, on walls of bounce axes.
+    wall_restitution: f32,
+    wall_friction: f32,
+    z_extent: ZExtent,
     z_mode_enabled: bool,
-    z_force_scale: f32,
+    z_force_scales: ZForceScales,
     pos_x: Vec<f32>,
     pos_y: Vec<f32>,
     pos_z: Vec<f32>,
@@ -232,10 +719,104 @@ pub struct Sim {
     render_xy: Vec<f32>,
     render_z: Vec<f32>,
     render_heading_xy: Vec<f32>,
+    heading_smoothing: HeadingSmoothing,
+    render_crowding: Vec<f32>,
+    render_scale: Vec<f32>,
+    render_scale_settings: RenderScale,
+    depth_speed: DepthSpeed,
+    math_divergence: MathDivergence,
+    projection: Projection,
+    camera: Camera,
+    color_map: ColorMap,
+    view_grid: ViewGrid,
+    boid_size: Vec<f32>,
+    variation: Vec<f32>,
+    variation_seed: u32,
+    external_scalar: Vec<f32>,
+    external_targets: ExternalScalarTargets,
+    behavior: Behavior,
+    roles: Roles,
+    role: Vec<u8>,
+    crowding_cap: f32,
     shape_points_xyz: Vec<f32>,
+    attractor_falloff: AttractorFalloff,
     neighbor_grid: NeighborGrid,
+    constraint_grid: HierarchicalGrid,
+    // Depth slab of each boid for the constraint pass in z mode; the grid
+    // itself only bins x and y.
+    constraint_z_bins: Vec<u32>,
+    neighbor_backend: NeighborBackend,
+    neighbor_memory: NeighborMemory,
+    // Ranked neighbor slots for flock2 topological caps too large to keep on
+    // the stack; grown to the cap and reused across boids and steps.
+    topological_indices: Vec<usize>,
+    topological_dsq: Vec<f32>,
+    familiarity: Familiarity,
+    kd_tree: KdTree,
+    kd_rebuild_interval: u32,
+    grid_rebuild_interval: u32,
+    neighbor_cell_cap: usize,
     neighbors_visited_last_step: usize,
     step_index: u32,
+    step_events: StepEvents,
+    time_scale: f32,
+    paused: bool,
+    // Set during `warmup`, which publishes render buffers once at the end.
+    warming_up: bool,
+    last_dt: f32,
+    wind: Wind,
+    recording: Recording,
+    snapshots: SnapshotHistory,
+    steering_debug: SteeringDebug,
+    clusters: Clusters,
+    metric_history: MetricHistory,
+    boundary_hit_flags_enabled: bool,
+    boundary_hit_flags: Vec<u8>,
+    overlap_metrics_enabled: bool,
+    contact_pairs: Vec<u32>,
+    step_hooks: [Option<Box<dyn StepHook>>; STEP_STAGE_COUNT],
+    step_hook_scratch: Vec<f32>,
+    custom_force: Option<Box<dyn CustomForce>>,
+    objective: ObjectiveState,
+    split_world: SplitWorld,
+    mirror: Mirror,
+    wrap_ghosts: WrapGhosts,
+    kaleidoscope: Kaleidoscope,
+    custom_force_x: Vec<f32>,
+    custom_force_y: Vec<f32>,
+    custom_force_z: Vec<f32>,
+    // Stable id of the boid stored in each slot, and its inverse.
+    boid_ids: Vec<u32>,
+    boid_slots: Vec<u32>,
+    boid_generations: Vec<u32>,
+    spawned_since_step: u32,
+    despawned_since_step: u32,
+    lod_tiers: Vec<u8>,
+    lod_interval: u32,
+    focus: FocusRegion,
+    update_fraction: f32,
+    partial_step: PartialStep,
+    altitude_hold: AltitudeHold,
+    altitude_integral: Vec<f32>,
+    species: Vec<u8>,
+    species_aero: [Option<AeroProfile>; MAX_SPECIES],
+    species_hard_min: SpeciesHardMin,
+    obstacles: Obstacles,
+    danger_zones: DangerZones,
+    safe_zones: SafeZones,
+    corridors: Corridors,
+    config_regions: ConfigRegions,
+    terrain: Terrain,
+    exclusion: ExclusionSphere,
+    predators: Predators,
+    predictive: PredictiveAvoidance,
+    pair_avoidance: PairAvoidance,
+    // Flock2 evasion per boid: direction scaled by threat, and seconds left.
+    evasion_xyz: Vec<f32>,
+    evasion_timer: Vec<f32>,
+    reaction_spread: ReactionSpread,
+    // Per-boid multiplier on the flock2 reaction time.
+    reaction_scale: Vec<f32>,
 }
 
 #[wasm_bindgen]
@@ -246,182 +827,278 @@ impl Sim {
         let height = height.max(MIN_BOUND);
         let config = SimConfig::default();
         let flock2_config = Flock2Config::default();
-        let mut rng = Lcg32::new(seed);
-
-        let mut pos_x = vec![0.0; count];
-        let mut pos_y = vec![0.0; count];
-        let mut pos_z = vec![0.0; count];
-        let mut vel_x = vec![0.0; count];
-        let mut vel_y = vec![0.0; count];
-        let mut vel_z = vec![0.0; count];
-        let mut heading_x = vec![0.0; count];
-        let mut heading_y = vec![0.0; count];
-        let mut heading_z = vec![0.0; count];
-        let mut render_xy = vec![0.0; count * 2];
-        let mut render_z = vec![DEFAULT_Z_LAYER; count];
-        let mut render_heading_xy = vec![0.0; count * 2];
         let shape_points_xyz = vec![0.5, 0.5, DEFAULT_Z_LAYER];
 
-        for i in 0..count {
-            pos_x[i] = rng.next_f32();
-            pos_y[i] = rng.next_f32();
-            pos_z[i] = rng.next_f32();
-
-            let angle = rng.next_f32() * TAU;
-            let speed = config.min_speed + (config.max_speed - config.min_speed) * rng.next_f32();
-            vel_x[i] = angle.cos() * speed;
-            vel_y[i] = angle.sin() * speed;
-            vel_z[i] = (rng.next_f32() * 2.0 - 1.0) * speed * 0.35;
-            let (hx, hy, hz) = normalize_or_default(vel_x[i], vel_y[i], vel_z[i], 1.0, 0.0, 0.0);
-            heading_x[i] = hx;
-            heading_y[i] = hy;
-            heading_z[i] = hz;
-
-            let base = 2 * i;
-            render_xy[base] = pos_x[i];
-            render_xy[base + 1] = pos_y[i];
-            render_z[i] = DEFAULT_Z_LAYER;
-            render_heading_xy[base] = heading_x[i];
-            render_heading_xy[base + 1] = heading_y[i];
-        }
-
-        Sim {
-            count,
-            active_count: count,
+        let mut sim = Sim {
+            count: 0,
+            active_count: 0,
             width,
             height,
             model_kind: ModelKind::Classic,
             config,
             flock2_config,
+            fish_config: FishConfig::default(),
+            fish_phase: 0.0,
+            burst_coast: BurstCoast::default(),
+            accel_ramp: AccelRamp::default(),
+            spawn_layout: SpawnLayout::default(),
+            classic_headings: ClassicHeadings::default(),
+            speed_radius: SpeedRadius::default(),
+            species_partition: SpeciesPartition::default(),
+            turn_noise: TurnNoise::default(),
             bounce_x: false,
             bounce_y: false,
             bounce_z: false,
+            wall_restitution: DEFAULT_WALL_RESTITUTION,
+            wall_friction: DEFAULT_WALL_FRICTION,
+            z_extent: ZExtent::default(),
             z_mode_enabled: false,
-            z_force_scale: DEFAULT_Z_FORCE_SCALE,
-            pos_x,
-            pos_y,
-            pos_z,
-            vel_x,
-            vel_y,
-            vel_z,
-            heading_x,
-            heading_y,
-            heading_z,
-            accel_x: vec![0.0; count],
-            accel_y: vec![0.0; count],
-            accel_z: vec![0.0; count],
-            render_xy,
-            render_z,
-            render_heading_xy,
+            z_force_scales: ZForceScales::uniform(DEFAULT_Z_FORCE_SCALE),
+            rng: Lcg32::new(seed),
+            pos_x: Vec::new(),
+            pos_y: Vec::new(),
+            pos_z: Vec::new(),
+            vel_x: Vec::new(),
+            vel_y: Vec::new(),
+            vel_z: Vec::new(),
+            heading_x: Vec::new(),
+            heading_y: Vec::new(),
+            heading_z: Vec::new(),
+            accel_x: Vec::new(),
+            accel_y: Vec::new(),
+            accel_z: Vec::new(),
+            render_xy: Vec::new(),
+            render_z: Vec::new(),
+            render_heading_xy: Vec::new(),
+            heading_smoothing: HeadingSmoothing::default(),
+            render_crowding: Vec::new(),
+            render_scale: Vec::new(),
+            render_scale_settings: RenderScale::default(),
+            depth_speed: DepthSpeed::default(),
+            math_divergence: MathDivergence::default(),
+            projection: Projection::default(),
+            camera: Camera::default(),
+            color_map: ColorMap::default(),
+            view_grid: ViewGrid::new(count),
+            boid_size: Vec::new(),
+            variation: Vec::new(),
+            variation_seed: seed,
+            external_scalar: Vec::new(),
+            external_targets: ExternalScalarTargets::default(),
+            behavior: Behavior::default(),
+            roles: Roles::default(),
+            role: Vec::new(),
+            crowding_cap: DEFAULT_CROWDING_CAP,
             shape_points_xyz,
+            attractor_falloff: AttractorFalloff::default(),
             neighbor_grid: NeighborGrid::new(count, WORLD_SIZE, WORLD_SIZE, config.neighbor_radius),
+            constraint_grid: HierarchicalGrid::new(
+                count,
+                WORLD_SIZE,
+                WORLD_SIZE,
+                config.neighbor_radius,
+            ),
+            constraint_z_bins: Vec::new(),
+            neighbor_backend: NeighborBackend::Grid,
+            neighbor_memory: NeighborMemory::default(),
+            topological_indices: Vec::new(),
+            topological_dsq: Vec::new(),
+            familiarity: Familiarity::default(),
+            kd_tree: KdTree::new(count),
+            kd_rebuild_interval: 1,
+            grid_rebuild_interval: 1,
+            neighbor_cell_cap: 0,
             neighbors_visited_last_step: 0,
             step_index: 0,
-        }
-    }
-
-    #[allow(clippy::too_many_arguments)]
-    pub fn set_config(
-        &mut self,
-        sep_weight: f32,
-        align_weight: f32,
-        coh_weight: f32,
-        neighbor_radius: f32,
-        separation_radius: f32,
-        min_speed: f32,
-        max_speed: f32,
-        max_force: f32,
-    ) {
-        self.config = SimConfig {
-            sep_weight,
-            align_weight,
-            coh_weight,
-            neighbor_radius,
-            separation_radius,
-            min_speed,
-            max_speed,
-            max_force,
-            math_mode: self.config.math_mode,
-            max_neighbors_sampled: self.config.max_neighbors_sampled,
-            soft_min_distance: self.config.soft_min_distance,
-            hard_min_distance: self.config.hard_min_distance,
-            jitter_strength: self.config.jitter_strength,
-            drag: self.config.drag,
-            shape_attractor_weight: self.config.shape_attractor_weight,
+            step_events: StepEvents::default(),
+            time_scale: 1.0,
+            paused: false,
+            warming_up: false,
+            last_dt: DEFAULT_DEBUG_DT,
+            wind: Wind::default(),
+            recording: Recording::default(),
+            snapshots: SnapshotHistory::default(),
+            steering_debug: SteeringDebug::default(),
+            clusters: Clusters::new(count),
+            metric_history: MetricHistory::default(),
+            boundary_hit_flags_enabled: false,
+            boundary_hit_flags: Vec::new(),
+            overlap_metrics_enabled: false,
+            contact_pairs: vec![0; MAX_RECORDED_CONTACTS * 2],
+            step_hooks: Default::default(),
+            step_hook_scratch: Vec::new(),
+            custom_force: None,
+            objective: ObjectiveState::default(),
+            split_world: SplitWorld::default(),
+            mirror: Mirror::default(),
+            wrap_ghosts: WrapGhosts::default(),
+            kaleidoscope: Kaleidoscope::default(),
+            custom_force_x: Vec::new(),
+            custom_force_y: Vec::new(),
+            custom_force_z: Vec::new(),
+            boid_ids: Vec::new(),
+            boid_slots: Vec::new(),
+            boid_generations: Vec::new(),
+            spawned_since_step: 0,
+            despawned_since_step: 0,
+            lod_tiers: Vec::new(),
+            lod_interval: DEFAULT_LOD_INTERVAL,
+            focus: FocusRegion::default(),
+            update_fraction: DEFAULT_UPDATE_FRACTION,
+            partial_step: PartialStep::default(),
+            altitude_hold: AltitudeHold::default(),
+            altitude_integral: Vec::new(),
+            species: Vec::new(),
+            species_aero: [None; MAX_SPECIES],
+            species_hard_min: [[None; MAX_SPECIES]; MAX_SPECIES],
+            obstacles: Obstacles::default(),
+            danger_zones: DangerZones::default(),
+            safe_zones: SafeZones::default(),
+            corridors: Corridors::default(),
+            config_regions: ConfigRegions::default(),
+            terrain: Terrain::default(),
+            exclusion: ExclusionSphere::default(),
+            predators: Predators::default(),
+            predictive: PredictiveAvoidance::default(),
+            pair_avoidance: PairAvoidance::default(),
+            evasion_xyz: Vec::new(),
+            evasion_timer: Vec::new(),
+            reaction_spread: ReactionSpread::default(),
+            reaction_scale: Vec::new(),
         };
-        self.config.sanitize();
+        sim.reserve(count);
+        sim.active_count = count;
+        sim
+    }
 
-        self.neighbor_grid
-            .set_cell_size(self.config.neighbor_radius);
+    // Allocates room for `max_count` boids up front with none active, so
+    // spawning through `set_active_count` never grows wasm memory.
+    pub fn with_capacity(max_count: usize, seed: u32, width: f32, height: f32) -> Sim {
+        let mut sim = Sim::new(max_count, seed, width, height);
+        sim.active_count = 0;
+        sim
     }
 
+    // Velocities are only reseeded when the switch crosses between classic
+    // units (classic, fish) and flock2 units (the four flock2 kinds): they are
+    // converted and clamped to the new model's speed range. Switching within a
+    // family keeps every velocity and heading as it is.
     pub fn set_model_kind(&mut self, kind: u32) {
         let next_kind = ModelKind::from_u32(kind);
         if self.model_kind == next_kind {
             return;
         }
 
+        let convert = self.model_kind.uses_flock2_units() != next_kind.uses_flock2_units();
         self.model_kind = next_kind;
-        self.reseed_velocity_for_model();
+        self.render_crowding.fill(0.0);
+        self.sync_neighbor_cell_size();
+        if convert {
+            self.reseed_velocity_for_model();
+        }
     }
 
     pub fn model_kind(&self) -> u32 {
         self.model_kind.as_u32()
     }
 
+    // Caps flock2 turn rate with a coordinated-turn model, so faster birds
+    // turn in wider arcs. 90 degrees removes the limit.
+    pub fn set_flock2_max_bank_deg(&mut self, degrees: f32) {
+        self.flock2_config.max_bank_deg = clamp_finite(
+            degrees,
+            FLOCK2_MIN_BANK_DEG,
+            FLOCK2_MAX_BANK_DEG,
+            FLOCK2_MAX_BANK_DEG,
+        );
+    }
+
+    pub fn flock2_max_bank_deg(&self) -> f32 {
+        self.flock2_config.max_bank_deg
+    }
+
+    // Perturbs every flock2 steering decision by a seeded random yaw and
+    // pitch of at most `amplitude_deg`; 0 turns the noise off.
+    pub fn set_flock2_decision_noise(&mut self, amplitude_deg: f32, seed: u32) {
+        self.flock2_config.decision_noise_deg =
+            clamp_finite(amplitude_deg, 0.0, FLOCK2_MAX_DECISION_NOISE_DEG, 0.0);
+        self.flock2_config.decision_noise_seed = seed;
+    }
+
+    pub fn flock2_decision_noise_deg(&self) -> f32 {
+        self.flock2_config.decision_noise_deg
+    }
+
+    // Field of view for flock2 collision avoidance, separate from the cone
+    // used for alignment and cohesion. 0 follows the social field of view.
+    pub fn set_flock2_avoid_fov_deg(&mut self, degrees: f32) {
+        self.flock2_config.avoid_field_of_view_deg = if degrees == 0.0 {
+            0.0
+        } else {
+            clamp_finite(degrees, FLOCK2_MIN_FOV_DEG, FLOCK2_MAX_FOV_DEG, 0.0)
+        };
+    }
+
+    pub fn flock2_avoid_fov_deg(&self) -> f32 {
+        self.flock2_config.avoid_field_of_view_deg
+    }
+
+    // Flock2 Lite samples the first visible neighbors it finds rather than
+    // the nearest, so it takes at most this many on top of the topological
+    // count. 0 lifts the extra cap.
+    pub fn set_flock2_lite_neighbor_cap(&mut self, cap: u32) {
+        self.flock2_config.lite_neighbor_cap = (cap as usize).min(FLOCK2_MAX_TOPOLOGICAL_NEIGHBORS);
+    }
+
+    pub fn flock2_lite_neighbor_cap(&self) -> u32 {
+        self.flock2_config.lite_neighbor_cap as u32
+    }
+
     #[allow(clippy::too_many_arguments)]
-    pub fn set_flock2_social_config(
+    pub fn set_fish_config(
         &mut self,
-        avoid_weight: f32,
-        align_weight: f32,
-        cohesion_weight: f32,
-        boundary_weight: f32,
-        boundary_count: f32,
-        neighbor_radius: f32,
-        topological_neighbors: usize,
-        field_of_view_deg: f32,
+        lateral_alignment: f32,
+        preferred_depth: f32,
+        depth_weight: f32,
+        burst_period: f32,
+        burst_fraction: f32,
+        burst_thrust: f32,
+        coast_drag: f32,
     ) {
-        self.flock2_config.avoid_weight = avoid_weight;
-        self.flock2_config.align_weight = align_weight;
-        self.flock2_config.cohesion_weight = cohesion_weight;
-        self.flock2_config.boundary_weight = boundary_weight;
-        self.flock2_config.boundary_count = boundary_count;
-        self.flock2_config.neighbor_radius = neighbor_radius;
-        self.flock2_config.topological_neighbors = topological_neighbors;
-        self.flock2_config.field_of_view_deg = field_of_view_deg;
-        self.flock2_config.sanitize();
-        self.neighbor_grid
-            .set_cell_size(self.flock2_config.neighbor_radius);
+        self.set_fish_config_checked(
+            lateral_alignment,
+            preferred_depth,
+            depth_weight,
+            burst_period,
+            burst_fraction,
+            burst_thrust,
+            coast_drag,
+        );
     }
 
     #[allow(clippy::too_many_arguments)]
-    pub fn set_flock2_flight_config(
+    pub fn set_fish_config_checked(
         &mut self,
-        reaction_time_ms: f32,
-        dynamic_stability: f32,
-        mass: f32,
-        wing_area: f32,
-        lift_factor: f32,
-        drag_factor: f32,
-        thrust: f32,
-        min_speed: f32,
-        max_speed: f32,
-        gravity: f32,
-        air_density: f32,
-    ) {
-        self.flock2_config.reaction_time_ms = reaction_time_ms;
-        self.flock2_config.dynamic_stability = dynamic_stability;
-        self.flock2_config.mass = mass;
-        self.flock2_config.wing_area = wing_area;
-        self.flock2_config.lift_factor = lift_factor;
-        self.flock2_config.drag_factor = drag_factor;
-        self.flock2_config.thrust = thrust;
-        self.flock2_config.min_speed = min_speed;
-        self.flock2_config.max_speed = max_speed;
-        self.flock2_config.gravity = gravity;
-        self.flock2_config.air_density = air_density;
-        self.flock2_config.sanitize();
-        self.reseed_velocity_for_model();
+        lateral_alignment: f32,
+        preferred_depth: f32,
+        depth_weight: f32,
+        burst_period: f32,
+        burst_fraction: f32,
+        burst_thrust: f32,
+        coast_drag: f32,
+    ) -> Vec<ConfigAdjustment> {
+        self.fish_config = FishConfig {
+            lateral_alignment,
+            preferred_depth,
+            depth_weight,
+            burst_period,
+            burst_fraction,
+            burst_thrust,
+            coast_drag,
+        };
+        let mut report = Vec::new();
+        self.fish_config.sanitize_reported(&mut report);
+        report
     }
 
     pub fn set_z_mode(&mut self, enabled: bool) {
@@ -433,12 +1110,15 @@ impl Sim {
                 self.vel_z[i] = 0.0;
                 self.accel_z[i] = 0.0;
                 self.render_z[i] = DEFAULT_Z_LAYER;
+                self.sync_render_scale(i);
             }
             return;
         }
 
         for i in 0..self.count {
+            self.pos_z[i] = self.z_extent.project(self.pos_z[i], self.bounce_z);
             self.render_z[i] = self.pos_z[i];
+            self.sync_render_scale(i);
         }
     }
 
@@ -446,13 +1126,42 @@ impl Sim {
         self.z_mode_enabled
     }
 
+    // Sets every behavior's z scale at once.
     pub fn set_z_force_scale(&mut self, scale: f32) {
-        self.z_force_scale = clamp_finite(
-            scale,
-            MIN_Z_FORCE_SCALE,
-            MAX_Z_FORCE_SCALE,
-            DEFAULT_Z_FORCE_SCALE,
-        );
+        self.z_force_scales = ZForceScales::uniform(clamp_z_force_scale(scale));
+    }
+
+    // Separate z scales per behavior, e.g. planar separation with cohesion
+    // driving depth for a layered 2.5D look.
+    pub fn set_z_force_scales(
+        &mut self,
+        separation: f32,
+        alignment: f32,
+        cohesion: f32,
+        attractor: f32,
+    ) {
+        self.z_force_scales = ZForceScales {
+            separation: clamp_z_force_scale(separation),
+            alignment: clamp_z_force_scale(alignment),
+            cohesion: clamp_z_force_scale(cohesion),
+            attractor: clamp_z_force_scale(attractor),
+        };
+    }
+
+    pub fn z_force_scale_separation(&self) -> f32 {
+        self.z_force_scales.separation
+    }
+
+    pub fn z_force_scale_alignment(&self) -> f32 {
+        self.z_force_scales.alignment
+    }
+
+    pub fn z_force_scale_cohesion(&self) -> f32 {
+        self.z_force_scales.cohesion
+    }
+
+    pub fn z_force_scale_attractor(&self) -> f32 {
+        self.z_force_scales.attractor
     }
 
     pub fn set_bounce_bounds(&mut self, enabled: bool) {
@@ -483,12 +1192,69 @@ impl Sim {
         self.bounce_z
     }
 
+    // Restitution 1 and friction 0 is a mirror bounce. Lower restitution
+    // loses energy off the wall; restitution 0 with friction 1 absorbs boids,
+    // which stay parked on the wall until steering pushes them away from it.
+    pub fn set_wall_response(&mut self, restitution: f32, friction: f32) {
+        self.wall_restitution = clamp_finite(restitution, 0.0, 1.0, DEFAULT_WALL_RESTITUTION);
+        self.wall_friction = clamp_finite(friction, 0.0, 1.0, DEFAULT_WALL_FRICTION);
+    }
+
+    pub fn wall_restitution(&self) -> f32 {
+        self.wall_restitution
+    }
+
+    pub fn wall_friction(&self) -> f32 {
+        self.wall_friction
+    }
+
+    // Sets every math path at once.
     pub fn set_math_mode(&mut self, mode: u32) {
-        self.config.math_mode = MathMode::from_u32(mode);
+        self.config.math_modes = [MathMode::from_u32(mode); MATH_PATH_COUNT];
     }
 
+    // The steering path's mode.
     pub fn math_mode(&self) -> u32 {
-        self.config.math_mode.as_u32()
+        self.config.math_modes[MathPath::Steering.index()].as_u32()
+    }
+
+    // `path` is 0 for steering, 1 for speed limits, hard-min separation and
+    // other constraints, 2 for flock2 and fish propulsion; unknown paths are
+    // ignored.
+    pub fn set_path_math_mode(&mut self, path: u32, mode: u32) {
+        if let Some(path) = MathPath::from_u32(path) {
+            self.config.math_modes[path.index()] = MathMode::from_u32(mode);
+        }
+    }
+
+    pub fn path_math_mode(&self, path: u32) -> u32 {
+        MathPath::from_u32(path).map_or(0, |path| self.config.math_modes[path.index()].as_u32())
+    }
+
+    // Runs the accurate path next to every approximated call and tracks the
+    // largest gap per math path, to show where the fast modes are safe.
+    // Turning it on or off resets the gaps.
+    pub fn set_math_verification(&mut self, enabled: bool) {
+        self.math_divergence.enabled = enabled;
+        self.math_divergence.reset();
+    }
+
+    pub fn math_verification(&self) -> bool {
+        self.math_divergence.enabled
+    }
+
+    // Largest gap per path since verification started, relative to the exact
+    // value or absolute where that is below 1, in path order.
+    pub fn math_divergence(&self) -> Vec<f32> {
+        self.math_divergence
+            .max
+            .iter()
+            .map(|max| max.get())
+            .collect()
+    }
+
+    pub fn reset_math_divergence(&mut self) {
+        self.math_divergence.reset();
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -523,6 +1289,99 @@ impl Sim {
         self.neighbors_visited_last_step
     }
 
+    pub fn set_sep_weight(&mut self, weight: f32) {
+        self.config.sep_weight = clamp_finite(
+            weight,
+            MIN_STEERING_WEIGHT,
+            MAX_STEERING_WEIGHT,
+            DEFAULT_SEP_WEIGHT,
+        );
+    }
+
+    pub fn sep_weight(&self) -> f32 {
+        self.config.sep_weight
+    }
+
+    pub fn set_align_weight(&mut self, weight: f32) {
+        self.config.align_weight = clamp_finite(
+            weight,
+            MIN_STEERING_WEIGHT,
+            MAX_STEERING_WEIGHT,
+            DEFAULT_ALIGN_WEIGHT,
+        );
+    }
+
+    pub fn align_weight(&self) -> f32 {
+        self.config.align_weight
+    }
+
+    pub fn set_coh_weight(&mut self, weight: f32) {
+        self.config.coh_weight = clamp_finite(
+            weight,
+            MIN_STEERING_WEIGHT,
+            MAX_STEERING_WEIGHT,
+            DEFAULT_COH_WEIGHT,
+        );
+    }
+
+    pub fn coh_weight(&self) -> f32 {
+        self.config.coh_weight
+    }
+
+    // Pulls each boid's speed toward the mean neighbor speed along its own
+    // heading, independent of where the neighbors are headed.
+    pub fn set_speed_match_weight(&mut self, weight: f32) {
+        self.config.speed_match_weight = clamp_finite(
+            weight,
+            MIN_STEERING_WEIGHT,
+            MAX_STEERING_WEIGHT,
+            DEFAULT_SPEED_MATCH_WEIGHT,
+        );
+    }
+
+    pub fn speed_match_weight(&self) -> f32 {
+        self.config.speed_match_weight
+    }
+
+    // Radius and speed bounds depend on each other, so these setters re-run the
+    // full sanitize pass instead of clamping a single field.
+    pub fn set_neighbor_radius(&mut self, radius: f32) {
+        self.config.neighbor_radius = radius;
+        self.config.sanitize();
+        self.sync_neighbor_cell_size();
+    }
+
+    pub fn neighbor_radius(&self) -> f32 {
+        self.config.neighbor_radius
+    }
+
+    pub fn set_separation_radius(&mut self, radius: f32) {
+        self.config.separation_radius = radius;
+        self.config.sanitize();
+    }
+
+    pub fn separation_radius(&self) -> f32 {
+        self.config.separation_radius
+    }
+
+    pub fn set_min_speed(&mut self, min_speed: f32) {
+        self.config.min_speed = min_speed;
+        self.config.sanitize();
+    }
+
+    pub fn min_speed(&self) -> f32 {
+        self.config.min_speed
+    }
+
+    pub fn set_max_speed(&mut self, max_speed: f32) {
+        self.config.max_speed = max_speed;
+        self.config.sanitize();
+    }
+
+    pub fn max_speed(&self) -> f32 {
+        self.config.max_speed
+    }
+
     pub fn set_max_force(&mut self, max_force: f32) {
         self.config.max_force =
             clamp_finite(max_force, MIN_MAX_FORCE, MAX_MAX_FORCE, DEFAULT_MAX_FORCE);
@@ -579,6 +1438,106 @@ impl Sim {
         self.config.drag
     }
 
+    pub fn set_drag_model(&mut self, model: u32) {
+        self.config.drag_model = DragModel::from_u32(model);
+    }
+
+    pub fn drag_model(&self) -> u32 {
+        self.config.drag_model.as_u32()
+    }
+
+    // 0: 1/d, 1: 1/d^2 (default), 2: 1/d^3, 3: smooth falloff to the
+    // separation radius.
+    pub fn set_separation_kernel(&mut self, kernel: u32) {
+        self.config.separation_kernel = SeparationKernel::from_u32(kernel);
+    }
+
+    pub fn separation_kernel(&self) -> u32 {
+        self.config.separation_kernel.as_u32()
+    }
+
+    pub fn set_quadratic_drag(&mut self, drag: f32) {
+        self.config.quadratic_drag = clamp_finite(
+            drag,
+            MIN_QUADRATIC_DRAG,
+            MAX_QUADRATIC_DRAG,
+            DEFAULT_QUADRATIC_DRAG,
+        );
+    }
+
+    pub fn quadratic_drag(&self) -> f32 {
+        self.config.quadratic_drag
+    }
+
+    // Classic alignment averages neighbor velocities by default; with this
+    // on it averages their unit directions instead.
+    pub fn set_align_to_headings(&mut self, enabled: bool) {
+        self.config.align_to_headings = enabled;
+    }
+
+    pub fn align_to_headings(&self) -> bool {
+        self.config.align_to_headings
+    }
+
+    // Classic cohesion steers to the plain mean offset by default; with this
+    // on each neighbor counts with weight 1 - d / neighbor_radius.
+    pub fn set_distance_weighted_cohesion(&mut self, enabled: bool) {
+        self.config.distance_weighted_cohesion = enabled;
+    }
+
+    pub fn distance_weighted_cohesion(&self) -> bool {
+        self.config.distance_weighted_cohesion
+    }
+
+    pub fn set_axis_drag_enabled(&mut self, enabled: bool) {
+        self.config.axis_drag_enabled = enabled;
+    }
+
+    pub fn axis_drag_enabled(&self) -> bool {
+        self.config.axis_drag_enabled
+    }
+
+    pub fn set_z_drag(&mut self, drag: f32) {
+        self.config.z_drag = clamp_finite(drag, MIN_DRAG, MAX_DRAG, DEFAULT_DRAG);
+    }
+
+    pub fn z_drag(&self) -> f32 {
+        self.config.z_drag
+    }
+
+    pub fn set_z_quadratic_drag(&mut self, drag: f32) {
+        self.config.z_quadratic_drag = clamp_finite(
+            drag,
+            MIN_QUADRATIC_DRAG,
+            MAX_QUADRATIC_DRAG,
+            DEFAULT_QUADRATIC_DRAG,
+        );
+    }
+
+    pub fn z_quadratic_drag(&self) -> f32 {
+        self.config.z_quadratic_drag
+    }
+
+    // Applied to classic velocities after steering, outside the max_force clamp.
+    pub fn set_global_acceleration(&mut self, ax: f32, ay: f32, az: f32) {
+        self.config.global_accel_x = ax;
+        self.config.global_accel_y = ay;
+        self.config.global_accel_z = az;
+        self.config.sanitize();
+    }
+
+    pub fn global_acceleration_x(&self) -> f32 {
+        self.config.global_accel_x
+    }
+
+    pub fn global_acceleration_y(&self) -> f32 {
+        self.config.global_accel_y
+    }
+
+    pub fn global_acceleration_z(&self) -> f32 {
+        self.config.global_accel_z
+    }
+
     pub fn set_shape_attractor_weight(&mut self, weight: f32) {
         self.config.shape_attractor_weight = clamp_finite(
             weight,
@@ -592,6 +1551,22 @@ impl Sim {
         self.config.shape_attractor_weight
     }
 
+    // Opening threshold (cell size / distance) below which distant cells feed
+    // alignment and cohesion as aggregates; 0 disables the approximation.
+    pub fn set_far_field_opening(&mut self, opening: f32) {
+        self.config.far_field_opening = clamp_finite(
+            opening,
+            MIN_FAR_FIELD_OPENING,
+            MAX_FAR_FIELD_OPENING,
+            DEFAULT_FAR_FIELD_OPENING,
+        );
+        self.sync_neighbor_cell_size();
+    }
+
+    pub fn far_field_opening(&self) -> f32 {
+        self.config.far_field_opening
+    }
+
     pub fn set_shape_points_xyz(&mut self, points_xyz: &[f32]) {
         self.shape_points_xyz.clear();
 
@@ -617,42 +1592,134 @@ impl Sim {
     }
 
     pub fn step(&mut self, dt: f32) {
-        let dt = dt.clamp(DT_MIN, DT_MAX);
-        if dt <= 0.0 || self.active_count == 0 {
-            self.neighbors_visited_last_step = 0;
+        if self.paused {
+            // Paused sims still publish edits made through setters or hooks.
+            self.sync_render_buffers();
             return;
         }
 
-        match self.model_kind {
-            ModelKind::Classic => {
-                self.step_classic(dt);
-                return;
-            }
-            ModelKind::Flock2Social => {
-                self.step_flock2(dt, false);
-                return;
-            }
-            ModelKind::Flock2SocialFlight => {
-                self.step_flock2(dt, true);
-                return;
-            }
-            ModelKind::Flock2LiteSocial => {
-                self.step_flock2_lite(dt, false);
-                return;
-            }
-            ModelKind::Flock2LiteSocialFlight => {
-                self.step_flock2_lite(dt, true);
-                return;
-            }
-        }
+        self.advance(dt);
     }
 
-    pub fn set_bounds(&mut self, width: f32, height: f32) {
-        self.width = width.max(MIN_BOUND);
-        self.height = height.max(MIN_BOUND);
+    pub fn pause(&mut self) {
+        self.paused = true;
     }
 
-    pub fn set_active_count(&mut self, active_count: usize) {
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    // Advances exactly one frame using the most recent non-zero dt, whether or
+    // not the sim is paused.
+    pub fn debug_step(&mut self) {
+        self.advance(self.last_dt);
+    }
+
+    // Runs `steps` frames of `dt` regardless of pause, exactly as `step`
+    // would move the boids, but without syncing render buffers, validating
+    // state or feeding snapshots, metric history and objectives. The render
+    // buffers are published once at the end with unsmoothed headings.
+    // Recording stops and snapshots are cleared since neither covers the
+    // skipped frames.
+    pub fn warmup(&mut self, steps: u32, dt: f32) {
+        self.stop_recording();
+        self.clear_snapshots();
+        self.warming_up = true;
+        for _ in 0..steps {
+            self.advance_frame(dt);
+        }
+        self.warming_up = false;
+        for i in 0..self.active_count {
+            let heading = self.raw_render_heading(i);
+            self.heading_smoothing.reset(i, heading);
+        }
+        self.sync_render_buffers();
+        self.debug_validate_state();
+    }
+
+    pub fn set_time_scale(&mut self, scale: f32) {
+        self.time_scale = clamp_finite(scale, MIN_TIME_SCALE, MAX_TIME_SCALE, 1.0);
+    }
+
+    pub fn time_scale(&self) -> f32 {
+        self.time_scale
+    }
+
+    pub fn set_bounds(&mut self, width: f32, height: f32) {
+        self.width = width.max(MIN_BOUND);
+        self.height = height.max(MIN_BOUND);
+    }
+
+    // Grows capacity to `max_count`, drawing new boids from the construction
+    // RNG so `new(n)` followed by `reserve(m)` matches `new(m)`. All exported
+    // buffers move, so JS views must be recreated. Recording stops and the
+    // snapshot history is cleared because both assume a fixed capacity.
+    pub fn reserve(&mut self, max_count: usize) {
+        if max_count <= self.count {
+            return;
+        }
+
+        for buffer in [
+            &mut self.pos_x,
+            &mut self.pos_y,
+            &mut self.pos_z,
+            &mut self.vel_x,
+            &mut self.vel_y,
+            &mut self.vel_z,
+            &mut self.heading_x,
+            &mut self.heading_y,
+            &mut self.heading_z,
+            &mut self.accel_x,
+            &mut self.accel_y,
+            &mut self.accel_z,
+            &mut self.render_crowding,
+            &mut self.custom_force_x,
+            &mut self.custom_force_y,
+            &mut self.custom_force_z,
+            &mut self.altitude_integral,
+            &mut self.evasion_timer,
+        ] {
+            buffer.resize(max_count, 0.0);
+        }
+        self.evasion_xyz.resize(max_count * 3, 0.0);
+        self.render_z.resize(max_count, DEFAULT_Z_LAYER);
+        self.render_scale.resize(max_count, 1.0);
+        self.boid_size.resize(max_count, 1.0);
+        self.external_scalar.resize(max_count, 1.0);
+        self.behavior.resize(max_count);
+        self.render_xy.resize(max_count * 2, 0.0);
+        self.render_heading_xy.resize(max_count * 2, 0.0);
+        self.heading_smoothing.resize(max_count);
+        self.boundary_hit_flags.resize(max_count, 0);
+        self.lod_tiers.resize(max_count, lod::LOD_FULL);
+        self.species.resize(max_count, 0);
+        self.boid_ids.extend(self.count as u32..max_count as u32);
+        self.boid_slots.extend(self.count as u32..max_count as u32);
+        self.boid_generations.resize(max_count, 0);
+        self.reaction_scale.resize(max_count, 1.0);
+        self.refill_reaction_scales(self.count);
+        self.variation.resize(max_count, 0.0);
+        self.refill_variation(self.count);
+        self.role.resize(max_count, 0);
+        self.refill_roles(self.count);
+        self.clusters.resize(max_count);
+        self.steering_debug.resize(max_count);
+        self.camera.resize(max_count);
+        self.color_map.resize(max_count);
+
+        for i in self.count..max_count {
+            self.spawn_boid(i);
+        }
+        self.count = max_count;
+        self.stop_recording();
+        self.clear_snapshots();
+    }
+
+    pub fn set_active_count(&mut self, active_count: usize) {
         self.active_count = active_count.min(self.count);
     }
 
@@ -687,10 +1754,34 @@ impl Sim {
     pub fn render_heading_xy_len(&self) -> usize {
         self.render_heading_xy.len()
     }
+
+    // Separation-radius neighbors per boid divided by the cap and clamped to 1.
+    // Filled by the classic and fish models; others leave it at zero.
+    pub fn render_crowding_ptr(&self) -> *const f32 {
+        self.render_crowding.as_ptr()
+    }
+
+    pub fn render_crowding_len(&self) -> usize {
+        self.render_crowding.len()
+    }
+
+    pub fn set_crowding_cap(&mut self, cap: f32) {
+        self.crowding_cap = clamp_finite(
+            cap,
+            MIN_CROWDING_CAP,
+            MAX_CROWDING_CAP,
+            DEFAULT_CROWDING_CAP,
+        );
+    }
+
+    pub fn crowding_cap(&self) -> f32 {
+        self.crowding_cap
+    }
 }
 
 impl Sim {
-    fn shape_attractor_direction(&self, i: usize) -> Option<(f32, f32, f32)> {
+    // Direction to the nearest shape point and the distance to it.
+    fn shape_attractor_direction(&self, i: usize) -> Option<(f32, f32, f32, f32)> {
         if self.config.shape_attractor_weight <= EPSILON || self.shape_points_xyz.len() < 3 {
             return None;
         }
@@ -715,7 +1806,7 @@ impl Sim {
             let dx = axis_delta(point[0] - px, wrap_x);
             let dy = axis_delta(point[1] - py, wrap_y);
             let dz = if self.z_mode_enabled {
-                axis_delta(point[2] - pz, wrap_z)
+                self.z_extent.delta(point[2] - pz, wrap_z)
             } else {
                 0.0
             };
@@ -740,14 +1831,14 @@ impl Sim {
             0.0,
             0.0,
         );
-        Some((nx, ny, nz))
+        Some((nx, ny, nz, best_dist_sq.sqrt()))
     }
 
     fn shape_attractor_force(&self, i: usize) -> (f32, f32, f32) {
-        let Some((nx, ny, nz)) = self.shape_attractor_direction(i) else {
+        let Some((nx, ny, nz, distance)) = self.shape_attractor_direction(i) else {
             return (0.0, 0.0, 0.0);
         };
-        let force = self.config.shape_attractor_weight;
+        let force = self.config.shape_attractor_weight * self.attractor_falloff.scale(distance);
         (
             nx * force,
             ny * force,
@@ -756,7 +1847,13 @@ impl Sim {
     }
 
     fn resolve_hard_min_distance_constraints(&mut self) {
-        let hard_min_distance = self.config.hard_min_distance;
+        self.resolve_hard_min_distance_pass(HARD_CONSTRAINT_RELAXATION, HARD_CONSTRAINT_MAX_PUSH);
+    }
+
+    // One pass moving each overlapping pair apart by `relaxation` of its
+    // penetration, at most `max_push` per boid.
+    fn resolve_hard_min_distance_pass(&mut self, relaxation: f32, max_push: f32) {
+        let hard_min_distance = self.max_hard_min_distance();
         if hard_min_distance <= EPSILON || self.active_count < 2 {
             return;
         }
@@ -764,126 +1861,490 @@ impl Sim {
         let wrap_x = !self.bounce_x;
         let wrap_y = !self.bounce_y;
         let wrap_z = !self.bounce_z;
-        let min_distance_sq = hard_min_distance * hard_min_distance;
-
-        self.neighbor_grid.set_cell_size(hard_min_distance);
-        self.neighbor_grid.rebuild(
-            &self.pos_x[..self.active_count],
-            &self.pos_y[..self.active_count],
-            WORLD_SIZE,
-            WORLD_SIZE,
-        );
+        let per_pair = self.has_species_hard_min();
+        let radii = self.rebuild_constraint_grid(hard_min_distance);
 
+        let z_bin_count = self.rebuild_constraint_z_bins(hard_min_distance);
         let mut neighbors = Vec::new();
         for i in 0..self.active_count {
+            let radius = radii[self.species[i] as usize];
+            if radius <= EPSILON {
+                continue;
+            }
             neighbors.clear();
-            self.neighbor_grid.for_each_neighbor_with_wrap(
-                i,
-                hard_min_distance,
-                wrap_x,
-                wrap_y,
-                |j| {
-                    if j > i && !neighbors.contains(&j) {
+            let z_bins = &self.constraint_z_bins;
+            self.constraint_grid
+                .for_each_neighbor_with_wrap(i, radius, wrap_x, wrap_y, |j| {
+                    if j > i
+                        && z_bins_adjacent(z_bins, z_bin_count, wrap_z, i, j)
+                        && !neighbors.contains(&j)
+                    {
                         neighbors.push(j);
                     }
                     true
-                },
-            );
+                });
 
             for &j in &neighbors {
+                if self.split_separates(i, j) {
+                    continue;
+                }
                 let dx = axis_delta(self.pos_x[j] - self.pos_x[i], wrap_x);
                 let dy = axis_delta(self.pos_y[j] - self.pos_y[i], wrap_y);
                 let dz = if self.z_mode_enabled {
-                    axis_delta(self.pos_z[j] - self.pos_z[i], wrap_z)
+                    self.z_extent.delta(self.pos_z[j] - self.pos_z[i], wrap_z)
                 } else {
                     0.0
                 };
                 let dist_sq = math::distance_sq_3d(dx, dy, dz);
-                if dist_sq >= min_distance_sq {
+                let pair_min_distance = if per_pair {
+                    self.pair_hard_min_distance(i, j)
+                } else {
+                    hard_min_distance
+                };
+                if dist_sq >= pair_min_distance * pair_min_distance {
                     continue;
                 }
 
+                let constraints = self.math(MathPath::Constraints);
                 let (nx, ny, nz, dist) = if dist_sq > EPSILON {
-                    let dist = dist_sq.sqrt();
-                    (
-                        dx / dist,
-                        dy / dist,
-                        if self.z_mode_enabled { dz / dist } else { 0.0 },
-                        dist,
-                    )
+                    let (nx, ny, nz) =
+                        math::normalize_or_default(constraints, dx, dy, dz, 1.0, 0.0, 0.0);
+                    (nx, ny, nz, math::length_from_sq(constraints, dist_sq))
                 } else {
-                    let mut nx = hash_unit(self.step_index, i as u32, 0);
-                    let mut ny = hash_unit(self.step_index, j as u32, 1);
-                    let mut nz = if self.z_mode_enabled {
-                        hash_unit(self.step_index, (i ^ j) as u32, 2)
-                    } else {
-                        0.0
-                    };
-                    let len_sq = nx * nx + ny * ny + nz * nz;
-                    if len_sq > EPSILON {
-                        let inv_len = 1.0 / len_sq.sqrt();
-                        nx *= inv_len;
-                        ny *= inv_len;
-                        nz *= inv_len;
-                    } else {
-                        nx = 1.0;
-                        ny = 0.0;
-                        nz = 0.0;
-                    }
+                    let (nx, ny, nz) = math::normalize_or_default(
+                        constraints,
+                        hash_unit(self.step_index, i as u32, 0),
+                        hash_unit(self.step_index, j as u32, 1),
+                        if self.z_mode_enabled {
+                            hash_unit(self.step_index, (i ^ j) as u32, 2)
+                        } else {
+                            0.0
+                        },
+                        1.0,
+                        0.0,
+                        0.0,
+                    );
                     (nx, ny, nz, 0.0)
                 };
 
-                let push = ((hard_min_distance - dist) * 0.5 * HARD_CONSTRAINT_RELAXATION)
-                    .min(HARD_CONSTRAINT_MAX_PUSH);
+                let penetration = pair_min_distance - dist;
+                self.record_contact(i, j, penetration);
+
+                let push = (penetration * 0.5 * relaxation).min(max_push);
                 if push <= 0.0 {
                     continue;
                 }
 
-                self.pos_x[i] = project_axis_position(self.pos_x[i] - nx * push, self.bounce_x);
-                self.pos_y[i] = project_axis_position(self.pos_y[i] - ny * push, self.bounce_y);
-                self.pos_x[j] = project_axis_position(self.pos_x[j] + nx * push, self.bounce_x);
-                self.pos_y[j] = project_axis_position(self.pos_y[j] + ny * push, self.bounce_y);
+                let half = self.split_half(i);
+                self.pos_x[i] = self.project_x(self.pos_x[i] - nx * push, half);
+                let (mirror_y, bounce_y) = (self.mirror.y, self.bounce_y);
+                self.pos_y[i] =
+                    self.project_mirror_axis(self.pos_y[i] - ny * push, mirror_y, bounce_y);
+                self.pos_x[j] = self.project_x(self.pos_x[j] + nx * push, half);
+                self.pos_y[j] =
+                    self.project_mirror_axis(self.pos_y[j] + ny * push, mirror_y, bounce_y);
 
                 if self.z_mode_enabled {
-                    self.pos_z[i] = project_axis_position(self.pos_z[i] - nz * push, self.bounce_z);
-                    self.pos_z[j] = project_axis_position(self.pos_z[j] + nz * push, self.bounce_z);
+                    let z_extent = self.z_extent;
+                    self.pos_z[i] = z_extent.project(self.pos_z[i] - nz * push, self.bounce_z);
+                    self.pos_z[j] = z_extent.project(self.pos_z[j] + nz * push, self.bounce_z);
+                }
+            }
+        }
+
+        if self.overlap_metrics_enabled {
+            self.measure_remaining_overlaps();
+        }
+    }
+
+    // The constraint pass keeps its own grid so it never disturbs the steering
+    // grid's cell size. Its levels span the smallest to the largest species
+    // radius, so each boid scans cells sized to the distances it keeps.
+    // Returns the search radius per species.
+    fn rebuild_constraint_grid(&mut self, max_distance: f32) -> [f32; MAX_SPECIES] {
+        let radii = if self.has_species_hard_min() {
+            self.species_hard_min_radii()
+        } else {
+            [max_distance; MAX_SPECIES]
+        };
+        let min_distance = radii
+            .iter()
+            .copied()
+            .filter(|&radius| radius > EPSILON)
+            .fold(max_distance, f32::min);
+        self.constraint_grid.rebuild(
+            &self.pos_x[..self.active_count],
+            &self.pos_y[..self.active_count],
+            WORLD_SIZE,
+            WORLD_SIZE,
+            min_distance,
+            max_distance,
+        );
+        radii
+    }
+
+    // Slabs are at least `min_distance` deep, so pairs closer than that sit
+    // in the same or adjacent slabs. Returns the slab count, or 0 when there
+    // are too few slabs for the filter to skip anything.
+    fn rebuild_constraint_z_bins(&mut self, min_distance: f32) -> u32 {
+        let bin_count = if self.z_mode_enabled {
+            (self.z_extent.depth() / min_distance).floor() as u32
+        } else {
+            0
+        };
+        if bin_count < 3 {
+            return 0;
+        }
+        let z_extent = self.z_extent;
+        self.constraint_z_bins.clear();
+        self.constraint_z_bins.extend(
+            self.pos_z[..self.active_count]
+                .iter()
+                .map(|&z| ((z_extent.fraction(z) * bin_count as f32) as u32).min(bin_count - 1)),
+        );
+        bin_count
+    }
+
+    // Re-checks every pair against the resolved positions. This needs a second
+    // grid build, which is why it is opt-in.
+    fn measure_remaining_overlaps(&mut self) {
+        let hard_min_distance = self.max_hard_min_distance();
+        let wrap_x = !self.bounce_x;
+        let wrap_y = !self.bounce_y;
+        let wrap_z = !self.bounce_z;
+        let radii = self.rebuild_constraint_grid(hard_min_distance);
+
+        let mut overlaps = 0;
+        let mut max_penetration = 0.0_f32;
+        let z_bin_count = self.rebuild_constraint_z_bins(hard_min_distance);
+        let mut neighbors = Vec::new();
+        for i in 0..self.active_count {
+            let radius = radii[self.species[i] as usize];
+            if radius <= EPSILON {
+                continue;
+            }
+            neighbors.clear();
+            let z_bins = &self.constraint_z_bins;
+            self.constraint_grid
+                .for_each_neighbor_with_wrap(i, radius, wrap_x, wrap_y, |j| {
+                    if j > i
+                        && z_bins_adjacent(z_bins, z_bin_count, wrap_z, i, j)
+                        && !neighbors.contains(&j)
+                    {
+                        neighbors.push(j);
+                    }
+                    true
+                });
+
+            for &j in &neighbors {
+                if self.split_separates(i, j) {
+                    continue;
+                }
+                let dx = axis_delta(self.pos_x[j] - self.pos_x[i], wrap_x);
+                let dy = axis_delta(self.pos_y[j] - self.pos_y[i], wrap_y);
+                let dz = if self.z_mode_enabled {
+                    self.z_extent.delta(self.pos_z[j] - self.pos_z[i], wrap_z)
+                } else {
+                    0.0
+                };
+                let dist = math::length_from_sq(
+                    self.math(MathPath::Constraints),
+                    math::distance_sq_3d(dx, dy, dz),
+                );
+                let pair_min_distance = self.pair_hard_min_distance(i, j);
+                if dist < pair_min_distance {
+                    overlaps += 1;
+                    max_penetration = max_penetration.max(pair_min_distance - dist);
+                }
+            }
+        }
+
+        self.step_events.remaining_overlaps = overlaps;
+        self.step_events.max_remaining_penetration = max_penetration;
+    }
+
+    fn advance(&mut self, dt: f32) {
+        let recording = self.is_recording_active();
+        if recording {
+            self.record_before_advance(dt);
+        }
+        self.advance_frame(dt);
+        if recording {
+            self.record_after_advance();
+        }
+        if self.snapshots.enabled() {
+            self.capture_snapshot();
+        }
+        if self.metric_history.enabled() {
+            self.sample_metric_history();
+        }
+        self.run_objective();
+    }
+
+    fn advance_frame(&mut self, dt: f32) {
+        self.begin_step_events();
+        let dt = dt.clamp(DT_MIN, DT_MAX);
+        if dt <= 0.0 || self.active_count == 0 {
+            self.neighbors_visited_last_step = 0;
+            return;
+        }
+        self.last_dt = dt;
+        self.step_events.simulated_dt = dt * self.time_scale;
+
+        // Fast-forward splits the scaled time into substeps no longer than the
+        // caller's dt so integration quality matches real-time playback.
+        let substeps = self.time_scale.ceil().max(1.0);
+        let sub_dt = dt * self.time_scale / substeps;
+        for _ in 0..substeps as u32 {
+            self.heading_smoothing.advance(dt / substeps);
+            self.burst_coast.advance(sub_dt);
+            self.turn_noise.advance(sub_dt);
+            self.step_model(sub_dt);
+            self.move_obstacles(sub_dt);
+        }
+        self.count_danger_zone_occupancy();
+    }
+
+    fn step_model(&mut self, dt: f32) {
+        match self.model_kind {
+            ModelKind::Classic | ModelKind::FishSchool => self.step_classic(dt),
+            ModelKind::Flock2Social => self.step_flock2(dt, false),
+            ModelKind::Flock2SocialFlight => self.step_flock2(dt, true),
+            ModelKind::Flock2LiteSocial => self.step_flock2_lite(dt, false),
+            ModelKind::Flock2LiteSocialFlight => self.step_flock2_lite(dt, true),
+        }
+    }
+
+    // Integrates one boid's position with the given velocity, applying wrap or
+    // bounce per axis, and returns the (possibly reflected) velocity.
+    fn integrate_boid(&mut self, i: usize, vx: f32, vy: f32, vz: f32, dt: f32) -> (f32, f32, f32) {
+        self.integrate_boid_in_wind(i, vx, vy, vz, (0.0, 0.0, 0.0), dt)
+    }
+
+    // Positions move with the ground velocity (boid velocity plus wind), but
+    // only the boid's own velocity is returned, flipped wherever a wall
+    // reflected the ground velocity.
+    fn integrate_boid_in_wind(
+        &mut self,
+        i: usize,
+        vx: f32,
+        vy: f32,
+        vz: f32,
+        wind: (f32, f32, f32),
+        dt: f32,
+    ) -> (f32, f32, f32) {
+        let restitution = self.wall_restitution;
+        let (bounce_x, world_x) = match self.split_half(i) {
+            Some(half) => (true, half.range()),
+            None => self.mirror_axis_bounds(self.mirror.x, self.bounce_x),
+        };
+        let (bounce_y, world_y) = self.mirror_axis_bounds(self.mirror.y, self.bounce_y);
+        let (x, ground_x, hit_x) = integrate_axis(
+            self.pos_x[i],
+            vx + wind.0,
+            dt,
+            bounce_x,
+            restitution,
+            world_x,
+        );
+        let (y, ground_y, hit_y) = integrate_axis(
+            self.pos_y[i],
+            vy + wind.1,
+            dt,
+            bounce_y,
+            restitution,
+            world_y,
+        );
+        let (z, mut next_vz, hit_z) = if self.z_mode_enabled {
+            let slab = (self.z_extent.min, self.z_extent.max);
+            let (z, ground_z, hit_z) = integrate_axis(
+                self.pos_z[i],
+                vz + wind.2,
+                dt,
+                self.bounce_z,
+                restitution,
+                slab,
+            );
+            (z, reflect_like(vz, vz + wind.2, ground_z), hit_z)
+        } else {
+            (DEFAULT_Z_LAYER, 0.0, false)
+        };
+        let mut next_vx = reflect_like(vx, vx + wind.0, ground_x);
+        let mut next_vy = reflect_like(vy, vy + wind.1, ground_y);
+
+        // Friction acts along the wall, on the axes that did not hit it.
+        let contact = [hit_x && bounce_x, hit_y && bounce_y, hit_z && self.bounce_z];
+        if self.wall_friction > 0.0 && contact.contains(&true) {
+            let keep = 1.0 - self.wall_friction;
+            for (velocity, touching) in [&mut next_vx, &mut next_vy, &mut next_vz]
+                .into_iter()
+                .zip(contact)
+            {
+                if !touching {
+                    *velocity *= keep;
                 }
             }
         }
+
+        self.pos_x[i] = x;
+        self.pos_y[i] = y;
+        self.pos_z[i] = z;
+        let next_vz = self.clamp_to_terrain(i, next_vz);
+        self.record_boundary_hits(i, hit_x, hit_y, hit_z);
+        let dvz = if self.z_mode_enabled {
+            next_vz - vz
+        } else {
+            0.0
+        };
+        self.record_boundary_correction(next_vx - vx, next_vy - vy, dvz);
+        let (next_vx, next_vy, next_vz) = self.keep_out_of_exclusion(i, next_vx, next_vy, next_vz);
+        let (next_vx, next_vy) = self.confine_to_corridors(i, next_vx, next_vy);
+        let (next_vx, next_vy) = self.wrap_into_wedge(i, next_vx, next_vy);
+        (next_vx, next_vy, next_vz)
+    }
+
+    fn spawn_boid(&mut self, i: usize) {
+        let (min_speed, max_speed) = if self.model_kind.uses_flock2_units() {
+            (self.flock2_config.min_speed, self.flock2_config.max_speed)
+        } else {
+            (self.config.min_speed, self.config.max_speed)
+        };
+        let rng = &mut self.rng;
+        let layout = self.spawn_layout;
+        self.pos_x[i] = layout.place(rng.next_f32(), 0.0, WORLD_SIZE);
+        self.pos_y[i] = layout.place(rng.next_f32(), 0.0, WORLD_SIZE);
+        self.pos_z[i] = layout.place(rng.next_f32(), self.z_extent.min, self.z_extent.depth());
+
+        let angle = rng.next_f32() * TAU;
+        let speed = min_speed + (max_speed - min_speed) * rng.next_f32();
+        self.vel_x[i] = angle.cos() * speed;
+        self.vel_y[i] = angle.sin() * speed;
+        self.vel_z[i] = (rng.next_f32() * 2.0 - 1.0) * speed * 0.35;
+        let (hx, hy, hz) =
+            normalize_or_default(self.vel_x[i], self.vel_y[i], self.vel_z[i], 1.0, 0.0, 0.0);
+        self.heading_x[i] = hx;
+        self.heading_y[i] = hy;
+        self.heading_z[i] = hz;
+        self.settle_boid(i);
+    }
+
+    // Applies the mirror, corridors and wedge to a boid placed from outside
+    // the integrator and refreshes its render slot.
+    fn settle_boid(&mut self, i: usize) {
+        self.fold_into_mirror(i);
+        (self.vel_x[i], self.vel_y[i]) = self.confine_to_corridors(i, self.vel_x[i], self.vel_y[i]);
+        (self.vel_x[i], self.vel_y[i]) = self.wrap_into_wedge(i, self.vel_x[i], self.vel_y[i]);
+        let (hx, hy) = (self.heading_x[i], self.heading_y[i]);
+
+        let base = 2 * i;
+        self.render_xy[base] = self.pos_x[i];
+        self.render_xy[base + 1] = self.pos_y[i];
+        self.render_z[i] = if self.z_mode_enabled {
+            self.pos_z[i]
+        } else {
+            DEFAULT_Z_LAYER
+        };
+        self.render_heading_xy[base] = hx;
+        self.render_heading_xy[base + 1] = hy;
+        self.heading_smoothing.reset(i, (hx, hy));
+        self.sync_render_scale(i);
+        self.view_grid.mark_stale();
+    }
+
+    // Spawns into a slot that already held a boid, clearing what the old one
+    // carried between steps.
+    fn respawn_boid(&mut self, i: usize) {
+        self.spawn_boid(i);
+        self.accel_x[i] = 0.0;
+        self.accel_y[i] = 0.0;
+        self.accel_z[i] = 0.0;
+        self.altitude_integral[i] = 0.0;
+        self.evasion_timer[i] = 0.0;
+        self.evasion_xyz[3 * i..3 * i + 3].fill(0.0);
+        self.neighbor_memory.forget(i);
+        self.familiarity.forget(i);
+        self.accel_ramp.forget(i);
+        self.behavior.states[i] = BehaviorState::Flock.as_u32() as u8;
+        if !self.z_mode_enabled {
+            self.pos_z[i] = DEFAULT_Z_LAYER;
+            self.vel_z[i] = 0.0;
+        }
+    }
+
+    fn math(&self, path: MathPath) -> Math<'_> {
+        let divergence = &self.math_divergence;
+        Math::new(
+            self.config.math_modes[path.index()],
+            divergence.enabled.then(|| &divergence.max[path.index()]),
+        )
     }
 
     fn sync_render_buffers(&mut self) {
+        if self.warming_up {
+            return;
+        }
+        let smoothing = self.heading_smoothing.enabled();
+        let blend = if smoothing {
+            self.heading_smoothing.take_blend()
+        } else {
+            0.0
+        };
         for i in 0..self.active_count {
             let base = 2 * i;
             self.render_xy[base] = self.pos_x[i];
             self.render_xy[base + 1] = self.pos_y[i];
             self.render_z[i] = self.pos_z[i];
-            let vx = self.vel_x[i];
-            let vy = self.vel_y[i];
-            let vel_len_sq = vx * vx + vy * vy;
-            if vel_len_sq > EPSILON {
-                let inv_len = vel_len_sq.sqrt().recip();
-                self.render_heading_xy[base] = vx * inv_len;
-                self.render_heading_xy[base + 1] = vy * inv_len;
-                continue;
+            self.sync_render_scale(i);
+            if let Some((sx, sy, _)) =
+                self.projection
+                    .project(self.pos_x[i], self.pos_y[i], self.render_z[i])
+            {
+                self.render_xy[base] = sx;
+                self.render_xy[base + 1] = sy;
             }
+            let raw = self.raw_render_heading(i);
+            let (hx, hy) = if smoothing {
+                self.heading_smoothing.smooth(i, raw, blend)
+            } else {
+                raw
+            };
+            self.render_heading_xy[base] = hx;
+            self.render_heading_xy[base + 1] = hy;
+        }
+        self.sync_camera_outputs();
+        self.sync_colors();
+        self.sync_mirror();
+        self.sync_kaleidoscope();
+        self.sync_wrap_ghosts();
+        self.sync_species_partition();
+        self.view_grid.mark_stale();
+    }
 
-            let hx = self.heading_x[i];
-            let hy = self.heading_y[i];
-            let heading_len_sq = hx * hx + hy * hy;
-            if heading_len_sq > EPSILON {
-                let inv_len = heading_len_sq.sqrt().recip();
-                self.render_heading_xy[base] = hx * inv_len;
-                self.render_heading_xy[base + 1] = hy * inv_len;
-                continue;
-            }
+    // Unit xy direction of travel, falling back to the heading, then +x.
+    fn raw_render_heading(&self, i: usize) -> (f32, f32) {
+        let vx = self.vel_x[i];
+        let vy = self.vel_y[i];
+        let vel_len_sq = vx * vx + vy * vy;
+        if vel_len_sq > EPSILON {
+            let inv_len = vel_len_sq.sqrt().recip();
+            return (vx * inv_len, vy * inv_len);
+        }
 
-            self.render_heading_xy[base] = 1.0;
-            self.render_heading_xy[base + 1] = 0.0;
+        let hx = self.heading_x[i];
+        let hy = self.heading_y[i];
+        let heading_len_sq = hx * hx + hy * hy;
+        if heading_len_sq > EPSILON {
+            let inv_len = heading_len_sq.sqrt().recip();
+            return (hx * inv_len, hy * inv_len);
         }
+
+        (1.0, 0.0)
     }
 
     fn debug_validate_state(&self) {
+        if self.warming_up {
+            return;
+        }
         #[cfg(debug_assertions)]
         for i in 0..self.count {
             debug_assert!(self.pos_x[i].is_finite());
@@ -900,7 +2361,10 @@ impl Sim {
             debug_assert!(self.heading_z[i].is_finite());
             debug_assert!((0.0..=1.0).contains(&self.pos_x[i]));
             debug_assert!((0.0..=1.0).contains(&self.pos_y[i]));
-            debug_assert!((0.0..=1.0).contains(&self.pos_z[i]));
+            debug_assert!(
+                !self.z_mode_enabled
+                    || (self.z_extent.min..=self.z_extent.max).contains(&self.pos_z[i])
+            );
             debug_assert!(self.render_z[i].is_finite());
         }
     }
@@ -924,6 +2388,14 @@ fn shortest_wrapped_delta(delta: f32) -> f32 {
     }
 }
 
+fn z_bins_adjacent(bins: &[u32], bin_count: u32, wrap: bool, i: usize, j: usize) -> bool {
+    if bin_count == 0 {
+        return true;
+    }
+    let diff = bins[i].abs_diff(bins[j]);
+    diff <= 1 || (wrap && bin_count - diff <= 1)
+}
+
 fn project_axis_position(position: f32, bounce: bool) -> f32 {
     if bounce {
         position.clamp(0.0, WORLD_SIZE)
@@ -932,39 +2404,66 @@ fn project_axis_position(position: f32, bounce: bool) -> f32 {
     }
 }
 
-fn integrate_axis(position: f32, velocity: f32, dt: f32, bounce: bool) -> (f32, f32) {
-    if !bounce {
-        return ((position + velocity * dt).rem_euclid(WORLD_SIZE), velocity);
+// Applies whatever the walls did to the ground velocity (flips and
+// restitution losses) to the boid's own velocity.
+fn reflect_like(velocity: f32, ground_before: f32, ground_after: f32) -> f32 {
+    if ground_after == ground_before {
+        velocity
+    } else if ground_before.abs() > EPSILON {
+        velocity * (ground_after / ground_before)
+    } else {
+        -velocity
     }
+}
 
+// Moves along one axis within [lo, hi], wrapping or bouncing at its ends.
+fn integrate_axis(
+    position: f32,
+    velocity: f32,
+    dt: f32,
+    bounce: bool,
+    restitution: f32,
+    (lo, hi): (f32, f32),
+) -> (f32, f32, bool) {
     let mut next_position = position + velocity * dt;
+    if !bounce {
+        let wrapped = !(lo..hi).contains(&next_position);
+        return (
+            lo + (next_position - lo).rem_euclid(hi - lo),
+            velocity,
+            wrapped,
+        );
+    }
+
     let mut next_velocity = velocity;
+    let mut reflected = false;
 
     // Multiple reflections are unlikely with the current dt/speed caps, but this
     // guards against pathological inputs while keeping behavior deterministic.
     for _ in 0..4 {
-        if (0.0..=WORLD_SIZE).contains(&next_position) {
+        if (lo..=hi).contains(&next_position) {
             break;
         }
 
-        if next_position < 0.0 {
-            next_position = -next_position;
-            next_velocity = -next_velocity;
+        reflected = true;
+        if next_position < lo {
+            next_position = lo + (lo - next_position) * restitution;
+            next_velocity = -next_velocity * restitution;
             continue;
         }
 
-        if next_position > WORLD_SIZE {
-            next_position = WORLD_SIZE * 2.0 - next_position;
-            next_velocity = -next_velocity;
+        if next_position > hi {
+            next_position = hi - (next_position - hi) * restitution;
+            next_velocity = -next_velocity * restitution;
         }
     }
 
-    (next_position.clamp(0.0, WORLD_SIZE), next_velocity)
+    (next_position.clamp(lo, hi), next_velocity, reflected)
 }
 
 #[allow(clippy::too_many_arguments)]
 fn steer_towards_3d(
-    mode: MathMode,
+    mode: Math<'_>,
     desired_x: f32,
     desired_y: f32,
     desired_z: f32,
@@ -983,6 +2482,15 @@ fn steer_towards_3d(
     )
 }
 
+fn clamp_z_force_scale(scale: f32) -> f32 {
+    clamp_finite(
+        scale,
+        MIN_Z_FORCE_SCALE,
+        MAX_Z_FORCE_SCALE,
+        DEFAULT_Z_FORCE_SCALE,
+    )
+}
+
 fn clamp_finite(value: f32, min: f32, max: f32, fallback: f32) -> f32 {
     if !value.is_finite() {
         return fallback;
@@ -1015,7 +2523,16 @@ pub fn wasm_loaded_message() -> String {
 
 #[cfg(test)]
 mod tests {
-    use super::{shortest_wrapped_delta, Sim, DEFAULT_Z_LAYER, WORLD_SIZE};
+    use super::{
+        run_golden, shortest_wrapped_delta, CustomForce, CustomForceView, Objective,
+        ObjectiveMetrics, SeparationKernel, Sim, SimConfig, SoaViewMut, StepHook, StepStage,
+        DEFAULT_MAX_FORCE, DEFAULT_Z_LAYER, FAR_FIELD_CELL_FRACTION, MAX_STEERING_WEIGHT,
+        WORLD_SIZE,
+    };
+    use crate::flock2::Flock2Config;
+    use crate::steering_debug::STEERING_DEBUG_STRIDE;
+    use std::cell::Cell;
+    use std::rc::Rc;
 
     #[test]
     fn disabled_z_mode_keeps_particles_in_mid_layer() {
@@ -1063,6 +2580,27 @@ mod tests {
         assert!(sim.vel_x[0] > 0.0);
     }
 
+    #[test]
+    fn boundary_hits_are_counted_per_axis() {
+        let mut sim = Sim::new(1, 7, 1.0, 1.0);
+        sim.set_axis_bounce(true, false, false);
+        sim.set_boundary_hit_flags_enabled(true);
+        sim.pos_x[0] = 0.01;
+        sim.pos_y[0] = 0.99;
+        sim.vel_x[0] = -0.2;
+        sim.vel_y[0] = 0.2;
+
+        sim.step(0.1);
+
+        assert_eq!(sim.boundary_hits_x(), 1);
+        assert_eq!(sim.boundary_hits_y(), 1);
+        assert_eq!(sim.boundary_hits_z(), 0);
+        assert_eq!(
+            sim.boundary_hit_flags[0],
+            super::events::BOUNDARY_HIT_X | super::events::BOUNDARY_HIT_Y
+        );
+    }
+
     #[test]
     fn wrap_mode_keeps_velocity_sign() {
         let mut sim = Sim::new(1, 11, 1.0, 1.0);
@@ -1149,12 +2687,2937 @@ mod tests {
     }
 
     #[test]
-    fn soft_and_hard_min_distance_are_independent() {
-        let mut sim = Sim::new(2, 5, 1.0, 1.0);
-        sim.set_min_distance(0.12);
-        sim.set_hard_min_distance(0.34);
+    fn granular_setters_keep_dependent_fields_consistent() {
+        let mut sim = Sim::new(4, 3, 1.0, 1.0);
+        sim.set_separation_radius(0.05);
+        sim.set_neighbor_radius(0.02);
+        assert!((sim.neighbor_radius() - 0.02).abs() < 1.0e-6);
+        assert!(sim.separation_radius() <= sim.neighbor_radius());
+
+        sim.set_max_speed(0.3);
+        sim.set_min_speed(0.5);
+        assert!(sim.max_speed() >= sim.min_speed());
+
+        sim.set_sep_weight(f32::NAN);
+        assert!((sim.sep_weight() - super::DEFAULT_SEP_WEIGHT).abs() < 1.0e-6);
+    }
 
-        assert!((sim.min_distance() - 0.12).abs() < 1.0e-6);
-        assert!((sim.hard_min_distance() - 0.34).abs() < 1.0e-6);
+    #[test]
+    fn hard_constraint_reports_contacts() {
+        let mut sim = Sim::new(2, 123, 1.0, 1.0);
+        sim.set_max_force(0.0);
+        sim.set_hard_min_distance(0.2);
+        sim.pos_x[0] = 0.5;
+        sim.pos_y[0] = 0.5;
+        sim.pos_x[1] = 0.55;
+        sim.pos_y[1] = 0.5;
+        sim.vel_x.fill(0.0);
+        sim.vel_y.fill(0.0);
+
+        sim.step(0.016);
+
+        assert_eq!(sim.contact_count(), 1);
+        assert_eq!(sim.recorded_contact_count(), 1);
+        assert_eq!(&sim.contact_pairs[..2], &[0, 1]);
+        assert!((sim.contact_penetration_sum() - 0.15).abs() < 1.0e-3);
+    }
+
+    #[test]
+    fn overlap_metrics_report_pairs_left_after_resolve() {
+        let mut sim = Sim::new(2, 123, 1.0, 1.0);
+        sim.set_max_force(0.0);
+        sim.set_hard_min_distance(0.2);
+        sim.set_overlap_metrics_enabled(true);
+        sim.pos_x[0] = 0.5;
+        sim.pos_y[0] = 0.5;
+        sim.pos_x[1] = 0.55;
+        sim.pos_y[1] = 0.5;
+        sim.vel_x.fill(0.0);
+        sim.vel_y.fill(0.0);
+
+        sim.step(0.016);
+
+        let gap = (sim.pos_x[1] - sim.pos_x[0]).abs();
+        assert_eq!(sim.remaining_overlap_count(), 1);
+        assert!((sim.max_remaining_penetration() - (0.2 - gap)).abs() < 1.0e-4);
+        assert!(sim.max_remaining_penetration() < 0.15);
+    }
+
+    #[test]
+    fn step_hooks_can_rewrite_state() {
+        struct FreezeX;
+        impl StepHook for FreezeX {
+            fn on_stage(&mut self, stage: StepStage, state: SoaViewMut<'_>) {
+                assert_eq!(stage, StepStage::AfterConstraints);
+                state.pos_x.fill(0.25);
+            }
+        }
+
+        let mut sim = Sim::new(8, 21, 1.0, 1.0);
+        sim.set_step_hook(StepStage::AfterConstraints, Box::new(FreezeX));
+        sim.step(0.016);
+
+        for i in 0..sim.count() {
+            assert_eq!(sim.render_xy[2 * i], 0.25);
+        }
+    }
+
+    #[test]
+    fn step_hook_writes_are_sanitized() {
+        struct Corrupt(Rc<Cell<u32>>);
+        impl StepHook for Corrupt {
+            fn on_stage(&mut self, _stage: StepStage, state: SoaViewMut<'_>) {
+                self.0.set(self.0.get() + 1);
+                state.pos_x[0] = 4.0;
+                state.pos_y[0] = f32::NAN;
+                state.vel_x[0] = f32::INFINITY;
+                state.accel_y[0] = f32::NAN;
+            }
+        }
+
+        let calls = Rc::new(Cell::new(0));
+        let mut sim = Sim::new(2, 23, 1.0, 1.0);
+        sim.set_max_force(0.0);
+        sim.set_step_hook(StepStage::AfterForces, Box::new(Corrupt(calls.clone())));
+        let (y, vx) = (sim.pos_y[0], sim.vel_x[0]);
+        sim.step(1.0 / 60.0);
+
+        // Steering is off, and the hook still runs once per step.
+        assert_eq!(calls.get(), 1);
+        assert!(sim.pos_x[0] <= WORLD_SIZE);
+        assert!(sim.pos_y[0].is_finite() && (sim.pos_y[0] - y).abs() < 0.1);
+        assert!(sim.vel_x[0].is_finite() && (sim.vel_x[0] - vx).abs() < 0.1);
+        assert!(sim.accel_y[0].is_finite());
+    }
+
+    #[test]
+    fn custom_force_is_clamped_by_max_force() {
+        struct PushRight;
+        impl CustomForce for PushRight {
+            fn compute(&mut self, view: CustomForceView<'_>) {
+                view.out_x.fill(100.0);
+            }
+        }
+
+        let mut sim = Sim::new(1, 4, 1.0, 1.0);
+        sim.set_jitter_strength(0.0);
+        sim.set_shape_attractor_weight(0.0);
+        sim.set_custom_force(Some(Box::new(PushRight)));
+        sim.step(0.016);
+
+        assert!((sim.accel_x[0] - sim.max_force()).abs() < 1.0e-4);
+        assert!(sim.accel_y[0].abs() < 1.0e-4);
+    }
+
+    #[test]
+    fn quadratic_drag_slows_fast_boids_harder() {
+        let mut sim = Sim::new(2, 9, 1.0, 1.0);
+        sim.set_max_force(0.0);
+        sim.set_min_speed(0.0);
+        sim.set_max_speed(3.0);
+        sim.set_drag(0.5);
+        sim.set_quadratic_drag(20.0);
+        sim.set_drag_model(2);
+        sim.vel_x[0] = 0.05;
+        sim.vel_y[0] = 0.0;
+        sim.vel_x[1] = 1.0;
+        sim.vel_y[1] = 0.0;
+        sim.step(0.016);
+
+        let slow_ratio = sim.vel_x[0] / 0.05;
+        let fast_ratio = sim.vel_x[1] / 1.0;
+        assert!(fast_ratio > 0.0 && fast_ratio < slow_ratio);
+
+        // Linear drag ignores speed and only uses the linear coefficient.
+        sim.set_drag_model(1);
+        sim.vel_x[0] = 0.05;
+        sim.vel_x[1] = 1.0;
+        sim.step(0.016);
+        assert!((sim.vel_x[0] / 0.05 - sim.vel_x[1]).abs() < 1.0e-5);
+    }
+
+    #[test]
+    fn axis_drag_damps_depth_separately() {
+        let mut sim = Sim::new(1, 12, 1.0, 1.0);
+        sim.set_z_mode(true);
+        sim.set_max_force(0.0);
+        sim.set_min_speed(0.0);
+        sim.set_drag(0.5);
+        sim.set_z_drag(4.0);
+        sim.vel_x[0] = 0.1;
+        sim.vel_y[0] = 0.0;
+        sim.vel_z[0] = 0.1;
+
+        // Disabled axis drag keeps the z axis on the horizontal coefficient.
+        sim.step(0.016);
+        assert!((sim.vel_x[0] - sim.vel_z[0]).abs() < 1.0e-6);
+
+        sim.set_axis_drag_enabled(true);
+        sim.step(0.016);
+        assert!(sim.vel_z[0] < sim.vel_x[0]);
+        assert!(sim.vel_z[0] > 0.0);
+    }
+
+    #[test]
+    fn global_acceleration_bypasses_max_force() {
+        let mut sim = Sim::new(1, 3, 1.0, 1.0);
+        sim.set_max_force(0.0);
+        sim.set_min_speed(0.0);
+        sim.set_global_acceleration(0.0, 2.0, 0.0);
+        sim.vel_x[0] = 0.0;
+        sim.vel_y[0] = 0.0;
+        sim.step(0.05);
+
+        assert!((sim.vel_y[0] - 0.1).abs() < 1.0e-5);
+        assert!(sim.vel_x[0].abs() < 1.0e-6);
+    }
+
+    #[test]
+    fn wind_advects_positions_without_changing_velocity() {
+        let mut sim = Sim::new(1, 6, 1.0, 1.0);
+        sim.set_max_force(0.0);
+        sim.set_min_speed(0.0);
+        sim.set_axis_bounce(false, false, false);
+        sim.pos_x[0] = 0.5;
+        sim.pos_y[0] = 0.5;
+        sim.vel_x[0] = 0.0;
+        sim.vel_y[0] = 0.0;
+
+        sim.set_wind(0.5, 0.0, 0.0);
+        sim.step(0.05);
+        let eased = sim.wind_x();
+        assert!(eased > 0.0 && eased < 0.5);
+        assert!((sim.pos_x[0] - (0.5 + eased * 0.05)).abs() < 1.0e-6);
+        assert_eq!(sim.vel_x[0], 0.0);
+
+        sim.set_wind_response(0.0);
+        sim.step(0.05);
+        assert_eq!(sim.wind_x(), 0.5);
+    }
+
+    #[test]
+    fn fish_school_bursts_and_seeks_preferred_depth() {
+        let mut sim = Sim::new(1, 21, 1.0, 1.0);
+        sim.set_z_mode(true);
+        sim.set_jitter_strength(0.0);
+        sim.set_shape_attractor_weight(0.0);
+        sim.set_model_kind(5);
+        sim.set_fish_config(1.8, 0.8, 2.0, 0.5, 0.3, 0.6, 2.0);
+        sim.pos_z[0] = 0.2;
+
+        let mut min_speed = f32::MAX;
+        let mut max_speed = 0.0_f32;
+        for _ in 0..300 {
+            sim.step(0.016);
+            let speed = (sim.vel_x[0].powi(2) + sim.vel_y[0].powi(2) + sim.vel_z[0].powi(2)).sqrt();
+            min_speed = min_speed.min(speed);
+            max_speed = max_speed.max(speed);
+        }
+
+        assert!(max_speed - min_speed > 0.02, "{min_speed}..{max_speed}");
+        assert!((sim.pos_z[0] - 0.8).abs() < 0.2, "z={}", sim.pos_z[0]);
+    }
+
+    #[test]
+    fn kd_tree_backend_matches_grid_steering() {
+        let mut grid = Sim::new(256, 31, 1.0, 1.0);
+        grid.set_neighbor_radius(0.25);
+        grid.set_jitter_strength(0.0);
+        let mut kd_tree = Sim::new(256, 31, 1.0, 1.0);
+        kd_tree.set_neighbor_radius(0.25);
+        kd_tree.set_jitter_strength(0.0);
+        kd_tree.set_neighbor_backend(1);
+        kd_tree.set_kd_rebuild_interval(3);
+
+        for _ in 0..4 {
+            grid.step(0.016);
+            kd_tree.step(0.016);
+            assert_eq!(
+                grid.neighbors_visited_last_step(),
+                kd_tree.neighbors_visited_last_step()
+            );
+        }
+        for i in 0..grid.count() {
+            assert!((grid.pos_x[i] - kd_tree.pos_x[i]).abs() < 1.0e-4);
+            assert!((grid.pos_y[i] - kd_tree.pos_y[i]).abs() < 1.0e-4);
+        }
+
+        kd_tree.set_neighbor_radius(0.01);
+        assert_eq!(kd_tree.recommended_neighbor_backend(), 0);
+        kd_tree.set_neighbor_radius(0.3);
+        assert_eq!(kd_tree.recommended_neighbor_backend(), 1);
+    }
+
+    #[test]
+    fn time_scale_substeps_match_manual_steps() {
+        let mut scaled = Sim::new(64, 8, 1.0, 1.0);
+        let mut manual = Sim::new(64, 8, 1.0, 1.0);
+        scaled.set_time_scale(2.0);
+        scaled.step(0.016);
+        manual.step(0.016);
+        manual.step(0.016);
+
+        assert_eq!(scaled.pos_x, manual.pos_x);
+        assert_eq!(scaled.vel_y, manual.vel_y);
+
+        scaled.set_time_scale(0.5);
+        let before = scaled.pos_x.clone();
+        scaled.step(0.016);
+        manual.step(0.008);
+        assert_ne!(scaled.pos_x, before);
+        assert_eq!(scaled.pos_x, manual.pos_x);
+    }
+
+    #[test]
+    fn fish_cycle_offsets_follow_boid_ids() {
+        let mut sim = Sim::new(16, 5, 1.0, 1.0);
+        sim.set_model_kind(5);
+        let before: Vec<f32> = (0..16).map(|i| sim.fish_cycle_offset(i)).collect();
+        let order: Vec<u32> = (0..16).rev().collect();
+        sim.set_active_indices(&order);
+        assert_eq!(sim.boid_ids[0], 15);
+        for i in 0..16 {
+            let id = sim.boid_ids[i] as usize;
+            assert_eq!(sim.fish_cycle_offset(i), before[id]);
+        }
+    }
+
+    #[test]
+    fn moving_obstacles_record_as_state() {
+        let mut recorded = Sim::new(40, 13, 1.0, 1.0);
+        recorded.set_obstacles_xyzr(&[0.3, 0.4, 0.5, 0.05]);
+        assert!(recorded.update_obstacle(0, 0.3, 0.4, 0.2, -0.1));
+        recorded.start_recording();
+        let start_len = recorded.export_recording().len();
+        for _ in 0..10 {
+            recorded.step(0.016);
+        }
+        // Each step adds only its tag and dt; nothing is re-logged.
+        assert_eq!(recorded.export_recording().len(), start_len + 10 * 5);
+
+        let mut replayed = Sim::new(40, 2, 1.0, 1.0);
+        assert!(replayed.replay(&recorded.export_recording()));
+        assert_eq!(replayed.obstacles_xyzr(), recorded.obstacles_xyzr());
+        assert_eq!(replayed.pos_x, recorded.pos_x);
+    }
+
+    #[test]
+    fn replay_rejects_corrupt_buffer_lengths() {
+        // Records from `sim` and overwrites the buffer length `len`, which must
+        // be the only aligned word with that value in the start block.
+        let corrupted = |mut sim: Sim, len: u32| {
+            sim.step(0.016);
+            sim.start_recording();
+            let log = sim.export_recording();
+            let needle = len.to_le_bytes();
+            let offsets: Vec<usize> = (12..log.len() - 3)
+                .step_by(4)
+                .filter(|&k| log[k..k + 4] == needle)
+                .collect();
+            assert_eq!(offsets.len(), 1);
+            assert!(Sim::new(37, 2, 1.0, 1.0).replay(&log));
+            let mut corrupt = log;
+            corrupt[offsets[0]..offsets[0] + 4].copy_from_slice(&0x7fff_ffffu32.to_le_bytes());
+            corrupt
+        };
+
+        // 37 boids give a ramp history of 111 floats.
+        let mut ramped = Sim::new(37, 11, 1.0, 1.0);
+        ramped.set_accel_ramp(2.0);
+        assert!(!Sim::new(37, 2, 1.0, 1.0).replay(&corrupted(ramped, 111)));
+
+        // Seven remembered neighbors each for 37 boids.
+        let mut remembering = Sim::new(37, 11, 1.0, 1.0);
+        remembering.set_model_kind(1);
+        remembering.set_flock2_neighbor_hysteresis(0.2);
+        assert!(!Sim::new(37, 2, 1.0, 1.0).replay(&corrupted(remembering, 259)));
+
+        // Eight bond slots each for 37 boids.
+        let mut bonded = Sim::new(37, 11, 1.0, 1.0);
+        bonded.set_familiarity(1.0, 2.0, 30.0);
+        assert!(!Sim::new(37, 2, 1.0, 1.0).replay(&corrupted(bonded, 296)));
+    }
+
+    #[test]
+    fn replay_reproduces_recorded_session() {
+        let mut recorded = Sim::new(48, 11, 1.0, 1.0);
+        recorded.step(0.016);
+        recorded.start_recording();
+        for _ in 0..5 {
+            recorded.step(0.016);
+        }
+        recorded.set_sep_weight(2.5);
+        recorded.set_wind(0.2, -0.1, 0.0);
+        recorded.set_z_mode(true);
+        recorded.pos_x[3] = 0.75;
+        for _ in 0..5 {
+            recorded.step(0.02);
+        }
+        recorded.stop_recording();
+        let log = recorded.export_recording();
+
+        let mut replayed = Sim::new(48, 99, 1.0, 1.0);
+        assert!(replayed.replay(&log));
+        assert_eq!(replayed.pos_x, recorded.pos_x);
+        assert_eq!(replayed.vel_z, recorded.vel_z);
+        assert_eq!(replayed.step_index, recorded.step_index);
+        assert_eq!(replayed.config.sep_weight, 2.5);
+        assert!(!Sim::new(8, 1, 1.0, 1.0).replay(&log));
+
+        // The first setting after the 12-byte header is `sep_weight`.
+        let mut corrupt = log.clone();
+        corrupt[12..16].copy_from_slice(&f32::NAN.to_le_bytes());
+        let mut rejected = Sim::new(48, 99, 1.0, 1.0);
+        assert!(!rejected.replay(&corrupt));
+        assert!(rejected.config.sep_weight.is_finite());
+
+        corrupt[12..16].copy_from_slice(&1.0e9f32.to_le_bytes());
+        assert!(!rejected.replay(&corrupt));
+        assert!(rejected.config.sep_weight <= MAX_STEERING_WEIGHT);
+    }
+
+    #[test]
+    fn snapshot_history_rolls_back_within_quantization() {
+        let mut sim = Sim::new(256, 21, 1.0, 1.0);
+        sim.set_snapshot_capacity(40);
+        let mut history = Vec::new();
+        for _ in 0..50 {
+            sim.step(0.016);
+            history.push((sim.pos_x.clone(), sim.vel_y.clone(), sim.step_index));
+        }
+        assert_eq!(sim.snapshot_count(), 40);
+        assert!(sim.snapshot_history_bytes() < 40 * 256 * 12 * 4 / 2);
+
+        assert!(sim.restore_snapshot(36));
+        let (pos_x, vel_y, step_index) = &history[13];
+        assert_eq!(sim.step_index, *step_index);
+        for i in 0..256 {
+            assert!((sim.pos_x[i] - pos_x[i]).abs() <= 1.0 / 65535.0);
+            assert!((sim.vel_y[i] - vel_y[i]).abs() <= 1.0 / 32767.0);
+        }
+        assert_eq!(sim.snapshot_count(), 4);
+        assert!(!sim.restore_snapshot(4));
+    }
+
+    #[test]
+    fn golden_run_is_deterministic_per_seed() {
+        assert_eq!(run_golden(5, 30), run_golden(5, 30));
+        assert_ne!(run_golden(5, 30), run_golden(6, 30));
+        assert_ne!(run_golden(5, 30), run_golden(5, 31));
+        // Only an intended behavior change should move this value.
+        assert_eq!(run_golden(5, 30), 12642618550581300470);
+    }
+
+    #[test]
+    fn steering_debug_components_sum_to_acceleration() {
+        let mut sim = Sim::new(32, 17, 1.0, 1.0);
+        sim.set_max_force(100.0);
+        sim.set_shape_attractor_weight(0.0);
+        sim.set_jitter_strength(0.05);
+        sim.set_speed_match_weight(0.5);
+        sim.set_steering_debug(1, 3);
+        sim.step(0.016);
+
+        let components = &sim.steering_debug.components;
+        assert_eq!(components.len(), STEERING_DEBUG_STRIDE);
+        assert!(components[9..12].iter().any(|value| *value != 0.0));
+        for axis in 0..3 {
+            let sum: f32 = (0..5).map(|term| components[term * 3 + axis]).sum();
+            let accel = [sim.accel_x[3], sim.accel_y[3], sim.accel_z[3]][axis];
+            assert!((sum - accel).abs() < 1e-5);
+        }
+
+        sim.set_steering_debug(2, 0);
+        sim.step(0.016);
+        assert_eq!(sim.steering_debug_len(), 32 * STEERING_DEBUG_STRIDE);
+    }
+
+    #[test]
+    fn crowding_counts_separation_neighbors_against_cap() {
+        let mut sim = Sim::new(5, 4, 1.0, 1.0);
+        sim.set_crowding_cap(2.0);
+        for i in 0..4 {
+            sim.pos_x[i] = 0.5 + i as f32 * 0.002;
+            sim.pos_y[i] = 0.5;
+        }
+        sim.pos_x[4] = 0.1;
+        sim.pos_y[4] = 0.1;
+        sim.step(0.001);
+
+        assert_eq!(sim.render_crowding[0], 1.0);
+        assert_eq!(sim.render_crowding[4], 0.0);
+
+        sim.set_crowding_cap(6.0);
+        sim.step(0.001);
+        assert_eq!(sim.render_crowding[0], 0.5);
+    }
+
+    #[test]
+    fn metric_history_keeps_latest_samples_in_ring_order() {
+        let mut sim = Sim::new(64, 3, 1.0, 1.0);
+        sim.set_metric_history_length(4);
+        let mut expected = Vec::new();
+        for _ in 0..6 {
+            sim.step(0.016);
+            expected.push(sim.polarization());
+        }
+
+        assert_eq!(sim.metric_history_filled(), 4);
+        let start = sim.metric_history_start();
+        let samples: Vec<f32> = (0..4)
+            .map(|k| sim.metric_history.polarization[(start + k) % 4])
+            .collect();
+        assert_eq!(samples, expected[2..]);
+        assert!(expected.iter().all(|p| (0.0..=1.0).contains(p)));
+    }
+
+    #[test]
+    fn boundary_pressure_measures_wall_reflections() {
+        let mut sim = Sim::new(2, 9, 1.0, 1.0);
+        sim.set_z_mode(false);
+        sim.apply_config(&SimConfig {
+            sep_weight: 0.0,
+            align_weight: 0.0,
+            coh_weight: 0.0,
+            neighbor_radius: 0.08,
+            separation_radius: 0.035,
+            min_speed: 0.0,
+            max_speed: 2.0,
+            max_force: 0.0,
+            ..sim.config()
+        });
+        sim.set_drag(0.0);
+        sim.pos_x[0] = 0.995;
+        sim.pos_y[0] = 0.5;
+        sim.vel_x[0] = 1.0;
+        sim.vel_y[0] = 0.0;
+        sim.pos_x[1] = 0.2;
+        sim.pos_y[1] = 0.2;
+        sim.vel_x[1] = 1.0;
+        sim.vel_y[1] = 0.0;
+
+        sim.step(0.01);
+        assert_eq!(sim.boundary_correction(), 0.0);
+
+        sim.set_bounce_bounds(true);
+        sim.pos_x[0] = 0.995;
+        sim.step(0.01);
+        assert!((sim.boundary_correction() - 2.0).abs() < 1e-4);
+        assert!((sim.boundary_pressure() - 100.0).abs() < 1e-2);
+    }
+
+    #[test]
+    fn flight_metrics_only_cover_flight_models() {
+        let mut sim = Sim::new(32, 14, 1.0, 1.0);
+        sim.step(0.016);
+        assert_eq!(sim.mean_airspeed(), 0.0);
+        assert_eq!(sim.thrust_work(), 0.0);
+
+        for kind in [2, 4] {
+            sim.set_model_kind(kind);
+            sim.step(0.016);
+            let config = sim.flock2_config;
+            assert!(sim.mean_airspeed() >= config.min_speed - 1.0e-3);
+            assert!(sim.mean_airspeed() <= config.max_speed + 1.0e-3);
+            assert!(sim.thrust_work() > 0.0);
+            assert!((0.0..=1.0).contains(&sim.stall_fraction()));
+        }
+
+        sim.set_model_kind(1);
+        sim.step(0.016);
+        assert_eq!(sim.stall_fraction(), 0.0);
+    }
+
+    #[test]
+    fn paused_sim_refreshes_render_buffers_only() {
+        let mut sim = Sim::new(4, 2, 1.0, 1.0);
+        sim.step(0.02);
+        sim.pause();
+        let frozen = sim.pos_x.clone();
+
+        sim.pos_x[0] = 0.25;
+        sim.step(0.02);
+        assert!(sim.is_paused());
+        assert_eq!(sim.render_xy[0], 0.25);
+        assert_eq!(sim.pos_x[1..], frozen[1..]);
+
+        sim.debug_step();
+        assert_ne!(sim.pos_x[1..], frozen[1..]);
+        assert!(sim.is_paused());
+
+        sim.resume();
+        assert!(!sim.is_paused());
+    }
+
+    #[test]
+    fn far_field_cohesion_stays_close_to_exact() {
+        // A radius that divides the world evenly keeps the coarse and fine
+        // grids scanning the same wrapped neighborhood.
+        let mean_accel_diff = |opening: f32| {
+            let mut exact = Sim::new(512, 77, 1.0, 1.0);
+            exact.set_neighbor_radius(0.25);
+            exact.set_jitter_strength(0.0);
+            let mut approx = Sim::new(512, 77, 1.0, 1.0);
+            approx.set_neighbor_radius(0.25);
+            approx.set_jitter_strength(0.0);
+            approx.set_far_field_opening(opening);
+
+            exact.step(0.016);
+            approx.step(0.016);
+
+            let mut total_diff = 0.0;
+            for i in 0..exact.count() {
+                total_diff += (exact.accel_x[i] - approx.accel_x[i]).abs()
+                    + (exact.accel_y[i] - approx.accel_y[i]).abs();
+            }
+            total_diff / exact.count() as f32
+        };
+
+        // Tiny openings never aggregate, so they must reproduce the exact sums.
+        assert!(mean_accel_diff(0.01) < 1.0e-5);
+
+        // Individual boids with a near-zero neighbor average can flip direction
+        // under any approximation, so compare the population mean instead.
+        let diff = mean_accel_diff(0.5);
+        assert!(diff < 0.25 * DEFAULT_MAX_FORCE, "mean diff {diff}");
+    }
+
+    #[test]
+    fn soft_and_hard_min_distance_are_independent() {
+        let mut sim = Sim::new(2, 5, 1.0, 1.0);
+        sim.set_min_distance(0.12);
+        sim.set_hard_min_distance(0.34);
+
+        assert!((sim.min_distance() - 0.12).abs() < 1.0e-6);
+        assert!((sim.hard_min_distance() - 0.34).abs() < 1.0e-6);
+    }
+
+    #[test]
+    fn reserve_extends_capacity_like_a_larger_sim() {
+        let mut grown = Sim::new(16, 21, 1.0, 1.0);
+        grown.reserve(64);
+        grown.reserve(8);
+        let fresh = Sim::new(64, 21, 1.0, 1.0);
+
+        assert_eq!(grown.count(), 64);
+        assert_eq!(grown.active_count(), 16);
+        assert_eq!(grown.pos_x, fresh.pos_x);
+        assert_eq!(grown.vel_z, fresh.vel_z);
+        assert_eq!(grown.render_xy, fresh.render_xy);
+
+        let mut pooled = Sim::with_capacity(64, 21, 1.0, 1.0);
+        assert_eq!(pooled.active_count(), 0);
+        let ptr = pooled.render_xy_ptr();
+        pooled.set_active_count(40);
+        pooled.step(0.016);
+        assert_eq!(pooled.render_xy_ptr(), ptr);
+    }
+
+    #[test]
+    fn active_indices_freeze_the_rest_and_keep_ids() {
+        let mut sim = Sim::new(8, 31, 1.0, 1.0);
+        let start_x: Vec<f32> = sim.pos_x.clone();
+        sim.set_active_indices(&[6, 2, 6, 99, 4]);
+
+        assert_eq!(sim.active_count(), 3);
+        assert_eq!(&sim.boid_ids[..3], &[6, 2, 4]);
+        for id in 0..8u32 {
+            let slot = sim.boid_slot(id).unwrap() as usize;
+            assert_eq!(sim.boid_ids[slot], id);
+            assert_eq!(sim.pos_x[slot], start_x[id as usize]);
+        }
+
+        sim.step(0.016);
+        for id in [0u32, 1, 3, 5, 7] {
+            let slot = sim.boid_slot(id).unwrap() as usize;
+            assert_eq!(sim.pos_x[slot], start_x[id as usize]);
+            assert_eq!(sim.render_xy[2 * slot], start_x[id as usize]);
+        }
+        let moved = sim.boid_slot(6).unwrap() as usize;
+        assert_ne!(sim.pos_x[moved], start_x[6]);
+    }
+
+    #[test]
+    fn low_detail_boids_reuse_stale_steering() {
+        let mut full = Sim::new(64, 12, 1.0, 1.0);
+        let mut lod = Sim::new(64, 12, 1.0, 1.0);
+        lod.set_lod_interval(4);
+        lod.set_lod_tiers(&[1; 64]);
+
+        full.step(0.016);
+        lod.step(0.016);
+        assert!(lod.neighbors_visited_last_step < full.neighbors_visited_last_step / 2);
+
+        let before = lod.accel_x.clone();
+        lod.step(0.016);
+        for (i, &stale) in before.iter().enumerate() {
+            if !lod.lod_refreshes(i) {
+                assert_eq!(lod.accel_x[i], stale);
+            }
+        }
+
+        lod.set_lod_tier(3, 0);
+        assert_eq!(lod.lod_tier(3), 0);
+        assert!(lod.lod_refreshes(3));
+    }
+
+    #[test]
+    fn focus_region_overrides_manual_tiers() {
+        let mut sim = Sim::new(256, 40, 1.0, 1.0);
+        sim.set_lod_tiers(&[1; 256]);
+        sim.set_focus_region(0.25, 0.25, 0.2);
+        sim.set_max_neighbors_sampled(32);
+
+        for i in 0..256 {
+            let dx = shortest_wrapped_delta(sim.pos_x[i] - 0.25);
+            let dy = shortest_wrapped_delta(sim.pos_y[i] - 0.25);
+            let inside = dx * dx + dy * dy <= 0.04;
+            assert_eq!(sim.low_detail(i), !inside);
+            assert_eq!(sim.neighbor_sample_cap(i), if inside { 32 } else { 8 });
+        }
+
+        sim.set_focus_region(0.0, 0.0, 0.0);
+        assert_eq!(sim.focus_radius(), 0.0);
+        assert!(sim.low_detail(0));
+        assert_eq!(sim.neighbor_sample_cap(0), 32);
+    }
+
+    #[test]
+    fn update_fraction_rotates_through_every_boid() {
+        let mut sim = Sim::new(10, 8, 1.0, 1.0);
+        sim.set_update_fraction(0.25);
+        let mut refreshed = [0; 10];
+        for step in 0..4 {
+            sim.step_index = step;
+            let due: Vec<usize> = (0..10).filter(|&i| sim.lod_refreshes(i)).collect();
+            assert_eq!(due.len(), 3);
+            for i in due {
+                refreshed[i] += 1;
+            }
+        }
+        assert!(refreshed.iter().all(|&count| count >= 1));
+        // 3 does not divide 10, so the fourth window wraps onto two boids.
+        assert_eq!(refreshed.iter().filter(|&&count| count == 2).count(), 2);
+
+        sim.set_update_fraction(f32::NAN);
+        assert_eq!(sim.update_fraction(), 1.0);
+        assert!((0..10).all(|i| sim.lod_refreshes(i)));
+    }
+
+    #[test]
+    fn partial_steps_match_a_full_step() {
+        let mut full = Sim::new(100, 9, 1.0, 1.0);
+        let mut sliced = Sim::new(100, 9, 1.0, 1.0);
+        full.set_jitter_strength(0.0);
+        sliced.set_jitter_strength(0.0);
+
+        for _ in 0..3 {
+            full.step(0.016);
+            assert!(!sliced.step_partial(0.016, 30));
+            assert!(!sliced.step_partial(0.016, 30));
+            assert!(!sliced.step_partial(0.016, 30));
+            assert!(sliced.partial_step_pending());
+            assert!(sliced.step_partial(0.016, 30));
+            assert!(!sliced.partial_step_pending());
+        }
+
+        assert_eq!(sliced.pos_x, full.pos_x);
+        assert_eq!(sliced.vel_y, full.vel_y);
+        assert_eq!(sliced.step_index, full.step_index);
+        assert_eq!(
+            sliced.neighbors_visited_last_step,
+            full.neighbors_visited_last_step
+        );
+    }
+
+    #[test]
+    fn sliced_steps_record_and_replay_exactly() {
+        let mut recorded = Sim::new(100, 9, 1.0, 1.0);
+        let mut full = Sim::new(100, 9, 1.0, 1.0);
+        recorded.start_recording();
+        for _ in 0..3 {
+            while !recorded.step_partial(0.016, 30) {}
+        }
+        // A full step taken mid-pass finishes the pass instead of starting
+        // another, so the step index still advances once.
+        assert!(!recorded.step_partial(0.016, 30));
+        recorded.step(0.016);
+        assert!(!recorded.partial_step_pending());
+        for _ in 0..4 {
+            full.step(0.016);
+        }
+        assert_eq!(recorded.step_index, full.step_index);
+        assert_eq!(recorded.pos_x, full.pos_x);
+        recorded.set_sep_weight(2.0);
+        while !recorded.step_partial(0.02, 45) {}
+        recorded.stop_recording();
+
+        let mut replayed = Sim::new(100, 77, 1.0, 1.0);
+        assert!(replayed.replay(&recorded.export_recording()));
+        assert_eq!(replayed.step_index, recorded.step_index);
+        assert_eq!(replayed.pos_x, recorded.pos_x);
+        assert_eq!(replayed.vel_y, recorded.vel_y);
+    }
+
+    #[test]
+    fn reused_grid_still_finds_every_neighbor() {
+        // A radius that divides the world evenly keeps the fresh grid exact
+        // across the wrap seam, so only the reuse can differ.
+        let mut fresh = Sim::new(300, 17, 1.0, 1.0);
+        let mut reused = Sim::new(300, 17, 1.0, 1.0);
+        for sim in [&mut fresh, &mut reused] {
+            sim.set_jitter_strength(0.0);
+            sim.set_neighbor_radius(0.1);
+        }
+        reused.set_grid_rebuild_interval(8);
+
+        for _ in 0..4 {
+            fresh.step(0.016);
+            reused.step(0.016);
+        }
+        assert_eq!(reused.neighbor_grid.steps_since_rebuild(), 3);
+        assert!(reused.neighbor_grid.query_slack() > 0.0);
+        let max_diff = (0..300)
+            .map(|i| {
+                (fresh.pos_x[i] - reused.pos_x[i])
+                    .abs()
+                    .max((fresh.pos_y[i] - reused.pos_y[i]).abs())
+            })
+            .fold(0.0_f32, f32::max);
+        assert!(max_diff < 1.0e-4, "max diff {max_diff}");
+
+        reused.set_active_count(200);
+        reused.step(0.016);
+        assert_eq!(reused.neighbor_grid.steps_since_rebuild(), 0);
+    }
+
+    #[test]
+    fn large_topological_caps_use_more_neighbors() {
+        let heading_after_step = |cap: usize| {
+            let mut sim = Sim::new(400, 23, 1.0, 1.0);
+            sim.set_model_kind(2);
+            let report = sim.apply_flock2_config(&Flock2Config {
+                avoid_weight: 1.0,
+                align_weight: 1.0,
+                cohesion_weight: 1.0,
+                neighbor_radius: 0.5,
+                topological_neighbors: cap,
+                field_of_view_deg: 360.0,
+                ..sim.flock2_config()
+            });
+            assert!(report.is_empty());
+            assert_eq!(sim.flock2_config.topological_neighbors, cap);
+            sim.step(0.016);
+            sim.heading_x.clone()
+        };
+
+        let inline = heading_after_step(64);
+        assert_ne!(heading_after_step(300), inline);
+        assert_eq!(heading_after_step(64), inline);
+
+        // Large caps rank into a scratch buffer that later steps reuse.
+        let mut sim = Sim::new(50, 23, 1.0, 1.0);
+        sim.set_model_kind(1);
+        sim.flock2_config.topological_neighbors = 300;
+        sim.step(0.016);
+        assert_eq!(sim.topological_indices.len(), 300);
+        let scratch = sim.topological_indices.as_ptr();
+        sim.step(0.016);
+        assert_eq!(sim.topological_indices.as_ptr(), scratch);
+    }
+
+    #[test]
+    fn bank_limit_bounds_turn_rate_by_speed() {
+        let mut sim = Sim::new(200, 6, 1.0, 1.0);
+        sim.set_model_kind(3);
+        sim.set_flock2_max_bank_deg(10.0);
+        assert_eq!(sim.flock2_max_bank_deg(), 10.0);
+        let dt = 0.016;
+        let max_turn_rate = |speed: f32| 9.8 * 10.0_f32.to_radians().tan() / speed;
+
+        for _ in 0..5 {
+            let before: Vec<(f32, f32, f32)> = (0..200)
+                .map(|i| (sim.heading_x[i], sim.heading_y[i], sim.flock2_speed(i)))
+                .collect();
+            sim.step(dt);
+            for (i, &(hx, hy, speed)) in before.iter().enumerate() {
+                let cos_turn = (hx * sim.heading_x[i] + hy * sim.heading_y[i]).clamp(-1.0, 1.0);
+                assert!(cos_turn.acos() <= max_turn_rate(speed) * dt + 1.0e-3);
+            }
+        }
+
+        sim.set_flock2_max_bank_deg(f32::NAN);
+        assert_eq!(sim.flock2_max_bank_deg(), 90.0);
+    }
+
+    #[test]
+    fn altitude_hold_pulls_flight_toward_target() {
+        let mean_height_error = |hold: bool| {
+            let mut sim = Sim::new(64, 19, 1.0, 1.0);
+            sim.set_bounce_bounds(true);
+            sim.set_model_kind(2);
+            sim.set_altitude_hold(hold, 0.3, 4.0, 0.5, 2.5);
+            for _ in 0..600 {
+                sim.step(1.0 / 60.0);
+            }
+            sim.pos_y.iter().map(|y| (y - 0.3).abs()).sum::<f32>() / 64.0
+        };
+
+        let held = mean_height_error(true);
+        assert!(held < 0.05, "held error {held}");
+        assert!(held < mean_height_error(false));
+    }
+
+    #[test]
+    fn wind_shear_changes_flight_forces_and_energy() {
+        let mut sim = Sim::new(32, 23, 1.0, 1.0);
+        sim.set_model_kind(2);
+        sim.set_wind_shear(2.0, 0.0, 0.5);
+        assert_eq!(sim.wind.velocity_at_height(0.75), (0.5, 0.0, 0.0));
+        assert_eq!(sim.wind.velocity_at_height(0.25), (-0.5, 0.0, 0.0));
+
+        let energy = sim.mean_flight_energy();
+        let expected = (0..32)
+            .map(|i| {
+                let speed_sq = sim.vel_x[i].powi(2) + sim.vel_y[i].powi(2) + sim.vel_z[i].powi(2);
+                0.5 * speed_sq
+                    + sim.flock2_config.gravity * sim.pos_y[i] / crate::flock2::FLOCK2_WORLD_SCALE
+            })
+            .sum::<f32>()
+            / 32.0;
+        assert!((energy - expected).abs() < 1e-3 * expected.abs().max(1.0));
+
+        // A flock climbing downwind through a positive shear draws energy from
+        // it in both flight models.
+        for model in [2, 4] {
+            let climbing = |shear: f32| {
+                let mut sim = Sim::new(32, 23, 1.0, 1.0);
+                sim.set_model_kind(model);
+                sim.set_wind_shear(shear, 0.0, 0.25);
+                for i in 0..32 {
+                    sim.pos_y[i] = 0.6 + 0.005 * i as f32;
+                    sim.vel_x[i] = 12.0;
+                    sim.vel_y[i] = 3.0;
+                    sim.vel_z[i] = 0.0;
+                    sim.heading_x[i] = 0.97;
+                    sim.heading_y[i] = 0.243;
+                    sim.heading_z[i] = 0.0;
+                }
+                let before = sim.mean_flight_energy();
+                for _ in 0..30 {
+                    sim.step(1.0 / 60.0);
+                }
+                sim.mean_flight_energy() - before
+            };
+            let (sheared, calm) = (climbing(0.5), climbing(0.0));
+            assert!(sheared > calm, "model {model}: {sheared} vs {calm}");
+        }
+
+        let mut classic = Sim::new(8, 23, 1.0, 1.0);
+        classic.set_model_kind(0);
+        assert_eq!(classic.mean_flight_energy(), 0.0);
+    }
+
+    #[test]
+    fn species_fly_with_their_own_aero_profile() {
+        let mut sim = Sim::new(64, 29, 1.0, 1.0);
+        sim.set_model_kind(2);
+        let species: Vec<u8> = (0..64).map(|i| (i % 2) as u8).collect();
+        sim.set_species(&species);
+        sim.set_flock2_species_aero(1, 0.4, 0.05, 0.6, 0.2, 1.5, 22.0, 30.0);
+        assert!(sim.has_flock2_species_aero(1));
+        for _ in 0..60 {
+            sim.step(1.0 / 60.0);
+        }
+
+        for i in 0..64 {
+            let speed = sim.flock2_speed(i);
+            if sim.boid_species(i) == 1 {
+                assert!((22.0 - 1e-3..=30.0 + 1e-3).contains(&speed), "fast {speed}");
+            } else {
+                assert!(speed <= sim.flock2_config.max_speed + 1e-3, "slow {speed}");
+            }
+        }
+
+        sim.clear_flock2_species_aero(1);
+        assert!(!sim.has_flock2_species_aero(1));
+        sim.set_boid_species(0, 200);
+        assert_eq!(sim.boid_species(0), 7);
+    }
+
+    #[test]
+    fn lite_neighbor_cap_can_be_raised_or_lifted() {
+        let visited = |cap: u32| {
+            let mut sim = Sim::new(400, 31, 1.0, 1.0);
+            sim.set_model_kind(3);
+            sim.apply_flock2_config(&Flock2Config {
+                neighbor_radius: 0.25,
+                topological_neighbors: 64,
+                field_of_view_deg: 360.0,
+                ..sim.flock2_config()
+            });
+            sim.set_flock2_lite_neighbor_cap(cap);
+            sim.step(1.0 / 60.0);
+            sim.neighbors_visited_last_step()
+        };
+
+        let default_cap = visited(16);
+        assert!(default_cap <= 400 * 16);
+        assert!(visited(48) > default_cap);
+        assert!(visited(0) >= visited(48));
+        assert!(visited(0) <= 400 * 64);
+    }
+
+    #[test]
+    fn avoidance_fov_is_independent_of_social_fov() {
+        let turn = |avoid_fov: f32| {
+            let mut sim = Sim::new(2, 37, 1.0, 1.0);
+            sim.set_model_kind(1);
+            sim.set_bounce_bounds(true);
+            sim.apply_flock2_config(&Flock2Config {
+                avoid_weight: 1.0,
+                align_weight: 0.0,
+                cohesion_weight: 0.0,
+                boundary_weight: 0.0,
+                boundary_count: 0.0,
+                neighbor_radius: 0.25,
+                field_of_view_deg: 90.0,
+                ..sim.flock2_config()
+            });
+            sim.set_flock2_avoid_fov_deg(avoid_fov);
+            // Boid 1 trails boid 0, outside its 90 degree social cone.
+            sim.pos_x[..2].copy_from_slice(&[0.5, 0.45]);
+            sim.pos_y[..2].copy_from_slice(&[0.5, 0.52]);
+            sim.heading_x[..2].copy_from_slice(&[1.0, 1.0]);
+            sim.heading_y[..2].copy_from_slice(&[0.0, 0.0]);
+            sim.step(1.0 / 60.0);
+            sim.heading_y[0].abs()
+        };
+
+        assert!(turn(0.0) < 1e-6);
+        assert!(turn(360.0) > 1e-4);
+    }
+
+    #[test]
+    fn obstacles_keep_classic_steering_running() {
+        let mut sim = Sim::new(1, 59, 1.0, 1.0);
+        sim.set_sep_weight(0.0);
+        sim.set_align_weight(0.0);
+        sim.set_coh_weight(0.0);
+        sim.set_jitter_strength(0.0);
+        sim.set_shape_attractor_weight(0.0);
+        sim.set_bounce_bounds(true);
+        sim.pos_x[0] = 0.5;
+        sim.pos_y[0] = 0.5;
+        sim.vel_x[0] = 0.0;
+        sim.vel_y[0] = 0.0;
+        sim.set_obstacles_xyzr(&[0.5, 0.45, 0.5, 0.03]);
+        sim.step(1.0 / 60.0);
+        assert!(sim.vel_y[0] > 0.0);
+    }
+
+    #[test]
+    fn obstacles_turn_both_model_families_away() {
+        // A single boid flying straight at a circle dead ahead.
+        let closest_approach = |model: u32, obstacles: &[f32]| {
+            let mut sim = Sim::new(1, 41, 1.0, 1.0);
+            sim.set_model_kind(model);
+            sim.set_bounce_bounds(true);
+            sim.set_obstacles_xyzr(obstacles);
+            sim.set_obstacle_avoidance(8.0, 4.0, 0.15);
+            sim.pos_x[0] = 0.2;
+            sim.pos_y[0] = 0.51;
+            sim.heading_x[0] = 1.0;
+            sim.heading_y[0] = 0.0;
+            let speed = if model == 0 { 0.3 } else { 10.0 };
+            sim.vel_x[0] = speed;
+            sim.vel_y[0] = 0.0;
+            let mut closest = f32::MAX;
+            for _ in 0..120 {
+                sim.step(1.0 / 60.0);
+                let dx = sim.pos_x[0] - 0.5;
+                let dy = sim.pos_y[0] - 0.5;
+                closest = closest.min((dx * dx + dy * dy).sqrt());
+            }
+            closest
+        };
+
+        for model in [0, 1, 3] {
+            assert!(
+                closest_approach(model, &[]) < 0.05,
+                "model {model} baseline"
+            );
+            assert!(
+                closest_approach(model, &[0.5, 0.5, 0.5, 0.1]) > 0.1,
+                "model {model} hit the obstacle"
+            );
+        }
+    }
+
+    #[test]
+    fn predators_keep_classic_steering_running() {
+        let mut sim = Sim::new(1, 59, 1.0, 1.0);
+        sim.set_sep_weight(0.0);
+        sim.set_align_weight(0.0);
+        sim.set_coh_weight(0.0);
+        sim.set_jitter_strength(0.0);
+        sim.set_shape_attractor_weight(0.0);
+        sim.set_bounce_bounds(true);
+        sim.pos_x[0] = 0.5;
+        sim.pos_y[0] = 0.5;
+        sim.vel_x[0] = 0.0;
+        sim.vel_y[0] = 0.0;
+        sim.set_predators_xyz(&[0.5, 0.45, 0.5]);
+        sim.step(1.0 / 60.0);
+        assert!(sim.vel_y[0] > 0.0);
+    }
+
+    #[test]
+    fn predators_drive_evasion_with_refractory_period() {
+        for model in [0, 1, 3] {
+            let mut sim = Sim::new(1, 43, 1.0, 1.0);
+            sim.set_model_kind(model);
+            sim.set_bounce_bounds(true);
+            sim.set_predator_response(0.2, 6.0, 4.0, 0.5);
+            sim.pos_x[0] = 0.5;
+            sim.pos_y[0] = 0.5;
+            sim.heading_x[0] = 1.0;
+            sim.heading_y[0] = 0.0;
+            sim.set_predators_xyz(&[0.5, 0.45, 0.5]);
+            for _ in 0..30 {
+                sim.step(1.0 / 60.0);
+            }
+            assert!(
+                sim.pos_y[0] > 0.52,
+                "model {model} did not flee: {}",
+                sim.pos_y[0]
+            );
+        }
+
+        let mut sim = Sim::new(1, 43, 1.0, 1.0);
+        sim.set_model_kind(1);
+        sim.set_predator_response(0.2, 6.0, 4.0, 0.5);
+        sim.pos_x[0] = 0.5;
+        sim.pos_y[0] = 0.5;
+        sim.set_predators_xyz(&[0.5, 0.45, 0.5]);
+        sim.step(1.0 / 60.0);
+        assert_eq!(sim.evasion_time_left(0), 0.5);
+        sim.set_predators_xyz(&[]);
+        for _ in 0..15 {
+            sim.step(1.0 / 60.0);
+        }
+        assert!((sim.evasion_time_left(0) - 0.25).abs() < 1e-3);
+        for _ in 0..30 {
+            sim.step(1.0 / 60.0);
+        }
+        assert_eq!(sim.evasion_time_left(0), 0.0);
+    }
+
+    #[test]
+    fn reaction_times_scatter_per_boid_and_follow_ids() {
+        let mut sim = Sim::new(200, 47, 1.0, 1.0);
+        sim.set_model_kind(1);
+        let base = sim.flock2_config.reaction_time_ms;
+        assert!((0..200).all(|i| sim.flock2_reaction_time_ms_of(i) == base));
+
+        sim.set_flock2_reaction_distribution(1, 0.5, 9);
+        let times: Vec<f32> = (0..200)
+            .map(|i| sim.flock2_reaction_time_ms_of(i))
+            .collect();
+        assert!(times.iter().all(|t| (base * 0.5..=base * 1.5).contains(t)));
+        let mean = times.iter().sum::<f32>() / 200.0;
+        assert!((mean - base).abs() < base * 0.1);
+        assert!(times.iter().any(|t| (t - base).abs() > base * 0.3));
+
+        let mut reseeded = Sim::new(200, 3, 1.0, 1.0);
+        reseeded.set_flock2_reaction_distribution(1, 0.5, 9);
+        assert_eq!(reseeded.flock2_reaction_time_ms_of(17), times[17]);
+
+        sim.set_active_indices(&[17, 3]);
+        let slot = sim.boid_slot(17).unwrap() as usize;
+        assert_eq!(sim.flock2_reaction_time_ms_of(slot), times[17]);
+
+        sim.set_flock2_reaction_distribution(2, 0.8, 9);
+        let slow = (0..200)
+            .filter(|&i| sim.flock2_reaction_time_ms_of(i) > base * 2.0)
+            .count();
+        assert!(slow > 0 && slow < 100);
+    }
+
+    #[test]
+    fn decision_noise_is_bounded_and_seeded() {
+        let headings = |model: u32, z_mode: bool, noise: f32, seed: u32| {
+            let mut sim = Sim::new(50, 53, 1.0, 1.0);
+            sim.set_model_kind(model);
+            sim.set_z_mode(z_mode);
+            sim.apply_flock2_config(&Flock2Config {
+                avoid_weight: 0.0,
+                align_weight: 0.0,
+                cohesion_weight: 0.0,
+                boundary_weight: 0.0,
+                boundary_count: 0.0,
+                ..sim.flock2_config()
+            });
+            sim.set_flock2_decision_noise(noise, seed);
+            sim.step(1.0 / 60.0);
+            (0..50)
+                .map(|i| [sim.heading_x[i], sim.heading_y[i], sim.heading_z[i]])
+                .collect::<Vec<_>>()
+        };
+
+        for (model, z_mode) in [(1, false), (1, true), (3, false), (3, true)] {
+            let quiet = headings(model, z_mode, 0.0, 1);
+            let noisy = headings(model, z_mode, 10.0, 1);
+            assert_eq!(noisy, headings(model, z_mode, 10.0, 1));
+            assert_ne!(noisy, headings(model, z_mode, 10.0, 2));
+            assert_ne!(noisy, quiet);
+            // Two bounded rotations in 3D, one in the plane.
+            let bound = if z_mode { 2.0 } else { 1.0 } * 10f32.to_radians() + 1e-3;
+            for (a, b) in quiet.iter().zip(&noisy) {
+                let cos = (a[0] * b[0] + a[1] * b[1] + a[2] * b[2]).clamp(-1.0, 1.0);
+                assert!(cos.acos() <= bound, "model {model} z {z_mode}");
+            }
+        }
+    }
+
+    #[test]
+    fn heading_alignment_ignores_neighbor_speed() {
+        let accel = |headings: bool| {
+            let mut sim = Sim::new(4, 61, 1.0, 1.0);
+            sim.set_sep_weight(0.0);
+            sim.set_coh_weight(0.0);
+            sim.set_jitter_strength(0.0);
+            sim.set_shape_attractor_weight(0.0);
+            sim.set_neighbor_radius(0.1);
+            sim.set_align_to_headings(headings);
+            // One fast neighbor heading +x, two slow ones heading +y.
+            sim.pos_x[..4].copy_from_slice(&[0.5, 0.52, 0.48, 0.5]);
+            sim.pos_y[..4].copy_from_slice(&[0.5, 0.5, 0.5, 0.53]);
+            sim.vel_x[..4].copy_from_slice(&[0.0, 0.9, 0.0, 0.0]);
+            sim.vel_y[..4].copy_from_slice(&[0.0, 0.0, 0.1, 0.1]);
+            sim.step(1.0 / 60.0);
+            (sim.accel_x[0], sim.accel_y[0])
+        };
+
+        let (vx, vy) = accel(false);
+        assert!(vx > vy, "velocity alignment follows the fast neighbor");
+        let (hx, hy) = accel(true);
+        assert!(hy > hx, "heading alignment follows the majority");
+    }
+
+    #[test]
+    fn separation_kernel_changes_near_versus_far_balance() {
+        let push_x = |kernel: u32| {
+            let mut sim = Sim::new(4, 67, 1.0, 1.0);
+            sim.set_align_weight(0.0);
+            sim.set_coh_weight(0.0);
+            sim.set_jitter_strength(0.0);
+            sim.set_shape_attractor_weight(0.0);
+            sim.set_neighbor_radius(0.1);
+            sim.set_separation_radius(0.05);
+            sim.set_min_distance(0.0);
+            sim.set_separation_kernel(kernel);
+            // One close neighbor on the right, two farther ones on the left.
+            sim.pos_x[..4].copy_from_slice(&[0.5, 0.51, 0.47, 0.47]);
+            sim.pos_y[..4].copy_from_slice(&[0.5, 0.5, 0.501, 0.499]);
+            sim.vel_x[..4].fill(0.0);
+            sim.vel_y[..4].fill(0.0);
+            sim.step(1.0 / 60.0);
+            sim.accel_x[0]
+        };
+
+        assert_eq!(Sim::new(1, 1, 1.0, 1.0).separation_kernel(), 1);
+        assert!(push_x(0) > 0.0, "unit pushes: two neighbors outvote one");
+        assert!(push_x(1) < 0.0, "1/d pushes: the close neighbor wins");
+        assert!(push_x(2) < 0.0);
+
+        let smooth = SeparationKernel::Smooth;
+        assert_eq!(smooth.offset_weight(0.05 * 0.05, 0.05), 0.0);
+        assert!(smooth.offset_weight(0.01 * 0.01, 0.05) > smooth.offset_weight(0.0004, 0.05));
+    }
+
+    #[test]
+    fn distance_weighted_cohesion_favors_close_neighbors() {
+        let accel_x = |weighted: bool| {
+            let mut sim = Sim::new(3, 71, 1.0, 1.0);
+            sim.set_sep_weight(0.0);
+            sim.set_align_weight(0.0);
+            sim.set_jitter_strength(0.0);
+            sim.set_shape_attractor_weight(0.0);
+            sim.set_neighbor_radius(0.1);
+            sim.set_distance_weighted_cohesion(weighted);
+            // A close neighbor on the right and a far one on the left.
+            sim.pos_x[..3].copy_from_slice(&[0.5, 0.52, 0.41]);
+            sim.pos_y[..3].fill(0.5);
+            sim.vel_x[..3].fill(0.0);
+            sim.vel_y[..3].fill(0.0);
+            sim.step(1.0 / 60.0);
+            sim.accel_x[0]
+        };
+
+        assert!(
+            accel_x(false) < 0.0,
+            "plain mean lies toward the far neighbor"
+        );
+        assert!(
+            accel_x(true) > 0.0,
+            "weighted mean lies toward the close one"
+        );
+    }
+
+    #[test]
+    fn speed_matching_pulls_toward_mean_neighbor_speed() {
+        let mut sim = Sim::new(3, 73, 1.0, 1.0);
+        sim.set_sep_weight(0.0);
+        sim.set_align_weight(0.0);
+        sim.set_coh_weight(0.0);
+        sim.set_jitter_strength(0.0);
+        sim.set_shape_attractor_weight(0.0);
+        sim.set_neighbor_radius(0.1);
+        sim.set_speed_match_weight(1.0);
+        // A slow boid heading +x between fast neighbors heading +y and -y.
+        sim.pos_x[..3].copy_from_slice(&[0.5, 0.5, 0.5]);
+        sim.pos_y[..3].copy_from_slice(&[0.5, 0.53, 0.47]);
+        sim.vel_x[..3].copy_from_slice(&[0.05, 0.0, 0.0]);
+        sim.vel_y[..3].copy_from_slice(&[0.0, 0.2, -0.2]);
+        sim.step(1.0 / 60.0);
+
+        assert!(sim.accel_x[0] > 0.0, "the slow boid speeds up");
+        assert!(sim.accel_y[0].abs() < 1e-6, "without turning");
+        assert!(sim.accel_y[1] < 0.0, "the fast boids slow down");
+        assert!(sim.accel_y[2] > 0.0);
+    }
+
+    #[test]
+    fn wall_response_damps_or_absorbs_bounces() {
+        let bounce = |restitution: f32, friction: f32| {
+            let mut sim = Sim::new(1, 7, 1.0, 1.0);
+            sim.set_sep_weight(0.0);
+            sim.set_align_weight(0.0);
+            sim.set_coh_weight(0.0);
+            sim.set_jitter_strength(0.0);
+            sim.set_shape_attractor_weight(0.0);
+            sim.set_min_speed(0.0);
+            sim.set_axis_bounce(true, false, false);
+            sim.set_wall_response(restitution, friction);
+            sim.pos_x[0] = 0.01;
+            sim.vel_x[0] = -0.2;
+            sim.vel_y[0] = 0.1;
+            sim.step(0.1);
+            (sim.pos_x[0], sim.vel_x[0], sim.vel_y[0])
+        };
+
+        let (mirror_x, mirror_vx, mirror_vy) = bounce(1.0, 0.0);
+        let (damped_x, damped_vx, damped_vy) = bounce(0.5, 0.5);
+        assert!(damped_vx > 0.0 && damped_vx < mirror_vx * 0.75);
+        assert!(damped_x < mirror_x);
+        assert!(damped_vy < mirror_vy * 0.75);
+
+        let (parked_x, parked_vx, parked_vy) = bounce(0.0, 1.0);
+        assert_eq!(parked_x, 0.0);
+        assert_eq!(parked_vx, 0.0);
+        assert_eq!(parked_vy, 0.0);
+    }
+
+    #[test]
+    fn constraint_grid_spans_species_hard_min_radii() {
+        let mut sim = Sim::new(4, 79, 1.0, 1.0);
+        sim.set_hard_min_distance(0.01);
+        sim.set_species(&[0, 0, 1, 1]);
+        sim.set_species_hard_min_distance(1, 1, 0.08);
+        sim.pos_x[..4].copy_from_slice(&[0.2, 0.205, 0.6, 0.66]);
+        sim.pos_y[..4].fill(0.5);
+        sim.resolve_hard_min_distance_constraints();
+
+        // 0.01 to 0.08 takes all four doubling levels.
+        assert_eq!(sim.constraint_grid.level_for_radius(0.01), 0);
+        assert_eq!(sim.constraint_grid.level_for_radius(0.08), 3);
+        assert!(sim.pos_x[1] - sim.pos_x[0] > 0.005, "small radius resolved");
+        assert!(sim.pos_x[3] - sim.pos_x[2] > 0.06, "large radius resolved");
+    }
+
+    #[test]
+    fn species_pairs_can_override_hard_min_distance() {
+        let mut sim = Sim::new(3, 79, 1.0, 1.0);
+        sim.set_sep_weight(0.0);
+        sim.set_align_weight(0.0);
+        sim.set_coh_weight(0.0);
+        sim.set_jitter_strength(0.0);
+        sim.set_shape_attractor_weight(0.0);
+        sim.set_min_speed(0.0);
+        sim.set_hard_min_distance(0.05);
+        sim.set_species(&[0, 0, 1]);
+        sim.set_species_hard_min_distance(1, 0, 0.0);
+        assert_eq!(sim.species_hard_min_distance(0, 1), 0.0);
+        assert!((sim.species_hard_min_distance(0, 0) - 0.05).abs() < 1e-6);
+
+        // Two same-species boids and a predator, all on top of each other.
+        sim.pos_x[..3].copy_from_slice(&[0.5, 0.51, 0.505]);
+        sim.pos_y[..3].fill(0.5);
+        sim.vel_x[..3].fill(0.0);
+        sim.vel_y[..3].fill(0.0);
+        for _ in 0..120 {
+            sim.step(1.0 / 60.0);
+        }
+
+        let gap = |a: usize, b: usize| (sim.pos_x[a] - sim.pos_x[b]).abs();
+        assert!(gap(0, 1) > 0.045, "same species keep their spacing");
+        assert!(
+            (sim.pos_x[2] - 0.505).abs() < 1e-6,
+            "the predator is never pushed"
+        );
+        assert!(gap(0, 2) < 0.05 && gap(1, 2) < 0.05);
+
+        sim.clear_species_hard_min_distances();
+        assert!((sim.species_hard_min_distance(0, 1) - 0.05).abs() < 1e-6);
+    }
+
+    #[test]
+    fn depth_separated_stacks_skip_the_constraint_pass() {
+        let mut sim = Sim::new(24, 83, 1.0, 1.0);
+        sim.set_z_mode(true);
+        sim.set_sep_weight(0.0);
+        sim.set_align_weight(0.0);
+        sim.set_coh_weight(0.0);
+        sim.set_jitter_strength(0.0);
+        sim.set_shape_attractor_weight(0.0);
+        sim.set_min_speed(0.0);
+        sim.set_hard_min_distance(0.03);
+        // A column of boids sharing one xy position, spaced out in depth, plus
+        // one pair overlapping in depth at the bottom of the column.
+        for i in 0..24 {
+            sim.pos_x[i] = 0.5;
+            sim.pos_y[i] = 0.5;
+            sim.pos_z[i] = 0.02 + 0.04 * i as f32;
+            sim.vel_x[i] = 0.0;
+            sim.vel_y[i] = 0.0;
+            sim.vel_z[i] = 0.0;
+        }
+        sim.pos_z[1] = 0.03;
+        sim.step(1.0 / 60.0);
+
+        assert_eq!(sim.constraint_z_bins.len(), 24);
+        assert_eq!(sim.contact_count(), 1);
+        for i in 2..24 {
+            assert_eq!((sim.pos_x[i], sim.pos_y[i]), (0.5, 0.5));
+        }
+        assert!(sim.pos_z[1] - sim.pos_z[0] > 0.01);
+    }
+
+    #[test]
+    fn z_extent_keeps_boids_in_a_thin_slab() {
+        for bounce_z in [false, true] {
+            let mut sim = Sim::new(64, 89, 1.0, 1.0);
+            sim.set_z_extent(0.4, 0.6);
+            sim.set_z_mode(true);
+            sim.set_axis_bounce(false, false, bounce_z);
+            assert_eq!((sim.z_min(), sim.z_max()), (0.4, 0.6));
+            for i in 0..64 {
+                sim.vel_z[i] = if i % 2 == 0 { 0.3 } else { -0.3 };
+            }
+            for _ in 0..120 {
+                sim.step(1.0 / 60.0);
+                assert!(sim.pos_z[..64].iter().all(|z| (0.4..=0.6).contains(z)));
+            }
+        }
+
+        let extent = super::ZExtent { min: 0.4, max: 0.6 };
+        assert!((extent.delta(0.59 - 0.41, true) + 0.02).abs() < 1e-6);
+        assert!((extent.delta(0.59 - 0.41, false) - 0.18).abs() < 1e-6);
+        assert!((extent.project(0.65, false) - 0.45).abs() < 1e-6);
+
+        let mut sim = Sim::new(1, 1, 1.0, 1.0);
+        sim.set_z_extent(0.9, 0.2);
+        assert!((sim.z_min() - 0.9).abs() < 1e-6 && (sim.z_max() - 0.91).abs() < 1e-6);
+    }
+
+    #[test]
+    fn z_force_scales_apply_per_behavior() {
+        let accel_z = |offset: f32, scales: [f32; 4]| {
+            let mut sim = Sim::new(2, 97, 1.0, 1.0);
+            sim.set_z_mode(true);
+            sim.set_align_weight(0.0);
+            sim.set_jitter_strength(0.0);
+            sim.set_shape_attractor_weight(0.0);
+            sim.set_neighbor_radius(0.1);
+            sim.set_separation_radius(0.03);
+            sim.set_min_distance(0.0);
+            sim.set_hard_min_distance(0.0);
+            sim.set_z_force_scales(scales[0], scales[1], scales[2], scales[3]);
+            sim.pos_x[..2].fill(0.5);
+            sim.pos_y[..2].fill(0.5);
+            sim.pos_z[..2].copy_from_slice(&[0.5, 0.5 + offset]);
+            sim.vel_x[..2].fill(0.0);
+            sim.vel_y[..2].fill(0.0);
+            sim.vel_z[..2].fill(0.0);
+            sim.step(1.0 / 60.0);
+            sim.accel_z[0]
+        };
+
+        // A close neighbor above: separation pushes down only if scaled in.
+        assert!(accel_z(0.01, [0.0, 1.0, 0.0, 1.0]).abs() < 1e-6);
+        assert!(accel_z(0.01, [1.0, 1.0, 0.0, 1.0]) < 0.0);
+        // A farther neighbor above: cohesion alone pulls up.
+        assert!(accel_z(0.06, [0.0, 0.0, 1.0, 0.0]) > 0.0);
+        assert!(accel_z(0.06, [1.0, 1.0, 0.0, 1.0]).abs() < 1e-6);
+
+        let mut sim = Sim::new(1, 1, 1.0, 1.0);
+        sim.set_z_force_scale(0.5);
+        assert_eq!(sim.z_force_scale_separation(), 0.5);
+        assert_eq!(sim.z_force_scale_attractor(), 0.5);
+    }
+
+    #[test]
+    fn render_scale_combines_depth_species_and_boid_size() {
+        let mut sim = Sim::new(3, 101, 1.0, 1.0);
+        sim.step(1.0 / 60.0);
+        assert_eq!(sim.render_scale_len(), 3);
+        assert!(sim.render_scale.iter().all(|s| (s - 1.0).abs() < 1e-6));
+
+        sim.set_z_mode(true);
+        sim.set_species(&[0, 1, 1]);
+        sim.set_species_size(1, 2.0);
+        sim.set_boid_sizes(&[1.0, 1.0, 0.5]);
+        for (i, z) in [0.0, 0.5, 1.0].into_iter().enumerate() {
+            sim.pos_z[i] = z;
+            sim.vel_z[i] = 0.0;
+        }
+        // Setting the mapping re-syncs the render buffers immediately.
+        sim.set_render_depth_scale(0.5, 1.5);
+
+        let expected = [0.5, 2.0, 1.5];
+        for (scale, want) in sim.render_scale.iter().zip(expected) {
+            assert!((scale - want).abs() < 1e-5, "{scale} != {want}");
+        }
+    }
+
+    #[test]
+    fn perspective_projects_render_positions_by_depth() {
+        let mut sim = Sim::new(2, 103, 1.0, 1.0);
+        sim.set_z_mode(true);
+        sim.pos_x[..2].fill(0.75);
+        sim.pos_y[..2].fill(0.5);
+        sim.pos_z[..2].copy_from_slice(&[1.0, 0.0]);
+        sim.set_perspective(true, 90.0, 0.5, 1.5, 0.5, 0.5);
+        assert!(sim.perspective_enabled());
+
+        // A 90 degree fov puts the focal length at 0.5: the near boid keeps
+        // its offset from the centre and the far one shrinks to a third.
+        assert!((sim.render_xy[0] - 0.75).abs() < 1e-5);
+        assert!((sim.render_xy[2] - (0.5 + 0.25 / 3.0)).abs() < 1e-5);
+        assert!((sim.render_xy[1] - 0.5).abs() < 1e-5);
+        assert!((sim.render_scale[0] - 1.0).abs() < 1e-5);
+        assert!((sim.render_scale[1] - 1.0 / 3.0).abs() < 1e-5);
+        assert_eq!((sim.pos_x[0], sim.pos_x[1]), (0.75, 0.75));
+
+        sim.set_perspective(false, 90.0, 0.5, 1.5, 0.5, 0.5);
+        assert_eq!(sim.render_xy[2], 0.75);
+    }
+
+    #[test]
+    fn camera_writes_clip_positions_and_culls() {
+        let mut sim = Sim::new(3, 107, 1.0, 1.0);
+        sim.pos_x[..3].copy_from_slice(&[0.5, 0.9, 0.2]);
+        sim.pos_y[..3].copy_from_slice(&[0.5, 0.5, 0.5]);
+        // Maps [0.25, 0.75] in x and y onto clip space [-1, 1], with w = 1.
+        let mut view_proj = [0.0; 16];
+        view_proj[0] = 4.0;
+        view_proj[5] = 4.0;
+        view_proj[10] = 1.0;
+        view_proj[12] = -2.0;
+        view_proj[13] = -2.0;
+        view_proj[14] = -0.5;
+        view_proj[15] = 1.0;
+        sim.set_camera(&view_proj);
+        assert!(sim.camera_enabled());
+
+        let clip = &sim.camera.clip_xyzw;
+        assert_eq!(&clip[..4], &[0.0, 0.0, 0.0, 1.0]);
+        assert!((clip[4] - 1.6).abs() < 1e-5);
+        assert_eq!(sim.visible_count(), 1);
+        assert_eq!(sim.camera.visible[0], 0);
+
+        // A full-width margin keeps the boids just past the edges.
+        sim.set_camera_cull_margin(1.0);
+        sim.set_camera(&view_proj);
+        assert_eq!(sim.visible_count(), 3);
+        assert_eq!(sim.visible_indices_len(), 3);
+
+        sim.set_camera(&[]);
+        assert!(!sim.camera_enabled());
+        assert_eq!(sim.visible_count(), 0);
+    }
+
+    #[test]
+    fn visible_indices_match_a_linear_scan() {
+        let mut sim = Sim::new(300, 17, 1.0, 1.0);
+        for _ in 0..4 {
+            sim.step(1.0 / 60.0);
+        }
+        let rect = (0.2, 0.35, 0.55, 0.6);
+        let expected: Vec<u32> = (0..sim.active_count)
+            .filter(|&i| {
+                (rect.0..=rect.2).contains(&sim.pos_x[i])
+                    && (rect.1..=rect.3).contains(&sim.pos_y[i])
+            })
+            .map(|i| i as u32)
+            .collect();
+        assert!(!expected.is_empty());
+        assert_eq!(
+            sim.visible_indices(rect.0, rect.1, rect.2, rect.3),
+            expected
+        );
+
+        sim.step(1.0 / 60.0);
+        let all = sim.visible_indices(0.0, 0.0, 1.0, 1.0);
+        assert_eq!(all.len(), sim.active_count);
+        assert!(sim.visible_indices(0.6, 0.6, 0.4, 0.4).is_empty());
+    }
+
+    #[test]
+    fn color_map_samples_the_gradient_per_source() {
+        let mut sim = Sim::new(3, 5, 1.0, 1.0);
+        sim.set_color_gradient(&[0, 0, 0, 255, 200, 100, 50, 255]);
+        assert_eq!(sim.color_gradient_stops(), 2);
+
+        let max_speed = sim.max_speed();
+        sim.vel_x[..3].copy_from_slice(&[0.0, 0.5 * max_speed, max_speed]);
+        sim.vel_y[..3].fill(0.0);
+        sim.vel_z[..3].fill(0.0);
+        sim.set_color_source(1);
+        assert_eq!(&sim.color_map.rgba[..4], &[0, 0, 0, 255]);
+        assert_eq!(&sim.color_map.rgba[4..8], &[100, 50, 25, 255]);
+        assert_eq!(&sim.color_map.rgba[8..12], &[200, 100, 50, 255]);
+
+        sim.pos_x[..3].copy_from_slice(&[0.5, 0.51, 0.1]);
+        sim.pos_y[..3].copy_from_slice(&[0.5, 0.5, 0.1]);
+        sim.detect_clusters();
+        sim.set_color_source(3);
+        assert_eq!(&sim.color_map.rgba[..4], &[124, 62, 31, 255]);
+        assert_eq!(&sim.color_map.rgba[4..8], &[124, 62, 31, 255]);
+        assert_eq!(&sim.color_map.rgba[8..12], &[0, 0, 0, 255]);
+
+        sim.set_color_gradient(&[]);
+        assert_eq!(sim.color_gradient_stops(), 2);
+        sim.set_color_source(99);
+        assert_eq!(sim.color_source(), 0);
+    }
+
+    #[test]
+    fn external_scalars_multiply_jitter_or_cohesion_per_boid() {
+        let run = |scalars: &[f32], jitter: bool, cohesion: bool| {
+            let mut sim = Sim::new(16, 9, 1.0, 1.0);
+            sim.set_max_force(100.0);
+            sim.set_jitter_strength(0.05);
+            sim.set_steering_debug(2, 0);
+            sim.set_external_scalar_targets(jitter, cohesion);
+            sim.set_external_scalars(scalars);
+            sim.step(0.016);
+            sim.steering_debug.components
+        };
+        let base = run(&[], true, false);
+        let stirred = run(&[0.0, 3.0, f32::NAN], true, false);
+        let term = |components: &[f32], slot: usize, offset: usize| {
+            components[slot * STEERING_DEBUG_STRIDE + offset]
+        };
+        for axis in 0..2 {
+            assert_eq!(term(&stirred, 0, 9 + axis), 0.0);
+            let expected = term(&base, 1, 9 + axis) * 3.0;
+            assert!((term(&stirred, 1, 9 + axis) - expected).abs() < 1e-6);
+            assert_eq!(term(&stirred, 2, 9 + axis), term(&base, 2, 9 + axis));
+            assert_eq!(term(&stirred, 1, 6 + axis), term(&base, 1, 6 + axis));
+        }
+
+        let cohesive = run(&[1.0, 2.0], false, true);
+        for axis in 0..2 {
+            let expected = term(&base, 1, 6 + axis) * 2.0;
+            assert!((term(&cohesive, 1, 6 + axis) - expected).abs() < 1e-6);
+            assert_eq!(term(&cohesive, 1, 9 + axis), term(&base, 1, 9 + axis));
+        }
+    }
+
+    #[test]
+    fn behavior_states_follow_events() {
+        let mut sim = Sim::new(8, 21, 1.0, 1.0);
+        sim.set_behavior_enabled(true);
+        sim.step(0.016);
+        assert!((0..8).all(|i| sim.behavior_state(i) <= 1));
+
+        sim.set_behavior_goal(0.9, 0.9, 0.5);
+        sim.step(0.016);
+        assert!((0..8).all(|i| sim.behavior_state(i) == 3));
+
+        sim.pos_x[2] = 0.3;
+        sim.pos_y[2] = 0.3;
+        sim.command_landing(0.3, 0.3, 0.5, 0.02);
+        sim.step(0.016);
+        assert_eq!(sim.behavior_state(2), 4);
+        let perched_at = (sim.pos_x[2], sim.pos_y[2]);
+        for _ in 0..10 {
+            sim.step(0.016);
+        }
+        assert_eq!((sim.pos_x[2], sim.pos_y[2]), perched_at);
+        assert_eq!(sim.vel_x[2], 0.0);
+
+        sim.set_predators_xyz(&[0.31, 0.3, 0.5]);
+        sim.step(0.016);
+        assert_eq!(sim.behavior_state(2), 2);
+
+        sim.set_predators_xyz(&[]);
+        sim.command_takeoff();
+        sim.clear_behavior_goal();
+        sim.step(0.016);
+        assert!(sim.behavior_state(2) <= 1);
+        assert_ne!((sim.pos_x[2], sim.pos_y[2]), perched_at);
+    }
+
+    #[test]
+    fn role_fractions_assign_stable_roles_with_overrides() {
+        let mut sim = Sim::new(1000, 2, 1.0, 1.0);
+        sim.set_role_fraction(1, 0.3);
+        let scouts: Vec<usize> = (0..1000).filter(|&i| sim.boid_role(i) == 1).collect();
+        assert!((250..350).contains(&scouts.len()));
+        sim.set_role_fraction(1, 0.5);
+        assert!(scouts.iter().all(|&i| sim.boid_role(i) == 1));
+        sim.set_role_fraction(2, 0.9);
+        assert!((sim.role_fraction(2) - 0.5).abs() < 1e-6);
+        assert!(sim.role_fraction(0).abs() < 1e-6);
+        sim.set_role_fraction(0, 1.0);
+        assert!(sim.role_fraction(0).abs() < 1e-6);
+
+        let mut pair = Sim::new(2, 2, 1.0, 1.0);
+        pair.pos_x[..2].copy_from_slice(&[0.5, 0.62]);
+        pair.pos_y[..2].fill(0.5);
+        pair.set_steering_debug(1, 0);
+        pair.step(0.016);
+        assert!(pair.steering_debug.components[6..9]
+            .iter()
+            .all(|&c| c == 0.0));
+        pair.set_role_overrides(0, 1.75, 1.0, 1.0, 1.0);
+        pair.step(0.016);
+        assert!(pair.steering_debug.components[6..9]
+            .iter()
+            .any(|&c| c != 0.0));
+    }
+
+    #[test]
+    fn evolution_keeps_the_best_genome_and_reports_json() {
+        let settings = crate::evolve::EvolutionSettings {
+            seed: 4,
+            population: 6,
+            generations: 3,
+            steps: 40,
+            boid_count: 48,
+            polarization_weight: 1.0,
+            collision_weight: 0.5,
+            collision_distance: 0.0,
+        };
+        let result = crate::evolve::evolve_classic(settings);
+        assert_eq!(result.history.len(), 3);
+        assert!(result.history.windows(2).all(|w| w[1] >= w[0]));
+        assert_eq!(result.best_fitness, result.history[2]);
+        assert!(result.best.separation_radius <= result.best.neighbor_radius);
+
+        let json = result.to_json();
+        assert!(json.starts_with("{\"sepWeight\":"));
+        assert!(json.ends_with("]}"));
+        assert_eq!(crate::evolve::evolve_classic(settings).to_json(), json);
+    }
+
+    #[test]
+    fn objective_accumulates_every_interval() {
+        struct Polarized {
+            seen: Vec<u32>,
+        }
+        impl Objective for Polarized {
+            fn evaluate(&mut self, metrics: &ObjectiveMetrics) -> f32 {
+                self.seen.push(metrics.steps);
+                assert_eq!(metrics.active_count, 16);
+                if metrics.steps == 9 {
+                    f32::NAN
+                } else {
+                    metrics.polarization
+                }
+            }
+        }
+
+        let mut sim = Sim::new(16, 6, 1.0, 1.0);
+        sim.set_objective(Some(Box::new(Polarized { seen: Vec::new() })), 3);
+        let mut expected = 0.0;
+        for step in 1..=10 {
+            sim.step(0.016);
+            if step % 3 == 0 && step != 9 {
+                expected += sim.polarization();
+            }
+        }
+        assert_eq!(sim.objective_evaluations(), 3);
+        assert!((sim.objective_score() - expected).abs() < 1e-6);
+
+        sim.reset_objective_score();
+        sim.step(0.016);
+        assert_eq!(sim.objective_score(), 0.0);
+        sim.clear_objective();
+        assert!(!sim.has_objective());
+    }
+
+    #[test]
+    fn split_world_runs_each_half_with_its_own_config() {
+        let mut sim = Sim::new(400, 8, 1.0, 1.0);
+        sim.set_bounce_bounds(false);
+        sim.set_split_world(true);
+        assert_eq!(sim.split_config()[6], sim.max_speed());
+        sim.set_split_config(1.45, 1.0, 0.85, 0.08, 0.035, 0.02, 0.06, 0.42);
+        let left_side: Vec<bool> = sim.pos_x[..400].iter().map(|&x| x < 0.5).collect();
+        for _ in 0..120 {
+            sim.step(0.016);
+        }
+        assert!((0..400).all(|i| (sim.pos_x[i] < 0.5) == left_side[i]));
+        let left = sim.split_metrics(0);
+        let right = sim.split_metrics(1);
+        assert_eq!(left[0] + right[0], 400.0);
+        assert!(right[2] <= 0.06 + 1e-4);
+        assert!(left[2] > 0.06);
+        assert_eq!(sim.max_speed(), 0.19);
+
+        let mut pair = Sim::new(2, 8, 1.0, 1.0);
+        pair.pos_x[..2].copy_from_slice(&[0.49, 0.51]);
+        pair.pos_y[..2].fill(0.5);
+        pair.step(0.016);
+        assert!(pair.neighbors_visited_last_step() > 0);
+        pair.pos_x[..2].copy_from_slice(&[0.49, 0.51]);
+        pair.set_split_world(true);
+        pair.step(0.016);
+        assert_eq!(pair.neighbors_visited_last_step(), 0);
+    }
+
+    #[test]
+    fn mirror_reflects_render_copies_and_folds_into_a_quadrant() {
+        let mut sim = Sim::new(200, 9, 1.0, 1.0);
+        sim.set_bounce_bounds(false);
+        sim.set_mirror(true, false, false);
+        assert_eq!(sim.mirror_copies(), 2);
+        assert_eq!(sim.mirror_xy_len(), 2 * 200 * 2);
+        assert!(sim.pos_x[..200].iter().any(|&x| x > 0.5));
+
+        sim.set_mirror(true, true, true);
+        for _ in 0..60 {
+            sim.step(0.016);
+        }
+        assert!((0..200).all(|i| sim.pos_x[i] < 0.5 && sim.pos_y[i] < 0.5));
+        assert_eq!(sim.mirror_copies(), 4);
+        let n = 200;
+        for i in [0, 77, 199] {
+            let (x, y) = (sim.render_xy[2 * i], sim.render_xy[2 * i + 1]);
+            let (hx, hy) = (
+                sim.render_heading_xy[2 * i],
+                sim.render_heading_xy[2 * i + 1],
+            );
+            let xy = |copy: usize| {
+                let slot = 2 * (copy * n + i);
+                (sim.mirror.xy[slot], sim.mirror.xy[slot + 1])
+            };
+            assert_eq!(xy(0), (x, y));
+            assert_eq!(xy(1), (1.0 - x, y));
+            assert_eq!(xy(2), (x, 1.0 - y));
+            assert_eq!(xy(3), (1.0 - x, 1.0 - y));
+            let slot = 2 * (3 * n + i);
+            assert_eq!(
+                (sim.mirror.heading_xy[slot], sim.mirror.heading_xy[slot + 1]),
+                (-hx, -hy)
+            );
+        }
+
+        sim.set_mirror(false, false, false);
+        assert_eq!(sim.mirror_copies(), 1);
+        assert_eq!(sim.mirror_xy_len(), 0);
+    }
+
+    #[test]
+    fn kaleidoscope_keeps_boids_in_one_wedge_and_repeats_it() {
+        let wedge = std::f32::consts::TAU / 6.0;
+        let angle = |sim: &Sim, i: usize| (sim.pos_y[i] - 0.5).atan2(sim.pos_x[i] - 0.5);
+        let mut sim = Sim::new(300, 10, 1.0, 1.0);
+        sim.set_kaleidoscope_segments(6);
+        for _ in 0..90 {
+            sim.step(0.016);
+        }
+        assert!((0..300).all(|i| (-1e-4..wedge + 1e-4).contains(&angle(&sim, i))));
+        assert_eq!(sim.kaleidoscope_xy_len(), 2 * 300 * 6);
+        let i = 42;
+        let (x, y) = (sim.render_xy[2 * i] - 0.5, sim.render_xy[2 * i + 1] - 0.5);
+        let slot = 2 * (2 * 300 + i);
+        let copy = (
+            sim.kaleidoscope.xy[slot] - 0.5,
+            sim.kaleidoscope.xy[slot + 1] - 0.5,
+        );
+        let (sin, cos) = (2.0 * wedge).sin_cos();
+        assert!((copy.0 - (x * cos - y * sin)).abs() < 1e-5);
+        assert!((copy.1 - (x * sin + y * cos)).abs() < 1e-5);
+
+        // Leaving through the far edge comes back in through the near one.
+        let mut lone = Sim::new(1, 10, 1.0, 1.0);
+        lone.set_kaleidoscope_segments(6);
+        let (sin, cos) = (wedge - 0.01).sin_cos();
+        lone.pos_x[0] = 0.5 + 0.2 * cos;
+        lone.pos_y[0] = 0.5 + 0.2 * sin;
+        lone.vel_x[0] = -0.15 * sin;
+        lone.vel_y[0] = 0.15 * cos;
+        lone.step(0.05);
+        let after = angle(&lone, 0);
+        assert!((0.0..0.05).contains(&after));
+        assert!(lone.vel_y[0] > 0.0);
+        lone.set_kaleidoscope_segments(0);
+        assert_eq!(lone.kaleidoscope_xy_len(), 0);
+    }
+
+    #[test]
+    fn attractor_falloff_shapes_the_pull_by_distance() {
+        fn pull_at(sim: &mut Sim, distance: f32) -> f32 {
+            sim.pos_x[0] = 0.5 + distance;
+            sim.pos_y[0] = 0.5;
+            -sim.shape_attractor_force(0).0
+        }
+
+        let mut sim = Sim::new(1, 3, 1.0, 1.0);
+        sim.set_shape_points_xyz(&[0.5, 0.5, DEFAULT_Z_LAYER]);
+        sim.set_shape_attractor_weight(0.1);
+        assert!((pull_at(&mut sim, 0.3) - 0.1).abs() < 1e-6);
+
+        let cases = [
+            (1, 0.03, 0.0),
+            (1, 0.15, 0.05),
+            (1, 0.3, 0.0),
+            (2, 0.1, 0.05),
+            (3, 0.1, 0.025),
+            (4, 0.15, 0.05),
+            (4, 0.2, 0.015_625),
+        ];
+        for (profile, distance, expected) in cases {
+            sim.set_attractor_falloff(profile, 0.05, 0.25);
+            let pull = pull_at(&mut sim, distance);
+            assert!(
+                (pull - expected).abs() < 1e-5,
+                "profile {profile} at {distance}: {pull}"
+            );
+        }
+
+        sim.set_attractor_falloff(1, 0.2, 0.1);
+        assert_eq!(sim.attractor_max_radius(), 0.2);
+        sim.set_attractor_falloff(9, f32::NAN, -1.0);
+        assert_eq!(sim.attractor_falloff_profile(), 0);
+        assert_eq!(sim.attractor_dead_zone(), 0.0);
+        assert_eq!(sim.attractor_max_radius(), 0.0);
+    }
+
+    #[test]
+    fn danger_zones_drive_boids_out_and_report_occupancy() {
+        let mut sim = Sim::new(400, 12, 1.0, 1.0);
+        sim.set_bounce_bounds(true);
+        let circle = sim.add_danger_circle(0.5, 0.5, 0.15);
+        let rect = sim.add_danger_rect(0.9, 0.0, 0.7, 0.3);
+        assert_eq!((circle, rect), (0, 1));
+        assert_eq!(sim.add_danger_circle(0.2, 0.2, 0.0), -1);
+        sim.step(0.016);
+        let inside = sim.danger_zone_occupancies();
+        assert!(inside[0] > 10 && inside[1] > 10);
+        for _ in 0..240 {
+            sim.step(0.016);
+        }
+        assert!(sim.danger_zone_occupancy(0) * 4 < inside[0]);
+        assert!(sim.danger_zone_occupancy(1) * 4 < inside[1]);
+
+        assert!(sim.remove_danger_zone(0));
+        assert!(!sim.remove_danger_zone(0));
+        assert_eq!(sim.danger_zone_ids(), vec![1]);
+
+        let rect = crate::regions::Region::rect(0.2, 0.2, 0.4, 0.3);
+        let (distance, normal) = rect.signed_distance([0.0, 0.04]);
+        assert!((distance + 0.01).abs() < 1e-6);
+        assert_eq!(normal, [0.0, 1.0]);
+        let (distance, normal) = rect.signed_distance([0.13, 0.09]);
+        assert!((distance - 0.05).abs() < 1e-6);
+        assert!((normal[0] - 0.6).abs() < 1e-6 && (normal[1] - 0.8).abs() < 1e-6);
+    }
+
+    #[test]
+    fn safe_zones_keep_predators_out_and_calm_prey() {
+        let mut sim = Sim::new(1, 43, 1.0, 1.0);
+        sim.set_model_kind(1);
+        sim.set_predator_response(0.2, 6.0, 4.0, 0.5);
+        sim.pos_x[0] = 0.5;
+        sim.pos_y[0] = 0.5;
+        sim.set_predators_xyz(&[0.5, 0.45, 0.5]);
+        assert_eq!(sim.add_safe_circle(0.5, 0.5, 0.1), 0);
+        let predator = sim.predators_xyz();
+        assert!((predator[1] - 0.4).abs() < 1e-4, "{predator:?}");
+        assert!(sim.boid_in_safe_zone(0));
+
+        sim.set_safe_zone_panic_decay(4.0);
+        sim.step(1.0 / 60.0);
+        assert_eq!(sim.evasion_time_left(0), 0.5);
+        sim.set_predators_xyz(&[0.5, 0.45, 0.5]);
+        assert!(sim.predators_xyz()[1] <= 0.4);
+        sim.set_predators_xyz(&[]);
+        for _ in 0..5 {
+            sim.step(1.0 / 60.0);
+        }
+        assert!((sim.evasion_time_left(0) - (0.5 - 4.0 * 5.0 / 60.0)).abs() < 1e-3);
+
+        assert!(sim.remove_safe_zone(0));
+        assert_eq!(sim.safe_zone_count(), 0);
+        assert!(!sim.boid_in_safe_zone(0));
+    }
+
+    #[test]
+    fn moving_obstacles_are_dodged_ahead_of_their_sweep() {
+        let closest_approach = |announce_velocity: bool| {
+            let mut sim = Sim::new(1, 44, 1.0, 1.0);
+            sim.set_bounce_bounds(true);
+            sim.set_obstacle_avoidance(8.0, 4.0, 0.1);
+            sim.pos_x[0] = 0.5;
+            sim.pos_y[0] = 0.51;
+            sim.set_obstacles_xyzr(&[0.1, 0.5, 0.5, 0.05]);
+            let mut closest = f32::MAX;
+            for frame in 0..90 {
+                let x = 0.1 + 0.6 * frame as f32 / 60.0;
+                if announce_velocity {
+                    if frame == 0 {
+                        assert!(sim.update_obstacle(0, x, 0.5, 0.6, 0.0));
+                    }
+                } else {
+                    sim.update_obstacle(0, x, 0.5, 0.0, 0.0);
+                }
+                sim.step(1.0 / 60.0);
+                let obstacle = sim.obstacles_xyzr();
+                let (dx, dy) = (sim.pos_x[0] - obstacle[0], sim.pos_y[0] - obstacle[1]);
+                closest = closest.min((dx * dx + dy * dy).sqrt());
+            }
+            closest
+        };
+        let reactive = closest_approach(false);
+        let anticipating = closest_approach(true);
+        assert!(anticipating > reactive, "{anticipating} <= {reactive}");
+        assert!(anticipating > 0.05, "{anticipating}");
+
+        let mut sim = Sim::new(1, 44, 1.0, 1.0);
+        sim.set_obstacles_xyzr(&[0.5, 0.5, 0.5, 0.05]);
+        assert!(!sim.update_obstacle(1, 0.5, 0.5, 0.0, 0.0));
+    }
+
+    #[test]
+    fn predictive_avoidance_sidesteps_before_contact() {
+        let closest_clearance = |weight: f32| {
+            let mut sim = Sim::new(1, 45, 1.0, 1.0);
+            sim.set_bounce_bounds(true);
+            sim.set_obstacle_avoidance(4.0, 1.0, 0.01);
+            sim.set_predictive_avoidance(weight, 1.0, 0.03);
+            sim.set_obstacles_xyzr(&[0.6, 0.5, 0.5, 0.05]);
+            sim.pos_x[0] = 0.3;
+            sim.pos_y[0] = 0.505;
+            sim.vel_x[0] = 0.3;
+            sim.vel_y[0] = 0.0;
+            let mut closest = f32::MAX;
+            for _ in 0..90 {
+                sim.step(1.0 / 60.0);
+                let (dx, dy) = (sim.pos_x[0] - 0.6, sim.pos_y[0] - 0.5);
+                closest = closest.min((dx * dx + dy * dy).sqrt() - 0.05);
+            }
+            closest
+        };
+        let reactive = closest_clearance(0.0);
+        let predictive = closest_clearance(8.0);
+        assert!(predictive > reactive, "{predictive} <= {reactive}");
+        assert!(predictive > 0.0, "{predictive}");
+
+        let mut sim = Sim::new(1, 45, 1.0, 1.0);
+        sim.set_predictive_avoidance(6.0, 1.0, 0.0);
+        sim.set_predators_xyz(&[0.2, 0.5, 0.5]);
+        sim.set_predator_velocities_xyz(&[0.5, 0.0, 0.0]);
+        sim.pos_x[0] = 0.6;
+        sim.pos_y[0] = 0.51;
+        sim.vel_x[0] = 0.0;
+        sim.vel_y[0] = 0.0;
+        let (side, weight) = sim.predictive_avoidance(0).unwrap();
+        assert!(side[1] > 0.99 && weight > 0.0);
+    }
+
+    #[test]
+    fn pair_avoidance_steers_apart_before_contact() {
+        let closest_approach = |weight: f32| {
+            let mut sim = Sim::new(2, 46, 1.0, 1.0);
+            sim.set_bounce_bounds(true);
+            sim.set_sep_weight(0.0);
+            sim.set_align_weight(0.0);
+            sim.set_coh_weight(0.0);
+            sim.set_jitter_strength(0.0);
+            sim.set_pair_avoidance(weight, 2, 0.5, 0.02);
+            (sim.pos_x[0], sim.pos_y[0], sim.vel_x[0], sim.vel_y[0]) = (0.4, 0.5, 0.2, 0.0);
+            (sim.pos_x[1], sim.pos_y[1], sim.vel_x[1], sim.vel_y[1]) = (0.6, 0.502, -0.2, 0.0);
+            let mut closest = f32::MAX;
+            for _ in 0..60 {
+                sim.step(1.0 / 60.0);
+                let (dx, dy) = (sim.pos_x[0] - sim.pos_x[1], sim.pos_y[0] - sim.pos_y[1]);
+                closest = closest.min((dx * dx + dy * dy).sqrt());
+            }
+            closest
+        };
+        let unaware = closest_approach(0.0);
+        let predicted = closest_approach(10.0);
+        assert!(predicted > unaware + 0.005, "{predicted} vs {unaware}");
+
+        let mut nearest = crate::pair_avoidance::NearestNeighbors::new(2);
+        for (dist_sq, j) in [(0.5, 1), (0.2, 2), (0.9, 3), (0.1, 4)] {
+            nearest.offer(dist_sq, j);
+        }
+        let mut sim = Sim::new(5, 46, 1.0, 1.0);
+        sim.set_pair_avoidance(1.0, 2, 0.5, 0.1);
+        for j in 0..5 {
+            (sim.pos_x[j], sim.pos_y[j], sim.vel_x[j], sim.vel_y[j]) = (0.5, 0.5, 0.0, 0.0);
+        }
+        // Only the two nearest, 4 and 2, head for boid 0.
+        (sim.pos_x[4], sim.vel_x[4]) = (0.6, -0.5);
+        (sim.pos_x[2], sim.vel_x[2]) = (0.4, 0.5);
+        (sim.pos_x[1], sim.vel_x[1]) = (0.7, -0.5);
+        assert_ne!(sim.pair_avoidance_force(0, &nearest), [0.0; 3]);
+        (sim.vel_x[4], sim.vel_x[2]) = (0.0, 0.0);
+        assert_eq!(sim.pair_avoidance_force(0, &nearest), [0.0; 3]);
+    }
+
+    #[test]
+    fn corridors_keep_every_model_inside() {
+        for model in [0, 1] {
+            let mut sim = Sim::new(200, 47, 1.0, 1.0);
+            sim.set_model_kind(model);
+            let river = [0.1, 0.3, 0.5, 0.5, 0.9, 0.5];
+            assert_eq!(sim.add_corridor(&river, 0.1), 0);
+            assert_eq!(sim.add_corridor(&[0.5], 0.1), -1);
+            let inside = |sim: &Sim, i: usize| {
+                let (x, y) = (sim.pos_x[i], sim.pos_y[i]);
+                let depth = sim.corridor_offset(x, y).unwrap().1;
+                depth >= -1e-4
+            };
+            for _ in 0..120 {
+                sim.step(1.0 / 60.0);
+                assert!(
+                    (0..sim.active_count).all(|i| inside(&sim, i)),
+                    "model {model}"
+                );
+            }
+        }
+
+        let mut sim = Sim::new(1, 47, 1.0, 1.0);
+        sim.add_corridor(&[0.2, 0.5, 0.8, 0.5], 0.2);
+        sim.set_corridor_steering(5.0, 0.5);
+        sim.pos_x[0] = 0.5;
+        sim.pos_y[0] = 0.58;
+        let (fx, fy) = sim.corridor_force(0);
+        assert!(fx.abs() < 1e-6 && fy < -1.0, "{fx} {fy}");
+        sim.pos_y[0] = 0.52;
+        assert_eq!(sim.corridor_force(0), (0.0, 0.0));
+        assert!(sim.remove_corridor(0));
+        assert_eq!(sim.corridor_count(), 0);
+    }
+
+    #[test]
+    fn config_regions_blend_scales_across_their_edges() {
+        let mut sim = Sim::new(300, 48, 1.0, 1.0);
+        sim.set_bounce_bounds(true);
+        let fog = sim.add_config_rect(0.0, 0.1, 0.5, 0.9, 0.1);
+        let roost = sim.add_config_circle(0.75, 0.5, 0.1, 0.0);
+        assert_eq!((fog, roost), (0, 1));
+        assert!(sim.set_config_region_scales(0, 1.0, 1.0, 1.0, 1.0, 0.25));
+        assert!(sim.set_config_region_scales(1, 1.0, 1.0, 1.0, 3.0, 1.0));
+        assert!(!sim.set_config_region_scales(7, 1.0, 1.0, 1.0, 1.0, 1.0));
+
+        sim.pos_x[0] = 0.2;
+        sim.pos_y[0] = 0.5;
+        sim.pos_x[1] = 0.5;
+        sim.pos_y[1] = 0.5;
+        sim.pos_x[2] = 0.75;
+        sim.pos_y[2] = 0.5;
+        assert_eq!(sim.boid_config_scales(0), vec![1.0, 1.0, 1.0, 1.0, 0.25]);
+        let edge = sim.boid_config_scales(1);
+        assert!((edge[4] - 0.625).abs() < 1e-5, "{edge:?}");
+        assert_eq!(sim.boid_config_scales(2)[3], 3.0);
+
+        for _ in 0..60 {
+            sim.step(1.0 / 60.0);
+        }
+        let max_speed = sim.max_speed();
+        let slow = (0..sim.active_count)
+            .filter(|&i| (0.1..0.4).contains(&sim.pos_x[i]) && (0.2..0.8).contains(&sim.pos_y[i]))
+            .map(|i| (sim.vel_x[i].powi(2) + sim.vel_y[i].powi(2)).sqrt())
+            .fold(0.0f32, f32::max);
+        assert!(slow <= max_speed * 0.25 + 1e-4, "{slow}");
+    }
+
+    #[test]
+    fn terrain_floor_holds_boids_above_the_heightmap() {
+        let ridge = [0.2, 0.6, 0.2, 0.2, 0.6, 0.2];
+        for model in [0, 1, 3] {
+            let mut sim = Sim::new(200, 49, 1.0, 1.0);
+            sim.set_model_kind(model);
+            sim.set_z_mode(true);
+            assert!(!sim.set_terrain_heightmap(3, 3, &ridge));
+            assert!(sim.set_terrain_heightmap(3, 2, &ridge));
+            for _ in 0..120 {
+                sim.step(1.0 / 60.0);
+                for i in 0..sim.active_count {
+                    let floor = sim.terrain_height(sim.pos_x[i], sim.pos_y[i]);
+                    assert!(sim.pos_z[i] >= floor - 1e-5, "model {model}");
+                }
+            }
+        }
+
+        let mut sim = Sim::new(1, 49, 1.0, 1.0);
+        sim.set_terrain_heightmap(3, 2, &ridge);
+        assert!((sim.terrain_height(0.25, 0.5) - 0.4).abs() < 1e-6);
+        assert!((sim.terrain_height(0.5, 0.9) - 0.6).abs() < 1e-6);
+        assert_eq!(sim.terrain_lift(0), 0.0);
+        sim.set_z_mode(true);
+        sim.set_terrain_avoidance(5.0, 0.1);
+        (sim.pos_x[0], sim.pos_y[0], sim.pos_z[0]) = (0.5, 0.5, 0.65);
+        (sim.vel_x[0], sim.vel_y[0]) = (0.0, 0.0);
+        assert!((sim.terrain_lift(0) - 2.5).abs() < 1e-4);
+        sim.clear_terrain();
+        assert!(!sim.has_terrain());
+    }
+
+    #[test]
+    fn depth_speed_scale_slows_far_boids() {
+        for model in [0, 3] {
+            let mut sim = Sim::new(200, 50, 1.0, 1.0);
+            sim.set_model_kind(model);
+            sim.set_z_mode(true);
+            sim.set_depth_speed_scale(0.25, 1.0);
+            assert_eq!(sim.depth_speed_scale_far(), 0.25);
+            let max_speed = if model == 0 {
+                sim.max_speed()
+            } else {
+                sim.flock2_config.max_speed
+            };
+            for _ in 0..30 {
+                let limits: Vec<f32> = (0..sim.active_count)
+                    .map(|i| max_speed * sim.depth_speed_scale(i))
+                    .collect();
+                sim.step(1.0 / 60.0);
+                for (i, limit) in limits.into_iter().enumerate() {
+                    let speed =
+                        (sim.vel_x[i].powi(2) + sim.vel_y[i].powi(2) + sim.vel_z[i].powi(2)).sqrt();
+                    assert!(
+                        speed <= limit * 1.001 + 1e-5,
+                        "model {model}: {speed} > {limit}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn variation_follows_ids_through_compaction_and_growth() {
+        let mut sim = Sim::new(8, 52, 1.0, 1.0);
+        let start = sim.variation.clone();
+        assert!(start.iter().all(|v| (0.0..1.0).contains(v)));
+        assert!(start.windows(2).any(|pair| pair[0] != pair[1]));
+
+        sim.set_active_indices(&[6, 2, 4]);
+        for id in 0..8u32 {
+            let slot = sim.boid_slot(id).unwrap() as usize;
+            assert_eq!(sim.boid_variation(slot), start[id as usize]);
+        }
+
+        sim.reserve(12);
+        assert_eq!(sim.variation_len(), 12);
+        let grown = Sim::new(12, 52, 1.0, 1.0);
+        assert_eq!(&sim.variation[8..], &grown.variation[8..]);
+
+        sim.set_variation_seed(53);
+        assert_ne!(sim.variation[8..], grown.variation[8..]);
+    }
+
+    #[test]
+    fn handles_survive_reordering_and_go_stale_on_despawn() {
+        let mut sim = Sim::with_capacity(8, 54, 1.0, 1.0);
+        let handles: Vec<u32> = (0..5).map(|_| sim.spawn().unwrap()).collect();
+        assert_eq!(sim.active_count(), 5);
+        let tracked_x = sim.pos_x[sim.handle_slot(handles[1]).unwrap() as usize];
+
+        assert!(sim.despawn(handles[0]));
+        assert!(!sim.despawn(handles[0]));
+        assert!(!sim.handle_is_live(handles[0]));
+        assert_eq!(sim.handle_slot(handles[0]), None);
+        assert_eq!(sim.active_count(), 4);
+        let slot = sim.handle_slot(handles[4]).unwrap();
+        assert_eq!(slot, 0);
+        assert_eq!(sim.boid_handle(slot as usize), Some(handles[4]));
+
+        sim.set_active_indices(&[3, 2, 1]);
+        let slot = sim.handle_slot(handles[1]).unwrap() as usize;
+        assert_eq!(sim.pos_x[slot], tracked_x);
+
+        // Respawning the despawned boid's storage hands out a new handle.
+        sim.set_active_count(4);
+        let respawned = sim.spawn().unwrap();
+        assert_ne!(respawned & 0x3F_FFFF, respawned);
+        assert!(sim.handle_is_live(respawned));
+        while sim.spawn().is_some() {}
+        assert_eq!(sim.active_count(), 8);
+        assert!(!sim.handle_is_live(handles[0]));
+        assert!(handles[1..]
+            .iter()
+            .all(|&handle| sim.handle_is_live(handle)));
+    }
+
+    #[test]
+    fn math_verification_reports_divergence_per_path() {
+        let mut sim = Sim::new(100, 55, 1.0, 1.0);
+        sim.set_path_math_mode(0, 1);
+        sim.set_path_math_mode(9, 2);
+        assert_eq!(sim.math_mode(), 1);
+        assert_eq!(sim.path_math_mode(1), 0);
+        sim.set_math_verification(true);
+        for _ in 0..10 {
+            sim.step(1.0 / 60.0);
+        }
+        let divergence = sim.math_divergence();
+        assert!(
+            divergence[0] > 0.0 && divergence[0] < 1.0e-2,
+            "{divergence:?}"
+        );
+        assert_eq!(&divergence[1..], &[0.0, 0.0]);
+
+        sim.set_model_kind(1);
+        sim.set_math_mode(2);
+        sim.reset_math_divergence();
+        for _ in 0..10 {
+            sim.step(1.0 / 60.0);
+        }
+        let divergence = sim.math_divergence();
+        assert!(divergence[0] > 0.0 && divergence[2] > 0.0, "{divergence:?}");
+        assert!(divergence.iter().all(|&gap| gap < 1.0e-2), "{divergence:?}");
+    }
+
+    #[test]
+    fn hard_min_resolver_follows_the_constraints_math_mode() {
+        let mut sim = Sim::new(16, 3, 1.0, 1.0);
+        sim.set_hard_min_distance(0.05);
+        sim.set_overlap_metrics_enabled(true);
+        for i in 0..16 {
+            sim.pos_x[i] = 0.5 + 0.003 * i as f32;
+            sim.pos_y[i] = 0.5 + 0.002 * (i % 3) as f32;
+        }
+        sim.set_path_math_mode(1, 1);
+        sim.set_math_verification(true);
+        sim.resolve_hard_min_distance_constraints();
+        let divergence = sim.math_divergence();
+        assert!(divergence[1] > 0.0, "{divergence:?}");
+        assert_eq!(divergence[0], 0.0);
+    }
+
+    #[test]
+    fn flock2_normalization_follows_the_math_mode() {
+        let mut sim = Sim::new(100, 57, 1.0, 1.0);
+        sim.set_model_kind(3);
+        sim.set_math_mode(1);
+        sim.set_math_verification(true);
+        for _ in 0..10 {
+            sim.step(1.0 / 60.0);
+        }
+        let divergence = sim.math_divergence();
+        assert!(divergence[0] > 0.0 && divergence[2] > 0.0, "{divergence:?}");
+        assert!(divergence.iter().all(|&gap| gap < 1.0e-2), "{divergence:?}");
+    }
+
+    #[test]
+    fn model_switches_reseed_only_across_unit_families() {
+        let mut sim = Sim::new(16, 37, 1.0, 1.0);
+        sim.step(1.0 / 60.0);
+        let classic = sim.vel_x.clone();
+        sim.set_model_kind(5);
+        assert_eq!(sim.vel_x, classic);
+
+        sim.set_model_kind(1);
+        let flock2 = sim.vel_x.clone();
+        assert_ne!(flock2, classic);
+        let max_speed = sim.flock2_config.max_speed;
+        assert!((0..16).all(|i| {
+            let speed = (sim.vel_x[i].powi(2) + sim.vel_y[i].powi(2)).sqrt();
+            speed <= max_speed * 1.001
+        }));
+        sim.set_model_kind(4);
+        assert_eq!(sim.vel_x, flock2);
+    }
+
+    #[test]
+    fn set_boids_writes_selected_channels_for_listed_slots() {
+        use crate::boid_writes::{BOID_WRITE_HEADING, BOID_WRITE_POSITION, BOID_WRITE_VELOCITY};
+
+        let mut sim = Sim::new(16, 56, 1.0, 1.0);
+        let untouched = (sim.pos_x[1], sim.vel_x[1]);
+        let layout = BOID_WRITE_POSITION | BOID_WRITE_VELOCITY;
+        let data = [
+            0.25, 0.5, 0.0, 0.0, 0.2, 0.0, // slot 3
+            1.25, -0.5, 0.0, 0.1, 0.0, 0.0, // slot 99, skipped
+            0.75, 0.125, 0.0, -0.3, 0.0, 0.0, // slot 0
+        ];
+        assert_eq!(sim.set_boids(&[3, 99, 0, 7], &data, layout), 2);
+        assert_eq!((sim.pos_x[3], sim.pos_y[3]), (0.25, 0.5));
+        assert_eq!((sim.vel_x[0], sim.heading_x[0]), (-0.3, -1.0));
+        assert_eq!(sim.heading_y[3], 1.0);
+        assert_eq!(&sim.render_xy[6..8], &[0.25, 0.5]);
+        assert_eq!((sim.pos_x[1], sim.vel_x[1]), untouched);
+
+        sim.set_bounce_bounds(true);
+        let (velocity, heading) = (sim.vel_x[5], sim.heading_x[5]);
+        sim.set_boids(
+            &[5],
+            &[2.0, f32::NAN, 0.0, 0.0, 0.0, 3.0],
+            BOID_WRITE_POSITION | BOID_WRITE_HEADING,
+        );
+        assert_eq!(sim.pos_x[5], 1.0);
+        assert!(sim.pos_y[5].is_finite());
+        assert_eq!(sim.vel_x[5], velocity);
+        // Outside z mode that heading is zero, so the old one stays.
+        assert_eq!(sim.heading_x[5], heading);
+        assert_eq!(sim.set_boids(&[5], &[0.5; 3], 0), 0);
+    }
+
+    #[test]
+    fn step_report_bundles_the_frame_counters() {
+        let mut sim = Sim::with_capacity(64, 57, 1.0, 1.0);
+        let handles: Vec<u32> = (0..40).map(|_| sim.spawn().unwrap()).collect();
+        assert!(sim.despawn(handles[3]));
+        sim.set_bounce_bounds(true);
+        sim.step(1.0 / 60.0);
+
+        let report = sim.step_report();
+        assert_eq!((report.spawned(), report.despawned()), (40, 1));
+        assert_eq!(
+            report.neighbors_visited(),
+            sim.neighbors_visited_last_step()
+        );
+        assert_eq!(report.boundary_hits_x(), sim.boundary_hits_x());
+        assert_eq!(report.contact_count(), sim.contact_count());
+        assert_eq!(report.simulated_dt(), 1.0 / 60.0);
+
+        sim.step(1.0 / 60.0);
+        let report = sim.step_report();
+        assert_eq!((report.spawned(), report.despawned()), (0, 0));
+    }
+
+    #[test]
+    fn heading_smoothing_calms_render_headings_only() {
+        let run = |time_constant_s: f32| {
+            let mut sim = Sim::new(64, 58, 1.0, 1.0);
+            sim.set_jitter_strength(0.5);
+            sim.set_heading_smoothing(time_constant_s);
+            let mut turning = 0.0;
+            for _ in 0..60 {
+                let before = sim.render_heading_xy.clone();
+                sim.step(1.0 / 60.0);
+                for (i, pair) in before.chunks_exact(2).enumerate() {
+                    let after = &sim.render_heading_xy[2 * i..2 * i + 2];
+                    turning += (pair[0] * after[1] - pair[1] * after[0]).abs();
+                }
+                for pair in sim.render_heading_xy.chunks_exact(2) {
+                    assert!((pair[0].hypot(pair[1]) - 1.0).abs() < 1e-4);
+                }
+            }
+            (turning, sim.state_hash())
+        };
+        let (raw_turning, raw_hash) = run(0.0);
+        let (smooth_turning, smooth_hash) = run(0.2);
+        assert_eq!(raw_hash, smooth_hash);
+        assert!(
+            smooth_turning < raw_turning * 0.8,
+            "{smooth_turning} vs {raw_turning}"
+        );
+    }
+
+    #[test]
+    fn typed_configs_apply_with_reports() {
+        let mut sim = Sim::new(20, 61, 1.0, 1.0);
+        let mut config = sim.config();
+        config.neighbor_radius = 0.12;
+        config.max_force = f32::NAN;
+        config.set_drag_model_name("quadratic");
+        config.set_separation_kernel_name("smooth");
+        config.set_math_mode_names(vec!["fast".into(), "bogus".into(), "lut".into()]);
+        let report = sim.apply_config(&config);
+        assert_eq!(report.len(), 1);
+        assert_eq!(report[0].field_name(), "max_force");
+        assert_eq!(sim.config.max_force, DEFAULT_MAX_FORCE);
+        assert_eq!(sim.neighbor_grid.cell_size(), 0.12);
+        assert_eq!(sim.drag_model(), 2);
+        assert_eq!(sim.separation_kernel(), 3);
+        assert_eq!(
+            sim.config().math_mode_names(),
+            vec!["fast", "accurate", "lut"]
+        );
+
+        let report = sim.apply_flock2_config(&Flock2Config {
+            topological_neighbors: 0,
+            mass: 9.0,
+            ..sim.flock2_config()
+        });
+        let fields: Vec<_> = report.iter().map(|entry| entry.field_name()).collect();
+        assert_eq!(fields, vec!["topological_neighbors", "mass"]);
+        assert_eq!(sim.flock2_config().mass, 5.0);
+        // The classic model still queries with its own radius.
+        assert_eq!(sim.neighbor_grid.cell_size(), 0.12);
+        sim.set_model_kind(1);
+        assert_eq!(sim.neighbor_grid.cell_size(), 0.1);
+
+        sim.set_model_kind(0);
+        config.far_field_opening = 0.5;
+        sim.apply_config(&config);
+        assert_eq!(
+            sim.neighbor_grid.cell_size(),
+            0.12 * FAR_FIELD_CELL_FRACTION
+        );
+    }
+
+    #[test]
+    fn exclusion_sphere_keeps_every_model_out() {
+        for model in [0, 1, 3, 5] {
+            for z_mode in [false, true] {
+                let mut sim = Sim::new(200, 67, 1.0, 1.0);
+                sim.set_model_kind(model);
+                sim.set_z_mode(z_mode);
+                sim.set_exclusion_sphere(0.5, 0.5, 0.5, 0.2);
+                for _ in 0..120 {
+                    sim.step(1.0 / 60.0);
+                    for i in 0..sim.active_count {
+                        let dz = if z_mode { sim.pos_z[i] - 0.5 } else { 0.0 };
+                        let (dx, dy) = (sim.pos_x[i] - 0.5, sim.pos_y[i] - 0.5);
+                        let dist = (dx * dx + dy * dy + dz * dz).sqrt();
+                        assert!(dist >= 0.2 - 1e-4, "model {model} boid {i} at {dist}");
+                    }
+                }
+            }
+        }
+
+        let mut sim = Sim::new(1, 71, 1.0, 1.0);
+        sim.set_exclusion_sphere(0.5, 0.5, 0.5, 0.1);
+        sim.pos_x[0] = 0.35;
+        sim.pos_y[0] = 0.5;
+        assert!(sim.exclusion_steer(0).is_some_and(|(away, weight)| {
+            (away[0] + 1.0).abs() < 1e-5 && (weight - 4.0 * (1.0 - 0.05 / 0.08)).abs() < 1e-4
+        }));
+        sim.pos_x[0] = 0.2;
+        assert!(sim.exclusion_steer(0).is_none());
+        sim.clear_exclusion_sphere();
+        assert_eq!(sim.exclusion_sphere()[3], 0.0);
+    }
+
+    #[test]
+    fn burst_and_coast_pulses_speed() {
+        // Mean speed change per step while coasting and while bursting.
+        let speed_changes = |model: u32, strength: f32| {
+            let mut sim = Sim::new(1, 73, 1.0, 1.0);
+            sim.set_model_kind(model);
+            sim.set_burst_coast(1.0, 0.3, strength);
+            let speed = |sim: &Sim| (sim.vel_x[0].powi(2) + sim.vel_y[0].powi(2)).sqrt();
+            let mut sums = [(0.0, 0); 2];
+            for _ in 0..240 {
+                let bursting = sim.boid_bursting(0);
+                let before = speed(&sim);
+                sim.step(1.0 / 60.0);
+                let entry = &mut sums[usize::from(bursting)];
+                *entry = (entry.0 + speed(&sim) - before, entry.1 + 1);
+            }
+            sums.map(|(sum, steps)| (sum / steps.max(1) as f32, steps))
+        };
+
+        for model in [0, 2, 4] {
+            let [(_, steady_coast), (_, steady_bursts)] = speed_changes(model, 0.0);
+            assert_eq!((steady_coast, steady_bursts), (240, 0));
+            let [(coast_dv, _), (burst_dv, bursts)] = speed_changes(model, 2.0);
+            assert!(
+                burst_dv > coast_dv,
+                "model {model}: {burst_dv} vs {coast_dv}"
+            );
+            // Four cycles at 30 percent duty.
+            assert!(
+                (55..=90).contains(&bursts),
+                "model {model}: {bursts} burst steps"
+            );
+        }
+
+        let mut sim = Sim::new(1, 73, 1.0, 1.0);
+        sim.set_burst_coast(f32::NAN, 2.0, -1.0);
+        assert_eq!(
+            (sim.burst_period(), sim.burst_duty(), sim.burst_strength()),
+            (1.0, 0.95, 0.0)
+        );
+    }
+
+    #[test]
+    fn turn_noise_turns_neighbors_together() {
+        let mut sim = Sim::new(32, 79, 1.0, 1.0);
+        sim.set_turn_noise(1.0, 0.5, 2.0, 5);
+        let mut noise_at = |x: f32, y: f32| {
+            sim.pos_x[0] = x;
+            sim.pos_y[0] = y;
+            sim.turn_noise(0).0
+        };
+        assert!((noise_at(0.3, 0.3) - noise_at(0.31, 0.3)).abs() < 0.05);
+        assert!((noise_at(0.0, 0.7) - noise_at(1.0, 0.7)).abs() < 1e-5);
+
+        sim.apply_config(&SimConfig {
+            sep_weight: 0.0,
+            align_weight: 0.0,
+            coh_weight: 0.0,
+            jitter_strength: 0.0,
+            ..sim.config()
+        });
+        for i in 0..32 {
+            sim.pos_x[i] = 0.4 + (i % 8) as f32 * 0.005;
+            sim.pos_y[i] = 0.4 + (i / 8) as f32 * 0.005;
+            (sim.vel_x[i], sim.vel_y[i]) = (0.1, 0.0);
+        }
+        for _ in 0..90 {
+            sim.step(1.0 / 60.0);
+        }
+        let angles: Vec<f32> = (0..32).map(|i| sim.vel_y[i].atan2(sim.vel_x[i])).collect();
+        let (low, high) = angles
+            .iter()
+            .fold((f32::MAX, f32::MIN), |(lo, hi), &a| (lo.min(a), hi.max(a)));
+        let mean = angles.iter().sum::<f32>() / 32.0;
+        assert!(mean.abs() > 0.2, "the cluster did not turn: {mean}");
+        assert!(
+            high - low < mean.abs() * 0.25,
+            "turns diverged: {low}..{high}"
+        );
+    }
+
+    #[test]
+    fn neighbor_hysteresis_reduces_topological_churn() {
+        let churn = |margin: f32| {
+            let mut sim = Sim::new(200, 83, 1.0, 1.0);
+            sim.set_model_kind(1);
+            sim.apply_flock2_config(&Flock2Config {
+                topological_neighbors: 4,
+                ..sim.flock2_config()
+            });
+            sim.set_flock2_neighbor_hysteresis(margin);
+            let sets = |sim: &Sim| -> Vec<Vec<u32>> {
+                (0..200)
+                    .map(|slot| {
+                        let mut ids = sim.flock2_remembered_neighbors(slot);
+                        ids.sort_unstable();
+                        ids
+                    })
+                    .collect()
+            };
+            for _ in 0..240 {
+                sim.step(1.0 / 60.0);
+            }
+            let mut previous = sets(&sim);
+            let mut changes = 0;
+            for _ in 0..120 {
+                sim.step(1.0 / 60.0);
+                let current = sets(&sim);
+                changes += previous
+                    .iter()
+                    .zip(&current)
+                    .filter(|(a, b)| a != b)
+                    .count();
+                previous = current;
+            }
+            changes
+        };
+        let loose = churn(1e-4);
+        let sticky = churn(0.5);
+        assert!(loose > 0);
+        assert!(sticky * 4 < loose * 3, "{sticky} vs {loose} set changes");
+
+        let mut sim = Sim::new(8, 1, 1.0, 1.0);
+        sim.set_flock2_neighbor_hysteresis(3.0);
+        assert_eq!(sim.flock2_neighbor_hysteresis(), 1.0);
+        sim.set_flock2_neighbor_hysteresis(f32::NAN);
+        assert_eq!(sim.flock2_neighbor_hysteresis(), 0.0);
+        sim.set_model_kind(1);
+        sim.step(1.0 / 60.0);
+        assert!(sim.flock2_remembered_neighbors(0).is_empty());
+    }
+
+    #[test]
+    fn wrap_ghosts_copy_edge_boids_across() {
+        let mut sim = Sim::new(3, 89, 1.0, 1.0);
+        sim.set_axis_bounce(false, false, false);
+        let place = |sim: &mut Sim| {
+            for (i, (x, y)) in [(0.02, 0.5), (0.5, 0.5), (0.99, 0.03)]
+                .into_iter()
+                .enumerate()
+            {
+                (sim.pos_x[i], sim.pos_y[i]) = (x, y);
+            }
+        };
+        place(&mut sim);
+        sim.set_wrap_ghost_margin(0.05);
+        assert_eq!(sim.wrap_ghost_count(), 4);
+        assert_eq!(sim.wrap_ghosts.source, vec![0, 2, 2, 2]);
+        assert!((sim.wrap_ghosts.xy[0] - 1.02).abs() < 1e-6);
+        let corner = &sim.wrap_ghosts.xy[2..];
+        assert!((corner[0] + 0.01).abs() < 1e-6 && (corner[1] - 0.03).abs() < 1e-6);
+        assert!((corner[4] + 0.01).abs() < 1e-6 && (corner[5] - 1.03).abs() < 1e-6);
+        assert_eq!(
+            &sim.wrap_ghosts.heading_xy[..2],
+            &sim.render_heading_xy[..2]
+        );
+
+        sim.set_axis_bounce(true, false, false);
+        place(&mut sim);
+        sim.set_wrap_ghost_margin(0.05);
+        assert_eq!(sim.wrap_ghosts.source, vec![2]);
+
+        sim.set_wrap_ghost_margin(f32::NAN);
+        assert_eq!(sim.wrap_ghost_count(), 0);
+        sim.set_wrap_ghost_margin(1.0);
+        assert_eq!(sim.wrap_ghost_margin(), 0.25);
+    }
+
+    #[test]
+    fn familiarity_bonds_build_decay_and_favor_flockmates() {
+        let mut sim = Sim::new(3, 97, 1.0, 1.0);
+        sim.set_z_mode(false);
+        sim.apply_config(&SimConfig {
+            sep_weight: 0.0,
+            align_weight: 0.0,
+            coh_weight: 1.0,
+            jitter_strength: 0.0,
+            ..sim.config()
+        });
+        sim.set_familiarity(2.0, 0.5, 5.0);
+        let place = |sim: &mut Sim, xs: [f32; 3]| {
+            for (i, x) in xs.into_iter().enumerate() {
+                (sim.pos_x[i], sim.pos_y[i]) = (x, 0.5);
+                (sim.vel_x[i], sim.vel_y[i]) = (0.0, 0.0);
+            }
+        };
+        for _ in 0..120 {
+            place(&mut sim, [0.5, 0.48, 0.9]);
+            sim.step(1.0 / 60.0);
+        }
+        assert!(sim.familiarity_bond(0, 1) > 0.95);
+        assert_eq!(sim.familiarity_bond(0, 2), 0.0);
+
+        // Equidistant neighbors on either side: the familiar one pulls harder.
+        place(&mut sim, [0.5, 0.48, 0.52]);
+        sim.step(1.0 / 60.0);
+        assert!(sim.accel_x[0] < -1e-4, "{}", sim.accel_x[0]);
+
+        for _ in 0..60 {
+            place(&mut sim, [0.5, 0.2, 0.9]);
+            sim.step(1.0 / 60.0);
+        }
+        let bond = sim.familiarity_bond(0, 1);
+        assert!(bond > 0.75 && bond < 0.85, "{bond}");
+
+        sim.set_familiarity(9.0, 0.0, f32::INFINITY);
+        assert_eq!(sim.familiarity_boost(), 4.0);
+        assert_eq!(sim.familiarity_build_time(), 0.1);
+        assert_eq!(sim.familiarity_decay_time(), 30.0);
+        sim.set_familiarity(0.0, 1.0, 1.0);
+        assert_eq!(sim.familiarity_bond(0, 1), 0.0);
+    }
+
+    #[test]
+    fn accel_ramp_limits_force_change_per_step() {
+        let dt = 1.0 / 60.0;
+        let largest_jump = |rate: f32| {
+            let mut sim = Sim::new(64, 101, 1.0, 1.0);
+            sim.set_accel_ramp(rate);
+            sim.apply_config(&SimConfig {
+                coh_weight: 0.0,
+                ..sim.config()
+            });
+            for _ in 0..30 {
+                sim.step(dt);
+            }
+            let before: Vec<[f32; 3]> = (0..64)
+                .map(|i| [sim.accel_x[i], sim.accel_y[i], sim.accel_z[i]])
+                .collect();
+            sim.apply_config(&SimConfig {
+                coh_weight: 8.0,
+                ..sim.config()
+            });
+            sim.step(dt);
+            let jump = (0..64)
+                .map(|i| {
+                    let [x, y, z] = before[i];
+                    let (dx, dy, dz) = (sim.accel_x[i] - x, sim.accel_y[i] - y, sim.accel_z[i] - z);
+                    (dx * dx + dy * dy + dz * dz).sqrt()
+                })
+                .fold(0.0, f32::max);
+            (jump, sim.config().max_force)
+        };
+        let (ramped, max_force) = largest_jump(2.0);
+        assert!(ramped <= 2.0 * max_force * dt * 1.001, "{ramped}");
+        let (free, _) = largest_jump(0.0);
+        assert!(free > 4.0 * max_force * dt, "{free}");
+
+        let mut sim = Sim::new(4, 1, 1.0, 1.0);
+        sim.set_accel_ramp(500.0);
+        assert_eq!(sim.accel_ramp(), 100.0);
+        sim.set_accel_ramp(-1.0);
+        assert_eq!(sim.accel_ramp(), 0.0);
+    }
+
+    #[test]
+    fn scatter_spawns_inside_margin_and_relaxes_overlaps() {
+        let scatter = |iterations: u32| {
+            let mut sim = Sim::new(400, 103, 1.0, 1.0);
+            sim.set_z_mode(false);
+            sim.set_bounce_bounds(true);
+            sim.set_hard_min_distance(0.03);
+            sim.set_spawn_layout(0.1, iterations);
+            sim.scatter_boids();
+            let min = sim.config.hard_min_distance;
+            // Relaxation may push a boid up to about one minimum distance out.
+            let inside = 0.1 - min..=0.9 + min;
+            let mut overlaps = 0;
+            for i in 0..400 {
+                assert!(inside.contains(&sim.pos_x[i]), "{}", sim.pos_x[i]);
+                assert!(inside.contains(&sim.pos_y[i]), "{}", sim.pos_y[i]);
+                for j in i + 1..400 {
+                    let (dx, dy) = (sim.pos_x[j] - sim.pos_x[i], sim.pos_y[j] - sim.pos_y[i]);
+                    overlaps += usize::from(dx * dx + dy * dy < min * min * 0.5);
+                }
+            }
+            assert_eq!(sim.contact_count(), 0);
+            overlaps
+        };
+        let raw = scatter(0);
+        let relaxed = scatter(16);
+        assert!(raw > 20, "{raw}");
+        assert!(relaxed * 10 < raw, "{relaxed} vs {raw}");
+
+        let mut sim = Sim::new(4, 1, 1.0, 1.0);
+        sim.set_spawn_layout(1.0, 1_000);
+        assert_eq!(sim.spawn_margin(), 0.25);
+        assert_eq!(sim.spawn_relax_iterations(), 64);
+    }
+
+    #[test]
+    fn warmup_matches_stepping_and_publishes_once() {
+        for model in [0, 1, 4] {
+            let mut stepped = Sim::new(64, 107, 1.0, 1.0);
+            let mut warmed = Sim::new(64, 107, 1.0, 1.0);
+            stepped.set_model_kind(model);
+            warmed.set_model_kind(model);
+            for _ in 0..40 {
+                stepped.step(1.0 / 60.0);
+            }
+            warmed.pause();
+            warmed.warmup(40, 1.0 / 60.0);
+            assert_eq!(stepped.pos_x, warmed.pos_x, "model {model}");
+            assert_eq!(stepped.vel_y, warmed.vel_y, "model {model}");
+            assert_eq!(warmed.render_xy[0], warmed.pos_x[0]);
+            assert_eq!(warmed.render_xy[1], warmed.pos_y[0]);
+            let (hx, hy) = warmed.raw_render_heading(0);
+            assert_eq!(warmed.render_heading_xy[..2], [hx, hy]);
+        }
+    }
+
+    #[test]
+    fn classic_headings_follow_velocity() {
+        let dt = 1.0 / 60.0;
+        let mut sim = Sim::new(48, 103, 1.0, 1.0);
+        sim.set_classic_heading_smoothing(0.0);
+        for _ in 0..20 {
+            sim.step(dt);
+        }
+        for i in 0..48 {
+            let speed = (sim.vel_x[i].powi(2) + sim.vel_y[i].powi(2)).sqrt();
+            assert!((sim.heading_x[i] - sim.vel_x[i] / speed).abs() < 1.0e-5);
+            assert!((sim.heading_y[i] - sim.vel_y[i] / speed).abs() < 1.0e-5);
+            assert_eq!(sim.heading_z[i], 0.0);
+        }
+
+        sim.set_classic_heading_smoothing(1.0);
+        sim.vel_x[0] = -sim.vel_x[0];
+        sim.vel_y[0] = -sim.vel_y[0];
+        let before = (sim.heading_x[0], sim.heading_y[0]);
+        sim.step(dt);
+        let (hx, hy) = (sim.heading_x[0], sim.heading_y[0]);
+        assert!((hx * hx + hy * hy - 1.0).abs() < 1.0e-4);
+        // A reversal eases in rather than flipping the heading in one step.
+        assert!(hx * before.0 + hy * before.1 > 0.5, "{hx} {hy}");
+        assert_eq!(sim.heading_len(), sim.count());
+
+        sim.set_classic_heading_smoothing(f32::NAN);
+        assert_eq!(sim.classic_heading_smoothing(), 0.1);
+    }
+
+    #[test]
+    fn speed_radius_widens_fast_boids_perception() {
+        let neighbors = |lookahead_s: f32, max_scale: f32| {
+            let mut sim = Sim::new(96, 107, 1.0, 1.0);
+            sim.set_neighbor_radius(0.05);
+            sim.set_speed_radius(lookahead_s, max_scale);
+            sim.step(1.0 / 60.0);
+            sim.neighbors_visited_last_step()
+        };
+        let fixed = neighbors(0.0, 2.0);
+        assert!(neighbors(0.5, 2.0) > fixed);
+        assert_eq!(neighbors(0.5, 1.0), fixed);
+
+        let mut sim = Sim::new(4, 1, 1.0, 1.0);
+        sim.set_speed_radius(5.0, f32::NAN);
+        assert_eq!(sim.speed_radius_lookahead(), 2.0);
+        assert_eq!(sim.speed_radius_max_scale(), 2.0);
+    }
+
+    #[test]
+    fn species_partition_groups_render_entries_by_species() {
+        let mut sim = Sim::new(10, 109, 1.0, 1.0);
+        sim.set_species(&[2, 0, 2, 1, 0, 2, 0, 0, 1, 2]);
+        sim.set_species_size(2, 2.0);
+        sim.set_species_partition(true);
+        sim.step(1.0 / 60.0);
+
+        let partition = &sim.species_partition;
+        assert_eq!(&partition.offsets[..4], &[0, 4, 6, 10]);
+        assert!(partition.offsets[4..].iter().all(|&offset| offset == 10));
+        assert_eq!(partition.source, [1, 4, 6, 7, 3, 8, 0, 2, 5, 9]);
+        for (entry, &slot) in partition.source.iter().enumerate() {
+            let i = slot as usize;
+            assert_eq!(
+                partition.xy[2 * entry..2 * entry + 2],
+                sim.render_xy[2 * i..2 * i + 2]
+            );
+            assert_eq!(
+                partition.heading_xy[2 * entry..2 * entry + 2],
+                sim.render_heading_xy[2 * i..2 * i + 2]
+            );
+            assert_eq!(partition.z[entry], sim.render_z[i]);
+            assert_eq!(partition.scale[entry], sim.render_scale[i]);
+        }
+
+        sim.set_species_partition(false);
+        assert!(sim.species_partition.source.is_empty());
+        assert!(sim
+            .species_partition
+            .offsets
+            .iter()
+            .all(|&offset| offset == 0));
+    }
+
+    #[test]
+    fn neighbor_cell_cap_bounds_scans_of_a_collapsed_flock() {
+        let visited = |cap: usize| {
+            let mut sim = Sim::new(200, 113, 1.0, 1.0);
+            sim.set_neighbor_cell_cap(cap);
+            for i in 0..200 {
+                sim.pos_x[i] = 0.5 + 0.0001 * (i % 10) as f32;
+                sim.pos_y[i] = 0.5 + 0.0001 * (i / 10) as f32;
+            }
+            sim.step(1.0 / 60.0);
+            sim.neighbors_visited_last_step()
+        };
+        assert!(visited(0) > 200 * 16);
+        assert!(visited(16) <= 200 * 16);
     }
 }
@@ -0,0 +1,137 @@
+use crate::{axis_delta, clamp_finite, Sim};
+use wasm_bindgen::prelude::*;
+
+const MIN_LOD_INTERVAL: u32 = 1;
+const MAX_LOD_INTERVAL: u32 = 16;
+pub const DEFAULT_LOD_INTERVAL: u32 = 4;
+
+pub const LOD_FULL: u8 = 0;
+pub const LOD_LOW: u8 = 1;
+const MIN_UPDATE_FRACTION: f32 = 0.01;
+const MAX_UPDATE_FRACTION: f32 = 1.0;
+pub const DEFAULT_UPDATE_FRACTION: f32 = 1.0;
+// Neighbor samples per steering update for boids outside the focus region.
+const FOCUS_OUTER_SAMPLE_CAP: usize = 8;
+
+// Circle in world xy; a radius of zero disables it.
+#[derive(Clone, Copy, Default)]
+pub struct FocusRegion {
+    pub x: f32,
+    pub y: f32,
+    pub radius: f32,
+}
+
+impl Sim {
+    // Low-detail boids recompute steering on one step in `lod_interval`,
+    // staggered by slot so the work spreads evenly, and integrate with their
+    // previous acceleration in between.
+    // Full-detail boids are further limited to the update-fraction window.
+    pub(super) fn lod_refreshes(&self, i: usize) -> bool {
+        if self.low_detail(i) {
+            (self.step_index as usize)
+                .wrapping_add(i)
+                .is_multiple_of(self.lod_interval as usize)
+        } else {
+            self.in_update_window(i)
+        }
+    }
+
+    // The window covers `update_fraction` of the active slots and advances by
+    // its own length every step, so each boid is refreshed at least once every
+    // ceil(n / window) steps. When the window does not divide n it wraps past
+    // the start and some boids are refreshed twice in that span.
+    fn in_update_window(&self, i: usize) -> bool {
+        if self.update_fraction >= MAX_UPDATE_FRACTION {
+            return true;
+        }
+        let n = self.active_count;
+        let window = ((n as f32 * self.update_fraction).ceil() as usize).clamp(1, n);
+        let start = (self.step_index as usize).wrapping_mul(window) % n;
+        (i + n - start) % n < window
+    }
+
+    // While a focus region is set it replaces the manual tiers: boids inside
+    // get full detail, boids outside low detail.
+    pub(super) fn low_detail(&self, i: usize) -> bool {
+        if self.focus.radius <= 0.0 {
+            return self.lod_tiers[i] != LOD_FULL;
+        }
+        let dx = axis_delta(self.pos_x[i] - self.focus.x, !self.bounce_x);
+        let dy = axis_delta(self.pos_y[i] - self.focus.y, !self.bounce_y);
+        dx * dx + dy * dy > self.focus.radius * self.focus.radius
+    }
+
+    // Outside the focus region the configured cap is tightened further, so
+    // steering there averages a sample of the neighborhood.
+    pub(super) fn neighbor_sample_cap(&self, i: usize) -> usize {
+        let cap = self.config.max_neighbors_sampled;
+        if self.focus.radius <= 0.0 || !self.low_detail(i) {
+            cap
+        } else if cap == 0 {
+            FOCUS_OUTER_SAMPLE_CAP
+        } else {
+            cap.min(FOCUS_OUTER_SAMPLE_CAP)
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl Sim {
+    // Tier per slot: 0 is full detail, anything else low detail. Slots past
+    // the end of `tiers` are reset to full detail.
+    pub fn set_lod_tiers(&mut self, tiers: &[u8]) {
+        for (slot, tier) in self.lod_tiers.iter_mut().enumerate() {
+            *tier = match tiers.get(slot) {
+                Some(&LOD_FULL) | None => LOD_FULL,
+                Some(_) => LOD_LOW,
+            };
+        }
+    }
+
+    pub fn set_lod_tier(&mut self, slot: usize, tier: u8) {
+        if let Some(entry) = self.lod_tiers.get_mut(slot) {
+            *entry = if tier == LOD_FULL { LOD_FULL } else { LOD_LOW };
+        }
+    }
+
+    pub fn lod_tier(&self, slot: usize) -> u8 {
+        self.lod_tiers.get(slot).copied().unwrap_or(LOD_FULL)
+    }
+
+    // Boids outside the circle refresh steering every `lod_interval` steps
+    // from a capped neighbor sample. A radius of zero or less removes it.
+    pub fn set_focus_region(&mut self, x: f32, y: f32, radius: f32) {
+        self.focus = if radius.is_finite() && radius > 0.0 && x.is_finite() && y.is_finite() {
+            FocusRegion { x, y, radius }
+        } else {
+            FocusRegion::default()
+        };
+    }
+
+    pub fn focus_radius(&self) -> f32 {
+        self.focus.radius
+    }
+
+    // Fraction of full-detail boids whose steering is recomputed each step,
+    // rotating through the population; the rest reuse their last result.
+    pub fn set_update_fraction(&mut self, fraction: f32) {
+        self.update_fraction = clamp_finite(
+            fraction,
+            MIN_UPDATE_FRACTION,
+            MAX_UPDATE_FRACTION,
+            DEFAULT_UPDATE_FRACTION,
+        );
+    }
+
+    pub fn update_fraction(&self) -> f32 {
+        self.update_fraction
+    }
+
+    pub fn set_lod_interval(&mut self, steps: u32) {
+        self.lod_interval = steps.clamp(MIN_LOD_INTERVAL, MAX_LOD_INTERVAL);
+    }
+
+    pub fn lod_interval(&self) -> u32 {
+        self.lod_interval
+    }
+}
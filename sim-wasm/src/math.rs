@@ -1,15 +1,33 @@
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+use std::f32::consts::{FRAC_PI_2, PI, TAU};
+use std::sync::OnceLock;
+
 const EPSILON: f32 = 1.0e-6;
+const SIN_TABLE_SIZE: usize = 1_024;
+const ATAN_TABLE_SIZE: usize = 512;
+const ASIN_TABLE_SIZE: usize = 512;
+const INV_SQRT_TABLE_SIZE: usize = 512;
+// asin is tabulated on [0, 0.5]; larger inputs use the half-angle identity so
+// the table never has to cover the steep region near 1.
+const ASIN_TABLE_MAX: f32 = 0.5;
+// Mantissas are normalized into [1, 4) so the exponent halves exactly.
+const INV_SQRT_TABLE_MIN: f32 = 1.0;
+const INV_SQRT_TABLE_MAX: f32 = 4.0;
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum MathMode {
     Accurate,
     Fast,
+    Lut,
 }
 
 impl MathMode {
     pub fn from_u32(value: u32) -> Self {
         match value {
             1 => Self::Fast,
+            2 => Self::Lut,
             _ => Self::Accurate,
         }
     }
@@ -18,16 +36,173 @@ impl MathMode {
         match self {
             Self::Accurate => 0,
             Self::Fast => 1,
+            Self::Lut => 2,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Accurate => "accurate",
+            Self::Fast => "fast",
+            Self::Lut => "lut",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "accurate" => Some(Self::Accurate),
+            "fast" => Some(Self::Fast),
+            "lut" => Some(Self::Lut),
+            _ => None,
+        }
+    }
+}
+
+// The parts of a step that can each run their own math mode: steering
+// forces and flock2 heading decisions, speed limits, hard-min separation and
+// other constraints, and the flock2 and fish propulsion updates.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MathPath {
+    Steering,
+    Constraints,
+    Flight,
+}
+
+pub const MATH_PATH_COUNT: usize = 3;
+
+impl MathPath {
+    pub fn from_u32(value: u32) -> Option<Self> {
+        match value {
+            0 => Some(Self::Steering),
+            1 => Some(Self::Constraints),
+            2 => Some(Self::Flight),
+            _ => None,
+        }
+    }
+
+    pub fn index(self) -> usize {
+        self as usize
+    }
+}
+
+// While enabled, every approximated call also runs the accurate path and
+// keeps the largest gap per math path: relative to the exact value, or
+// absolute where that is below 1. atan2 gaps are measured around the circle.
+#[derive(Default)]
+pub struct MathDivergence {
+    pub enabled: bool,
+    pub max: [Cell<f32>; MATH_PATH_COUNT],
+}
+
+impl MathDivergence {
+    pub fn reset(&self) {
+        for max in &self.max {
+            max.set(0.0);
         }
     }
 }
 
+// A math mode plus where to report its divergence, if verifying.
+#[derive(Clone, Copy)]
+pub struct Math<'a> {
+    mode: MathMode,
+    probe: Option<&'a Cell<f32>>,
+}
+
+impl<'a> Math<'a> {
+    pub fn new(mode: MathMode, probe: Option<&'a Cell<f32>>) -> Self {
+        Self { mode, probe }
+    }
+
+    fn verify(self, approx: f32, exact: impl FnOnce() -> f32) -> f32 {
+        self.record(|| {
+            let exact = exact();
+            (approx - exact).abs() / exact.abs().max(1.0)
+        });
+        approx
+    }
+
+    // Angles a turn apart are the same heading, so -pi against pi is no gap.
+    fn verify_angle(self, approx: f32, exact: impl FnOnce() -> f32) -> f32 {
+        self.record(|| {
+            let gap = (approx - exact()).rem_euclid(TAU);
+            gap.min(TAU - gap)
+        });
+        approx
+    }
+
+    fn record(self, gap: impl FnOnce() -> f32) {
+        if let Some(probe) = self.probe.filter(|_| self.mode != MathMode::Accurate) {
+            let gap = gap();
+            if gap > probe.get() {
+                probe.set(gap);
+            }
+        }
+    }
+}
+
+impl From<MathMode> for Math<'static> {
+    fn from(mode: MathMode) -> Self {
+        Self { mode, probe: None }
+    }
+}
+
+struct LutTables {
+    sin: Vec<f32>,
+    atan: Vec<f32>,
+    asin: Vec<f32>,
+    inv_sqrt: Vec<f32>,
+}
+
+impl LutTables {
+    fn build() -> Self {
+        // Each table carries one extra sample so interpolation never reads past
+        // the end at the upper bound.
+        let sin = (0..=SIN_TABLE_SIZE)
+            .map(|k| (k as f32 / SIN_TABLE_SIZE as f32 * TAU).sin())
+            .collect();
+        let atan = (0..=ATAN_TABLE_SIZE)
+            .map(|k| (k as f32 / ATAN_TABLE_SIZE as f32).atan())
+            .collect();
+        let asin = (0..=ASIN_TABLE_SIZE)
+            .map(|k| (k as f32 / ASIN_TABLE_SIZE as f32 * ASIN_TABLE_MAX).asin())
+            .collect();
+        let inv_sqrt = (0..=INV_SQRT_TABLE_SIZE)
+            .map(|k| {
+                let t = k as f32 / INV_SQRT_TABLE_SIZE as f32;
+                let value = INV_SQRT_TABLE_MIN + t * (INV_SQRT_TABLE_MAX - INV_SQRT_TABLE_MIN);
+                1.0 / value.sqrt()
+            })
+            .collect();
+
+        Self {
+            sin,
+            atan,
+            asin,
+            inv_sqrt,
+        }
+    }
+}
+
+fn lut_tables() -> &'static LutTables {
+    static TABLES: OnceLock<LutTables> = OnceLock::new();
+    TABLES.get_or_init(LutTables::build)
+}
+
+fn sample_table(table: &[f32], position: f32) -> f32 {
+    let last = table.len() - 2;
+    let position = position.clamp(0.0, (last + 1) as f32);
+    let index = (position as usize).min(last);
+    let frac = position - index as f32;
+    table[index] + (table[index + 1] - table[index]) * frac
+}
+
 pub fn distance_sq_3d(dx: f32, dy: f32, dz: f32) -> f32 {
     dx * dx + dy * dy + dz * dz
 }
 
 pub fn normalize_to_magnitude(
-    mode: MathMode,
+    math: Math<'_>,
     x: f32,
     y: f32,
     z: f32,
@@ -38,13 +213,40 @@ pub fn normalize_to_magnitude(
         return (0.0, 0.0, 0.0);
     }
 
-    let inv_mag = inverse_sqrt(mode, mag_sq);
+    let inv_mag = inverse_sqrt(math, mag_sq);
     let scale = magnitude * inv_mag;
     (x * scale, y * scale, z * scale)
 }
 
+// Unit vector along (x, y, z), or the default when it is too short to have a
+// direction.
+pub fn normalize_or_default(
+    math: Math<'_>,
+    x: f32,
+    y: f32,
+    z: f32,
+    default_x: f32,
+    default_y: f32,
+    default_z: f32,
+) -> (f32, f32, f32) {
+    let len_sq = distance_sq_3d(x, y, z);
+    if len_sq <= EPSILON {
+        return (default_x, default_y, default_z);
+    }
+    let inv_len = inverse_sqrt(math, len_sq);
+    (x * inv_len, y * inv_len, z * inv_len)
+}
+
+// Length from a squared length, or 0 when it is too short to measure.
+pub fn length_from_sq(math: Math<'_>, len_sq: f32) -> f32 {
+    if len_sq <= EPSILON {
+        return 0.0;
+    }
+    len_sq * inverse_sqrt(math, len_sq)
+}
+
 pub fn limit_magnitude_3d(
-    mode: MathMode,
+    math: Math<'_>,
     x: f32,
     y: f32,
     z: f32,
@@ -60,15 +262,121 @@ pub fn limit_magnitude_3d(
         return (x, y, z);
     }
 
-    let scale = max_magnitude * inverse_sqrt(mode, mag_sq);
+    let scale = max_magnitude * inverse_sqrt(math, mag_sq);
     (x * scale, y * scale, z * scale)
 }
 
-fn inverse_sqrt(mode: MathMode, value: f32) -> f32 {
-    match mode {
+pub fn atan2(math: Math<'_>, y: f32, x: f32) -> f32 {
+    let approx = match math.mode {
+        MathMode::Accurate | MathMode::Fast => y.atan2(x),
+        MathMode::Lut => lut_atan2(y, x),
+    };
+    math.verify_angle(approx, || y.atan2(x))
+}
+
+pub fn asin(math: Math<'_>, value: f32) -> f32 {
+    let approx = match math.mode {
+        MathMode::Accurate | MathMode::Fast => value.asin(),
+        MathMode::Lut => lut_asin(value),
+    };
+    math.verify(approx, || value.asin())
+}
+
+pub fn sin_cos(math: Math<'_>, angle_radians: f32) -> (f32, f32) {
+    let (sin, cos) = match math.mode {
+        MathMode::Accurate | MathMode::Fast => angle_radians.sin_cos(),
+        MathMode::Lut => lut_sin_cos(angle_radians),
+    };
+    (
+        math.verify(sin, || angle_radians.sin()),
+        math.verify(cos, || angle_radians.cos()),
+    )
+}
+
+fn inverse_sqrt(math: Math<'_>, value: f32) -> f32 {
+    let approx = match math.mode {
         MathMode::Accurate => 1.0 / value.sqrt(),
         MathMode::Fast => fast_inverse_sqrt(value),
+        MathMode::Lut => lut_inverse_sqrt(value),
+    };
+    math.verify(approx, || 1.0 / value.sqrt())
+}
+
+fn lut_sin_cos(angle_radians: f32) -> (f32, f32) {
+    if !angle_radians.is_finite() {
+        return angle_radians.sin_cos();
     }
+
+    let table = &lut_tables().sin;
+    let turns = angle_radians.rem_euclid(TAU) / TAU;
+    let sin_position = turns * SIN_TABLE_SIZE as f32;
+    let cos_position = (turns + 0.25).rem_euclid(1.0) * SIN_TABLE_SIZE as f32;
+    (
+        sample_table(table, sin_position),
+        sample_table(table, cos_position),
+    )
+}
+
+fn lut_atan(ratio: f32) -> f32 {
+    // atan(1/t) = pi/2 - atan(t) keeps the table domain at [0, 1].
+    let table = &lut_tables().atan;
+    let magnitude = ratio.abs();
+    let angle = if magnitude <= 1.0 {
+        sample_table(table, magnitude * ATAN_TABLE_SIZE as f32)
+    } else {
+        FRAC_PI_2 - sample_table(table, ATAN_TABLE_SIZE as f32 / magnitude)
+    };
+    angle.copysign(ratio)
+}
+
+fn lut_atan2(y: f32, x: f32) -> f32 {
+    if x.abs() <= f32::MIN_POSITIVE {
+        if y.abs() <= f32::MIN_POSITIVE {
+            return 0.0_f32.copysign(y);
+        }
+        return FRAC_PI_2.copysign(y);
+    }
+
+    let base = lut_atan(y / x);
+    if x > 0.0 {
+        base
+    } else if y >= 0.0 {
+        base + PI
+    } else {
+        base - PI
+    }
+}
+
+fn lut_asin(value: f32) -> f32 {
+    let magnitude = value.abs().min(1.0);
+    let table = &lut_tables().asin;
+    let angle = if magnitude <= ASIN_TABLE_MAX {
+        sample_table(table, magnitude / ASIN_TABLE_MAX * ASIN_TABLE_SIZE as f32)
+    } else {
+        let half = ((1.0 - magnitude) * 0.5).sqrt();
+        FRAC_PI_2 - 2.0 * sample_table(table, half / ASIN_TABLE_MAX * ASIN_TABLE_SIZE as f32)
+    };
+    angle.copysign(value)
+}
+
+fn lut_inverse_sqrt(value: f32) -> f32 {
+    if !value.is_normal() || value < 0.0 {
+        return 1.0 / value.sqrt();
+    }
+
+    let bits = value.to_bits();
+    let mut exponent = ((bits >> 23) & 0xff) as i32 - 127;
+    let mut mantissa = f32::from_bits((bits & 0x007f_ffff) | 0x3f80_0000);
+    if exponent % 2 != 0 {
+        mantissa *= 2.0;
+        exponent -= 1;
+    }
+
+    let table = &lut_tables().inv_sqrt;
+    let position = (mantissa - INV_SQRT_TABLE_MIN) / (INV_SQRT_TABLE_MAX - INV_SQRT_TABLE_MIN)
+        * INV_SQRT_TABLE_SIZE as f32;
+    let scale = f32::from_bits(((127 - exponent / 2) as u32) << 23);
+    sample_table(table, position) * scale
 }
 
 // One Newton-Raphson refinement keeps this fast while staying stable enough
@@ -84,12 +392,13 @@ fn fast_inverse_sqrt(value: f32) -> f32 {
 
 #[cfg(test)]
 mod tests {
-    use super::{limit_magnitude_3d, normalize_to_magnitude, MathMode};
+    use super::{asin, atan2, limit_magnitude_3d, normalize_to_magnitude, sin_cos, Math, MathMode};
+    use std::cell::Cell;
 
     #[test]
     fn fast_mode_normalize_is_reasonable() {
-        let (ax, ay, az) = normalize_to_magnitude(MathMode::Accurate, 3.0, 4.0, 0.0, 10.0);
-        let (fx, fy, fz) = normalize_to_magnitude(MathMode::Fast, 3.0, 4.0, 0.0, 10.0);
+        let (ax, ay, az) = normalize_to_magnitude(MathMode::Accurate.into(), 3.0, 4.0, 0.0, 10.0);
+        let (fx, fy, fz) = normalize_to_magnitude(MathMode::Fast.into(), 3.0, 4.0, 0.0, 10.0);
 
         assert!((ax - fx).abs() < 0.2);
         assert!((ay - fy).abs() < 0.2);
@@ -98,7 +407,52 @@ mod tests {
 
     #[test]
     fn limited_vector_has_expected_upper_bound() {
-        let (_, _, z) = limit_magnitude_3d(MathMode::Fast, 0.0, 0.0, 10.0, 2.0);
+        let (_, _, z) = limit_magnitude_3d(MathMode::Fast.into(), 0.0, 0.0, 10.0, 2.0);
         assert!(z <= 2.1);
     }
+
+    #[test]
+    fn lut_mode_tracks_accurate_math() {
+        for k in -40..=40 {
+            let t = k as f32 * 0.173;
+            let (s_lut, c_lut) = sin_cos(MathMode::Lut.into(), t);
+            assert!((s_lut - t.sin()).abs() < 1.0e-4, "sin({t})");
+            assert!((c_lut - t.cos()).abs() < 1.0e-4, "cos({t})");
+
+            let (y, x) = (t.sin() * 3.0, (t * 0.7).cos() - 0.2);
+            assert!((atan2(MathMode::Lut.into(), y, x) - y.atan2(x)).abs() < 1.0e-4);
+
+            let v = (t * 0.05).clamp(-1.0, 1.0);
+            assert!(
+                (asin(MathMode::Lut.into(), v) - v.asin()).abs() < 1.0e-3,
+                "asin({v})"
+            );
+        }
+
+        for value in [0.01, 0.3, 1.0, 2.0, 3.9, 17.0, 1.0e5] {
+            let (lx, _, _) = normalize_to_magnitude(MathMode::Lut.into(), value, 0.0, 0.0, 1.0);
+            assert!((lx - 1.0).abs() < 1.0e-4, "normalize({value})");
+        }
+    }
+
+    #[test]
+    fn verification_reports_the_gap_to_accurate_math() {
+        let probe = Cell::new(0.0);
+        let fast = Math::new(MathMode::Fast, Some(&probe));
+        let (x, _, _) = normalize_to_magnitude(fast, 3.0, 4.0, 0.0, 1.0);
+        let gap = probe.get();
+        assert!(gap > 0.0 && gap < 1.0e-2, "{gap}");
+        assert!((x - 0.6).abs() <= gap * 3.0 + 1.0e-6);
+
+        // Fast keeps library trig, and accurate mode never records.
+        atan2(fast, 1.0, 2.0);
+        assert_eq!(probe.get(), gap);
+        probe.set(0.0);
+        let accurate = Math::new(MathMode::Accurate, Some(&probe));
+        normalize_to_magnitude(accurate, 3.0, 4.0, 0.0, 1.0);
+        assert_eq!(probe.get(), 0.0);
+
+        sin_cos(Math::new(MathMode::Lut, Some(&probe)), 0.3);
+        assert!(probe.get() > 0.0 && probe.get() < 1.0e-4);
+    }
 }
@@ -0,0 +1,197 @@
+use crate::flock2::FLOCK2_WORLD_SCALE;
+use crate::{Sim, EPSILON};
+use wasm_bindgen::prelude::*;
+
+const MAX_METRIC_HISTORY_LENGTH: usize = 4_096;
+
+// Fixed-length ring buffers filled once per `step`. `start` is the oldest
+// sample; the newest sits `filled - 1` slots after it, wrapping.
+#[derive(Default)]
+pub struct MetricHistory {
+    pub polarization: Vec<f32>,
+    mean_speed: Vec<f32>,
+    cluster_count: Vec<f32>,
+    start: usize,
+    filled: usize,
+}
+
+impl MetricHistory {
+    pub fn enabled(&self) -> bool {
+        !self.polarization.is_empty()
+    }
+
+    fn push(&mut self, polarization: f32, mean_speed: f32, cluster_count: f32) {
+        let length = self.polarization.len();
+        let slot = (self.start + self.filled) % length;
+        self.polarization[slot] = polarization;
+        self.mean_speed[slot] = mean_speed;
+        self.cluster_count[slot] = cluster_count;
+        if self.filled < length {
+            self.filled += 1;
+        } else {
+            self.start = (self.start + 1) % length;
+        }
+    }
+}
+
+impl Sim {
+    // Polarization is the length of the mean heading (1 when every boid flies
+    // the same way); boids at rest are left out of both averages.
+    pub(super) fn polarization_and_mean_speed(&self) -> (f32, f32) {
+        self.polarization_and_mean_speed_where(|_| true)
+    }
+
+    pub(super) fn polarization_and_mean_speed_where(
+        &self,
+        include: impl Fn(usize) -> bool,
+    ) -> (f32, f32) {
+        let mut heading_sum = [0.0_f32; 3];
+        let mut speed_sum = 0.0;
+        let mut moving = 0usize;
+        for i in (0..self.active_count).filter(|&i| include(i)) {
+            let vz = if self.z_mode_enabled {
+                self.vel_z[i]
+            } else {
+                0.0
+            };
+            let speed =
+                (self.vel_x[i] * self.vel_x[i] + self.vel_y[i] * self.vel_y[i] + vz * vz).sqrt();
+            if speed <= EPSILON {
+                continue;
+            }
+            heading_sum[0] += self.vel_x[i] / speed;
+            heading_sum[1] += self.vel_y[i] / speed;
+            heading_sum[2] += vz / speed;
+            speed_sum += speed;
+            moving += 1;
+        }
+
+        if moving == 0 {
+            return (0.0, 0.0);
+        }
+        let inv = 1.0 / moving as f32;
+        let polarization = (heading_sum[0] * heading_sum[0]
+            + heading_sum[1] * heading_sum[1]
+            + heading_sum[2] * heading_sum[2])
+            .sqrt()
+            * inv;
+        (polarization, speed_sum * inv)
+    }
+
+    // Speeds are in flight-model units; y is the vertical axis. A boid counts
+    // as stalled when flight forces left it below min_speed before clamping.
+    pub(super) fn record_flight_sample(
+        &mut self,
+        i: usize,
+        thrust_power: f32,
+        stalled: bool,
+        dt: f32,
+    ) {
+        let airspeed = (self.vel_x[i] * self.vel_x[i]
+            + self.vel_y[i] * self.vel_y[i]
+            + self.vel_z[i] * self.vel_z[i])
+            .sqrt();
+        let flight = &mut self.step_events.flight;
+        flight.samples += 1;
+        flight.airspeed_sum += airspeed;
+        flight.climb_rate_sum += self.vel_y[i];
+        flight.thrust_work += thrust_power * dt;
+        flight.stalled += u32::from(stalled);
+    }
+
+    pub(super) fn sample_metric_history(&mut self) {
+        let (polarization, mean_speed) = self.polarization_and_mean_speed();
+        self.detect_clusters();
+        let cluster_count = self.clusters.count() as f32;
+        self.metric_history
+            .push(polarization, mean_speed, cluster_count);
+    }
+}
+
+#[wasm_bindgen]
+impl Sim {
+    pub fn polarization(&self) -> f32 {
+        self.polarization_and_mean_speed().0
+    }
+
+    pub fn mean_speed(&self) -> f32 {
+        self.polarization_and_mean_speed().1
+    }
+
+    // Flight metrics cover the last step and stay zero unless a flight model
+    // ran it.
+    pub fn mean_airspeed(&self) -> f32 {
+        let flight = &self.step_events.flight;
+        flight.airspeed_sum / flight.samples.max(1) as f32
+    }
+
+    pub fn mean_climb_rate(&self) -> f32 {
+        let flight = &self.step_events.flight;
+        flight.climb_rate_sum / flight.samples.max(1) as f32
+    }
+
+    // Mean kinetic plus potential energy per unit mass, in flight-model units
+    // with height measured along world y. A rising value without thrust is
+    // energy drawn from the wind. Zero outside the flock2 models.
+    pub fn mean_flight_energy(&self) -> f32 {
+        if !self.model_kind.uses_flock2_units() || self.active_count == 0 {
+            return 0.0;
+        }
+        let gravity = self.flock2_config.gravity;
+        let total: f32 = (0..self.active_count)
+            .map(|i| {
+                let speed_sq = self.vel_x[i] * self.vel_x[i]
+                    + self.vel_y[i] * self.vel_y[i]
+                    + self.vel_z[i] * self.vel_z[i];
+                0.5 * speed_sq + gravity * self.pos_y[i] / FLOCK2_WORLD_SCALE
+            })
+            .sum();
+        total / self.active_count as f32
+    }
+
+    pub fn thrust_work(&self) -> f32 {
+        self.step_events.flight.thrust_work
+    }
+
+    pub fn stall_fraction(&self) -> f32 {
+        let flight = &self.step_events.flight;
+        flight.stalled as f32 / flight.samples.max(1) as f32
+    }
+
+    // 0 disables sampling. Resizing clears the history and moves the buffers,
+    // so JS views must be recreated afterwards.
+    pub fn set_metric_history_length(&mut self, length: usize) {
+        let length = length.min(MAX_METRIC_HISTORY_LENGTH);
+        self.metric_history = MetricHistory {
+            polarization: vec![0.0; length],
+            mean_speed: vec![0.0; length],
+            cluster_count: vec![0.0; length],
+            start: 0,
+            filled: 0,
+        };
+    }
+
+    pub fn metric_history_length(&self) -> usize {
+        self.metric_history.polarization.len()
+    }
+
+    pub fn metric_history_filled(&self) -> usize {
+        self.metric_history.filled
+    }
+
+    pub fn metric_history_start(&self) -> usize {
+        self.metric_history.start
+    }
+
+    pub fn polarization_history_ptr(&self) -> *const f32 {
+        self.metric_history.polarization.as_ptr()
+    }
+
+    pub fn mean_speed_history_ptr(&self) -> *const f32 {
+        self.metric_history.mean_speed.as_ptr()
+    }
+
+    pub fn cluster_count_history_ptr(&self) -> *const f32 {
+        self.metric_history.cluster_count.as_ptr()
+    }
+}
@@ -0,0 +1,157 @@
+use crate::recording::FieldCodec;
+use crate::split_world::SplitHalf;
+use crate::{project_axis_position, Sim, WORLD_SIZE};
+use wasm_bindgen::prelude::*;
+
+const MIRROR_LINE: f32 = 0.5 * WORLD_SIZE;
+
+// Output-stage symmetry. Render positions and headings are reflected across
+// the vertical center line (`x`), the horizontal one (`y`) or both, into
+// their own buffers. Folding keeps the simulated boids in the lower half of
+// each mirrored axis so the reflections tile the world instead of
+// overlapping it.
+#[derive(Default)]
+pub struct Mirror {
+    pub x: bool,
+    pub y: bool,
+    pub fold: bool,
+    pub xy: Vec<f32>,
+    pub heading_xy: Vec<f32>,
+}
+
+impl Mirror {
+    pub fn copies(&self) -> usize {
+        (1 + usize::from(self.x)) * (1 + usize::from(self.y))
+    }
+
+    pub fn visit_settings(&mut self, codec: &mut dyn FieldCodec) {
+        codec.bool(&mut self.x);
+        codec.bool(&mut self.y);
+        codec.bool(&mut self.fold);
+    }
+}
+
+impl Sim {
+    // Wall setting and extent of one axis: the lower half when it is folded,
+    // the whole world otherwise.
+    pub(super) fn mirror_axis_bounds(&self, mirrored: bool, bounce: bool) -> (bool, (f32, f32)) {
+        if self.mirror.fold && mirrored {
+            (true, SplitHalf::Left.range())
+        } else {
+            (bounce, (0.0, WORLD_SIZE))
+        }
+    }
+
+    pub(super) fn project_mirror_axis(&self, position: f32, mirrored: bool, bounce: bool) -> f32 {
+        let (bounce, (lo, hi)) = self.mirror_axis_bounds(mirrored, bounce);
+        if bounce {
+            position.clamp(lo, hi)
+        } else {
+            project_axis_position(position, false)
+        }
+    }
+
+    // Reflects boids in the upper half of a folded axis into the lower one.
+    pub(super) fn fold_into_mirror(&mut self, i: usize) {
+        if !self.mirror.fold {
+            return;
+        }
+        let (_, (_, hi)) = self.mirror_axis_bounds(true, true);
+        if self.mirror.x && self.pos_x[i] > hi {
+            self.pos_x[i] = (WORLD_SIZE - self.pos_x[i]).min(hi);
+            self.vel_x[i] = -self.vel_x[i];
+            self.heading_x[i] = -self.heading_x[i];
+        }
+        if self.mirror.y && self.pos_y[i] > hi {
+            self.pos_y[i] = (WORLD_SIZE - self.pos_y[i]).min(hi);
+            self.vel_y[i] = -self.vel_y[i];
+            self.heading_y[i] = -self.heading_y[i];
+        }
+    }
+
+    // Copy `k` of boid `i` sits at `k * active_count + i`; copy 0 is the boid
+    // itself, then the x reflection, the y reflection and the xy reflection.
+    pub(super) fn sync_mirror(&mut self) {
+        let mirror = &mut self.mirror;
+        let copies = mirror.copies();
+        if copies == 1 {
+            mirror.xy.clear();
+            mirror.heading_xy.clear();
+            return;
+        }
+        let n = self.active_count;
+        mirror.xy.resize(2 * n * copies, 0.0);
+        mirror.heading_xy.resize(2 * n * copies, 0.0);
+        let flips = [(false, false), (true, false), (false, true), (true, true)];
+        let mut copy = 0;
+        for (flip_x, flip_y) in flips {
+            if (flip_x && !mirror.x) || (flip_y && !mirror.y) {
+                continue;
+            }
+            let base = 2 * n * copy;
+            for i in 0..n {
+                let (x, y) = (self.render_xy[2 * i], self.render_xy[2 * i + 1]);
+                let (hx, hy) = (
+                    self.render_heading_xy[2 * i],
+                    self.render_heading_xy[2 * i + 1],
+                );
+                let slot = base + 2 * i;
+                mirror.xy[slot] = if flip_x { 2.0 * MIRROR_LINE - x } else { x };
+                mirror.xy[slot + 1] = if flip_y { 2.0 * MIRROR_LINE - y } else { y };
+                mirror.heading_xy[slot] = if flip_x { -hx } else { hx };
+                mirror.heading_xy[slot + 1] = if flip_y { -hy } else { hy };
+            }
+            copy += 1;
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl Sim {
+    // Folding an axis reflects boids already in its upper half into the lower
+    // one; they then bounce off the mirror line whatever the wrap setting.
+    pub fn set_mirror(&mut self, x: bool, y: bool, fold: bool) {
+        self.mirror.x = x;
+        self.mirror.y = y;
+        self.mirror.fold = fold;
+        for i in 0..self.active_count {
+            self.fold_into_mirror(i);
+        }
+        self.sync_render_buffers();
+    }
+
+    pub fn mirror_x(&self) -> bool {
+        self.mirror.x
+    }
+
+    pub fn mirror_y(&self) -> bool {
+        self.mirror.y
+    }
+
+    pub fn mirror_fold(&self) -> bool {
+        self.mirror.fold
+    }
+
+    // 1 when mirroring is off, else 2 or 4 rendered copies per boid.
+    pub fn mirror_copies(&self) -> usize {
+        self.mirror.copies()
+    }
+
+    // Empty while mirroring is off. Other per-boid render buffers are shared
+    // by every copy: index them with `copy_index % active_count`.
+    pub fn mirror_xy_ptr(&self) -> *const f32 {
+        self.mirror.xy.as_ptr()
+    }
+
+    pub fn mirror_xy_len(&self) -> usize {
+        self.mirror.xy.len()
+    }
+
+    pub fn mirror_heading_xy_ptr(&self) -> *const f32 {
+        self.mirror.heading_xy.as_ptr()
+    }
+
+    pub fn mirror_heading_xy_len(&self) -> usize {
+        self.mirror.heading_xy.len()
+    }
+}
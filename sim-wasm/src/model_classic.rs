@@ -1,83 +1,251 @@
+use crate::familiarity::Familiarity;
+use crate::flock2::normalize_or_default;
+use crate::math::MathPath;
+use crate::neighbor_backend::NeighborBackend;
+use crate::neighbor_grid::GridVisit;
+use crate::pair_avoidance::NearestNeighbors;
+use crate::split_world::SplitHalf;
+use crate::steering_debug::SteeringComponents;
 use crate::{
-    axis_delta, hash_unit, integrate_axis, math, steer_towards_3d, Sim, DEFAULT_Z_LAYER, EPSILON,
-    WORLD_SIZE,
+    axis_delta, hash_unit, math, steer_towards_3d, DragModel, Sim, StepStage, EPSILON,
+    MAX_NEIGHBOR_RADIUS, WORLD_SIZE,
 };
 
 impl Sim {
-    pub(super) fn step_classic(&mut self, dt: f32) {
+    // If steering cannot produce non-zero acceleration, skip neighbor/force work.
+    // Fish and burst-and-coast always need the full pass for their thrust.
+    pub(super) fn classic_steering_disabled(&self) -> bool {
+        !self.fish_enabled()
+            && !self.burst_coast.enabled()
+            && (self.config.max_force <= EPSILON
+                || ((self.config.sep_weight <= EPSILON
+                    && self.config.align_weight <= EPSILON
+                    && self.config.coh_weight <= EPSILON
+                    && self.config.speed_match_weight <= EPSILON)
+                    && self.config.jitter_strength <= EPSILON
+                    && self.config.shape_attractor_weight <= EPSILON
+                    && self.custom_force.is_none()
+                    && self.obstacles.is_empty()
+                    && self.danger_zones.is_empty()
+                    && self.corridors.is_empty()
+                    && !self.terrain_active()
+                    && !self.exclusion.enabled()
+                    && !self.turn_noise.enabled()
+                    && !self.pair_avoidance.enabled()
+                    && self.predators.is_empty()
+                    && !self.behavior.enabled
+                    && !self.split_active()))
+    }
+
+    // Starts a step's steering pass: advances the step index and builds the
+    // neighbor structure. Returns whether a custom force was evaluated.
+    pub(super) fn begin_classic_steering(&mut self) -> bool {
         self.step_index = self.step_index.wrapping_add(1);
-        self.neighbors_visited_last_step = 0;
-
-        // If steering cannot produce non-zero acceleration, skip neighbor/force work.
-        let steering_disabled = self.config.max_force <= EPSILON
-            || ((self.config.sep_weight <= EPSILON
-                && self.config.align_weight <= EPSILON
-                && self.config.coh_weight <= EPSILON)
-                && self.config.jitter_strength <= EPSILON
-                && self.config.shape_attractor_weight <= EPSILON);
-        let drag_damping = if self.config.drag <= EPSILON {
-            1.0
+        self.prepare_classic_steering()
+    }
+
+    // The part of `begin_classic_steering` a restarted pass repeats, without
+    // advancing the step index again.
+    pub(super) fn prepare_classic_steering(&mut self) -> bool {
+        self.steering_debug.clear();
+
+        let far_field = self.config.far_field_opening > EPSILON;
+        if self.kd_tree_queries_enabled() {
+            self.refresh_kd_tree();
         } else {
-            (-self.config.drag * dt).exp()
-        };
+            self.refresh_neighbor_grid(self.neighbor_cell_size(), far_field);
+            if far_field {
+                self.neighbor_grid.rebuild_aggregates(
+                    &self.pos_z[..self.active_count],
+                    &self.vel_x[..self.active_count],
+                    &self.vel_y[..self.active_count],
+                    &self.vel_z[..self.active_count],
+                );
+            }
+        }
+
+        self.compute_custom_forces()
+    }
+
+    // Fills the accel arrays for slots `start..end` and returns the number of
+    // neighbors visited.
+    pub(super) fn compute_classic_steering(
+        &mut self,
+        start: usize,
+        end: usize,
+        has_custom_force: bool,
+    ) -> usize {
+        let mut neighbors_visited = 0;
+        for &pass in self.split_passes() {
+            neighbors_visited += self.with_split_config(pass, |sim| {
+                sim.compute_classic_steering_pass(start, end, has_custom_force, pass)
+            });
+        }
+        neighbors_visited
+    }
+
+    fn compute_classic_steering_pass(
+        &mut self,
+        start: usize,
+        end: usize,
+        has_custom_force: bool,
+        pass: Option<SplitHalf>,
+    ) -> usize {
+        let mut neighbors_visited = 0;
+        let mut familiarity = std::mem::take(&mut self.familiarity);
+        if familiarity.enabled() {
+            familiarity.prepare(self.boid_ids.len());
+        }
+        for i in start..end {
+            if !self.in_split_pass(i, pass) || !self.lod_refreshes(i) {
+                continue;
+            }
+            self.update_behavior_state(i);
+            let mut components = SteeringComponents::default();
+            let debug_slot = self.steering_debug.slot(i);
+            let (ax, ay, az, neighbors_used, crowded_by) = self.compute_boids_acceleration(
+                i,
+                has_custom_force,
+                &mut familiarity,
+                debug_slot.is_some().then_some(&mut components),
+            );
+            if let Some(slot) = debug_slot {
+                self.steering_debug.store(slot, &components);
+            }
+            self.accel_x[i] = ax;
+            self.accel_y[i] = ay;
+            self.accel_z[i] = az;
+            neighbors_visited += neighbors_used;
+            self.behavior.settle_roaming(i, neighbors_used > 0);
+            self.render_crowding[i] = (crowded_by as f32 / self.crowding_cap).min(1.0);
+        }
+        self.familiarity = familiarity;
+        neighbors_visited
+    }
 
-        if steering_disabled {
+    pub(super) fn step_classic(&mut self, dt: f32) {
+        // A pass begun by `step_partial` is finished rather than restarted, so
+        // a step taken mid-pass still advances the step index once.
+        let partial = std::mem::take(&mut self.partial_step);
+        self.wind.advance(dt);
+        let (wind_x, wind_y, wind_z) = self.wind.velocity();
+        let wind = (
+            wind_x,
+            wind_y,
+            if self.z_mode_enabled { wind_z } else { 0.0 },
+        );
+
+        if self.classic_steering_disabled() {
+            let (drag_damping, [global_dv_x, global_dv_y, global_dv_z]) =
+                self.classic_step_terms(dt);
+            if partial.next.is_none() {
+                self.step_index = self.step_index.wrapping_add(1);
+            }
+            self.neighbors_visited_last_step = 0;
+            self.steering_debug.clear();
+            self.render_crowding.fill(0.0);
+            self.run_step_hook(StepStage::AfterForces);
+            self.run_step_hook(StepStage::BeforeIntegration);
             for i in 0..self.active_count {
-                let vx = self.vel_x[i] * drag_damping;
-                let vy = self.vel_y[i] * drag_damping;
+                if self.hold_perched(i) {
+                    continue;
+                }
+                let vx = self.vel_x[i] + global_dv_x;
+                let vy = self.vel_y[i] + global_dv_y;
                 let vz = if self.z_mode_enabled {
-                    self.vel_z[i] * drag_damping
+                    self.vel_z[i] + global_dv_z
                 } else {
                     0.0
                 };
+                let (damping, z_damping) = self.drag_scale(drag_damping, vx, vy, vz, dt);
+                let vx = vx * damping;
+                let vy = vy * damping;
+                let vz = vz * z_damping;
 
-                let (x, vx) = integrate_axis(self.pos_x[i], vx, dt, self.bounce_x);
-                let (y, vy) = integrate_axis(self.pos_y[i], vy, dt, self.bounce_y);
-                let (z, vz) = if self.z_mode_enabled {
-                    integrate_axis(self.pos_z[i], vz, dt, self.bounce_z)
-                } else {
-                    (DEFAULT_Z_LAYER, 0.0)
-                };
-
+                let (vx, vy, vz) = self.integrate_boid_in_wind(i, vx, vy, vz, wind, dt);
                 self.vel_x[i] = vx;
                 self.vel_y[i] = vy;
-                self.vel_z[i] = if self.z_mode_enabled { vz } else { 0.0 };
-                self.pos_x[i] = x;
-                self.pos_y[i] = y;
-                self.pos_z[i] = z;
+                self.vel_z[i] = vz;
             }
+            self.sync_classic_headings(dt);
 
             self.resolve_hard_min_distance_constraints();
+            self.run_step_hook(StepStage::AfterConstraints);
             self.sync_render_buffers();
             self.debug_validate_state();
             return;
         }
 
-        self.neighbor_grid
-            .set_cell_size(self.config.neighbor_radius);
-        self.neighbor_grid.rebuild(
-            &self.pos_x[..self.active_count],
-            &self.pos_y[..self.active_count],
-            WORLD_SIZE,
-            WORLD_SIZE,
-        );
+        let (start, visited, has_custom_force) = match partial.next {
+            Some(next) if partial.active_count == self.active_count => {
+                (next, partial.neighbors_visited, partial.has_custom_force)
+            }
+            Some(_) => (0, 0, self.prepare_classic_steering()),
+            None => (0, 0, self.begin_classic_steering()),
+        };
+        self.neighbors_visited_last_step =
+            visited + self.compute_classic_steering(start, self.active_count, has_custom_force);
+        self.familiarity.advance(dt);
+        if self.fish_enabled() {
+            self.apply_fish_forces(dt);
+        } else if self.burst_coast.enabled() {
+            self.apply_burst_coast();
+        }
+        self.apply_accel_ramp(dt);
+        self.run_step_hook(StepStage::AfterForces);
+        self.run_step_hook(StepStage::BeforeIntegration);
 
-        for i in 0..self.active_count {
-            let (ax, ay, az, neighbors_used) = self.compute_boids_acceleration(i);
-            self.accel_x[i] = ax;
-            self.accel_y[i] = ay;
-            self.accel_z[i] = az;
-            self.neighbors_visited_last_step += neighbors_used;
+        for &pass in self.split_passes() {
+            self.with_split_config(pass, |sim| sim.integrate_classic_pass(dt, wind, pass));
         }
+        self.sync_classic_headings(dt);
 
+        self.resolve_hard_min_distance_constraints();
+        self.run_step_hook(StepStage::AfterConstraints);
+        self.sync_render_buffers();
+        self.debug_validate_state();
+    }
+
+    // Exponential drag damping for the horizontal and z axes, and the global
+    // acceleration's velocity change over `dt`.
+    fn classic_step_terms(&self, dt: f32) -> ((f32, f32), [f32; 3]) {
+        let (z_drag, _) = self.config.z_drag_coefficients();
+        let drag_damping = (
+            exponential_damping(self.config.drag, dt),
+            exponential_damping(z_drag, dt),
+        );
+        let global_dv_z = if self.z_mode_enabled {
+            self.config.global_accel_z * dt
+        } else {
+            0.0
+        };
+        (
+            drag_damping,
+            [
+                self.config.global_accel_x * dt,
+                self.config.global_accel_y * dt,
+                global_dv_z,
+            ],
+        )
+    }
+
+    fn integrate_classic_pass(&mut self, dt: f32, wind: (f32, f32, f32), pass: Option<SplitHalf>) {
+        let (drag_damping, [global_dv_x, global_dv_y, global_dv_z]) = self.classic_step_terms(dt);
         for i in 0..self.active_count {
-            let mut vx = (self.vel_x[i] + self.accel_x[i] * dt) * drag_damping;
-            let mut vy = (self.vel_y[i] + self.accel_y[i] * dt) * drag_damping;
+            if !self.in_split_pass(i, pass) || self.hold_perched(i) {
+                continue;
+            }
+            let mut vx = self.vel_x[i] + self.accel_x[i] * dt + global_dv_x;
+            let mut vy = self.vel_y[i] + self.accel_y[i] * dt + global_dv_y;
             let mut vz = if self.z_mode_enabled {
-                (self.vel_z[i] + self.accel_z[i] * dt) * drag_damping
+                self.vel_z[i] + self.accel_z[i] * dt + global_dv_z
             } else {
                 0.0
             };
+            let (damping, z_damping) = self.drag_scale(drag_damping, vx, vy, vz, dt);
+            vx *= damping;
+            vy *= damping;
+            vz *= z_damping;
 
             let speed_sq = if self.z_mode_enabled {
                 vx * vx + vy * vy + vz * vz
@@ -85,22 +253,27 @@ impl Sim {
                 vx * vx + vy * vy
             };
 
+            let speed_scale = self.config_speed_scale(i) * self.depth_speed_scale(i);
+            let (min_speed, max_speed) = (
+                self.config.min_speed * speed_scale,
+                self.config.max_speed * speed_scale,
+            );
             if speed_sq <= EPSILON {
-                if self.config.min_speed > 0.0 {
-                    vx = self.config.min_speed;
+                if min_speed > 0.0 {
+                    vx = min_speed;
                     vy = 0.0;
                     vz = 0.0;
                 }
             } else {
-                let min_speed_sq = self.config.min_speed * self.config.min_speed;
-                let max_speed_sq = self.config.max_speed * self.config.max_speed;
+                let min_speed_sq = min_speed * min_speed;
+                let max_speed_sq = max_speed * max_speed;
                 if speed_sq < min_speed_sq {
                     let (nvx, nvy, nvz) = math::normalize_to_magnitude(
-                        self.config.math_mode,
+                        self.math(MathPath::Constraints),
                         vx,
                         vy,
                         if self.z_mode_enabled { vz } else { 0.0 },
-                        self.config.min_speed,
+                        min_speed,
                     );
                     vx = nvx;
                     vy = nvy;
@@ -109,11 +282,11 @@ impl Sim {
                     }
                 } else if speed_sq > max_speed_sq {
                     let (nvx, nvy, nvz) = math::normalize_to_magnitude(
-                        self.config.math_mode,
+                        self.math(MathPath::Constraints),
                         vx,
                         vy,
                         if self.z_mode_enabled { vz } else { 0.0 },
-                        self.config.max_speed,
+                        max_speed,
                     );
                     vx = nvx;
                     vy = nvy;
@@ -123,31 +296,54 @@ impl Sim {
                 }
             }
 
-            let (x, vx) = integrate_axis(self.pos_x[i], vx, dt, self.bounce_x);
-            let (y, vy) = integrate_axis(self.pos_y[i], vy, dt, self.bounce_y);
-            let (z, vz) = if self.z_mode_enabled {
-                integrate_axis(self.pos_z[i], vz, dt, self.bounce_z)
-            } else {
-                (DEFAULT_Z_LAYER, 0.0)
-            };
-
+            let (vx, vy, vz) = self.integrate_boid_in_wind(i, vx, vy, vz, wind, dt);
             self.vel_x[i] = vx;
             self.vel_y[i] = vy;
-            self.vel_z[i] = if self.z_mode_enabled { vz } else { 0.0 };
-            self.pos_x[i] = x;
-            self.pos_y[i] = y;
-            self.pos_z[i] = z;
+            self.vel_z[i] = vz;
         }
+    }
 
-        self.resolve_hard_min_distance_constraints();
-        self.sync_render_buffers();
-        self.debug_validate_state();
+    // Horizontal and z factors applied to the post-acceleration velocity. The
+    // force-based models integrate drag semi-implicitly so large coefficients
+    // cannot flip velocity.
+    fn drag_scale(
+        &self,
+        exponential_damping: (f32, f32),
+        vx: f32,
+        vy: f32,
+        vz: f32,
+        dt: f32,
+    ) -> (f32, f32) {
+        let (linear_weight, quadratic_weight) = match self.config.drag_model {
+            DragModel::Exponential => return exponential_damping,
+            DragModel::Linear => (1.0, 0.0),
+            DragModel::Quadratic => (0.0, 1.0),
+            DragModel::Combined => (1.0, 1.0),
+        };
+
+        let speed = (vx * vx + vy * vy + vz * vz).sqrt();
+        let (z_linear, z_quadratic) = self.config.z_drag_coefficients();
+        let force_scale = |linear: f32, quadratic: f32| {
+            1.0 / (1.0 + (linear * linear_weight + quadratic * quadratic_weight * speed) * dt)
+        };
+        (
+            force_scale(self.config.drag, self.config.quadratic_drag),
+            force_scale(z_linear, z_quadratic),
+        )
     }
 
-    fn compute_boids_acceleration(&self, i: usize) -> (f32, f32, f32, usize) {
+    fn compute_boids_acceleration(
+        &self,
+        i: usize,
+        has_custom_force: bool,
+        familiarity: &mut Familiarity,
+        debug: Option<&mut SteeringComponents>,
+    ) -> (f32, f32, f32, usize, usize) {
         let wrap_x = !self.bounce_x;
         let wrap_y = !self.bounce_y;
         let wrap_z = !self.bounce_z;
+        let state_weights = self.behavior.weights(i);
+        let role = self.classic_overrides(i);
         let px = self.pos_x[i];
         let py = self.pos_y[i];
         let pz = self.pos_z[i];
@@ -155,9 +351,23 @@ impl Sim {
         let vy = self.vel_y[i];
         let vz = self.vel_z[i];
 
-        let neighbor_radius_sq = self.config.neighbor_radius * self.config.neighbor_radius;
+        let speed = if self.z_mode_enabled {
+            math::distance_sq_3d(vx, vy, vz).sqrt()
+        } else {
+            (vx * vx + vy * vy).sqrt()
+        };
+        let neighbor_radius = self
+            .speed_radius
+            .radius(self.config.neighbor_radius * role.radius_scale, speed)
+            .min(MAX_NEIGHBOR_RADIUS);
+        let neighbor_radius_sq = neighbor_radius * neighbor_radius;
         let separation_radius_sq = self.config.separation_radius * self.config.separation_radius;
         let min_distance_sq = self.config.soft_min_distance * self.config.soft_min_distance;
+        let mut nearest = NearestNeighbors::new(if self.pair_avoidance.enabled() {
+            self.pair_avoidance.k
+        } else {
+            0
+        });
 
         let mut sep_x = 0.0;
         let mut sep_y = 0.0;
@@ -167,88 +377,207 @@ impl Sim {
         let mut align_x = 0.0;
         let mut align_y = 0.0;
         let mut align_z = 0.0;
+        let mut align_weight_sum = 0.0;
 
         let mut coh_x = 0.0;
         let mut coh_y = 0.0;
         let mut coh_z = 0.0;
+        let mut coh_weight_sum = 0.0;
+
+        let mut speed_sum = 0.0;
 
         let mut neighbor_count = 0usize;
         let mut neighbor_samples = 0usize;
-        let sample_cap = self.config.max_neighbors_sampled;
-
-        self.neighbor_grid.for_each_neighbor_with_wrap(
-            i,
-            self.config.neighbor_radius,
-            wrap_x,
-            wrap_y,
-            |j| {
-                if sample_cap > 0 && neighbor_samples >= sample_cap {
-                    return false;
-                }
-                neighbor_samples += 1;
+        let sample_cap = self.neighbor_sample_cap(i);
+        let align_to_headings = self.config.align_to_headings;
+        let separation_kernel = self.config.separation_kernel;
+        let cohesion_weight = |dist_sq: f32| {
+            if self.config.distance_weighted_cohesion {
+                (1.0 - dist_sq.sqrt() / neighbor_radius).max(0.0)
+            } else {
+                1.0
+            }
+        };
 
-                let dx = axis_delta(self.pos_x[j] - px, wrap_x);
-                let dy = axis_delta(self.pos_y[j] - py, wrap_y);
-                let dz = if self.z_mode_enabled {
-                    axis_delta(self.pos_z[j] - pz, wrap_z)
-                } else {
-                    0.0
+        let own_half = self.split_half(i);
+        let mut visit = |visit: GridVisit<'_>| {
+            if let Some(half) = own_half {
+                // Cells are judged by their centroid, which may sit across the
+                // divide from a few of their members.
+                let x = match visit {
+                    GridVisit::Point(j) => self.pos_x[j],
+                    GridVisit::Cell(aggregate) => aggregate.sum_x / aggregate.count as f32,
                 };
-                let dist_sq = math::distance_sq_3d(dx, dy, dz);
+                if SplitHalf::of(x) != half {
+                    return true;
+                }
+            }
+            if sample_cap > 0 && neighbor_samples >= sample_cap {
+                return false;
+            }
+            neighbor_samples += 1;
+
+            let j = match visit {
+                GridVisit::Point(j) => j,
+                GridVisit::Cell(aggregate) => {
+                    // Far cells lie outside the separation radius by construction,
+                    // so they only feed alignment and cohesion.
+                    let members = aggregate.count as f32;
+                    let inv_members = 1.0 / members;
+                    let dx = axis_delta(aggregate.sum_x * inv_members - px, wrap_x);
+                    let dy = axis_delta(aggregate.sum_y * inv_members - py, wrap_y);
+                    let dz = if self.z_mode_enabled {
+                        self.z_extent
+                            .delta(aggregate.sum_z * inv_members - pz, wrap_z)
+                    } else {
+                        0.0
+                    };
+                    let dist_sq = math::distance_sq_3d(dx, dy, dz);
+                    if dist_sq > neighbor_radius_sq {
+                        return true;
+                    }
 
-                if dist_sq <= EPSILON || dist_sq > neighbor_radius_sq {
+                    neighbor_count += aggregate.count as usize;
+                    let sum_vz = if self.z_mode_enabled {
+                        aggregate.sum_vz
+                    } else {
+                        0.0
+                    };
+                    // Cells only carry summed velocities, so heading alignment
+                    // counts the cell's mean direction once per member.
+                    let (cell_ax, cell_ay, cell_az) = if align_to_headings {
+                        let (hx, hy, hz) = normalize_or_default(
+                            aggregate.sum_vx,
+                            aggregate.sum_vy,
+                            sum_vz,
+                            0.0,
+                            0.0,
+                            0.0,
+                        );
+                        (hx * members, hy * members, hz * members)
+                    } else {
+                        (aggregate.sum_vx, aggregate.sum_vy, sum_vz)
+                    };
+                    align_x += cell_ax;
+                    align_y += cell_ay;
+                    align_z += cell_az;
+                    align_weight_sum += members;
+                    // The mean velocity's length stands in for the members'
+                    // speeds, which cells do not keep.
+                    speed_sum +=
+                        math::distance_sq_3d(aggregate.sum_vx, aggregate.sum_vy, sum_vz).sqrt();
+                    let weight = cohesion_weight(dist_sq) * members;
+                    coh_x += dx * weight;
+                    coh_y += dy * weight;
+                    coh_z += dz * weight;
+                    coh_weight_sum += weight;
                     return true;
                 }
+            };
 
-                neighbor_count += 1;
-                align_x += self.vel_x[j];
-                align_y += self.vel_y[j];
-                align_z += if self.z_mode_enabled {
-                    self.vel_z[j]
-                } else {
-                    0.0
-                };
+            let dx = axis_delta(self.pos_x[j] - px, wrap_x);
+            let dy = axis_delta(self.pos_y[j] - py, wrap_y);
+            let dz = if self.z_mode_enabled {
+                self.z_extent.delta(self.pos_z[j] - pz, wrap_z)
+            } else {
+                0.0
+            };
+            let dist_sq = math::distance_sq_3d(dx, dy, dz);
 
-                coh_x += dx;
-                coh_y += dy;
-                coh_z += dz;
-
-                if dist_sq <= separation_radius_sq {
-                    let inv_dist_sq = 1.0 / dist_sq.max(EPSILON);
-                    sep_x -= dx * inv_dist_sq;
-                    sep_y -= dy * inv_dist_sq;
-                    sep_z -= dz * inv_dist_sq;
-
-                    if min_distance_sq > EPSILON && dist_sq < min_distance_sq {
-                        let hard_push_mag =
-                            self.config.soft_min_distance * (1.0 - dist_sq / min_distance_sq);
-                        let (hard_x, hard_y, hard_z) = math::normalize_to_magnitude(
-                            self.config.math_mode,
-                            -dx,
-                            -dy,
-                            if self.z_mode_enabled { -dz } else { 0.0 },
-                            hard_push_mag,
-                        );
-                        sep_x += hard_x;
-                        sep_y += hard_y;
-                        sep_z += hard_z;
-                    }
+            if dist_sq <= EPSILON || dist_sq > neighbor_radius_sq {
+                return true;
+            }
 
-                    sep_count += 1;
+            neighbor_count += 1;
+            nearest.offer(dist_sq, j);
+            let vz_j = if self.z_mode_enabled {
+                self.vel_z[j]
+            } else {
+                0.0
+            };
+            speed_sum += math::distance_sq_3d(self.vel_x[j], self.vel_y[j], vz_j).sqrt();
+            let bond_weight = familiarity.touch(i, self.boid_ids[j]);
+            let (ax, ay, az) = if align_to_headings {
+                normalize_or_default(self.vel_x[j], self.vel_y[j], vz_j, 0.0, 0.0, 0.0)
+            } else {
+                (self.vel_x[j], self.vel_y[j], vz_j)
+            };
+            align_x += ax * bond_weight;
+            align_y += ay * bond_weight;
+            align_z += az * bond_weight;
+            align_weight_sum += bond_weight;
+
+            let coh_weight = cohesion_weight(dist_sq) * bond_weight;
+            coh_x += dx * coh_weight;
+            coh_y += dy * coh_weight;
+            coh_z += dz * coh_weight;
+            coh_weight_sum += coh_weight;
+
+            if dist_sq <= separation_radius_sq {
+                let weight =
+                    separation_kernel.offset_weight(dist_sq, self.config.separation_radius);
+                sep_x -= dx * weight;
+                sep_y -= dy * weight;
+                sep_z -= dz * weight;
+
+                if min_distance_sq > EPSILON && dist_sq < min_distance_sq {
+                    let hard_push_mag =
+                        self.config.soft_min_distance * (1.0 - dist_sq / min_distance_sq);
+                    let (hard_x, hard_y, hard_z) = math::normalize_to_magnitude(
+                        self.math(MathPath::Steering),
+                        -dx,
+                        -dy,
+                        if self.z_mode_enabled { -dz } else { 0.0 },
+                        hard_push_mag,
+                    );
+                    sep_x += hard_x;
+                    sep_y += hard_y;
+                    sep_z += hard_z;
                 }
 
-                true
-            },
-        );
+                sep_count += 1;
+            }
 
-        let mut force_x = 0.0;
-        let mut force_y = 0.0;
-        let mut force_z = 0.0;
+            true
+        };
 
+        if self.kd_tree_queries_enabled() {
+            self.kd_tree.for_each_neighbor_with_wrap(
+                i,
+                px,
+                py,
+                neighbor_radius,
+                WORLD_SIZE,
+                WORLD_SIZE,
+                wrap_x,
+                wrap_y,
+                |j| visit(GridVisit::Point(j)),
+            );
+        } else if self.config.far_field_opening > EPSILON {
+            self.neighbor_grid.for_each_neighbor_far_field(
+                i,
+                neighbor_radius,
+                wrap_x,
+                wrap_y,
+                self.config.separation_radius,
+                self.config.far_field_opening,
+                &mut visit,
+            );
+        } else {
+            self.neighbor_grid.for_each_neighbor_with_wrap(
+                i,
+                neighbor_radius,
+                wrap_x,
+                wrap_y,
+                |j| visit(GridVisit::Point(j)),
+            );
+        }
+
+        let mut separation = [0.0; 3];
         if sep_count > 0 {
             let n = sep_count as f32;
             let (steer_x, steer_y, steer_z) = steer_towards_3d(
-                self.config.math_mode,
+                self.math(MathPath::Steering),
                 sep_x / n,
                 sep_y / n,
                 sep_z / n,
@@ -257,68 +586,194 @@ impl Sim {
                 if self.z_mode_enabled { vz } else { 0.0 },
                 self.config.max_speed,
             );
-            force_x += steer_x * self.config.sep_weight;
-            force_y += steer_y * self.config.sep_weight;
-            force_z += steer_z * self.config.sep_weight * self.z_force_scale;
+            let sep_weight = self.config.sep_weight * state_weights.separation * role.separation;
+            separation = [
+                steer_x * sep_weight,
+                steer_y * sep_weight,
+                steer_z * sep_weight * self.z_force_scales.separation,
+            ];
         }
 
+        let mut alignment = [0.0; 3];
+        let mut cohesion = [0.0; 3];
+        let mut speed_match = [0.0; 3];
         if neighbor_count > 0 {
             let n = neighbor_count as f32;
 
+            let align_n = align_weight_sum.max(EPSILON);
             let (align_force_x, align_force_y, align_force_z) = steer_towards_3d(
-                self.config.math_mode,
-                align_x / n,
-                align_y / n,
-                align_z / n,
+                self.math(MathPath::Steering),
+                align_x / align_n,
+                align_y / align_n,
+                align_z / align_n,
                 vx,
                 vy,
                 if self.z_mode_enabled { vz } else { 0.0 },
                 self.config.max_speed,
             );
-            force_x += align_force_x * self.config.align_weight;
-            force_y += align_force_y * self.config.align_weight;
-            force_z += align_force_z * self.config.align_weight * self.z_force_scale;
+            let (align_weight, align_z_weight) = self.alignment_weights();
+            let (align_weight, align_z_weight) = (
+                align_weight * state_weights.alignment * role.alignment,
+                align_z_weight * state_weights.alignment * role.alignment,
+            );
+            alignment = [
+                align_force_x * align_weight,
+                align_force_y * align_weight,
+                align_force_z * align_z_weight,
+            ];
 
+            let coh_n = coh_weight_sum.max(EPSILON);
             let (coh_force_x, coh_force_y, coh_force_z) = steer_towards_3d(
-                self.config.math_mode,
-                coh_x / n,
-                coh_y / n,
-                coh_z / n,
+                self.math(MathPath::Steering),
+                coh_x / coh_n,
+                coh_y / coh_n,
+                coh_z / coh_n,
                 vx,
                 vy,
                 if self.z_mode_enabled { vz } else { 0.0 },
                 self.config.max_speed,
             );
-            force_x += coh_force_x * self.config.coh_weight;
-            force_y += coh_force_y * self.config.coh_weight;
-            force_z += coh_force_z * self.config.coh_weight * self.z_force_scale;
+            let coh_weight = self.config.coh_weight
+                * self.cohesion_scale(i)
+                * state_weights.cohesion
+                * role.cohesion;
+            cohesion = [
+                coh_force_x * coh_weight,
+                coh_force_y * coh_weight,
+                coh_force_z * coh_weight * self.z_force_scales.cohesion,
+            ];
+
+            if self.config.speed_match_weight > 0.0 {
+                let own_vz = if self.z_mode_enabled { vz } else { 0.0 };
+                let speed = math::distance_sq_3d(vx, vy, own_vz).sqrt();
+                if speed > EPSILON {
+                    let push = (speed_sum / n - speed) * self.config.speed_match_weight / speed;
+                    speed_match = [
+                        vx * push,
+                        vy * push,
+                        own_vz * push * self.z_force_scales.alignment,
+                    ];
+                }
+            }
         }
 
         if !self.z_mode_enabled {
-            force_z = 0.0;
+            separation[2] = 0.0;
+            alignment[2] = 0.0;
+            cohesion[2] = 0.0;
+            speed_match[2] = 0.0;
         }
 
-        if self.config.jitter_strength > 0.0 {
-            force_x += hash_unit(self.step_index, i as u32, 0) * self.config.jitter_strength;
-            force_y += hash_unit(self.step_index, i as u32, 1) * self.config.jitter_strength;
+        let mut jitter = [0.0; 3];
+        let jitter_strength =
+            self.config.jitter_strength * self.jitter_scale(i) * state_weights.jitter;
+        if jitter_strength > 0.0 {
+            jitter[0] = hash_unit(self.step_index, i as u32, 0) * jitter_strength;
+            jitter[1] = hash_unit(self.step_index, i as u32, 1) * jitter_strength;
             if self.z_mode_enabled {
-                force_z += hash_unit(self.step_index, i as u32, 2) * self.config.jitter_strength;
+                jitter[2] = hash_unit(self.step_index, i as u32, 2) * jitter_strength;
             }
         }
 
+        if let Some(debug) = debug {
+            *debug = SteeringComponents {
+                separation,
+                alignment,
+                cohesion,
+                jitter,
+                speed_match,
+            };
+        }
+
+        let mut force_x = separation[0] + alignment[0] + cohesion[0] + jitter[0] + speed_match[0];
+        let mut force_y = separation[1] + alignment[1] + cohesion[1] + jitter[1] + speed_match[1];
+        let mut force_z = separation[2] + alignment[2] + cohesion[2] + jitter[2] + speed_match[2];
+
         let (shape_force_x, shape_force_y, shape_force_z) = self.shape_attractor_force(i);
         force_x += shape_force_x;
         force_y += shape_force_y;
-        force_z += shape_force_z * self.z_force_scale;
+        force_z += shape_force_z * self.z_force_scales.attractor;
+
+        let (obstacle_x, obstacle_y, obstacle_z) = self.obstacle_force(i);
+        force_x += obstacle_x;
+        force_y += obstacle_y;
+        force_z += obstacle_z * self.z_force_scales.attractor;
+
+        if let Some((side, weight)) = self.predictive_avoidance(i) {
+            force_x += side[0] * weight;
+            force_y += side[1] * weight;
+            force_z += side[2] * weight * self.z_force_scales.attractor;
+        }
+
+        if self.pair_avoidance.enabled() {
+            let [pair_x, pair_y, pair_z] = self.pair_avoidance_force(i, &nearest);
+            force_x += pair_x;
+            force_y += pair_y;
+            force_z += pair_z * self.z_force_scales.separation;
+        }
+
+        let (danger_x, danger_y) = self.danger_zone_force(i);
+        force_x += danger_x;
+        force_y += danger_y;
+
+        let (corridor_x, corridor_y) = self.corridor_force(i);
+        force_x += corridor_x;
+        force_y += corridor_y;
+
+        force_z += self.terrain_lift(i);
+
+        let (noise_x, noise_y, noise_z) = self.turn_noise_force(i, vx, vy);
+        force_x += noise_x;
+        force_y += noise_y;
+        force_z += noise_z * self.z_force_scales.attractor;
+
+        if let Some((away, weight)) = self.exclusion_steer(i) {
+            force_x += away[0] * weight;
+            force_y += away[1] * weight;
+            force_z += away[2] * weight * self.z_force_scales.attractor;
+        }
+
+        let (flee_x, flee_y, flee_z) = self.predator_flee_force(i);
+        force_x += flee_x;
+        force_y += flee_y;
+        force_z += flee_z * self.z_force_scales.attractor;
+
+        let own_vz = if self.z_mode_enabled { vz } else { 0.0 };
+        let [seek_x, seek_y, seek_z] = self.behavior_seek_force(i, vx, vy, own_vz);
+        force_x += seek_x;
+        force_y += seek_y;
+        force_z += seek_z * self.z_force_scales.attractor;
+
+        if has_custom_force {
+            force_x += self.custom_force_x[i];
+            force_y += self.custom_force_y[i];
+            if self.z_mode_enabled {
+                force_z += self.custom_force_z[i];
+            }
+        }
 
         let (fx, fy, fz) = math::limit_magnitude_3d(
-            self.config.math_mode,
+            self.math(MathPath::Steering),
             force_x,
             force_y,
             force_z,
             self.config.max_force,
         );
 
-        (fx, fy, fz, neighbor_count)
+        (fx, fy, fz, neighbor_count, sep_count)
+    }
+}
+
+impl Sim {
+    fn kd_tree_queries_enabled(&self) -> bool {
+        self.neighbor_backend == NeighborBackend::KdTree && self.config.far_field_opening <= EPSILON
+    }
+}
+
+fn exponential_damping(drag: f32, dt: f32) -> f32 {
+    if drag <= EPSILON {
+        1.0
+    } else {
+        (-drag * dt).exp()
     }
 }
@@ -0,0 +1,73 @@
+use crate::math::MathPath;
+use crate::{hash_unit, math, ModelKind, Sim};
+
+impl Sim {
+    pub(super) fn fish_enabled(&self) -> bool {
+        self.model_kind == ModelKind::FishSchool
+    }
+
+    // Layers the fish-specific forces over the classic steering result held in
+    // the accel arrays: depth preference, burst thrust and coast drag.
+    pub(super) fn apply_fish_forces(&mut self, dt: f32) {
+        let fish = self.fish_config;
+        self.fish_phase = (self.fish_phase + dt / fish.burst_period).fract();
+
+        for i in 0..self.active_count {
+            // Stale accelerations already carry the fish forces.
+            if !self.lod_refreshes(i) {
+                continue;
+            }
+            let vx = self.vel_x[i];
+            let vy = self.vel_y[i];
+            let vz = if self.z_mode_enabled {
+                self.vel_z[i]
+            } else {
+                0.0
+            };
+
+            let phase = (self.fish_phase + self.fish_cycle_offset(i)).fract();
+            let (fx, fy, fz) = if phase < fish.burst_fraction {
+                math::normalize_to_magnitude(
+                    self.math(MathPath::Flight),
+                    vx,
+                    vy,
+                    vz,
+                    fish.burst_thrust,
+                )
+            } else {
+                (
+                    -vx * fish.coast_drag,
+                    -vy * fish.coast_drag,
+                    -vz * fish.coast_drag,
+                )
+            };
+
+            self.accel_x[i] += fx;
+            self.accel_y[i] += fy;
+            if self.z_mode_enabled {
+                self.accel_z[i] += fz + (fish.preferred_depth - self.pos_z[i]) * fish.depth_weight;
+            }
+        }
+    }
+
+    // Each fish keeps a fixed offset into the shared cycle so the school does
+    // not pulse in unison. It follows the boid's id, so reordering slots
+    // leaves every fish at its place in the cycle.
+    pub(super) fn fish_cycle_offset(&self, i: usize) -> f32 {
+        hash_unit(0, self.boid_ids[i], 5) * 0.5 + 0.5
+    }
+
+    // Fish align mostly within the horizontal plane; depth is left to the
+    // preferred-depth pull.
+    pub(super) fn alignment_weights(&self) -> (f32, f32) {
+        let align_z = self.config.align_weight * self.z_force_scales.alignment;
+        if self.fish_enabled() {
+            (
+                self.config.align_weight * self.fish_config.lateral_alignment,
+                align_z,
+            )
+        } else {
+            (self.config.align_weight, align_z)
+        }
+    }
+}
@@ -1,16 +1,18 @@
+use crate::familiarity::Familiarity;
 use crate::flock2::{
-    dot3, heading_basis, normalize_or_default, rotate_vector_around_axis,
-    FLOCK2_MAX_TOPOLOGICAL_NEIGHBORS, FLOCK2_WORLD_SCALE,
+    dot3, heading_basis, rotate_vector_around_axis, FLOCK2_INLINE_TOPOLOGICAL_NEIGHBORS,
+    FLOCK2_WORLD_SCALE,
 };
+use crate::math::MathPath;
+use crate::neighbor_memory::NeighborMemory;
 use crate::{
-    axis_delta, clamp_finite, integrate_axis, math, ModelKind, Sim, DEFAULT_Z_LAYER, EPSILON,
-    WORLD_SIZE,
+    axis_delta, clamp_finite, hash_unit, math, ModelKind, Sim, StepStage, DEFAULT_Z_LAYER, EPSILON,
 };
 
 impl Sim {
     pub(super) fn reseed_velocity_for_model(&mut self) {
         match self.model_kind {
-            ModelKind::Classic => {
+            ModelKind::Classic | ModelKind::FishSchool => {
                 for i in 0..self.count {
                     let mut vx = self.vel_x[i] * FLOCK2_WORLD_SCALE;
                     let mut vy = self.vel_y[i] * FLOCK2_WORLD_SCALE;
@@ -33,7 +35,7 @@ impl Sim {
                     }
 
                     let (nvx, nvy, nvz) = math::normalize_to_magnitude(
-                        self.config.math_mode,
+                        self.math(MathPath::Constraints),
                         vx,
                         vy,
                         if self.z_mode_enabled { vz } else { 0.0 },
@@ -47,7 +49,8 @@ impl Sim {
                     self.vel_x[i] = nvx;
                     self.vel_y[i] = nvy;
                     self.vel_z[i] = if self.z_mode_enabled { nvz } else { 0.0 };
-                    let (hx, hy, hz) = normalize_or_default(
+                    let (hx, hy, hz) = math::normalize_or_default(
+                        self.math(MathPath::Constraints),
                         self.vel_x[i],
                         self.vel_y[i],
                         self.vel_z[i],
@@ -65,8 +68,6 @@ impl Sim {
             | ModelKind::Flock2LiteSocial
             | ModelKind::Flock2LiteSocialFlight => {
                 self.flock2_config.sanitize();
-                self.neighbor_grid
-                    .set_cell_size(self.flock2_config.neighbor_radius);
 
                 for i in 0..self.count {
                     let mut vx = self.vel_x[i] / FLOCK2_WORLD_SCALE;
@@ -95,7 +96,7 @@ impl Sim {
                         self.flock2_config.min_speed,
                     );
                     let (nvx, nvy, nvz) = math::normalize_to_magnitude(
-                        self.config.math_mode,
+                        self.math(MathPath::Constraints),
                         vx,
                         vy,
                         if self.z_mode_enabled { vz } else { 0.0 },
@@ -104,7 +105,8 @@ impl Sim {
                     self.vel_x[i] = nvx;
                     self.vel_y[i] = nvy;
                     self.vel_z[i] = if self.z_mode_enabled { nvz } else { 0.0 };
-                    let (hx, hy, hz) = normalize_or_default(
+                    let (hx, hy, hz) = math::normalize_or_default(
+                        self.math(MathPath::Constraints),
                         self.vel_x[i],
                         self.vel_y[i],
                         self.vel_z[i],
@@ -125,14 +127,7 @@ impl Sim {
         self.neighbors_visited_last_step = 0;
 
         self.flock2_config.sanitize();
-        self.neighbor_grid
-            .set_cell_size(self.flock2_config.neighbor_radius);
-        self.neighbor_grid.rebuild(
-            &self.pos_x[..self.active_count],
-            &self.pos_y[..self.active_count],
-            WORLD_SIZE,
-            WORLD_SIZE,
-        );
+        self.refresh_neighbor_grid(self.neighbor_cell_size(), false);
 
         let mut centroid_x = 0.0;
         let mut centroid_y = 0.0;
@@ -150,17 +145,60 @@ impl Sim {
         centroid_x *= inv_active;
         centroid_y *= inv_active;
         centroid_z *= inv_active;
+        self.update_flock2_evasion(dt);
 
+        let mut memory = std::mem::take(&mut self.neighbor_memory);
+        if memory.enabled() {
+            memory.prepare(
+                self.boid_ids.len(),
+                self.flock2_config.topological_neighbors,
+            );
+        }
+        let mut familiarity = std::mem::take(&mut self.familiarity);
+        if familiarity.enabled() {
+            familiarity.prepare(self.boid_ids.len());
+        }
+        let mut heap_indices = std::mem::take(&mut self.topological_indices);
+        let mut heap_dsq = std::mem::take(&mut self.topological_dsq);
+        let topological_cap = self.flock2_config.topological_neighbors;
+        if topological_cap > FLOCK2_INLINE_TOPOLOGICAL_NEIGHBORS {
+            heap_indices.resize(topological_cap, usize::MAX);
+            heap_dsq.resize(topological_cap, f32::MAX);
+        }
         for i in 0..self.active_count {
-            let (next_hx, next_hy, next_hz, neighbors_used) =
-                self.compute_flock2_heading(i, dt, centroid_x, centroid_y, centroid_z);
+            if !self.lod_refreshes(i) {
+                continue;
+            }
+            let (next_hx, next_hy, next_hz, neighbors_used) = self.compute_flock2_heading(
+                i,
+                dt,
+                centroid_x,
+                centroid_y,
+                centroid_z,
+                &mut memory,
+                &mut familiarity,
+                &mut heap_indices,
+                &mut heap_dsq,
+            );
             self.accel_x[i] = next_hx;
             self.accel_y[i] = next_hy;
             self.accel_z[i] = next_hz;
             self.neighbors_visited_last_step += neighbors_used;
         }
-
+        self.neighbor_memory = memory;
+        self.topological_indices = heap_indices;
+        self.topological_dsq = heap_dsq;
+        familiarity.advance(dt);
+        self.familiarity = familiarity;
+        self.run_step_hook(StepStage::AfterForces);
+        self.run_step_hook(StepStage::BeforeIntegration);
+
+        if with_flight {
+            self.wind.advance(dt);
+        }
+        let calm_air = self.wind.is_calm();
         for i in 0..self.active_count {
+            let aero = self.depth_scaled_aero(i);
             self.heading_x[i] = self.accel_x[i];
             self.heading_y[i] = self.accel_y[i];
             self.heading_z[i] = if self.z_mode_enabled {
@@ -178,17 +216,33 @@ impl Sim {
                 })
             .sqrt();
             if speed <= EPSILON {
-                speed = self.flock2_config.min_speed;
+                speed = aero.min_speed;
             }
-            speed = speed.clamp(self.flock2_config.min_speed, self.flock2_config.max_speed);
+            speed = speed.clamp(aero.min_speed, aero.max_speed);
 
+            let mut flight_sample = None;
             if with_flight {
-                let v_axis = if speed > EPSILON {
+                // Aerodynamic forces act on the velocity relative to the air.
+                let (air_x, air_y, air_z, air_speed) = if calm_air {
+                    (self.vel_x[i], self.vel_y[i], self.vel_z[i], speed)
+                } else {
+                    let (wind_x, wind_y, wind_z) = self.wind.velocity_at_height(self.pos_y[i]);
+                    let air_x = self.vel_x[i] - wind_x / FLOCK2_WORLD_SCALE;
+                    let air_y = self.vel_y[i] - wind_y / FLOCK2_WORLD_SCALE;
+                    let air_z = if self.z_mode_enabled {
+                        self.vel_z[i] - wind_z / FLOCK2_WORLD_SCALE
+                    } else {
+                        0.0
+                    };
+                    let air_speed = (air_x * air_x + air_y * air_y + air_z * air_z).sqrt();
+                    (air_x, air_y, air_z, air_speed)
+                };
+                let v_axis = if air_speed > EPSILON {
                     (
-                        self.vel_x[i] / speed,
-                        self.vel_y[i] / speed,
+                        air_x / air_speed,
+                        air_y / air_speed,
                         if self.z_mode_enabled {
-                            self.vel_z[i] / speed
+                            air_z / air_speed
                         } else {
                             0.0
                         },
@@ -207,16 +261,11 @@ impl Sim {
                     },
                 );
 
-                let dynamic_pressure = 0.5
-                    * self.flock2_config.air_density
-                    * speed.max(self.flock2_config.min_speed).powi(2);
-                let lift_mag = dynamic_pressure
-                    * self.flock2_config.lift_factor
-                    * self.flock2_config.wing_area;
-                let drag_mag = dynamic_pressure
-                    * self.flock2_config.drag_factor
-                    * self.flock2_config.wing_area;
-                let gravity_force = self.flock2_config.gravity * self.flock2_config.mass;
+                let dynamic_pressure =
+                    0.5 * self.flock2_config.air_density * air_speed.max(aero.min_speed).powi(2);
+                let lift_mag = dynamic_pressure * aero.lift_factor * aero.wing_area;
+                let drag_mag = dynamic_pressure * aero.drag_factor * aero.wing_area;
+                let gravity_force = self.flock2_config.gravity * aero.mass;
 
                 let lift_x = up_x * lift_mag;
                 let lift_y = up_y * lift_mag;
@@ -234,10 +283,11 @@ impl Sim {
                     0.0
                 };
 
-                let thrust_x = self.heading_x[i] * self.flock2_config.thrust;
-                let thrust_y = self.heading_y[i] * self.flock2_config.thrust;
+                let thrust = self.burst_coast_thrust(i, aero.thrust);
+                let thrust_x = self.heading_x[i] * thrust;
+                let thrust_y = self.heading_y[i] * thrust;
                 let thrust_z = if self.z_mode_enabled {
-                    self.heading_z[i] * self.flock2_config.thrust
+                    self.heading_z[i] * thrust
                 } else {
                     0.0
                 };
@@ -250,10 +300,10 @@ impl Sim {
                     0.0
                 };
 
-                self.accel_x[i] = force_x / self.flock2_config.mass;
-                self.accel_y[i] = force_y / self.flock2_config.mass;
+                self.accel_x[i] = force_x / aero.mass;
+                self.accel_y[i] = force_y / aero.mass + self.altitude_hold_accel(i, dt);
                 self.accel_z[i] = if self.z_mode_enabled {
-                    force_z / self.flock2_config.mass
+                    force_z / aero.mass
                 } else {
                     0.0
                 };
@@ -265,6 +315,14 @@ impl Sim {
                 } else {
                     self.vel_z[i] = 0.0;
                 }
+
+                let raw_speed = (self.vel_x[i] * self.vel_x[i]
+                    + self.vel_y[i] * self.vel_y[i]
+                    + self.vel_z[i] * self.vel_z[i])
+                    .sqrt();
+                let thrust_power =
+                    thrust_x * self.vel_x[i] + thrust_y * self.vel_y[i] + thrust_z * self.vel_z[i];
+                flight_sample = Some((thrust_power, raw_speed < aero.min_speed));
             } else {
                 self.accel_x[i] = 0.0;
                 self.accel_y[i] = 0.0;
@@ -288,7 +346,7 @@ impl Sim {
             }
 
             let (vx, vy, vz) = math::normalize_to_magnitude(
-                self.config.math_mode,
+                self.math(MathPath::Flight),
                 self.vel_x[i],
                 self.vel_y[i],
                 if self.z_mode_enabled {
@@ -305,16 +363,17 @@ impl Sim {
                             0.0
                         })
                     .sqrt(),
-                    self.flock2_config.min_speed,
-                    self.flock2_config.max_speed,
-                    self.flock2_config.min_speed,
+                    aero.min_speed,
+                    aero.max_speed,
+                    aero.min_speed,
                 ),
             );
             self.vel_x[i] = vx;
             self.vel_y[i] = vy;
             self.vel_z[i] = if self.z_mode_enabled { vz } else { 0.0 };
 
-            let vel_norm = normalize_or_default(
+            let vel_norm = math::normalize_or_default(
+                self.math(MathPath::Flight),
                 self.vel_x[i],
                 self.vel_y[i],
                 if self.z_mode_enabled {
@@ -344,8 +403,15 @@ impl Sim {
             } else {
                 0.0
             };
-            let (hx, hy, hz) =
-                normalize_or_default(blended_hx, blended_hy, blended_hz, 1.0, 0.0, 0.0);
+            let (hx, hy, hz) = math::normalize_or_default(
+                self.math(MathPath::Flight),
+                blended_hx,
+                blended_hy,
+                blended_hz,
+                1.0,
+                0.0,
+                0.0,
+            );
             self.heading_x[i] = hx;
             self.heading_y[i] = hy;
             self.heading_z[i] = if self.z_mode_enabled { hz } else { 0.0 };
@@ -357,27 +423,18 @@ impl Sim {
             } else {
                 0.0
             };
-
-            let (x, vx_world_reflect) = integrate_axis(self.pos_x[i], vx_world, dt, self.bounce_x);
-            let (y, vy_world_reflect) = integrate_axis(self.pos_y[i], vy_world, dt, self.bounce_y);
-            let (z, vz_world_reflect) = if self.z_mode_enabled {
-                integrate_axis(self.pos_z[i], vz_world, dt, self.bounce_z)
-            } else {
-                (DEFAULT_Z_LAYER, 0.0)
-            };
-
-            self.pos_x[i] = x;
-            self.pos_y[i] = y;
-            self.pos_z[i] = z;
+            let (vx_world_reflect, vy_world_reflect, vz_world_reflect) =
+                self.integrate_boid(i, vx_world, vy_world, vz_world, dt);
             self.vel_x[i] = vx_world_reflect / FLOCK2_WORLD_SCALE;
             self.vel_y[i] = vy_world_reflect / FLOCK2_WORLD_SCALE;
-            self.vel_z[i] = if self.z_mode_enabled {
-                vz_world_reflect / FLOCK2_WORLD_SCALE
-            } else {
-                0.0
-            };
+            self.vel_z[i] = vz_world_reflect / FLOCK2_WORLD_SCALE;
+
+            if let Some((thrust_power, stalled)) = flight_sample {
+                self.record_flight_sample(i, thrust_power, stalled, dt);
+            }
         }
 
+        self.run_step_hook(StepStage::AfterConstraints);
         self.sync_render_buffers();
         self.debug_validate_state();
     }
@@ -387,14 +444,7 @@ impl Sim {
         self.neighbors_visited_last_step = 0;
 
         self.flock2_config.sanitize();
-        self.neighbor_grid
-            .set_cell_size(self.flock2_config.neighbor_radius);
-        self.neighbor_grid.rebuild(
-            &self.pos_x[..self.active_count],
-            &self.pos_y[..self.active_count],
-            WORLD_SIZE,
-            WORLD_SIZE,
-        );
+        self.refresh_neighbor_grid(self.neighbor_cell_size(), false);
 
         let mut centroid_x = 0.0;
         let mut centroid_y = 0.0;
@@ -412,8 +462,12 @@ impl Sim {
         centroid_x *= inv_active;
         centroid_y *= inv_active;
         centroid_z *= inv_active;
+        self.update_flock2_evasion(dt);
 
         for i in 0..self.active_count {
+            if !self.lod_refreshes(i) {
+                continue;
+            }
             let (next_hx, next_hy, next_hz, neighbors_used) =
                 self.compute_flock2_lite_heading(i, dt, centroid_x, centroid_y, centroid_z);
             self.accel_x[i] = next_hx;
@@ -421,8 +475,14 @@ impl Sim {
             self.accel_z[i] = next_hz;
             self.neighbors_visited_last_step += neighbors_used;
         }
+        self.run_step_hook(StepStage::AfterForces);
+        self.run_step_hook(StepStage::BeforeIntegration);
 
+        if with_flight {
+            self.wind.advance(dt);
+        }
         for i in 0..self.active_count {
+            let aero = self.depth_scaled_aero(i);
             self.heading_x[i] = self.accel_x[i];
             self.heading_y[i] = self.accel_y[i];
             self.heading_z[i] = if self.z_mode_enabled {
@@ -439,14 +499,26 @@ impl Sim {
                     0.0
                 })
             .sqrt()
-            .max(self.flock2_config.min_speed);
+            .max(aero.min_speed);
 
+            let mut flight_sample = None;
             if with_flight {
-                let drag_loss = self.flock2_config.drag_factor * speed * speed * 0.01;
+                // Drag acts on the airspeed along the heading, so a tailwind
+                // carries the bird and a headwind holds it back.
+                let (wind_x, wind_y, wind_z) = self.wind.velocity_at_height(self.pos_y[i]);
+                let wind_along = (wind_x * self.heading_x[i]
+                    + wind_y * self.heading_y[i]
+                    + wind_z * self.heading_z[i])
+                    / FLOCK2_WORLD_SCALE;
+                let air_speed = speed - wind_along;
+                let drag_loss = aero.drag_factor * air_speed * air_speed.abs() * 0.01;
                 let climb_loss = self.flock2_config.gravity * self.heading_y[i].max(0.0) * 0.02;
-                speed += (self.flock2_config.thrust - drag_loss - climb_loss) * dt;
+                let thrust = self.burst_coast_thrust(i, aero.thrust);
+                speed += (thrust - drag_loss - climb_loss) * dt;
+                // Thrust acts along the heading, which is also the velocity here.
+                flight_sample = Some((thrust * speed, speed < aero.min_speed));
             }
-            speed = speed.clamp(self.flock2_config.min_speed, self.flock2_config.max_speed);
+            speed = speed.clamp(aero.min_speed, aero.max_speed);
 
             self.vel_x[i] = self.heading_x[i] * speed;
             self.vel_y[i] = self.heading_y[i] * speed;
@@ -456,6 +528,10 @@ impl Sim {
                 0.0
             };
 
+            if with_flight {
+                self.vel_y[i] += self.altitude_hold_accel(i, dt) * dt;
+            }
+
             let (shape_force_x, shape_force_y, shape_force_z) = self.shape_attractor_force(i);
             self.vel_x[i] += shape_force_x * dt;
             self.vel_y[i] += shape_force_y * dt;
@@ -466,7 +542,7 @@ impl Sim {
             }
 
             let (vx, vy, vz) = math::normalize_to_magnitude(
-                self.config.math_mode,
+                self.math(MathPath::Flight),
                 self.vel_x[i],
                 self.vel_y[i],
                 if self.z_mode_enabled {
@@ -483,9 +559,9 @@ impl Sim {
                             0.0
                         })
                     .sqrt(),
-                    self.flock2_config.min_speed,
-                    self.flock2_config.max_speed,
-                    self.flock2_config.min_speed,
+                    aero.min_speed,
+                    aero.max_speed,
+                    aero.min_speed,
                 ),
             );
             self.vel_x[i] = vx;
@@ -499,30 +575,23 @@ impl Sim {
             } else {
                 0.0
             };
-            let (x, vx_world_reflect) = integrate_axis(self.pos_x[i], vx_world, dt, self.bounce_x);
-            let (y, vy_world_reflect) = integrate_axis(self.pos_y[i], vy_world, dt, self.bounce_y);
-            let (z, vz_world_reflect) = if self.z_mode_enabled {
-                integrate_axis(self.pos_z[i], vz_world, dt, self.bounce_z)
-            } else {
-                (DEFAULT_Z_LAYER, 0.0)
-            };
-
-            self.pos_x[i] = x;
-            self.pos_y[i] = y;
-            self.pos_z[i] = z;
+            let (vx_world_reflect, vy_world_reflect, vz_world_reflect) =
+                self.integrate_boid(i, vx_world, vy_world, vz_world, dt);
             self.vel_x[i] = vx_world_reflect / FLOCK2_WORLD_SCALE;
             self.vel_y[i] = vy_world_reflect / FLOCK2_WORLD_SCALE;
-            self.vel_z[i] = if self.z_mode_enabled {
-                vz_world_reflect / FLOCK2_WORLD_SCALE
-            } else {
-                0.0
-            };
+            self.vel_z[i] = vz_world_reflect / FLOCK2_WORLD_SCALE;
+
+            if let Some((thrust_power, stalled)) = flight_sample {
+                self.record_flight_sample(i, thrust_power, stalled, dt);
+            }
         }
 
+        self.run_step_hook(StepStage::AfterConstraints);
         self.sync_render_buffers();
         self.debug_validate_state();
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn compute_flock2_heading(
         &self,
         i: usize,
@@ -530,6 +599,10 @@ impl Sim {
         centroid_x: f32,
         centroid_y: f32,
         centroid_z: f32,
+        memory: &mut NeighborMemory,
+        familiarity: &mut Familiarity,
+        heap_indices: &mut [usize],
+        heap_dsq: &mut [f32],
     ) -> (f32, f32, f32, usize) {
         let wrap_x = !self.bounce_x;
         let wrap_y = !self.bounce_y;
@@ -537,7 +610,8 @@ impl Sim {
         let px = self.pos_x[i];
         let py = self.pos_y[i];
         let pz = self.pos_z[i];
-        let (fwd_x, fwd_y, fwd_z) = normalize_or_default(
+        let (fwd_x, fwd_y, fwd_z) = math::normalize_or_default(
+            self.math(MathPath::Steering),
             self.heading_x[i],
             self.heading_y[i],
             if self.z_mode_enabled {
@@ -551,18 +625,32 @@ impl Sim {
         );
         let (_, _, _, up_x, up_y, up_z, right_x, right_y, right_z) =
             heading_basis(fwd_x, fwd_y, fwd_z);
+        let mode = self.math(MathPath::Steering);
 
         let mut nearest_index = usize::MAX;
         let mut nearest_dist_sq = f32::MAX;
-        let mut topological_indices = [usize::MAX; FLOCK2_MAX_TOPOLOGICAL_NEIGHBORS];
-        let mut topological_dsq = [f32::MAX; FLOCK2_MAX_TOPOLOGICAL_NEIGHBORS];
+        let topological_cap = self.flock2_config.topological_neighbors;
+        let mut inline_indices = [usize::MAX; FLOCK2_INLINE_TOPOLOGICAL_NEIGHBORS];
+        let mut inline_dsq = [f32::MAX; FLOCK2_INLINE_TOPOLOGICAL_NEIGHBORS];
+        let (topological_indices, topological_dsq): (&mut [usize], &mut [f32]) =
+            if topological_cap <= FLOCK2_INLINE_TOPOLOGICAL_NEIGHBORS {
+                (&mut inline_indices, &mut inline_dsq)
+            } else {
+                let heap_indices = &mut heap_indices[..topological_cap];
+                let heap_dsq = &mut heap_dsq[..topological_cap];
+                heap_indices.fill(usize::MAX);
+                heap_dsq.fill(f32::MAX);
+                (heap_indices, heap_dsq)
+            };
         let mut topological_count = 0usize;
         let mut visible_neighbors = 0usize;
         let mut candidates_visited = 0usize;
-        let topological_cap = self.flock2_config.topological_neighbors;
         let fov_cos = self.flock2_config.fov_cos();
+        let avoid_fov_cos = self.flock2_config.avoid_fov_cos();
         let search_radius_sq =
             self.flock2_config.neighbor_radius * self.flock2_config.neighbor_radius;
+        let keep_scale = memory.keep_scale();
+        let remembered = memory.slot_mut(i);
 
         self.neighbor_grid.for_each_neighbor_with_wrap(
             i,
@@ -573,7 +661,7 @@ impl Sim {
                 let dx = axis_delta(self.pos_x[j] - px, wrap_x);
                 let dy = axis_delta(self.pos_y[j] - py, wrap_y);
                 let dz = if self.z_mode_enabled {
-                    axis_delta(self.pos_z[j] - pz, wrap_z)
+                    self.z_extent.delta(self.pos_z[j] - pz, wrap_z)
                 } else {
                     0.0
                 };
@@ -590,20 +678,32 @@ impl Sim {
                 } else {
                     0.0
                 };
+                // Avoidance and the social terms each see their own cone.
                 let forward_dot = dot3(fwd_x, fwd_y, fwd_z, dir_x, dir_y, dir_z);
-                if forward_dot < fov_cos {
+                let avoid_visible = forward_dot >= avoid_fov_cos;
+                let social_visible = forward_dot >= fov_cos;
+                if !avoid_visible && !social_visible {
                     return true;
                 }
 
-                visible_neighbors += 1;
                 candidates_visited += 1;
-                if dist_sq < nearest_dist_sq {
+                if avoid_visible && dist_sq < nearest_dist_sq {
                     nearest_dist_sq = dist_sq;
                     nearest_index = j;
                 }
+                if !social_visible {
+                    return true;
+                }
+                visible_neighbors += 1;
 
+                // Last step's neighbors rank as if a little closer.
+                let rank_dsq = if remembered.contains(&self.boid_ids[j]) {
+                    dist_sq * keep_scale
+                } else {
+                    dist_sq
+                };
                 let mut insert_at = topological_count;
-                while insert_at > 0 && dist_sq < topological_dsq[insert_at - 1] {
+                while insert_at > 0 && rank_dsq < topological_dsq[insert_at - 1] {
                     insert_at -= 1;
                 }
                 if insert_at < topological_cap {
@@ -614,7 +714,7 @@ impl Sim {
                         topological_indices[m] = topological_indices[m - 1];
                         m -= 1;
                     }
-                    topological_dsq[insert_at] = dist_sq;
+                    topological_dsq[insert_at] = rank_dsq;
                     topological_indices[insert_at] = j;
                     if topological_count < topological_cap {
                         topological_count += 1;
@@ -625,6 +725,14 @@ impl Sim {
             },
         );
 
+        for (k, id) in remembered.iter_mut().enumerate() {
+            *id = if k < topological_count {
+                self.boid_ids[topological_indices[k]]
+            } else {
+                u32::MAX
+            };
+        }
+
         let mut target_yaw = 0.0;
         let mut target_pitch = 0.0;
 
@@ -632,16 +740,24 @@ impl Sim {
             let dx = axis_delta(self.pos_x[nearest_index] - px, wrap_x);
             let dy = axis_delta(self.pos_y[nearest_index] - py, wrap_y);
             let dz = if self.z_mode_enabled {
-                axis_delta(self.pos_z[nearest_index] - pz, wrap_z)
+                self.z_extent.delta(self.pos_z[nearest_index] - pz, wrap_z)
             } else {
                 0.0
             };
-            let (dir_x, dir_y, dir_z) = normalize_or_default(-dx, -dy, -dz, 0.0, 0.0, 0.0);
+            let (dir_x, dir_y, dir_z) = math::normalize_or_default(
+                self.math(MathPath::Steering),
+                -dx,
+                -dy,
+                -dz,
+                0.0,
+                0.0,
+                0.0,
+            );
             let local_x = dot3(dir_x, dir_y, dir_z, fwd_x, fwd_y, fwd_z);
             let local_y = dot3(dir_x, dir_y, dir_z, up_x, up_y, up_z).clamp(-1.0, 1.0);
             let local_z = dot3(dir_x, dir_y, dir_z, right_x, right_y, right_z);
-            target_yaw += local_z.atan2(local_x) * self.flock2_config.avoid_weight;
-            target_pitch += local_y.asin() * self.flock2_config.avoid_weight;
+            target_yaw += math::atan2(mode, local_z, local_x) * self.flock2_config.avoid_weight;
+            target_pitch += math::asin(mode, local_y) * self.flock2_config.avoid_weight;
         }
 
         if topological_count > 0 {
@@ -652,25 +768,29 @@ impl Sim {
             let mut ave_pos_dy = 0.0;
             let mut ave_pos_dz = 0.0;
 
+            let mut weight_sum = 0.0;
+
             for idx in topological_indices.iter().take(topological_count) {
                 let j = *idx;
-                ave_vel_x += self.vel_x[j];
-                ave_vel_y += self.vel_y[j];
+                let weight = familiarity.touch(i, self.boid_ids[j]);
+                weight_sum += weight;
+                ave_vel_x += self.vel_x[j] * weight;
+                ave_vel_y += self.vel_y[j] * weight;
                 ave_vel_z += if self.z_mode_enabled {
-                    self.vel_z[j]
+                    self.vel_z[j] * weight
                 } else {
                     0.0
                 };
-                ave_pos_dx += axis_delta(self.pos_x[j] - px, wrap_x);
-                ave_pos_dy += axis_delta(self.pos_y[j] - py, wrap_y);
+                ave_pos_dx += axis_delta(self.pos_x[j] - px, wrap_x) * weight;
+                ave_pos_dy += axis_delta(self.pos_y[j] - py, wrap_y) * weight;
                 ave_pos_dz += if self.z_mode_enabled {
-                    axis_delta(self.pos_z[j] - pz, wrap_z)
+                    self.z_extent.delta(self.pos_z[j] - pz, wrap_z) * weight
                 } else {
                     0.0
                 };
             }
 
-            let inv_n = 1.0 / topological_count as f32;
+            let inv_n = 1.0 / weight_sum;
             ave_vel_x *= inv_n;
             ave_vel_y *= inv_n;
             ave_vel_z *= inv_n;
@@ -678,21 +798,37 @@ impl Sim {
             ave_pos_dy *= inv_n;
             ave_pos_dz *= inv_n;
 
-            let (align_x, align_y, align_z) =
-                normalize_or_default(ave_vel_x, ave_vel_y, ave_vel_z, 0.0, 0.0, 0.0);
+            let (align_x, align_y, align_z) = math::normalize_or_default(
+                self.math(MathPath::Steering),
+                ave_vel_x,
+                ave_vel_y,
+                ave_vel_z,
+                0.0,
+                0.0,
+                0.0,
+            );
             let align_local_x = dot3(align_x, align_y, align_z, fwd_x, fwd_y, fwd_z);
             let align_local_y = dot3(align_x, align_y, align_z, up_x, up_y, up_z).clamp(-1.0, 1.0);
             let align_local_z = dot3(align_x, align_y, align_z, right_x, right_y, right_z);
-            target_yaw += align_local_z.atan2(align_local_x) * self.flock2_config.align_weight;
-            target_pitch += align_local_y.asin() * self.flock2_config.align_weight;
-
-            let (coh_x, coh_y, coh_z) =
-                normalize_or_default(ave_pos_dx, ave_pos_dy, ave_pos_dz, 0.0, 0.0, 0.0);
+            target_yaw +=
+                math::atan2(mode, align_local_z, align_local_x) * self.flock2_config.align_weight;
+            target_pitch += math::asin(mode, align_local_y) * self.flock2_config.align_weight;
+
+            let (coh_x, coh_y, coh_z) = math::normalize_or_default(
+                self.math(MathPath::Steering),
+                ave_pos_dx,
+                ave_pos_dy,
+                ave_pos_dz,
+                0.0,
+                0.0,
+                0.0,
+            );
             let coh_local_x = dot3(coh_x, coh_y, coh_z, fwd_x, fwd_y, fwd_z);
             let coh_local_y = dot3(coh_x, coh_y, coh_z, up_x, up_y, up_z).clamp(-1.0, 1.0);
             let coh_local_z = dot3(coh_x, coh_y, coh_z, right_x, right_y, right_z);
-            target_yaw += coh_local_z.atan2(coh_local_x) * self.flock2_config.cohesion_weight;
-            target_pitch += coh_local_y.asin() * self.flock2_config.cohesion_weight;
+            target_yaw +=
+                math::atan2(mode, coh_local_z, coh_local_x) * self.flock2_config.cohesion_weight;
+            target_pitch += math::asin(mode, coh_local_y) * self.flock2_config.cohesion_weight;
         }
 
         if self.flock2_config.boundary_count > EPSILON
@@ -704,37 +840,96 @@ impl Sim {
             let to_centroid_x = axis_delta(centroid_x - px, wrap_x);
             let to_centroid_y = axis_delta(centroid_y - py, wrap_y);
             let to_centroid_z = if self.z_mode_enabled {
-                axis_delta(centroid_z - pz, wrap_z)
+                self.z_extent.delta(centroid_z - pz, wrap_z)
             } else {
                 0.0
             };
-            let (bound_x, bound_y, bound_z) =
-                normalize_or_default(to_centroid_x, to_centroid_y, to_centroid_z, 0.0, 0.0, 0.0);
+            let (bound_x, bound_y, bound_z) = math::normalize_or_default(
+                self.math(MathPath::Steering),
+                to_centroid_x,
+                to_centroid_y,
+                to_centroid_z,
+                0.0,
+                0.0,
+                0.0,
+            );
             let bound_local_x = dot3(bound_x, bound_y, bound_z, fwd_x, fwd_y, fwd_z);
             let bound_local_y = dot3(bound_x, bound_y, bound_z, up_x, up_y, up_z).clamp(-1.0, 1.0);
             let bound_local_z = dot3(bound_x, bound_y, bound_z, right_x, right_y, right_z);
-            target_yaw += bound_local_z.atan2(bound_local_x)
+            target_yaw += math::atan2(mode, bound_local_z, bound_local_x)
+                * self.flock2_config.boundary_weight
+                * boundary_ratio;
+            target_pitch += math::asin(mode, bound_local_y)
                 * self.flock2_config.boundary_weight
                 * boundary_ratio;
-            target_pitch +=
-                bound_local_y.asin() * self.flock2_config.boundary_weight * boundary_ratio;
         }
 
-        let reaction_gain = (dt * 1_000.0 / self.flock2_config.reaction_time_ms).clamp(0.0, 1.0);
-        let mut next_heading = rotate_vector_around_axis(
-            (fwd_x, fwd_y, fwd_z),
-            (up_x, up_y, up_z),
-            -target_yaw * reaction_gain,
-        );
+        let lateral: &[(f32, f32, f32)] = if self.z_mode_enabled {
+            &[(right_x, right_y, right_z), (up_x, up_y, up_z)]
+        } else {
+            &[(-fwd_y, fwd_x, 0.0)]
+        };
+        if let Some(((dir_x, dir_y, dir_z), weight)) =
+            self.flock2_obstacle_steer(i, (fwd_x, fwd_y, fwd_z), lateral)
+        {
+            let local_x = dot3(dir_x, dir_y, dir_z, fwd_x, fwd_y, fwd_z);
+            let local_y = dot3(dir_x, dir_y, dir_z, up_x, up_y, up_z).clamp(-1.0, 1.0);
+            let local_z = dot3(dir_x, dir_y, dir_z, right_x, right_y, right_z);
+            target_yaw += math::atan2(mode, local_z, local_x) * weight;
+            target_pitch += math::asin(mode, local_y) * weight;
+        }
+        let lift = self.terrain_lift(i);
+        if lift > 0.0 {
+            // Toward world +z, expressed in the heading frame.
+            let local_y = up_z.clamp(-1.0, 1.0);
+            target_yaw += math::atan2(mode, right_z, fwd_z) * lift;
+            target_pitch += math::asin(mode, local_y) * lift;
+        }
+        let (noise_yaw, noise_pitch) = self.turn_noise(i);
+        target_yaw += noise_yaw;
+        target_pitch += noise_pitch;
+        if let Some(([dir_x, dir_y, dir_z], weight)) = self.exclusion_steer(i) {
+            let local_x = dot3(dir_x, dir_y, dir_z, fwd_x, fwd_y, fwd_z);
+            let local_y = dot3(dir_x, dir_y, dir_z, up_x, up_y, up_z).clamp(-1.0, 1.0);
+            let local_z = dot3(dir_x, dir_y, dir_z, right_x, right_y, right_z);
+            target_yaw += math::atan2(mode, local_z, local_x) * weight;
+            target_pitch += math::asin(mode, local_y) * weight;
+        }
+        if let Some(([dir_x, dir_y, dir_z], weight)) = self.predictive_avoidance(i) {
+            let local_x = dot3(dir_x, dir_y, dir_z, fwd_x, fwd_y, fwd_z);
+            let local_y = dot3(dir_x, dir_y, dir_z, up_x, up_y, up_z).clamp(-1.0, 1.0);
+            let local_z = dot3(dir_x, dir_y, dir_z, right_x, right_y, right_z);
+            target_yaw += math::atan2(mode, local_z, local_x) * weight;
+            target_pitch += math::asin(mode, local_y) * weight;
+        }
+        if let Some(((dir_x, dir_y, dir_z), weight)) = self.flock2_evasion(i) {
+            let local_x = dot3(dir_x, dir_y, dir_z, fwd_x, fwd_y, fwd_z);
+            let local_y = dot3(dir_x, dir_y, dir_z, up_x, up_y, up_z).clamp(-1.0, 1.0);
+            let local_z = dot3(dir_x, dir_y, dir_z, right_x, right_y, right_z);
+            target_yaw += math::atan2(mode, local_z, local_x) * weight;
+            target_pitch += math::asin(mode, local_y) * weight;
+        }
+
+        let reaction_gain = (dt * 1_000.0 / self.flock2_reaction_time_ms(i)).clamp(0.0, 1.0);
+        // Positive yaw about `up` turns toward `right`, and a positive angle
+        // about `right` tilts the nose down, so pitch is negated to climb.
+        let mut yaw = target_yaw * reaction_gain;
+        if let Some(max_yaw) = self.flock2_config.max_turn_angle(self.flock2_speed(i), dt) {
+            yaw = yaw.clamp(-max_yaw, max_yaw);
+        }
+        let mut next_heading =
+            rotate_vector_around_axis(mode, (fwd_x, fwd_y, fwd_z), (up_x, up_y, up_z), yaw);
         let (_, _, _, _, _, _, next_right_x, next_right_y, next_right_z) =
             heading_basis(next_heading.0, next_heading.1, next_heading.2);
         next_heading = rotate_vector_around_axis(
+            mode,
             next_heading,
             (next_right_x, next_right_y, next_right_z),
-            target_pitch * reaction_gain,
+            -target_pitch * reaction_gain,
         );
 
-        let (hx, hy, hz) = normalize_or_default(
+        let (hx, hy, hz) = math::normalize_or_default(
+            self.math(MathPath::Steering),
             next_heading.0,
             next_heading.1,
             if self.z_mode_enabled {
@@ -746,6 +941,7 @@ impl Sim {
             0.0,
             0.0,
         );
+        let (hx, hy, hz) = self.apply_decision_noise(i, (hx, hy, hz));
         (hx, hy, hz, candidates_visited)
     }
 
@@ -763,7 +959,8 @@ impl Sim {
         let px = self.pos_x[i];
         let py = self.pos_y[i];
         let pz = self.pos_z[i];
-        let (fwd_x, fwd_y, fwd_z) = normalize_or_default(
+        let (fwd_x, fwd_y, fwd_z) = math::normalize_or_default(
+            self.math(MathPath::Steering),
             self.heading_x[i],
             self.heading_y[i],
             if self.z_mode_enabled {
@@ -777,7 +974,7 @@ impl Sim {
         );
         let fov_cos = self.flock2_config.fov_cos();
         let radius_sq = self.flock2_config.neighbor_radius * self.flock2_config.neighbor_radius;
-        let neighbor_cap = self.flock2_config.topological_neighbors.min(16);
+        let neighbor_cap = self.flock2_config.lite_neighbor_limit();
 
         let mut sep_x = 0.0;
         let mut sep_y = 0.0;
@@ -803,7 +1000,7 @@ impl Sim {
                 let dx = axis_delta(self.pos_x[j] - px, wrap_x);
                 let dy = axis_delta(self.pos_y[j] - py, wrap_y);
                 let dz = if self.z_mode_enabled {
-                    axis_delta(self.pos_z[j] - pz, wrap_z)
+                    self.z_extent.delta(self.pos_z[j] - pz, wrap_z)
                 } else {
                     0.0
                 };
@@ -833,7 +1030,8 @@ impl Sim {
                 sep_y -= dir_y * inv_dsq;
                 sep_z -= dir_z * inv_dsq;
 
-                let (avx, avy, avz) = normalize_or_default(
+                let (avx, avy, avz) = math::normalize_or_default(
+                    self.math(MathPath::Steering),
                     self.vel_x[j],
                     self.vel_y[j],
                     if self.z_mode_enabled {
@@ -884,18 +1082,62 @@ impl Sim {
             let to_center_x = axis_delta(centroid_x - px, wrap_x);
             let to_center_y = axis_delta(centroid_y - py, wrap_y);
             let to_center_z = if self.z_mode_enabled {
-                axis_delta(centroid_z - pz, wrap_z)
+                self.z_extent.delta(centroid_z - pz, wrap_z)
             } else {
                 0.0
             };
-            let (bcx, bcy, bcz) =
-                normalize_or_default(to_center_x, to_center_y, to_center_z, 0.0, 0.0, 0.0);
+            let (bcx, bcy, bcz) = math::normalize_or_default(
+                self.math(MathPath::Steering),
+                to_center_x,
+                to_center_y,
+                to_center_z,
+                0.0,
+                0.0,
+                0.0,
+            );
             target_x += bcx * self.flock2_config.boundary_weight * boundary_ratio;
             target_y += bcy * self.flock2_config.boundary_weight * boundary_ratio;
             target_z += bcz * self.flock2_config.boundary_weight * boundary_ratio;
         }
 
-        let (target_x, target_y, target_z) = normalize_or_default(
+        let (_, _, _, up_x, up_y, up_z, right_x, right_y, right_z) =
+            heading_basis(fwd_x, fwd_y, fwd_z);
+        let lateral: &[(f32, f32, f32)] = if self.z_mode_enabled {
+            &[(right_x, right_y, right_z), (up_x, up_y, up_z)]
+        } else {
+            &[(-fwd_y, fwd_x, 0.0)]
+        };
+        if let Some(((dir_x, dir_y, dir_z), weight)) =
+            self.flock2_obstacle_steer(i, (fwd_x, fwd_y, fwd_z), lateral)
+        {
+            target_x += dir_x * weight;
+            target_y += dir_y * weight;
+            target_z += dir_z * weight;
+        }
+        target_z += self.terrain_lift(i);
+        let (noise_yaw, noise_pitch) = self.turn_noise(i);
+        let side = lateral[0];
+        target_x += side.0 * noise_yaw + up_x * noise_pitch;
+        target_y += side.1 * noise_yaw + up_y * noise_pitch;
+        target_z += side.2 * noise_yaw + up_z * noise_pitch;
+        if let Some(([dir_x, dir_y, dir_z], weight)) = self.exclusion_steer(i) {
+            target_x += dir_x * weight;
+            target_y += dir_y * weight;
+            target_z += dir_z * weight;
+        }
+        if let Some(([dir_x, dir_y, dir_z], weight)) = self.predictive_avoidance(i) {
+            target_x += dir_x * weight;
+            target_y += dir_y * weight;
+            target_z += dir_z * weight;
+        }
+        if let Some(((dir_x, dir_y, dir_z), weight)) = self.flock2_evasion(i) {
+            target_x += dir_x * weight;
+            target_y += dir_y * weight;
+            target_z += dir_z * weight;
+        }
+
+        let (target_x, target_y, target_z) = math::normalize_or_default(
+            self.math(MathPath::Steering),
             target_x,
             target_y,
             if self.z_mode_enabled { target_z } else { 0.0 },
@@ -903,7 +1145,7 @@ impl Sim {
             fwd_y,
             if self.z_mode_enabled { fwd_z } else { 0.0 },
         );
-        let reaction_gain = (dt * 1_000.0 / self.flock2_config.reaction_time_ms).clamp(0.0, 1.0);
+        let reaction_gain = (dt * 1_000.0 / self.flock2_reaction_time_ms(i)).clamp(0.0, 1.0);
         let blend_x = fwd_x * (1.0 - reaction_gain) + target_x * reaction_gain;
         let blend_y = fwd_y * (1.0 - reaction_gain) + target_y * reaction_gain;
         let blend_z = if self.z_mode_enabled {
@@ -911,7 +1153,8 @@ impl Sim {
         } else {
             0.0
         };
-        let (hx, hy, hz) = normalize_or_default(
+        let (mut hx, mut hy, mut hz) = math::normalize_or_default(
+            self.math(MathPath::Steering),
             blend_x,
             blend_y,
             blend_z,
@@ -919,6 +1162,75 @@ impl Sim {
             fwd_y,
             if self.z_mode_enabled { fwd_z } else { 0.0 },
         );
+
+        // The lite model has no separate yaw, so the bank limit caps the whole
+        // turn toward the blended heading.
+        if let Some(max_turn) = self.flock2_config.max_turn_angle(self.flock2_speed(i), dt) {
+            let cos_turn = dot3(fwd_x, fwd_y, fwd_z, hx, hy, hz).clamp(-1.0, 1.0);
+            if cos_turn.acos() > max_turn {
+                let (perp_x, perp_y, perp_z) = math::normalize_or_default(
+                    self.math(MathPath::Steering),
+                    hx - fwd_x * cos_turn,
+                    hy - fwd_y * cos_turn,
+                    hz - fwd_z * cos_turn,
+                    0.0,
+                    0.0,
+                    0.0,
+                );
+                let (sin_max, cos_max) = max_turn.sin_cos();
+                (hx, hy, hz) = math::normalize_or_default(
+                    self.math(MathPath::Steering),
+                    fwd_x * cos_max + perp_x * sin_max,
+                    fwd_y * cos_max + perp_y * sin_max,
+                    fwd_z * cos_max + perp_z * sin_max,
+                    fwd_x,
+                    fwd_y,
+                    fwd_z,
+                );
+            }
+        }
+        let (hx, hy, hz) = self.apply_decision_noise(i, (hx, hy, hz));
         (hx, hy, hz, visited_count)
     }
+
+    // Turns a freshly decided heading by a random yaw and pitch, hashed from
+    // the seed, step and boid id so replays repeat it. Without z mode the
+    // turn stays in the xy plane.
+    fn apply_decision_noise(&self, i: usize, heading: (f32, f32, f32)) -> (f32, f32, f32) {
+        let amplitude = self.flock2_config.decision_noise_deg.to_radians();
+        if amplitude <= 0.0 {
+            return heading;
+        }
+        let key = self.step_index.wrapping_add(
+            self.flock2_config
+                .decision_noise_seed
+                .wrapping_mul(0x9E37_79B9),
+        );
+        let id = self.boid_ids[i];
+        let yaw = hash_unit(key, id, 3) * amplitude;
+        let mode = self.math(MathPath::Steering);
+        if !self.z_mode_enabled {
+            let (sin, cos) = math::sin_cos(mode, yaw);
+            return (
+                heading.0 * cos - heading.1 * sin,
+                heading.0 * sin + heading.1 * cos,
+                0.0,
+            );
+        }
+        let pitch = hash_unit(key, id, 4) * amplitude;
+        let (_, _, _, up_x, up_y, up_z, right_x, right_y, right_z) =
+            heading_basis(heading.0, heading.1, heading.2);
+        let turned = rotate_vector_around_axis(mode, heading, (up_x, up_y, up_z), yaw);
+        let (x, y, z) = rotate_vector_around_axis(mode, turned, (right_x, right_y, right_z), pitch);
+        math::normalize_or_default(mode, x, y, z, heading.0, heading.1, heading.2)
+    }
+
+    pub(super) fn flock2_speed(&self, i: usize) -> f32 {
+        let vz = if self.z_mode_enabled {
+            self.vel_z[i]
+        } else {
+            0.0
+        };
+        (self.vel_x[i] * self.vel_x[i] + self.vel_y[i] * self.vel_y[i] + vz * vz).sqrt()
+    }
 }
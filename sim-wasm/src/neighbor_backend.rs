@@ -0,0 +1,168 @@
+use crate::{Sim, EPSILON, FAR_FIELD_CELL_FRACTION, WORLD_SIZE};
+use wasm_bindgen::prelude::*;
+
+const MIN_KD_REBUILD_INTERVAL: u32 = 1;
+const MAX_KD_REBUILD_INTERVAL: u32 = 64;
+const MIN_GRID_REBUILD_INTERVAL: u32 = 1;
+const MAX_GRID_REBUILD_INTERVAL: u32 = 64;
+// Extra node visits per tree level on top of the points actually in range.
+const KD_VISITS_PER_LEVEL: f32 = 2.0;
+
+// Spatial index used for classic steering queries. Far-field mode and the
+// hard-constraint pass always use the grid.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NeighborBackend {
+    Grid,
+    KdTree,
+}
+
+impl NeighborBackend {
+    pub fn from_u32(value: u32) -> Self {
+        match value {
+            1 => Self::KdTree,
+            _ => Self::Grid,
+        }
+    }
+
+    pub fn as_u32(self) -> u32 {
+        match self {
+            Self::Grid => 0,
+            Self::KdTree => 1,
+        }
+    }
+}
+
+impl Sim {
+    // Rebuilds on schedule or when the active count changed; otherwise reuses
+    // the tree unless accumulated drift would let wrapped query images overlap.
+    pub(super) fn refresh_kd_tree(&mut self) {
+        let n = self.active_count;
+        let due = self.kd_tree.particle_count() != n
+            || self.kd_tree.steps_since_rebuild() + 1 >= self.kd_rebuild_interval;
+        if !due {
+            self.kd_tree
+                .reuse(&self.pos_x[..n], &self.pos_y[..n], WORLD_SIZE, WORLD_SIZE);
+            if self.config.neighbor_radius + self.kd_tree.query_slack() < 0.5 * WORLD_SIZE {
+                return;
+            }
+        }
+
+        self.kd_tree.rebuild(&self.pos_x[..n], &self.pos_y[..n]);
+    }
+
+    // Cell size the current model's steering pass queries with: the flock2
+    // radius under flock2 units, otherwise the classic radius, quartered while
+    // far-field aggregates are on.
+    pub(super) fn neighbor_cell_size(&self) -> f32 {
+        if self.model_kind.uses_flock2_units() {
+            self.flock2_config.neighbor_radius
+        } else if self.config.far_field_opening > EPSILON {
+            self.config.neighbor_radius * FAR_FIELD_CELL_FRACTION
+        } else {
+            self.config.neighbor_radius
+        }
+    }
+
+    // Puts the grid on the size the next step will query with, so a config
+    // change doesn't invalidate cells the step would only resize again.
+    pub(super) fn sync_neighbor_cell_size(&mut self) {
+        let cell_size = self.neighbor_cell_size();
+        self.neighbor_grid.set_cell_size(cell_size);
+    }
+
+    // Rebuilds on schedule, when the active count or cell size changed, or
+    // when drift has widened queries past half a cell; otherwise the grid from
+    // an earlier step is reused. Far-field aggregates need fresh cells, so
+    // callers pass `always` for them.
+    pub(super) fn refresh_neighbor_grid(&mut self, cell_size: f32, always: bool) {
+        let n = self.active_count;
+        let grid = &mut self.neighbor_grid;
+        grid.set_cell_cap(self.neighbor_cell_cap);
+        grid.set_scan_rotation(self.step_index);
+        let due = always
+            || grid.point_count() != n
+            || grid.cell_size() != cell_size
+            || grid.steps_since_rebuild().saturating_add(1) >= self.grid_rebuild_interval;
+        if !due {
+            grid.reuse(&self.pos_x[..n], &self.pos_y[..n]);
+            if grid.query_slack() < 0.5 * cell_size {
+                return;
+            }
+        }
+
+        grid.set_cell_size(cell_size);
+        grid.rebuild(&self.pos_x[..n], &self.pos_y[..n], WORLD_SIZE, WORLD_SIZE);
+    }
+
+    // Candidate distance checks per step under a uniform-density model. Only
+    // meant for comparing backends, not as an absolute timing.
+    fn estimated_neighbor_cost(&self, backend: NeighborBackend) -> f32 {
+        let n = self.active_count as f32;
+        let radius = self.config.neighbor_radius / WORLD_SIZE;
+        match backend {
+            // Cells match the radius, so every query scans a 3x3 block.
+            NeighborBackend::Grid => n * n * (9.0 * radius * radius).min(1.0),
+            NeighborBackend::KdTree => {
+                let levels = n.max(2.0).log2();
+                let per_query = n * (4.0 * radius * radius).min(1.0) + KD_VISITS_PER_LEVEL * levels;
+                n * per_query + n * levels / self.kd_rebuild_interval as f32
+            }
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl Sim {
+    pub fn set_neighbor_backend(&mut self, backend: u32) {
+        self.neighbor_backend = NeighborBackend::from_u32(backend);
+    }
+
+    pub fn neighbor_backend(&self) -> u32 {
+        self.neighbor_backend.as_u32()
+    }
+
+    pub fn set_kd_rebuild_interval(&mut self, steps: u32) {
+        self.kd_rebuild_interval = steps.clamp(MIN_KD_REBUILD_INTERVAL, MAX_KD_REBUILD_INTERVAL);
+    }
+
+    pub fn kd_rebuild_interval(&self) -> u32 {
+        self.kd_rebuild_interval
+    }
+
+    // Steps between steering-grid rebuilds for slow, dense flocks; 1 rebuilds
+    // every step.
+    pub fn set_grid_rebuild_interval(&mut self, steps: u32) {
+        self.grid_rebuild_interval =
+            steps.clamp(MIN_GRID_REBUILD_INTERVAL, MAX_GRID_REBUILD_INTERVAL);
+    }
+
+    pub fn grid_rebuild_interval(&self) -> u32 {
+        self.grid_rebuild_interval
+    }
+
+    // Most candidates a steering query scans in any one grid cell, so a flock
+    // collapsed onto one point stays cheap; crowded cells offer a subset that
+    // rotates every step. 0 scans everything. The kd-tree backend and the
+    // hard-constraint pass are never capped.
+    pub fn set_neighbor_cell_cap(&mut self, cap: usize) {
+        self.neighbor_cell_cap = cap;
+    }
+
+    pub fn neighbor_cell_cap(&self) -> usize {
+        self.neighbor_cell_cap
+    }
+
+    pub fn neighbor_backend_cost_estimate(&self, backend: u32) -> f32 {
+        self.estimated_neighbor_cost(NeighborBackend::from_u32(backend))
+    }
+
+    pub fn recommended_neighbor_backend(&self) -> u32 {
+        let grid = self.estimated_neighbor_cost(NeighborBackend::Grid);
+        let kd_tree = self.estimated_neighbor_cost(NeighborBackend::KdTree);
+        if kd_tree < grid {
+            NeighborBackend::KdTree.as_u32()
+        } else {
+            NeighborBackend::Grid.as_u32()
+        }
+    }
+}
@@ -1,7 +1,26 @@
+use wasm_bindgen::prelude::*;
+
 const MIN_BOUND: f32 = 1.0e-6;
 const MIN_CELL_SIZE: f32 = 1.0e-6;
 const INVALID_INDEX: usize = usize::MAX;
 
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CellAggregate {
+    pub count: u32,
+    pub sum_x: f32,
+    pub sum_y: f32,
+    pub sum_z: f32,
+    pub sum_vx: f32,
+    pub sum_vy: f32,
+    pub sum_vz: f32,
+}
+
+pub enum GridVisit<'a> {
+    Point(usize),
+    Cell(&'a CellAggregate),
+}
+
+#[wasm_bindgen]
 pub struct NeighborGrid {
     cell_size: f32,
     width: f32,
@@ -13,6 +32,16 @@ pub struct NeighborGrid {
     next: Vec<usize>,
     cached_x: Vec<f32>,
     cached_y: Vec<f32>,
+    aggregates: Vec<CellAggregate>,
+    steps_since_rebuild: u32,
+    query_slack: f32,
+    // Per-cell scan cap; 0 scans every candidate. Cells over the cap are also
+    // laid out contiguously in `cell_items` from `cell_start` so a query can
+    // jump straight to its window.
+    cell_cap: usize,
+    scan_rotation: u32,
+    cell_start: Vec<usize>,
+    cell_items: Vec<usize>,
 }
 
 impl NeighborGrid {
@@ -28,17 +57,19 @@ impl NeighborGrid {
             next: Vec::new(),
             cached_x: Vec::new(),
             cached_y: Vec::new(),
+            aggregates: Vec::new(),
+            steps_since_rebuild: 0,
+            query_slack: 0.0,
+            cell_cap: 0,
+            scan_rotation: 0,
+            cell_start: Vec::new(),
+            cell_items: Vec::new(),
         };
 
         grid.ensure_layout(count, grid.width, grid.height);
         grid
     }
 
-    pub fn set_cell_size(&mut self, cell_size: f32) {
-        self.cell_size = cell_size.max(MIN_CELL_SIZE);
-        self.ensure_layout(self.particle_count, self.width, self.height);
-    }
-
     pub fn rebuild(&mut self, positions_x: &[f32], positions_y: &[f32], width: f32, height: f32) {
         assert_eq!(positions_x.len(), positions_y.len());
 
@@ -48,6 +79,8 @@ impl NeighborGrid {
 
         self.ensure_layout(count, width, height);
         self.head.fill(INVALID_INDEX);
+        self.steps_since_rebuild = 0;
+        self.query_slack = 0.0;
 
         if count == 0 {
             return;
@@ -61,16 +94,73 @@ impl NeighborGrid {
             self.next[i] = self.head[cell];
             self.head[cell] = i;
         }
+        if self.cell_cap > 0 {
+            self.rebuild_cell_items();
+        }
+    }
+
+    // Counting sort of the built particles by cell, in slot order per cell.
+    fn rebuild_cell_items(&mut self) {
+        let cells = self.cols * self.rows;
+        self.cell_start.clear();
+        self.cell_start.resize(cells + 1, 0);
+        for i in 0..self.particle_count {
+            let cell = self.cell_index_for_position(self.cached_x[i], self.cached_y[i]);
+            self.cell_start[cell + 1] += 1;
+        }
+        for cell in 0..cells {
+            self.cell_start[cell + 1] += self.cell_start[cell];
+        }
+        let mut fill = self.cell_start[..cells].to_vec();
+        self.cell_items.resize(self.particle_count, 0);
+        for i in 0..self.particle_count {
+            let cell = self.cell_index_for_position(self.cached_x[i], self.cached_y[i]);
+            self.cell_items[fill[cell]] = i;
+            fill[cell] += 1;
+        }
+    }
+
+    // Shifts which candidates capped cells offer; the sim passes its step
+    // index so the choice rotates deterministically from step to step.
+    pub fn set_scan_rotation(&mut self, rotation: u32) {
+        self.scan_rotation = rotation;
+    }
+
+    pub fn steps_since_rebuild(&self) -> u32 {
+        self.steps_since_rebuild
+    }
+
+    pub fn query_slack(&self) -> f32 {
+        self.query_slack
+    }
+
+    // Forces the next scheduled refresh to rebuild.
+    pub fn invalidate(&mut self) {
+        self.steps_since_rebuild = u32::MAX;
+    }
+
+    // Keeps the cells from the last build for another step. Query origins and
+    // candidates both sit at their built positions, so queries widen by twice
+    // the largest displacement since then; callers must re-check distances
+    // against current positions.
+    pub fn reuse(&mut self, positions_x: &[f32], positions_y: &[f32]) {
+        let mut max_drift_sq = 0.0_f32;
+        for i in 0..self.particle_count {
+            let dx = wrapped_delta(positions_x[i] - self.cached_x[i], self.width);
+            let dy = wrapped_delta(positions_y[i] - self.cached_y[i], self.height);
+            max_drift_sq = max_drift_sq.max(dx * dx + dy * dy);
+        }
+        self.steps_since_rebuild = self.steps_since_rebuild.saturating_add(1);
+        self.query_slack = 2.0 * max_drift_sq.sqrt();
     }
 
-    #[allow(clippy::too_many_arguments)]
     pub fn for_each_neighbor_with_wrap<F>(
         &self,
         i: usize,
         radius: f32,
         wrap_x: bool,
         wrap_y: bool,
-        mut callback: F,
+        callback: F,
     ) where
         F: FnMut(usize) -> bool,
     {
@@ -78,12 +168,205 @@ impl NeighborGrid {
             return;
         }
 
-        let radius = radius.max(0.0);
+        let x = self.cached_x[i];
+        let y = self.cached_y[i];
+        self.for_each_within(i, x, y, radius, wrap_x, wrap_y, callback);
+    }
+
+    // Point queries have no owning particle, so nothing is excluded.
+    pub fn for_each_point_in_radius<F>(
+        &self,
+        x: f32,
+        y: f32,
+        radius: f32,
+        wrap_x: bool,
+        wrap_y: bool,
+        callback: F,
+    ) where
+        F: FnMut(usize) -> bool,
+    {
+        if self.particle_count == 0 {
+            return;
+        }
+
+        self.for_each_within(INVALID_INDEX, x, y, radius, wrap_x, wrap_y, callback);
+    }
+
+    // Visits points whose built position lies in the rectangle, widened by the
+    // reuse slack. No wrapping; callers re-check current positions.
+    pub fn for_each_point_in_rect<F>(
+        &self,
+        min_x: f32,
+        min_y: f32,
+        max_x: f32,
+        max_y: f32,
+        mut callback: F,
+    ) where
+        F: FnMut(usize) -> bool,
+    {
+        if self.particle_count == 0 {
+            return;
+        }
+
+        let (min_x, min_y) = (min_x - self.query_slack, min_y - self.query_slack);
+        let (max_x, max_y) = (max_x + self.query_slack, max_y + self.query_slack);
+        for cell_y in self.cell_y(min_y)..=self.cell_y(max_y) {
+            for cell_x in self.cell_x(min_x)..=self.cell_x(max_x) {
+                let mut candidate = self.head[cell_y as usize * self.cols + cell_x as usize];
+                while candidate != INVALID_INDEX {
+                    let x = self.cached_x[candidate];
+                    let y = self.cached_y[candidate];
+                    if (min_x..=max_x).contains(&x)
+                        && (min_y..=max_y).contains(&y)
+                        && !callback(candidate)
+                    {
+                        return;
+                    }
+                    candidate = self.next[candidate];
+                }
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn for_each_within<F>(
+        &self,
+        exclude: usize,
+        x: f32,
+        y: f32,
+        radius: f32,
+        wrap_x: bool,
+        wrap_y: bool,
+        mut callback: F,
+    ) where
+        F: FnMut(usize) -> bool,
+    {
+        let radius = radius.max(0.0) + self.query_slack;
         let radius_sq = radius * radius;
-        let cell_radius = (radius / self.cell_size).ceil() as isize;
 
+        self.for_each_cell_in_range(x, y, radius, wrap_x, wrap_y, |cell_x, cell_y| {
+            self.scan_cell(
+                cell_x,
+                cell_y,
+                exclude,
+                x,
+                y,
+                radius_sq,
+                wrap_x,
+                wrap_y,
+                &mut callback,
+            )
+        });
+    }
+
+    // Sums cached positions plus the supplied z/velocity channels per cell so
+    // far-field queries can treat a whole cell as one pseudo-particle.
+    pub fn rebuild_aggregates(
+        &mut self,
+        pos_z: &[f32],
+        vel_x: &[f32],
+        vel_y: &[f32],
+        vel_z: &[f32],
+    ) {
+        self.aggregates.clear();
+        self.aggregates
+            .resize(self.head.len(), CellAggregate::default());
+
+        for (cell, aggregate) in self.aggregates.iter_mut().enumerate() {
+            let mut candidate = self.head[cell];
+            while candidate != INVALID_INDEX {
+                aggregate.count += 1;
+                aggregate.sum_x += self.cached_x[candidate];
+                aggregate.sum_y += self.cached_y[candidate];
+                aggregate.sum_z += pos_z[candidate];
+                aggregate.sum_vx += vel_x[candidate];
+                aggregate.sum_vy += vel_y[candidate];
+                aggregate.sum_vz += vel_z[candidate];
+                candidate = self.next[candidate];
+            }
+        }
+    }
+
+    // Like `for_each_neighbor_with_wrap`, but cells that lie entirely beyond
+    // `near_radius` and subtend less than `opening` (cell size / distance) are
+    // reported once as an aggregate instead of per particle. Aggregates must be
+    // rebuilt after `rebuild` for the results to be meaningful.
+    #[allow(clippy::too_many_arguments)]
+    pub fn for_each_neighbor_far_field<F>(
+        &self,
+        i: usize,
+        radius: f32,
+        wrap_x: bool,
+        wrap_y: bool,
+        near_radius: f32,
+        opening: f32,
+        mut callback: F,
+    ) where
+        F: FnMut(GridVisit<'_>) -> bool,
+    {
+        if i >= self.particle_count || self.particle_count == 0 {
+            return;
+        }
+
+        let radius = radius.max(0.0) + self.query_slack;
+        let radius_sq = radius * radius;
+        let near_radius_sq = near_radius * near_radius;
+        let half_cell = self.cell_size * 0.5;
         let x = self.cached_x[i];
         let y = self.cached_y[i];
+
+        self.for_each_cell_in_range(x, y, radius, wrap_x, wrap_y, |cell_x, cell_y| {
+            let aggregate = self.aggregates.get(cell_y * self.cols + cell_x);
+            if let Some(aggregate) = aggregate.filter(|aggregate| aggregate.count > 1) {
+                let raw_dx = (cell_x as f32 + 0.5) * self.cell_size - x;
+                let raw_dy = (cell_y as f32 + 0.5) * self.cell_size - y;
+                let center_dx = if wrap_x {
+                    wrapped_delta(raw_dx, self.width)
+                } else {
+                    raw_dx
+                };
+                let center_dy = if wrap_y {
+                    wrapped_delta(raw_dy, self.height)
+                } else {
+                    raw_dy
+                };
+                let gap_x = (center_dx.abs() - half_cell).max(0.0);
+                let gap_y = (center_dy.abs() - half_cell).max(0.0);
+                let center_dist_sq = center_dx * center_dx + center_dy * center_dy;
+
+                if gap_x * gap_x + gap_y * gap_y > near_radius_sq
+                    && self.cell_size * self.cell_size < opening * opening * center_dist_sq
+                {
+                    return callback(GridVisit::Cell(aggregate));
+                }
+            }
+
+            self.scan_cell(
+                cell_x,
+                cell_y,
+                i,
+                x,
+                y,
+                radius_sq,
+                wrap_x,
+                wrap_y,
+                &mut |j| callback(GridVisit::Point(j)),
+            )
+        });
+    }
+
+    fn for_each_cell_in_range<F>(
+        &self,
+        x: f32,
+        y: f32,
+        radius: f32,
+        wrap_x: bool,
+        wrap_y: bool,
+        mut visit: F,
+    ) where
+        F: FnMut(usize, usize) -> bool,
+    {
+        let cell_radius = (radius / self.cell_size).ceil() as isize;
         let base_cell_x = self.cell_x(x);
         let base_cell_y = self.cell_y(y);
 
@@ -99,33 +382,13 @@ impl NeighborGrid {
                 if wrap_x {
                     for x_offset in -cell_radius..=cell_radius {
                         let cell_x = wrap_cell_index(base_cell_x + x_offset, self.cols);
-                        if !self.scan_cell(
-                            cell_x,
-                            cell_y,
-                            i,
-                            x,
-                            y,
-                            radius_sq,
-                            wrap_x,
-                            wrap_y,
-                            &mut callback,
-                        ) {
+                        if !visit(cell_x, cell_y) {
                             return;
                         }
                     }
                 } else {
                     for cell_x in min_x..=max_x {
-                        if !self.scan_cell(
-                            cell_x as usize,
-                            cell_y,
-                            i,
-                            x,
-                            y,
-                            radius_sq,
-                            wrap_x,
-                            wrap_y,
-                            &mut callback,
-                        ) {
+                        if !visit(cell_x as usize, cell_y) {
                             return;
                         }
                     }
@@ -138,33 +401,13 @@ impl NeighborGrid {
             if wrap_x {
                 for x_offset in -cell_radius..=cell_radius {
                     let cell_x = wrap_cell_index(base_cell_x + x_offset, self.cols);
-                    if !self.scan_cell(
-                        cell_x,
-                        cell_y as usize,
-                        i,
-                        x,
-                        y,
-                        radius_sq,
-                        wrap_x,
-                        wrap_y,
-                        &mut callback,
-                    ) {
+                    if !visit(cell_x, cell_y as usize) {
                         return;
                     }
                 }
             } else {
                 for cell_x in min_x..=max_x {
-                    if !self.scan_cell(
-                        cell_x as usize,
-                        cell_y as usize,
-                        i,
-                        x,
-                        y,
-                        radius_sq,
-                        wrap_x,
-                        wrap_y,
-                        &mut callback,
-                    ) {
+                    if !visit(cell_x as usize, cell_y as usize) {
                         return;
                     }
                 }
@@ -225,32 +468,169 @@ impl NeighborGrid {
         F: FnMut(usize) -> bool,
     {
         let cell_index = cell_y * self.cols + cell_x;
-        let mut candidate = self.head[cell_index];
+        let mut offer = |candidate: usize| {
+            if candidate == i {
+                return true;
+            }
+            let raw_dx = self.cached_x[candidate] - x;
+            let raw_dy = self.cached_y[candidate] - y;
+            let dx = if wrap_x {
+                wrapped_delta(raw_dx, self.width)
+            } else {
+                raw_dx
+            };
+            let dy = if wrap_y {
+                wrapped_delta(raw_dy, self.height)
+            } else {
+                raw_dy
+            };
+            dx * dx + dy * dy > radius_sq || callback(candidate)
+        };
 
+        if let Some(items) = self.capped_cell(cell_index) {
+            // A window of `cell_cap` members, wrapping around the cell. It
+            // moves with the rotation and differs per querying particle, so
+            // over a few steps every member gets seen.
+            let salt = if i == INVALID_INDEX { 0 } else { i };
+            let start = (self.scan_rotation as usize)
+                .wrapping_mul(self.cell_cap)
+                .wrapping_add(salt)
+                % items.len();
+            return (0..self.cell_cap).all(|k| offer(items[(start + k) % items.len()]));
+        }
+
+        let mut candidate = self.head[cell_index];
         while candidate != INVALID_INDEX {
-            if candidate != i {
-                let raw_dx = self.cached_x[candidate] - x;
-                let raw_dy = self.cached_y[candidate] - y;
-                let dx = if wrap_x {
-                    wrapped_delta(raw_dx, self.width)
-                } else {
-                    raw_dx
-                };
-                let dy = if wrap_y {
-                    wrapped_delta(raw_dy, self.height)
-                } else {
-                    raw_dy
-                };
-                if dx * dx + dy * dy <= radius_sq && !callback(candidate) {
-                    return false;
-                }
+            if !offer(candidate) {
+                return false;
             }
-
             candidate = self.next[candidate];
         }
 
         true
     }
+
+    fn capped_cell(&self, cell_index: usize) -> Option<&[usize]> {
+        if self.cell_cap == 0 || self.cell_start.len() != self.cols * self.rows + 1 {
+            return None;
+        }
+        let items = &self.cell_items[self.cell_start[cell_index]..self.cell_start[cell_index + 1]];
+        (items.len() > self.cell_cap).then_some(items)
+    }
+}
+
+// JS-facing surface so front-ends can index their own entities with the same
+// grid the simulation uses. Positions are copied in on `build`.
+#[wasm_bindgen]
+impl NeighborGrid {
+    #[wasm_bindgen(constructor)]
+    pub fn create(width: f32, height: f32, cell_size: f32) -> NeighborGrid {
+        NeighborGrid::new(0, width, height, cell_size)
+    }
+
+    // Changing the layout leaves the cells stale until the next rebuild.
+    pub fn set_cell_size(&mut self, cell_size: f32) {
+        let cell_size = cell_size.max(MIN_CELL_SIZE);
+        if cell_size != self.cell_size {
+            self.invalidate();
+        }
+        self.cell_size = cell_size;
+        self.ensure_layout(self.particle_count, self.width, self.height);
+    }
+
+    pub fn cell_size(&self) -> f32 {
+        self.cell_size
+    }
+
+    // Most candidates a radius query examines per cell; crowded cells offer a
+    // rotating subset. 0 scans every candidate. Applies from the next rebuild.
+    pub fn set_cell_cap(&mut self, cap: usize) {
+        if cap != self.cell_cap {
+            self.invalidate();
+        }
+        self.cell_cap = cap;
+        if cap == 0 {
+            self.cell_start = Vec::new();
+            self.cell_items = Vec::new();
+        }
+    }
+
+    pub fn cell_cap(&self) -> usize {
+        self.cell_cap
+    }
+
+    pub fn set_bounds(&mut self, width: f32, height: f32) {
+        self.invalidate();
+        self.ensure_layout(self.particle_count, width, height);
+    }
+
+    pub fn build(&mut self, positions_x: &[f32], positions_y: &[f32]) {
+        let count = positions_x.len().min(positions_y.len());
+        self.rebuild(
+            &positions_x[..count],
+            &positions_y[..count],
+            self.width,
+            self.height,
+        );
+    }
+
+    pub fn point_count(&self) -> usize {
+        self.particle_count
+    }
+
+    pub fn query_radius(
+        &self,
+        x: f32,
+        y: f32,
+        radius: f32,
+        wrap_x: bool,
+        wrap_y: bool,
+    ) -> Vec<u32> {
+        let mut found = Vec::new();
+        self.for_each_point_in_radius(x, y, radius, wrap_x, wrap_y, |j| {
+            found.push(j as u32);
+            true
+        });
+        found
+    }
+
+    // Allocation-free variant: fills `out` and returns how many indices were
+    // written, stopping early once it is full.
+    pub fn query_radius_into(
+        &self,
+        x: f32,
+        y: f32,
+        radius: f32,
+        wrap_x: bool,
+        wrap_y: bool,
+        out: &mut [u32],
+    ) -> usize {
+        let mut written = 0;
+        self.for_each_point_in_radius(x, y, radius, wrap_x, wrap_y, |j| {
+            if written >= out.len() {
+                return false;
+            }
+            out[written] = j as u32;
+            written += 1;
+            true
+        });
+        written
+    }
+
+    pub fn query_neighbors_of(
+        &self,
+        index: usize,
+        radius: f32,
+        wrap_x: bool,
+        wrap_y: bool,
+    ) -> Vec<u32> {
+        let mut found = Vec::new();
+        self.for_each_neighbor_with_wrap(index, radius, wrap_x, wrap_y, |j| {
+            found.push(j as u32);
+            true
+        });
+        found
+    }
 }
 
 fn wrap_cell_index(index: isize, len: usize) -> usize {
@@ -270,7 +650,7 @@ fn wrapped_delta(delta: f32, world_extent: f32) -> f32 {
 
 #[cfg(test)]
 mod tests {
-    use super::NeighborGrid;
+    use super::{GridVisit, NeighborGrid};
 
     fn sorted_neighbors(grid: &NeighborGrid, i: usize, radius: f32) -> Vec<usize> {
         let mut neighbors = Vec::new();
@@ -294,6 +674,53 @@ mod tests {
         assert_eq!(sorted_neighbors(&grid, 2, 2.0), Vec::<usize>::new());
     }
 
+    #[test]
+    fn far_cells_are_reported_as_aggregates() {
+        let mut pos_x = vec![0.5];
+        let mut pos_y = vec![0.5];
+        for k in 0..4 {
+            pos_x.push(0.80 + 0.01 * k as f32);
+            pos_y.push(0.5);
+        }
+        let zeros = vec![0.0; pos_x.len()];
+
+        let mut grid = NeighborGrid::new(pos_x.len(), 1.0, 1.0, 0.1);
+        grid.rebuild(&pos_x, &pos_y, 1.0, 1.0);
+        grid.rebuild_aggregates(&zeros, &zeros, &zeros, &zeros);
+
+        let mut points = 0;
+        let mut cell_members = 0;
+        grid.for_each_neighbor_far_field(0, 0.4, true, true, 0.05, 0.6, |visit| {
+            match visit {
+                GridVisit::Point(_) => points += 1,
+                GridVisit::Cell(aggregate) => cell_members += aggregate.count,
+            }
+            true
+        });
+
+        assert_eq!(points, 0);
+        assert_eq!(cell_members, 4);
+    }
+
+    #[test]
+    fn point_queries_include_every_point_in_range() {
+        let pos_x = vec![0.1, 0.15, 0.9, 0.5];
+        let pos_y = vec![0.1, 0.1, 0.1, 0.5];
+        let mut grid = NeighborGrid::create(1.0, 1.0, 0.1);
+        grid.build(&pos_x, &pos_y);
+
+        let mut found = grid.query_radius(0.05, 0.1, 0.16, true, true);
+        found.sort_unstable();
+        assert_eq!(found, vec![0, 1, 2]);
+
+        let mut out = [0_u32; 1];
+        assert_eq!(
+            grid.query_radius_into(0.05, 0.1, 0.16, true, true, &mut out),
+            1
+        );
+        assert_eq!(grid.query_radius(0.05, 0.1, 0.16, false, false).len(), 2);
+    }
+
     #[test]
     fn checks_across_cell_boundaries() {
         let pos_x = vec![1.9, 2.1, 5.0];
@@ -305,4 +732,29 @@ mod tests {
         assert_eq!(sorted_neighbors(&grid, 0, 0.25), vec![1]);
         assert_eq!(sorted_neighbors(&grid, 1, 0.25), vec![0]);
     }
+
+    #[test]
+    fn capped_cells_offer_a_rotating_window() {
+        let pos_x: Vec<f32> = (0..20).map(|k| 0.5 + 0.001 * k as f32).collect();
+        let pos_y = vec![0.5; 20];
+        let mut grid = NeighborGrid::new(pos_x.len(), 1.0, 1.0, 0.1);
+        grid.set_cell_cap(5);
+        grid.rebuild(&pos_x, &pos_y, 1.0, 1.0);
+
+        let mut seen = [false; 20];
+        for rotation in 0..4 {
+            grid.set_scan_rotation(rotation);
+            let found = sorted_neighbors(&grid, 0, 0.1);
+            assert!(found.len() <= 5 && found.len() >= 4, "{found:?}");
+            assert_eq!(found, sorted_neighbors(&grid, 0, 0.1));
+            for j in found {
+                seen[j] = true;
+            }
+        }
+        assert!(seen[1..].iter().all(|&seen| seen));
+
+        grid.set_cell_cap(0);
+        grid.rebuild(&pos_x, &pos_y, 1.0, 1.0);
+        assert_eq!(sorted_neighbors(&grid, 0, 0.1).len(), 19);
+    }
 }
@@ -0,0 +1,122 @@
+use crate::flock2::FLOCK2_INLINE_TOPOLOGICAL_NEIGHBORS;
+use crate::recording::FieldCodec;
+use crate::{clamp_finite, Sim};
+use wasm_bindgen::prelude::*;
+
+const MAX_NEIGHBOR_HYSTERESIS: f32 = 1.0;
+const EMPTY: u32 = u32::MAX;
+
+// Hysteresis for the full flock2 model's topological neighbor set. Each boid
+// remembers the ids it ranked last step, and a remembered neighbor keeps its
+// place until a newcomer is closer by more than `margin` of its distance, so
+// neighbors near the k-th rank stop flapping in and out every frame. Only the
+// nearest `FLOCK2_INLINE_TOPOLOGICAL_NEIGHBORS` are remembered. Flock2 Lite
+// takes the first neighbors it finds rather than ranking, so it is
+// unaffected. Off while the margin is 0.
+#[derive(Default)]
+pub struct NeighborMemory {
+    margin: f32,
+    width: usize,
+    ids: Vec<u32>,
+}
+
+impl NeighborMemory {
+    pub fn enabled(&self) -> bool {
+        self.margin > 0.0
+    }
+
+    // Sizes the memory for `count` boids remembering up to `cap` neighbors,
+    // forgetting everything when either changes.
+    pub fn prepare(&mut self, count: usize, cap: usize) {
+        let width = cap.min(FLOCK2_INLINE_TOPOLOGICAL_NEIGHBORS);
+        if width != self.width || self.ids.len() != count * width {
+            self.width = width;
+            self.ids.clear();
+            self.ids.resize(count * width, EMPTY);
+        }
+    }
+
+    // Squared-distance factor that ranks a remembered neighbor ahead of
+    // newcomers up to `margin` closer.
+    pub fn keep_scale(&self) -> f32 {
+        1.0 / ((1.0 + self.margin) * (1.0 + self.margin))
+    }
+
+    pub fn slot_mut(&mut self, i: usize) -> &mut [u32] {
+        self.ids
+            .get_mut(i * self.width..(i + 1) * self.width)
+            .unwrap_or_default()
+    }
+
+    pub fn forget(&mut self, i: usize) {
+        self.slot_mut(i).fill(EMPTY);
+    }
+
+    pub fn swap(&mut self, a: usize, b: usize) {
+        if self.width == 0 || self.ids.len() < (a.max(b) + 1) * self.width {
+            return;
+        }
+        for k in 0..self.width {
+            self.ids.swap(a * self.width + k, b * self.width + k);
+        }
+    }
+
+    pub fn visit_settings(&mut self, codec: &mut dyn FieldCodec) {
+        codec.f32(&mut self.margin);
+    }
+
+    // Memory holds at most `width` ids per slot of a `count`-boid sim.
+    pub fn visit_state(&mut self, codec: &mut dyn FieldCodec, count: usize) {
+        codec.usize(&mut self.width);
+        let mut len = self.ids.len();
+        codec.usize(&mut len);
+        if self.width > FLOCK2_INLINE_TOPOLOGICAL_NEIGHBORS {
+            codec.malformed();
+            self.width = FLOCK2_INLINE_TOPOLOGICAL_NEIGHBORS;
+        }
+        let width = self.width.max(1);
+        if len > count * self.width || !len.is_multiple_of(width) {
+            codec.malformed();
+            len = (len / width).min(count) * self.width;
+        }
+        self.ids.resize(len, EMPTY);
+        for id in &mut self.ids {
+            codec.u32(id);
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl Sim {
+    // Fraction closer, clamped to [0, 1], that a newcomer must be to push a
+    // remembered flock2 neighbor out of the topological set; 0 turns the
+    // memory off and frees it.
+    pub fn set_flock2_neighbor_hysteresis(&mut self, margin: f32) {
+        let memory = &mut self.neighbor_memory;
+        memory.margin = clamp_finite(margin, 0.0, MAX_NEIGHBOR_HYSTERESIS, 0.0);
+        if !memory.enabled() {
+            (memory.width, memory.ids) = (0, Vec::new());
+        }
+    }
+
+    pub fn flock2_neighbor_hysteresis(&self) -> f32 {
+        self.neighbor_memory.margin
+    }
+
+    // Ids of the topological neighbors the boid in `slot` kept last step;
+    // empty while the memory is off or for slots past the active count.
+    pub fn flock2_remembered_neighbors(&self, slot: usize) -> Vec<u32> {
+        let memory = &self.neighbor_memory;
+        if slot >= self.active_count {
+            return Vec::new();
+        }
+        memory
+            .ids
+            .get(slot * memory.width..(slot + 1) * memory.width)
+            .unwrap_or_default()
+            .iter()
+            .copied()
+            .filter(|&id| id != EMPTY)
+            .collect()
+    }
+}
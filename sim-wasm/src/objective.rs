@@ -0,0 +1,171 @@
+use crate::Sim;
+use wasm_bindgen::prelude::*;
+
+// What an objective sees on each evaluation. Event counters cover only the
+// step that just ran.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ObjectiveMetrics {
+    // Steps since the objective was installed, including this one.
+    pub steps: u32,
+    pub active_count: u32,
+    pub polarization: f32,
+    pub mean_speed: f32,
+    pub contact_count: u32,
+    pub remaining_overlaps: u32,
+    pub boundary_hits: u32,
+}
+
+// Returns this evaluation's contribution; the sim keeps the running sum.
+pub trait Objective {
+    fn evaluate(&mut self, metrics: &ObjectiveMetrics) -> f32;
+}
+
+pub struct ObjectiveState {
+    objective: Option<Box<dyn Objective>>,
+    interval: u32,
+    steps: u32,
+    score: f32,
+    evaluations: u32,
+}
+
+impl Default for ObjectiveState {
+    fn default() -> Self {
+        Self {
+            objective: None,
+            interval: 1,
+            steps: 0,
+            score: 0.0,
+            evaluations: 0,
+        }
+    }
+}
+
+#[wasm_bindgen(typescript_custom_section)]
+const OBJECTIVE_CALLBACK_TS: &str = r#"
+export type ObjectiveCallback = (
+  steps: number,
+  activeCount: number,
+  polarization: number,
+  meanSpeed: number,
+  contactCount: number,
+  remainingOverlaps: number,
+  boundaryHits: number,
+) => number;
+"#;
+
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(typescript_type = "ObjectiveCallback")]
+    pub type ObjectiveCallback;
+
+    #[allow(clippy::too_many_arguments)]
+    #[wasm_bindgen(method, js_name = call)]
+    fn invoke(
+        this: &ObjectiveCallback,
+        this_arg: &JsValue,
+        steps: u32,
+        active_count: u32,
+        polarization: f32,
+        mean_speed: f32,
+        contact_count: u32,
+        remaining_overlaps: u32,
+        boundary_hits: u32,
+    ) -> f64;
+}
+
+struct JsObjective {
+    callback: ObjectiveCallback,
+}
+
+impl Objective for JsObjective {
+    fn evaluate(&mut self, metrics: &ObjectiveMetrics) -> f32 {
+        self.callback.invoke(
+            &JsValue::NULL,
+            metrics.steps,
+            metrics.active_count,
+            metrics.polarization,
+            metrics.mean_speed,
+            metrics.contact_count,
+            metrics.remaining_overlaps,
+            metrics.boundary_hits,
+        ) as f32
+    }
+}
+
+impl Sim {
+    // Installs `objective` (or removes it with None), evaluated every
+    // `interval` steps. Both reset the accumulated score.
+    pub fn set_objective(&mut self, objective: Option<Box<dyn Objective>>, interval: u32) {
+        self.objective = ObjectiveState {
+            objective,
+            interval: interval.max(1),
+            ..ObjectiveState::default()
+        };
+    }
+
+    pub(super) fn run_objective(&mut self) {
+        let state = &mut self.objective;
+        if state.objective.is_none() {
+            return;
+        }
+        state.steps = state.steps.saturating_add(1);
+        if !state.steps.is_multiple_of(state.interval) {
+            return;
+        }
+
+        let (polarization, mean_speed) = self.polarization_and_mean_speed();
+        let events = &self.step_events;
+        let metrics = ObjectiveMetrics {
+            steps: self.objective.steps,
+            active_count: self.active_count as u32,
+            polarization,
+            mean_speed,
+            contact_count: events.contact_count,
+            remaining_overlaps: events.remaining_overlaps,
+            boundary_hits: events.boundary_hits.iter().sum(),
+        };
+        let state = &mut self.objective;
+        if let Some(objective) = state.objective.as_mut() {
+            let value = objective.evaluate(&metrics);
+            // A bad sample should not poison the whole run.
+            if value.is_finite() {
+                state.score += value;
+            }
+            state.evaluations += 1;
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl Sim {
+    // `callback` gets the metrics as arguments and returns a number that is
+    // added to the run's score. Not part of recordings; install it again
+    // before replaying.
+    pub fn set_objective_callback(&mut self, callback: ObjectiveCallback, interval: u32) {
+        self.set_objective(Some(Box::new(JsObjective { callback })), interval);
+    }
+
+    pub fn clear_objective(&mut self) {
+        self.set_objective(None, 1);
+    }
+
+    pub fn has_objective(&self) -> bool {
+        self.objective.objective.is_some()
+    }
+
+    // Starts a new run with the same objective and interval.
+    pub fn reset_objective_score(&mut self) {
+        let state = &mut self.objective;
+        state.steps = 0;
+        state.score = 0.0;
+        state.evaluations = 0;
+    }
+
+    pub fn objective_score(&self) -> f32 {
+        self.objective.score
+    }
+
+    pub fn objective_evaluations(&self) -> u32 {
+        self.objective.evaluations
+    }
+}
@@ -0,0 +1,359 @@
+use crate::flock2::{dot3, normalize_or_default};
+use crate::recording::FieldCodec;
+use crate::{axis_delta, clamp_finite, project_axis_position, Sim, DEFAULT_Z_LAYER, EPSILON};
+use wasm_bindgen::prelude::*;
+
+pub const MAX_OBSTACLES: usize = 64;
+const MAX_OBSTACLE_RADIUS: f32 = 0.5;
+const MAX_OBSTACLE_WEIGHT: f32 = 20.0;
+const DEFAULT_CLASSIC_OBSTACLE_WEIGHT: f32 = 4.0;
+const MAX_FLOCK2_OBSTACLE_WEIGHT: f32 = 4.0;
+const DEFAULT_FLOCK2_OBSTACLE_WEIGHT: f32 = 1.0;
+const MIN_OBSTACLE_LOOKAHEAD: f32 = 0.005;
+const MAX_OBSTACLE_LOOKAHEAD: f32 = 0.5;
+const DEFAULT_OBSTACLE_LOOKAHEAD: f32 = 0.08;
+const MAX_OBSTACLE_SPEED: f32 = 2.0;
+// Cap on how far ahead a moving obstacle's sweep is projected, for boids too
+// slow to cover the lookahead in reasonable time.
+const MAX_SWEEP_HORIZON_S: f32 = 1.0;
+
+// Spheres in world units, stored flat as x, y, z, radius. Without z mode they
+// act as circles in the xy plane. Each also has an xy velocity, zero unless
+// set through `update_obstacle`, that moves it every step.
+#[derive(Clone)]
+pub struct Obstacles {
+    spheres_xyzr: Vec<f32>,
+    velocities_xy: Vec<f32>,
+    classic_weight: f32,
+    flock2_weight: f32,
+    lookahead: f32,
+}
+
+impl Default for Obstacles {
+    fn default() -> Self {
+        Self {
+            spheres_xyzr: Vec::new(),
+            velocities_xy: Vec::new(),
+            classic_weight: DEFAULT_CLASSIC_OBSTACLE_WEIGHT,
+            flock2_weight: DEFAULT_FLOCK2_OBSTACLE_WEIGHT,
+            lookahead: DEFAULT_OBSTACLE_LOOKAHEAD,
+        }
+    }
+}
+
+impl Obstacles {
+    pub fn is_empty(&self) -> bool {
+        self.spheres_xyzr.is_empty()
+    }
+
+    pub fn visit_settings(&mut self, codec: &mut dyn FieldCodec) {
+        codec.f32(&mut self.classic_weight);
+        codec.f32(&mut self.flock2_weight);
+        codec.f32(&mut self.lookahead);
+        let mut values = self.spheres_xyzr.len();
+        codec.usize(&mut values);
+        self.spheres_xyzr
+            .resize(values.min(MAX_OBSTACLES * 4) / 4 * 4, 0.0);
+        self.velocities_xy.resize(self.spheres_xyzr.len() / 2, 0.0);
+        for sphere in self.spheres_xyzr.chunks_exact_mut(4) {
+            codec.f32(&mut sphere[2]);
+            codec.f32(&mut sphere[3]);
+        }
+    }
+
+    // Moving obstacles change every step, so their xy positions and
+    // velocities are state; logging them as settings would re-log every
+    // setting each step.
+    pub fn visit_state(&mut self, codec: &mut dyn FieldCodec) {
+        for sphere in self.spheres_xyzr.chunks_exact_mut(4) {
+            codec.f32(&mut sphere[0]);
+            codec.f32(&mut sphere[1]);
+        }
+        codec.f32_slice(&mut self.velocities_xy);
+    }
+
+    fn velocity(&self, index: usize) -> [f32; 2] {
+        [
+            self.velocities_xy[index * 2],
+            self.velocities_xy[index * 2 + 1],
+        ]
+    }
+}
+
+impl Sim {
+    pub(super) fn move_obstacles(&mut self, dt: f32) {
+        let obstacles = &mut self.obstacles;
+        for (s, v) in obstacles
+            .spheres_xyzr
+            .chunks_exact_mut(4)
+            .zip(obstacles.velocities_xy.chunks_exact(2))
+        {
+            s[0] = project_axis_position(s[0] + v[0] * dt, self.bounce_x);
+            s[1] = project_axis_position(s[1] + v[1] * dt, self.bounce_y);
+        }
+    }
+
+    fn boid_speed(&self, i: usize) -> f32 {
+        let (vx, vy, vz) = (self.vel_x[i], self.vel_y[i], self.vel_z[i]);
+        (vx * vx + vy * vy + vz * vz).sqrt()
+    }
+
+    // Seconds ahead a boid at `speed` looks for moving obstacles: the time it
+    // takes to cover the lookahead.
+    fn sweep_horizon(&self, speed: f32) -> f32 {
+        if speed * MAX_SWEEP_HORIZON_S <= self.obstacles.lookahead {
+            MAX_SWEEP_HORIZON_S
+        } else {
+            self.obstacles.lookahead / speed
+        }
+    }
+
+    // Offset from each obstacle centre to boid `i`, with the sphere radius and
+    // the obstacle's velocity.
+    pub(super) fn obstacle_offsets(
+        &self,
+        i: usize,
+    ) -> impl Iterator<Item = ([f32; 3], f32, [f32; 2])> + '_ {
+        let (px, py, pz) = (self.pos_x[i], self.pos_y[i], self.pos_z[i]);
+        let spheres = self.obstacles.spheres_xyzr.chunks_exact(4);
+        spheres.enumerate().map(move |(index, s)| {
+            let dz = if self.z_mode_enabled {
+                self.z_extent.delta(pz - s[2], !self.bounce_z)
+            } else {
+                0.0
+            };
+            (
+                [
+                    axis_delta(px - s[0], !self.bounce_x),
+                    axis_delta(py - s[1], !self.bounce_y),
+                    dz,
+                ],
+                s[3],
+                self.obstacles.velocity(index),
+            )
+        })
+    }
+
+    // Classic-model push away from every obstacle surface within the
+    // lookahead distance, growing linearly to full weight at contact. A moving
+    // obstacle counts as the capsule it sweeps over the boid's horizon, so
+    // boids clear its path instead of waiting for it to arrive.
+    pub(super) fn obstacle_force(&self, i: usize) -> (f32, f32, f32) {
+        let mut force = [0.0; 3];
+        let lookahead = self.obstacles.lookahead;
+        let horizon = self.sweep_horizon(self.boid_speed(i));
+        for (mut offset, radius, velocity) in self.obstacle_offsets(i) {
+            let sweep = [velocity[0] * horizon, velocity[1] * horizon];
+            let sweep_sq = sweep[0] * sweep[0] + sweep[1] * sweep[1];
+            if sweep_sq > EPSILON * EPSILON {
+                let along = (offset[0] * sweep[0] + offset[1] * sweep[1]) / sweep_sq;
+                let along = along.clamp(0.0, 1.0);
+                offset[0] -= sweep[0] * along;
+                offset[1] -= sweep[1] * along;
+            }
+            let dist =
+                (offset[0] * offset[0] + offset[1] * offset[1] + offset[2] * offset[2]).sqrt();
+            let clearance = dist - radius;
+            if clearance >= lookahead || dist <= EPSILON {
+                continue;
+            }
+            let strength =
+                self.obstacles.classic_weight * (1.0 - clearance.max(0.0) / lookahead) / dist;
+            for (f, o) in force.iter_mut().zip(offset) {
+                *f += o * strength;
+            }
+        }
+        (force[0], force[1], force[2])
+    }
+
+    // Distance the boid travels along unit `dir` before hitting the first
+    // obstacle surface within the lookahead (0 from inside one), with that
+    // obstacle's offset to the boid. Moving obstacles are traced in their own
+    // frame, where the boid flies along its velocity minus theirs.
+    fn obstacle_ray_hit(&self, i: usize, dir: (f32, f32, f32)) -> Option<(f32, [f32; 3])> {
+        let mut nearest: Option<(f32, [f32; 3])> = None;
+        let speed = self
+            .boid_speed(i)
+            .max(self.obstacles.lookahead / MAX_SWEEP_HORIZON_S);
+        for (offset, radius, velocity) in self.obstacle_offsets(i) {
+            let (ray, travel_per_unit) = if velocity == [0.0, 0.0] {
+                (dir, 1.0)
+            } else {
+                let rel = (
+                    dir.0 * speed - velocity[0],
+                    dir.1 * speed - velocity[1],
+                    dir.2 * speed,
+                );
+                let rel_speed = dot3(rel.0, rel.1, rel.2, rel.0, rel.1, rel.2).sqrt();
+                if rel_speed <= EPSILON {
+                    continue;
+                }
+                (
+                    (rel.0 / rel_speed, rel.1 / rel_speed, rel.2 / rel_speed),
+                    speed / rel_speed,
+                )
+            };
+            // `offset` points from the centre to the boid.
+            let b = -dot3(offset[0], offset[1], offset[2], ray.0, ray.1, ray.2);
+            let c = offset[0] * offset[0] + offset[1] * offset[1] + offset[2] * offset[2]
+                - radius * radius;
+            let t = if c <= 0.0 {
+                0.0
+            } else {
+                let disc = b * b - c;
+                if disc < 0.0 || b <= 0.0 {
+                    continue;
+                }
+                (b - disc.sqrt()) * travel_per_unit
+            };
+            if t <= self.obstacles.lookahead && nearest.is_none_or(|(n, _)| t < n) {
+                nearest = Some((t, offset));
+            }
+        }
+        nearest
+    }
+
+    // Flock2 casts probes along the heading and either side of it along each
+    // turn axis in `lateral`. The nearest hit sets the urgency, and the boid
+    // steers across its heading away from the obstacle that probe struck,
+    // taking the clearest probe when it is headed dead at the centre.
+    pub(super) fn flock2_obstacle_steer(
+        &self,
+        i: usize,
+        fwd: (f32, f32, f32),
+        lateral: &[(f32, f32, f32)],
+    ) -> Option<((f32, f32, f32), f32)> {
+        if self.obstacles.is_empty() || self.obstacles.flock2_weight <= 0.0 {
+            return None;
+        }
+        let mut nearest = self.obstacle_ray_hit(i, fwd);
+        let mut clearest = (fwd, f32::MAX);
+        for &side in lateral {
+            for sign in [1.0, -1.0] {
+                let dir = normalize_or_default(
+                    fwd.0 + side.0 * sign,
+                    fwd.1 + side.1 * sign,
+                    fwd.2 + side.2 * sign,
+                    fwd.0,
+                    fwd.1,
+                    fwd.2,
+                );
+                let hit = self.obstacle_ray_hit(i, dir);
+                let clearance = hit.map_or(f32::MAX, |(t, _)| t);
+                if clearest.0 == fwd || clearance > clearest.1 {
+                    clearest = (dir, clearance);
+                }
+                if let Some((t, offset)) = hit {
+                    if nearest.is_none_or(|(best, _)| t < best) {
+                        nearest = Some((t, offset));
+                    }
+                }
+            }
+        }
+        let (t, offset) = nearest?;
+        let along = dot3(offset[0], offset[1], offset[2], fwd.0, fwd.1, fwd.2);
+        let away = (
+            offset[0] - fwd.0 * along,
+            offset[1] - fwd.1 * along,
+            offset[2] - fwd.2 * along,
+        );
+        let (clear_x, clear_y, clear_z) = clearest.0;
+        let target = if dot3(away.0, away.1, away.2, away.0, away.1, away.2) > EPSILON {
+            normalize_or_default(away.0, away.1, away.2, clear_x, clear_y, clear_z)
+        } else {
+            clearest.0
+        };
+        let urgency = 1.0 - t / self.obstacles.lookahead;
+        Some((target, self.obstacles.flock2_weight * urgency))
+    }
+}
+
+#[wasm_bindgen]
+impl Sim {
+    // Flat x, y, z, radius per sphere in world units; invalid or empty
+    // spheres are skipped and at most `MAX_OBSTACLES` are kept. Velocities
+    // start at zero.
+    pub fn set_obstacles_xyzr(&mut self, spheres_xyzr: &[f32]) {
+        self.obstacles.spheres_xyzr.clear();
+        for sphere in spheres_xyzr.chunks_exact(4) {
+            if self.obstacles.spheres_xyzr.len() >= MAX_OBSTACLES * 4 {
+                break;
+            }
+            let radius = clamp_finite(sphere[3], 0.0, MAX_OBSTACLE_RADIUS, 0.0);
+            if radius <= EPSILON {
+                continue;
+            }
+            self.obstacles.spheres_xyzr.extend_from_slice(&[
+                clamp_finite(sphere[0], 0.0, 1.0, 0.5),
+                clamp_finite(sphere[1], 0.0, 1.0, 0.5),
+                clamp_finite(sphere[2], 0.0, 1.0, DEFAULT_Z_LAYER),
+                radius,
+            ]);
+        }
+        self.obstacles
+            .velocities_xy
+            .resize(self.obstacles.spheres_xyzr.len() / 2, 0.0);
+        self.obstacles.velocities_xy.fill(0.0);
+    }
+
+    pub fn obstacle_count(&self) -> usize {
+        self.obstacles.spheres_xyzr.len() / 4
+    }
+
+    // Moves obstacle `id`, its index in the last `set_obstacles_xyzr` list,
+    // and sets the xy velocity it keeps moving with between updates.
+    // Velocities are clamped to 2 world units per second per axis. Returns
+    // false for an unknown id.
+    pub fn update_obstacle(&mut self, id: usize, x: f32, y: f32, vx: f32, vy: f32) -> bool {
+        if id >= self.obstacle_count() {
+            return false;
+        }
+        let obstacles = &mut self.obstacles;
+        obstacles.spheres_xyzr[id * 4] = clamp_finite(x, 0.0, 1.0, 0.5);
+        obstacles.spheres_xyzr[id * 4 + 1] = clamp_finite(y, 0.0, 1.0, 0.5);
+        obstacles.velocities_xy[id * 2] =
+            clamp_finite(vx, -MAX_OBSTACLE_SPEED, MAX_OBSTACLE_SPEED, 0.0);
+        obstacles.velocities_xy[id * 2 + 1] =
+            clamp_finite(vy, -MAX_OBSTACLE_SPEED, MAX_OBSTACLE_SPEED, 0.0);
+        true
+    }
+
+    // Flat x, y, z, radius per sphere, including any movement since the last
+    // update.
+    pub fn obstacles_xyzr(&self) -> Vec<f32> {
+        self.obstacles.spheres_xyzr.clone()
+    }
+
+    // `classic_weight` scales the classic repulsion force, `flock2_weight`
+    // the flock2 yaw/pitch command; both ramp up over `lookahead` world
+    // units from the surface.
+    pub fn set_obstacle_avoidance(
+        &mut self,
+        classic_weight: f32,
+        flock2_weight: f32,
+        lookahead: f32,
+    ) {
+        self.obstacles.classic_weight = clamp_finite(
+            classic_weight,
+            0.0,
+            MAX_OBSTACLE_WEIGHT,
+            DEFAULT_CLASSIC_OBSTACLE_WEIGHT,
+        );
+        self.obstacles.flock2_weight = clamp_finite(
+            flock2_weight,
+            0.0,
+            MAX_FLOCK2_OBSTACLE_WEIGHT,
+            DEFAULT_FLOCK2_OBSTACLE_WEIGHT,
+        );
+        self.obstacles.lookahead = clamp_finite(
+            lookahead,
+            MIN_OBSTACLE_LOOKAHEAD,
+            MAX_OBSTACLE_LOOKAHEAD,
+            DEFAULT_OBSTACLE_LOOKAHEAD,
+        );
+    }
+
+    pub fn obstacle_lookahead(&self) -> f32 {
+        self.obstacles.lookahead
+    }
+}
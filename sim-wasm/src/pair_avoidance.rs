@@ -0,0 +1,185 @@
+use crate::recording::FieldCodec;
+use crate::{axis_delta, clamp_finite, Sim, EPSILON};
+use wasm_bindgen::prelude::*;
+
+pub const MAX_PAIR_NEIGHBORS: usize = 16;
+const DEFAULT_PAIR_NEIGHBORS: u32 = 4;
+const MAX_PAIR_WEIGHT: f32 = 20.0;
+const MIN_PAIR_HORIZON_S: f32 = 0.05;
+const MAX_PAIR_HORIZON_S: f32 = 2.0;
+const DEFAULT_PAIR_HORIZON_S: f32 = 0.5;
+const MIN_PAIR_CONTACT: f32 = 0.001;
+const MAX_PAIR_CONTACT: f32 = 0.1;
+const DEFAULT_PAIR_CONTACT: f32 = 0.012;
+
+// Time-to-collision steering between classic boids. Each boid looks at its
+// `k` nearest neighbors, finds when each pair is closest under their current
+// velocities, and if that is within the horizon and nearer than the contact
+// distance it pushes off along the miss direction. Both boids of a pair see
+// the same course, so the dodge is shared. Off while the weight is zero.
+#[derive(Clone, Copy)]
+pub struct PairAvoidance {
+    pub weight: f32,
+    pub k: u32,
+    horizon_s: f32,
+    contact: f32,
+}
+
+impl Default for PairAvoidance {
+    fn default() -> Self {
+        Self {
+            weight: 0.0,
+            k: DEFAULT_PAIR_NEIGHBORS,
+            horizon_s: DEFAULT_PAIR_HORIZON_S,
+            contact: DEFAULT_PAIR_CONTACT,
+        }
+    }
+}
+
+impl PairAvoidance {
+    pub fn enabled(&self) -> bool {
+        self.weight > 0.0 && self.k > 0
+    }
+
+    pub fn visit_settings(&mut self, codec: &mut dyn FieldCodec) {
+        codec.f32(&mut self.weight);
+        codec.u32(&mut self.k);
+        codec.f32(&mut self.horizon_s);
+        codec.f32(&mut self.contact);
+    }
+}
+
+// The closest neighbors seen so far, nearest first.
+pub struct NearestNeighbors {
+    len: usize,
+    cap: usize,
+    items: [(f32, usize); MAX_PAIR_NEIGHBORS],
+}
+
+impl NearestNeighbors {
+    pub fn new(k: u32) -> Self {
+        Self {
+            len: 0,
+            cap: (k as usize).min(MAX_PAIR_NEIGHBORS),
+            items: [(0.0, 0); MAX_PAIR_NEIGHBORS],
+        }
+    }
+
+    pub fn offer(&mut self, dist_sq: f32, j: usize) {
+        if self.cap == 0 || (self.len == self.cap && dist_sq >= self.items[self.len - 1].0) {
+            return;
+        }
+        let mut at = self.len.min(self.cap - 1);
+        while at > 0 && self.items[at - 1].0 > dist_sq {
+            self.items[at] = self.items[at - 1];
+            at -= 1;
+        }
+        self.items[at] = (dist_sq, j);
+        self.len = (self.len + 1).min(self.cap);
+    }
+
+    fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.items[..self.len].iter().map(|&(_, j)| j)
+    }
+}
+
+impl Sim {
+    pub(super) fn pair_avoidance_force(&self, i: usize, nearest: &NearestNeighbors) -> [f32; 3] {
+        let pair = self.pair_avoidance;
+        let mut force = [0.0; 3];
+        for j in nearest.iter() {
+            let offset = [
+                axis_delta(self.pos_x[i] - self.pos_x[j], !self.bounce_x),
+                axis_delta(self.pos_y[i] - self.pos_y[j], !self.bounce_y),
+                if self.z_mode_enabled {
+                    self.z_extent
+                        .delta(self.pos_z[i] - self.pos_z[j], !self.bounce_z)
+                } else {
+                    0.0
+                },
+            ];
+            let rel = [
+                self.vel_x[i] - self.vel_x[j],
+                self.vel_y[i] - self.vel_y[j],
+                if self.z_mode_enabled {
+                    self.vel_z[i] - self.vel_z[j]
+                } else {
+                    0.0
+                },
+            ];
+            let speed_sq = rel[0] * rel[0] + rel[1] * rel[1] + rel[2] * rel[2];
+            if speed_sq <= EPSILON * EPSILON {
+                continue;
+            }
+            let t = -(offset[0] * rel[0] + offset[1] * rel[1] + offset[2] * rel[2]) / speed_sq;
+            if t <= 0.0 || t > pair.horizon_s {
+                continue;
+            }
+            let miss = [
+                offset[0] + rel[0] * t,
+                offset[1] + rel[1] * t,
+                offset[2] + rel[2] * t,
+            ];
+            let miss_len = (miss[0] * miss[0] + miss[1] * miss[1] + miss[2] * miss[2]).sqrt();
+            if miss_len >= pair.contact {
+                continue;
+            }
+            let strength =
+                pair.weight * (1.0 - t / pair.horizon_s) * (1.0 - miss_len / pair.contact);
+            let side = if miss_len > EPSILON {
+                miss.map(|m| m / miss_len)
+            } else {
+                // Head on: each turns left of its own relative course, which
+                // sends the two to opposite sides.
+                let planar = (rel[0] * rel[0] + rel[1] * rel[1]).sqrt().max(EPSILON);
+                [-rel[1] / planar, rel[0] / planar, 0.0]
+            };
+            for (f, s) in force.iter_mut().zip(side) {
+                *f += s * strength;
+            }
+        }
+        force
+    }
+}
+
+#[wasm_bindgen]
+impl Sim {
+    // Classic and fish models only. Weight is clamped to [0, 20] (0 turns it
+    // off), `k` to 16 neighbors, the horizon to [0.05, 2] seconds and the
+    // contact distance to [0.001, 0.1] world units. The far-field
+    // approximation does not feed it from aggregated cells.
+    pub fn set_pair_avoidance(&mut self, weight: f32, k: u32, horizon_s: f32, contact: f32) {
+        self.pair_avoidance = PairAvoidance {
+            weight: clamp_finite(weight, 0.0, MAX_PAIR_WEIGHT, 0.0),
+            k: k.min(MAX_PAIR_NEIGHBORS as u32),
+            horizon_s: clamp_finite(
+                horizon_s,
+                MIN_PAIR_HORIZON_S,
+                MAX_PAIR_HORIZON_S,
+                DEFAULT_PAIR_HORIZON_S,
+            ),
+            contact: clamp_finite(
+                contact,
+                MIN_PAIR_CONTACT,
+                MAX_PAIR_CONTACT,
+                DEFAULT_PAIR_CONTACT,
+            ),
+        };
+    }
+
+    pub fn pair_avoidance_weight(&self) -> f32 {
+        self.pair_avoidance.weight
+    }
+
+    pub fn pair_avoidance_neighbors(&self) -> u32 {
+        self.pair_avoidance.k
+    }
+
+    pub fn pair_avoidance_horizon_s(&self) -> f32 {
+        self.pair_avoidance.horizon_s
+    }
+
+    pub fn pair_avoidance_contact(&self) -> f32 {
+        self.pair_avoidance.contact
+    }
+}
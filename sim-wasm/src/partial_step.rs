@@ -0,0 +1,80 @@
+use crate::{ModelKind, Sim};
+use wasm_bindgen::prelude::*;
+
+// Progress of a steering pass spread over several `step_partial` calls.
+// `next` is the first slot still to steer, or None when no pass has begun.
+#[derive(Default)]
+pub struct PartialStep {
+    pub next: Option<usize>,
+    pub neighbors_visited: usize,
+    pub active_count: usize,
+    pub has_custom_force: bool,
+    // The settings and state the pass began from are already in the log.
+    pub recorded: bool,
+}
+
+#[wasm_bindgen]
+impl Sim {
+    // Computes steering for up to `budget_hint` more boids and returns false
+    // until every active boid is done; the call that finishes the pass also
+    // integrates the step, exactly as `step(dt)` would. Only the classic and
+    // fish models split their work, other models step in full on the first
+    // call. Positions should not be edited between calls of one frame, and a
+    // change in active count restarts the pass. Calling `step` mid-pass
+    // finishes the pass first. A recording logs the state the pass began
+    // from, so replaying it reproduces the sliced steps exactly.
+    pub fn step_partial(&mut self, dt: f32, budget_hint: usize) -> bool {
+        let splittable = matches!(self.model_kind, ModelKind::Classic | ModelKind::FishSchool);
+        if self.paused || !splittable || self.active_count == 0 || self.classic_steering_disabled()
+        {
+            self.partial_step = PartialStep::default();
+            self.step(dt);
+            return true;
+        }
+
+        if self.partial_step.next.is_none() {
+            let recorded = self.is_recording_active();
+            if recorded {
+                self.record_before_pass();
+            }
+            let has_custom_force = self.begin_classic_steering();
+            self.partial_step = PartialStep {
+                next: Some(0),
+                active_count: self.active_count,
+                has_custom_force,
+                recorded,
+                ..PartialStep::default()
+            };
+        } else if self.partial_step.active_count != self.active_count {
+            let has_custom_force = self.prepare_classic_steering();
+            self.partial_step = PartialStep {
+                next: Some(0),
+                active_count: self.active_count,
+                has_custom_force,
+                recorded: self.partial_step.recorded,
+                ..PartialStep::default()
+            };
+        }
+
+        let start = self.partial_step.next.unwrap_or(0);
+        let end = start
+            .saturating_add(budget_hint.max(1))
+            .min(self.active_count);
+        let visited = self.compute_classic_steering(start, end, self.partial_step.has_custom_force);
+        self.partial_step.neighbors_visited += visited;
+        if end < self.active_count {
+            self.partial_step.next = Some(end);
+            return false;
+        }
+
+        // Only the first substep of a fast-forwarded frame reuses the pass.
+        self.partial_step.next = Some(end);
+        self.step(dt);
+        self.partial_step = PartialStep::default();
+        true
+    }
+
+    pub fn partial_step_pending(&self) -> bool {
+        self.partial_step.next.is_some()
+    }
+}
@@ -0,0 +1,241 @@
+use crate::recording::FieldCodec;
+use crate::{axis_delta, clamp_finite, Sim, DEFAULT_Z_LAYER, EPSILON};
+use wasm_bindgen::prelude::*;
+
+pub const MAX_PREDATORS: usize = 16;
+const MIN_PREDATOR_RADIUS: f32 = 0.01;
+const MAX_PREDATOR_RADIUS: f32 = 0.5;
+const DEFAULT_PREDATOR_RADIUS: f32 = 0.15;
+const MAX_CLASSIC_FLEE_WEIGHT: f32 = 20.0;
+const DEFAULT_CLASSIC_FLEE_WEIGHT: f32 = 3.0;
+const MAX_FLOCK2_EVASION_WEIGHT: f32 = 8.0;
+const DEFAULT_FLOCK2_EVASION_WEIGHT: f32 = 2.0;
+const MAX_EVASION_REFRACTORY_S: f32 = 10.0;
+const DEFAULT_EVASION_REFRACTORY_S: f32 = 1.0;
+const MAX_PREDATOR_SPEED: f32 = 2.0;
+
+// Threat positions are supplied by the host each frame, in world units.
+#[derive(Clone)]
+pub struct Predators {
+    pub positions_xyz: Vec<f32>,
+    velocities_xyz: Vec<f32>,
+    radius: f32,
+    classic_weight: f32,
+    flock2_weight: f32,
+    refractory_s: f32,
+}
+
+impl Default for Predators {
+    fn default() -> Self {
+        Self {
+            positions_xyz: Vec::new(),
+            velocities_xyz: Vec::new(),
+            radius: DEFAULT_PREDATOR_RADIUS,
+            classic_weight: DEFAULT_CLASSIC_FLEE_WEIGHT,
+            flock2_weight: DEFAULT_FLOCK2_EVASION_WEIGHT,
+            refractory_s: DEFAULT_EVASION_REFRACTORY_S,
+        }
+    }
+}
+
+impl Predators {
+    pub fn is_empty(&self) -> bool {
+        self.positions_xyz.is_empty()
+    }
+
+    pub fn visit_settings(&mut self, codec: &mut dyn FieldCodec) {
+        codec.f32(&mut self.radius);
+        codec.f32(&mut self.classic_weight);
+        codec.f32(&mut self.flock2_weight);
+        codec.f32(&mut self.refractory_s);
+        let mut values = self.positions_xyz.len();
+        codec.usize(&mut values);
+        self.positions_xyz
+            .resize(values.min(MAX_PREDATORS * 3) / 3 * 3, 0.0);
+        codec.f32_slice(&mut self.positions_xyz);
+        self.velocities_xyz.resize(self.positions_xyz.len(), 0.0);
+        codec.f32_slice(&mut self.velocities_xyz);
+    }
+}
+
+impl Sim {
+    // Offset from each predator to boid `i`, with the predator's velocity.
+    pub(super) fn predator_offsets(
+        &self,
+        i: usize,
+    ) -> impl Iterator<Item = ([f32; 3], &[f32])> + '_ {
+        let predators = &self.predators;
+        let positions = predators.positions_xyz.chunks_exact(3);
+        positions
+            .zip(predators.velocities_xyz.chunks_exact(3))
+            .map(move |(p, v)| {
+                let away = [
+                    axis_delta(self.pos_x[i] - p[0], !self.bounce_x),
+                    axis_delta(self.pos_y[i] - p[1], !self.bounce_y),
+                    if self.z_mode_enabled {
+                        self.z_extent.delta(self.pos_z[i] - p[2], !self.bounce_z)
+                    } else {
+                        0.0
+                    },
+                ];
+                (away, v)
+            })
+    }
+
+    // Unit direction away from the nearest predator in range of boid `i`, and
+    // a falloff that is 1 on top of it and 0 at the awareness radius.
+    pub(super) fn predator_threat(&self, i: usize) -> Option<([f32; 3], f32)> {
+        let radius = self.predators.radius;
+        let mut nearest: Option<([f32; 3], f32)> = None;
+        for (away, _) in self.predator_offsets(i) {
+            let dist = (away[0] * away[0] + away[1] * away[1] + away[2] * away[2]).sqrt();
+            if dist >= radius || nearest.is_some_and(|(_, d)| dist >= d) {
+                continue;
+            }
+            let dir = if dist > EPSILON {
+                away.map(|a| a / dist)
+            } else {
+                [1.0, 0.0, 0.0]
+            };
+            nearest = Some((dir, dist));
+        }
+        nearest.map(|(dir, dist)| (dir, 1.0 - dist / radius))
+    }
+
+    // Classic flee force: immediate and stateless, and damped inside safe
+    // zones.
+    pub(super) fn predator_flee_force(&self, i: usize) -> (f32, f32, f32) {
+        let Some((dir, falloff)) = self.predator_threat(i) else {
+            return (0.0, 0.0, 0.0);
+        };
+        let strength = self.predators.classic_weight * falloff * falloff / self.panic_decay(i);
+        (dir[0] * strength, dir[1] * strength, dir[2] * strength)
+    }
+
+    // Flock2 keeps evading the last threat for the refractory period after it
+    // leaves the awareness radius, with the command fading out over it.
+    pub(super) fn update_flock2_evasion(&mut self, dt: f32) {
+        for i in 0..self.active_count {
+            if let Some((dir, falloff)) = self.predator_threat(i) {
+                let strength = falloff.sqrt();
+                for (axis, value) in dir.iter().enumerate() {
+                    self.evasion_xyz[i * 3 + axis] = value * strength;
+                }
+                self.evasion_timer[i] = self.predators.refractory_s.max(EPSILON);
+            } else {
+                let decay = dt * self.panic_decay(i);
+                self.evasion_timer[i] = (self.evasion_timer[i] - decay).max(0.0);
+            }
+        }
+    }
+
+    // Direction to turn toward and its weight for the flock2 heading.
+    pub(super) fn flock2_evasion(&self, i: usize) -> Option<((f32, f32, f32), f32)> {
+        let timer = self.evasion_timer[i];
+        if timer <= 0.0 || self.predators.flock2_weight <= 0.0 {
+            return None;
+        }
+        let v = &self.evasion_xyz[i * 3..i * 3 + 3];
+        let strength = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+        if strength <= EPSILON {
+            return None;
+        }
+        let fade = (timer / self.predators.refractory_s.max(EPSILON)).min(1.0);
+        Some((
+            (v[0] / strength, v[1] / strength, v[2] / strength),
+            self.predators.flock2_weight * strength * fade,
+        ))
+    }
+}
+
+#[wasm_bindgen]
+impl Sim {
+    // Flat x, y, z per predator in world units, replacing the previous set.
+    // Predators inside a safe zone are moved to its edge; read the adjusted
+    // positions back with `predators_xyz`.
+    pub fn set_predators_xyz(&mut self, positions_xyz: &[f32]) {
+        self.predators.positions_xyz.clear();
+        for p in positions_xyz.chunks_exact(3).take(MAX_PREDATORS) {
+            let (x, y) = self.keep_out_of_safe_zones(
+                clamp_finite(p[0], 0.0, 1.0, 0.5),
+                clamp_finite(p[1], 0.0, 1.0, 0.5),
+            );
+            self.predators.positions_xyz.extend_from_slice(&[
+                x,
+                y,
+                clamp_finite(p[2], 0.0, 1.0, DEFAULT_Z_LAYER),
+            ]);
+        }
+        let values = self.predators.positions_xyz.len();
+        self.predators.velocities_xyz.resize(values, 0.0);
+    }
+
+    // Flat vx, vy, vz per predator in world units per second, in the same
+    // order as the positions. Missing entries are zero; velocities only feed
+    // predictive avoidance.
+    pub fn set_predator_velocities_xyz(&mut self, velocities_xyz: &[f32]) {
+        let values = self.predators.positions_xyz.len();
+        let velocities = &mut self.predators.velocities_xyz;
+        velocities.clear();
+        velocities.extend(
+            velocities_xyz
+                .iter()
+                .take(values)
+                .map(|&v| clamp_finite(v, -MAX_PREDATOR_SPEED, MAX_PREDATOR_SPEED, 0.0)),
+        );
+        velocities.resize(values, 0.0);
+    }
+
+    pub fn predators_xyz(&self) -> Vec<f32> {
+        self.predators.positions_xyz.clone()
+    }
+
+    pub fn predator_count(&self) -> usize {
+        self.predators.positions_xyz.len() / 3
+    }
+
+    // Awareness radius in world units shared by both model families; the
+    // classic flee force and the flock2 evasion command are weighted
+    // separately. Flock2 keeps evading for `refractory_s` after escaping.
+    pub fn set_predator_response(
+        &mut self,
+        radius: f32,
+        classic_weight: f32,
+        flock2_weight: f32,
+        refractory_s: f32,
+    ) {
+        self.predators.radius = clamp_finite(
+            radius,
+            MIN_PREDATOR_RADIUS,
+            MAX_PREDATOR_RADIUS,
+            DEFAULT_PREDATOR_RADIUS,
+        );
+        self.predators.classic_weight = clamp_finite(
+            classic_weight,
+            0.0,
+            MAX_CLASSIC_FLEE_WEIGHT,
+            DEFAULT_CLASSIC_FLEE_WEIGHT,
+        );
+        self.predators.flock2_weight = clamp_finite(
+            flock2_weight,
+            0.0,
+            MAX_FLOCK2_EVASION_WEIGHT,
+            DEFAULT_FLOCK2_EVASION_WEIGHT,
+        );
+        self.predators.refractory_s = clamp_finite(
+            refractory_s,
+            0.0,
+            MAX_EVASION_REFRACTORY_S,
+            DEFAULT_EVASION_REFRACTORY_S,
+        );
+    }
+
+    pub fn predator_radius(&self) -> f32 {
+        self.predators.radius
+    }
+
+    // Seconds of evasion left for the boid in `slot`.
+    pub fn evasion_time_left(&self, slot: usize) -> f32 {
+        self.evasion_timer.get(slot).copied().unwrap_or(0.0)
+    }
+}
@@ -0,0 +1,161 @@
+use crate::recording::FieldCodec;
+use crate::{clamp_finite, Sim, EPSILON};
+use wasm_bindgen::prelude::*;
+
+const MAX_PREDICTIVE_WEIGHT: f32 = 20.0;
+const MIN_PREDICTIVE_HORIZON_S: f32 = 0.05;
+const MAX_PREDICTIVE_HORIZON_S: f32 = 5.0;
+const DEFAULT_PREDICTIVE_HORIZON_S: f32 = 1.0;
+const MAX_PREDICTIVE_MARGIN: f32 = 0.25;
+const DEFAULT_PREDICTIVE_MARGIN: f32 = 0.03;
+
+// Anticipatory steering around obstacles and predators. Each collider is
+// extrapolated along the relative velocity to the time of closest approach;
+// if that falls within the horizon and passes closer than the obstacle
+// radius (the awareness radius for predators) plus the margin, the boid
+// steers sideways off the collision course. The most urgent course wins, weighted by how soon
+// it is and how near the miss. Off while the weight is zero.
+#[derive(Clone, Copy)]
+pub struct PredictiveAvoidance {
+    weight: f32,
+    horizon_s: f32,
+    margin: f32,
+}
+
+impl Default for PredictiveAvoidance {
+    fn default() -> Self {
+        Self {
+            weight: 0.0,
+            horizon_s: DEFAULT_PREDICTIVE_HORIZON_S,
+            margin: DEFAULT_PREDICTIVE_MARGIN,
+        }
+    }
+}
+
+impl PredictiveAvoidance {
+    pub fn visit_settings(&mut self, codec: &mut dyn FieldCodec) {
+        codec.f32(&mut self.weight);
+        codec.f32(&mut self.horizon_s);
+        codec.f32(&mut self.margin);
+    }
+
+    // Urgency in [0, 1] and the unit sidestep for one collider at `offset` from
+    // the boid's point of view, closing at `rel_velocity`, or None when the
+    // courses do not cross within the horizon.
+    fn course(
+        &self,
+        offset: [f32; 3],
+        rel_velocity: [f32; 3],
+        radius: f32,
+    ) -> Option<(f32, [f32; 3])> {
+        let [vx, vy, vz] = rel_velocity;
+        let speed_sq = vx * vx + vy * vy + vz * vz;
+        if speed_sq <= EPSILON * EPSILON {
+            return None;
+        }
+        let t = -(offset[0] * vx + offset[1] * vy + offset[2] * vz) / speed_sq;
+        if t <= 0.0 || t > self.horizon_s {
+            return None;
+        }
+        let miss = [offset[0] + vx * t, offset[1] + vy * t, offset[2] + vz * t];
+        let miss_len = (miss[0] * miss[0] + miss[1] * miss[1] + miss[2] * miss[2]).sqrt();
+        let reach = radius + self.margin;
+        if miss_len >= reach {
+            return None;
+        }
+        let urgency = (1.0 - t / self.horizon_s) * (1.0 - miss_len / reach);
+        let side = if miss_len > EPSILON {
+            miss.map(|m| m / miss_len)
+        } else {
+            // Dead on: turn left of the relative course.
+            let planar = (vx * vx + vy * vy).sqrt();
+            if planar <= EPSILON {
+                [1.0, 0.0, 0.0]
+            } else {
+                [-vy / planar, vx / planar, 0.0]
+            }
+        };
+        Some((urgency, side))
+    }
+}
+
+impl Sim {
+    // Unit sidestep direction for boid `i` and its weight, for the most urgent
+    // collision course.
+    pub(super) fn predictive_avoidance(&self, i: usize) -> Option<([f32; 3], f32)> {
+        let predictive = self.predictive;
+        if predictive.weight <= 0.0 {
+            return None;
+        }
+        let own = [
+            self.vel_x[i],
+            self.vel_y[i],
+            if self.z_mode_enabled {
+                self.vel_z[i]
+            } else {
+                0.0
+            },
+        ];
+        let obstacles = self
+            .obstacle_offsets(i)
+            .map(|(offset, radius, v)| (offset, [v[0], v[1], 0.0], radius));
+        let predators = self
+            .predator_offsets(i)
+            .map(|(offset, v)| (offset, [v[0], v[1], v[2]], self.predator_radius()));
+        let mut best: Option<(f32, [f32; 3])> = None;
+        for (offset, velocity, radius) in obstacles.chain(predators) {
+            let rel_velocity = [
+                own[0] - velocity[0],
+                own[1] - velocity[1],
+                if self.z_mode_enabled {
+                    own[2] - velocity[2]
+                } else {
+                    0.0
+                },
+            ];
+            if let Some((urgency, side)) = predictive.course(offset, rel_velocity, radius) {
+                if best.is_none_or(|(u, _)| urgency > u) {
+                    best = Some((urgency, side));
+                }
+            }
+        }
+        best.map(|(urgency, side)| (side, predictive.weight * urgency))
+    }
+}
+
+#[wasm_bindgen]
+impl Sim {
+    // Weight is clamped to [0, 20] (0 turns it off), the horizon to
+    // [0.05, 5] seconds and the margin to [0, 0.25] world units. Both model
+    // families use it; flock2 turns toward the sidestep like it does for
+    // obstacles.
+    pub fn set_predictive_avoidance(&mut self, weight: f32, horizon_s: f32, margin: f32) {
+        self.predictive = PredictiveAvoidance {
+            weight: clamp_finite(weight, 0.0, MAX_PREDICTIVE_WEIGHT, 0.0),
+            horizon_s: clamp_finite(
+                horizon_s,
+                MIN_PREDICTIVE_HORIZON_S,
+                MAX_PREDICTIVE_HORIZON_S,
+                DEFAULT_PREDICTIVE_HORIZON_S,
+            ),
+            margin: clamp_finite(
+                margin,
+                0.0,
+                MAX_PREDICTIVE_MARGIN,
+                DEFAULT_PREDICTIVE_MARGIN,
+            ),
+        };
+    }
+
+    pub fn predictive_weight(&self) -> f32 {
+        self.predictive.weight
+    }
+
+    pub fn predictive_horizon_s(&self) -> f32 {
+        self.predictive.horizon_s
+    }
+
+    pub fn predictive_margin(&self) -> f32 {
+        self.predictive.margin
+    }
+}
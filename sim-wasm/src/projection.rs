@@ -0,0 +1,110 @@
+use crate::recording::FieldCodec;
+use crate::{clamp_finite, Sim};
+use wasm_bindgen::prelude::*;
+
+const MIN_PROJECTION_FOV_DEG: f32 = 10.0;
+const MAX_PROJECTION_FOV_DEG: f32 = 150.0;
+const DEFAULT_PROJECTION_FOV_DEG: f32 = 60.0;
+const MIN_PROJECTION_DISTANCE: f32 = 0.05;
+const MAX_PROJECTION_DISTANCE: f32 = 100.0;
+const DEFAULT_PROJECTION_NEAR: f32 = 1.0;
+const DEFAULT_PROJECTION_FAR: f32 = 2.0;
+
+// Pinhole camera looking down the z axis at the unit square. World z 1 sits
+// `near` from the camera and z 0 sits `far`, matching the renderer's
+// convention that larger z is closer.
+#[derive(Clone, Copy)]
+pub struct Projection {
+    enabled: bool,
+    fov_deg: f32,
+    near: f32,
+    far: f32,
+    center_x: f32,
+    center_y: f32,
+}
+
+impl Default for Projection {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            fov_deg: DEFAULT_PROJECTION_FOV_DEG,
+            near: DEFAULT_PROJECTION_NEAR,
+            far: DEFAULT_PROJECTION_FAR,
+            center_x: 0.5,
+            center_y: 0.5,
+        }
+    }
+}
+
+impl Projection {
+    // Screen xy and the perspective scale for a world point, when enabled.
+    pub fn project(&self, x: f32, y: f32, z: f32) -> Option<(f32, f32, f32)> {
+        if !self.enabled {
+            return None;
+        }
+        let focal = 0.5 / (self.fov_deg.to_radians() * 0.5).tan();
+        let distance = self.near + (1.0 - z) * (self.far - self.near);
+        let scale = focal / distance;
+        Some((
+            self.center_x + (x - self.center_x) * scale,
+            self.center_y + (y - self.center_y) * scale,
+            scale,
+        ))
+    }
+
+    pub fn visit_settings(&mut self, codec: &mut dyn FieldCodec) {
+        codec.bool(&mut self.enabled);
+        codec.f32(&mut self.fov_deg);
+        codec.f32(&mut self.near);
+        codec.f32(&mut self.far);
+        codec.f32(&mut self.center_x);
+        codec.f32(&mut self.center_y);
+    }
+}
+
+#[wasm_bindgen]
+impl Sim {
+    // When enabled, the render xy buffer holds projected screen positions
+    // (still in unit-square coordinates) and the render scale buffer uses the
+    // perspective factor in place of the linear depth mapping. Simulation
+    // positions are unaffected.
+    pub fn set_perspective(
+        &mut self,
+        enabled: bool,
+        fov_deg: f32,
+        z_near: f32,
+        z_far: f32,
+        center_x: f32,
+        center_y: f32,
+    ) {
+        let near = clamp_finite(
+            z_near,
+            MIN_PROJECTION_DISTANCE,
+            MAX_PROJECTION_DISTANCE,
+            DEFAULT_PROJECTION_NEAR,
+        );
+        self.projection = Projection {
+            enabled,
+            fov_deg: clamp_finite(
+                fov_deg,
+                MIN_PROJECTION_FOV_DEG,
+                MAX_PROJECTION_FOV_DEG,
+                DEFAULT_PROJECTION_FOV_DEG,
+            ),
+            near,
+            far: clamp_finite(
+                z_far,
+                near,
+                MAX_PROJECTION_DISTANCE,
+                near.max(DEFAULT_PROJECTION_FAR),
+            ),
+            center_x: clamp_finite(center_x, 0.0, 1.0, 0.5),
+            center_y: clamp_finite(center_y, 0.0, 1.0, 0.5),
+        };
+        self.sync_render_buffers();
+    }
+
+    pub fn perspective_enabled(&self) -> bool {
+        self.projection.enabled
+    }
+}
@@ -0,0 +1,115 @@
+use crate::flock2::{FLOCK2_MAX_REACTION_MS, FLOCK2_MIN_REACTION_MS};
+use crate::recording::FieldCodec;
+use crate::{clamp_finite, hash_unit, Sim};
+use std::f32::consts::TAU;
+use wasm_bindgen::prelude::*;
+
+const MAX_REACTION_SPREAD: f32 = 2.0;
+
+// How per-boid reaction times scatter around `reaction_time_ms`. Each boid
+// draws a multiplier from a hash of the seed and its id, so the draw follows
+// the boid through reordering and does not depend on the population size.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReactionDistribution {
+    Fixed,
+    // Multipliers of (1 - spread) to (1 + spread).
+    Uniform,
+    // exp(spread * z) for a standard normal z: long tail of slow birds.
+    LogNormal,
+}
+
+impl ReactionDistribution {
+    fn from_u32(value: u32) -> Self {
+        match value {
+            1 => Self::Uniform,
+            2 => Self::LogNormal,
+            _ => Self::Fixed,
+        }
+    }
+
+    fn as_u32(self) -> u32 {
+        match self {
+            Self::Fixed => 0,
+            Self::Uniform => 1,
+            Self::LogNormal => 2,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct ReactionSpread {
+    pub distribution: ReactionDistribution,
+    pub spread: f32,
+    pub seed: u32,
+}
+
+impl Default for ReactionSpread {
+    fn default() -> Self {
+        Self {
+            distribution: ReactionDistribution::Fixed,
+            spread: 0.0,
+            seed: 0,
+        }
+    }
+}
+
+impl ReactionSpread {
+    fn scale(self, id: u32) -> f32 {
+        match self.distribution {
+            ReactionDistribution::Fixed => 1.0,
+            ReactionDistribution::Uniform => 1.0 + self.spread * hash_unit(self.seed, id, 0),
+            ReactionDistribution::LogNormal => {
+                // Box-Muller on two hashed uniforms in (0, 1].
+                let u1 = (hash_unit(self.seed, id, 0) * 0.5 + 0.5).max(f32::MIN_POSITIVE);
+                let u2 = hash_unit(self.seed, id, 1) * 0.5 + 0.5;
+                let z = (-2.0 * u1.ln()).sqrt() * (TAU * u2).cos();
+                (self.spread * z).exp()
+            }
+        }
+    }
+
+    pub fn visit_settings(&mut self, codec: &mut dyn FieldCodec) {
+        let mut distribution = self.distribution.as_u32();
+        codec.u32(&mut distribution);
+        self.distribution = ReactionDistribution::from_u32(distribution);
+        codec.f32(&mut self.spread);
+        codec.u32(&mut self.seed);
+    }
+}
+
+impl Sim {
+    pub(super) fn refill_reaction_scales(&mut self, from: usize) {
+        let spread = self.reaction_spread;
+        for slot in from..self.reaction_scale.len() {
+            self.reaction_scale[slot] = spread.scale(self.boid_ids[slot]);
+        }
+    }
+
+    pub(super) fn flock2_reaction_time_ms(&self, i: usize) -> f32 {
+        (self.flock2_config.reaction_time_ms * self.reaction_scale[i])
+            .clamp(FLOCK2_MIN_REACTION_MS, FLOCK2_MAX_REACTION_MS)
+    }
+}
+
+#[wasm_bindgen]
+impl Sim {
+    // Scatters flock2 reaction times around `reaction_time_ms` so a turn
+    // spreads through the flock as a wave. `distribution` is 0 for none, 1
+    // for a uniform spread of +/- `spread`, 2 for log-normal with sigma
+    // `spread`; per-boid times stay within the reaction time limits.
+    pub fn set_flock2_reaction_distribution(&mut self, distribution: u32, spread: f32, seed: u32) {
+        self.reaction_spread = ReactionSpread {
+            distribution: ReactionDistribution::from_u32(distribution),
+            spread: clamp_finite(spread, 0.0, MAX_REACTION_SPREAD, 0.0),
+            seed,
+        };
+        self.refill_reaction_scales(0);
+    }
+
+    pub fn flock2_reaction_time_ms_of(&self, slot: usize) -> f32 {
+        if slot >= self.count {
+            return self.flock2_config.reaction_time_ms;
+        }
+        self.flock2_reaction_time_ms(slot)
+    }
+}
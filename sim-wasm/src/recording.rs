@@ -0,0 +1,584 @@
+use crate::kd_tree::KdTree;
+use crate::neighbor_backend::NeighborBackend;
+use crate::partial_step::PartialStep;
+use crate::roles::MAX_ROLES;
+use crate::species::{AeroProfile, MAX_SPECIES};
+use crate::{DragModel, MathMode, ModelKind, SeparationKernel, Sim, MAX_SHAPE_POINTS};
+use wasm_bindgen::prelude::*;
+
+const RECORDING_MAGIC: [u8; 4] = *b"FLRC";
+const RECORDING_VERSION: u32 = 1;
+const RECORD_SETTINGS: u8 = 1;
+const RECORD_STATE: u8 = 2;
+const RECORD_STEP: u8 = 3;
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+const GOLDEN_COUNT: usize = 512;
+const GOLDEN_DT: f32 = 1.0 / 60.0;
+
+// One field list drives encoding, decoding and hashing, so the three can never
+// disagree about layout.
+pub trait FieldCodec {
+    fn u32(&mut self, value: &mut u32);
+
+    // Decoders are told when a field failed validation and was clamped;
+    // encoders and the hasher only ever see valid values.
+    fn malformed(&mut self) {}
+
+    fn f32(&mut self, value: &mut f32) {
+        let mut bits = value.to_bits();
+        self.u32(&mut bits);
+        *value = f32::from_bits(bits);
+    }
+
+    fn bool(&mut self, value: &mut bool) {
+        let mut raw = u32::from(*value);
+        self.u32(&mut raw);
+        *value = raw != 0;
+    }
+
+    fn usize(&mut self, value: &mut usize) {
+        let mut raw = *value as u32;
+        self.u32(&mut raw);
+        *value = raw as usize;
+    }
+
+    fn f32_slice(&mut self, values: &mut [f32]) {
+        for value in values {
+            self.f32(value);
+        }
+    }
+}
+
+struct ByteWriter<'a> {
+    out: &'a mut Vec<u8>,
+}
+
+impl FieldCodec for ByteWriter<'_> {
+    fn u32(&mut self, value: &mut u32) {
+        self.out.extend_from_slice(&value.to_le_bytes());
+    }
+}
+
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+    truncated: bool,
+    // Set when a decoded float was NaN or infinite; the field keeps its value.
+    non_finite: bool,
+    // Set when a decoded length or count was out of bounds.
+    malformed: bool,
+}
+
+impl ByteReader<'_> {
+    fn read_u8(&mut self) -> Option<u8> {
+        let byte = self.bytes.get(self.offset).copied()?;
+        self.offset += 1;
+        Some(byte)
+    }
+}
+
+impl FieldCodec for ByteReader<'_> {
+    fn u32(&mut self, value: &mut u32) {
+        match self.bytes.get(self.offset..self.offset + 4) {
+            Some(raw) => {
+                *value = u32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]);
+                self.offset += 4;
+            }
+            None => self.truncated = true,
+        }
+    }
+
+    fn malformed(&mut self) {
+        self.malformed = true;
+    }
+
+    fn f32(&mut self, value: &mut f32) {
+        let mut bits = value.to_bits();
+        self.u32(&mut bits);
+        let decoded = f32::from_bits(bits);
+        if decoded.is_finite() {
+            *value = decoded;
+        } else {
+            self.non_finite = true;
+        }
+    }
+}
+
+pub struct StateHasher {
+    hash: u64,
+}
+
+impl StateHasher {
+    pub fn new() -> Self {
+        Self { hash: FNV_OFFSET }
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.hash
+    }
+}
+
+impl FieldCodec for StateHasher {
+    fn u32(&mut self, value: &mut u32) {
+        for byte in value.to_le_bytes() {
+            self.hash = (self.hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME);
+        }
+    }
+}
+
+// A diff log of settings and state, not a log of API calls. Before each
+// recorded step, the full settings block is logged if any setting changed,
+// and the full state block if anything edited it out of step (setters with
+// side effects, hooks, direct buffer writes), followed by the step's dt. The
+// log shows what changed before a step, not which call changed it. JS step
+// hooks and custom forces are not part of the log and must be installed
+// again before replaying.
+#[derive(Default)]
+pub struct Recording {
+    active: bool,
+    log: Vec<u8>,
+    last_settings: Vec<u8>,
+    scratch: Vec<u8>,
+    last_state_hash: u64,
+}
+
+impl Sim {
+    fn visit_settings(&mut self, codec: &mut dyn FieldCodec) {
+        let config = &mut self.config;
+        codec.f32(&mut config.sep_weight);
+        codec.f32(&mut config.align_weight);
+        codec.f32(&mut config.coh_weight);
+        codec.f32(&mut config.speed_match_weight);
+        codec.f32(&mut config.neighbor_radius);
+        codec.f32(&mut config.separation_radius);
+        codec.f32(&mut config.min_speed);
+        codec.f32(&mut config.max_speed);
+        codec.f32(&mut config.max_force);
+        for mode in &mut config.math_modes {
+            let mut raw = mode.as_u32();
+            codec.u32(&mut raw);
+            *mode = MathMode::from_u32(raw);
+        }
+        codec.usize(&mut config.max_neighbors_sampled);
+        codec.f32(&mut config.soft_min_distance);
+        codec.f32(&mut config.hard_min_distance);
+        codec.f32(&mut config.jitter_strength);
+        codec.f32(&mut config.drag);
+        let mut drag_model = config.drag_model.as_u32();
+        codec.u32(&mut drag_model);
+        config.drag_model = DragModel::from_u32(drag_model);
+        let mut separation_kernel = config.separation_kernel.as_u32();
+        codec.u32(&mut separation_kernel);
+        config.separation_kernel = SeparationKernel::from_u32(separation_kernel);
+        codec.f32(&mut config.quadratic_drag);
+        codec.bool(&mut config.axis_drag_enabled);
+        codec.bool(&mut config.align_to_headings);
+        codec.bool(&mut config.distance_weighted_cohesion);
+        codec.f32(&mut config.z_drag);
+        codec.f32(&mut config.z_quadratic_drag);
+        codec.f32(&mut config.global_accel_x);
+        codec.f32(&mut config.global_accel_y);
+        codec.f32(&mut config.global_accel_z);
+        codec.f32(&mut config.shape_attractor_weight);
+        codec.f32(&mut config.far_field_opening);
+
+        let flock2 = &mut self.flock2_config;
+        codec.f32(&mut flock2.avoid_weight);
+        codec.f32(&mut flock2.align_weight);
+        codec.f32(&mut flock2.cohesion_weight);
+        codec.f32(&mut flock2.boundary_weight);
+        codec.f32(&mut flock2.boundary_count);
+        codec.f32(&mut flock2.neighbor_radius);
+        codec.usize(&mut flock2.topological_neighbors);
+        codec.usize(&mut flock2.lite_neighbor_cap);
+        codec.f32(&mut flock2.field_of_view_deg);
+        codec.f32(&mut flock2.avoid_field_of_view_deg);
+        codec.f32(&mut flock2.reaction_time_ms);
+        codec.f32(&mut flock2.dynamic_stability);
+        codec.f32(&mut flock2.mass);
+        codec.f32(&mut flock2.wing_area);
+        codec.f32(&mut flock2.lift_factor);
+        codec.f32(&mut flock2.drag_factor);
+        codec.f32(&mut flock2.thrust);
+        codec.f32(&mut flock2.min_speed);
+        codec.f32(&mut flock2.max_speed);
+        codec.f32(&mut flock2.gravity);
+        codec.f32(&mut flock2.air_density);
+        codec.f32(&mut flock2.max_bank_deg);
+        codec.f32(&mut flock2.decision_noise_deg);
+        codec.u32(&mut flock2.decision_noise_seed);
+        let hold = &mut self.altitude_hold;
+        codec.bool(&mut hold.enabled);
+        codec.f32(&mut hold.target);
+        codec.f32(&mut hold.kp);
+        codec.f32(&mut hold.ki);
+        codec.f32(&mut hold.kd);
+
+        let fish = &mut self.fish_config;
+        codec.f32(&mut fish.lateral_alignment);
+        codec.f32(&mut fish.preferred_depth);
+        codec.f32(&mut fish.depth_weight);
+        codec.f32(&mut fish.burst_period);
+        codec.f32(&mut fish.burst_fraction);
+        codec.f32(&mut fish.burst_thrust);
+        codec.f32(&mut fish.coast_drag);
+
+        let mut model_kind = self.model_kind.as_u32();
+        codec.u32(&mut model_kind);
+        self.model_kind = ModelKind::from_u32(model_kind);
+        let mut neighbor_backend = self.neighbor_backend.as_u32();
+        codec.u32(&mut neighbor_backend);
+        self.neighbor_backend = NeighborBackend::from_u32(neighbor_backend);
+        codec.u32(&mut self.kd_rebuild_interval);
+        codec.u32(&mut self.grid_rebuild_interval);
+        codec.usize(&mut self.neighbor_cell_cap);
+        codec.u32(&mut self.lod_interval);
+        codec.f32(&mut self.update_fraction);
+        codec.f32(&mut self.focus.x);
+        codec.f32(&mut self.focus.y);
+        codec.f32(&mut self.focus.radius);
+        for tier in &mut self.lod_tiers {
+            let mut raw = u32::from(*tier);
+            codec.u32(&mut raw);
+            *tier = raw.min(u32::from(u8::MAX)) as u8;
+        }
+        for species in &mut self.species {
+            let mut raw = u32::from(*species);
+            codec.u32(&mut raw);
+            *species = raw.min(MAX_SPECIES as u32 - 1) as u8;
+        }
+        for entry in &mut self.species_aero {
+            let mut present = entry.is_some();
+            codec.bool(&mut present);
+            let mut profile =
+                entry.unwrap_or_else(|| AeroProfile::from_config(&self.flock2_config));
+            codec.f32(&mut profile.mass);
+            codec.f32(&mut profile.wing_area);
+            codec.f32(&mut profile.lift_factor);
+            codec.f32(&mut profile.drag_factor);
+            codec.f32(&mut profile.thrust);
+            codec.f32(&mut profile.min_speed);
+            codec.f32(&mut profile.max_speed);
+            *entry = present.then_some(profile);
+        }
+        codec.f32_slice(&mut self.boid_size);
+        codec.u32(&mut self.variation_seed);
+        codec.f32_slice(&mut self.external_scalar);
+        self.external_targets.visit_settings(codec);
+        self.behavior.visit_settings(codec);
+        self.roles.visit_settings(codec);
+        self.split_world.visit_settings(codec);
+        self.mirror.visit_settings(codec);
+        self.kaleidoscope.visit_settings(codec);
+        self.wrap_ghosts.visit_settings(codec);
+        self.attractor_falloff.visit_settings(codec);
+        self.danger_zones.visit_settings(codec);
+        self.safe_zones.visit_settings(codec);
+        self.corridors.visit_settings(codec);
+        self.config_regions.visit_settings(codec);
+        self.terrain.visit_settings(codec);
+        self.exclusion.visit_settings(codec);
+        self.predictive.visit_settings(codec);
+        self.pair_avoidance.visit_settings(codec);
+        self.neighbor_memory.visit_settings(codec);
+        self.familiarity.visit_settings(codec);
+        self.render_scale_settings.visit_settings(codec);
+        self.depth_speed.visit_settings(codec);
+        self.burst_coast.visit_settings(codec);
+        self.accel_ramp.visit_settings(codec);
+        self.spawn_layout.visit_settings(codec);
+        self.classic_headings.visit_settings(codec);
+        self.speed_radius.visit_settings(codec);
+        self.species_partition.visit_settings(codec);
+        self.turn_noise.visit_settings(codec);
+        self.heading_smoothing.visit_settings(codec);
+        self.projection.visit_settings(codec);
+        self.camera.visit_settings(codec);
+        self.color_map.visit_settings(codec);
+        for entry in self.species_hard_min.iter_mut().flatten() {
+            let mut present = entry.is_some();
+            codec.bool(&mut present);
+            let mut distance = entry.unwrap_or(0.0);
+            codec.f32(&mut distance);
+            *entry = present.then_some(distance);
+        }
+        codec.f32(&mut self.width);
+        codec.f32(&mut self.height);
+        codec.bool(&mut self.bounce_x);
+        codec.bool(&mut self.bounce_y);
+        codec.bool(&mut self.bounce_z);
+        codec.f32(&mut self.wall_restitution);
+        codec.f32(&mut self.wall_friction);
+        self.z_extent.visit_settings(codec);
+        codec.bool(&mut self.z_mode_enabled);
+        codec.f32(&mut self.z_force_scales.separation);
+        codec.f32(&mut self.z_force_scales.alignment);
+        codec.f32(&mut self.z_force_scales.cohesion);
+        codec.f32(&mut self.z_force_scales.attractor);
+        codec.f32(&mut self.time_scale);
+        codec.bool(&mut self.boundary_hit_flags_enabled);
+        self.wind.visit_settings(codec);
+        self.obstacles.visit_settings(codec);
+        self.predators.visit_settings(codec);
+        self.reaction_spread.visit_settings(codec);
+
+        let mut shape_values = self.shape_points_xyz.len();
+        codec.usize(&mut shape_values);
+        self.shape_points_xyz
+            .resize(shape_values.min(MAX_SHAPE_POINTS * 3), 0.0);
+        codec.f32_slice(&mut self.shape_points_xyz);
+    }
+
+    // Everything a step reads besides settings. The count is fixed for a
+    // recording, so only the per-boid arrays up to it are included.
+    pub(super) fn visit_state(&mut self, codec: &mut dyn FieldCodec) {
+        codec.usize(&mut self.active_count);
+        self.active_count = self.active_count.min(self.count);
+        codec.u32(&mut self.step_index);
+        codec.f32(&mut self.fish_phase);
+        codec.f32(&mut self.burst_coast.phase);
+        codec.f32(&mut self.turn_noise.time_s);
+        self.wind.visit_state(codec);
+        self.obstacles.visit_state(codec);
+        codec.f32_slice(&mut self.altitude_integral);
+        codec.f32_slice(&mut self.evasion_xyz);
+        codec.f32_slice(&mut self.evasion_timer);
+        codec.f32_slice(&mut self.reaction_scale);
+        codec.f32_slice(&mut self.variation);
+        self.behavior.visit_state(codec);
+        self.neighbor_memory.visit_state(codec, self.count);
+        self.familiarity.visit_state(codec, self.count);
+        self.accel_ramp.visit_state(codec, self.count);
+        for role in &mut self.role {
+            let mut raw = u32::from(*role);
+            codec.u32(&mut raw);
+            *role = raw.min(MAX_ROLES as u32 - 1) as u8;
+        }
+        for values in [
+            &mut self.pos_x,
+            &mut self.pos_y,
+            &mut self.pos_z,
+            &mut self.vel_x,
+            &mut self.vel_y,
+            &mut self.vel_z,
+            &mut self.heading_x,
+            &mut self.heading_y,
+            &mut self.heading_z,
+            &mut self.accel_x,
+            &mut self.accel_y,
+            &mut self.accel_z,
+        ] {
+            codec.f32_slice(values);
+        }
+        for id in &mut self.boid_ids {
+            codec.u32(id);
+        }
+        for generation in &mut self.boid_generations {
+            codec.u32(generation);
+        }
+        self.rebuild_boid_slots();
+    }
+
+    pub(super) fn state_hash(&mut self) -> u64 {
+        let mut hasher = StateHasher::new();
+        self.visit_state(&mut hasher);
+        hasher.finish()
+    }
+
+    // Reused neighbor indices depend on when they were last built, so both
+    // the recording and the replay start from a fresh build.
+    fn reset_neighbor_indices(&mut self) {
+        self.kd_tree = KdTree::new(self.count);
+        self.neighbor_grid.invalidate();
+    }
+
+    pub(super) fn record_before_advance(&mut self, dt: f32) {
+        self.record_settings();
+        // A sliced step logged its starting state when the pass began; the
+        // state has moved on since, but replay redoes that work itself.
+        if !self.partial_step.recorded {
+            self.record_state();
+        }
+        self.recording.log.push(RECORD_STEP);
+        self.recording.log.extend_from_slice(&dt.to_le_bytes());
+    }
+
+    // Logs settings and out-of-step state edits made since the last step.
+    pub(super) fn record_before_pass(&mut self) {
+        self.record_settings();
+        self.record_state();
+    }
+
+    fn record_settings(&mut self) {
+        let mut settings = std::mem::take(&mut self.recording.scratch);
+        settings.clear();
+        self.visit_settings(&mut ByteWriter { out: &mut settings });
+        if settings != self.recording.last_settings {
+            self.recording.log.push(RECORD_SETTINGS);
+            self.recording.log.extend_from_slice(&settings);
+            std::mem::swap(&mut settings, &mut self.recording.last_settings);
+        }
+        self.recording.scratch = settings;
+    }
+
+    fn record_state(&mut self) {
+        if self.state_hash() != self.recording.last_state_hash {
+            let mut log = std::mem::take(&mut self.recording.log);
+            log.push(RECORD_STATE);
+            self.visit_state(&mut ByteWriter { out: &mut log });
+            self.recording.log = log;
+        }
+    }
+
+    pub(super) fn record_after_advance(&mut self) {
+        self.recording.last_state_hash = self.state_hash();
+    }
+
+    pub(super) fn is_recording_active(&self) -> bool {
+        self.recording.active
+    }
+}
+
+#[wasm_bindgen]
+impl Sim {
+    // Starts a new log from the current state. Any previous log is discarded,
+    // as is a `step_partial` pass in progress, which began before the log did.
+    pub fn start_recording(&mut self) {
+        self.reset_neighbor_indices();
+        self.partial_step = PartialStep::default();
+
+        let mut log = Vec::new();
+        log.extend_from_slice(&RECORDING_MAGIC);
+        log.extend_from_slice(&RECORDING_VERSION.to_le_bytes());
+        log.extend_from_slice(&(self.count as u32).to_le_bytes());
+
+        let mut settings = Vec::new();
+        self.visit_settings(&mut ByteWriter { out: &mut settings });
+        log.extend_from_slice(&settings);
+        self.visit_state(&mut ByteWriter { out: &mut log });
+
+        self.recording = Recording {
+            active: true,
+            log,
+            last_settings: settings,
+            scratch: Vec::new(),
+            last_state_hash: self.state_hash(),
+        };
+    }
+
+    pub fn stop_recording(&mut self) {
+        self.recording.active = false;
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.active
+    }
+
+    pub fn recording_len(&self) -> usize {
+        self.recording.log.len()
+    }
+
+    pub fn export_recording(&self) -> Vec<u8> {
+        self.recording.log.clone()
+    }
+
+    // Decoded configs go through the same clamping as `apply_config`. Returns
+    // false if any value was non-finite or out of range.
+    fn replay_settings(&mut self, reader: &mut ByteReader) -> bool {
+        self.visit_settings(reader);
+        let mut report = Vec::new();
+        self.config.sanitize_reported(&mut report);
+        self.flock2_config.sanitize_reported(&mut report);
+        self.fish_config.sanitize_reported(&mut report);
+        self.sync_neighbor_cell_size();
+        report.is_empty() && !reader.non_finite
+    }
+
+    // Restores the recorded start state and re-runs every logged step. The sim
+    // must have the same capacity as the recorder; returns false on a mismatch,
+    // a malformed log or an invalid setting or buffer length, in which case
+    // the sim may be partially replayed.
+    pub fn replay(&mut self, bytes: &[u8]) -> bool {
+        self.recording.active = false;
+        self.partial_step = PartialStep::default();
+        if bytes.len() < 12 || bytes[..4] != RECORDING_MAGIC {
+            return false;
+        }
+
+        let mut reader = ByteReader {
+            bytes,
+            offset: 4,
+            truncated: false,
+            non_finite: false,
+            malformed: false,
+        };
+        let mut version = 0;
+        let mut count = 0;
+        reader.u32(&mut version);
+        reader.usize(&mut count);
+        if version != RECORDING_VERSION || count != self.count {
+            return false;
+        }
+
+        if !self.replay_settings(&mut reader) {
+            return false;
+        }
+        self.visit_state(&mut reader);
+        self.reset_neighbor_indices();
+        while !reader.truncated {
+            let Some(tag) = reader.read_u8() else {
+                break;
+            };
+            match tag {
+                RECORD_SETTINGS => {
+                    if !self.replay_settings(&mut reader) {
+                        return false;
+                    }
+                }
+                RECORD_STATE => self.visit_state(&mut reader),
+                RECORD_STEP => {
+                    let mut dt = 0.0;
+                    reader.f32(&mut dt);
+                    if reader.truncated {
+                        break;
+                    }
+                    self.advance(dt);
+                }
+                _ => return false,
+            }
+        }
+
+        self.sync_render_buffers();
+        !reader.truncated && !reader.non_finite && !reader.malformed
+    }
+}
+
+// Runs the default classic config in z mode from `seed` and hashes the final
+// positions, velocities and headings. Settings and bookkeeping state are left
+// out, so the value only changes when simulation behavior does and apps can
+// pin it in their tests to catch upgrades that would alter their scenes.
+#[wasm_bindgen]
+pub fn run_golden(seed: u32, steps: u32) -> u64 {
+    let mut sim = Sim::new(GOLDEN_COUNT, seed, 1.0, 1.0);
+    sim.set_z_mode(true);
+    for _ in 0..steps {
+        sim.step(GOLDEN_DT);
+    }
+    let n = sim.active_count;
+    let mut hasher = StateHasher::new();
+    for column in [
+        &mut sim.pos_x,
+        &mut sim.pos_y,
+        &mut sim.pos_z,
+        &mut sim.vel_x,
+        &mut sim.vel_y,
+        &mut sim.vel_z,
+        &mut sim.heading_x,
+        &mut sim.heading_y,
+        &mut sim.heading_z,
+    ] {
+        hasher.f32_slice(&mut column[..n]);
+    }
+    hasher.finish()
+}
@@ -0,0 +1,175 @@
+use crate::recording::FieldCodec;
+use crate::{axis_delta, clamp_finite, Sim, EPSILON, WORLD_SIZE};
+
+const MAX_REGION_RADIUS: f32 = 0.5 * WORLD_SIZE;
+
+// A circle or axis-aligned rectangle in the xy plane, stored as a rounded
+// box: circles have no half extents and rectangles no corner radius.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Region {
+    center: [f32; 2],
+    half: [f32; 2],
+    radius: f32,
+}
+
+impl Region {
+    pub fn circle(x: f32, y: f32, radius: f32) -> Self {
+        Self {
+            center: [
+                clamp_finite(x, 0.0, 1.0, 0.5),
+                clamp_finite(y, 0.0, 1.0, 0.5),
+            ],
+            half: [0.0; 2],
+            radius: clamp_finite(radius, 0.0, MAX_REGION_RADIUS, 0.0),
+        }
+    }
+
+    // Corners may come in either order.
+    pub fn rect(x0: f32, y0: f32, x1: f32, y1: f32) -> Self {
+        let x0 = clamp_finite(x0, 0.0, 1.0, 0.5);
+        let x1 = clamp_finite(x1, 0.0, 1.0, 0.5);
+        let y0 = clamp_finite(y0, 0.0, 1.0, 0.5);
+        let y1 = clamp_finite(y1, 0.0, 1.0, 0.5);
+        Self {
+            center: [0.5 * (x0 + x1), 0.5 * (y0 + y1)],
+            half: [0.5 * (x1 - x0).abs(), 0.5 * (y1 - y0).abs()],
+            radius: 0.0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.radius <= EPSILON && (self.half[0] <= EPSILON || self.half[1] <= EPSILON)
+    }
+
+    // Distance from a point at `offset` from the centre to the edge, negative
+    // inside, and the outward direction that gets it out fastest.
+    pub fn signed_distance(&self, offset: [f32; 2]) -> (f32, [f32; 2]) {
+        let q = [
+            offset[0].abs() - self.half[0],
+            offset[1].abs() - self.half[1],
+        ];
+        let sign = |v: f32| if v < 0.0 { -1.0 } else { 1.0 };
+        let outside = [q[0].max(0.0), q[1].max(0.0)];
+        let outside_len = (outside[0] * outside[0] + outside[1] * outside[1]).sqrt();
+        if outside_len > EPSILON {
+            let normal = [
+                sign(offset[0]) * outside[0] / outside_len,
+                sign(offset[1]) * outside[1] / outside_len,
+            ];
+            return (outside_len - self.radius, normal);
+        }
+        // Inside the box part: leave through the nearest side.
+        let normal = if q[0] > q[1] {
+            [sign(offset[0]), 0.0]
+        } else {
+            [0.0, sign(offset[1])]
+        };
+        (q[0].max(q[1]) - self.radius, normal)
+    }
+
+    pub fn visit_settings(&mut self, codec: &mut dyn FieldCodec) {
+        codec.f32_slice(&mut self.center);
+        codec.f32_slice(&mut self.half);
+        codec.f32(&mut self.radius);
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct RegionEntry<T> {
+    pub id: u32,
+    pub region: Region,
+    pub data: T,
+}
+
+// Regions with stable ids, in insertion order, each carrying `T`.
+pub struct RegionList<T> {
+    pub entries: Vec<RegionEntry<T>>,
+    next_id: u32,
+    capacity: usize,
+}
+
+impl<T: Copy + Default> RegionList<T> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: Vec::new(),
+            next_id: 0,
+            capacity,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // Returns the new id, or -1 when `region` is empty or the list is full.
+    pub fn add(&mut self, region: Region, data: T) -> i32 {
+        if region.is_empty() || self.entries.len() >= self.capacity {
+            return -1;
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        self.entries.push(RegionEntry { id, region, data });
+        id as i32
+    }
+
+    pub fn remove(&mut self, id: u32) -> bool {
+        let before = self.entries.len();
+        self.entries.retain(|entry| entry.id != id);
+        self.entries.len() != before
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn ids(&self) -> Vec<u32> {
+        self.entries.iter().map(|entry| entry.id).collect()
+    }
+
+    pub fn get(&self, id: u32) -> Option<&RegionEntry<T>> {
+        self.entries.iter().find(|entry| entry.id == id)
+    }
+
+    pub fn get_mut(&mut self, id: u32) -> Option<&mut RegionEntry<T>> {
+        self.entries.iter_mut().find(|entry| entry.id == id)
+    }
+
+    // `visit_data` covers whatever part of `T` belongs in recordings.
+    pub fn visit_settings(
+        &mut self,
+        codec: &mut dyn FieldCodec,
+        mut visit_data: impl FnMut(&mut T, &mut dyn FieldCodec),
+    ) {
+        codec.u32(&mut self.next_id);
+        let mut count = self.entries.len();
+        codec.usize(&mut count);
+        self.entries.resize(
+            count.min(self.capacity),
+            RegionEntry {
+                id: 0,
+                region: Region::default(),
+                data: T::default(),
+            },
+        );
+        for entry in &mut self.entries {
+            codec.u32(&mut entry.id);
+            entry.region.visit_settings(codec);
+            visit_data(&mut entry.data, codec);
+        }
+    }
+}
+
+impl Sim {
+    // Offset from `region`'s centre to a point, following the wrap setting.
+    pub(super) fn region_offset(&self, x: f32, y: f32, region: &Region) -> [f32; 2] {
+        [
+            axis_delta(x - region.center[0], !self.bounce_x),
+            axis_delta(y - region.center[1], !self.bounce_y),
+        ]
+    }
+
+    // Signed distance from boid `i` to `region`.
+    pub(super) fn region_distance(&self, i: usize, region: &Region) -> (f32, [f32; 2]) {
+        region.signed_distance(self.region_offset(self.pos_x[i], self.pos_y[i], region))
+    }
+}
@@ -0,0 +1,113 @@
+use crate::recording::FieldCodec;
+use crate::species::MAX_SPECIES;
+use crate::{clamp_finite, Sim};
+use wasm_bindgen::prelude::*;
+
+const MIN_RENDER_SCALE: f32 = 0.0;
+const MAX_RENDER_SCALE: f32 = 16.0;
+// Matches the web renderer's historical 0.55 + 0.9 * z sprite attenuation.
+const DEFAULT_FAR_SCALE: f32 = 0.55;
+const DEFAULT_NEAR_SCALE: f32 = 1.45;
+
+// Sprite size multiplier per boid: a depth term interpolated over render z
+// (or the perspective factor when projecting), times the boid's species size
+// and its own size.
+#[derive(Clone, Copy)]
+pub struct RenderScale {
+    far: f32,
+    near: f32,
+    species_size: [f32; MAX_SPECIES],
+}
+
+impl Default for RenderScale {
+    fn default() -> Self {
+        Self {
+            far: DEFAULT_FAR_SCALE,
+            near: DEFAULT_NEAR_SCALE,
+            species_size: [1.0; MAX_SPECIES],
+        }
+    }
+}
+
+impl RenderScale {
+    pub fn visit_settings(&mut self, codec: &mut dyn FieldCodec) {
+        codec.f32(&mut self.far);
+        codec.f32(&mut self.near);
+        codec.f32_slice(&mut self.species_size);
+    }
+}
+
+impl Sim {
+    pub(super) fn sync_render_scale(&mut self, i: usize) {
+        let settings = &self.render_scale_settings;
+        let z = self.render_z[i];
+        let depth = match self.projection.project(0.0, 0.0, z) {
+            Some((_, _, perspective)) => perspective,
+            None => settings.far + (settings.near - settings.far) * z,
+        };
+        self.render_scale[i] =
+            depth * settings.species_size[self.species[i] as usize] * self.boid_size[i];
+    }
+}
+
+#[wasm_bindgen]
+impl Sim {
+    // Sprite scale at render z 0 and at render z 1. Equal values turn depth
+    // attenuation off.
+    pub fn set_render_depth_scale(&mut self, far: f32, near: f32) {
+        let settings = &mut self.render_scale_settings;
+        settings.far = clamp_finite(far, MIN_RENDER_SCALE, MAX_RENDER_SCALE, DEFAULT_FAR_SCALE);
+        settings.near = clamp_finite(near, MIN_RENDER_SCALE, MAX_RENDER_SCALE, DEFAULT_NEAR_SCALE);
+        self.sync_render_buffers();
+    }
+
+    pub fn render_depth_scale_far(&self) -> f32 {
+        self.render_scale_settings.far
+    }
+
+    pub fn render_depth_scale_near(&self) -> f32 {
+        self.render_scale_settings.near
+    }
+
+    pub fn set_species_size(&mut self, species: u8, size: f32) {
+        if let Some(entry) = self
+            .render_scale_settings
+            .species_size
+            .get_mut(species as usize)
+        {
+            *entry = clamp_finite(size, MIN_RENDER_SCALE, MAX_RENDER_SCALE, 1.0);
+            self.sync_render_buffers();
+        }
+    }
+
+    pub fn species_size(&self, species: u8) -> f32 {
+        self.render_scale_settings
+            .species_size
+            .get(species as usize)
+            .copied()
+            .unwrap_or(1.0)
+    }
+
+    // Size per slot; slots past the end of `sizes` are reset to 1.
+    pub fn set_boid_sizes(&mut self, sizes: &[f32]) {
+        for (slot, entry) in self.boid_size.iter_mut().enumerate() {
+            *entry = sizes.get(slot).map_or(1.0, |&size| {
+                clamp_finite(size, MIN_RENDER_SCALE, MAX_RENDER_SCALE, 1.0)
+            });
+        }
+        self.sync_render_buffers();
+    }
+
+    pub fn boid_size(&self, slot: usize) -> f32 {
+        self.boid_size.get(slot).copied().unwrap_or(1.0)
+    }
+
+    // Written alongside the other render buffers on every step.
+    pub fn render_scale_ptr(&self) -> *const f32 {
+        self.render_scale.as_ptr()
+    }
+
+    pub fn render_scale_len(&self) -> usize {
+        self.render_scale.len()
+    }
+}
@@ -0,0 +1,182 @@
+use crate::recording::FieldCodec;
+use crate::{clamp_finite, hash_unit, Sim};
+use wasm_bindgen::prelude::*;
+
+pub const MAX_ROLES: usize = 4;
+const ROLE_FOLLOWER: u32 = 0;
+const ROLE_SCOUT: u32 = 1;
+const MIN_ROLE_RADIUS_SCALE: f32 = 0.25;
+const MAX_ROLE_RADIUS_SCALE: f32 = 4.0;
+const MAX_ROLE_WEIGHT: f32 = 8.0;
+// Keeps role draws independent of the reaction-time draws on the same ids.
+const ROLE_HASH_SEED: u32 = 0x524F_4C45;
+
+// Classic-model overrides for one role: a neighbor radius scale and
+// multipliers on the separation, alignment and cohesion weights.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RoleOverrides {
+    pub radius_scale: f32,
+    pub separation: f32,
+    pub alignment: f32,
+    pub cohesion: f32,
+}
+
+impl RoleOverrides {
+    const NEUTRAL: Self = Self {
+        radius_scale: 1.0,
+        separation: 1.0,
+        alignment: 1.0,
+        cohesion: 1.0,
+    };
+
+    // Scouts see farther and hold the group loosely, so they pull ahead.
+    const SCOUT: Self = Self {
+        radius_scale: 1.75,
+        separation: 1.0,
+        alignment: 0.6,
+        cohesion: 0.3,
+    };
+
+    fn defaults(role: usize) -> Self {
+        if role == ROLE_SCOUT as usize {
+            Self::SCOUT
+        } else {
+            Self::NEUTRAL
+        }
+    }
+}
+
+// Role 0 is the follower role and takes whatever fraction the others leave.
+// Each boid's role comes from a hash of its id, so assignments follow boids
+// through reordering and only the boids at the margin change role when a
+// fraction moves.
+pub struct Roles {
+    overrides: [RoleOverrides; MAX_ROLES],
+    fractions: [f32; MAX_ROLES],
+}
+
+impl Default for Roles {
+    fn default() -> Self {
+        Self {
+            overrides: std::array::from_fn(RoleOverrides::defaults),
+            fractions: [0.0; MAX_ROLES],
+        }
+    }
+}
+
+impl Roles {
+    fn role_for(&self, id: u32) -> u8 {
+        let draw = hash_unit(ROLE_HASH_SEED, id, 0) * 0.5 + 0.5;
+        let mut upper = 0.0;
+        for role in 1..MAX_ROLES {
+            upper += self.fractions[role];
+            if draw < upper {
+                return role as u8;
+            }
+        }
+        ROLE_FOLLOWER as u8
+    }
+
+    pub fn visit_settings(&mut self, codec: &mut dyn FieldCodec) {
+        for (overrides, fraction) in self.overrides.iter_mut().zip(&mut self.fractions) {
+            codec.f32(&mut overrides.radius_scale);
+            codec.f32(&mut overrides.separation);
+            codec.f32(&mut overrides.alignment);
+            codec.f32(&mut overrides.cohesion);
+            codec.f32(fraction);
+        }
+    }
+}
+
+impl Sim {
+    pub(super) fn refill_roles(&mut self, from: usize) {
+        for slot in from..self.role.len() {
+            self.role[slot] = self.roles.role_for(self.boid_ids[slot]);
+        }
+    }
+
+    pub(super) fn role_overrides(&self, i: usize) -> RoleOverrides {
+        self.roles.overrides[self.role[i] as usize]
+    }
+}
+
+#[wasm_bindgen]
+impl Sim {
+    // Share of the population in `role` (1-3). Fractions of the non-follower
+    // roles are capped so they sum to at most 1; the follower share is what
+    // remains and cannot be set directly.
+    pub fn set_role_fraction(&mut self, role: u32, fraction: f32) {
+        let role = role as usize;
+        if role == ROLE_FOLLOWER as usize || role >= MAX_ROLES {
+            return;
+        }
+        let others: f32 = (1..MAX_ROLES)
+            .filter(|&r| r != role)
+            .map(|r| self.roles.fractions[r])
+            .sum();
+        self.roles.fractions[role] = clamp_finite(fraction, 0.0, (1.0 - others).max(0.0), 0.0);
+        self.refill_roles(0);
+    }
+
+    pub fn role_fraction(&self, role: u32) -> f32 {
+        match role as usize {
+            0 => 1.0 - self.roles.fractions[1..].iter().sum::<f32>(),
+            role if role < MAX_ROLES => self.roles.fractions[role],
+            _ => 0.0,
+        }
+    }
+
+    // Classic model only. Radius scales are clamped to [0.25, 4] and the
+    // effective radius to the neighbor radius limit; weights to [0, 8].
+    pub fn set_role_overrides(
+        &mut self,
+        role: u32,
+        radius_scale: f32,
+        separation: f32,
+        alignment: f32,
+        cohesion: f32,
+    ) {
+        let Some(entry) = self.roles.overrides.get_mut(role as usize) else {
+            return;
+        };
+        let defaults = RoleOverrides::defaults(role as usize);
+        let weight =
+            |value: f32, fallback: f32| clamp_finite(value, 0.0, MAX_ROLE_WEIGHT, fallback);
+        *entry = RoleOverrides {
+            radius_scale: clamp_finite(
+                radius_scale,
+                MIN_ROLE_RADIUS_SCALE,
+                MAX_ROLE_RADIUS_SCALE,
+                defaults.radius_scale,
+            ),
+            separation: weight(separation, defaults.separation),
+            alignment: weight(alignment, defaults.alignment),
+            cohesion: weight(cohesion, defaults.cohesion),
+        };
+    }
+
+    // Radius scale, separation, alignment and cohesion for `role`.
+    pub fn role_overrides_of(&self, role: u32) -> Vec<f32> {
+        self.roles
+            .overrides
+            .get(role as usize)
+            .map_or_else(Vec::new, |o| {
+                vec![o.radius_scale, o.separation, o.alignment, o.cohesion]
+            })
+    }
+
+    pub fn boid_role(&self, slot: usize) -> u32 {
+        self.role
+            .get(slot)
+            .map_or(ROLE_FOLLOWER, |&role| u32::from(role))
+    }
+
+    // One byte per slot.
+    pub fn roles_ptr(&self) -> *const u8 {
+        self.role.as_ptr()
+    }
+
+    pub fn roles_len(&self) -> usize {
+        self.role.len()
+    }
+}
@@ -0,0 +1,133 @@
+use crate::recording::FieldCodec;
+use crate::regions::{Region, RegionList};
+use crate::{clamp_finite, project_axis_position, Sim, EPSILON};
+use wasm_bindgen::prelude::*;
+
+pub const MAX_SAFE_ZONES: usize = 32;
+const MAX_PANIC_DECAY: f32 = 20.0;
+const DEFAULT_PANIC_DECAY: f32 = 4.0;
+// Passes over the zones when pushing a predator out; overlapping zones can
+// push it back into one it already left.
+const PREDATOR_PUSH_PASSES: usize = 4;
+
+// Refuges from predators. Predators are pushed out to the nearest edge, and
+// prey inside calm down `panic_decay` times faster: the flock2 evasion timer
+// runs down that much quicker, and the stateless classic flee force is
+// divided by it instead.
+pub struct SafeZones {
+    zones: RegionList<()>,
+    panic_decay: f32,
+}
+
+impl Default for SafeZones {
+    fn default() -> Self {
+        Self {
+            zones: RegionList::new(MAX_SAFE_ZONES),
+            panic_decay: DEFAULT_PANIC_DECAY,
+        }
+    }
+}
+
+impl SafeZones {
+    pub fn visit_settings(&mut self, codec: &mut dyn FieldCodec) {
+        codec.f32(&mut self.panic_decay);
+        self.zones.visit_settings(codec, |_, _| {});
+    }
+}
+
+impl Sim {
+    fn in_safe_zone(&self, i: usize) -> bool {
+        self.safe_zones
+            .zones
+            .entries
+            .iter()
+            .any(|zone| self.region_distance(i, &zone.region).0 < 0.0)
+    }
+
+    // How much faster panic fades for boid `i`: 1 outside every safe zone.
+    pub(super) fn panic_decay(&self, i: usize) -> f32 {
+        if self.in_safe_zone(i) {
+            self.safe_zones.panic_decay
+        } else {
+            1.0
+        }
+    }
+
+    // Moves a predator at (x, y) to just outside any safe zone it is in.
+    pub(super) fn keep_out_of_safe_zones(&self, mut x: f32, mut y: f32) -> (f32, f32) {
+        for _ in 0..PREDATOR_PUSH_PASSES {
+            let mut moved = false;
+            for zone in &self.safe_zones.zones.entries {
+                let offset = self.region_offset(x, y, &zone.region);
+                let (distance, normal) = zone.region.signed_distance(offset);
+                if distance >= 0.0 {
+                    continue;
+                }
+                let push = EPSILON - distance;
+                x = project_axis_position(x + normal[0] * push, self.bounce_x);
+                y = project_axis_position(y + normal[1] * push, self.bounce_y);
+                moved = true;
+            }
+            if !moved {
+                break;
+            }
+        }
+        (x, y)
+    }
+
+    fn push_predators_out(&mut self) {
+        let mut positions = std::mem::take(&mut self.predators.positions_xyz);
+        for p in positions.chunks_exact_mut(3) {
+            (p[0], p[1]) = self.keep_out_of_safe_zones(p[0], p[1]);
+        }
+        self.predators.positions_xyz = positions;
+    }
+}
+
+#[wasm_bindgen]
+impl Sim {
+    // Zones live in the xy plane and span every depth. Predators already
+    // inside a new zone are pushed out. Returns the new zone's id, or -1 when
+    // the zone is empty or `MAX_SAFE_ZONES` are in use.
+    pub fn add_safe_circle(&mut self, x: f32, y: f32, radius: f32) -> i32 {
+        let id = self.safe_zones.zones.add(Region::circle(x, y, radius), ());
+        self.push_predators_out();
+        id
+    }
+
+    pub fn add_safe_rect(&mut self, x0: f32, y0: f32, x1: f32, y1: f32) -> i32 {
+        let id = self.safe_zones.zones.add(Region::rect(x0, y0, x1, y1), ());
+        self.push_predators_out();
+        id
+    }
+
+    pub fn remove_safe_zone(&mut self, id: u32) -> bool {
+        self.safe_zones.zones.remove(id)
+    }
+
+    pub fn clear_safe_zones(&mut self) {
+        self.safe_zones.zones.clear();
+    }
+
+    pub fn safe_zone_count(&self) -> usize {
+        self.safe_zones.zones.entries.len()
+    }
+
+    pub fn safe_zone_ids(&self) -> Vec<u32> {
+        self.safe_zones.zones.ids()
+    }
+
+    // Clamped to [1, 20]; 1 leaves panic fading at the normal rate.
+    pub fn set_safe_zone_panic_decay(&mut self, panic_decay: f32) {
+        self.safe_zones.panic_decay =
+            clamp_finite(panic_decay, 1.0, MAX_PANIC_DECAY, DEFAULT_PANIC_DECAY);
+    }
+
+    pub fn safe_zone_panic_decay(&self) -> f32 {
+        self.safe_zones.panic_decay
+    }
+
+    pub fn boid_in_safe_zone(&self, slot: usize) -> bool {
+        slot < self.active_count && self.in_safe_zone(slot)
+    }
+}
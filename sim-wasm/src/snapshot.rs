@@ -0,0 +1,283 @@
+use crate::{Sim, WORLD_SIZE};
+use std::collections::VecDeque;
+use wasm_bindgen::prelude::*;
+
+const MAX_SNAPSHOT_CAPACITY: usize = 3600;
+const KEYFRAME_INTERVAL: usize = 30;
+const POSITION_LEVELS: f32 = 65535.0;
+const VECTOR_LEVELS: f32 = 32767.0;
+// pos, vel, heading and accel, three axes each.
+const CHANNEL_COUNT: usize = 12;
+
+// One captured step. Every channel is quantized to integers, then stored as
+// zigzag varints of the difference from the previous frame (or from zero for
+// keyframes), so slowly changing state costs one or two bytes per value.
+struct SnapshotFrame {
+    keyframe: bool,
+    active_count: usize,
+    step_index: u32,
+    fish_phase: f32,
+    burst_phase: f32,
+    turn_noise_time: f32,
+    wind: (f32, f32, f32),
+    vector_scales: [f32; CHANNEL_COUNT],
+    payload: Vec<u8>,
+}
+
+#[derive(Default)]
+pub struct SnapshotHistory {
+    capacity: usize,
+    frames: VecDeque<SnapshotFrame>,
+    // Quantized values of the newest frame, the base for the next delta.
+    last_quantized: Vec<i32>,
+}
+
+impl SnapshotHistory {
+    pub fn enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    fn payload_bytes(&self) -> usize {
+        self.frames.iter().map(|frame| frame.payload.len()).sum()
+    }
+
+    // Decodes frames from the nearest keyframe up to `index`, returning the
+    // quantized values of that frame.
+    fn decode(&self, index: usize) -> Vec<i32> {
+        let start = (0..=index)
+            .rev()
+            .find(|&k| self.frames[k].keyframe)
+            .unwrap_or(0);
+        let mut quantized = Vec::new();
+        for frame in self.frames.range(start..=index) {
+            apply_deltas(&mut quantized, &frame.payload, frame.keyframe);
+        }
+        quantized
+    }
+
+    fn push(&mut self, mut frame: SnapshotFrame, quantized: Vec<i32>) {
+        let recent_keyframe = self
+            .frames
+            .iter()
+            .rev()
+            .take(KEYFRAME_INTERVAL - 1)
+            .any(|frame| frame.keyframe);
+        frame.keyframe = !recent_keyframe || self.last_quantized.len() != quantized.len();
+        frame.payload = encode_deltas(&quantized, &self.last_quantized, frame.keyframe);
+        self.frames.push_back(frame);
+        self.last_quantized = quantized;
+
+        if self.frames.len() > self.capacity {
+            self.evict_oldest();
+        }
+    }
+
+    // Dropping the oldest keyframe re-encodes its successor as one so the rest
+    // of the group stays decodable.
+    fn evict_oldest(&mut self) {
+        if self.frames.len() > 1 && !self.frames[1].keyframe {
+            let quantized = self.decode(1);
+            self.frames[1].payload = encode_deltas(&quantized, &[], true);
+            self.frames[1].keyframe = true;
+        }
+        self.frames.pop_front();
+    }
+}
+
+impl Sim {
+    pub(super) fn capture_snapshot(&mut self) {
+        let mut vector_scales = [0.0; CHANNEL_COUNT];
+        let mut quantized = Vec::with_capacity(self.count * CHANNEL_COUNT);
+        for (channel, values) in self.snapshot_channels().into_iter().enumerate() {
+            if channel < 3 {
+                vector_scales[channel] = WORLD_SIZE / POSITION_LEVELS;
+            } else {
+                // Range is rounded up to a power of two so the scale, and with
+                // it the meaning of the deltas, rarely changes between frames.
+                let max_abs = values.iter().fold(0.0_f32, |max, v| max.max(v.abs()));
+                let range = if max_abs > 0.0 {
+                    max_abs.log2().ceil().exp2()
+                } else {
+                    1.0
+                };
+                vector_scales[channel] = range / VECTOR_LEVELS;
+            }
+            let scale = vector_scales[channel];
+            quantized.extend(values.iter().map(|v| (v / scale).round() as i32));
+        }
+
+        let frame = SnapshotFrame {
+            keyframe: false,
+            active_count: self.active_count,
+            step_index: self.step_index,
+            fish_phase: self.fish_phase,
+            burst_phase: self.burst_coast.phase,
+            turn_noise_time: self.turn_noise.time_s,
+            wind: self.wind.velocity(),
+            vector_scales,
+            payload: Vec::new(),
+        };
+        self.snapshots.push(frame, quantized);
+    }
+
+    fn snapshot_channels(&self) -> [&[f32]; CHANNEL_COUNT] {
+        [
+            &self.pos_x,
+            &self.pos_y,
+            &self.pos_z,
+            &self.vel_x,
+            &self.vel_y,
+            &self.vel_z,
+            &self.heading_x,
+            &self.heading_y,
+            &self.heading_z,
+            &self.accel_x,
+            &self.accel_y,
+            &self.accel_z,
+        ]
+    }
+}
+
+#[wasm_bindgen]
+impl Sim {
+    // Keeps the last `capacity` steps for rollback; 0 disables capture and
+    // frees the history.
+    pub fn set_snapshot_capacity(&mut self, capacity: usize) {
+        let capacity = capacity.min(MAX_SNAPSHOT_CAPACITY);
+        self.snapshots.capacity = capacity;
+        while self.snapshots.frames.len() > capacity {
+            self.snapshots.evict_oldest();
+        }
+        if capacity == 0 {
+            self.snapshots.last_quantized = Vec::new();
+        }
+    }
+
+    pub fn snapshot_capacity(&self) -> usize {
+        self.snapshots.capacity
+    }
+
+    pub fn snapshot_count(&self) -> usize {
+        self.snapshots.frames.len()
+    }
+
+    pub fn snapshot_history_bytes(&self) -> usize {
+        self.snapshots.payload_bytes()
+    }
+
+    // Rolls back to the snapshot taken `steps_back` steps before the newest one
+    // (0 is the newest) and discards everything after it. Positions come back
+    // within 1/65535 of the world, the vector channels within 1/32767 of their
+    // largest magnitude at capture time, rounded up to a power of two.
+    pub fn restore_snapshot(&mut self, steps_back: usize) -> bool {
+        let frame_count = self.snapshots.frames.len();
+        if steps_back >= frame_count {
+            return false;
+        }
+
+        let index = frame_count - 1 - steps_back;
+        let quantized = self.snapshots.decode(index);
+        let frame = &self.snapshots.frames[index];
+        let scales = frame.vector_scales;
+        self.active_count = frame.active_count;
+        self.step_index = frame.step_index;
+        self.fish_phase = frame.fish_phase;
+        self.burst_coast.phase = frame.burst_phase;
+        self.turn_noise.time_s = frame.turn_noise_time;
+        self.wind.set_velocity(frame.wind);
+
+        let n = self.count;
+        for (channel, values) in [
+            &mut self.pos_x,
+            &mut self.pos_y,
+            &mut self.pos_z,
+            &mut self.vel_x,
+            &mut self.vel_y,
+            &mut self.vel_z,
+            &mut self.heading_x,
+            &mut self.heading_y,
+            &mut self.heading_z,
+            &mut self.accel_x,
+            &mut self.accel_y,
+            &mut self.accel_z,
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let stored = &quantized[channel * n..(channel + 1) * n];
+            for (value, &q) in values.iter_mut().zip(stored) {
+                *value = q as f32 * scales[channel];
+            }
+        }
+
+        self.snapshots.frames.truncate(index + 1);
+        self.snapshots.last_quantized = quantized;
+        self.sync_render_buffers();
+        true
+    }
+
+    pub fn clear_snapshots(&mut self) {
+        self.snapshots.frames.clear();
+        self.snapshots.last_quantized.clear();
+    }
+}
+
+fn encode_deltas(values: &[i32], previous: &[i32], keyframe: bool) -> Vec<u8> {
+    let mut out = Vec::with_capacity(values.len() * 2);
+    for (i, &value) in values.iter().enumerate() {
+        let base = if keyframe { 0 } else { previous[i] };
+        let delta = value.wrapping_sub(base);
+        let mut zigzag = ((delta << 1) ^ (delta >> 31)) as u32;
+        while zigzag >= 0x80 {
+            out.push((zigzag as u8) | 0x80);
+            zigzag >>= 7;
+        }
+        out.push(zigzag as u8);
+    }
+    out
+}
+
+fn apply_deltas(values: &mut Vec<i32>, payload: &[u8], keyframe: bool) {
+    if keyframe {
+        values.clear();
+    }
+    let mut index = 0;
+    let mut bytes = payload.iter();
+    while let Some(&first) = bytes.next() {
+        let mut zigzag = u32::from(first & 0x7f);
+        let mut shift = 7;
+        let mut byte = first;
+        while byte & 0x80 != 0 {
+            byte = *bytes.next().unwrap_or(&0);
+            zigzag |= u32::from(byte & 0x7f) << shift;
+            shift += 7;
+        }
+        let delta = ((zigzag >> 1) as i32) ^ -((zigzag & 1) as i32);
+        if keyframe {
+            values.push(delta);
+        } else {
+            values[index] = values[index].wrapping_add(delta);
+        }
+        index += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply_deltas, encode_deltas};
+
+    #[test]
+    fn deltas_round_trip_through_varints() {
+        let keyframe = vec![0, 1, -1, 65535, i32::MIN, i32::MAX];
+        let next = vec![3, 1, -200, 0, i32::MAX, i32::MIN];
+
+        let mut decoded = Vec::new();
+        apply_deltas(&mut decoded, &encode_deltas(&keyframe, &[], true), true);
+        assert_eq!(decoded, keyframe);
+
+        let delta = encode_deltas(&next, &keyframe, false);
+        assert_eq!(delta[1], 0);
+        apply_deltas(&mut decoded, &delta, false);
+        assert_eq!(decoded, next);
+    }
+}
@@ -0,0 +1,66 @@
+use crate::recording::FieldCodec;
+use crate::{clamp_finite, Sim};
+use wasm_bindgen::prelude::*;
+
+const MAX_SPAWN_MARGIN: f32 = 0.25;
+const MAX_SPAWN_RELAX_ITERATIONS: u32 = 64;
+
+// Where new boids appear. Every spawn lands at least `margin` inside the
+// world on x and y (and on z within a quarter of the depth), so nothing
+// starts against a wall. `Sim::scatter_boids` also runs the hard minimum
+// distance solver `relax_iterations` times over the fresh layout, so the
+// first steps do not fling overlapping boids apart.
+#[derive(Clone, Copy, Default)]
+pub struct SpawnLayout {
+    margin: f32,
+    relax_iterations: u32,
+}
+
+impl SpawnLayout {
+    // Maps a unit random draw onto `[min, min + extent]` less the margin,
+    // which is capped at a quarter of the extent.
+    pub fn place(&self, unit: f32, min: f32, extent: f32) -> f32 {
+        let margin = self.margin.min(extent * 0.25);
+        min + margin + unit * (extent - 2.0 * margin)
+    }
+
+    pub fn visit_settings(&mut self, codec: &mut dyn FieldCodec) {
+        codec.f32(&mut self.margin);
+        codec.u32(&mut self.relax_iterations);
+    }
+}
+
+#[wasm_bindgen]
+impl Sim {
+    // Margin in world units is clamped to [0, 0.25] and relaxation to at most
+    // 64 passes. Applies to later spawns; `scatter_boids` re-lays the flock.
+    pub fn set_spawn_layout(&mut self, margin: f32, relax_iterations: u32) {
+        self.spawn_layout.margin = clamp_finite(margin, 0.0, MAX_SPAWN_MARGIN, 0.0);
+        self.spawn_layout.relax_iterations = relax_iterations.min(MAX_SPAWN_RELAX_ITERATIONS);
+    }
+
+    pub fn spawn_margin(&self) -> f32 {
+        self.spawn_layout.margin
+    }
+
+    pub fn spawn_relax_iterations(&self) -> u32 {
+        self.spawn_layout.relax_iterations
+    }
+
+    // Respawns every active boid under the current layout and relaxes the
+    // result, leaving ids, handles and the last step's report untouched.
+    pub fn scatter_boids(&mut self) {
+        for i in 0..self.active_count {
+            self.respawn_boid(i);
+        }
+        let events = self.step_events;
+        // No step runs between passes, so each may close the whole overlap.
+        let max_push = self.max_hard_min_distance();
+        for _ in 0..self.spawn_layout.relax_iterations {
+            self.resolve_hard_min_distance_pass(1.0, max_push);
+        }
+        self.step_events = events;
+        self.clear_snapshots();
+        self.sync_render_buffers();
+    }
+}
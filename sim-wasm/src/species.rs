@@ -0,0 +1,175 @@
+use crate::flock2::Flock2Config;
+use crate::{clamp_finite, Sim, MAX_MIN_DISTANCE, MIN_MIN_DISTANCE};
+use wasm_bindgen::prelude::*;
+
+pub const MAX_SPECIES: usize = 8;
+
+// The per-bird part of the flight config. Gravity and air density stay
+// shared, since every species flies through the same air.
+#[derive(Clone, Copy)]
+pub struct AeroProfile {
+    pub mass: f32,
+    pub wing_area: f32,
+    pub lift_factor: f32,
+    pub drag_factor: f32,
+    pub thrust: f32,
+    pub min_speed: f32,
+    pub max_speed: f32,
+}
+
+impl AeroProfile {
+    pub fn from_config(config: &Flock2Config) -> Self {
+        Self {
+            mass: config.mass,
+            wing_area: config.wing_area,
+            lift_factor: config.lift_factor,
+            drag_factor: config.drag_factor,
+            thrust: config.thrust,
+            min_speed: config.min_speed,
+            max_speed: config.max_speed,
+        }
+    }
+}
+
+// Symmetric per-pair override of the hard minimum distance; pairs without one
+// use the global `hard_min_distance`.
+pub type SpeciesHardMin = [[Option<f32>; MAX_SPECIES]; MAX_SPECIES];
+
+impl Sim {
+    pub(super) fn pair_hard_min_distance(&self, i: usize, j: usize) -> f32 {
+        self.species_hard_min[self.species[i] as usize][self.species[j] as usize]
+            .unwrap_or(self.config.hard_min_distance)
+    }
+
+    // Search radius for the constraint pass: the largest distance any pair
+    // may have to keep.
+    pub(super) fn max_hard_min_distance(&self) -> f32 {
+        self.species_hard_min
+            .iter()
+            .flatten()
+            .flatten()
+            .fold(self.config.hard_min_distance, |max, &d| max.max(d))
+    }
+
+    // Per-species search radius for the constraint pass: the largest distance
+    // that species keeps from any other.
+    pub(super) fn species_hard_min_radii(&self) -> [f32; MAX_SPECIES] {
+        std::array::from_fn(|a| {
+            (0..MAX_SPECIES)
+                .map(|b| self.species_hard_min_distance(a as u8, b as u8))
+                .fold(0.0, f32::max)
+        })
+    }
+
+    pub(super) fn has_species_hard_min(&self) -> bool {
+        self.species_hard_min.iter().flatten().any(Option::is_some)
+    }
+
+    // Species without their own profile fly with the shared flock2 config.
+    pub(super) fn aero_profile(&self, i: usize) -> AeroProfile {
+        self.species_aero
+            .get(self.species[i] as usize)
+            .copied()
+            .flatten()
+            .unwrap_or_else(|| AeroProfile::from_config(&self.flock2_config))
+    }
+
+    // The aero profile with its speed range scaled by boid `i`'s depth.
+    pub(super) fn depth_scaled_aero(&self, i: usize) -> AeroProfile {
+        let mut aero = self.aero_profile(i);
+        let scale = self.depth_speed_scale(i);
+        aero.min_speed *= scale;
+        aero.max_speed *= scale;
+        aero
+    }
+}
+
+#[wasm_bindgen]
+impl Sim {
+    // Species per slot; slots past the end of `species` are reset to 0 and
+    // ids past the last species are clamped to it.
+    pub fn set_species(&mut self, species: &[u8]) {
+        for (slot, entry) in self.species.iter_mut().enumerate() {
+            *entry = species
+                .get(slot)
+                .map_or(0, |&id| id.min(MAX_SPECIES as u8 - 1));
+        }
+    }
+
+    pub fn set_boid_species(&mut self, slot: usize, species: u8) {
+        if let Some(entry) = self.species.get_mut(slot) {
+            *entry = species.min(MAX_SPECIES as u8 - 1);
+        }
+    }
+
+    pub fn boid_species(&self, slot: usize) -> u8 {
+        self.species.get(slot).copied().unwrap_or(0)
+    }
+
+    // Gives one species its own flock2 aerodynamics and speed range, clamped
+    // to the same limits as `apply_flock2_config`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn set_flock2_species_aero(
+        &mut self,
+        species: u8,
+        mass: f32,
+        wing_area: f32,
+        lift_factor: f32,
+        drag_factor: f32,
+        thrust: f32,
+        min_speed: f32,
+        max_speed: f32,
+    ) {
+        let Some(entry) = self.species_aero.get_mut(species as usize) else {
+            return;
+        };
+        let mut config = Flock2Config {
+            mass,
+            wing_area,
+            lift_factor,
+            drag_factor,
+            thrust,
+            min_speed,
+            max_speed,
+            ..self.flock2_config
+        };
+        config.sanitize();
+        *entry = Some(AeroProfile::from_config(&config));
+    }
+
+    pub fn clear_flock2_species_aero(&mut self, species: u8) {
+        if let Some(entry) = self.species_aero.get_mut(species as usize) {
+            *entry = None;
+        }
+    }
+
+    pub fn has_flock2_species_aero(&self, species: u8) -> bool {
+        matches!(self.species_aero.get(species as usize), Some(Some(_)))
+    }
+
+    // Hard minimum distance kept between boids of species `a` and `b`, in
+    // either order; 0 lets the pair overlap freely.
+    pub fn set_species_hard_min_distance(&mut self, a: u8, b: u8, distance: f32) {
+        let (a, b) = (a as usize, b as usize);
+        if a >= MAX_SPECIES || b >= MAX_SPECIES {
+            return;
+        }
+        let distance = clamp_finite(distance, MIN_MIN_DISTANCE, MAX_MIN_DISTANCE, 0.0);
+        self.species_hard_min[a][b] = Some(distance);
+        self.species_hard_min[b][a] = Some(distance);
+    }
+
+    pub fn clear_species_hard_min_distances(&mut self) {
+        self.species_hard_min = [[None; MAX_SPECIES]; MAX_SPECIES];
+    }
+
+    // The distance the resolver enforces for the pair, override or global.
+    pub fn species_hard_min_distance(&self, a: u8, b: u8) -> f32 {
+        self.species_hard_min
+            .get(a as usize)
+            .and_then(|row| row.get(b as usize))
+            .copied()
+            .flatten()
+            .unwrap_or(self.config.hard_min_distance)
+    }
+}
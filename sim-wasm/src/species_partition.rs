@@ -0,0 +1,118 @@
+use crate::recording::FieldCodec;
+use crate::species::MAX_SPECIES;
+use crate::Sim;
+use wasm_bindgen::prelude::*;
+
+// Output-stage copy of the render buffers with the active boids grouped by
+// species, so a renderer can draw each species as one contiguous range with
+// its own material. Species k fills entries `offsets[k]..offsets[k + 1]`, in
+// slot order within the species; `source` gives the slot each entry copies
+// for any other per-boid buffer. Mirror, kaleidoscope and wrap ghost copies
+// keep their own buffers. Off unless enabled.
+#[derive(Default)]
+pub struct SpeciesPartition {
+    enabled: bool,
+    pub offsets: [u32; MAX_SPECIES + 1],
+    pub xy: Vec<f32>,
+    pub z: Vec<f32>,
+    pub heading_xy: Vec<f32>,
+    pub scale: Vec<f32>,
+    pub rgba: Vec<u8>,
+    pub source: Vec<u32>,
+}
+
+impl SpeciesPartition {
+    pub fn visit_settings(&mut self, codec: &mut dyn FieldCodec) {
+        codec.bool(&mut self.enabled);
+    }
+}
+
+impl Sim {
+    pub(super) fn sync_species_partition(&mut self) {
+        let mut partition = std::mem::take(&mut self.species_partition);
+        partition.offsets = [0; MAX_SPECIES + 1];
+        partition.xy.clear();
+        partition.z.clear();
+        partition.heading_xy.clear();
+        partition.scale.clear();
+        partition.rgba.clear();
+        partition.source.clear();
+        if partition.enabled {
+            let species = &self.species[..self.active_count];
+            for &s in species {
+                partition.offsets[s as usize + 1] += 1;
+            }
+            for k in 0..MAX_SPECIES {
+                partition.offsets[k + 1] += partition.offsets[k];
+            }
+            let mut next = partition.offsets;
+            partition.source.resize(self.active_count, 0);
+            for (i, &s) in species.iter().enumerate() {
+                let entry = &mut next[s as usize];
+                partition.source[*entry as usize] = i as u32;
+                *entry += 1;
+            }
+            for &slot in &partition.source {
+                let i = slot as usize;
+                partition
+                    .xy
+                    .extend_from_slice(&self.render_xy[2 * i..2 * i + 2]);
+                partition.z.push(self.render_z[i]);
+                partition
+                    .heading_xy
+                    .extend_from_slice(&self.render_heading_xy[2 * i..2 * i + 2]);
+                partition.scale.push(self.render_scale[i]);
+                partition
+                    .rgba
+                    .extend_from_slice(&self.color_map.rgba[4 * i..4 * i + 4]);
+            }
+        }
+        self.species_partition = partition;
+    }
+}
+
+#[wasm_bindgen]
+impl Sim {
+    pub fn set_species_partition(&mut self, enabled: bool) {
+        self.species_partition.enabled = enabled;
+        self.sync_render_buffers();
+    }
+
+    pub fn species_partition(&self) -> bool {
+        self.species_partition.enabled
+    }
+
+    // MAX_SPECIES + 1 entry offsets; all zero while the partition is off.
+    pub fn species_partition_offsets_ptr(&self) -> *const u32 {
+        self.species_partition.offsets.as_ptr()
+    }
+
+    pub fn species_partition_offsets_len(&self) -> usize {
+        self.species_partition.offsets.len()
+    }
+
+    pub fn species_partition_xy_ptr(&self) -> *const f32 {
+        self.species_partition.xy.as_ptr()
+    }
+
+    pub fn species_partition_z_ptr(&self) -> *const f32 {
+        self.species_partition.z.as_ptr()
+    }
+
+    pub fn species_partition_heading_xy_ptr(&self) -> *const f32 {
+        self.species_partition.heading_xy.as_ptr()
+    }
+
+    pub fn species_partition_scale_ptr(&self) -> *const f32 {
+        self.species_partition.scale.as_ptr()
+    }
+
+    pub fn species_partition_colors_ptr(&self) -> *const u8 {
+        self.species_partition.rgba.as_ptr()
+    }
+
+    // Slot of the boid each entry copies.
+    pub fn species_partition_source_ptr(&self) -> *const u32 {
+        self.species_partition.source.as_ptr()
+    }
+}
@@ -0,0 +1,64 @@
+use crate::recording::FieldCodec;
+use crate::{clamp_finite, Sim};
+use wasm_bindgen::prelude::*;
+
+const MAX_SPEED_RADIUS_LOOKAHEAD_S: f32 = 2.0;
+const MIN_SPEED_RADIUS_MAX_SCALE: f32 = 1.0;
+const MAX_SPEED_RADIUS_MAX_SCALE: f32 = 4.0;
+const DEFAULT_SPEED_RADIUS_MAX_SCALE: f32 = 2.0;
+
+// Perception that reaches further at speed. A classic boid's neighbor radius
+// grows by the distance it covers in `lookahead_s` at its current speed, up to
+// `max_scale` times the radius it would otherwise use, so a fast flock keeps
+// its members in range instead of out-running them and fragmenting. The grown
+// radius also sets the distance-weighted cohesion falloff. Flock2 picks its
+// neighbors topologically and ignores this. Off while the lookahead is 0.
+pub struct SpeedRadius {
+    lookahead_s: f32,
+    max_scale: f32,
+}
+
+impl Default for SpeedRadius {
+    fn default() -> Self {
+        Self {
+            lookahead_s: 0.0,
+            max_scale: DEFAULT_SPEED_RADIUS_MAX_SCALE,
+        }
+    }
+}
+
+impl SpeedRadius {
+    pub fn radius(&self, radius: f32, speed: f32) -> f32 {
+        (radius + speed * self.lookahead_s).min(radius * self.max_scale)
+    }
+
+    pub fn visit_settings(&mut self, codec: &mut dyn FieldCodec) {
+        codec.f32(&mut self.lookahead_s);
+        codec.f32(&mut self.max_scale);
+    }
+}
+
+#[wasm_bindgen]
+impl Sim {
+    // Lookahead is clamped to [0, 2] seconds and the cap to [1, 4] times the
+    // base radius. The grown radius still stops at the global neighbor radius
+    // limit.
+    pub fn set_speed_radius(&mut self, lookahead_s: f32, max_scale: f32) {
+        self.speed_radius.lookahead_s =
+            clamp_finite(lookahead_s, 0.0, MAX_SPEED_RADIUS_LOOKAHEAD_S, 0.0);
+        self.speed_radius.max_scale = clamp_finite(
+            max_scale,
+            MIN_SPEED_RADIUS_MAX_SCALE,
+            MAX_SPEED_RADIUS_MAX_SCALE,
+            DEFAULT_SPEED_RADIUS_MAX_SCALE,
+        );
+    }
+
+    pub fn speed_radius_lookahead(&self) -> f32 {
+        self.speed_radius.lookahead_s
+    }
+
+    pub fn speed_radius_max_scale(&self) -> f32 {
+        self.speed_radius.max_scale
+    }
+}
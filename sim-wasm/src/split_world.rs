@@ -0,0 +1,240 @@
+use crate::recording::FieldCodec;
+use crate::{ModelKind, Sim, SimConfig, WORLD_SIZE};
+use wasm_bindgen::prelude::*;
+
+const SPLIT_X: f32 = 0.5 * WORLD_SIZE;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SplitHalf {
+    Left,
+    Right,
+}
+
+impl SplitHalf {
+    pub fn from_u32(value: u32) -> Self {
+        match value {
+            1 => Self::Right,
+            _ => Self::Left,
+        }
+    }
+
+    pub fn of(x: f32) -> Self {
+        if x < SPLIT_X {
+            Self::Left
+        } else {
+            Self::Right
+        }
+    }
+
+    // Left boids stop just short of the divide so their half never changes.
+    pub fn range(self) -> (f32, f32) {
+        match self {
+            Self::Left => (0.0, f32::from_bits(SPLIT_X.to_bits() - 1)),
+            Self::Right => (SPLIT_X, WORLD_SIZE),
+        }
+    }
+}
+
+// The right half's classic weights, radii and speed limits. Everything else
+// is shared with the main config.
+#[derive(Clone, Copy)]
+struct SplitConfig {
+    sep_weight: f32,
+    align_weight: f32,
+    coh_weight: f32,
+    neighbor_radius: f32,
+    separation_radius: f32,
+    min_speed: f32,
+    max_speed: f32,
+    max_force: f32,
+}
+
+impl SplitConfig {
+    fn from_config(config: &SimConfig) -> Self {
+        Self {
+            sep_weight: config.sep_weight,
+            align_weight: config.align_weight,
+            coh_weight: config.coh_weight,
+            neighbor_radius: config.neighbor_radius,
+            separation_radius: config.separation_radius,
+            min_speed: config.min_speed,
+            max_speed: config.max_speed,
+            max_force: config.max_force,
+        }
+    }
+
+    fn over(self, config: SimConfig) -> SimConfig {
+        SimConfig {
+            sep_weight: self.sep_weight,
+            align_weight: self.align_weight,
+            coh_weight: self.coh_weight,
+            neighbor_radius: self.neighbor_radius,
+            separation_radius: self.separation_radius,
+            min_speed: self.min_speed,
+            max_speed: self.max_speed,
+            max_force: self.max_force,
+            ..config
+        }
+    }
+}
+
+// A/B mode for the classic models: the left half runs the main config and
+// the right half its own. The divide is a wall on both sides, so boids never
+// cross it, and neither steering nor the hard constraint looks across it.
+pub struct SplitWorld {
+    pub enabled: bool,
+    right: SplitConfig,
+}
+
+impl Default for SplitWorld {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            right: SplitConfig::from_config(&SimConfig::default()),
+        }
+    }
+}
+
+impl SplitWorld {
+    pub fn visit_settings(&mut self, codec: &mut dyn FieldCodec) {
+        codec.bool(&mut self.enabled);
+        let right = &mut self.right;
+        codec.f32(&mut right.sep_weight);
+        codec.f32(&mut right.align_weight);
+        codec.f32(&mut right.coh_weight);
+        codec.f32(&mut right.neighbor_radius);
+        codec.f32(&mut right.separation_radius);
+        codec.f32(&mut right.min_speed);
+        codec.f32(&mut right.max_speed);
+        codec.f32(&mut right.max_force);
+    }
+}
+
+impl Sim {
+    pub(super) fn split_active(&self) -> bool {
+        self.split_world.enabled
+            && matches!(self.model_kind, ModelKind::Classic | ModelKind::FishSchool)
+    }
+
+    // None unless the split is in effect.
+    pub(super) fn split_half(&self, i: usize) -> Option<SplitHalf> {
+        self.split_active().then(|| SplitHalf::of(self.pos_x[i]))
+    }
+
+    // Per-boid passes run once over everyone, or once per half when split.
+    pub(super) fn split_passes(&self) -> &'static [Option<SplitHalf>] {
+        if self.split_active() {
+            &[Some(SplitHalf::Left), Some(SplitHalf::Right)]
+        } else {
+            &[None]
+        }
+    }
+
+    pub(super) fn in_split_pass(&self, i: usize, pass: Option<SplitHalf>) -> bool {
+        pass.is_none_or(|half| SplitHalf::of(self.pos_x[i]) == half)
+    }
+
+    // Runs `f` with `config` swapped for the right half's while in that pass.
+    pub(super) fn with_split_config<R>(
+        &mut self,
+        pass: Option<SplitHalf>,
+        f: impl FnOnce(&mut Self) -> R,
+    ) -> R {
+        if pass != Some(SplitHalf::Right) {
+            return f(self);
+        }
+        let main = self.config;
+        self.config = self.split_world.right.over(main);
+        let result = f(self);
+        self.config = main;
+        result
+    }
+
+    pub(super) fn split_separates(&self, i: usize, j: usize) -> bool {
+        self.split_active() && SplitHalf::of(self.pos_x[i]) != SplitHalf::of(self.pos_x[j])
+    }
+
+    // Like `project_axis_position` for x, but held inside the boid's half or
+    // the folded mirror half.
+    pub(super) fn project_x(&self, x: f32, half: Option<SplitHalf>) -> f32 {
+        match half {
+            Some(half) => {
+                let (lo, hi) = half.range();
+                x.clamp(lo, hi)
+            }
+            None => self.project_mirror_axis(x, self.mirror.x, self.bounce_x),
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl Sim {
+    // Classic models only. Enabling starts the right half from the current
+    // config; boids keep the half they are in. While split, x bounces at the
+    // world edges and the divide regardless of the wrap setting.
+    pub fn set_split_world(&mut self, enabled: bool) {
+        if enabled && !self.split_world.enabled {
+            self.split_world.right = SplitConfig::from_config(&self.config);
+        }
+        self.split_world.enabled = enabled;
+    }
+
+    pub fn split_world(&self) -> bool {
+        self.split_world.enabled
+    }
+
+    // Same parameters and clamping as `set_config`, for the right half.
+    #[allow(clippy::too_many_arguments)]
+    pub fn set_split_config(
+        &mut self,
+        sep_weight: f32,
+        align_weight: f32,
+        coh_weight: f32,
+        neighbor_radius: f32,
+        separation_radius: f32,
+        min_speed: f32,
+        max_speed: f32,
+        max_force: f32,
+    ) {
+        let mut config = SimConfig {
+            sep_weight,
+            align_weight,
+            coh_weight,
+            neighbor_radius,
+            separation_radius,
+            min_speed,
+            max_speed,
+            max_force,
+            ..self.config
+        };
+        config.sanitize();
+        self.split_world.right = SplitConfig::from_config(&config);
+    }
+
+    // The right half's values in `set_split_config` order.
+    pub fn split_config(&self) -> Vec<f32> {
+        let right = self.split_world.right;
+        vec![
+            right.sep_weight,
+            right.align_weight,
+            right.coh_weight,
+            right.neighbor_radius,
+            right.separation_radius,
+            right.min_speed,
+            right.max_speed,
+            right.max_force,
+        ]
+    }
+
+    // Boid count, polarization and mean speed of one half (0 left, 1 right),
+    // measured whether or not the split is enabled.
+    pub fn split_metrics(&self, half: u32) -> Vec<f32> {
+        let half = SplitHalf::from_u32(half);
+        let count = (0..self.active_count)
+            .filter(|&i| SplitHalf::of(self.pos_x[i]) == half)
+            .count();
+        let (polarization, mean_speed) =
+            self.polarization_and_mean_speed_where(|i| SplitHalf::of(self.pos_x[i]) == half);
+        vec![count as f32, polarization, mean_speed]
+    }
+}
@@ -0,0 +1,123 @@
+use crate::Sim;
+use wasm_bindgen::prelude::*;
+
+// Separation, alignment, cohesion, jitter and speed matching, xyz each.
+pub const STEERING_DEBUG_STRIDE: usize = 15;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SteeringDebugMode {
+    Off,
+    Inspected,
+    All,
+}
+
+impl SteeringDebugMode {
+    fn from_u32(value: u32) -> Self {
+        match value {
+            1 => Self::Inspected,
+            2 => Self::All,
+            _ => Self::Off,
+        }
+    }
+
+    fn as_u32(self) -> u32 {
+        match self {
+            Self::Off => 0,
+            Self::Inspected => 1,
+            Self::All => 2,
+        }
+    }
+}
+
+// Weighted steering terms before the max-force limit. Shape, custom and
+// model-specific forces are not broken out.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SteeringComponents {
+    pub separation: [f32; 3],
+    pub alignment: [f32; 3],
+    pub cohesion: [f32; 3],
+    pub jitter: [f32; 3],
+    pub speed_match: [f32; 3],
+}
+
+pub struct SteeringDebug {
+    mode: SteeringDebugMode,
+    inspected: usize,
+    pub components: Vec<f32>,
+}
+
+impl Default for SteeringDebug {
+    fn default() -> Self {
+        Self {
+            mode: SteeringDebugMode::Off,
+            inspected: 0,
+            components: Vec::new(),
+        }
+    }
+}
+
+impl SteeringDebug {
+    // Buffer slot for boid `i`, if its components are being captured.
+    pub fn slot(&self, i: usize) -> Option<usize> {
+        match self.mode {
+            SteeringDebugMode::Off => None,
+            SteeringDebugMode::Inspected => (i == self.inspected).then_some(0),
+            SteeringDebugMode::All => Some(i),
+        }
+    }
+
+    // Only the all-boids mode sizes its buffer by capacity.
+    pub fn resize(&mut self, count: usize) {
+        if self.mode == SteeringDebugMode::All {
+            self.components.resize(count * STEERING_DEBUG_STRIDE, 0.0);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.components.fill(0.0);
+    }
+
+    pub fn store(&mut self, slot: usize, components: &SteeringComponents) {
+        let base = slot * STEERING_DEBUG_STRIDE;
+        let out = &mut self.components[base..base + STEERING_DEBUG_STRIDE];
+        out[0..3].copy_from_slice(&components.separation);
+        out[3..6].copy_from_slice(&components.alignment);
+        out[6..9].copy_from_slice(&components.cohesion);
+        out[9..12].copy_from_slice(&components.jitter);
+        out[12..15].copy_from_slice(&components.speed_match);
+    }
+}
+
+#[wasm_bindgen]
+impl Sim {
+    // Mode 1 captures only `inspected`, mode 2 every boid. Only the classic and
+    // fish models fill the buffer. It is reallocated here and, in mode 2, by
+    // `reserve`, so JS views must be recreated after either call.
+    pub fn set_steering_debug(&mut self, mode: u32, inspected: usize) {
+        let debug = &mut self.steering_debug;
+        debug.mode = SteeringDebugMode::from_u32(mode);
+        debug.inspected = inspected.min(self.count.saturating_sub(1));
+        let slots = match debug.mode {
+            SteeringDebugMode::Off => 0,
+            SteeringDebugMode::Inspected => 1,
+            SteeringDebugMode::All => self.count,
+        };
+        debug.components = vec![0.0; slots * STEERING_DEBUG_STRIDE];
+    }
+
+    pub fn steering_debug_mode(&self) -> u32 {
+        self.steering_debug.mode.as_u32()
+    }
+
+    pub fn steering_debug_inspected(&self) -> usize {
+        self.steering_debug.inspected
+    }
+
+    pub fn steering_debug_ptr(&self) -> *const f32 {
+        self.steering_debug.components.as_ptr()
+    }
+
+    pub fn steering_debug_len(&self) -> usize {
+        self.steering_debug.components.len()
+    }
+}
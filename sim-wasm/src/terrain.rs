@@ -0,0 +1,180 @@
+use crate::recording::FieldCodec;
+use crate::{clamp_finite, Sim};
+use wasm_bindgen::prelude::*;
+
+pub const MAX_TERRAIN_SIDE: u32 = 512;
+const MAX_TERRAIN_WEIGHT: f32 = 20.0;
+const DEFAULT_TERRAIN_WEIGHT: f32 = 4.0;
+const MIN_TERRAIN_CLEARANCE: f32 = 0.005;
+const MAX_TERRAIN_CLEARANCE: f32 = 0.5;
+const DEFAULT_TERRAIN_CLEARANCE: f32 = 0.05;
+// How far ahead along its velocity a boid checks the ground, in seconds.
+const TERRAIN_LOOKAHEAD_S: f32 = 0.25;
+
+// A z floor over the xy plane, sampled bilinearly from a grid whose corners
+// sit on the world corners. Only used in z mode. Boids within `clearance` of
+// the ground under them or just ahead climb, harder the closer they are, and
+// the integrator never lets them below it.
+pub struct Terrain {
+    width: u32,
+    height: u32,
+    heights: Vec<f32>,
+    weight: f32,
+    clearance: f32,
+}
+
+impl Default for Terrain {
+    fn default() -> Self {
+        Self {
+            width: 0,
+            height: 0,
+            heights: Vec::new(),
+            weight: DEFAULT_TERRAIN_WEIGHT,
+            clearance: DEFAULT_TERRAIN_CLEARANCE,
+        }
+    }
+}
+
+impl Terrain {
+    pub fn is_empty(&self) -> bool {
+        self.heights.is_empty()
+    }
+
+    pub fn sample(&self, x: f32, y: f32) -> f32 {
+        if self.heights.is_empty() {
+            return f32::NEG_INFINITY;
+        }
+        let (w, h) = (self.width as usize, self.height as usize);
+        let gx = x.clamp(0.0, 1.0) * (w - 1) as f32;
+        let gy = y.clamp(0.0, 1.0) * (h - 1) as f32;
+        let (x0, y0) = (gx.floor() as usize, gy.floor() as usize);
+        let (x1, y1) = ((x0 + 1).min(w - 1), (y0 + 1).min(h - 1));
+        let (tx, ty) = (gx - x0 as f32, gy - y0 as f32);
+        let at = |cx: usize, cy: usize| self.heights[cy * w + cx];
+        let top = at(x0, y0) + (at(x1, y0) - at(x0, y0)) * tx;
+        let bottom = at(x0, y1) + (at(x1, y1) - at(x0, y1)) * tx;
+        top + (bottom - top) * ty
+    }
+
+    pub fn visit_settings(&mut self, codec: &mut dyn FieldCodec) {
+        codec.f32(&mut self.weight);
+        codec.f32(&mut self.clearance);
+        codec.u32(&mut self.width);
+        codec.u32(&mut self.height);
+        self.width = self.width.min(MAX_TERRAIN_SIDE);
+        self.height = self.height.min(MAX_TERRAIN_SIDE);
+        if self.width < 2 || self.height < 2 {
+            (self.width, self.height) = (0, 0);
+        }
+        self.heights
+            .resize((self.width * self.height) as usize, 0.0);
+        codec.f32_slice(&mut self.heights);
+    }
+}
+
+impl Sim {
+    pub(super) fn terrain_active(&self) -> bool {
+        self.z_mode_enabled && !self.terrain.is_empty()
+    }
+
+    // The ground's height under boid `i`, or just ahead of it if higher.
+    fn terrain_floor_ahead(&self, i: usize) -> f32 {
+        let (x, y) = (self.pos_x[i], self.pos_y[i]);
+        let ahead = self.terrain.sample(
+            x + self.vel_x[i] * TERRAIN_LOOKAHEAD_S,
+            y + self.vel_y[i] * TERRAIN_LOOKAHEAD_S,
+        );
+        self.terrain.sample(x, y).max(ahead)
+    }
+
+    // Upward push for boid `i`, growing linearly to the full weight at the
+    // ground; 0 outside z mode or without terrain.
+    pub(super) fn terrain_lift(&self, i: usize) -> f32 {
+        if !self.terrain_active() {
+            return 0.0;
+        }
+        let terrain = &self.terrain;
+        let gap = self.pos_z[i] - self.terrain_floor_ahead(i);
+        if gap >= terrain.clearance {
+            return 0.0;
+        }
+        terrain.weight * (1.0 - gap.max(0.0) / terrain.clearance)
+    }
+
+    // Lifts boid `i` onto the ground if it sank below, dropping any downward
+    // `vz`, which is returned.
+    pub(super) fn clamp_to_terrain(&mut self, i: usize, vz: f32) -> f32 {
+        if !self.terrain_active() {
+            return vz;
+        }
+        let floor = self
+            .terrain
+            .sample(self.pos_x[i], self.pos_y[i])
+            .min(self.z_extent.max);
+        if self.pos_z[i] >= floor {
+            return vz;
+        }
+        self.pos_z[i] = floor;
+        vz.max(0.0)
+    }
+}
+
+#[wasm_bindgen]
+impl Sim {
+    // Row-major heights, `width` samples along x per row and `height` rows
+    // along y, each side 2 to 512. Heights are world z, clamped to [0, 1].
+    // Returns false and keeps the previous map when the sizes do not match.
+    pub fn set_terrain_heightmap(&mut self, width: u32, height: u32, heights: &[f32]) -> bool {
+        let sides = 2..=MAX_TERRAIN_SIDE;
+        if !sides.contains(&width)
+            || !sides.contains(&height)
+            || heights.len() != (width * height) as usize
+        {
+            return false;
+        }
+        let terrain = &mut self.terrain;
+        (terrain.width, terrain.height) = (width, height);
+        terrain.heights.clear();
+        terrain
+            .heights
+            .extend(heights.iter().map(|&h| clamp_finite(h, 0.0, 1.0, 0.0)));
+        true
+    }
+
+    pub fn clear_terrain(&mut self) {
+        self.terrain.heights.clear();
+        (self.terrain.width, self.terrain.height) = (0, 0);
+    }
+
+    pub fn has_terrain(&self) -> bool {
+        !self.terrain.is_empty()
+    }
+
+    // Ground height at (x, y), or 0 without terrain.
+    pub fn terrain_height(&self, x: f32, y: f32) -> f32 {
+        if self.terrain.is_empty() {
+            0.0
+        } else {
+            self.terrain.sample(x, y)
+        }
+    }
+
+    // Weight is clamped to [0, 20] and the clearance to [0.005, 0.5].
+    pub fn set_terrain_avoidance(&mut self, weight: f32, clearance: f32) {
+        self.terrain.weight = clamp_finite(weight, 0.0, MAX_TERRAIN_WEIGHT, DEFAULT_TERRAIN_WEIGHT);
+        self.terrain.clearance = clamp_finite(
+            clearance,
+            MIN_TERRAIN_CLEARANCE,
+            MAX_TERRAIN_CLEARANCE,
+            DEFAULT_TERRAIN_CLEARANCE,
+        );
+    }
+
+    pub fn terrain_weight(&self) -> f32 {
+        self.terrain.weight
+    }
+
+    pub fn terrain_clearance(&self) -> f32 {
+        self.terrain.clearance
+    }
+}
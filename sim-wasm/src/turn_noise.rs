@@ -0,0 +1,167 @@
+use crate::recording::FieldCodec;
+use crate::{clamp_finite, hash_unit, Sim};
+use wasm_bindgen::prelude::*;
+
+const MAX_TURN_NOISE_STRENGTH: f32 = 4.0;
+const MIN_TURN_NOISE_SCALE: f32 = 0.1;
+const MAX_TURN_NOISE_SCALE: f32 = 2.0;
+const DEFAULT_TURN_NOISE_SCALE: f32 = 0.5;
+const MIN_TURN_NOISE_PERIOD_S: f32 = 0.5;
+const MAX_TURN_NOISE_PERIOD_S: f32 = 60.0;
+const DEFAULT_TURN_NOISE_PERIOD_S: f32 = 6.0;
+// The lattice repeats in time after this many periods, which keeps the clock
+// small enough for f32 precision.
+const TIME_CELLS: u32 = 1024;
+// `hash_unit` axes for the yaw and pitch fields.
+const YAW_AXIS: u32 = 10;
+const PITCH_AXIS: u32 = 11;
+
+// Turning noise shared by nearby boids: smooth value noise over a coarse
+// lattice in x, y and time, wrapping with the world. Boids within a `scale`
+// of each other read nearly the same turn, and the field drifts over
+// `period_s`, so the flock makes occasional coherent turns that per-boid
+// jitter never adds up to. Depth does not enter the field; in z mode a
+// second field pitches the flock. Off while the strength is 0.
+#[derive(Clone, Copy)]
+pub struct TurnNoise {
+    strength: f32,
+    scale: f32,
+    period_s: f32,
+    seed: u32,
+    pub time_s: f32,
+}
+
+impl Default for TurnNoise {
+    fn default() -> Self {
+        Self {
+            strength: 0.0,
+            scale: DEFAULT_TURN_NOISE_SCALE,
+            period_s: DEFAULT_TURN_NOISE_PERIOD_S,
+            seed: 0,
+            time_s: 0.0,
+        }
+    }
+}
+
+impl TurnNoise {
+    pub fn enabled(&self) -> bool {
+        self.strength > 0.0
+    }
+
+    pub fn advance(&mut self, dt: f32) {
+        if self.enabled() {
+            self.time_s = (self.time_s + dt) % (self.period_s * TIME_CELLS as f32);
+        }
+    }
+
+    // Field value in [-1, 1] at world (x, y).
+    fn sample(&self, x: f32, y: f32, axis: u32) -> f32 {
+        let cells = (1.0 / self.scale).round().max(1.0) as u32;
+        let gx = x.rem_euclid(1.0) * cells as f32;
+        let gy = y.rem_euclid(1.0) * cells as f32;
+        let gt = self.time_s / self.period_s;
+        let (x0, y0, t0) = (gx.floor(), gy.floor(), gt.floor());
+        let (tx, ty, tt) = (
+            smoothstep(gx - x0),
+            smoothstep(gy - y0),
+            smoothstep(gt - t0),
+        );
+        let (x0, y0, t0) = (x0 as u32 % cells, y0 as u32 % cells, t0 as u32 % TIME_CELLS);
+        let (x1, y1, t1) = ((x0 + 1) % cells, (y0 + 1) % cells, (t0 + 1) % TIME_CELLS);
+        let lattice = |cx: u32, cy: u32, ct: u32| {
+            let key = ct.wrapping_add(self.seed.wrapping_mul(0x9E37_79B9));
+            hash_unit(key, cy * cells + cx, axis)
+        };
+        let plane = |ct: u32| {
+            let top = lerp(lattice(x0, y0, ct), lattice(x1, y0, ct), tx);
+            let bottom = lerp(lattice(x0, y1, ct), lattice(x1, y1, ct), tx);
+            lerp(top, bottom, ty)
+        };
+        lerp(plane(t0), plane(t1), tt)
+    }
+
+    pub fn visit_settings(&mut self, codec: &mut dyn FieldCodec) {
+        codec.f32(&mut self.strength);
+        codec.f32(&mut self.scale);
+        codec.f32(&mut self.period_s);
+        codec.u32(&mut self.seed);
+    }
+}
+
+fn smoothstep(t: f32) -> f32 {
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+impl Sim {
+    // Yaw and pitch turns for boid `i`, each within +-strength. Pitch is 0
+    // outside z mode.
+    pub(super) fn turn_noise(&self, i: usize) -> (f32, f32) {
+        let noise = &self.turn_noise;
+        if !noise.enabled() {
+            return (0.0, 0.0);
+        }
+        let (x, y) = (self.pos_x[i], self.pos_y[i]);
+        let yaw = noise.sample(x, y, YAW_AXIS) * noise.strength;
+        let pitch = if self.z_mode_enabled {
+            noise.sample(x, y, PITCH_AXIS) * noise.strength
+        } else {
+            0.0
+        };
+        (yaw, pitch)
+    }
+
+    // Classic-model force across boid `i`'s velocity: yaw turns it left in
+    // the xy plane and pitch lifts it along z.
+    pub(super) fn turn_noise_force(&self, i: usize, vx: f32, vy: f32) -> (f32, f32, f32) {
+        let (yaw, pitch) = self.turn_noise(i);
+        if yaw == 0.0 && pitch == 0.0 {
+            return (0.0, 0.0, 0.0);
+        }
+        let speed = (vx * vx + vy * vy).sqrt();
+        if speed <= f32::EPSILON {
+            return (0.0, 0.0, pitch);
+        }
+        (-vy / speed * yaw, vx / speed * yaw, pitch)
+    }
+}
+
+#[wasm_bindgen]
+impl Sim {
+    // Strength is clamped to [0, 4], the feature size to [0.1, 2] world units
+    // (rounded to a whole number of cells across the world) and the drift
+    // period to [0.5, 60] seconds. In classic models strength is a force, in
+    // flock2 a turn weight. A strength of 0 turns the noise off.
+    pub fn set_turn_noise(&mut self, strength: f32, scale: f32, period_s: f32, seed: u32) {
+        let noise = &mut self.turn_noise;
+        noise.strength = clamp_finite(strength, 0.0, MAX_TURN_NOISE_STRENGTH, 0.0);
+        noise.scale = clamp_finite(
+            scale,
+            MIN_TURN_NOISE_SCALE,
+            MAX_TURN_NOISE_SCALE,
+            DEFAULT_TURN_NOISE_SCALE,
+        );
+        noise.period_s = clamp_finite(
+            period_s,
+            MIN_TURN_NOISE_PERIOD_S,
+            MAX_TURN_NOISE_PERIOD_S,
+            DEFAULT_TURN_NOISE_PERIOD_S,
+        );
+        noise.seed = seed;
+    }
+
+    pub fn turn_noise_strength(&self) -> f32 {
+        self.turn_noise.strength
+    }
+
+    pub fn turn_noise_scale(&self) -> f32 {
+        self.turn_noise.scale
+    }
+
+    pub fn turn_noise_period(&self) -> f32 {
+        self.turn_noise.period_s
+    }
+}
@@ -0,0 +1,45 @@
+use crate::{hash_unit, Sim};
+use wasm_bindgen::prelude::*;
+
+// Axis passed to `hash_unit` so variation draws never line up with the other
+// id-keyed hashes.
+const VARIATION_AXIS: u32 = 7;
+
+impl Sim {
+    // Per-boid values in [0, 1) drawn from a hash of the variation seed and
+    // the boid's id, so they follow boids through compaction and reloads and
+    // do not depend on capacity.
+    pub(super) fn refill_variation(&mut self, from: usize) {
+        for slot in from..self.variation.len() {
+            let unit = hash_unit(self.variation_seed, self.boid_ids[slot], VARIATION_AXIS);
+            self.variation[slot] = (unit * 0.5 + 0.5).min(1.0 - f32::EPSILON);
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl Sim {
+    // Starts as the construction seed. Redraws every boid's value.
+    pub fn set_variation_seed(&mut self, seed: u32) {
+        self.variation_seed = seed;
+        self.refill_variation(0);
+    }
+
+    pub fn variation_seed(&self) -> u32 {
+        self.variation_seed
+    }
+
+    // Stable per-boid values in [0, 1) for renderers to hang hue, size or
+    // flap rate variety on. Indexed by slot like the render buffers.
+    pub fn variation_ptr(&self) -> *const f32 {
+        self.variation.as_ptr()
+    }
+
+    pub fn variation_len(&self) -> usize {
+        self.variation.len()
+    }
+
+    pub fn boid_variation(&self, slot: usize) -> f32 {
+        self.variation.get(slot).copied().unwrap_or(0.0)
+    }
+}
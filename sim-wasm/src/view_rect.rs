@@ -0,0 +1,55 @@
+use crate::neighbor_grid::NeighborGrid;
+use crate::{Sim, WORLD_SIZE};
+use wasm_bindgen::prelude::*;
+
+const VIEW_GRID_CELL_SIZE: f32 = 1.0 / 16.0;
+
+// Grid over simulation positions used only for view-rectangle queries. It is
+// kept apart from the steering grid, whose cell size and rebuild schedule
+// follow the model, and is rebuilt lazily on the first query after the render
+// buffers change.
+pub struct ViewGrid {
+    grid: NeighborGrid,
+    stale: bool,
+}
+
+impl ViewGrid {
+    pub fn new(count: usize) -> Self {
+        Self {
+            grid: NeighborGrid::new(count, WORLD_SIZE, WORLD_SIZE, VIEW_GRID_CELL_SIZE),
+            stale: true,
+        }
+    }
+
+    pub fn mark_stale(&mut self) {
+        self.stale = true;
+    }
+}
+
+#[wasm_bindgen]
+impl Sim {
+    // Active slots whose position lies inside the rectangle (inclusive), in
+    // ascending order. Positions are world xy, not projected render xy.
+    pub fn visible_indices(&mut self, min_x: f32, min_y: f32, max_x: f32, max_y: f32) -> Vec<u32> {
+        let n = self.active_count;
+        let view = &mut self.view_grid;
+        if view.stale || view.grid.point_count() != n {
+            view.grid
+                .rebuild(&self.pos_x[..n], &self.pos_y[..n], WORLD_SIZE, WORLD_SIZE);
+            view.stale = false;
+        }
+
+        let mut found = Vec::new();
+        view.grid
+            .for_each_point_in_rect(min_x, min_y, max_x, max_y, |i| {
+                if (min_x..=max_x).contains(&self.pos_x[i])
+                    && (min_y..=max_y).contains(&self.pos_y[i])
+                {
+                    found.push(i as u32);
+                }
+                true
+            });
+        found.sort_unstable();
+        found
+    }
+}
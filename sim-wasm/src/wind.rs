@@ -0,0 +1,135 @@
+use crate::recording::FieldCodec;
+use crate::{clamp_finite, Sim, EPSILON};
+use wasm_bindgen::prelude::*;
+
+const MAX_WIND_SPEED: f32 = 3.0;
+const MIN_WIND_RESPONSE: f32 = 0.0;
+const MAX_WIND_RESPONSE: f32 = 50.0;
+const DEFAULT_WIND_RESPONSE: f32 = 1.5;
+const MAX_WIND_SHEAR: f32 = 20.0;
+const DEFAULT_SHEAR_BASE: f32 = 0.5;
+
+// Air velocity. `current` eases towards `target` at `response` per second so
+// changing the wind never snaps the whole flock sideways. `shear` adds a
+// horizontal gradient with height (world y), zero at `shear_base`.
+#[derive(Clone, Copy, Debug)]
+pub struct Wind {
+    current: [f32; 3],
+    target: [f32; 3],
+    response: f32,
+    shear: [f32; 2],
+    shear_base: f32,
+}
+
+impl Default for Wind {
+    fn default() -> Self {
+        Self {
+            current: [0.0; 3],
+            target: [0.0; 3],
+            response: DEFAULT_WIND_RESPONSE,
+            shear: [0.0; 2],
+            shear_base: DEFAULT_SHEAR_BASE,
+        }
+    }
+}
+
+impl Wind {
+    pub fn advance(&mut self, dt: f32) {
+        // A zero response applies new targets immediately.
+        let blend = if self.response <= EPSILON {
+            1.0
+        } else {
+            1.0 - (-self.response * dt).exp()
+        };
+        for axis in 0..3 {
+            self.current[axis] += (self.target[axis] - self.current[axis]) * blend;
+        }
+    }
+
+    pub fn velocity(&self) -> (f32, f32, f32) {
+        (self.current[0], self.current[1], self.current[2])
+    }
+
+    pub fn velocity_at_height(&self, y: f32) -> (f32, f32, f32) {
+        let offset = y - self.shear_base;
+        (
+            self.current[0] + self.shear[0] * offset,
+            self.current[1],
+            self.current[2] + self.shear[1] * offset,
+        )
+    }
+
+    pub fn is_calm(&self) -> bool {
+        self.current == [0.0; 3] && self.shear == [0.0; 2]
+    }
+
+    pub fn set_velocity(&mut self, (x, y, z): (f32, f32, f32)) {
+        self.current = [x, y, z];
+    }
+
+    pub fn visit_settings(&mut self, codec: &mut dyn FieldCodec) {
+        codec.f32_slice(&mut self.target);
+        codec.f32(&mut self.response);
+        codec.f32_slice(&mut self.shear);
+        codec.f32(&mut self.shear_base);
+    }
+
+    pub fn visit_state(&mut self, codec: &mut dyn FieldCodec) {
+        codec.f32_slice(&mut self.current);
+    }
+}
+
+#[wasm_bindgen]
+impl Sim {
+    // Wind advects classic boids during integration; it is not a steering force
+    // and never shows up in the stored velocities. The flight models instead
+    // fly through it: the full model computes lift and drag from airspeed,
+    // the lite model drags against the airspeed along its heading.
+    pub fn set_wind(&mut self, x: f32, y: f32, z: f32) {
+        self.wind.target = [x, y, z].map(|v| clamp_finite(v, -MAX_WIND_SPEED, MAX_WIND_SPEED, 0.0));
+    }
+
+    // Horizontal wind change per world unit of height, in world units per
+    // second, zero at `base_height`. Only the flight models feel it, as the
+    // air their drag (and, in the full model, lift) is computed against; a
+    // bird that climbs into a headwind and descends downwind can gain energy
+    // from the gradient.
+    pub fn set_wind_shear(&mut self, x_per_height: f32, z_per_height: f32, base_height: f32) {
+        self.wind.shear = [x_per_height, z_per_height]
+            .map(|v| clamp_finite(v, -MAX_WIND_SHEAR, MAX_WIND_SHEAR, 0.0));
+        self.wind.shear_base = clamp_finite(base_height, 0.0, 1.0, DEFAULT_SHEAR_BASE);
+    }
+
+    pub fn wind_shear_x(&self) -> f32 {
+        self.wind.shear[0]
+    }
+
+    pub fn wind_shear_z(&self) -> f32 {
+        self.wind.shear[1]
+    }
+
+    pub fn wind_x(&self) -> f32 {
+        self.wind.current[0]
+    }
+
+    pub fn wind_y(&self) -> f32 {
+        self.wind.current[1]
+    }
+
+    pub fn wind_z(&self) -> f32 {
+        self.wind.current[2]
+    }
+
+    pub fn set_wind_response(&mut self, response: f32) {
+        self.wind.response = clamp_finite(
+            response,
+            MIN_WIND_RESPONSE,
+            MAX_WIND_RESPONSE,
+            DEFAULT_WIND_RESPONSE,
+        );
+    }
+
+    pub fn wind_response(&self) -> f32 {
+        self.wind.response
+    }
+}
@@ -0,0 +1,110 @@
+use crate::recording::FieldCodec;
+use crate::{clamp_finite, Sim, WORLD_SIZE};
+use wasm_bindgen::prelude::*;
+
+const MAX_WRAP_GHOST_MARGIN: f32 = 0.25;
+
+// Output-stage duplicates for wrap-around rendering. A boid within `margin`
+// of a wrapping edge is copied one world over, so a sprite crossing the edge
+// shows on both sides instead of popping; corner boids get three copies.
+// Ghosts live in their own buffers, with `source` giving the slot each one
+// copies for every other per-boid buffer. Bounce, folded and split axes never
+// wrap, and perspective projection turns ghosts off. Off while the margin is 0.
+#[derive(Default)]
+pub struct WrapGhosts {
+    margin: f32,
+    pub xy: Vec<f32>,
+    pub heading_xy: Vec<f32>,
+    pub source: Vec<u32>,
+}
+
+impl WrapGhosts {
+    pub fn enabled(&self) -> bool {
+        self.margin > 0.0
+    }
+
+    // Shift toward the far side for a position within the margin of an edge.
+    fn shift(&self, position: f32) -> f32 {
+        if position < self.margin {
+            WORLD_SIZE
+        } else if position > WORLD_SIZE - self.margin {
+            -WORLD_SIZE
+        } else {
+            0.0
+        }
+    }
+
+    pub fn visit_settings(&mut self, codec: &mut dyn FieldCodec) {
+        codec.f32(&mut self.margin);
+    }
+}
+
+impl Sim {
+    fn wraps_for_ghosts(&self, i: usize) -> (bool, bool) {
+        let bounce_x =
+            self.split_half(i).is_some() || self.mirror_axis_bounds(self.mirror.x, self.bounce_x).0;
+        let bounce_y = self.mirror_axis_bounds(self.mirror.y, self.bounce_y).0;
+        (!bounce_x, !bounce_y)
+    }
+
+    pub(super) fn sync_wrap_ghosts(&mut self) {
+        let mut ghosts = std::mem::take(&mut self.wrap_ghosts);
+        ghosts.xy.clear();
+        ghosts.heading_xy.clear();
+        ghosts.source.clear();
+        if ghosts.enabled() && !self.perspective_enabled() {
+            for i in 0..self.active_count {
+                let (wrap_x, wrap_y) = self.wraps_for_ghosts(i);
+                let (x, y) = (self.render_xy[2 * i], self.render_xy[2 * i + 1]);
+                let sx = if wrap_x { ghosts.shift(x) } else { 0.0 };
+                let sy = if wrap_y { ghosts.shift(y) } else { 0.0 };
+                let corner = sx != 0.0 && sy != 0.0;
+                for (k, (dx, dy)) in [(sx, 0.0), (0.0, sy), (sx, sy)].into_iter().enumerate() {
+                    // The diagonal copy only differs from the others at corners.
+                    if (dx == 0.0 && dy == 0.0) || (k == 2 && !corner) {
+                        continue;
+                    }
+                    ghosts.xy.extend([x + dx, y + dy]);
+                    ghosts
+                        .heading_xy
+                        .extend_from_slice(&self.render_heading_xy[2 * i..2 * i + 2]);
+                    ghosts.source.push(i as u32);
+                }
+            }
+        }
+        self.wrap_ghosts = ghosts;
+    }
+}
+
+#[wasm_bindgen]
+impl Sim {
+    // Margin in world units, clamped to [0, 0.25]; usually the drawn boid
+    // radius. 0 turns ghosts off.
+    pub fn set_wrap_ghost_margin(&mut self, margin: f32) {
+        self.wrap_ghosts.margin = clamp_finite(margin, 0.0, MAX_WRAP_GHOST_MARGIN, 0.0);
+        self.sync_render_buffers();
+    }
+
+    pub fn wrap_ghost_margin(&self) -> f32 {
+        self.wrap_ghosts.margin
+    }
+
+    // Extra boids to draw after the active ones this frame.
+    pub fn wrap_ghost_count(&self) -> usize {
+        self.wrap_ghosts.source.len()
+    }
+
+    pub fn wrap_ghost_xy_ptr(&self) -> *const f32 {
+        self.wrap_ghosts.xy.as_ptr()
+    }
+
+    pub fn wrap_ghost_heading_xy_ptr(&self) -> *const f32 {
+        self.wrap_ghosts.heading_xy.as_ptr()
+    }
+
+    // Slot of the boid each ghost copies, for colors, scale and the other
+    // per-boid render buffers.
+    pub fn wrap_ghost_source_ptr(&self) -> *const u32 {
+        self.wrap_ghosts.source.as_ptr()
+    }
+}
@@ -0,0 +1,89 @@
+use crate::recording::FieldCodec;
+use crate::{clamp_finite, Sim, WORLD_SIZE};
+use wasm_bindgen::prelude::*;
+
+const MIN_Z_EXTENT_DEPTH: f32 = 0.01;
+
+// Depth range boids live in when z mode is on. It defaults to the full unit
+// cube; a thinner slab keeps parallax subtle without touching z forces. The
+// z axis wraps or bounces within it like x and y do within the world.
+#[derive(Clone, Copy)]
+pub struct ZExtent {
+    pub min: f32,
+    pub max: f32,
+}
+
+impl Default for ZExtent {
+    fn default() -> Self {
+        Self {
+            min: 0.0,
+            max: WORLD_SIZE,
+        }
+    }
+}
+
+impl ZExtent {
+    pub fn depth(self) -> f32 {
+        self.max - self.min
+    }
+
+    // Shortest z offset, going around the slab when it wraps.
+    pub fn delta(self, delta: f32, wrap: bool) -> f32 {
+        let depth = self.depth();
+        if !wrap {
+            delta
+        } else if delta > depth * 0.5 {
+            delta - depth
+        } else if delta < -depth * 0.5 {
+            delta + depth
+        } else {
+            delta
+        }
+    }
+
+    pub fn project(self, z: f32, bounce: bool) -> f32 {
+        if (self.min..=self.max).contains(&z) {
+            z
+        } else if bounce {
+            z.clamp(self.min, self.max)
+        } else {
+            self.min + (z - self.min).rem_euclid(self.depth())
+        }
+    }
+
+    // Fraction of the way through the slab, in [0, 1].
+    pub fn fraction(self, z: f32) -> f32 {
+        ((z - self.min) / self.depth()).clamp(0.0, 1.0)
+    }
+
+    pub fn visit_settings(&mut self, codec: &mut dyn FieldCodec) {
+        codec.f32(&mut self.min);
+        codec.f32(&mut self.max);
+    }
+}
+
+#[wasm_bindgen]
+impl Sim {
+    // Depth range in world units within [0, 1], at least 0.01 deep. Boids
+    // already outside it are wrapped or clamped into it.
+    pub fn set_z_extent(&mut self, z_min: f32, z_max: f32) {
+        let min = clamp_finite(z_min, 0.0, WORLD_SIZE - MIN_Z_EXTENT_DEPTH, 0.0);
+        let max = clamp_finite(z_max, min + MIN_Z_EXTENT_DEPTH, WORLD_SIZE, WORLD_SIZE);
+        self.z_extent = ZExtent { min, max };
+        if !self.z_mode_enabled {
+            return;
+        }
+        for i in 0..self.count {
+            self.pos_z[i] = self.z_extent.project(self.pos_z[i], self.bounce_z);
+            self.render_z[i] = self.pos_z[i];
+        }
+    }
+
+    pub fn z_min(&self) -> f32 {
+        self.z_extent.min
+    }
+
+    pub fn z_max(&self) -> f32 {
+        self.z_extent.max
+    }
+}
@@ -251,7 +251,7 @@ async function start(): Promise<void> {
 
   const applyActiveModelSettings = (): void => {
     sim.setModelKind(modelKind);
-    if (modelKind === "classic") {
+    if (usesClassicControls(modelKind)) {
       applyClassicSettings();
       return;
     }
@@ -350,7 +350,7 @@ async function start(): Promise<void> {
     controls.f2AirDensityValueLabel.textContent = `rho=${flock2Flight.airDensity.toFixed(3)} (air density)`;
     controls.f2AirDensitySlider.value = flock2Flight.airDensity.toFixed(3);
     controls.menuButton.textContent = menuOpen ? "Menu: On" : "Menu: Off";
-    const isClassic = modelKind === "classic";
+    const isClassic = usesClassicControls(modelKind);
     const isFlightModel =
       modelKind === "flock2-social-flight" ||
       modelKind === "f2-lite-social-flight";
@@ -718,6 +718,7 @@ async function start(): Promise<void> {
       1,
       activeBoids,
       sim.getHeading(),
+      sim.getRenderScale(),
     );
     const renderMs = performance.now() - renderStartMs;
     const frameMs = performance.now() - frameStartMs;
@@ -927,6 +928,7 @@ function createDebugControls(
     { value: "flock2-social-flight", label: "F2 Social+Flight" },
     { value: "f2-lite-social", label: "F2 Lite Social" },
     { value: "f2-lite-social-flight", label: "F2 Lite Social+Flight" },
+    { value: "fish-school", label: "Fish School" },
   ];
   modelOptions.forEach((option) => {
     const node = document.createElement("option");
@@ -1766,9 +1768,16 @@ function modelKindLabel(modelKind: SimModelKind): string {
       return "F2 Lite Social";
     case "f2-lite-social-flight":
       return "F2 Lite Social+Flight";
+    case "fish-school":
+      return "Fish School";
   }
 }
 
+// Fish schooling runs on the classic pipeline and shares its sliders.
+function usesClassicControls(modelKind: SimModelKind): boolean {
+  return modelKind === "classic" || modelKind === "fish-school";
+}
+
 function modelLegendText(
   modelKind: SimModelKind,
   legendTokens: ControlLegendTokens,
@@ -1856,7 +1865,7 @@ function modelLegendText(
     formatLegendItems(legendTokens.flight, flightDescriptions),
   );
 
-  if (modelKind === "classic") {
+  if (usesClassicControls(modelKind)) {
     return [...classicLines, ...baseLines].join("\n");
   }
   if (modelKind === "flock2-social" || modelKind === "f2-lite-social") {
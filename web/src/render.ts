@@ -102,6 +102,7 @@ export class FlockView {
     sampleStride = 1,
     maxCount?: number,
     heading?: Float32Array,
+    scale?: Float32Array,
   ): void {
     const availableCount = positions.length >>> 1;
     const totalCount =
@@ -122,6 +123,7 @@ export class FlockView {
     const width = this.app.screen.width * this.renderScale;
     const height = this.app.screen.height * this.renderScale;
     const hasDepth = Boolean(depth) && (depth?.length ?? 0) >= totalCount;
+    const hasScale = Boolean(scale) && (scale?.length ?? 0) >= totalCount;
     const hasHeading =
       this.theme.particleShape !== "dot" &&
       Boolean(heading) &&
@@ -135,7 +137,8 @@ export class FlockView {
       const y = positions[p + 1] * height;
       const v = renderIndex * 8;
       const z = hasDepth ? clamp01(depth![sourceIndex]) : DEFAULT_Z_LAYER;
-      const halfSize = baseHalfSize * (0.55 + 0.9 * z);
+      const halfSize =
+        baseHalfSize * (hasScale ? scale![sourceIndex] : 0.55 + 0.9 * z);
 
       if (hasHeading) {
         const h = sourceIndex * 2;
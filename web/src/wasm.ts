@@ -1,9 +1,21 @@
 // Generated by wasm-pack during `npm run wasm:build:*`.
 import initWasm, {
+  ConfigAdjustment,
+  CustomForceCallback,
+  Flock2Config,
+  ObjectiveCallback,
   Sim,
+  SimConfig,
+  StepHookCallback,
+  evolve_classic_config,
+  run_golden,
   wasm_loaded_message,
 } from "../../sim-wasm/pkg/sim_wasm.js";
 
+// Standalone spatial index for front-end entities (obstacles, pickups). It is
+// usable once `initWasmModule` has resolved.
+export { NeighborGrid } from "../../sim-wasm/pkg/sim_wasm.js";
+
 export interface SimBoidsConfig {
   sepWeight: number;
   alignWeight: number;
@@ -22,13 +34,39 @@ export interface SimBoidsConfig {
   jitterStrength?: number;
 }
 
-export type SimMathMode = "accurate" | "fast";
+export type SimMathMode = "accurate" | "fast" | "lut";
+export type SimMathPath = "steering" | "constraints" | "flight";
+export type SimNeighborBackend = "grid" | "kd-tree";
+export type SimSteeringDebugMode = "off" | "inspected" | "all";
+export type SimDragModel = "exponential" | "linear" | "quadratic" | "combined";
+export type SimSeparationKernel =
+  | "inverse-linear"
+  | "inverse-square"
+  | "inverse-cube"
+  | "smooth";
+export type SimBehaviorState = "wander" | "flock" | "flee" | "seek" | "perch";
+export type SimRole = "follower" | "scout" | "custom-a" | "custom-b";
+export type SimColorSource = "off" | "speed" | "density" | "cluster" | "depth";
+export type SimFalloffProfile =
+  | "constant"
+  | "linear"
+  | "inverse"
+  | "inverse-square"
+  | "smoothstep";
 export type SimModelKind =
   | "classic"
   | "flock2-social"
   | "flock2-social-flight"
   | "f2-lite-social"
-  | "f2-lite-social-flight";
+  | "f2-lite-social-flight"
+  | "fish-school";
+
+// Channels `setBoids` writes, each as x, y, z per boid in this order.
+export type SimBoidChannel = "position" | "velocity" | "heading";
+export type SimStepStage =
+  | "after-forces"
+  | "before-integration"
+  | "after-constraints";
 
 export interface Flock2SocialConfig {
   avoidWeight: number;
@@ -55,6 +93,135 @@ export interface Flock2FlightConfig {
   airDensity: number;
 }
 
+// Every classic config field, as `SimConfig.toObject` returns it. Math modes
+// run in `SimMathPath` order.
+export interface SimConfigObject {
+  sepWeight: number;
+  alignWeight: number;
+  cohWeight: number;
+  speedMatchWeight: number;
+  neighborRadius: number;
+  separationRadius: number;
+  minSpeed: number;
+  maxSpeed: number;
+  maxForce: number;
+  mathModes: [SimMathMode, SimMathMode, SimMathMode];
+  maxNeighborsSampled: number;
+  softMinDistance: number;
+  hardMinDistance: number;
+  jitterStrength: number;
+  drag: number;
+  dragModel: SimDragModel;
+  quadraticDrag: number;
+  axisDragEnabled: boolean;
+  zDrag: number;
+  zQuadraticDrag: number;
+  globalAccelX: number;
+  globalAccelY: number;
+  globalAccelZ: number;
+  shapeAttractorWeight: number;
+  farFieldOpening: number;
+  alignToHeadings: boolean;
+  distanceWeightedCohesion: boolean;
+  separationKernel: SimSeparationKernel;
+}
+
+// Every flock2 field, social and flight, as `Flock2Config.toObject` returns
+// it.
+export interface Flock2ConfigObject
+  extends Flock2SocialConfig, Flock2FlightConfig {
+  liteNeighborCap: number;
+  avoidFieldOfViewDeg: number;
+  maxBankDeg: number;
+  decisionNoiseDeg: number;
+  decisionNoiseSeed: number;
+}
+
+export interface FishSchoolConfig {
+  lateralAlignment: number;
+  preferredDepth: number;
+  depthWeight: number;
+  burstPeriod: number;
+  burstFraction: number;
+  burstThrust: number;
+  coastDrag: number;
+}
+
+// Ring buffers: samples run from `start` for `filled` entries, wrapping at the
+// array length.
+export interface SimMetricHistory {
+  polarization: Float32Array;
+  meanSpeed: Float32Array;
+  clusterCount: Float32Array;
+  start: number;
+  filled: number;
+}
+
+// Per-cluster views from the latest detection, indexed by cluster id. Vector
+// arrays hold xyz triples.
+export interface SimClusterStats {
+  count: number;
+  sizes: Uint32Array;
+  centroids: Float32Array;
+  meanVelocities: Float32Array;
+  boundingRadii: Float32Array;
+  milling: Float32Array;
+}
+
+// A split names the old cluster and the new clusters it became; a merge
+// names the new cluster and the old clusters it absorbed. Old ids refer to
+// the detection before the latest one.
+export interface SimClusterEvent {
+  kind: "split" | "merge";
+  cluster: number;
+  size: number;
+  parts: { cluster: number; size: number }[];
+}
+
+// One step's counters; spawns and despawns are those made before the step.
+export interface SimStepReport {
+  simulatedDt: number;
+  neighborsVisited: number;
+  boundaryHits: { x: number; y: number; z: number };
+  boundaryCorrection: number;
+  contactCount: number;
+  contactPenetrationSum: number;
+  remainingOverlaps: number;
+  maxRemainingPenetration: number;
+  spawned: number;
+  despawned: number;
+}
+
+// The right half's classic weights, radii and speed limits in split mode.
+export type SimSplitConfig = Pick<
+  SimBoidsConfig,
+  | "sepWeight"
+  | "alignWeight"
+  | "cohWeight"
+  | "neighborRadius"
+  | "separationRadius"
+  | "minSpeed"
+  | "maxSpeed"
+  | "maxForce"
+>;
+
+export type SimSplitHalf = "left" | "right";
+
+// Multipliers a config region applies to the classic config.
+export interface SimConfigScales {
+  radius: number;
+  separation: number;
+  alignment: number;
+  cohesion: number;
+  speed: number;
+}
+
+export interface SimSplitMetrics {
+  count: number;
+  polarization: number;
+  meanSpeed: number;
+}
+
 export interface ClassicModelConfig {
   mathMode: SimMathMode;
   maxNeighborsSampled: number;
@@ -65,8 +232,83 @@ export interface ClassicModelConfig {
   jitterStrength: number;
 }
 
+export type ConfigAdjustmentReason = "non-finite" | "below-min" | "above-max";
+
+export interface SimConfigAdjustment {
+  field: string;
+  supplied: number;
+  applied: number;
+  reason: ConfigAdjustmentReason;
+}
+
 const MAX_BIRD_CAPACITY = 10_000;
 
+function toConfigAdjustments(
+  adjustments: ConfigAdjustment[],
+): SimConfigAdjustment[] {
+  return adjustments.map((adjustment) => {
+    const reasonId = adjustment.reason();
+    const result: SimConfigAdjustment = {
+      field: adjustment.field(),
+      supplied: adjustment.supplied(),
+      applied: adjustment.applied(),
+      reason:
+        reasonId === 0
+          ? "non-finite"
+          : reasonId === 1
+            ? "below-min"
+            : "above-max",
+    };
+    adjustment.free();
+    return result;
+  });
+}
+
+function mathModeId(mode: SimMathMode): number {
+  return mode === "fast" ? 1 : mode === "lut" ? 2 : 0;
+}
+
+function mathPathId(path: SimMathPath): number {
+  return path === "constraints" ? 1 : path === "flight" ? 2 : 0;
+}
+
+function boidWriteLayout(channels: readonly SimBoidChannel[]): number {
+  return (
+    (channels.includes("position") ? 1 : 0) |
+    (channels.includes("velocity") ? 2 : 0) |
+    (channels.includes("heading") ? 4 : 0)
+  );
+}
+
+function stepStageId(stage: SimStepStage): number {
+  return stage === "after-forces" ? 0 : stage === "before-integration" ? 1 : 2;
+}
+
+const BEHAVIOR_STATES: SimBehaviorState[] = [
+  "wander",
+  "flock",
+  "flee",
+  "seek",
+  "perch",
+];
+
+const ROLES: SimRole[] = ["follower", "scout", "custom-a", "custom-b"];
+
+const MATH_MODES: SimMathMode[] = ["accurate", "fast", "lut"];
+
+const FALLOFF_PROFILES: SimFalloffProfile[] = [
+  "constant",
+  "linear",
+  "inverse",
+  "inverse-square",
+  "smoothstep",
+];
+
+function configScales(values: Float32Array): SimConfigScales {
+  const [radius, separation, alignment, cohesion, speed] = values;
+  return { radius, separation, alignment, cohesion, speed };
+}
+
 function randomSeed32(): number {
   const bytes = new Uint32Array(1);
   crypto.getRandomValues(bytes);
@@ -79,13 +321,28 @@ export class WasmSimClient {
   private positionsView: Float32Array;
   private depthView: Float32Array;
   private headingView: Float32Array;
+  private crowdingView: Float32Array;
+  private scaleView: Float32Array;
+  private boundaryHitFlagsView: Uint8Array;
+  private rolesView: Uint8Array;
+  private contactPairsView: Uint32Array;
   private memoryBuffer: ArrayBuffer;
-  private readonly positionsPointer: number;
-  private readonly positionsLength: number;
-  private readonly depthPointer: number;
-  private readonly depthLength: number;
-  private readonly headingPointer: number;
-  private readonly headingLength: number;
+  private positionsPointer: number;
+  private positionsLength: number;
+  private depthPointer: number;
+  private depthLength: number;
+  private headingPointer: number;
+  private headingLength: number;
+  private crowdingPointer: number;
+  private crowdingLength: number;
+  private scalePointer: number;
+  private scaleLength: number;
+  private boundaryHitFlagsPointer: number;
+  private boundaryHitFlagsLength: number;
+  private rolesPointer: number;
+  private rolesLength: number;
+  private readonly contactPairsPointer: number;
+  private readonly contactPairsLength: number;
 
   constructor(
     count: number,
@@ -102,6 +359,16 @@ export class WasmSimClient {
     this.depthLength = this.sim.render_z_len();
     this.headingPointer = this.sim.render_heading_xy_ptr();
     this.headingLength = this.sim.render_heading_xy_len();
+    this.crowdingPointer = this.sim.render_crowding_ptr();
+    this.crowdingLength = this.sim.render_crowding_len();
+    this.scalePointer = this.sim.render_scale_ptr();
+    this.scaleLength = this.sim.render_scale_len();
+    this.boundaryHitFlagsPointer = this.sim.boundary_hit_flags_ptr();
+    this.boundaryHitFlagsLength = this.sim.boundary_hit_flags_len();
+    this.rolesPointer = this.sim.roles_ptr();
+    this.rolesLength = this.sim.roles_len();
+    this.contactPairsPointer = this.sim.contact_pairs_ptr();
+    this.contactPairsLength = this.sim.contact_pairs_len();
     this.memoryBuffer = wasmMemory.buffer;
     this.positionsView = new Float32Array(
       this.memoryBuffer,
@@ -118,6 +385,31 @@ export class WasmSimClient {
       this.headingPointer,
       this.headingLength,
     );
+    this.crowdingView = new Float32Array(
+      this.memoryBuffer,
+      this.crowdingPointer,
+      this.crowdingLength,
+    );
+    this.scaleView = new Float32Array(
+      this.memoryBuffer,
+      this.scalePointer,
+      this.scaleLength,
+    );
+    this.boundaryHitFlagsView = new Uint8Array(
+      this.memoryBuffer,
+      this.boundaryHitFlagsPointer,
+      this.boundaryHitFlagsLength,
+    );
+    this.rolesView = new Uint8Array(
+      this.memoryBuffer,
+      this.rolesPointer,
+      this.rolesLength,
+    );
+    this.contactPairsView = new Uint32Array(
+      this.memoryBuffer,
+      this.contactPairsPointer,
+      this.contactPairsLength,
+    );
   }
 
   step(dt: number): void {
@@ -125,17 +417,112 @@ export class WasmSimClient {
     this.refreshViewIfMemoryChanged();
   }
 
+  // Spreads one step's steering pass over several calls of at most
+  // `budgetHint` boids each. Returns true once the step has been integrated.
+  stepPartial(dt: number, budgetHint: number): boolean {
+    const done = this.sim.step_partial(dt, Math.max(1, Math.floor(budgetHint)));
+    this.refreshViewIfMemoryChanged();
+    return done;
+  }
+
+  // True while a `stepPartial` pass is split across calls.
+  isPartialStepPending(): boolean {
+    return this.sim.partial_step_pending();
+  }
+
+  // While paused, `step` only refreshes render buffers so edits stay visible.
+  pause(): void {
+    this.sim.pause();
+  }
+
+  resume(): void {
+    this.sim.resume();
+  }
+
+  isPaused(): boolean {
+    return this.sim.is_paused();
+  }
+
+  debugStep(): void {
+    this.sim.debug_step();
+    this.refreshViewIfMemoryChanged();
+  }
+
+  // Pre-rolls `steps` frames before display, skipping per-frame render sync.
+  // Stops any recording and clears snapshots.
+  warmup(steps: number, dt: number): void {
+    this.sim.warmup(Math.max(0, Math.floor(steps)), dt);
+    this.refreshViewIfMemoryChanged();
+  }
+
+  // Logs settings and state changes between steps, not the calls that made
+  // them. Step hooks and custom forces are not captured; install them again
+  // before replaying.
+  startRecording(): void {
+    this.sim.start_recording();
+  }
+
+  stopRecording(): void {
+    this.sim.stop_recording();
+  }
+
+  isRecording(): boolean {
+    return this.sim.is_recording();
+  }
+
+  // Bytes logged so far.
+  getRecordingLength(): number {
+    return this.sim.recording_len();
+  }
+
+  exportRecording(): Uint8Array {
+    return this.sim.export_recording();
+  }
+
+  replay(recording: Uint8Array): boolean {
+    const replayed = this.sim.replay(recording);
+    this.refreshViewIfMemoryChanged();
+    return replayed;
+  }
+
+  // Capacity is in steps; 0 disables capture and frees the history.
+  setSnapshotCapacity(capacity: number): void {
+    this.sim.set_snapshot_capacity(capacity);
+  }
+
+  getSnapshotCapacity(): number {
+    return this.sim.snapshot_capacity();
+  }
+
+  getSnapshotCount(): number {
+    return this.sim.snapshot_count();
+  }
+
+  getSnapshotHistoryBytes(): number {
+    return this.sim.snapshot_history_bytes();
+  }
+
+  restoreSnapshot(stepsBack: number): boolean {
+    const restored = this.sim.restore_snapshot(stepsBack);
+    this.refreshViewIfMemoryChanged();
+    return restored;
+  }
+
+  clearSnapshots(): void {
+    this.sim.clear_snapshots();
+  }
+
+  // Values above 1 fast-forward through substeps; below 1 slows playback.
+  setTimeScale(scale: number): void {
+    this.sim.set_time_scale(scale);
+  }
+
+  getTimeScale(): number {
+    return this.sim.time_scale();
+  }
+
   setConfig(config: SimBoidsConfig): void {
-    this.sim.set_config(
-      config.sepWeight,
-      config.alignWeight,
-      config.cohWeight,
-      config.neighborRadius,
-      config.separationRadius,
-      config.minSpeed,
-      config.maxSpeed,
-      config.maxForce,
-    );
+    this.setConfigChecked(config);
 
     if (config.mathMode) {
       this.setMathMode(config.mathMode);
@@ -160,6 +547,89 @@ export class WasmSimClient {
     }
   }
 
+  setConfigChecked(config: SimBoidsConfig): SimConfigAdjustment[] {
+    return this.applyConfig({
+      sepWeight: config.sepWeight,
+      alignWeight: config.alignWeight,
+      cohWeight: config.cohWeight,
+      neighborRadius: config.neighborRadius,
+      separationRadius: config.separationRadius,
+      minSpeed: config.minSpeed,
+      maxSpeed: config.maxSpeed,
+      maxForce: config.maxForce,
+    });
+  }
+
+  getConfig(): SimConfigObject {
+    const config = this.sim.config();
+    const values = config.toObject() as SimConfigObject;
+    config.free();
+    return values;
+  }
+
+  // Fields left out keep their current values.
+  applyConfig(config: Partial<SimConfigObject>): SimConfigAdjustment[] {
+    const typed = SimConfig.fromObject({ ...this.getConfig(), ...config });
+    const adjustments = this.sim.apply_config(typed);
+    typed.free();
+    return toConfigAdjustments(adjustments);
+  }
+
+  // Hooks receive copies of the active SoA slices; writes are copied back into
+  // the simulation when the callback returns. NaN or infinite writes keep the
+  // previous value, and xy positions are clamped into the world.
+  setStepHook(stage: SimStepStage, callback: StepHookCallback): void {
+    this.sim.set_step_hook_callback(stepStageId(stage), callback);
+  }
+
+  clearStepHook(stage: SimStepStage): void {
+    this.sim.clear_step_hook(stepStageId(stage));
+  }
+
+  clearStepHooks(): void {
+    this.sim.clear_step_hooks();
+  }
+
+  // Called once per classic step; values written to outX/outY/outZ are added to
+  // the steering sum before the max force clamp.
+  setCustomForceCallback(callback: CustomForceCallback): void {
+    this.sim.set_custom_force_callback(callback);
+  }
+
+  clearCustomForceCallback(): void {
+    this.sim.clear_custom_force_callback();
+  }
+
+  hasCustomForce(): boolean {
+    return this.sim.has_custom_force();
+  }
+
+  // Evaluated every `interval` steps; returned numbers are summed into the
+  // score. Installing or clearing an objective resets the score.
+  setObjective(callback: ObjectiveCallback, interval = 1): void {
+    this.sim.set_objective_callback(callback, interval);
+  }
+
+  clearObjective(): void {
+    this.sim.clear_objective();
+  }
+
+  hasObjective(): boolean {
+    return this.sim.has_objective();
+  }
+
+  resetObjectiveScore(): void {
+    this.sim.reset_objective_score();
+  }
+
+  getObjectiveScore(): number {
+    return this.sim.objective_score();
+  }
+
+  getObjectiveEvaluations(): number {
+    return this.sim.objective_evaluations();
+  }
+
   setBounds(width: number, height: number): void {
     this.sim.set_bounds(width, height);
   }
@@ -188,10 +658,53 @@ export class WasmSimClient {
     return this.sim.bounce_z();
   }
 
+  // Restitution 0 with friction 1 parks boids on the wall until steered off.
+  setWallResponse(restitution: number, friction: number): void {
+    this.sim.set_wall_response(restitution, friction);
+  }
+
+  getWallResponse(): { restitution: number; friction: number } {
+    return {
+      restitution: this.sim.wall_restitution(),
+      friction: this.sim.wall_friction(),
+    };
+  }
+
   setMathMode(mode: SimMathMode): void {
-    this.sim.set_math_mode(mode === "fast" ? 1 : 0);
+    this.sim.set_math_mode(mathModeId(mode));
+  }
+
+  // Steering forces, constraints (speed limits and hard-min separation) and
+  // propulsion can each pick their own mode; `setMathMode` sets all three.
+  setPathMathMode(path: SimMathPath, mode: SimMathMode): void {
+    this.sim.set_path_math_mode(mathPathId(path), mathModeId(mode));
+  }
+
+  getPathMathMode(path: SimMathPath): SimMathMode {
+    return MATH_MODES[this.sim.path_math_mode(mathPathId(path))] ?? "accurate";
+  }
+
+  // Also runs the accurate path and tracks how far each path strays from it.
+  setMathVerification(enabled: boolean): void {
+    this.sim.set_math_verification(enabled);
   }
 
+  isMathVerificationEnabled(): boolean {
+    return this.sim.math_verification();
+  }
+
+  getMathDivergence(): Record<SimMathPath, number> {
+    const [steering, constraints, flight] = this.sim.math_divergence();
+    return { steering, constraints, flight };
+  }
+
+  resetMathDivergence(): void {
+    this.sim.reset_math_divergence();
+  }
+
+  // Velocities are converted and reseeded only when switching between the
+  // classic units (classic, fish) and the flock2 units (every flock2 kind);
+  // switches within a family leave them untouched.
   setModelKind(kind: SimModelKind): void {
     const kindId =
       kind === "flock2-social"
@@ -202,7 +715,9 @@ export class WasmSimClient {
             ? 3
             : kind === "f2-lite-social-flight"
               ? 4
-              : 0;
+              : kind === "fish-school"
+                ? 5
+                : 0;
     this.sim.set_model_kind(kindId);
   }
 
@@ -217,6 +732,8 @@ export class WasmSimClient {
         return "f2-lite-social";
       case 4:
         return "f2-lite-social-flight";
+      case 5:
+        return "fish-school";
       default:
         return "classic";
     }
@@ -224,7 +741,7 @@ export class WasmSimClient {
 
   setClassicConfig(config: ClassicModelConfig): void {
     this.sim.set_classic_config(
-      config.mathMode === "fast" ? 1 : 0,
+      mathModeId(config.mathMode),
       Math.max(0, Math.floor(config.maxNeighborsSampled)),
       Math.max(0, config.maxForce),
       Math.max(0, config.drag),
@@ -235,175 +752,2284 @@ export class WasmSimClient {
   }
 
   setFlock2SocialConfig(config: Flock2SocialConfig): void {
-    this.sim.set_flock2_social_config(
-      config.avoidWeight,
-      config.alignWeight,
-      config.cohesionWeight,
-      config.boundaryWeight,
-      config.boundaryCount,
-      config.neighborRadius,
-      Math.max(1, Math.floor(config.topologicalNeighbors)),
-      config.fieldOfViewDeg,
-    );
+    this.setFlock2SocialConfigChecked({
+      ...config,
+      topologicalNeighbors: Math.max(1, config.topologicalNeighbors),
+    });
   }
 
   setFlock2FlightConfig(config: Flock2FlightConfig): void {
-    this.sim.set_flock2_flight_config(
-      config.reactionTimeMs,
-      config.dynamicStability,
-      config.mass,
-      config.wingArea,
-      config.liftFactor,
-      config.dragFactor,
-      config.thrust,
-      config.minSpeed,
-      config.maxSpeed,
-      config.gravity,
-      config.airDensity,
-    );
+    this.setFlock2FlightConfigChecked(config);
   }
 
-  getMathMode(): SimMathMode {
-    return this.sim.math_mode() === 1 ? "fast" : "accurate";
+  setFlock2SocialConfigChecked(
+    config: Flock2SocialConfig,
+  ): SimConfigAdjustment[] {
+    return this.applyFlock2Config({
+      avoidWeight: config.avoidWeight,
+      alignWeight: config.alignWeight,
+      cohesionWeight: config.cohesionWeight,
+      boundaryWeight: config.boundaryWeight,
+      boundaryCount: config.boundaryCount,
+      neighborRadius: config.neighborRadius,
+      topologicalNeighbors: Math.max(
+        0,
+        Math.floor(config.topologicalNeighbors),
+      ),
+      fieldOfViewDeg: config.fieldOfViewDeg,
+    });
   }
 
-  setMaxNeighborsSampled(maxNeighbors: number): void {
-    this.sim.set_max_neighbors_sampled(Math.max(0, Math.floor(maxNeighbors)));
+  setFlock2FlightConfigChecked(
+    config: Flock2FlightConfig,
+  ): SimConfigAdjustment[] {
+    return this.applyFlock2Config({
+      reactionTimeMs: config.reactionTimeMs,
+      dynamicStability: config.dynamicStability,
+      mass: config.mass,
+      wingArea: config.wingArea,
+      liftFactor: config.liftFactor,
+      dragFactor: config.dragFactor,
+      thrust: config.thrust,
+      minSpeed: config.minSpeed,
+      maxSpeed: config.maxSpeed,
+      gravity: config.gravity,
+      airDensity: config.airDensity,
+    });
   }
 
-  getMaxNeighborsSampled(): number {
-    return this.sim.max_neighbors_sampled();
+  getFlock2Config(): Flock2ConfigObject {
+    const config = this.sim.flock2_config();
+    const values = config.toObject() as Flock2ConfigObject;
+    config.free();
+    return values;
   }
 
-  getNeighborsVisitedLastStep(): number {
-    return this.sim.neighbors_visited_last_step();
+  // Social and flight fields together; fields left out keep their values.
+  applyFlock2Config(
+    config: Partial<Flock2ConfigObject>,
+  ): SimConfigAdjustment[] {
+    const typed = Flock2Config.fromObject({
+      ...this.getFlock2Config(),
+      ...config,
+    });
+    const adjustments = this.sim.apply_flock2_config(typed);
+    typed.free();
+    return toConfigAdjustments(adjustments);
   }
 
-  setMaxForce(maxForce: number): void {
-    this.sim.set_max_force(Math.max(0, maxForce));
+  // Bank-limited turning for the flock2 models; 90 degrees disables it.
+  setFlock2MaxBankDeg(degrees: number): void {
+    this.sim.set_flock2_max_bank_deg(degrees);
   }
 
-  getMaxForce(): number {
-    return this.sim.max_force();
+  getFlock2MaxBankDeg(): number {
+    return this.sim.flock2_max_bank_deg();
   }
 
-  setDrag(drag: number): void {
-    this.sim.set_drag(Math.max(0, drag));
+  // Field of view for flock2 avoidance; 0 follows the social field of view.
+  setFlock2AvoidFovDeg(degrees: number): void {
+    this.sim.set_flock2_avoid_fov_deg(degrees);
   }
 
-  getDrag(): number {
-    return this.sim.drag();
+  getFlock2AvoidFovDeg(): number {
+    return this.sim.flock2_avoid_fov_deg();
   }
 
-  setShapeAttractorWeight(weight: number): void {
-    this.sim.set_shape_attractor_weight(Math.max(0, weight));
+  // Per-boid flock2 reaction times around the configured one: 0 none,
+  // 1 uniform +/- spread, 2 log-normal with sigma `spread`.
+  setFlock2ReactionDistribution(
+    distribution: number,
+    spread: number,
+    seed: number,
+  ): void {
+    this.sim.set_flock2_reaction_distribution(distribution, spread, seed >>> 0);
   }
 
-  getShapeAttractorWeight(): number {
-    return this.sim.shape_attractor_weight();
+  getFlock2ReactionTimeMsOf(slot: number): number {
+    return this.sim.flock2_reaction_time_ms_of(slot);
   }
 
-  setShapePoints(pointsXyz: Float32Array): void {
-    this.sim.set_shape_points_xyz(pointsXyz);
+  // Seeded random yaw/pitch of up to `amplitudeDeg` on every flock2
+  // steering decision; 0 disables it.
+  setFlock2DecisionNoise(amplitudeDeg: number, seed = 0): void {
+    this.sim.set_flock2_decision_noise(amplitudeDeg, seed >>> 0);
   }
 
-  setMinDistance(minDistance: number): void {
-    this.sim.set_min_distance(Math.max(0, minDistance));
+  getFlock2DecisionNoiseDeg(): number {
+    return this.sim.flock2_decision_noise_deg();
   }
 
-  getMinDistance(): number {
-    return this.sim.min_distance();
+  // Extra neighbor cap for Flock2 Lite on top of the topological count;
+  // 0 removes it.
+  setFlock2LiteNeighborCap(cap: number): void {
+    this.sim.set_flock2_lite_neighbor_cap(Math.max(0, Math.round(cap)));
   }
 
-  setHardMinDistance(minDistance: number): void {
-    this.sim.set_hard_min_distance(Math.max(0, minDistance));
+  getFlock2LiteNeighborCap(): number {
+    return this.sim.flock2_lite_neighbor_cap();
   }
 
-  getHardMinDistance(): number {
-    return this.sim.hard_min_distance();
+  // Fraction closer, in [0, 1], a newcomer must be to displace a remembered
+  // flock2 topological neighbor; 0 disables the memory.
+  setFlock2NeighborHysteresis(margin: number): void {
+    this.sim.set_flock2_neighbor_hysteresis(margin);
   }
 
-  setJitterStrength(jitterStrength: number): void {
-    this.sim.set_jitter_strength(Math.max(0, jitterStrength));
+  getFlock2NeighborHysteresis(): number {
+    return this.sim.flock2_neighbor_hysteresis();
   }
 
-  getJitterStrength(): number {
-    return this.sim.jitter_strength();
+  // Ids the boid in `slot` kept as topological neighbors last step.
+  getFlock2RememberedNeighbors(slot: number): Uint32Array {
+    return this.sim.flock2_remembered_neighbors(slot);
   }
 
-  setZMode(enabled: boolean): void {
-    this.sim.set_z_mode(enabled);
+  // Flockmates seen often pull up to 1 + boost times harder. Bonds build over
+  // `buildS` seconds together and fade over `decayS` apart; boost 0 is off.
+  setFamiliarity(boost: number, buildS = 2, decayS = 30): void {
+    this.sim.set_familiarity(boost, buildS, decayS);
   }
 
-  isZModeEnabled(): boolean {
-    return this.sim.z_mode_enabled();
+  getFamiliarity(): { boost: number; buildS: number; decayS: number } {
+    return {
+      boost: this.sim.familiarity_boost(),
+      buildS: this.sim.familiarity_build_time(),
+      decayS: this.sim.familiarity_decay_time(),
+    };
   }
 
-  setZForceScale(scale: number): void {
-    this.sim.set_z_force_scale(scale);
+  // Bond in [0, 1] of the boid in slot `from` toward the one in slot `to`.
+  getFamiliarityBond(from: number, to: number): number {
+    return this.sim.familiarity_bond(from, to);
   }
 
-  getCount(): number {
-    return this.sim.count();
+  // Spheres as flat x, y, z, radius in world units; without z mode they act
+  // as circles. Both model families steer around them.
+  setObstacles(spheresXyzr: Float32Array): void {
+    this.sim.set_obstacles_xyzr(spheresXyzr);
   }
 
-  setActiveCount(activeCount: number): void {
-    this.sim.set_active_count(Math.max(0, Math.floor(activeCount)));
+  getObstacleCount(): number {
+    return this.sim.obstacle_count();
   }
 
-  getActiveCount(): number {
-    return this.sim.active_count();
+  // `id` is the obstacle's index in the last `setObstacles` list; it keeps
+  // moving with the given velocity until the next update.
+  updateObstacle(
+    id: number,
+    x: number,
+    y: number,
+    vx: number,
+    vy: number,
+  ): boolean {
+    return this.sim.update_obstacle(id, x, y, vx, vy);
   }
 
-  getPositions(): Float32Array {
-    this.refreshViewIfMemoryChanged();
-    return this.positionsView;
+  getObstacles(): Float32Array {
+    return this.sim.obstacles_xyzr();
   }
 
-  getDepth(): Float32Array {
-    this.refreshViewIfMemoryChanged();
-    return this.depthView;
+  setObstacleAvoidance(
+    classicWeight: number,
+    flock2Weight: number,
+    lookahead: number,
+  ): void {
+    this.sim.set_obstacle_avoidance(classicWeight, flock2Weight, lookahead);
   }
 
-  getHeading(): Float32Array {
-    this.refreshViewIfMemoryChanged();
-    return this.headingView;
+  getObstacleLookahead(): number {
+    return this.sim.obstacle_lookahead();
   }
 
-  getPointer(): number {
-    return this.positionsPointer;
+  // Danger zones return an id, or -1 when empty or over the zone limit.
+  addDangerCircle(x: number, y: number, radius: number): number {
+    return this.sim.add_danger_circle(x, y, radius);
   }
 
-  getDepthPointer(): number {
-    return this.depthPointer;
+  addDangerRect(x0: number, y0: number, x1: number, y1: number): number {
+    return this.sim.add_danger_rect(x0, y0, x1, y1);
   }
 
-  getHeadingPointer(): number {
-    return this.headingPointer;
+  removeDangerZone(id: number): boolean {
+    return this.sim.remove_danger_zone(id);
   }
 
-  private refreshViewIfMemoryChanged(): void {
-    if (this.memoryBuffer === this.wasmMemory.buffer) {
-      return;
-    }
+  clearDangerZones(): void {
+    this.sim.clear_danger_zones();
+  }
 
-    this.memoryBuffer = this.wasmMemory.buffer;
-    this.positionsView = new Float32Array(
-      this.memoryBuffer,
-      this.positionsPointer,
-      this.positionsLength,
-    );
-    this.depthView = new Float32Array(
-      this.memoryBuffer,
-      this.depthPointer,
-      this.depthLength,
-    );
-    this.headingView = new Float32Array(
-      this.memoryBuffer,
-      this.headingPointer,
-      this.headingLength,
-    );
+  getDangerZoneCount(): number {
+    return this.sim.danger_zone_count();
   }
+
+  // Boids inside each zone after the latest step, keyed by zone id.
+  getDangerZoneOccupancy(): Map<number, number> {
+    const ids = this.sim.danger_zone_ids();
+    const counts = this.sim.danger_zone_occupancies();
+    return new Map(Array.from(ids, (id, index) => [id, counts[index]]));
+  }
+
+  getDangerZoneOccupancyOf(id: number): number {
+    return this.sim.danger_zone_occupancy(id);
+  }
+
+  setDangerAvoidance(dreadWeight: number, margin: number): void {
+    this.sim.set_danger_avoidance(dreadWeight, margin);
+  }
+
+  getDangerAvoidance(): { dreadWeight: number; margin: number } {
+    return {
+      dreadWeight: this.sim.danger_dread_weight(),
+      margin: this.sim.danger_margin(),
+    };
+  }
+
+  // Centerline as flat x, y points; `width` is the full thickness. Returns
+  // an id, or -1 without points or over the corridor limit.
+  addCorridor(pointsXy: Float32Array, width: number): number {
+    return this.sim.add_corridor(pointsXy, width);
+  }
+
+  removeCorridor(id: number): boolean {
+    return this.sim.remove_corridor(id);
+  }
+
+  clearCorridors(): void {
+    this.sim.clear_corridors();
+  }
+
+  getCorridorCount(): number {
+    return this.sim.corridor_count();
+  }
+
+  setCorridorSteering(weight: number, edgeFraction: number): void {
+    this.sim.set_corridor_steering(weight, edgeFraction);
+  }
+
+  getCorridorSteering(): { weight: number; edgeFraction: number } {
+    return {
+      weight: this.sim.corridor_weight(),
+      edgeFraction: this.sim.corridor_edge_fraction(),
+    };
+  }
+
+  // Config regions start neutral and blend across a band `feather` wide
+  // centred on their edge. They return an id, or -1 when empty or over the
+  // region limit.
+  addConfigCircle(
+    x: number,
+    y: number,
+    radius: number,
+    feather: number,
+  ): number {
+    return this.sim.add_config_circle(x, y, radius, feather);
+  }
+
+  addConfigRect(
+    x0: number,
+    y0: number,
+    x1: number,
+    y1: number,
+    feather: number,
+  ): number {
+    return this.sim.add_config_rect(x0, y0, x1, y1, feather);
+  }
+
+  setConfigRegionScales(id: number, scales: SimConfigScales): boolean {
+    return this.sim.set_config_region_scales(
+      id,
+      scales.radius,
+      scales.separation,
+      scales.alignment,
+      scales.cohesion,
+      scales.speed,
+    );
+  }
+
+  getConfigRegionScales(id: number): SimConfigScales | null {
+    const values = this.sim.config_region_scales_of(id);
+    return values.length === 5 ? configScales(values) : null;
+  }
+
+  // The blended scales acting on one boid.
+  getBoidConfigScales(slot: number): SimConfigScales | null {
+    const values = this.sim.boid_config_scales(slot);
+    return values.length === 5 ? configScales(values) : null;
+  }
+
+  removeConfigRegion(id: number): boolean {
+    return this.sim.remove_config_region(id);
+  }
+
+  clearConfigRegions(): void {
+    this.sim.clear_config_regions();
+  }
+
+  getConfigRegionCount(): number {
+    return this.sim.config_region_count();
+  }
+
+  // Safe zones return an id, or -1 when empty or over the zone limit.
+  addSafeCircle(x: number, y: number, radius: number): number {
+    return this.sim.add_safe_circle(x, y, radius);
+  }
+
+  addSafeRect(x0: number, y0: number, x1: number, y1: number): number {
+    return this.sim.add_safe_rect(x0, y0, x1, y1);
+  }
+
+  removeSafeZone(id: number): boolean {
+    return this.sim.remove_safe_zone(id);
+  }
+
+  clearSafeZones(): void {
+    this.sim.clear_safe_zones();
+  }
+
+  getSafeZoneCount(): number {
+    return this.sim.safe_zone_count();
+  }
+
+  // Ids in insertion order.
+  getSafeZoneIds(): Uint32Array {
+    return this.sim.safe_zone_ids();
+  }
+
+  setSafeZonePanicDecay(panicDecay: number): void {
+    this.sim.set_safe_zone_panic_decay(panicDecay);
+  }
+
+  getSafeZonePanicDecay(): number {
+    return this.sim.safe_zone_panic_decay();
+  }
+
+  isBoidInSafeZone(slot: number): boolean {
+    return this.sim.boid_in_safe_zone(slot);
+  }
+
+  // Classic models only: time-to-collision steering against the k nearest
+  // neighbors; weight 0 is off.
+  setPairAvoidance(
+    weight: number,
+    neighbors: number,
+    horizonSeconds: number,
+    contact: number,
+  ): void {
+    this.sim.set_pair_avoidance(weight, neighbors, horizonSeconds, contact);
+  }
+
+  getPairAvoidance(): {
+    weight: number;
+    neighbors: number;
+    horizonSeconds: number;
+    contact: number;
+  } {
+    return {
+      weight: this.sim.pair_avoidance_weight(),
+      neighbors: this.sim.pair_avoidance_neighbors(),
+      horizonSeconds: this.sim.pair_avoidance_horizon_s(),
+      contact: this.sim.pair_avoidance_contact(),
+    };
+  }
+
+  // Predator positions as flat x, y, z in world units, set each frame.
+  setPredators(positionsXyz: Float32Array): void {
+    this.sim.set_predators_xyz(positionsXyz);
+  }
+
+  // Positions after any were moved out of safe zones.
+  getPredators(): Float32Array {
+    return this.sim.predators_xyz();
+  }
+
+  getPredatorCount(): number {
+    return this.sim.predator_count();
+  }
+
+  // Flat vx, vy, vz per predator, in the order of `setPredators`.
+  setPredatorVelocities(velocitiesXyz: Float32Array): void {
+    this.sim.set_predator_velocities_xyz(velocitiesXyz);
+  }
+
+  // Sidesteps collision courses with obstacles and predators; weight 0 is
+  // off.
+  setPredictiveAvoidance(
+    weight: number,
+    horizonSeconds: number,
+    margin: number,
+  ): void {
+    this.sim.set_predictive_avoidance(weight, horizonSeconds, margin);
+  }
+
+  getPredictiveAvoidance(): {
+    weight: number;
+    horizonSeconds: number;
+    margin: number;
+  } {
+    return {
+      weight: this.sim.predictive_weight(),
+      horizonSeconds: this.sim.predictive_horizon_s(),
+      margin: this.sim.predictive_margin(),
+    };
+  }
+
+  setPredatorResponse(
+    radius: number,
+    classicWeight: number,
+    flock2Weight: number,
+    refractorySeconds: number,
+  ): void {
+    this.sim.set_predator_response(
+      radius,
+      classicWeight,
+      flock2Weight,
+      refractorySeconds,
+    );
+  }
+
+  getPredatorRadius(): number {
+    return this.sim.predator_radius();
+  }
+
+  // Seconds of evasion left for the boid in `slot`.
+  getEvasionTimeLeft(slot: number): number {
+    return this.sim.evasion_time_left(slot);
+  }
+
+  // Classic model only; toggling resets every boid to "flock".
+  setBehaviorEnabled(enabled: boolean): void {
+    this.sim.set_behavior_enabled(enabled);
+  }
+
+  isBehaviorEnabled(): boolean {
+    return this.sim.behavior_enabled();
+  }
+
+  // Multipliers on the classic weights while in `state`, plus the absolute
+  // pull toward the goal or landing point.
+  setBehaviorWeights(
+    state: SimBehaviorState,
+    weights: {
+      separation: number;
+      alignment: number;
+      cohesion: number;
+      jitter: number;
+      seek: number;
+    },
+  ): void {
+    this.sim.set_behavior_weights(
+      BEHAVIOR_STATES.indexOf(state),
+      weights.separation,
+      weights.alignment,
+      weights.cohesion,
+      weights.jitter,
+      weights.seek,
+    );
+  }
+
+  getBehaviorWeights(state: SimBehaviorState): {
+    separation: number;
+    alignment: number;
+    cohesion: number;
+    jitter: number;
+    seek: number;
+  } {
+    const [separation, alignment, cohesion, jitter, seek] =
+      this.sim.behavior_weights(BEHAVIOR_STATES.indexOf(state));
+    return { separation, alignment, cohesion, jitter, seek };
+  }
+
+  setBehaviorGoal(goal: { x: number; y: number; z: number } | null): void {
+    if (goal === null) {
+      this.sim.clear_behavior_goal();
+      return;
+    }
+    this.sim.set_behavior_goal(goal.x, goal.y, goal.z);
+  }
+
+  hasBehaviorGoal(): boolean {
+    return this.sim.has_behavior_goal();
+  }
+
+  // Boids perch within `radius` of the point until `takeOff` is called.
+  land(x: number, y: number, z: number, radius: number): void {
+    this.sim.command_landing(x, y, z, radius);
+  }
+
+  takeOff(): void {
+    this.sim.command_takeoff();
+  }
+
+  isLanding(): boolean {
+    return this.sim.landing_active();
+  }
+
+  getBehaviorState(slot: number): SimBehaviorState {
+    return BEHAVIOR_STATES[this.sim.behavior_state(slot)] ?? "flock";
+  }
+
+  // State index per slot, in the order of SimBehaviorState. Rebuilt per call
+  // since reserve moves the buffer.
+  getBehaviorStates(): Uint8Array {
+    return new Uint8Array(
+      this.wasmMemory.buffer,
+      this.sim.behavior_states_ptr(),
+      this.sim.behavior_states_len(),
+    );
+  }
+
+  // Followers take whatever share the other roles leave, so their fraction
+  // cannot be set.
+  setRoleFraction(role: SimRole, fraction: number): void {
+    this.sim.set_role_fraction(ROLES.indexOf(role), fraction);
+  }
+
+  getRoleFraction(role: SimRole): number {
+    return this.sim.role_fraction(ROLES.indexOf(role));
+  }
+
+  // Classic model only: neighbor radius scale and weight multipliers.
+  setRoleOverrides(
+    role: SimRole,
+    overrides: {
+      radiusScale: number;
+      separation: number;
+      alignment: number;
+      cohesion: number;
+    },
+  ): void {
+    this.sim.set_role_overrides(
+      ROLES.indexOf(role),
+      overrides.radiusScale,
+      overrides.separation,
+      overrides.alignment,
+      overrides.cohesion,
+    );
+  }
+
+  getRoleOverrides(role: SimRole): {
+    radiusScale: number;
+    separation: number;
+    alignment: number;
+    cohesion: number;
+  } {
+    const [radiusScale, separation, alignment, cohesion] =
+      this.sim.role_overrides_of(ROLES.indexOf(role));
+    return { radiusScale, separation, alignment, cohesion };
+  }
+
+  getBoidRole(slot: number): SimRole {
+    return ROLES[this.sim.boid_role(slot)] ?? "follower";
+  }
+
+  // Role index per slot, in the order of SimRole.
+  getRoles(): Uint8Array {
+    this.refreshViewIfMemoryChanged();
+    return this.rolesView;
+  }
+
+  // Species per slot, 0-7. Species without their own aero profile fly with
+  // the shared flock2 flight config.
+  setSpecies(species: Uint8Array): void {
+    this.sim.set_species(species);
+  }
+
+  setBoidSpecies(slot: number, species: number): void {
+    this.sim.set_boid_species(slot, species);
+  }
+
+  getBoidSpecies(slot: number): number {
+    return this.sim.boid_species(slot);
+  }
+
+  setSpeciesAero(
+    species: number,
+    aero: {
+      mass: number;
+      wingArea: number;
+      liftFactor: number;
+      dragFactor: number;
+      thrust: number;
+      minSpeed: number;
+      maxSpeed: number;
+    } | null,
+  ): void {
+    if (aero === null) {
+      this.sim.clear_flock2_species_aero(species);
+      return;
+    }
+    this.sim.set_flock2_species_aero(
+      species,
+      aero.mass,
+      aero.wingArea,
+      aero.liftFactor,
+      aero.dragFactor,
+      aero.thrust,
+      aero.minSpeed,
+      aero.maxSpeed,
+    );
+  }
+
+  hasSpeciesAero(species: number): boolean {
+    return this.sim.has_flock2_species_aero(species);
+  }
+
+  // Hard minimum distance for one species pair, in either order; 0 lets the
+  // pair overlap. Pairs without an override use the global hard minimum.
+  setSpeciesHardMinDistance(a: number, b: number, distance: number): void {
+    this.sim.set_species_hard_min_distance(a, b, distance);
+  }
+
+  clearSpeciesHardMinDistances(): void {
+    this.sim.clear_species_hard_min_distances();
+  }
+
+  getSpeciesHardMinDistance(a: number, b: number): number {
+    return this.sim.species_hard_min_distance(a, b);
+  }
+
+  // PID altitude hold for the flight models; altitude is world y in [0, 1].
+  setAltitudeHold(
+    enabled: boolean,
+    target: number,
+    kp: number,
+    ki: number,
+    kd: number,
+  ): void {
+    this.sim.set_altitude_hold(enabled, target, kp, ki, kd);
+  }
+
+  isAltitudeHoldEnabled(): boolean {
+    return this.sim.altitude_hold_enabled();
+  }
+
+  getAltitudeHoldTarget(): number {
+    return this.sim.altitude_hold_target();
+  }
+
+  setFishSchoolConfig(config: FishSchoolConfig): void {
+    this.sim.set_fish_config(
+      config.lateralAlignment,
+      config.preferredDepth,
+      config.depthWeight,
+      config.burstPeriod,
+      config.burstFraction,
+      config.burstThrust,
+      config.coastDrag,
+    );
+  }
+
+  setFishSchoolConfigChecked(config: FishSchoolConfig): SimConfigAdjustment[] {
+    return toConfigAdjustments(
+      this.sim.set_fish_config_checked(
+        config.lateralAlignment,
+        config.preferredDepth,
+        config.depthWeight,
+        config.burstPeriod,
+        config.burstFraction,
+        config.burstThrust,
+        config.coastDrag,
+      ),
+    );
+  }
+
+  getMathMode(): SimMathMode {
+    const modeId = this.sim.math_mode();
+    return modeId === 1 ? "fast" : modeId === 2 ? "lut" : "accurate";
+  }
+
+  setMaxNeighborsSampled(maxNeighbors: number): void {
+    this.sim.set_max_neighbors_sampled(Math.max(0, Math.floor(maxNeighbors)));
+  }
+
+  getMaxNeighborsSampled(): number {
+    return this.sim.max_neighbors_sampled();
+  }
+
+  // The k-d tree only serves classic steering queries; far-field mode keeps
+  // using the grid.
+  setNeighborBackend(backend: SimNeighborBackend): void {
+    this.sim.set_neighbor_backend(backend === "kd-tree" ? 1 : 0);
+  }
+
+  getNeighborBackend(): SimNeighborBackend {
+    return this.sim.neighbor_backend() === 1 ? "kd-tree" : "grid";
+  }
+
+  setGridRebuildInterval(steps: number): void {
+    this.sim.set_grid_rebuild_interval(Math.max(1, Math.floor(steps)));
+  }
+
+  getGridRebuildInterval(): number {
+    return this.sim.grid_rebuild_interval();
+  }
+
+  // Most candidates a grid query scans per cell; crowded cells offer a
+  // subset that rotates every step. 0 scans everything.
+  setNeighborCellCap(cap: number): void {
+    this.sim.set_neighbor_cell_cap(Math.max(0, Math.floor(cap)));
+  }
+
+  getNeighborCellCap(): number {
+    return this.sim.neighbor_cell_cap();
+  }
+
+  setKdRebuildInterval(steps: number): void {
+    this.sim.set_kd_rebuild_interval(Math.max(1, Math.floor(steps)));
+  }
+
+  getKdRebuildInterval(): number {
+    return this.sim.kd_rebuild_interval();
+  }
+
+  getNeighborBackendCostEstimate(backend: SimNeighborBackend): number {
+    return this.sim.neighbor_backend_cost_estimate(
+      backend === "kd-tree" ? 1 : 0,
+    );
+  }
+
+  getRecommendedNeighborBackend(): SimNeighborBackend {
+    return this.sim.recommended_neighbor_backend() === 1 ? "kd-tree" : "grid";
+  }
+
+  getNeighborsVisitedLastStep(): number {
+    return this.sim.neighbors_visited_last_step();
+  }
+
+  setSepWeight(sepWeight: number): void {
+    this.sim.set_sep_weight(Math.max(0, sepWeight));
+  }
+
+  getSepWeight(): number {
+    return this.sim.sep_weight();
+  }
+
+  setAlignWeight(alignWeight: number): void {
+    this.sim.set_align_weight(Math.max(0, alignWeight));
+  }
+
+  getAlignWeight(): number {
+    return this.sim.align_weight();
+  }
+
+  setCohWeight(cohWeight: number): void {
+    this.sim.set_coh_weight(Math.max(0, cohWeight));
+  }
+
+  getCohWeight(): number {
+    return this.sim.coh_weight();
+  }
+
+  setSpeedMatchWeight(speedMatchWeight: number): void {
+    this.sim.set_speed_match_weight(Math.max(0, speedMatchWeight));
+  }
+
+  getSpeedMatchWeight(): number {
+    return this.sim.speed_match_weight();
+  }
+
+  setNeighborRadius(neighborRadius: number): void {
+    this.sim.set_neighbor_radius(neighborRadius);
+  }
+
+  getNeighborRadius(): number {
+    return this.sim.neighbor_radius();
+  }
+
+  setSeparationRadius(separationRadius: number): void {
+    this.sim.set_separation_radius(separationRadius);
+  }
+
+  getSeparationRadius(): number {
+    return this.sim.separation_radius();
+  }
+
+  setMinSpeed(minSpeed: number): void {
+    this.sim.set_min_speed(Math.max(0, minSpeed));
+  }
+
+  getMinSpeed(): number {
+    return this.sim.min_speed();
+  }
+
+  setMaxSpeed(maxSpeed: number): void {
+    this.sim.set_max_speed(Math.max(0, maxSpeed));
+  }
+
+  getMaxSpeed(): number {
+    return this.sim.max_speed();
+  }
+
+  setMaxForce(maxForce: number): void {
+    this.sim.set_max_force(Math.max(0, maxForce));
+  }
+
+  getMaxForce(): number {
+    return this.sim.max_force();
+  }
+
+  setDrag(drag: number): void {
+    this.sim.set_drag(Math.max(0, drag));
+  }
+
+  getDrag(): number {
+    return this.sim.drag();
+  }
+
+  setDragModel(model: SimDragModel): void {
+    const modelId =
+      model === "linear"
+        ? 1
+        : model === "quadratic"
+          ? 2
+          : model === "combined"
+            ? 3
+            : 0;
+    this.sim.set_drag_model(modelId);
+  }
+
+  getDragModel(): SimDragModel {
+    const modelId = this.sim.drag_model();
+    return modelId === 1
+      ? "linear"
+      : modelId === 2
+        ? "quadratic"
+        : modelId === 3
+          ? "combined"
+          : "exponential";
+  }
+
+  setSeparationKernel(kernel: SimSeparationKernel): void {
+    const kernelId =
+      kernel === "inverse-linear"
+        ? 0
+        : kernel === "inverse-cube"
+          ? 2
+          : kernel === "smooth"
+            ? 3
+            : 1;
+    this.sim.set_separation_kernel(kernelId);
+  }
+
+  getSeparationKernel(): SimSeparationKernel {
+    const kernelId = this.sim.separation_kernel();
+    return kernelId === 0
+      ? "inverse-linear"
+      : kernelId === 2
+        ? "inverse-cube"
+        : kernelId === 3
+          ? "smooth"
+          : "inverse-square";
+  }
+
+  setQuadraticDrag(drag: number): void {
+    this.sim.set_quadratic_drag(Math.max(0, drag));
+  }
+
+  getQuadraticDrag(): number {
+    return this.sim.quadratic_drag();
+  }
+
+  // Align to neighbors' directions rather than velocities (classic model).
+  setAlignToHeadings(enabled: boolean): void {
+    this.sim.set_align_to_headings(enabled);
+  }
+
+  isAlignToHeadings(): boolean {
+    return this.sim.align_to_headings();
+  }
+
+  // Cohesion weights closer neighbors more (classic model).
+  setDistanceWeightedCohesion(enabled: boolean): void {
+    this.sim.set_distance_weighted_cohesion(enabled);
+  }
+
+  isDistanceWeightedCohesion(): boolean {
+    return this.sim.distance_weighted_cohesion();
+  }
+
+  // When disabled the z axis reuses the horizontal drag coefficients.
+  setAxisDragEnabled(enabled: boolean): void {
+    this.sim.set_axis_drag_enabled(enabled);
+  }
+
+  isAxisDragEnabled(): boolean {
+    return this.sim.axis_drag_enabled();
+  }
+
+  setZDrag(drag: number): void {
+    this.sim.set_z_drag(Math.max(0, drag));
+  }
+
+  getZDrag(): number {
+    return this.sim.z_drag();
+  }
+
+  setZQuadraticDrag(drag: number): void {
+    this.sim.set_z_quadratic_drag(Math.max(0, drag));
+  }
+
+  getZQuadraticDrag(): number {
+    return this.sim.z_quadratic_drag();
+  }
+
+  setGlobalAcceleration(ax: number, ay: number, az: number): void {
+    this.sim.set_global_acceleration(ax, ay, az);
+  }
+
+  getGlobalAcceleration(): [number, number, number] {
+    return [
+      this.sim.global_acceleration_x(),
+      this.sim.global_acceleration_y(),
+      this.sim.global_acceleration_z(),
+    ];
+  }
+
+  // The applied wind eases towards the target at `response` per second; a
+  // response of 0 applies the target immediately.
+  setWind(x: number, y: number, z = 0): void {
+    this.sim.set_wind(x, y, z);
+  }
+
+  getWind(): [number, number, number] {
+    return [this.sim.wind_x(), this.sim.wind_y(), this.sim.wind_z()];
+  }
+
+  setWindResponse(response: number): void {
+    this.sim.set_wind_response(Math.max(0, response));
+  }
+
+  getWindResponse(): number {
+    return this.sim.wind_response();
+  }
+
+  // Horizontal wind change per unit of height, zero at `baseHeight`. Only
+  // the flight models feel it.
+  setWindShear(xPerHeight: number, zPerHeight: number, baseHeight = 0.5): void {
+    this.sim.set_wind_shear(xPerHeight, zPerHeight, baseHeight);
+  }
+
+  getWindShear(): [number, number] {
+    return [this.sim.wind_shear_x(), this.sim.wind_shear_z()];
+  }
+
+  setShapeAttractorWeight(weight: number): void {
+    this.sim.set_shape_attractor_weight(Math.max(0, weight));
+  }
+
+  getShapeAttractorWeight(): number {
+    return this.sim.shape_attractor_weight();
+  }
+
+  // Shapes the shape attractor and behavior goal pulls. Nothing pulls inside
+  // the dead zone or past the max radius; a max radius of 0 is unbounded.
+  setAttractorFalloff(
+    profile: SimFalloffProfile,
+    deadZone = 0,
+    maxRadius = 0,
+  ): void {
+    this.sim.set_attractor_falloff(
+      FALLOFF_PROFILES.indexOf(profile),
+      deadZone,
+      maxRadius,
+    );
+  }
+
+  getAttractorFalloff(): {
+    profile: SimFalloffProfile;
+    deadZone: number;
+    maxRadius: number;
+  } {
+    return {
+      profile: FALLOFF_PROFILES[this.sim.attractor_falloff_profile()],
+      deadZone: this.sim.attractor_dead_zone(),
+      maxRadius: this.sim.attractor_max_radius(),
+    };
+  }
+
+  setShapePoints(pointsXyz: Float32Array): void {
+    this.sim.set_shape_points_xyz(pointsXyz);
+  }
+
+  setMinDistance(minDistance: number): void {
+    this.sim.set_min_distance(Math.max(0, minDistance));
+  }
+
+  getMinDistance(): number {
+    return this.sim.min_distance();
+  }
+
+  setHardMinDistance(minDistance: number): void {
+    this.sim.set_hard_min_distance(Math.max(0, minDistance));
+  }
+
+  getHardMinDistance(): number {
+    return this.sim.hard_min_distance();
+  }
+
+  setJitterStrength(jitterStrength: number): void {
+    this.sim.set_jitter_strength(Math.max(0, jitterStrength));
+  }
+
+  getJitterStrength(): number {
+    return this.sim.jitter_strength();
+  }
+
+  setFarFieldOpening(opening: number): void {
+    this.sim.set_far_field_opening(Math.max(0, opening));
+  }
+
+  getFarFieldOpening(): number {
+    return this.sim.far_field_opening();
+  }
+
+  setZMode(enabled: boolean): void {
+    this.sim.set_z_mode(enabled);
+  }
+
+  isZModeEnabled(): boolean {
+    return this.sim.z_mode_enabled();
+  }
+
+  setZForceScale(scale: number): void {
+    this.sim.set_z_force_scale(scale);
+  }
+
+  // Per-behavior z scales; attractor also covers obstacles and predators.
+  setZForceScales(scales: {
+    separation: number;
+    alignment: number;
+    cohesion: number;
+    attractor: number;
+  }): void {
+    this.sim.set_z_force_scales(
+      scales.separation,
+      scales.alignment,
+      scales.cohesion,
+      scales.attractor,
+    );
+  }
+
+  getZForceScales(): {
+    separation: number;
+    alignment: number;
+    cohesion: number;
+    attractor: number;
+  } {
+    return {
+      separation: this.sim.z_force_scale_separation(),
+      alignment: this.sim.z_force_scale_alignment(),
+      cohesion: this.sim.z_force_scale_cohesion(),
+      attractor: this.sim.z_force_scale_attractor(),
+    };
+  }
+
+  // Depth slab within [0, 1] that z mode confines boids to; z wraps or
+  // bounces at its faces.
+  setZExtent(zMin: number, zMax: number): void {
+    this.sim.set_z_extent(zMin, zMax);
+  }
+
+  getZExtent(): { min: number; max: number } {
+    return { min: this.sim.z_min(), max: this.sim.z_max() };
+  }
+
+  // Z-mode ground: row-major world-z heights, `width` samples per row along
+  // x and `height` rows along y. Returns false when the sizes do not match.
+  setTerrainHeightmap(
+    width: number,
+    height: number,
+    heights: Float32Array,
+  ): boolean {
+    return this.sim.set_terrain_heightmap(width, height, heights);
+  }
+
+  clearTerrain(): void {
+    this.sim.clear_terrain();
+  }
+
+  hasTerrain(): boolean {
+    return this.sim.has_terrain();
+  }
+
+  getTerrainHeight(x: number, y: number): number {
+    return this.sim.terrain_height(x, y);
+  }
+
+  setTerrainAvoidance(weight: number, clearance: number): void {
+    this.sim.set_terrain_avoidance(weight, clearance);
+  }
+
+  getTerrainAvoidance(): { weight: number; clearance: number } {
+    return {
+      weight: this.sim.terrain_weight(),
+      clearance: this.sim.terrain_clearance(),
+    };
+  }
+
+  // A sphere boids steer around and never enter, such as the camera; a
+  // radius of 0 removes it.
+  setExclusionSphere(x: number, y: number, z: number, radius: number): void {
+    this.sim.set_exclusion_sphere(x, y, z, radius);
+  }
+
+  clearExclusionSphere(): void {
+    this.sim.clear_exclusion_sphere();
+  }
+
+  // Radius 0 while no sphere is set.
+  getExclusionSphere(): { x: number; y: number; z: number; radius: number } {
+    const [x, y, z, radius] = this.sim.exclusion_sphere();
+    return { x, y, z, radius };
+  }
+
+  setExclusionAvoidance(weight: number, margin: number): void {
+    this.sim.set_exclusion_avoidance(weight, margin);
+  }
+
+  getExclusionAvoidance(): { weight: number; margin: number } {
+    return {
+      weight: this.sim.exclusion_weight(),
+      margin: this.sim.exclusion_margin(),
+    };
+  }
+
+  // Alternating bursts and glides for the classic and flock2 flight models;
+  // a strength of 0 turns it off.
+  setBurstCoast(periodS: number, duty: number, strength: number): void {
+    this.sim.set_burst_coast(periodS, duty, strength);
+  }
+
+  getBurstCoast(): { periodS: number; duty: number; strength: number } {
+    return {
+      periodS: this.sim.burst_period(),
+      duty: this.sim.burst_duty(),
+      strength: this.sim.burst_strength(),
+    };
+  }
+
+  // Whether the boid in `slot` is in the pushing part of its cycle.
+  isBoidBursting(slot: number): boolean {
+    return this.sim.boid_bursting(slot);
+  }
+
+  // Largest change in classic applied force per second, in multiples of max
+  // force, so config changes ease in; 0 turns the ramp off.
+  setAccelRamp(rate: number): void {
+    this.sim.set_accel_ramp(rate);
+  }
+
+  getAccelRamp(): number {
+    return this.sim.accel_ramp();
+  }
+
+  // Classic neighbor radius grows by the distance covered in `lookaheadS` at
+  // each boid's speed, up to `maxScale` times its base; 0 turns it off.
+  setSpeedRadius(lookaheadS: number, maxScale: number): void {
+    this.sim.set_speed_radius(lookaheadS, maxScale);
+  }
+
+  getSpeedRadius(): { lookaheadS: number; maxScale: number } {
+    return {
+      lookaheadS: this.sim.speed_radius_lookahead(),
+      maxScale: this.sim.speed_radius_max_scale(),
+    };
+  }
+
+  // Slowly drifting turns shared by boids within `scale` of each other, so
+  // the flock wheels together; a strength of 0 turns it off.
+  setTurnNoise(
+    strength: number,
+    scale: number,
+    periodS: number,
+    seed = 0,
+  ): void {
+    this.sim.set_turn_noise(strength, scale, periodS, seed >>> 0);
+  }
+
+  getTurnNoise(): { strength: number; scale: number; periodS: number } {
+    return {
+      strength: this.sim.turn_noise_strength(),
+      scale: this.sim.turn_noise_scale(),
+      periodS: this.sim.turn_noise_period(),
+    };
+  }
+
+  // Grows capacity so later `setActiveCount` calls up to `maxCount` never
+  // allocate. Every buffer moves, so previously returned views go stale.
+  reserve(maxCount: number): void {
+    this.sim.reserve(Math.max(0, Math.floor(maxCount)));
+    this.positionsPointer = this.sim.render_xy_ptr();
+    this.positionsLength = this.sim.render_xy_len();
+    this.depthPointer = this.sim.render_z_ptr();
+    this.depthLength = this.sim.render_z_len();
+    this.headingPointer = this.sim.render_heading_xy_ptr();
+    this.headingLength = this.sim.render_heading_xy_len();
+    this.crowdingPointer = this.sim.render_crowding_ptr();
+    this.crowdingLength = this.sim.render_crowding_len();
+    this.scalePointer = this.sim.render_scale_ptr();
+    this.scaleLength = this.sim.render_scale_len();
+    this.boundaryHitFlagsPointer = this.sim.boundary_hit_flags_ptr();
+    this.boundaryHitFlagsLength = this.sim.boundary_hit_flags_len();
+    this.rolesPointer = this.sim.roles_ptr();
+    this.rolesLength = this.sim.roles_len();
+    this.rebuildViews();
+  }
+
+  getCount(): number {
+    return this.sim.count();
+  }
+
+  setActiveCount(activeCount: number): void {
+    this.sim.set_active_count(Math.max(0, Math.floor(activeCount)));
+  }
+
+  getActiveCount(): number {
+    return this.sim.active_count();
+  }
+
+  // Simulates only these boid ids and freezes the rest. Render buffers are
+  // reordered to match; use `getBoidIds` to map each slot back to its boid.
+  setActiveIndices(ids: Uint32Array): void {
+    this.sim.set_active_indices(ids);
+  }
+
+  // Writes the chosen channels for each slot in `indices`; `data` holds
+  // x, y, z for every channel in position, velocity, heading order per boid.
+  // Returns how many boids were written.
+  setBoids(
+    indices: Uint32Array,
+    data: Float32Array,
+    channels: readonly SimBoidChannel[],
+  ): number {
+    return this.sim.set_boids(indices, data, boidWriteLayout(channels));
+  }
+
+  // Handles pack a generation with the boid id, so they keep resolving to
+  // the same boid across reordering and go stale once it is despawned.
+  spawnBoid(): number | undefined {
+    return this.sim.spawn();
+  }
+
+  despawnBoid(handle: number): boolean {
+    return this.sim.despawn(handle >>> 0);
+  }
+
+  // Later spawns land at least `margin` inside the world; `scatterBoids`
+  // re-lays the flock and relaxes it against hard_min_distance.
+  setSpawnLayout(margin: number, relaxIterations = 0): void {
+    this.sim.set_spawn_layout(
+      margin,
+      Math.max(0, Math.floor(relaxIterations)),
+    );
+  }
+
+  getSpawnLayout(): { margin: number; relaxIterations: number } {
+    return {
+      margin: this.sim.spawn_margin(),
+      relaxIterations: this.sim.spawn_relax_iterations(),
+    };
+  }
+
+  scatterBoids(): void {
+    this.sim.scatter_boids();
+  }
+
+  getBoidHandle(slot: number): number | undefined {
+    return this.sim.boid_handle(slot);
+  }
+
+  getHandleSlot(handle: number): number | undefined {
+    return this.sim.handle_slot(handle >>> 0);
+  }
+
+  isHandleLive(handle: number): boolean {
+    return this.sim.handle_is_live(handle >>> 0);
+  }
+
+  // One tier per slot: 0 recomputes steering every step, 1 only every
+  // `interval` steps, reusing the previous acceleration in between.
+  setLodTiers(tiers: Uint8Array): void {
+    this.sim.set_lod_tiers(tiers);
+  }
+
+  setLodTier(slot: number, tier: number): void {
+    this.sim.set_lod_tier(slot, tier);
+  }
+
+  getLodTier(slot: number): number {
+    return this.sim.lod_tier(slot);
+  }
+
+  // Boids outside the circle update at the low-detail rate from a capped
+  // neighbor sample, overriding `setLodTiers`. A radius of 0 clears it.
+  setFocusRegion(x: number, y: number, radius: number): void {
+    this.sim.set_focus_region(x, y, radius);
+  }
+
+  getFocusRadius(): number {
+    return this.sim.focus_radius();
+  }
+
+  // Recompute steering for only this fraction of full-detail boids per step,
+  // rotating through the flock. 1 updates every boid every step.
+  setUpdateFraction(fraction: number): void {
+    this.sim.set_update_fraction(fraction);
+  }
+
+  getUpdateFraction(): number {
+    return this.sim.update_fraction();
+  }
+
+  setLodInterval(steps: number): void {
+    this.sim.set_lod_interval(Math.max(1, Math.floor(steps)));
+  }
+
+  getLodInterval(): number {
+    return this.sim.lod_interval();
+  }
+
+  getBoidIds(): Uint32Array {
+    return new Uint32Array(
+      this.wasmMemory.buffer,
+      this.sim.boid_ids_ptr(),
+      this.sim.boid_ids_len(),
+    );
+  }
+
+  getBoidSlot(id: number): number | undefined {
+    return this.sim.boid_slot(id >>> 0);
+  }
+
+  // Stable per-boid values in [0, 1) for hue, size or flap rate variety;
+  // they follow boids through compaction, growth and state reloads.
+  getVariation(): Float32Array {
+    return new Float32Array(
+      this.wasmMemory.buffer,
+      this.sim.variation_ptr(),
+      this.sim.variation_len(),
+    );
+  }
+
+  setVariationSeed(seed: number): void {
+    this.sim.set_variation_seed(seed >>> 0);
+  }
+
+  getVariationSeed(): number {
+    return this.sim.variation_seed();
+  }
+
+  getBoidVariation(slot: number): number {
+    return this.sim.boid_variation(slot);
+  }
+
+  getPositions(): Float32Array {
+    this.refreshViewIfMemoryChanged();
+    return this.positionsView;
+  }
+
+  getDepth(): Float32Array {
+    this.refreshViewIfMemoryChanged();
+    return this.depthView;
+  }
+
+  getHeading(): Float32Array {
+    this.refreshViewIfMemoryChanged();
+    return this.headingView;
+  }
+
+  // 0..1 per boid: separation-radius neighbors over the crowding cap.
+  getCrowding(): Float32Array {
+    this.refreshViewIfMemoryChanged();
+    return this.crowdingView;
+  }
+
+  // Sprite size multiplier per boid: depth attenuation times species and
+  // per-boid size.
+  getRenderScale(): Float32Array {
+    this.refreshViewIfMemoryChanged();
+    return this.scaleView;
+  }
+
+  // Scale at render z 0 and z 1; equal values disable depth attenuation.
+  setRenderDepthScale(far: number, near: number): void {
+    this.sim.set_render_depth_scale(far, near);
+  }
+
+  getRenderDepthScaleFar(): number {
+    return this.sim.render_depth_scale_far();
+  }
+
+  getRenderDepthScaleNear(): number {
+    return this.sim.render_depth_scale_near();
+  }
+
+  // Time constant in seconds for an average over the exported headings; 0
+  // exports the raw per-step headings. Steering is unaffected.
+  setHeadingSmoothing(timeConstantS: number): void {
+    this.sim.set_heading_smoothing(timeConstantS);
+  }
+
+  getHeadingSmoothing(): number {
+    return this.sim.heading_smoothing();
+  }
+
+  // Time constant in seconds over which classic and fish headings follow
+  // velocity; 0 takes the velocity direction every step.
+  setClassicHeadingSmoothing(timeConstantS: number): void {
+    this.sim.set_classic_heading_smoothing(timeConstantS);
+  }
+
+  getClassicHeadingSmoothing(): number {
+    return this.sim.classic_heading_smoothing();
+  }
+
+  // Unit forward vectors of the active boids, one array per axis, kept by
+  // every model.
+  getHeadings(): { x: Float32Array; y: Float32Array; z: Float32Array } {
+    const view = (ptr: number) =>
+      new Float32Array(this.wasmMemory.buffer, ptr, this.sim.active_count());
+    return {
+      x: view(this.sim.heading_x_ptr()),
+      y: view(this.sim.heading_y_ptr()),
+      z: view(this.sim.heading_z_ptr()),
+    };
+  }
+
+  // Entries in each heading buffer, one per slot including inactive ones.
+  getHeadingsLength(): number {
+    return this.sim.heading_len();
+  }
+
+  // Speed limit scale at render z 0 and z 1, applied by the sim itself so
+  // parallax layers keep their proportions; 1 and 1 turns it off.
+  setDepthSpeedScale(far: number, near: number): void {
+    this.sim.set_depth_speed_scale(far, near);
+  }
+
+  getDepthSpeedScaleFar(): number {
+    return this.sim.depth_speed_scale_far();
+  }
+
+  getDepthSpeedScaleNear(): number {
+    return this.sim.depth_speed_scale_near();
+  }
+
+  // Projects render positions through a pinhole camera; world z 1 sits
+  // `zNear` from it and z 0 sits `zFar`. Null turns projection off.
+  setPerspective(
+    camera: {
+      fovDeg: number;
+      zNear: number;
+      zFar: number;
+      centerX: number;
+      centerY: number;
+    } | null,
+  ): void {
+    if (camera === null) {
+      this.sim.set_perspective(false, 60, 1, 2, 0.5, 0.5);
+      return;
+    }
+    this.sim.set_perspective(
+      true,
+      camera.fovDeg,
+      camera.zNear,
+      camera.zFar,
+      camera.centerX,
+      camera.centerY,
+    );
+  }
+
+  isPerspectiveEnabled(): boolean {
+    return this.sim.perspective_enabled();
+  }
+
+  // Column-major view-projection matrix; null clears the camera. While set,
+  // each step writes clip-space positions and a culled visible-slot list.
+  setCamera(viewProj: Float32Array | null): void {
+    this.sim.set_camera(viewProj ?? new Float32Array(0));
+  }
+
+  isCameraEnabled(): boolean {
+    return this.sim.camera_enabled();
+  }
+
+  setCameraCullMargin(margin: number): void {
+    this.sim.set_camera_cull_margin(margin);
+  }
+
+  getCameraCullMargin(): number {
+    return this.sim.camera_cull_margin();
+  }
+
+  // x, y, z, w per slot. Rebuilt per call since reserve moves the buffer.
+  getClipPositions(): Float32Array {
+    return new Float32Array(
+      this.wasmMemory.buffer,
+      this.sim.clip_positions_ptr(),
+      this.sim.clip_positions_len(),
+    );
+  }
+
+  // Slots inside the camera frustum, in ascending order.
+  getVisibleIndices(): Uint32Array {
+    return new Uint32Array(
+      this.wasmMemory.buffer,
+      this.sim.visible_indices_ptr(),
+      this.sim.visible_count(),
+    );
+  }
+
+  // Capacity of the visible-slot buffer; only `getVisibleIndices` entries
+  // are meaningful.
+  getVisibleIndicesLength(): number {
+    return this.sim.visible_indices_len();
+  }
+
+  // Slots whose world position lies in the rectangle, in ascending order.
+  // Returns a copy, unlike the camera view above.
+  getIndicesInRect(
+    minX: number,
+    minY: number,
+    maxX: number,
+    maxY: number,
+  ): Uint32Array {
+    return this.sim.visible_indices(minX, minY, maxX, maxY);
+  }
+
+  // "cluster" reads labels from the latest cluster detection.
+  setColorSource(source: SimColorSource): void {
+    const sourceId =
+      source === "speed"
+        ? 1
+        : source === "density"
+          ? 2
+          : source === "cluster"
+            ? 3
+            : source === "depth"
+              ? 4
+              : 0;
+    this.sim.set_color_source(sourceId);
+  }
+
+  getColorSource(): SimColorSource {
+    const sourceId = this.sim.color_source();
+    return sourceId === 1
+      ? "speed"
+      : sourceId === 2
+        ? "density"
+        : sourceId === 3
+          ? "cluster"
+          : sourceId === 4
+            ? "depth"
+            : "off";
+  }
+
+  // Evenly spaced RGBA stops, four bytes each; empty restores the default.
+  setColorGradient(rgba: Uint8Array): void {
+    this.sim.set_color_gradient(rgba);
+  }
+
+  getColorGradientStops(): number {
+    return this.sim.color_gradient_stops();
+  }
+
+  // RGBA per slot, ready for a WebGL attribute upload. Rebuilt per call
+  // since reserve moves the buffer.
+  getColors(): Uint8Array {
+    return new Uint8Array(
+      this.wasmMemory.buffer,
+      this.sim.colors_ptr(),
+      this.sim.colors_len(),
+    );
+  }
+
+  // Reflects render output across the vertical and/or horizontal center
+  // line. Folding simulates only the lower half of each mirrored axis.
+  setMirror(x: boolean, y: boolean, fold = false): void {
+    this.sim.set_mirror(x, y, fold);
+  }
+
+  getMirror(): { x: boolean; y: boolean; fold: boolean } {
+    return {
+      x: this.sim.mirror_x(),
+      y: this.sim.mirror_y(),
+      fold: this.sim.mirror_fold(),
+    };
+  }
+
+  getMirrorCopies(): number {
+    return this.sim.mirror_copies();
+  }
+
+  // Copy k of boid i is entry k * activeCount + i; other per-boid buffers
+  // are shared, so index them with entry % activeCount. Empty while off.
+  getMirrorPositions(): Float32Array {
+    return new Float32Array(
+      this.wasmMemory.buffer,
+      this.sim.mirror_xy_ptr(),
+      this.sim.mirror_xy_len(),
+    );
+  }
+
+  getMirrorHeadings(): Float32Array {
+    return new Float32Array(
+      this.wasmMemory.buffer,
+      this.sim.mirror_heading_xy_ptr(),
+      this.sim.mirror_heading_xy_len(),
+    );
+  }
+
+  // Simulates one wedge of `segments` around the center and renders the
+  // full circle; 0 or 1 turns it off.
+  setKaleidoscopeSegments(segments: number): void {
+    this.sim.set_kaleidoscope_segments(Math.max(0, Math.floor(segments)));
+  }
+
+  getKaleidoscopeSegments(): number {
+    return this.sim.kaleidoscope_segments();
+  }
+
+  // Same layout as the mirror buffers: copy k of boid i is entry
+  // k * activeCount + i. Empty while off.
+  getKaleidoscopePositions(): Float32Array {
+    return new Float32Array(
+      this.wasmMemory.buffer,
+      this.sim.kaleidoscope_xy_ptr(),
+      this.sim.kaleidoscope_xy_len(),
+    );
+  }
+
+  getKaleidoscopeHeadings(): Float32Array {
+    return new Float32Array(
+      this.wasmMemory.buffer,
+      this.sim.kaleidoscope_heading_xy_ptr(),
+      this.sim.kaleidoscope_heading_xy_len(),
+    );
+  }
+
+  // Boids within `margin` of a wrapping edge are also drawn one world over;
+  // 0 turns the ghosts off.
+  setWrapGhostMargin(margin: number): void {
+    this.sim.set_wrap_ghost_margin(margin);
+  }
+
+  getWrapGhostMargin(): number {
+    return this.sim.wrap_ghost_margin();
+  }
+
+  getWrapGhostCount(): number {
+    return this.sim.wrap_ghost_count();
+  }
+
+  // Ghost k sits at entries 2k, 2k + 1; getWrapGhostSources()[k] is the slot
+  // it copies for every other per-boid buffer.
+  getWrapGhostPositions(): Float32Array {
+    return new Float32Array(
+      this.wasmMemory.buffer,
+      this.sim.wrap_ghost_xy_ptr(),
+      2 * this.sim.wrap_ghost_count(),
+    );
+  }
+
+  getWrapGhostHeadings(): Float32Array {
+    return new Float32Array(
+      this.wasmMemory.buffer,
+      this.sim.wrap_ghost_heading_xy_ptr(),
+      2 * this.sim.wrap_ghost_count(),
+    );
+  }
+
+  getWrapGhostSources(): Uint32Array {
+    return new Uint32Array(
+      this.wasmMemory.buffer,
+      this.sim.wrap_ghost_source_ptr(),
+      this.sim.wrap_ghost_count(),
+    );
+  }
+
+  // Keeps a copy of the render buffers grouped by species, refreshed with
+  // them; off by default.
+  setSpeciesPartition(enabled: boolean): void {
+    this.sim.set_species_partition(enabled);
+  }
+
+  isSpeciesPartitionEnabled(): boolean {
+    return this.sim.species_partition();
+  }
+
+  // Species k is entries offsets[k] to offsets[k + 1]; sources[entry] is the
+  // slot it copies. Rebuilt per call since reserve moves the buffers.
+  getSpeciesPartition(): {
+    offsets: Uint32Array;
+    positions: Float32Array;
+    depths: Float32Array;
+    headings: Float32Array;
+    scales: Float32Array;
+    colors: Uint8Array;
+    sources: Uint32Array;
+  } {
+    const buffer = this.wasmMemory.buffer;
+    const offsets = new Uint32Array(
+      buffer,
+      this.sim.species_partition_offsets_ptr(),
+      this.sim.species_partition_offsets_len(),
+    );
+    const count = offsets[offsets.length - 1];
+    return {
+      offsets,
+      positions: new Float32Array(
+        buffer,
+        this.sim.species_partition_xy_ptr(),
+        2 * count,
+      ),
+      depths: new Float32Array(
+        buffer,
+        this.sim.species_partition_z_ptr(),
+        count,
+      ),
+      headings: new Float32Array(
+        buffer,
+        this.sim.species_partition_heading_xy_ptr(),
+        2 * count,
+      ),
+      scales: new Float32Array(
+        buffer,
+        this.sim.species_partition_scale_ptr(),
+        count,
+      ),
+      colors: new Uint8Array(
+        buffer,
+        this.sim.species_partition_colors_ptr(),
+        4 * count,
+      ),
+      sources: new Uint32Array(
+        buffer,
+        this.sim.species_partition_source_ptr(),
+        count,
+      ),
+    };
+  }
+
+  setSpeciesSize(species: number, size: number): void {
+    this.sim.set_species_size(species, size);
+  }
+
+  getSpeciesSize(species: number): number {
+    return this.sim.species_size(species);
+  }
+
+  setBoidSizes(sizes: Float32Array): void {
+    this.sim.set_boid_sizes(sizes);
+  }
+
+  getBoidSize(slot: number): number {
+    return this.sim.boid_size(slot);
+  }
+
+  // Per-slot multiplier for jitter and/or cohesion, e.g. from camera motion
+  // or hand tracking; upload every frame. Missing slots fall back to 1.
+  setExternalScalars(scalars: Float32Array): void {
+    this.sim.set_external_scalars(scalars);
+  }
+
+  clearExternalScalars(): void {
+    this.sim.clear_external_scalars();
+  }
+
+  getExternalScalar(slot: number): number {
+    return this.sim.external_scalar(slot);
+  }
+
+  setExternalScalarTargets(targets: {
+    jitter: boolean;
+    cohesion: boolean;
+  }): void {
+    this.sim.set_external_scalar_targets(targets.jitter, targets.cohesion);
+  }
+
+  getExternalScalarTargets(): { jitter: boolean; cohesion: boolean } {
+    return {
+      jitter: this.sim.external_scalar_scales_jitter(),
+      cohesion: this.sim.external_scalar_scales_cohesion(),
+    };
+  }
+
+  setCrowdingCap(cap: number): void {
+    this.sim.set_crowding_cap(Math.max(1, cap));
+  }
+
+  getCrowdingCap(): number {
+    return this.sim.crowding_cap();
+  }
+
+  getBoundaryHitsX(): number {
+    return this.sim.boundary_hits_x();
+  }
+
+  getBoundaryHitsY(): number {
+    return this.sim.boundary_hits_y();
+  }
+
+  getBoundaryHitsZ(): number {
+    return this.sim.boundary_hits_z();
+  }
+
+  // Summed |velocity change| from wall reflections during the last step.
+  getBoundaryCorrection(): number {
+    return this.sim.boundary_correction();
+  }
+
+  // Boundary correction per boid per simulated second, comparable across
+  // counts and frame rates.
+  getBoundaryPressure(): number {
+    return this.sim.boundary_pressure();
+  }
+
+  setBoundaryHitFlagsEnabled(enabled: boolean): void {
+    this.sim.set_boundary_hit_flags_enabled(enabled);
+  }
+
+  isBoundaryHitFlagsEnabled(): boolean {
+    return this.sim.boundary_hit_flags_enabled();
+  }
+
+  // Bit 0/1/2 are set when the boid reflected or wrapped on x/y/z this step.
+  getBoundaryHitFlags(): Uint8Array {
+    this.refreshViewIfMemoryChanged();
+    return this.boundaryHitFlagsView;
+  }
+
+  getContactCount(): number {
+    return this.sim.contact_count();
+  }
+
+  getContactPenetrationSum(): number {
+    return this.sim.contact_penetration_sum();
+  }
+
+  // Only the first `getRecordedContactCount()` pairs (i, j) in the view are
+  // valid for the current step.
+  getRecordedContactCount(): number {
+    return this.sim.recorded_contact_count();
+  }
+
+  // Re-checks pairs after the constraint pass; costs a second grid build.
+  setOverlapMetricsEnabled(enabled: boolean): void {
+    this.sim.set_overlap_metrics_enabled(enabled);
+  }
+
+  isOverlapMetricsEnabled(): boolean {
+    return this.sim.overlap_metrics_enabled();
+  }
+
+  getRemainingOverlapCount(): number {
+    return this.sim.remaining_overlap_count();
+  }
+
+  getMaxRemainingPenetration(): number {
+    return this.sim.max_remaining_penetration();
+  }
+
+  getContactPairs(): Uint32Array {
+    this.refreshViewIfMemoryChanged();
+    return this.contactPairsView;
+  }
+
+  // The step counters in a single wasm call, for once-per-frame dashboards.
+  getStepReport(): SimStepReport {
+    const report = this.sim.step_report();
+    const result: SimStepReport = {
+      simulatedDt: report.simulated_dt(),
+      neighborsVisited: report.neighbors_visited(),
+      boundaryHits: {
+        x: report.boundary_hits_x(),
+        y: report.boundary_hits_y(),
+        z: report.boundary_hits_z(),
+      },
+      boundaryCorrection: report.boundary_correction(),
+      contactCount: report.contact_count(),
+      contactPenetrationSum: report.contact_penetration_sum(),
+      remainingOverlaps: report.remaining_overlaps(),
+      maxRemainingPenetration: report.max_remaining_penetration(),
+      spawned: report.spawned(),
+      despawned: report.despawned(),
+    };
+    report.free();
+    return result;
+  }
+
+  getPolarization(): number {
+    return this.sim.polarization();
+  }
+
+  getMeanSpeed(): number {
+    return this.sim.mean_speed();
+  }
+
+  // Runs the left half with the main config and the right half with the
+  // split config; boids stay on their side and ignore the other one.
+  setSplitWorld(enabled: boolean): void {
+    this.sim.set_split_world(enabled);
+  }
+
+  isSplitWorld(): boolean {
+    return this.sim.split_world();
+  }
+
+  setSplitConfig(config: SimSplitConfig): void {
+    this.sim.set_split_config(
+      config.sepWeight,
+      config.alignWeight,
+      config.cohWeight,
+      config.neighborRadius,
+      config.separationRadius,
+      config.minSpeed,
+      config.maxSpeed,
+      config.maxForce,
+    );
+  }
+
+  getSplitConfig(): SimSplitConfig {
+    const [
+      sepWeight,
+      alignWeight,
+      cohWeight,
+      neighborRadius,
+      separationRadius,
+      minSpeed,
+      maxSpeed,
+      maxForce,
+    ] = this.sim.split_config();
+    return {
+      sepWeight,
+      alignWeight,
+      cohWeight,
+      neighborRadius,
+      separationRadius,
+      minSpeed,
+      maxSpeed,
+      maxForce,
+    };
+  }
+
+  getSplitMetrics(half: SimSplitHalf): SimSplitMetrics {
+    const [count, polarization, meanSpeed] = this.sim.split_metrics(
+      half === "right" ? 1 : 0,
+    );
+    return { count, polarization, meanSpeed };
+  }
+
+  setClusterLinkRadius(radius: number): void {
+    this.sim.set_cluster_link_radius(radius);
+  }
+
+  getClusterLinkRadius(): number {
+    return this.sim.cluster_link_radius();
+  }
+
+  setClusterMinSize(minSize: number): void {
+    this.sim.set_cluster_min_size(Math.max(1, Math.floor(minSize)));
+  }
+
+  getClusterMinSize(): number {
+    return this.sim.cluster_min_size();
+  }
+
+  detectClusterCount(): number {
+    return this.sim.detect_cluster_count();
+  }
+
+  // Cluster id per boid from the latest detection; 0xffffffff is unclustered.
+  getClusterLabels(): Uint32Array {
+    return new Uint32Array(
+      this.wasmMemory.buffer,
+      this.sim.cluster_labels_ptr(),
+      this.sim.cluster_labels_len(),
+    );
+  }
+
+  // One value per cluster from the latest detection: near 1 for a rotating
+  // mill, near 0 for polarized or disordered groups.
+  getClusterMilling(): Float32Array {
+    return new Float32Array(
+      this.wasmMemory.buffer,
+      this.sim.cluster_milling_ptr(),
+      this.sim.cluster_milling_len(),
+    );
+  }
+
+  getClusterStats(): SimClusterStats {
+    const buffer = this.wasmMemory.buffer;
+    const count = this.sim.cluster_count();
+    return {
+      count,
+      sizes: new Uint32Array(buffer, this.sim.cluster_sizes_ptr(), count),
+      centroids: new Float32Array(
+        buffer,
+        this.sim.cluster_centroids_ptr(),
+        count * 3,
+      ),
+      meanVelocities: new Float32Array(
+        buffer,
+        this.sim.cluster_mean_velocities_ptr(),
+        count * 3,
+      ),
+      boundingRadii: new Float32Array(
+        buffer,
+        this.sim.cluster_bounding_radii_ptr(),
+        count,
+      ),
+      milling: new Float32Array(
+        buffer,
+        this.sim.cluster_milling_ptr(),
+        count,
+      ),
+    };
+  }
+
+  // Splits and merges between the latest detection and the one before it.
+  getClusterEvents(): SimClusterEvent[] {
+    const buffer = this.wasmMemory.buffer;
+    const count = this.sim.cluster_event_count();
+    const events = new Uint32Array(
+      buffer,
+      this.sim.cluster_events_ptr(),
+      count * 5,
+    );
+    const parts = new Uint32Array(
+      buffer,
+      this.sim.cluster_event_parts_ptr(),
+      this.sim.cluster_event_parts_len(),
+    );
+    return Array.from({ length: count }, (_, e) => {
+      const [kind, cluster, size, first, partCount] = events.subarray(
+        5 * e,
+        5 * e + 5,
+      );
+      return {
+        kind: kind === 0 ? "split" : "merge",
+        cluster,
+        size,
+        parts: Array.from({ length: partCount }, (_, p) => ({
+          cluster: parts[2 * (first + p)],
+          size: parts[2 * (first + p) + 1],
+        })),
+      };
+    });
+  }
+
+  getClusterSplitCount(): number {
+    return this.sim.cluster_splits();
+  }
+
+  getClusterMergeCount(): number {
+    return this.sim.cluster_merges();
+  }
+
+  // Returns -1 when the latest detection found no clusters.
+  getLargestCluster(): number {
+    const cluster = this.sim.largest_cluster();
+    return cluster === 0xffffffff ? -1 : cluster;
+  }
+
+  // Flight metrics describe the last step and stay zero outside the flight
+  // models. Speeds use flight-model units with y as the vertical axis.
+  getMeanAirspeed(): number {
+    return this.sim.mean_airspeed();
+  }
+
+  getMeanClimbRate(): number {
+    return this.sim.mean_climb_rate();
+  }
+
+  getThrustWork(): number {
+    return this.sim.thrust_work();
+  }
+
+  getMeanFlightEnergy(): number {
+    return this.sim.mean_flight_energy();
+  }
+
+  getStallFraction(): number {
+    return this.sim.stall_fraction();
+  }
+
+  // Sampled once per step while the length is non-zero.
+  setMetricHistoryLength(length: number): void {
+    this.sim.set_metric_history_length(Math.max(0, Math.floor(length)));
+  }
+
+  getMetricHistoryLength(): number {
+    return this.sim.metric_history_length();
+  }
+
+  getMetricHistory(): SimMetricHistory {
+    const buffer = this.wasmMemory.buffer;
+    const length = this.sim.metric_history_length();
+    return {
+      polarization: new Float32Array(
+        buffer,
+        this.sim.polarization_history_ptr(),
+        length,
+      ),
+      meanSpeed: new Float32Array(
+        buffer,
+        this.sim.mean_speed_history_ptr(),
+        length,
+      ),
+      clusterCount: new Float32Array(
+        buffer,
+        this.sim.cluster_count_history_ptr(),
+        length,
+      ),
+      start: this.sim.metric_history_start(),
+      filled: this.sim.metric_history_filled(),
+    };
+  }
+
+  // Only the classic and fish models fill the steering debug buffer.
+  setSteeringDebug(mode: SimSteeringDebugMode, inspectedIndex = 0): void {
+    const modeId = mode === "inspected" ? 1 : mode === "all" ? 2 : 0;
+    this.sim.set_steering_debug(
+      modeId,
+      Math.max(0, Math.floor(inspectedIndex)),
+    );
+  }
+
+  getSteeringDebugMode(): SimSteeringDebugMode {
+    const modeId = this.sim.steering_debug_mode();
+    return modeId === 1 ? "inspected" : modeId === 2 ? "all" : "off";
+  }
+
+  getSteeringDebugInspectedIndex(): number {
+    return this.sim.steering_debug_inspected();
+  }
+
+  // Fifteen floats per captured boid: separation, alignment, cohesion, jitter
+  // and speed matching, xyz each, weighted but before the max-force limit. The
+  // buffer moves when the debug mode changes, so the view is rebuilt on every
+  // call.
+  getSteeringDebugComponents(): Float32Array {
+    return new Float32Array(
+      this.wasmMemory.buffer,
+      this.sim.steering_debug_ptr(),
+      this.sim.steering_debug_len(),
+    );
+  }
+
+  getPointer(): number {
+    return this.positionsPointer;
+  }
+
+  getDepthPointer(): number {
+    return this.depthPointer;
+  }
+
+  getHeadingPointer(): number {
+    return this.headingPointer;
+  }
+
+  private refreshViewIfMemoryChanged(): void {
+    if (this.memoryBuffer === this.wasmMemory.buffer) {
+      return;
+    }
+
+    this.rebuildViews();
+  }
+
+  private rebuildViews(): void {
+    this.memoryBuffer = this.wasmMemory.buffer;
+    this.positionsView = new Float32Array(
+      this.memoryBuffer,
+      this.positionsPointer,
+      this.positionsLength,
+    );
+    this.depthView = new Float32Array(
+      this.memoryBuffer,
+      this.depthPointer,
+      this.depthLength,
+    );
+    this.headingView = new Float32Array(
+      this.memoryBuffer,
+      this.headingPointer,
+      this.headingLength,
+    );
+    this.crowdingView = new Float32Array(
+      this.memoryBuffer,
+      this.crowdingPointer,
+      this.crowdingLength,
+    );
+    this.scaleView = new Float32Array(
+      this.memoryBuffer,
+      this.scalePointer,
+      this.scaleLength,
+    );
+    this.boundaryHitFlagsView = new Uint8Array(
+      this.memoryBuffer,
+      this.boundaryHitFlagsPointer,
+      this.boundaryHitFlagsLength,
+    );
+    this.rolesView = new Uint8Array(
+      this.memoryBuffer,
+      this.rolesPointer,
+      this.rolesLength,
+    );
+    this.contactPairsView = new Uint32Array(
+      this.memoryBuffer,
+      this.contactPairsPointer,
+      this.contactPairsLength,
+    );
+  }
+}
+
+// Hash of a standardized run. Pin it per seed to detect behavior changes when
+// upgrading the simulation crate; requires `initWasmModule` to have resolved.
+export function runGolden(seed: number, steps: number): bigint {
+  return run_golden(seed, steps);
+}
+
+export interface EvolutionOptions {
+  seed: number;
+  population: number;
+  generations: number;
+  steps: number;
+  boidCount: number;
+  polarizationWeight: number;
+  collisionWeight: number;
+  // Pairs closer than this count as collisions; 0 uses the default.
+  collisionDistance?: number;
+}
+
+export type EvolvedConfig = Pick<
+  SimBoidsConfig,
+  | "sepWeight"
+  | "alignWeight"
+  | "cohWeight"
+  | "neighborRadius"
+  | "separationRadius"
+> & {
+  fitness: number;
+  // Best fitness after each generation.
+  history: number[];
+};
+
+// Evolves classic weights and radii over headless runs. Blocks until done,
+// so prefer a worker for large budgets.
+export function evolveClassicConfig(options: EvolutionOptions): EvolvedConfig {
+  return JSON.parse(
+    evolve_classic_config(
+      options.seed,
+      options.population,
+      options.generations,
+      options.steps,
+      options.boidCount,
+      options.polarizationWeight,
+      options.collisionWeight,
+      options.collisionDistance ?? 0,
+    ),
+  ) as EvolvedConfig;
 }
 
 export async function initWasmModule(): Promise<WasmSimClient> {